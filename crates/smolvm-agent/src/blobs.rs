@@ -0,0 +1,454 @@
+//! Content-addressed blob store used when pushing images.
+//!
+//! Pulled layers are stored extracted under `/storage/layers`, so the
+//! original compressed blobs are gone by the time an image is pushed. This
+//! module re-tars a layer directory into `/storage/blobs/sha256/<hex>`.
+//!
+//! The tar stream is deterministic: entries are sorted by name, headers are
+//! plain ustar (no atime/ctime), and compressors write no timestamps. Pushing
+//! the same layer twice therefore yields the same digest, and registries can
+//! deduplicate it.
+//!
+//! Layers created by image builds are overlayfs upper directories; their
+//! whiteouts (0/0 character devices and opaque directories) are written as
+//! OCI `.wh.` entries.
+
+use sha2::{Digest, Sha256};
+use smolvm_protocol::LayerCompression;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Subdirectory holding blobs, named by hex digest.
+const SHA256_DIR: &str = "sha256";
+
+/// Subdirectory mapping extracted layers to their re-tarred blobs.
+const INDEX_DIR: &str = "index";
+
+/// Prefix of OCI whiteout files marking deleted paths.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// OCI whiteout file marking a directory as opaque.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// A layer blob written to the store.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LayerBlob {
+    /// Digest of the compressed blob (sha256:...).
+    pub digest: String,
+    /// Digest of the uncompressed tar stream (sha256:...).
+    pub diff_id: String,
+    /// Size of the compressed blob in bytes.
+    pub size: u64,
+}
+
+/// Content-addressed blob store rooted at a directory.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    /// Open a blob store rooted at `root`. Directories are created lazily.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Path of the blob with the given digest.
+    ///
+    /// Rejects anything that is not a well-formed `sha256:<64 hex>` digest so
+    /// that host-supplied digests can't escape the store.
+    pub fn path(&self, digest: &str) -> io::Result<PathBuf> {
+        let hex = digest
+            .strip_prefix("sha256:")
+            .filter(|h| h.len() == 64 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid blob digest: {}", digest),
+                )
+            })?;
+        Ok(self.root.join(SHA256_DIR).join(hex))
+    }
+
+    /// Store `data` as a blob and return its digest.
+    pub fn put(&self, data: &[u8]) -> io::Result<String> {
+        let digest = format!("sha256:{:x}", Sha256::digest(data));
+        let path = self.path(&digest)?;
+        if !path.exists() {
+            let tmp = self.temp_path("put")?;
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(digest)
+    }
+
+    /// Get (or create) the compressed blob for an extracted layer directory.
+    ///
+    /// Results are cached per layer and compression, so repeated pushes of
+    /// the same image don't re-tar unchanged layers.
+    pub fn layer(
+        &self,
+        layer_id: &str,
+        layer_dir: &Path,
+        compression: LayerCompression,
+    ) -> io::Result<LayerBlob> {
+        if let Some(blob) = fs::read(self.index_path(layer_id, compression))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<LayerBlob>(&data).ok())
+        {
+            if self.path(&blob.digest)?.exists() {
+                debug!(layer = %layer_id, digest = %blob.digest, "layer blob cached");
+                return Ok(blob);
+            }
+        }
+
+        info!(layer = %layer_id, compression = compression.as_str(), "creating layer blob");
+        let blob = self.write_layer(layer_dir, compression)?;
+        self.record_layer(layer_id, compression, &blob)?;
+
+        Ok(blob)
+    }
+
+    /// Record `blob` as the compressed form of a layer, so later calls to
+    /// [`BlobStore::layer`] for that layer reuse it.
+    pub fn record_layer(
+        &self,
+        layer_id: &str,
+        compression: LayerCompression,
+        blob: &LayerBlob,
+    ) -> io::Result<()> {
+        fs::create_dir_all(self.root.join(INDEX_DIR))?;
+        let index = serde_json::to_vec(blob).map_err(io::Error::other)?;
+        fs::write(self.index_path(layer_id, compression), index)
+    }
+
+    fn index_path(&self, layer_id: &str, compression: LayerCompression) -> PathBuf {
+        self.root
+            .join(INDEX_DIR)
+            .join(format!("{}.{}.json", layer_id, compression.as_str()))
+    }
+
+    /// Tar and compress `layer_dir` into the store.
+    pub fn write_layer(
+        &self,
+        layer_dir: &Path,
+        compression: LayerCompression,
+    ) -> io::Result<LayerBlob> {
+        let tmp = self.temp_path("layer")?;
+        let file = HashingWriter::new(File::create(&tmp)?);
+
+        let result = (|| {
+            let (diff_id, compressed) = match compression {
+                LayerCompression::Gzip => {
+                    let encoder = flate2::GzBuilder::new()
+                        .mtime(0)
+                        .write(file, flate2::Compression::default());
+                    let tar = write_tar(layer_dir, HashingWriter::new(encoder))?;
+                    let (encoder, diff_id, _) = tar.finish();
+                    (diff_id, encoder.finish()?)
+                }
+                LayerCompression::Zstd => {
+                    let encoder = zstd::stream::write::Encoder::new(file, 0)?;
+                    let tar = write_tar(layer_dir, HashingWriter::new(encoder))?;
+                    let (encoder, diff_id, _) = tar.finish();
+                    (diff_id, encoder.finish()?)
+                }
+            };
+            let (mut file, digest, size) = compressed.finish();
+            file.flush()?;
+            file.sync_all()?;
+            Ok::<_, io::Error>(LayerBlob {
+                digest,
+                diff_id,
+                size,
+            })
+        })();
+
+        match result {
+            Ok(blob) => {
+                fs::rename(&tmp, self.path(&blob.digest)?)?;
+                Ok(blob)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Unique temporary path inside the store (same filesystem as the blobs,
+    /// so the final rename is atomic).
+    fn temp_path(&self, kind: &str) -> io::Result<PathBuf> {
+        let dir = self.root.join(SHA256_DIR);
+        fs::create_dir_all(&dir)?;
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        Ok(dir.join(format!(".tmp-{}-{}-{}", kind, std::process::id(), nanos)))
+    }
+}
+
+/// Writer that computes the sha256 and length of everything written to it.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    /// Return the inner writer, the `sha256:` digest and the byte count.
+    fn finish(self) -> (W, String, u64) {
+        let digest = format!("sha256:{:x}", self.hasher.finalize());
+        (self.inner, digest, self.written)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write a deterministic tar of `dir` to `out` and return the writer.
+fn write_tar<W: Write>(dir: &Path, out: W) -> io::Result<W> {
+    let mut builder = tar::Builder::new(out);
+    builder.follow_symlinks(false);
+
+    // (dev, inode) -> first archived path, for hard links
+    let mut seen: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    append_dir_entries(&mut builder, dir, Path::new(""), &mut seen)?;
+
+    builder.into_inner()
+}
+
+fn append_dir_entries<W: Write>(
+    builder: &mut tar::Builder<W>,
+    dir: &Path,
+    prefix: &Path,
+    seen: &mut HashMap<(u64, u64), PathBuf>,
+) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = prefix.join(entry.file_name());
+        let meta = fs::symlink_metadata(&path)?;
+
+        let mut header = tar::Header::new_ustar();
+        header.set_metadata_in_mode(&meta, tar::HeaderMode::Complete);
+        header.set_mode(meta.mode() & 0o7777);
+
+        let file_type = meta.file_type();
+        if file_type.is_char_device() && meta.rdev() == 0 {
+            // Overlayfs whiteout (from a build step) -> OCI whiteout file.
+            let mut wh_name = OsString::from(WHITEOUT_PREFIX);
+            wh_name.push(entry.file_name());
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(0);
+            builder.append_data(&mut header, prefix.join(wh_name), io::empty())?;
+        } else if file_type.is_dir() {
+            // Directories are archived with a trailing slash, as `tar` does.
+            let mut dir_name = name.clone().into_os_string();
+            dir_name.push("/");
+            header.set_size(0);
+            builder.append_data(&mut header, dir_name, io::empty())?;
+            if is_opaque_dir(&path) {
+                let mut opaque = tar::Header::new_ustar();
+                opaque.set_mode(0o644);
+                opaque.set_size(0);
+                builder.append_data(&mut opaque, name.join(OPAQUE_WHITEOUT), io::empty())?;
+            }
+            append_dir_entries(builder, &path, &name, seen)?;
+        } else if file_type.is_symlink() {
+            header.set_size(0);
+            builder.append_link(&mut header, &name, fs::read_link(&path)?)?;
+        } else if file_type.is_file() {
+            if meta.nlink() > 1 {
+                if let Some(target) = seen.get(&(meta.dev(), meta.ino())) {
+                    header.set_entry_type(tar::EntryType::Link);
+                    header.set_size(0);
+                    builder.append_link(&mut header, &name, target)?;
+                    continue;
+                }
+                seen.insert((meta.dev(), meta.ino()), name.clone());
+            }
+            header.set_size(meta.len());
+            builder.append_data(&mut header, &name, File::open(&path)?)?;
+        } else {
+            // Devices, FIFOs and sockets carry no data.
+            let rdev = meta.rdev();
+            header.set_device_major(((rdev >> 8) & 0xfff | (rdev >> 32) & !0xfff) as u32)?;
+            header.set_device_minor((rdev & 0xff | (rdev >> 12) & !0xff) as u32)?;
+            header.set_size(0);
+            builder.append_data(&mut header, &name, io::empty())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether an overlayfs upper directory is marked opaque (its lower
+/// contents are hidden).
+fn is_opaque_dir(path: &Path) -> bool {
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut value = [0u8; 1];
+    // SAFETY: valid NUL-terminated strings and a buffer of the given length.
+    let n = unsafe {
+        libc::lgetxattr(
+            c_path.as_ptr(),
+            c"trusted.overlay.opaque".as_ptr(),
+            value.as_mut_ptr() as *mut libc::c_void,
+            value.len(),
+        )
+    };
+    n == 1 && value[0] == b'y'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_layer(root: &Path) -> PathBuf {
+        let dir = root.join("layer");
+        fs::create_dir_all(dir.join("etc/conf.d")).unwrap();
+        fs::write(dir.join("etc/hostname"), "smolvm\n").unwrap();
+        fs::write(dir.join("etc/conf.d/a"), "a").unwrap();
+        fs::write(dir.join(".wh.removed"), "").unwrap();
+        std::os::unix::fs::symlink("hostname", dir.join("etc/name")).unwrap();
+        fs::hard_link(dir.join("etc/hostname"), dir.join("etc/hostname.bak")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_blob_path_rejects_invalid_digests() {
+        let store = BlobStore::new("/storage/blobs");
+        let hex = "a".repeat(64);
+        assert_eq!(
+            store.path(&format!("sha256:{}", hex)).unwrap(),
+            PathBuf::from(format!("/storage/blobs/sha256/{}", hex))
+        );
+        assert!(store.path("sha256:../../etc/passwd").is_err());
+        assert!(store.path(&hex).is_err());
+        assert!(store.path("sha512:abc").is_err());
+    }
+
+    #[test]
+    fn test_put_is_content_addressed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(tmp.path());
+        let digest = store.put(b"{}").unwrap();
+        assert_eq!(
+            digest,
+            "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_eq!(fs::read(store.path(&digest).unwrap()).unwrap(), b"{}");
+        assert_eq!(store.put(b"{}").unwrap(), digest);
+    }
+
+    #[test]
+    fn test_layer_blob_is_deterministic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let layer = sample_layer(tmp.path());
+
+        for compression in [LayerCompression::Gzip, LayerCompression::Zstd] {
+            let first = BlobStore::new(tmp.path().join("a"))
+                .layer("l1", &layer, compression)
+                .unwrap();
+            let second = BlobStore::new(tmp.path().join("b"))
+                .layer("l1", &layer, compression)
+                .unwrap();
+            assert_eq!(first, second);
+            assert_ne!(first.digest, first.diff_id);
+        }
+    }
+
+    #[test]
+    fn test_layer_blob_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let layer = sample_layer(tmp.path());
+        let store = BlobStore::new(tmp.path().join("blobs"));
+
+        let blob = store.layer("l1", &layer, LayerCompression::Gzip).unwrap();
+        let data = fs::read(store.path(&blob.digest).unwrap()).unwrap();
+        assert_eq!(data.len() as u64, blob.size);
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&data[..]));
+        let entries: Vec<(String, tar::EntryType)> = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (
+                    e.path().unwrap().to_string_lossy().into_owned(),
+                    e.header().entry_type(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (".wh.removed".to_string(), tar::EntryType::Regular),
+                ("etc/".to_string(), tar::EntryType::Directory),
+                ("etc/conf.d/".to_string(), tar::EntryType::Directory),
+                ("etc/conf.d/a".to_string(), tar::EntryType::Regular),
+                ("etc/hostname".to_string(), tar::EntryType::Regular),
+                ("etc/hostname.bak".to_string(), tar::EntryType::Link),
+                ("etc/name".to_string(), tar::EntryType::Symlink),
+            ]
+        );
+
+        // Second call is served from the index
+        fs::remove_dir_all(&layer).unwrap();
+        assert_eq!(
+            store.layer("l1", &layer, LayerCompression::Gzip).unwrap(),
+            blob
+        );
+    }
+
+    #[test]
+    fn test_overlay_whiteouts_become_oci_whiteouts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let layer = tmp.path().join("upper");
+        fs::create_dir_all(layer.join("etc")).unwrap();
+        let whiteout =
+            std::ffi::CString::new(layer.join("etc/motd").as_os_str().as_bytes()).unwrap();
+        // SAFETY: valid NUL-terminated path.
+        if unsafe { libc::mknod(whiteout.as_ptr(), libc::S_IFCHR | 0o600, 0) } != 0 {
+            // Creating device nodes needs CAP_MKNOD.
+            return;
+        }
+
+        let mut out = Vec::new();
+        write_tar(&layer, &mut out).unwrap();
+        let names: Vec<String> = tar::Archive::new(&out[..])
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["etc/", "etc/.wh.motd"]);
+    }
+}
@@ -0,0 +1,882 @@
+//! Image build steps (`smolvm build`).
+//!
+//! The host parses the Dockerfile and drives the build one step at a time
+//! with BuildStep requests. Each step starts from a stored image and stores
+//! its result as a new image:
+//!
+//! - RUN and COPY mount an overlay over the parent's layers, make their
+//!   changes, and turn the overlay's upper directory into a new layer.
+//! - Config steps (ENV, USER, CMD, ...) only rewrite the image config.
+//!
+//! Upper directories are used as layers as-is, so overlayfs whiteouts keep
+//! working when the image runs here; they are converted to OCI whiteouts
+//! when the image is pushed (see `blobs`).
+//!
+//! Build contexts are uploaded as tarballs and unpacked under
+//! `/storage/build/contexts/<id>`.
+
+use crate::blobs::{BlobStore, LayerBlob};
+use crate::oci::{generate_container_id, OciSpec, OciUser};
+use crate::paths;
+use crate::storage::{self, StorageError};
+use sha2::{Digest, Sha256};
+use smolvm_protocol::{BuildInstruction, BuildStepResult, CopySource, LayerCompression};
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info, warn};
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// Subdirectory of [`paths::BUILD_DIR`] holding unpacked build contexts.
+const CONTEXTS_DIR: &str = "contexts";
+
+/// Empty directory used as the only lower layer when building `FROM scratch`.
+const EMPTY_LOWER_DIR: &str = "empty";
+
+/// Maximum symlinks followed while resolving a path inside a rootfs.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+// ============================================================================
+// Build Contexts
+// ============================================================================
+
+/// Temporary path for an incoming build context archive.
+pub fn context_archive_path(id: &str) -> Result<PathBuf> {
+    validate_context_id(id)?;
+    let dir = Path::new(paths::BUILD_DIR).join(CONTEXTS_DIR);
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.tar", id)))
+}
+
+/// Unpack an uploaded context archive and remove the archive.
+pub fn install_context(id: &str, archive: &Path) -> Result<()> {
+    let dir = context_dir(id)?;
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+
+    let result = storage::unpack_tar(archive, &dir)
+        .map_err(|e| StorageError::new(format!("failed to unpack build context: {}", e)));
+    let _ = fs::remove_file(archive);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    result
+}
+
+/// Remove an uploaded build context.
+pub fn remove_context(id: &str) -> Result<()> {
+    let dir = context_dir(id)?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+fn context_dir(id: &str) -> Result<PathBuf> {
+    validate_context_id(id)?;
+    Ok(Path::new(paths::BUILD_DIR).join(CONTEXTS_DIR).join(id))
+}
+
+fn validate_context_id(id: &str) -> Result<()> {
+    if id.is_empty()
+        || id.len() > 128
+        || !id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(StorageError::ValidationFailed {
+            context: "build context id".into(),
+            reason: format!("invalid id: {:?}", id),
+        });
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Build Steps
+// ============================================================================
+
+/// Execute one build step on top of `parent` and store the result as `target`.
+pub fn build_step(
+    parent: Option<&str>,
+    target: &str,
+    step: &BuildInstruction,
+    created_by: &str,
+) -> Result<BuildStepResult> {
+    let (mut manifest, mut config) = match parent {
+        Some(p) => storage::read_image(p)?,
+        None => scratch_image(),
+    };
+    let layers: Vec<String> = manifest["layers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|l| l["digest"].as_str().map(String::from))
+        .collect();
+    let workload_id = format!("build-{}", sanitize_id(target));
+
+    info!(target = %target, step = %created_by, "executing build step");
+
+    let mut output = storage::RunResult {
+        exit_code: 0,
+        stdout: String::new(),
+        stderr: String::new(),
+    };
+    let layer = match step {
+        BuildInstruction::Run { command, env } => {
+            let (result, layer) = with_overlay(&layers, &workload_id, |rootfs, bundle| {
+                let result = run(&config, rootfs, bundle, command, env)?;
+                let ok = result.exit_code == 0;
+                Ok((result, ok))
+            })?;
+            if result.exit_code != 0 {
+                return Ok(BuildStepResult {
+                    exit_code: result.exit_code,
+                    stdout: result.stdout,
+                    stderr: result.stderr,
+                    image: None,
+                });
+            }
+            output = result;
+            layer
+        }
+        BuildInstruction::Copy {
+            source,
+            paths,
+            dest,
+            chown,
+            chmod,
+            extract,
+        } => {
+            let workdir = config["config"]["WorkingDir"].as_str().unwrap_or("/");
+            let dest = resolve_dest(workdir, dest);
+            with_overlay(&layers, &workload_id, |rootfs, _| {
+                let owner = match chown {
+                    Some(spec) => resolve_user(rootfs, spec)?,
+                    None => (0, 0),
+                };
+                let opts = CopyOptions {
+                    owner,
+                    chmod: *chmod,
+                    extract: *extract,
+                };
+                with_source_root(source, &workload_id, |src_root| {
+                    copy_sources(src_root, paths, rootfs, &dest, &opts)
+                })?;
+                Ok(((), true))
+            })?
+            .1
+        }
+        BuildInstruction::Config {
+            env,
+            workdir,
+            user,
+            entrypoint,
+            cmd,
+            labels,
+        } => {
+            let image_config = config_object(&mut config);
+            if !env.is_empty() {
+                let mut vars = json_strings(&image_config["Env"]);
+                for (key, value) in env {
+                    set_env(&mut vars, key, value);
+                }
+                image_config["Env"] = serde_json::json!(vars);
+            }
+            if let Some(user) = user {
+                image_config["User"] = serde_json::json!(user);
+            }
+            if let Some(entrypoint) = entrypoint {
+                image_config["Entrypoint"] = serde_json::json!(entrypoint);
+            }
+            if let Some(cmd) = cmd {
+                image_config["Cmd"] = if cmd.is_empty() {
+                    serde_json::Value::Null
+                } else {
+                    serde_json::json!(cmd)
+                };
+            }
+            if !labels.is_empty() {
+                if !image_config["Labels"].is_object() {
+                    image_config["Labels"] = serde_json::json!({});
+                }
+                for (key, value) in labels {
+                    image_config["Labels"][key] = serde_json::json!(value);
+                }
+            }
+
+            match workdir {
+                Some(dir) => {
+                    let current = image_config["WorkingDir"].as_str().unwrap_or("/");
+                    let dir = resolve_dest(current, dir);
+                    image_config["WorkingDir"] = serde_json::json!(dir);
+                    // WORKDIR creates the directory if the image lacks it.
+                    with_overlay(&layers, &workload_id, |rootfs, _| {
+                        let path = resolve_in_root(rootfs, &dir, true)?;
+                        fs::create_dir_all(path)?;
+                        Ok(((), true))
+                    })?
+                    .1
+                }
+                None => None,
+            }
+        }
+    };
+
+    // Record the step
+    let mut history = serde_json::json!({ "created_by": created_by });
+    match &layer {
+        Some(blob) => append_layer(&mut manifest, &mut config, blob),
+        None => history["empty_layer"] = serde_json::json!(true),
+    }
+    if !config["history"].is_array() {
+        config["history"] = serde_json::json!([]);
+    }
+    if let Some(entries) = config["history"].as_array_mut() {
+        entries.push(history);
+    }
+
+    let config_bytes =
+        serde_json::to_vec(&config).map_err(|e| StorageError::parse_error("config", e))?;
+    manifest["config"] = serde_json::json!({
+        "mediaType": storage::OCI_CONFIG_MEDIA_TYPE,
+        "digest": format!("sha256:{:x}", Sha256::digest(&config_bytes)),
+        "size": config_bytes.len(),
+    });
+    storage::store_image(target, &manifest.to_string(), &config_bytes)?;
+
+    // SAFETY: sync() is always safe to call
+    unsafe {
+        libc::sync();
+    }
+
+    let image = storage::query_image(target)?;
+    Ok(BuildStepResult {
+        exit_code: output.exit_code,
+        stdout: output.stdout,
+        stderr: output.stderr,
+        image,
+    })
+}
+
+/// Manifest and config of an empty image (`FROM scratch`).
+fn scratch_image() -> (serde_json::Value, serde_json::Value) {
+    #[cfg(target_arch = "aarch64")]
+    let arch = "arm64";
+    #[cfg(target_arch = "x86_64")]
+    let arch = "amd64";
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    let arch = "unknown";
+
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "layers": [],
+    });
+    let config = serde_json::json!({
+        "architecture": arch,
+        "os": "linux",
+        "config": {},
+        "rootfs": { "type": "layers", "diff_ids": [] },
+        "history": [],
+    });
+    (manifest, config)
+}
+
+/// Append a new layer to the manifest and the config's diff_ids.
+fn append_layer(
+    manifest: &mut serde_json::Value,
+    config: &mut serde_json::Value,
+    blob: &LayerBlob,
+) {
+    let descriptor = serde_json::json!({
+        "mediaType": LayerCompression::Gzip.media_type(),
+        "digest": blob.digest,
+        "size": blob.size,
+    });
+    match manifest["layers"].as_array_mut() {
+        Some(layers) => layers.push(descriptor),
+        None => manifest["layers"] = serde_json::json!([descriptor]),
+    }
+
+    match config["rootfs"]["diff_ids"].as_array_mut() {
+        Some(diff_ids) => diff_ids.push(serde_json::json!(blob.diff_id)),
+        None => {
+            config["rootfs"] = serde_json::json!({ "type": "layers", "diff_ids": [blob.diff_id] })
+        }
+    }
+}
+
+/// The `config` object of an image config, created if missing.
+fn config_object(config: &mut serde_json::Value) -> &mut serde_json::Value {
+    if !config["config"].is_object() {
+        config["config"] = serde_json::json!({});
+    }
+    &mut config["config"]
+}
+
+fn json_strings(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect()
+}
+
+/// Set `key` in a list of `KEY=VALUE` strings, replacing any existing value.
+fn set_env(vars: &mut Vec<String>, key: &str, value: &str) {
+    let entry = format!("{}={}", key, value);
+    match vars
+        .iter_mut()
+        .find(|v| v.split_once('=').map(|(k, _)| k) == Some(key))
+    {
+        Some(existing) => *existing = entry,
+        None => vars.push(entry),
+    }
+}
+
+/// Resolve a possibly relative destination against the working directory.
+fn resolve_dest(workdir: &str, dest: &str) -> String {
+    let trailing_slash = dest.ends_with('/') && dest.len() > 1;
+    let joined = if dest.starts_with('/') {
+        PathBuf::from(dest)
+    } else {
+        Path::new("/").join(workdir).join(dest)
+    };
+
+    let mut normalized = PathBuf::from("/");
+    for component in joined.components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+    let mut result = normalized.display().to_string();
+    if trailing_slash && result != "/" {
+        result.push('/');
+    }
+    result
+}
+
+fn sanitize_id(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// ============================================================================
+// Overlays
+// ============================================================================
+
+/// Mount an overlay over `layers`, call `f` with the merged rootfs and OCI
+/// bundle paths, then snapshot the upper directory as a layer.
+///
+/// `f` returns a value and whether to keep its changes. Returns the value
+/// and the new layer, or `None` if nothing changed.
+fn with_overlay<T>(
+    layers: &[String],
+    workload_id: &str,
+    f: impl FnOnce(&Path, &Path) -> Result<(T, bool)>,
+) -> Result<(T, Option<LayerBlob>)> {
+    let mut lowerdirs: Vec<String> = layers
+        .iter()
+        .rev()
+        .map(|digest| storage::layer_dir(digest).display().to_string())
+        .collect();
+    if lowerdirs.is_empty() {
+        let empty = Path::new(paths::BUILD_DIR).join(EMPTY_LOWER_DIR);
+        fs::create_dir_all(&empty)?;
+        lowerdirs.push(empty.display().to_string());
+    }
+
+    let overlay = storage::mount_overlay(workload_id, lowerdirs)?;
+    let rootfs = PathBuf::from(&overlay.rootfs_path);
+    let upper = PathBuf::from(&overlay.upper_path);
+    let bundle = rootfs.with_file_name("bundle");
+
+    let result = f(&rootfs, &bundle).and_then(|(value, keep)| {
+        unmount(&rootfs);
+        let layer = if keep { snapshot(&upper)? } else { None };
+        Ok((value, layer))
+    });
+
+    if let Err(e) = storage::cleanup_overlay(workload_id) {
+        warn!(workload_id = %workload_id, error = %e, "failed to clean up build overlay");
+    }
+    result
+}
+
+fn unmount(path: &Path) {
+    if let Err(e) = std::process::Command::new("umount").arg(path).status() {
+        debug!(path = %path.display(), error = %e, "failed to unmount build overlay");
+    }
+}
+
+/// Turn an overlay upper directory into a stored layer.
+fn snapshot(upper: &Path) -> Result<Option<LayerBlob>> {
+    // Drop what the overlay setup itself put into the upper layer.
+    let resolv = upper.join("etc/resolv.conf");
+    if fs::read_to_string(&resolv).ok().as_deref() == Some(&*crate::dns::resolv_conf()) {
+        let _ = fs::remove_file(&resolv);
+    }
+    for dir in ["etc", "dev"] {
+        // Only succeeds if empty
+        let _ = fs::remove_dir(upper.join(dir));
+    }
+
+    if fs::read_dir(upper)?.next().is_none() {
+        debug!("build step made no filesystem changes");
+        return Ok(None);
+    }
+
+    let store = BlobStore::new(paths::BLOBS_DIR);
+    let blob = store.write_layer(upper, LayerCompression::Gzip)?;
+    let layer_id = blob.digest.strip_prefix("sha256:").unwrap_or(&blob.digest);
+    store.record_layer(layer_id, LayerCompression::Gzip, &blob)?;
+
+    let layer_dir = storage::layer_dir(&blob.digest);
+    if fs::read_dir(&layer_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        debug!(layer = %layer_id, "identical layer already stored");
+    } else {
+        let _ = fs::remove_dir_all(&layer_dir);
+        fs::rename(upper, &layer_dir)?;
+    }
+
+    info!(layer = %layer_id, size = blob.size, "created build layer");
+    Ok(Some(blob))
+}
+
+// ============================================================================
+// RUN
+// ============================================================================
+
+fn run(
+    config: &serde_json::Value,
+    rootfs: &Path,
+    bundle: &Path,
+    command: &[String],
+    build_env: &[(String, String)],
+) -> Result<storage::RunResult> {
+    let image_config = &config["config"];
+    let workdir = image_config["WorkingDir"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("/");
+
+    let mut spec = OciSpec::new(command, &[], workdir, false);
+    // Image ENV wins over build args of the same name.
+    for (key, value) in build_env {
+        set_env(&mut spec.process.env, key, value);
+    }
+    for var in json_strings(&image_config["Env"]) {
+        if let Some((key, value)) = var.split_once('=') {
+            set_env(&mut spec.process.env, key, value);
+        }
+    }
+
+    if let Some(user) = image_config["User"].as_str().filter(|u| !u.is_empty()) {
+        let (uid, gid) = resolve_user(rootfs, user)?;
+        spec.process.user = OciUser {
+            uid,
+            gid,
+            additional_gids: vec![],
+        };
+    }
+
+    // crun needs the working directory to exist.
+    fs::create_dir_all(resolve_in_root(rootfs, workdir, true)?)?;
+
+    spec.write_to(bundle)
+        .map_err(|e| StorageError::new(format!("failed to write OCI spec: {}", e)))?;
+    storage::run_with_crun(bundle, &generate_container_id(), None)
+}
+
+/// Resolve `user[:group]` (names or IDs) against the rootfs's passwd/group.
+fn resolve_user(rootfs: &Path, spec: &str) -> Result<(u32, u32)> {
+    let (user, group) = match spec.split_once(':') {
+        Some((u, g)) => (u, Some(g)),
+        None => (spec, None),
+    };
+
+    let passwd = fs::read_to_string(rootfs.join("etc/passwd")).unwrap_or_default();
+    let (uid, primary_gid) = match user.parse::<u32>() {
+        Ok(uid) => {
+            let gid = lookup(&passwd, 2, &uid.to_string())
+                .and_then(|f| f[3].parse().ok())
+                .unwrap_or(uid);
+            (uid, gid)
+        }
+        Err(_) => lookup(&passwd, 0, user)
+            .and_then(|f| Some((f[2].parse().ok()?, f[3].parse().ok()?)))
+            .ok_or_else(|| StorageError::new(format!("unknown user: {}", user)))?,
+    };
+
+    let gid = match group {
+        None => primary_gid,
+        Some(g) => match g.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => {
+                let groups = fs::read_to_string(rootfs.join("etc/group")).unwrap_or_default();
+                lookup(&groups, 0, g)
+                    .and_then(|f| f[2].parse().ok())
+                    .ok_or_else(|| StorageError::new(format!("unknown group: {}", g)))?
+            }
+        },
+    };
+    Ok((uid, gid))
+}
+
+/// Find the colon-separated record whose field `index` equals `value`.
+/// Returns the record's fields if it has at least four.
+fn lookup<'a>(db: &'a str, index: usize, value: &str) -> Option<Vec<&'a str>> {
+    db.lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 4 && fields[index] == value)
+}
+
+// ============================================================================
+// COPY / ADD
+// ============================================================================
+
+struct CopyOptions {
+    owner: (u32, u32),
+    chmod: Option<u32>,
+    extract: bool,
+}
+
+/// Call `f` with the root directory of a COPY source.
+fn with_source_root<T>(
+    source: &CopySource,
+    workload_id: &str,
+    f: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    match source {
+        CopySource::Context { id } => {
+            let dir = context_dir(id)?;
+            if !dir.exists() {
+                return Err(StorageError::new(format!(
+                    "build context not found: {}",
+                    id
+                )));
+            }
+            f(&dir)
+        }
+        CopySource::Image { reference } => {
+            let info =
+                storage::query_image(reference)?.ok_or_else(|| StorageError::ImageNotFound {
+                    image: reference.clone(),
+                })?;
+            let lowerdirs = info
+                .layers
+                .iter()
+                .rev()
+                .map(|d| storage::layer_dir(d).display().to_string())
+                .collect();
+            let source_id = format!("{}-src", workload_id);
+            let overlay = storage::mount_overlay(&source_id, lowerdirs)?;
+            let result = f(Path::new(&overlay.rootfs_path));
+            if let Err(e) = storage::cleanup_overlay(&source_id) {
+                warn!(workload_id = %source_id, error = %e, "failed to clean up source overlay");
+            }
+            result
+        }
+    }
+}
+
+fn copy_sources(
+    src_root: &Path,
+    sources: &[String],
+    rootfs: &Path,
+    dest: &str,
+    opts: &CopyOptions,
+) -> Result<()> {
+    if sources.is_empty() {
+        return Err(StorageError::new("no source files to copy"));
+    }
+    let into_dir = dest.ends_with('/') || sources.len() > 1;
+
+    // Symlinks in the destination are resolved inside the rootfs here, so
+    // nothing below can write through one to the agent's own filesystem.
+    let dir_target = resolve_in_root(rootfs, dest, true)?;
+    let into_dir = into_dir || dir_target.is_dir();
+
+    for source in sources {
+        let src = resolve_in_root(src_root, source, false)?;
+        let meta = fs::symlink_metadata(&src)
+            .map_err(|_| StorageError::new(format!("source not found: {}", source)))?;
+
+        if meta.is_dir() {
+            let target = &dir_target;
+            // Directory contents are copied, not the directory itself.
+            create_dirs(target, opts)?;
+            for entry in fs::read_dir(&src)? {
+                let entry = entry?;
+                copy_entry(&entry.path(), &target.join(entry.file_name()), opts)?;
+            }
+        } else if opts.extract && meta.is_file() && is_archive(&src) {
+            create_dirs(&dir_target, opts)?;
+            storage::unpack_tar(&src, &dir_target)?;
+        } else {
+            let target = if into_dir {
+                create_dirs(&dir_target, opts)?;
+                dir_target.join(src.file_name().unwrap_or_default())
+            } else {
+                let target = resolve_in_root(rootfs, dest, false)?;
+                if let Some(parent) = target.parent() {
+                    create_dirs(parent, opts)?;
+                }
+                target
+            };
+            copy_entry(&src, &target, opts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file, symlink or directory tree, applying ownership and mode.
+fn copy_entry(src: &Path, dst: &Path, opts: &CopyOptions) -> Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    let existing = fs::symlink_metadata(dst).ok();
+
+    if meta.is_dir() {
+        if existing.as_ref().is_some_and(|m| !m.is_dir()) {
+            fs::remove_file(dst)?;
+        }
+        if !dst.exists() {
+            fs::create_dir(dst)?;
+        }
+        set_attributes(dst, meta.mode(), opts)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &dst.join(entry.file_name()), opts)?;
+        }
+        return Ok(());
+    }
+
+    // Never write through an existing symlink.
+    if let Some(existing) = existing {
+        if existing.is_dir() {
+            fs::remove_dir_all(dst)?;
+        } else {
+            fs::remove_file(dst)?;
+        }
+    }
+
+    if meta.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+        lchown(dst, opts.owner)?;
+    } else if meta.is_file() {
+        fs::copy(src, dst)?;
+        set_attributes(dst, meta.mode(), opts)?;
+    } else {
+        debug!(path = %src.display(), "skipping special file");
+    }
+    Ok(())
+}
+
+/// Create missing directories, owned like copied files.
+fn create_dirs(path: &Path, opts: &CopyOptions) -> Result<()> {
+    let mut missing = Vec::new();
+    let mut current = path;
+    while !current.exists() {
+        missing.push(current.to_path_buf());
+        match current.parent() {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+    for dir in missing.iter().rev() {
+        fs::create_dir(dir)?;
+        set_attributes(dir, 0o755, opts)?;
+    }
+    Ok(())
+}
+
+fn set_attributes(path: &Path, mode: u32, opts: &CopyOptions) -> Result<()> {
+    lchown(path, opts.owner)?;
+    let mode = opts.chmod.unwrap_or(mode & 0o7777);
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+fn lchown(path: &Path, (uid, gid): (u32, u32)) -> Result<()> {
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+    Ok(())
+}
+
+/// Whether a file is a (possibly compressed) tar archive that ADD unpacks.
+fn is_archive(path: &Path) -> bool {
+    let mut header = [0u8; 512];
+    let Ok(n) = fs::File::open(path).and_then(|mut f| f.read(&mut header)) else {
+        return false;
+    };
+    match &header[..n] {
+        [0x1f, 0x8b, ..] | [0x28, 0xb5, 0x2f, 0xfd, ..] => true,
+        h => h.len() >= 262 && &h[257..262] == b"ustar",
+    }
+}
+
+/// Resolve `path` inside `root` as if `root` were `/`, following symlinks
+/// without ever leaving `root`. The last component is only followed if
+/// `follow_last` is set.
+fn resolve_in_root(root: &Path, path: &str, follow_last: bool) -> Result<PathBuf> {
+    let mut pending: Vec<std::ffi::OsString> = Path::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c.to_os_string()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .rev()
+        .collect();
+    let mut resolved: Vec<std::ffi::OsString> = Vec::new();
+    let mut hops = 0;
+
+    while let Some(component) = pending.pop() {
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+        let candidate: PathBuf = std::iter::once(root.as_os_str())
+            .chain(resolved.iter().map(|c| c.as_os_str()))
+            .chain(std::iter::once(component.as_os_str()))
+            .collect();
+
+        let is_last = pending.is_empty();
+        match fs::symlink_metadata(&candidate) {
+            Ok(meta) if meta.file_type().is_symlink() && (follow_last || !is_last) => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(StorageError::new(format!(
+                        "too many levels of symbolic links: {}",
+                        path
+                    )));
+                }
+                let link = fs::read_link(&candidate)?;
+                if link.is_absolute() {
+                    resolved.clear();
+                }
+                for c in link.components().rev() {
+                    match c {
+                        Component::Normal(c) => pending.push(c.to_os_string()),
+                        Component::ParentDir => pending.push("..".into()),
+                        _ => {}
+                    }
+                }
+            }
+            _ => resolved.push(component),
+        }
+    }
+
+    Ok(std::iter::once(root.as_os_str())
+        .chain(resolved.iter().map(|c| c.as_os_str()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_dest() {
+        assert_eq!(resolve_dest("/", "app"), "/app");
+        assert_eq!(resolve_dest("/srv", "app/"), "/srv/app/");
+        assert_eq!(resolve_dest("/srv", "/opt/x"), "/opt/x");
+        assert_eq!(resolve_dest("/srv", "../../etc"), "/etc");
+        assert_eq!(resolve_dest("/srv", "."), "/srv");
+    }
+
+    #[test]
+    fn test_resolve_in_root_stays_inside() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::os::unix::fs::symlink("/usr/bin", root.join("bin")).unwrap();
+        std::os::unix::fs::symlink("../../../../etc", root.join("usr/escape")).unwrap();
+
+        assert_eq!(
+            resolve_in_root(root, "/bin/sh", false).unwrap(),
+            root.join("usr/bin/sh")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/usr/escape/passwd", false).unwrap(),
+            root.join("etc/passwd")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/../x", false).unwrap(),
+            root.join("x")
+        );
+        // The last component is only followed on request
+        assert_eq!(
+            resolve_in_root(root, "/bin", false).unwrap(),
+            root.join("bin")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/bin", true).unwrap(),
+            root.join("usr/bin")
+        );
+    }
+
+    #[test]
+    fn test_resolve_user() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(
+            root.join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1001::/home/app:/bin/sh\n",
+        )
+        .unwrap();
+        fs::write(root.join("etc/group"), "root:x:0:\nstaff:x:50:app\n").unwrap();
+
+        assert_eq!(resolve_user(root, "app").unwrap(), (1000, 1001));
+        assert_eq!(resolve_user(root, "app:staff").unwrap(), (1000, 50));
+        assert_eq!(resolve_user(root, "1000").unwrap(), (1000, 1001));
+        assert_eq!(resolve_user(root, "2000:3000").unwrap(), (2000, 3000));
+        assert!(resolve_user(root, "nobody").is_err());
+    }
+
+    #[test]
+    fn test_copy_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("ctx");
+        let rootfs = tmp.path().join("rootfs");
+        fs::create_dir_all(src.join("dir/sub")).unwrap();
+        fs::write(src.join("dir/sub/a.txt"), "a").unwrap();
+        fs::write(src.join("main.sh"), "#!/bin/sh").unwrap();
+        fs::create_dir_all(rootfs.join("usr/bin")).unwrap();
+        std::os::unix::fs::symlink("/usr/bin", rootfs.join("bin")).unwrap();
+
+        let opts = CopyOptions {
+            owner: (0, 0),
+            chmod: Some(0o750),
+            extract: false,
+        };
+        // Directory contents land in dest
+        copy_sources(&src, &["dir".into()], &rootfs, "/app", &opts).unwrap();
+        assert!(rootfs.join("app/sub/a.txt").exists());
+        // A file copied to an existing directory through a symlink
+        copy_sources(&src, &["main.sh".into()], &rootfs, "/bin/", &opts).unwrap();
+        let meta = fs::metadata(rootfs.join("usr/bin/main.sh")).unwrap();
+        assert_eq!(meta.mode() & 0o777, 0o750);
+        // A file copied to a new name
+        copy_sources(&src, &["main.sh".into()], &rootfs, "/opt/run", &opts).unwrap();
+        assert!(rootfs.join("opt/run").is_file());
+        // Sources can't escape the context
+        copy_sources(&src, &["../rootfs/opt/run".into()], &rootfs, "/x", &opts).unwrap_err();
+    }
+
+    #[test]
+    fn test_set_env_replaces() {
+        let mut vars = vec!["PATH=/bin".to_string(), "A=1".to_string()];
+        set_env(&mut vars, "A", "2");
+        set_env(&mut vars, "B", "x=y");
+        assert_eq!(vars, vec!["PATH=/bin", "A=2", "B=x=y"]);
+    }
+}
@@ -22,6 +22,7 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use smolvm_protocol::ContainerMount;
 use tracing::{debug, info, warn};
 
 use crate::crun::CrunCommand;
@@ -665,7 +666,7 @@ pub fn create_container(
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
+    mounts: &[ContainerMount],
 ) -> Result<ContainerInfo, StorageError> {
     // Validate inputs before proceeding
     validate_container_params(image, command, workdir)?;
@@ -683,6 +684,14 @@ pub fn create_container(
     // Setup volume mounts
     storage::setup_mounts(&overlay.rootfs_path, mounts)?;
 
+    // Trust the VM's custom CA certificates
+    crate::proxy::install_ca_certs_into(Path::new(&overlay.rootfs_path))
+        .map_err(|e| StorageError::new(format!("failed to install CA certificates: {}", e)))?;
+
+    // Apply the VM's DNS settings
+    crate::dns::install_into(Path::new(&overlay.rootfs_path))
+        .map_err(|e| StorageError::new(format!("failed to apply DNS settings: {}", e)))?;
+
     // Get bundle path
     let bundle_path = paths::bundle_dir(&workload_id);
 
@@ -690,15 +699,8 @@ pub fn create_container(
     let workdir_str = workdir.unwrap_or("/");
     let mut spec = OciSpec::new(command, env, workdir_str, false);
 
-    // Add bind mounts for virtiofs volumes
-    for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        spec.add_bind_mount(
-            &virtiofs_mount.to_string_lossy(),
-            container_path,
-            *read_only,
-        );
-    }
+    // Add bind and tmpfs mounts to OCI spec
+    spec.add_container_mounts(mounts);
 
     // Write config.json
     spec.write_to(&bundle_path)
@@ -1267,6 +1269,19 @@ pub fn list_containers() -> Vec<ContainerInfo> {
     containers
 }
 
+/// Registered containers with the storage they hold on to.
+pub fn storage_refs() -> Vec<storage::ContainerRef> {
+    list_containers()
+        .into_iter()
+        .map(|info| storage::ContainerRef {
+            workload_id: format!("container-{}", &info.id),
+            id: info.id,
+            image: info.image,
+            state: info.state.to_string(),
+        })
+        .collect()
+}
+
 /// Check if the overlay is mounted at the given path.
 fn is_overlay_mounted(merged_path: &Path) -> bool {
     paths::is_mount_point(merged_path)
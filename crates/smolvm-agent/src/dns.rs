@@ -0,0 +1,287 @@
+//! DNS configuration for the guest and its containers.
+//!
+//! `/etc/resolv.conf` points at, in order of precedence: the private
+//! network's gateway, the host resolver forwarder on loopback, the
+//! nameservers from the host's [`DnsConfig`], or public defaults. Search
+//! domains and options from the config are added, and its host entries go
+//! into a marked block of `/etc/hosts`. [`install_into`] applies the same
+//! files to a container rootfs.
+//!
+//! With `host_resolver`, queries to `127.0.0.1:53` are relayed over vsock
+//! to the host, which answers them with its system resolver.
+
+use crate::vsock::VsockStream;
+use parking_lot::RwLock;
+use smolvm_protocol::{cid, ports, DnsConfig, HostEntry};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::Once;
+use tracing::{debug, info, warn};
+
+/// Nameservers used when nothing else is configured.
+const DEFAULT_NAMESERVERS: &[&str] = &["8.8.8.8", "1.1.1.1"];
+
+/// Loopback address of the host resolver forwarder.
+const RESOLVER_ADDR: &str = "127.0.0.1";
+
+/// Largest DNS message relayed from the host.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Markers around the entries smolvm manages in `/etc/hosts`.
+const HOSTS_BEGIN: &str = "# BEGIN smolvm hosts";
+const HOSTS_END: &str = "# END smolvm hosts";
+
+/// `/etc/hosts` for a rootfs that has none.
+const DEFAULT_HOSTS: &str = "127.0.0.1\tlocalhost\n::1\tlocalhost\n";
+
+lazy_static::lazy_static! {
+    /// Current DNS configuration (None until the host configures one).
+    static ref CONFIG: RwLock<Option<DnsConfig>> = RwLock::new(None);
+}
+
+static RESOLVER: Once = Once::new();
+
+/// resolv.conf for the VM and its containers.
+pub fn resolv_conf() -> String {
+    let config = CONFIG.read();
+    let config = config.as_ref();
+
+    let mut search = Vec::new();
+    let nameservers: Vec<String> = if let Some((gateway, domain)) = crate::network::dns() {
+        if !domain.is_empty() {
+            search.push(domain.to_string());
+        }
+        vec![gateway.to_string()]
+    } else if config.is_some_and(host_resolver_enabled) {
+        vec![RESOLVER_ADDR.to_string()]
+    } else if let Some(config) = config.filter(|c| !c.servers.is_empty()) {
+        config.servers.iter().map(ToString::to_string).collect()
+    } else {
+        DEFAULT_NAMESERVERS
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    };
+
+    if let Some(config) = config {
+        search.extend(config.search.iter().cloned());
+    }
+    let options = config.map(|c| c.options.as_slice()).unwrap_or_default();
+    render_resolv_conf(&nameservers, &search, options)
+}
+
+fn render_resolv_conf(nameservers: &[String], search: &[String], options: &[String]) -> String {
+    let mut out = String::new();
+    if !search.is_empty() {
+        out.push_str(&format!("search {}\n", search.join(" ")));
+    }
+    for nameserver in nameservers {
+        out.push_str(&format!("nameserver {}\n", nameserver));
+    }
+    if !options.is_empty() {
+        out.push_str(&format!("options {}\n", options.join(" ")));
+    }
+    out
+}
+
+/// The host resolver is unused when egress goes through the allowlist
+/// proxy, which resolves names itself.
+fn host_resolver_enabled(config: &DnsConfig) -> bool {
+    config.host_resolver && !crate::egress::enabled()
+}
+
+/// Apply a DNS configuration, replacing any previous one.
+pub fn configure(config: DnsConfig) -> io::Result<()> {
+    let words = config.search.iter().chain(&config.options);
+    let names = config.hosts.iter().map(|h| &h.name);
+    if let Some(bad) = words
+        .chain(names)
+        .find(|s| s.is_empty() || s.contains(char::is_whitespace))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid DNS setting: '{}'", bad),
+        ));
+    }
+
+    if host_resolver_enabled(&config) {
+        RESOLVER.call_once(start_resolver);
+    }
+
+    info!(
+        servers = config.servers.len(),
+        search = config.search.len(),
+        hosts = config.hosts.len(),
+        host_resolver = config.host_resolver,
+        "DNS configuration applied"
+    );
+    *CONFIG.write() = Some(config);
+
+    // Only an overlayed root is private to this VM; without one, / is the
+    // host's shared rootfs directory and must not be modified.
+    if Path::new("/oldroot").exists() {
+        install_into(Path::new("/"))?;
+    }
+    Ok(())
+}
+
+/// Write the configured resolv.conf and hosts entries into a rootfs.
+pub fn install_into(rootfs: &Path) -> io::Result<()> {
+    let hosts = match CONFIG.read().as_ref() {
+        Some(config) => config.hosts.clone(),
+        None => return Ok(()),
+    };
+
+    let etc = rootfs.join("etc");
+    std::fs::create_dir_all(&etc)?;
+    write_regular_file(&etc.join("resolv.conf"), &resolv_conf())?;
+
+    let hosts_path = etc.join("hosts");
+    let existing = match std::fs::symlink_metadata(&hosts_path) {
+        Ok(meta) if meta.is_file() => Some(std::fs::read_to_string(&hosts_path)?),
+        _ => None,
+    };
+    if existing.is_none() && hosts.is_empty() {
+        return Ok(());
+    }
+    let existing = existing.as_deref().unwrap_or(DEFAULT_HOSTS);
+    let updated = update_hosts(existing, &hosts);
+    if updated != existing {
+        write_regular_file(&hosts_path, &updated)?;
+    }
+    Ok(())
+}
+
+/// Write a file, replacing a symlink (which may point outside the rootfs
+/// when resolved from the agent) rather than following it.
+fn write_regular_file(path: &Path, content: &str) -> io::Result<()> {
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink()) {
+        std::fs::remove_file(path)?;
+    }
+    std::fs::write(path, content)
+}
+
+/// Replace the smolvm-managed block of a hosts file with `entries`.
+fn update_hosts(existing: &str, entries: &[HostEntry]) -> String {
+    let mut out = String::new();
+    let mut in_block = false;
+    for line in existing.lines() {
+        match line {
+            HOSTS_BEGIN => in_block = true,
+            HOSTS_END => in_block = false,
+            _ if !in_block => {
+                out.push_str(line);
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    if !entries.is_empty() {
+        out.push_str(HOSTS_BEGIN);
+        out.push('\n');
+        for entry in entries {
+            out.push_str(&format!("{}\t{}\n", entry.address, entry.name));
+        }
+        out.push_str(HOSTS_END);
+        out.push('\n');
+    }
+    out
+}
+
+/// Relay queries on the loopback resolver address to the host.
+fn start_resolver() {
+    crate::egress::bring_up_loopback();
+
+    let socket = match UdpSocket::bind((RESOLVER_ADDR, 53)) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!(error = %e, "failed to bind host resolver forwarder");
+            return;
+        }
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name("dns-forward".to_string())
+        .spawn(move || {
+            let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+            loop {
+                let (n, client) = match socket.recv_from(&mut buf) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        debug!(error = %e, "host resolver forwarder receive failed");
+                        continue;
+                    }
+                };
+                let (query, socket) = (buf[..n].to_vec(), socket.try_clone());
+                std::thread::spawn(move || {
+                    if let Err(e) = socket.and_then(|s| forward(&s, &query, client)) {
+                        debug!(error = %e, "host resolver query failed");
+                    }
+                });
+            }
+        });
+    match spawned {
+        Ok(_) => info!(addr = RESOLVER_ADDR, "host resolver forwarder started"),
+        Err(e) => warn!(error = %e, "failed to start host resolver forwarder"),
+    }
+}
+
+/// Resolve one query on the host and send the answer back to the client.
+fn forward(socket: &UdpSocket, query: &[u8], client: SocketAddr) -> io::Result<()> {
+    let mut host = VsockStream::connect(cid::HOST, ports::DNS_RESOLVER)?;
+    host.write_all(&(query.len() as u16).to_be_bytes())?;
+    host.write_all(query)?;
+
+    let mut len = [0u8; 2];
+    host.read_exact(&mut len)?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    host.read_exact(&mut response)?;
+    socket.send_to(&response, client)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_resolv_conf() {
+        let nameservers = vec!["10.0.0.2".to_string()];
+        let search = vec!["dev".to_string(), "corp.example.com".to_string()];
+        let options = vec!["ndots:2".to_string()];
+        assert_eq!(
+            render_resolv_conf(&nameservers, &search, &options),
+            "search dev corp.example.com\nnameserver 10.0.0.2\noptions ndots:2\n"
+        );
+        assert_eq!(
+            render_resolv_conf(&nameservers, &[], &[]),
+            "nameserver 10.0.0.2\n"
+        );
+    }
+
+    #[test]
+    fn test_update_hosts_replaces_managed_block() {
+        let db = HostEntry {
+            name: "db".into(),
+            address: "10.0.0.5".parse().unwrap(),
+        };
+        let once = update_hosts(DEFAULT_HOSTS, std::slice::from_ref(&db));
+        assert!(once.starts_with(DEFAULT_HOSTS));
+        assert!(once.contains("10.0.0.5\tdb\n"));
+
+        // Re-applying doesn't duplicate; new entries replace old ones
+        assert_eq!(update_hosts(&once, &[db]), once);
+        let cache = HostEntry {
+            name: "cache".into(),
+            address: "fd00::1".parse().unwrap(),
+        };
+        let replaced = update_hosts(&once, &[cache]);
+        assert!(!replaced.contains("\tdb\n"));
+        assert!(replaced.contains("fd00::1\tcache\n"));
+
+        // No entries removes the block entirely
+        assert_eq!(update_hosts(&replaced, &[]), DEFAULT_HOSTS);
+    }
+}
@@ -0,0 +1,140 @@
+//! Egress proxy forwarding for allowlisted networking.
+//!
+//! When the host boots the VM with an egress allowlist it disables TSI and
+//! sets `SMOLVM_EGRESS_PROXY=1`. The agent then listens on a loopback HTTP
+//! proxy port and forwards each connection over vsock to the host-side
+//! proxy, which enforces the allowlist. Proxy environment variables point
+//! the agent's own tools (crane) and every workload at that port.
+
+use crate::vsock::VsockStream;
+use smolvm_protocol::{cid, ports};
+use std::io;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Environment variable set by the host launcher when an allowlist is active.
+const ENV_EGRESS_PROXY: &str = "SMOLVM_EGRESS_PROXY";
+
+/// Loopback address of the in-guest proxy port.
+const PROXY_LISTEN_ADDR: &str = "127.0.0.1:3128";
+
+/// Proxy URL advertised to workloads.
+const PROXY_URL: &str = "http://127.0.0.1:3128";
+
+/// Destinations that bypass the proxy.
+const NO_PROXY: &str = "localhost,127.0.0.1,::1";
+
+/// Whether this VM routes egress through the host proxy.
+pub fn enabled() -> bool {
+    std::env::var_os(ENV_EGRESS_PROXY).is_some()
+}
+
+/// Proxy environment variables for workloads (empty when egress is not proxied).
+pub fn proxy_env() -> Vec<(String, String)> {
+    if !enabled() {
+        return Vec::new();
+    }
+    [
+        ("HTTP_PROXY", PROXY_URL),
+        ("HTTPS_PROXY", PROXY_URL),
+        ("http_proxy", PROXY_URL),
+        ("https_proxy", PROXY_URL),
+        ("NO_PROXY", NO_PROXY),
+        ("no_proxy", NO_PROXY),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+
+/// Start the proxy forwarder if the host enabled it.
+///
+/// Also exports the proxy variables into the agent's own environment so
+/// image pulls and `vm_exec` children go through the allowlist.
+pub fn start() {
+    if !enabled() {
+        return;
+    }
+
+    bring_up_loopback();
+
+    for (key, value) in proxy_env() {
+        std::env::set_var(key, value);
+    }
+
+    let listener = match TcpListener::bind(PROXY_LISTEN_ADDR) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!(error = %e, addr = PROXY_LISTEN_ADDR, "failed to bind egress proxy port");
+            return;
+        }
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name("egress-forward".to_string())
+        .spawn(move || {
+            for conn in listener.incoming() {
+                match conn {
+                    Ok(client) => {
+                        std::thread::spawn(move || {
+                            if let Err(e) = forward(client) {
+                                debug!(error = %e, "egress forward failed");
+                            }
+                        });
+                    }
+                    Err(e) => debug!(error = %e, "egress proxy accept failed"),
+                }
+            }
+        });
+    match spawned {
+        Ok(_) => info!(addr = PROXY_LISTEN_ADDR, "egress proxy forwarder started"),
+        Err(e) => warn!(error = %e, "failed to start egress proxy forwarder"),
+    }
+}
+
+/// Pipe a loopback proxy connection to the host egress proxy over vsock.
+fn forward(client: TcpStream) -> io::Result<()> {
+    let host = VsockStream::connect(cid::HOST, ports::EGRESS_PROXY)?;
+
+    let mut client_read = client.try_clone()?;
+    let mut host_write = host.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut host_write);
+        let _ = host_write.shutdown_write();
+    });
+
+    let (mut client, mut host) = (client, host);
+    let _ = io::copy(&mut host, &mut client);
+    let _ = client.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+/// Bring the loopback interface up so the proxy port is reachable.
+///
+/// Without TSI nothing else configures `lo` inside the guest.
+#[cfg(target_os = "linux")]
+pub(crate) fn bring_up_loopback() {
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            warn!(error = %io::Error::last_os_error(), "failed to open socket for loopback setup");
+            return;
+        }
+
+        let mut req: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in req.ifr_name.iter_mut().zip(b"lo\0") {
+            *dst = *src as libc::c_char;
+        }
+
+        if libc::ioctl(fd, libc::SIOCGIFFLAGS, &mut req) == 0 {
+            req.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            if libc::ioctl(fd, libc::SIOCSIFFLAGS, &req) < 0 {
+                warn!(error = %io::Error::last_os_error(), "failed to bring up loopback");
+            }
+        }
+        libc::close(fd);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn bring_up_loopback() {}
@@ -0,0 +1,392 @@
+//! Reading image archives for `ImportImage`.
+//!
+//! Two archive formats are understood, both already unpacked to a directory:
+//!
+//! - `docker save` archives: `manifest.json` lists each image's config file,
+//!   layer tarballs and `RepoTags`. Blobs are not addressed by digest, so
+//!   digests are computed and an OCI manifest is synthesized.
+//! - OCI image layouts: `index.json` points at manifests (or nested indexes)
+//!   in `blobs/<alg>/<hex>`. Image names come from the descriptor
+//!   annotations.
+//!
+//! Archives written by Docker 25+ and `smolvm image save` contain both;
+//! `manifest.json` is preferred because its `RepoTags` match the names users
+//! typed.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Annotation holding the full image name (containerd, Docker 25+).
+const ANNOTATION_IMAGE_NAME: &str = "io.containerd.image.name";
+
+/// Standard OCI annotation holding the image reference name.
+const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// OCI image manifest media type.
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// OCI image config media type.
+const OCI_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
+/// An image found in an archive.
+#[derive(Debug)]
+pub struct ArchiveImage {
+    /// Names recorded for the image (may be empty).
+    pub names: Vec<String>,
+    /// Image manifest JSON, in the form stored under `manifests/`.
+    pub manifest: String,
+    /// Raw config JSON.
+    pub config: Vec<u8>,
+    /// Layers in order.
+    pub layers: Vec<ArchiveLayer>,
+}
+
+/// A layer blob inside an unpacked archive.
+#[derive(Debug)]
+pub struct ArchiveLayer {
+    /// Blob digest (sha256:...), as referenced by the manifest.
+    pub digest: String,
+    /// Path to the (possibly compressed) layer tarball.
+    pub path: PathBuf,
+}
+
+/// Read all images from an unpacked archive.
+///
+/// `arch` is the OCI architecture (e.g. "arm64") used to pick a manifest
+/// from multi-platform indexes.
+pub fn read_archive(dir: &Path, arch: &str) -> io::Result<Vec<ArchiveImage>> {
+    let images = if dir.join("manifest.json").exists() {
+        read_docker_archive(dir)?
+    } else if dir.join("index.json").exists() {
+        read_oci_layout(dir, arch)?
+    } else {
+        return Err(invalid(
+            "not an image archive (no manifest.json or index.json)",
+        ));
+    };
+
+    if images.is_empty() {
+        return Err(invalid("archive contains no images"));
+    }
+    Ok(images)
+}
+
+// ============================================================================
+// docker save
+// ============================================================================
+
+fn read_docker_archive(dir: &Path) -> io::Result<Vec<ArchiveImage>> {
+    let entries: Vec<serde_json::Value> = read_json(&dir.join("manifest.json"))?;
+
+    let mut images = Vec::new();
+    for entry in entries {
+        let config_path = entry["Config"]
+            .as_str()
+            .ok_or_else(|| invalid("manifest.json entry has no Config"))?;
+        let config = fs::read(archive_path(dir, config_path)?)?;
+        let config_digest = format!("sha256:{:x}", Sha256::digest(&config));
+
+        let mut layers = Vec::new();
+        let mut descriptors = Vec::new();
+        for layer in entry["Layers"].as_array().into_iter().flatten() {
+            let path = archive_path(dir, layer.as_str().unwrap_or_default())?;
+            let (digest, size) = file_digest(&path)?;
+            descriptors.push(serde_json::json!({
+                "mediaType": layer_media_type(&path)?,
+                "digest": digest,
+                "size": size,
+            }));
+            layers.push(ArchiveLayer { digest, path });
+        }
+
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+            "config": {
+                "mediaType": OCI_CONFIG_MEDIA_TYPE,
+                "digest": config_digest,
+                "size": config.len(),
+            },
+            "layers": descriptors,
+        });
+
+        let names = entry["RepoTags"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t.as_str().map(String::from))
+            .collect();
+
+        images.push(ArchiveImage {
+            names,
+            manifest: manifest.to_string(),
+            config,
+            layers,
+        });
+    }
+
+    Ok(images)
+}
+
+/// Media type for a layer tarball, sniffed from its magic bytes.
+fn layer_media_type(path: &Path) -> io::Result<&'static str> {
+    let mut magic = [0u8; 4];
+    let n = fs::File::open(path)?.read(&mut magic)?;
+    Ok(match &magic[..n] {
+        [0x1f, 0x8b, ..] => "application/vnd.oci.image.layer.v1.tar+gzip",
+        [0x28, 0xb5, 0x2f, 0xfd] => "application/vnd.oci.image.layer.v1.tar+zstd",
+        _ => "application/vnd.oci.image.layer.v1.tar",
+    })
+}
+
+// ============================================================================
+// OCI image layout
+// ============================================================================
+
+fn read_oci_layout(dir: &Path, arch: &str) -> io::Result<Vec<ArchiveImage>> {
+    let index: serde_json::Value = read_json(&dir.join("index.json"))?;
+
+    let mut images = Vec::new();
+    for descriptor in index["manifests"].as_array().into_iter().flatten() {
+        let annotations = &descriptor["annotations"];
+        let names: Vec<String> = annotations[ANNOTATION_IMAGE_NAME]
+            .as_str()
+            .or(annotations[ANNOTATION_REF_NAME].as_str())
+            .map(String::from)
+            .into_iter()
+            .collect();
+
+        let manifest = resolve_manifest(dir, descriptor, arch)?;
+        images.push(read_oci_image(dir, names, manifest)?);
+    }
+
+    Ok(images)
+}
+
+/// Follow a descriptor down to an image manifest, choosing the platform
+/// matching `arch` from any image indexes on the way.
+fn resolve_manifest(dir: &Path, descriptor: &serde_json::Value, arch: &str) -> io::Result<String> {
+    let digest = descriptor["digest"]
+        .as_str()
+        .ok_or_else(|| invalid("descriptor has no digest"))?;
+    let manifest = String::from_utf8(read_blob(dir, digest)?)
+        .map_err(|_| invalid("manifest is not valid UTF-8"))?;
+    let json: serde_json::Value = serde_json::from_str(&manifest)
+        .map_err(|e| invalid(&format!("invalid manifest: {}", e)))?;
+
+    let Some(manifests) = json["manifests"].as_array() else {
+        return Ok(manifest);
+    };
+
+    let entry = manifests
+        .iter()
+        .find(|m| m["platform"]["architecture"].as_str() == Some(arch))
+        .ok_or_else(|| {
+            invalid(&format!(
+                "image index has no manifest for architecture {}",
+                arch
+            ))
+        })?;
+    resolve_manifest(dir, entry, arch)
+}
+
+fn read_oci_image(dir: &Path, names: Vec<String>, manifest: String) -> io::Result<ArchiveImage> {
+    let json: serde_json::Value = serde_json::from_str(&manifest)
+        .map_err(|e| invalid(&format!("invalid manifest: {}", e)))?;
+
+    let config_digest = json["config"]["digest"]
+        .as_str()
+        .ok_or_else(|| invalid("manifest has no config digest"))?
+        .to_string();
+    let config = read_blob(dir, &config_digest)?;
+
+    let mut layers = Vec::new();
+    for layer in json["layers"].as_array().into_iter().flatten() {
+        let digest = layer["digest"]
+            .as_str()
+            .ok_or_else(|| invalid("layer descriptor has no digest"))?;
+        let path = blob_path(dir, digest)?;
+        let (actual, _) = file_digest(&path)?;
+        if actual != digest {
+            return Err(invalid(&format!("blob {} is corrupt", digest)));
+        }
+        layers.push(ArchiveLayer {
+            digest: digest.to_string(),
+            path,
+        });
+    }
+
+    Ok(ArchiveImage {
+        names,
+        manifest,
+        config,
+        layers,
+    })
+}
+
+/// Path of a blob in an OCI layout.
+fn blob_path(dir: &Path, digest: &str) -> io::Result<PathBuf> {
+    let (alg, hex) = digest
+        .split_once(':')
+        .filter(|(alg, hex)| *alg == "sha256" && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| invalid(&format!("unsupported digest: {}", digest)))?;
+    Ok(dir.join("blobs").join(alg).join(hex))
+}
+
+/// Read a small blob (manifest or config) and verify its digest.
+fn read_blob(dir: &Path, digest: &str) -> io::Result<Vec<u8>> {
+    let data = fs::read(blob_path(dir, digest)?)?;
+    if format!("sha256:{:x}", Sha256::digest(&data)) != digest {
+        return Err(invalid(&format!("blob {} is corrupt", digest)));
+    }
+    Ok(data)
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Resolve a path named inside the archive, refusing to leave it.
+fn archive_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(name);
+    if name.is_empty()
+        || relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid(&format!("invalid path in archive: {}", name)));
+    }
+    Ok(dir.join(relative))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data)
+        .map_err(|e| invalid(&format!("invalid {}: {}", path.display(), e)))
+}
+
+/// Compute the `sha256:` digest and size of a file.
+fn file_digest(path: &Path) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(data: &[u8]) -> String {
+        format!("sha256:{:x}", Sha256::digest(data))
+    }
+
+    fn write_blob(dir: &Path, data: &[u8]) -> String {
+        let d = digest(data);
+        let path = dir.join("blobs/sha256").join(&d[7..]);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+        d
+    }
+
+    #[test]
+    fn test_read_docker_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("abc")).unwrap();
+        fs::write(dir.join("abc/layer.tar"), b"plain tar").unwrap();
+        fs::write(dir.join("cfg.json"), br#"{"os":"linux"}"#).unwrap();
+        fs::write(
+            dir.join("manifest.json"),
+            r#"[{"Config":"cfg.json","RepoTags":["app:1.0"],"Layers":["abc/layer.tar"]}]"#,
+        )
+        .unwrap();
+
+        let images = read_archive(dir, "arm64").unwrap();
+        assert_eq!(images.len(), 1);
+        let image = &images[0];
+        assert_eq!(image.names, vec!["app:1.0"]);
+        assert_eq!(image.layers[0].digest, digest(b"plain tar"));
+
+        let manifest: serde_json::Value = serde_json::from_str(&image.manifest).unwrap();
+        assert_eq!(
+            manifest["config"]["digest"],
+            digest(br#"{"os":"linux"}"#).as_str()
+        );
+        assert_eq!(
+            manifest["layers"][0]["mediaType"],
+            "application/vnd.oci.image.layer.v1.tar"
+        );
+    }
+
+    #[test]
+    fn test_read_oci_layout_with_index() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let layer = write_blob(dir, b"layer");
+        let config = write_blob(dir, br#"{"architecture":"arm64"}"#);
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"config":{{"digest":"{}"}},"layers":[{{"digest":"{}"}}]}}"#,
+            config, layer
+        );
+        let manifest_digest = write_blob(dir, manifest.as_bytes());
+        let index = format!(
+            r#"{{"manifests":[{{"digest":"{}","platform":{{"architecture":"amd64"}}}},{{"digest":"{}","platform":{{"architecture":"arm64"}}}}]}}"#,
+            digest(b"missing"),
+            manifest_digest
+        );
+        let index_digest = write_blob(dir, index.as_bytes());
+        fs::write(
+            dir.join("index.json"),
+            format!(
+                r#"{{"manifests":[{{"digest":"{}","annotations":{{"{}":"latest"}}}}]}}"#,
+                index_digest, ANNOTATION_REF_NAME
+            ),
+        )
+        .unwrap();
+
+        let images = read_archive(dir, "arm64").unwrap();
+        assert_eq!(images[0].names, vec!["latest"]);
+        assert_eq!(images[0].manifest, manifest);
+        assert_eq!(images[0].config, br#"{"architecture":"arm64"}"#);
+        assert_eq!(images[0].layers[0].digest, layer);
+
+        assert!(read_archive(dir, "riscv64").is_err());
+    }
+
+    #[test]
+    fn test_read_archive_rejects_bad_input() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        assert!(read_archive(dir, "arm64").is_err());
+
+        fs::write(
+            dir.join("manifest.json"),
+            r#"[{"Config":"../etc/passwd","RepoTags":[],"Layers":[]}]"#,
+        )
+        .unwrap();
+        let err = read_archive(dir, "arm64").unwrap_err();
+        assert!(err.to_string().contains("invalid path"));
+
+        // Corrupt OCI blob
+        fs::remove_file(dir.join("manifest.json")).unwrap();
+        let config = write_blob(dir, b"{}");
+        fs::write(dir.join("blobs/sha256").join(&config[7..]), b"{ }").unwrap();
+        let manifest = write_blob(
+            dir,
+            format!(r#"{{"config":{{"digest":"{}"}},"layers":[]}}"#, config).as_bytes(),
+        );
+        fs::write(
+            dir.join("index.json"),
+            format!(r#"{{"manifests":[{{"digest":"{}"}}]}}"#, manifest),
+        )
+        .unwrap();
+        let err = read_archive(dir, "arm64").unwrap_err();
+        assert!(err.to_string().contains("corrupt"));
+    }
+}
@@ -9,23 +9,32 @@
 //! Communication is via vsock on port 6000.
 
 use smolvm_protocol::{
-    error_codes, ports, AgentRequest, AgentResponse, ContainerInfo, RegistryAuth, LAYER_CHUNK_SIZE,
-    PROTOCOL_VERSION,
+    error_codes, ports, AgentRequest, AgentResponse, ContainerInfo, ContainerMount, RegistryAuth,
+    LAYER_CHUNK_SIZE, PROTOCOL_VERSION,
 };
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
 use std::process::{Child, Command, Stdio};
 use tracing::{debug, error, info, warn};
 
+mod blobs;
+mod build;
 mod container;
 mod crun;
+mod dns;
+mod egress;
+mod image_archive;
+mod network;
 mod oci;
 mod paths;
+mod port_forward;
 mod process;
+mod proxy;
 #[cfg(target_os = "linux")]
 mod pty;
 mod retry;
 mod storage;
+mod sync;
 mod vsock;
 
 // ============================================================================
@@ -135,6 +144,15 @@ fn main() {
         );
     }
 
+    // Forward the in-guest proxy port to the host egress proxy (allowlist mode)
+    egress::start();
+
+    // Bring up the private network interface, if the VM is on one
+    network::start();
+
+    // Relay published ports the host can't map through TSI (UDP, bound addresses)
+    port_forward::start();
+
     // Registry load+reconcile deferred to first container operation via
     // REGISTRY.ensure_loaded(). On fresh boot, no containers from a previous
     // instance survive, so this work (~30-50ms for crun list + JSON parse)
@@ -185,11 +203,11 @@ fn signal_ready_to_host() {
     ];
 
     for path in &paths {
-        if Path::new(path).parent().map_or(false, |p| p.exists()) {
-            if std::fs::write(path, content.as_bytes()).is_ok() {
-                debug!(path = path, "ready marker written");
-                return;
-            }
+        if Path::new(path).parent().is_some_and(|p| p.exists())
+            && std::fs::write(path, content.as_bytes()).is_ok()
+        {
+            debug!(path = path, "ready marker written");
+            return;
         }
     }
 }
@@ -419,16 +437,21 @@ fn setup_persistent_rootfs() {
         }
     }
 
-    // Expand the ext4 filesystem to fill the block device on first boot.
-    // The host may have copied from a small template then extended the sparse
-    // file. After first resize, the FS spans the full device — skip on
-    // subsequent boots to avoid process spawn overhead (~3-5ms).
+    // Expand the ext4 filesystem to fill the block device whenever the device
+    // has grown. The host may have copied from a small template then extended
+    // the sparse file, or `microvm resize` may have extended it. The marker
+    // records the device size so unchanged boots skip the process spawn
+    // (~3-5ms).
     let resized_marker = format!("{}/.resized", OVERLAY_MOUNT);
-    if !std::path::Path::new(&resized_marker).exists() {
+    let sectors = storage::block_device_sectors(OVERLAY_DEVICE)
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+    let marker = std::fs::read_to_string(&resized_marker).unwrap_or_default();
+    if sectors.is_empty() || marker.trim() != sectors {
         let _ = std::process::Command::new("resize2fs")
             .arg(OVERLAY_DEVICE)
             .output();
-        let _ = std::fs::write(&resized_marker, "1");
+        let _ = std::fs::write(&resized_marker, sectors);
     }
 
     // Start storage disk mount in parallel while we set up overlayfs.
@@ -692,14 +715,17 @@ fn mount_storage_disk() {
         }
     };
 
-    // Expand the ext4 filesystem on first boot. After first resize, the FS
-    // spans the full device — skip on subsequent boots to avoid process spawn
-    // overhead (~3-5ms).
+    // Expand the ext4 filesystem when the device has grown since the last
+    // resize (first boot, or after `microvm resize`). The marker records the
+    // device size so unchanged boots skip the process spawn (~3-5ms).
     let resize_fs = || {
-        let resized_marker = format!("{}/.resized", STORAGE_MOUNT);
-        if !std::path::Path::new(&resized_marker).exists() {
+        let sectors = storage::block_device_sectors(STORAGE_DEVICE)
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        let marker = std::fs::read_to_string(paths::STORAGE_RESIZED_MARKER).unwrap_or_default();
+        if sectors.is_empty() || marker.trim() != sectors {
             let _ = Command::new("resize2fs").arg(STORAGE_DEVICE).output();
-            let _ = std::fs::write(&resized_marker, "1");
+            let _ = std::fs::write(paths::STORAGE_RESIZED_MARKER, sectors);
         }
     };
 
@@ -907,6 +933,24 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
             continue;
         }
 
+        // Handle ExportBlob with chunked streaming
+        if let AgentRequest::ExportBlob { ref digest } = request {
+            handle_streaming_export_blob(stream, digest)?;
+            continue;
+        }
+
+        // Handle ImportImage with chunked upload
+        if let AgentRequest::ImportImage { .. } = request {
+            handle_streaming_import(stream, request)?;
+            continue;
+        }
+
+        // Handle UploadBuildContext with chunked upload
+        if let AgentRequest::UploadBuildContext { .. } = request {
+            handle_streaming_build_context(stream, request)?;
+            continue;
+        }
+
         // Handle regular request
         let response = handle_request(request);
         send_response(stream, &response)?;
@@ -937,7 +981,15 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
 
         AgentRequest::ListImages => handle_list_images(),
 
-        AgentRequest::GarbageCollect { dry_run } => handle_gc(dry_run),
+        AgentRequest::GarbageCollect { dry_run, targets } => handle_gc(dry_run, &targets),
+
+        AgentRequest::RemoveImage { image } => handle_remove_image(&image),
+
+        AgentRequest::DiskUsage => handle_disk_usage(),
+
+        AgentRequest::PreparePush { image, compression } => {
+            handle_prepare_push(&image, compression)
+        }
 
         AgentRequest::PrepareOverlay { image, workload_id } => {
             handle_prepare_overlay(&image, &workload_id)
@@ -948,6 +1000,17 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
         AgentRequest::FormatStorage => handle_format_storage(),
 
         AgentRequest::StorageStatus => handle_storage_status(),
+        AgentRequest::SyncStatus => AgentResponse::ok_with_data(sync::status()),
+
+        AgentRequest::ResizeStorage { size_bytes } => handle_resize_storage(size_bytes),
+
+        AgentRequest::ConfigureProxy { config } => {
+            AgentResponse::from_result(proxy::configure(config), error_codes::PROXY_CONFIG_FAILED)
+        }
+
+        AgentRequest::ConfigureDns { config } => {
+            AgentResponse::from_result(dns::configure(config), error_codes::DNS_CONFIG_FAILED)
+        }
 
         AgentRequest::NetworkTest { url } => {
             info!(url = %url, "testing network connectivity directly from agent");
@@ -1012,6 +1075,8 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
 
         AgentRequest::Shutdown => {
             info!("shutdown requested");
+            // Copy out synced-mount changes the background loop hasn't yet
+            sync::flush();
             // Sync filesystem before shutdown to prevent corruption
             sync_and_unmount_storage();
             AgentResponse::Ok {
@@ -1119,6 +1184,41 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             // Streaming export is handled by handle_streaming_export_layer
             AgentResponse::error("export layer not handled here", error_codes::INTERNAL_ERROR)
         }
+
+        AgentRequest::ExportBlob { .. } => {
+            // Streaming export is handled by handle_streaming_export_blob
+            AgentResponse::error("export blob not handled here", error_codes::INTERNAL_ERROR)
+        }
+
+        AgentRequest::ImportImage { .. } => {
+            // Chunked import is handled by handle_streaming_import
+            AgentResponse::error("import image not handled here", error_codes::INTERNAL_ERROR)
+        }
+
+        AgentRequest::UploadBuildContext { .. } => {
+            // Chunked upload is handled by handle_streaming_build_context
+            AgentResponse::error(
+                "upload build context not handled here",
+                error_codes::INTERNAL_ERROR,
+            )
+        }
+
+        AgentRequest::RemoveBuildContext { id } => match build::remove_context(&id) {
+            Ok(()) => AgentResponse::ok(None),
+            Err(e) => AgentResponse::from_err(e, error_codes::BUILD_FAILED),
+        },
+
+        AgentRequest::BuildStep {
+            parent,
+            target,
+            step,
+            created_by,
+        } => handle_build_step(parent.as_deref(), &target, &step, &created_by),
+
+        AgentRequest::TagImage { source, target } => {
+            info!(source = %source, target = %target, "tagging image");
+            AgentResponse::from_result(storage::tag_image(&source, &target), error_codes::NOT_FOUND)
+        }
     }
 }
 
@@ -1204,7 +1304,7 @@ fn spawn_interactive_command(
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
+    mounts: &[ContainerMount],
     _tty: bool,
 ) -> Result<Child, Box<dyn std::error::Error>> {
     use std::path::Path;
@@ -1226,19 +1326,16 @@ fn spawn_interactive_command(
         return Err(format!("bundle directory not found: {}", bundle_path.display()).into());
     }
 
+    // Trust the VM's custom CA certificates and apply its DNS settings
+    proxy::install_ca_certs_into(rootfs_path)?;
+    dns::install_into(rootfs_path)?;
+
     // Generate OCI spec for this command
     let workdir_str = workdir.unwrap_or("/");
     let mut spec = oci::OciSpec::new(command, env, workdir_str, false);
 
-    // Add virtiofs bind mounts to OCI spec
-    for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        spec.add_bind_mount(
-            &virtiofs_mount.to_string_lossy(),
-            container_path,
-            *read_only,
-        );
-    }
+    // Add bind and tmpfs mounts to OCI spec
+    spec.add_container_mounts(mounts);
 
     // Write config.json to bundle
     spec.write_to(&bundle_path)
@@ -1936,7 +2033,7 @@ fn handle_run(
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
+    mounts: &[ContainerMount],
     timeout_ms: Option<u64>,
 ) -> AgentResponse {
     info!(image = %image, command = ?command, mounts = ?mounts, timeout_ms = ?timeout_ms, "running command");
@@ -2007,16 +2104,61 @@ fn handle_list_images() -> AgentResponse {
 }
 
 /// Handle garbage collection request.
-fn handle_gc(dry_run: bool) -> AgentResponse {
-    match storage::garbage_collect(dry_run) {
-        Ok(freed) => AgentResponse::ok_with_data(serde_json::json!({
-            "freed_bytes": freed,
-            "dry_run": dry_run,
-        })),
+fn handle_gc(dry_run: bool, targets: &[smolvm_protocol::GcTarget]) -> AgentResponse {
+    let in_use = storage::InUse::scan(container::storage_refs());
+    AgentResponse::from_result(
+        storage::garbage_collect(dry_run, targets, &in_use),
+        error_codes::GC_FAILED,
+    )
+}
+
+/// Handle image removal request.
+fn handle_remove_image(image: &str) -> AgentResponse {
+    info!(image = %image, "removing image");
+    let in_use = storage::InUse::scan(container::storage_refs());
+    match storage::remove_image(image, &in_use) {
+        Ok(report) => AgentResponse::ok_with_data(report),
+        Err(e @ storage::StorageError::ImageNotFound { .. }) => {
+            AgentResponse::from_err(e, error_codes::NOT_FOUND)
+        }
+        Err(e @ storage::StorageError::InUse { .. }) => {
+            AgentResponse::from_err(e, error_codes::IN_USE)
+        }
         Err(e) => AgentResponse::from_err(e, error_codes::GC_FAILED),
     }
 }
 
+/// Handle disk usage request.
+fn handle_disk_usage() -> AgentResponse {
+    let in_use = storage::InUse::scan(container::storage_refs());
+    AgentResponse::from_result(storage::disk_usage(&in_use), error_codes::LIST_FAILED)
+}
+
+/// Handle push preparation request.
+fn handle_prepare_push(
+    image: &str,
+    compression: smolvm_protocol::LayerCompression,
+) -> AgentResponse {
+    info!(image = %image, compression = compression.as_str(), "preparing image for push");
+    AgentResponse::from_result(
+        storage::prepare_push(image, compression),
+        error_codes::PUSH_FAILED,
+    )
+}
+
+/// Handle an image build step.
+fn handle_build_step(
+    parent: Option<&str>,
+    target: &str,
+    step: &smolvm_protocol::BuildInstruction,
+    created_by: &str,
+) -> AgentResponse {
+    AgentResponse::from_result(
+        build::build_step(parent, target, step, created_by),
+        error_codes::BUILD_FAILED,
+    )
+}
+
 /// Handle overlay preparation request.
 fn handle_prepare_overlay(image: &str, workload_id: &str) -> AgentResponse {
     info!(image = %image, workload_id = %workload_id, "preparing overlay");
@@ -2094,17 +2236,187 @@ fn handle_streaming_export_layer(
         }
     };
 
-    // Stream in chunks. Read ahead one chunk so we can mark the last
-    // data-carrying frame with done=true, avoiding an empty final frame.
+    let result = stream_file_chunks(stream, &mut file);
+
+    // Clean up temp file
+    let _ = std::fs::remove_file(&tar_path);
+
+    result
+}
+
+/// Handle export blob request with chunked streaming.
+///
+/// Streams a blob from the agent blob store (populated by PreparePush)
+/// as LayerData chunks.
+fn handle_streaming_export_blob(
+    stream: &mut impl Write,
+    digest: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!(digest = %digest, "exporting blob (chunked)");
+
+    let opened = storage::blob_path(digest).and_then(|path| {
+        std::fs::File::open(&path)
+            .map_err(|e| storage::StorageError::read_error(path.display().to_string(), e))
+    });
+    let mut file = match opened {
+        Ok(f) => f,
+        Err(e) => {
+            send_response(
+                stream,
+                &AgentResponse::from_err(e, error_codes::EXPORT_FAILED),
+            )?;
+            return Ok(());
+        }
+    };
+
+    stream_file_chunks(stream, &mut file)
+}
+
+/// Handle a chunked image import.
+///
+/// The first ImportImage chunk has already been read by the caller; the
+/// rest are read here until `done`. Chunks are appended to a temp file on
+/// the storage disk, then the archive is unpacked and installed. A single
+/// response is sent once the whole archive has been received.
+fn handle_streaming_import(
+    stream: &mut impl ReadWrite,
+    first: AgentRequest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reference = match &first {
+        AgentRequest::ImportImage { reference, .. } => reference.clone(),
+        _ => None,
+    };
+    let path = storage::import_archive_path().map_err(|e| e.to_string());
+
+    let received = receive_upload(stream, first, path.as_ref().ok(), |request| match request {
+        AgentRequest::ImportImage { data, done, .. } => Some((data, done)),
+        _ => None,
+    })?;
+
+    let response = match received.and_then(|bytes| path.clone().map(|p| (bytes, p))) {
+        Err(e) => AgentResponse::error(
+            format!("failed to receive image archive: {}", e),
+            error_codes::IMPORT_FAILED,
+        ),
+        Ok((bytes, path)) => {
+            info!(bytes, reference = ?reference, "importing image archive");
+            AgentResponse::from_result(
+                storage::import_image_archive(&path, reference.as_deref()),
+                error_codes::IMPORT_FAILED,
+            )
+        }
+    };
+    if let Ok(p) = &path {
+        let _ = std::fs::remove_file(p);
+    }
+
+    send_response(stream, &response)?;
+    Ok(())
+}
+
+/// Handle a chunked build context upload (see handle_streaming_import).
+fn handle_streaming_build_context(
+    stream: &mut impl ReadWrite,
+    first: AgentRequest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let id = match &first {
+        AgentRequest::UploadBuildContext { id, .. } => id.clone(),
+        _ => String::new(),
+    };
+    let path = build::context_archive_path(&id).map_err(|e| e.to_string());
+
+    let received = receive_upload(stream, first, path.as_ref().ok(), |request| match request {
+        AgentRequest::UploadBuildContext { data, done, .. } => Some((data, done)),
+        _ => None,
+    })?;
+
+    let response = match received.and_then(|bytes| path.clone().map(|p| (bytes, p))) {
+        Err(e) => AgentResponse::error(
+            format!("failed to receive build context: {}", e),
+            error_codes::BUILD_FAILED,
+        ),
+        Ok((bytes, path)) => {
+            info!(id = %id, bytes, "installing build context");
+            match build::install_context(&id, &path) {
+                Ok(()) => AgentResponse::ok(None),
+                Err(e) => AgentResponse::from_err(e, error_codes::BUILD_FAILED),
+            }
+        }
+    };
+    if let Ok(p) = &path {
+        let _ = std::fs::remove_file(p);
+    }
+
+    send_response(stream, &response)?;
+    Ok(())
+}
+
+/// Receive a chunked upload into `path`.
+///
+/// `first` is the request that started the upload; `chunk` extracts the
+/// data and done flag from it and from each following request. Frames are
+/// read until the final chunk even after a write error, so the connection
+/// stays in sync with the host. Returns the number of bytes received, or
+/// the error to report.
+fn receive_upload(
+    stream: &mut impl ReadWrite,
+    first: AgentRequest,
+    path: Option<&std::path::PathBuf>,
+    chunk: impl Fn(AgentRequest) -> Option<(Vec<u8>, bool)>,
+) -> Result<Result<usize, String>, Box<dyn std::error::Error>> {
+    let mut file = match path {
+        Some(p) => std::fs::File::create(p).map_err(|e| e.to_string()),
+        None => Err("no upload path".to_string()),
+    };
+    let mut received = 0usize;
+    let mut request = first;
+
+    loop {
+        let Some((data, done)) = chunk(request) else {
+            return Ok(Err("unexpected request during upload".to_string()));
+        };
+        received += data.len();
+        if let Ok(f) = &mut file {
+            if let Err(e) = f.write_all(&data) {
+                file = Err(e.to_string());
+            }
+        }
+        if done {
+            return Ok(file.map(|_| received));
+        }
+
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header)?;
+        let len = u32::from_be_bytes(header) as usize;
+        if len > MAX_MESSAGE_SIZE {
+            if let Some(p) = path {
+                let _ = std::fs::remove_file(p);
+            }
+            return Err(format!("message too large: {} bytes", len).into());
+        }
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf)?;
+        request = serde_json::from_slice(&buf)?;
+    }
+}
+
+/// Send a file as a sequence of LayerData chunks.
+///
+/// Reads ahead one chunk so the last data-carrying frame is marked
+/// done=true, avoiding an empty final frame. Read errors are reported to
+/// the host as an Error response.
+fn stream_file_chunks(
+    stream: &mut impl Write,
+    file: &mut std::fs::File,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut buf = vec![0u8; LAYER_CHUNK_SIZE];
     let mut pending = match file.read(&mut buf) {
         Ok(n) => n,
         Err(e) => {
-            let _ = std::fs::remove_file(&tar_path);
             send_response(
                 stream,
                 &AgentResponse::error(
-                    format!("failed to read tar file: {}", e),
+                    format!("failed to read file: {}", e),
                     error_codes::EXPORT_FAILED,
                 ),
             )?;
@@ -2118,11 +2430,10 @@ fn handle_streaming_export_layer(
         let next_n = match file.read(&mut next_buf) {
             Ok(n) => n,
             Err(e) => {
-                let _ = std::fs::remove_file(&tar_path);
                 send_response(
                     stream,
                     &AgentResponse::error(
-                        format!("failed to read tar file: {}", e),
+                        format!("failed to read file: {}", e),
                         error_codes::EXPORT_FAILED,
                     ),
                 )?;
@@ -2148,9 +2459,6 @@ fn handle_streaming_export_layer(
         pending = next_n;
     }
 
-    // Clean up temp file
-    let _ = std::fs::remove_file(&tar_path);
-
     Ok(())
 }
 
@@ -2159,6 +2467,18 @@ fn handle_storage_status() -> AgentResponse {
     AgentResponse::from_result(storage::status(), error_codes::STATUS_FAILED)
 }
 
+/// Handle online storage resize request.
+fn handle_resize_storage(size_bytes: u64) -> AgentResponse {
+    info!(size_bytes, "resizing storage online");
+    match storage::resize_online(size_bytes) {
+        Ok(status) => AgentResponse::ok_with_data(status),
+        Err(e @ storage::StorageError::DeviceNotResized { .. }) => {
+            AgentResponse::from_err(e, error_codes::RESIZE_UNSUPPORTED)
+        }
+        Err(e) => AgentResponse::from_err(e, error_codes::RESIZE_FAILED),
+    }
+}
+
 // ============================================================================
 // VM-Level Exec Handlers (Direct Execution in VM)
 // ============================================================================
@@ -2418,7 +2738,7 @@ fn handle_create_container(
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
+    mounts: &[ContainerMount],
 ) -> AgentResponse {
     info!(image = %image, command = ?command, "creating container");
 
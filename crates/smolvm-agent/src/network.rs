@@ -0,0 +1,136 @@
+//! Private network interface setup.
+//!
+//! When the host attaches the VM to a private network it sets
+//! `SMOLVM_NET_ADDR` (address/prefix), `SMOLVM_NET_GATEWAY` and
+//! `SMOLVM_NET_DOMAIN`. The agent gives `eth0` the address and points DNS
+//! at the gateway, which resolves the other members by name and forwards
+//! everything else upstream. No default route is added: only the subnet
+//! goes through `eth0`, other traffic keeps using TSI.
+
+use std::net::Ipv4Addr;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+const ENV_ADDR: &str = "SMOLVM_NET_ADDR";
+const ENV_GATEWAY: &str = "SMOLVM_NET_GATEWAY";
+const ENV_DOMAIN: &str = "SMOLVM_NET_DOMAIN";
+
+/// Interface the host attaches for the private network.
+const INTERFACE: &str = "eth0";
+
+/// Private network settings passed by the host.
+struct PrivateNet {
+    address: Ipv4Addr,
+    prefix_len: u8,
+    gateway: Ipv4Addr,
+    domain: String,
+}
+
+static CONFIG: OnceLock<Option<PrivateNet>> = OnceLock::new();
+
+fn config() -> Option<&'static PrivateNet> {
+    CONFIG
+        .get_or_init(|| {
+            let addr = std::env::var(ENV_ADDR).ok()?;
+            let (address, prefix_len) = addr.split_once('/')?;
+            Some(PrivateNet {
+                address: address.parse().ok()?,
+                prefix_len: prefix_len.parse().ok().filter(|len| *len <= 32)?,
+                gateway: std::env::var(ENV_GATEWAY).ok()?.parse().ok()?,
+                domain: std::env::var(ENV_DOMAIN).unwrap_or_default(),
+            })
+        })
+        .as_ref()
+}
+
+/// The private network's DNS server (its gateway) and search domain.
+pub fn dns() -> Option<(Ipv4Addr, &'static str)> {
+    config().map(|net| (net.gateway, net.domain.as_str()))
+}
+
+/// Configure the private network interface if the host attached one.
+pub fn start() {
+    let Some(net) = config() else {
+        return;
+    };
+
+    if let Err(e) = configure_interface(net) {
+        warn!(error = %e, interface = INTERFACE, "failed to configure private network");
+        return;
+    }
+
+    // Only an overlayed root is private to this VM; without one, / is the
+    // host's shared rootfs directory and must not be modified.
+    if std::path::Path::new("/oldroot").exists() {
+        if let Err(e) = std::fs::write("/etc/resolv.conf", crate::dns::resolv_conf()) {
+            warn!(error = %e, "failed to write resolv.conf");
+        }
+    }
+
+    info!(
+        address = %net.address,
+        prefix_len = net.prefix_len,
+        domain = %net.domain,
+        "private network configured"
+    );
+}
+
+#[cfg(target_os = "linux")]
+fn configure_interface(net: &PrivateNet) -> std::io::Result<()> {
+    use std::io;
+
+    fn sockaddr(addr: Ipv4Addr) -> libc::sockaddr {
+        let sin = libc::sockaddr_in {
+            sin_family: libc::AF_INET as libc::sa_family_t,
+            sin_port: 0,
+            sin_addr: libc::in_addr {
+                s_addr: u32::from(addr).to_be(),
+            },
+            sin_zero: [0; 8],
+        };
+        // SAFETY: sockaddr_in and sockaddr have the same size on Linux
+        unsafe { std::mem::transmute::<libc::sockaddr_in, libc::sockaddr>(sin) }
+    }
+
+    let netmask = Ipv4Addr::from(
+        u32::MAX
+            .checked_shl(32 - u32::from(net.prefix_len))
+            .unwrap_or(0),
+    );
+
+    // SAFETY: plain ioctls on a socket we own, with zero-initialized ifreqs
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut req: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in req.ifr_name.iter_mut().zip(INTERFACE.bytes()) {
+            *dst = src as libc::c_char;
+        }
+
+        let mut result = Ok(());
+        req.ifr_ifru.ifru_addr = sockaddr(net.address);
+        if libc::ioctl(fd, libc::SIOCSIFADDR, &req) < 0 {
+            result = Err(io::Error::last_os_error());
+        }
+        req.ifr_ifru.ifru_netmask = sockaddr(netmask);
+        if result.is_ok() && libc::ioctl(fd, libc::SIOCSIFNETMASK, &req) < 0 {
+            result = Err(io::Error::last_os_error());
+        }
+        if result.is_ok() && libc::ioctl(fd, libc::SIOCGIFFLAGS, &mut req) == 0 {
+            req.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            if libc::ioctl(fd, libc::SIOCSIFFLAGS, &req) < 0 {
+                result = Err(io::Error::last_os_error());
+            }
+        }
+        libc::close(fd);
+        result
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn configure_interface(_net: &PrivateNet) -> std::io::Result<()> {
+    Ok(())
+}
@@ -3,7 +3,9 @@
 //! This module provides types and functions for generating OCI-compliant
 //! config.json files used by crun to execute containers.
 
+use crate::paths;
 use serde::{Deserialize, Serialize};
+use smolvm_protocol::{ContainerMount, MountKind};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -167,6 +169,11 @@ impl OciSpec {
     /// * `workdir` - Working directory inside the container
     /// * `tty` - Whether to allocate a pseudo-terminal
     pub fn new(command: &[String], env: &[(String, String)], workdir: &str, tty: bool) -> Self {
+        // Proxy variables (allowlist or configured proxy), unless the caller set them
+        let proxy_env = crate::proxy::workload_env()
+            .into_iter()
+            .filter(|(k, _)| !env.iter().any(|(ek, _)| ek == k));
+
         // Build environment variables
         let env_strings: Vec<String> = [
             "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
@@ -174,6 +181,7 @@ impl OciSpec {
             "TERM=xterm-256color".to_string(),
         ]
         .into_iter()
+        .chain(proxy_env.map(|(k, v)| format!("{}={}", k, v)))
         .chain(env.iter().map(|(k, v)| format!("{}={}", k, v)))
         .collect();
 
@@ -274,6 +282,57 @@ impl OciSpec {
         });
     }
 
+    /// Add the container's requested mounts to the spec.
+    ///
+    /// Bind mounts come from the staging area under
+    /// [`paths::VIRTIOFS_MOUNT_ROOT`]. Mounts are added parents first, so
+    /// `-v data:/srv` with `--tmpfs /srv/cache` works in either order.
+    pub fn add_container_mounts(&mut self, mounts: &[ContainerMount]) {
+        let mut mounts: Vec<&ContainerMount> = mounts.iter().collect();
+        mounts.sort_by_key(|m| Path::new(&m.target).components().count());
+
+        for mount in mounts {
+            match &mount.kind {
+                MountKind::Bind { tag } => {
+                    let source = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
+                    self.add_bind_mount(&source.to_string_lossy(), &mount.target, mount.read_only);
+                }
+                MountKind::Tmpfs { size_bytes, mode } => {
+                    let mut options = Vec::new();
+                    if let Some(size) = size_bytes {
+                        options.push(format!("size={}", size));
+                    }
+                    if let Some(mode) = mode {
+                        options.push(format!("mode={:o}", mode));
+                    }
+                    if mount.read_only {
+                        options.push("ro".to_string());
+                    }
+                    self.mounts.push(OciMount {
+                        destination: mount.target.clone(),
+                        mount_type: Some("tmpfs".to_string()),
+                        source: "tmpfs".to_string(),
+                        options,
+                    });
+                }
+            }
+
+            let flags = [
+                (mount.noexec, "noexec"),
+                (mount.nosuid, "nosuid"),
+                (mount.nodev, "nodev"),
+            ];
+            if let Some(added) = self.mounts.last_mut() {
+                added.options.extend(
+                    flags
+                        .into_iter()
+                        .filter(|(set, _)| *set)
+                        .map(|(_, flag)| flag.to_string()),
+                );
+            }
+        }
+    }
+
     /// Write the OCI spec to a config.json file in the bundle directory.
     pub fn write_to(&self, bundle_dir: &Path) -> std::io::Result<()> {
         let config_path = bundle_dir.join("config.json");
@@ -732,6 +791,29 @@ mod tests {
         assert!(mount.options.contains(&"ro".to_string()));
     }
 
+    #[test]
+    fn test_add_container_mounts() {
+        let mut spec = OciSpec::new(&["sh".to_string()], &[], "/", false);
+        let defaults = spec.mounts.len();
+        let mut bind = ContainerMount::bind("smolvm0", "/srv", false);
+        bind.noexec = true;
+        spec.add_container_mounts(&[
+            ContainerMount::tmpfs("/srv/cache", Some(1024), Some(0o1777)),
+            bind,
+        ]);
+
+        let added = &spec.mounts[defaults..];
+        assert_eq!(added[0].destination, "/srv");
+        assert_eq!(added[0].source, "/mnt/virtiofs/smolvm0");
+        assert_eq!(added[0].options, vec!["bind", "rprivate", "noexec"]);
+        assert_eq!(added[1].destination, "/srv/cache");
+        assert_eq!(added[1].mount_type.as_deref(), Some("tmpfs"));
+        assert_eq!(
+            added[1].options,
+            vec!["size=1024", "mode=1777", "nosuid", "nodev"]
+        );
+    }
+
     #[test]
     fn test_validate_env_vars_valid() {
         // Valid env vars should pass
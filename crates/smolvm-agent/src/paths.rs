@@ -36,9 +36,25 @@ pub const VIRTIOFS_MOUNT_ROOT: &str = "/mnt/virtiofs";
 /// Root directory for all persistent storage.
 pub const STORAGE_ROOT: &str = "/storage";
 
+/// Block device backing the storage disk.
+pub const STORAGE_DEVICE: &str = "/dev/vda";
+
+/// Marker recording the device size (in sectors) the storage filesystem was
+/// last grown to.
+pub const STORAGE_RESIZED_MARKER: &str = "/storage/.resized";
+
 /// Directory for overlay filesystems.
 pub const OVERLAYS_DIR: &str = "/storage/overlays";
 
+/// Content-addressed blob store (re-tarred layers and configs for push).
+pub const BLOBS_DIR: &str = "/storage/blobs";
+
+/// Image build state (uploaded build contexts).
+pub const BUILD_DIR: &str = "/storage/build";
+
+/// Local copies of synced mounts (`-v SRC:DST:sync`), one per mount tag.
+pub const SYNC_DIR: &str = "/storage/sync";
+
 // =============================================================================
 // Container Runtime Paths
 // =============================================================================
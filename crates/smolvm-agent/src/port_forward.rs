@@ -0,0 +1,129 @@
+//! Guest end of published ports forwarded over vsock.
+//!
+//! The host opens one vsock stream per TCP connection or UDP peer for ports
+//! TSI can't publish (UDP, or TCP bound to a specific host address). Each
+//! stream names a guest port in its header; the agent connects to that port
+//! on loopback and relays traffic until either side closes.
+
+use crate::vsock::{self, VsockStream};
+use smolvm_protocol::port_forward::{self, PortProtocol, MAX_DATAGRAM_SIZE};
+use smolvm_protocol::ports;
+use std::io;
+use std::net::{Ipv4Addr, Shutdown, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How often a UDP reply thread checks whether its session has ended.
+const UDP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Start accepting forwarded connections from the host.
+pub fn start() {
+    let listener = match vsock::listen(ports::PORT_FORWARD) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!(error = %e, port = ports::PORT_FORWARD, "failed to listen for port forwarding");
+            return;
+        }
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name("port-forward".to_string())
+        .spawn(move || loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle(stream) {
+                            debug!(error = %e, "port forward failed");
+                        }
+                    });
+                }
+                Err(e) => debug!(error = %e, "port forward accept failed"),
+            }
+        });
+    match spawned {
+        Ok(_) => info!(
+            port = ports::PORT_FORWARD,
+            "port forwarding listener started"
+        ),
+        Err(e) => warn!(error = %e, "failed to start port forwarding listener"),
+    }
+}
+
+fn handle(mut host: VsockStream) -> io::Result<()> {
+    let (protocol, guest_port) = port_forward::read_header(&mut host)?;
+    match protocol {
+        PortProtocol::Tcp => relay_tcp(host, guest_port),
+        PortProtocol::Udp => relay_udp(host, guest_port),
+    }
+}
+
+fn relay_tcp(host: VsockStream, guest_port: u16) -> io::Result<()> {
+    let local = TcpStream::connect((Ipv4Addr::LOCALHOST, guest_port))?;
+
+    let mut host_read = host.try_clone()?;
+    let mut local_write = local.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut host_read, &mut local_write);
+        let _ = local_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut local, mut host) = (local, host);
+    let _ = io::copy(&mut local, &mut host);
+    let _ = host.shutdown_write();
+    Ok(())
+}
+
+fn relay_udp(host: VsockStream, guest_port: u16) -> io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    socket.connect((Ipv4Addr::LOCALHOST, guest_port))?;
+
+    // Replies from the guest service go back to the host peer. The timeout
+    // lets the thread notice when the session has ended.
+    let reply_socket = socket.try_clone()?;
+    reply_socket.set_read_timeout(Some(UDP_POLL_INTERVAL))?;
+    let mut host_write = host.try_clone()?;
+    let closed = Arc::new(AtomicBool::new(false));
+    let reply_closed = closed.clone();
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        while !reply_closed.load(Ordering::Relaxed) {
+            match reply_socket.recv(&mut buf) {
+                Ok(n) => {
+                    if port_forward::write_datagram(&mut host_write, &buf[..n]).is_err() {
+                        break;
+                    }
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::ConnectionRefused
+                    ) => {}
+                Err(_) => break,
+            }
+        }
+    });
+
+    // The session ends when the host closes the stream
+    let mut host = host;
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    let result = loop {
+        match port_forward::read_datagram(&mut host, &mut buf) {
+            Ok(n) => {
+                // Nothing listening yet is not fatal for UDP
+                if let Err(e) = socket.send(&buf[..n]) {
+                    debug!(error = %e, port = guest_port, "udp forward send failed");
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    closed.store(true, Ordering::Relaxed);
+    let _ = host.shutdown_write();
+    result
+}
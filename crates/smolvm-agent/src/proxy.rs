@@ -0,0 +1,290 @@
+//! HTTP(S) proxy and custom CA certificate configuration.
+//!
+//! The host sends a [`ProxyConfig`] after boot. The proxy variables are
+//! exported into the agent's own environment (so crane pulls and VM exec go
+//! through the proxy) and handed to every workload via [`workload_env`].
+//! Extra CA certificates are added to the guest trust store and, through
+//! [`install_ca_certs_into`], to each container rootfs.
+
+use crate::egress;
+use parking_lot::RwLock;
+use smolvm_protocol::ProxyConfig;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Guest CA bundle read by the agent's tools.
+const SYSTEM_CA_BUNDLE: &str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// Pristine copy of the guest CA bundle, taken before the first change.
+const SYSTEM_CA_BUNDLE_ORIG: &str = "/etc/ssl/certs/ca-certificates.crt.smolvm-orig";
+
+/// Directory for individual local CA certificates (`update-ca-certificates`).
+const LOCAL_CA_DIR: &str = "/usr/local/share/ca-certificates";
+
+/// CA bundle locations used by common distributions, relative to a rootfs.
+const ROOTFS_CA_BUNDLES: &[&str] = &[
+    "etc/ssl/certs/ca-certificates.crt", // Debian, Ubuntu, Alpine
+    "etc/pki/tls/certs/ca-bundle.crt",   // Fedora, RHEL
+    "etc/ssl/ca-bundle.pem",             // openSUSE
+];
+
+/// Proxy variables the agent may have exported previously.
+const PROXY_VARS: &[&str] = &[
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+];
+
+lazy_static::lazy_static! {
+    /// Current proxy configuration (None until the host configures one).
+    static ref CONFIG: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+}
+
+/// Apply a proxy configuration, replacing any previous one.
+pub fn configure(config: ProxyConfig) -> io::Result<()> {
+    for cert in &config.ca_certs {
+        if !cert.contains("-----BEGIN CERTIFICATE-----") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "CA certificate is not PEM encoded",
+            ));
+        }
+    }
+
+    install_system_ca_certs(&config.ca_certs)?;
+
+    if egress::enabled() {
+        if config.http_proxy.is_some() || config.https_proxy.is_some() {
+            warn!("egress allowlist is active, ignoring HTTP proxy settings");
+        }
+    } else {
+        for key in PROXY_VARS {
+            std::env::remove_var(key);
+        }
+        for (key, value) in config.env() {
+            std::env::set_var(key, value);
+        }
+    }
+
+    info!(
+        http_proxy = config.http_proxy.is_some(),
+        https_proxy = config.https_proxy.is_some(),
+        ca_certs = config.ca_certs.len(),
+        "proxy configuration applied"
+    );
+    *CONFIG.write() = Some(config);
+    Ok(())
+}
+
+/// Proxy environment for workloads: the egress allowlist proxy when active,
+/// otherwise the configured HTTP(S) proxy.
+pub fn workload_env() -> Vec<(String, String)> {
+    if egress::enabled() {
+        return egress::proxy_env();
+    }
+    CONFIG
+        .read()
+        .as_ref()
+        .map(ProxyConfig::env)
+        .unwrap_or_default()
+}
+
+/// Add the configured CA certificates to a container rootfs' trust bundles.
+pub fn install_ca_certs_into(rootfs: &Path) -> io::Result<()> {
+    let certs = match CONFIG.read().as_ref() {
+        Some(config) if !config.ca_certs.is_empty() => config.ca_certs.clone(),
+        _ => return Ok(()),
+    };
+    add_certs_to_rootfs(rootfs, &certs)
+}
+
+fn add_certs_to_rootfs(rootfs: &Path, certs: &[String]) -> io::Result<()> {
+    let mut found = false;
+    for rel in ROOTFS_CA_BUNDLES {
+        let Some(bundle) = resolve_in_rootfs(rootfs, &rootfs.join(rel))? else {
+            continue;
+        };
+        if bundle.is_file() {
+            append_missing_certs(&bundle, certs)?;
+            found = true;
+        }
+    }
+
+    // Images without a CA bundle get one with just the custom certificates
+    if !found {
+        let Some(bundle) = resolve_in_rootfs(rootfs, &rootfs.join(ROOTFS_CA_BUNDLES[0]))? else {
+            warn!(rootfs = %rootfs.display(), "CA bundle path leaves the rootfs, not creating it");
+            return Ok(());
+        };
+        if let Some(parent) = bundle.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&bundle, "")?;
+        append_missing_certs(&bundle, certs)?;
+    }
+    Ok(())
+}
+
+/// Resolve `path` within `rootfs`, following symlinks in its existing part.
+///
+/// The agent resolves a container's symlinks against its own root, so an
+/// absolute link in the image (`etc/ssl -> /etc/ssl`) would lead to the
+/// agent's files. Returns `None` when the resolved path is outside
+/// `rootfs`, or ends at a dangling symlink.
+fn resolve_in_rootfs(rootfs: &Path, path: &Path) -> io::Result<Option<PathBuf>> {
+    let root = rootfs.canonicalize()?;
+    let mut existing = path;
+    while std::fs::symlink_metadata(existing).is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return Ok(None),
+        }
+    }
+    let Ok(resolved) = existing.canonicalize() else {
+        return Ok(None);
+    };
+    if !resolved.starts_with(&root) {
+        return Ok(None);
+    }
+    match path.strip_prefix(existing) {
+        Ok(rest) if rest.as_os_str().is_empty() => Ok(Some(resolved)),
+        Ok(rest) => Ok(Some(resolved.join(rest))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn append_missing_certs(bundle: &Path, certs: &[String]) -> io::Result<()> {
+    let existing = std::fs::read_to_string(bundle).unwrap_or_default();
+    let mut file = std::fs::OpenOptions::new().append(true).open(bundle)?;
+    for cert in certs {
+        let pem = cert.trim();
+        if !existing.contains(pem) {
+            write!(file, "\n{}\n", pem)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuild the guest CA bundle from its pristine copy plus `certs`.
+fn install_system_ca_certs(certs: &[String]) -> io::Result<()> {
+    let orig = Path::new(SYSTEM_CA_BUNDLE_ORIG);
+    if certs.is_empty() && !orig.exists() {
+        return Ok(());
+    }
+
+    if !orig.exists() {
+        match std::fs::copy(SYSTEM_CA_BUNDLE, orig) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(parent) = orig.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(orig, "")?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut bundle = std::fs::read_to_string(orig)?;
+    for cert in certs {
+        bundle.push('\n');
+        bundle.push_str(cert.trim());
+        bundle.push('\n');
+    }
+    let tmp = Path::new(SYSTEM_CA_BUNDLE).with_extension("crt.tmp");
+    std::fs::write(&tmp, bundle)?;
+    std::fs::rename(&tmp, SYSTEM_CA_BUNDLE)?;
+
+    // Individual files, so update-ca-certificates keeps them
+    std::fs::create_dir_all(LOCAL_CA_DIR)?;
+    for entry in std::fs::read_dir(LOCAL_CA_DIR)?.flatten() {
+        if entry.file_name().to_string_lossy().starts_with("smolvm-") {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    for (i, cert) in certs.iter().enumerate() {
+        let path = Path::new(LOCAL_CA_DIR).join(format!("smolvm-{}.crt", i));
+        std::fs::write(path, format!("{}\n", cert.trim()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &str = "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+
+    #[test]
+    fn test_add_certs_to_existing_bundle_is_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join(ROOTFS_CA_BUNDLES[0]);
+        std::fs::create_dir_all(bundle.parent().unwrap()).unwrap();
+        std::fs::write(&bundle, "system certs\n").unwrap();
+
+        add_certs_to_rootfs(dir.path(), &[CERT.to_string()]).unwrap();
+        add_certs_to_rootfs(dir.path(), &[CERT.to_string()]).unwrap();
+
+        let content = std::fs::read_to_string(&bundle).unwrap();
+        assert!(content.starts_with("system certs\n"));
+        assert_eq!(content.matches("BEGIN CERTIFICATE").count(), 1);
+        // Other distro bundles are not created when one already exists
+        assert!(!dir.path().join(ROOTFS_CA_BUNDLES[1]).exists());
+    }
+
+    #[test]
+    fn test_add_certs_creates_bundle_and_skips_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let rhel = dir.path().join(ROOTFS_CA_BUNDLES[1]);
+        std::fs::create_dir_all(rhel.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", &rhel).unwrap();
+
+        add_certs_to_rootfs(dir.path(), &[CERT.to_string()]).unwrap();
+
+        let created = std::fs::read_to_string(dir.path().join(ROOTFS_CA_BUNDLES[0])).unwrap();
+        assert!(created.contains("BEGIN CERTIFICATE"));
+        assert!(std::fs::symlink_metadata(&rhel).unwrap().is_symlink());
+    }
+
+    #[test]
+    fn test_add_certs_skips_parent_symlinks_leaving_rootfs() {
+        let outside = tempfile::tempdir().unwrap();
+        let host_bundle = outside.path().join("certs/ca-certificates.crt");
+        std::fs::create_dir_all(host_bundle.parent().unwrap()).unwrap();
+        std::fs::write(&host_bundle, "host certs\n").unwrap();
+
+        // etc/ssl -> <outside> and etc/pki -> <outside>
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("etc")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("etc/ssl")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("etc/pki")).unwrap();
+
+        add_certs_to_rootfs(dir.path(), &[CERT.to_string()]).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&host_bundle).unwrap(),
+            "host certs\n"
+        );
+        assert!(!outside.path().join("tls").exists());
+        assert!(!outside.path().join("ca-bundle.pem").exists());
+    }
+
+    #[test]
+    fn test_add_certs_follows_symlinks_inside_rootfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("usr/share/ssl");
+        std::fs::create_dir_all(real.join("certs")).unwrap();
+        std::fs::write(real.join("certs/ca-certificates.crt"), "system certs\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("etc")).unwrap();
+        std::os::unix::fs::symlink("../usr/share/ssl", dir.path().join("etc/ssl")).unwrap();
+
+        add_certs_to_rootfs(dir.path(), &[CERT.to_string()]).unwrap();
+
+        let content = std::fs::read_to_string(real.join("certs/ca-certificates.crt")).unwrap();
+        assert!(content.contains("BEGIN CERTIFICATE"));
+    }
+}
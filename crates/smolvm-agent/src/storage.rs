@@ -7,12 +7,22 @@
 //! - Overlay filesystem management
 //! - Container execution via crun OCI runtime
 //! - Support for pre-packed OCI layers (smolvm pack)
+//! - Importing OCI layout and docker-archive tarballs
 
+use crate::blobs::BlobStore;
 use crate::crun::CrunCommand;
+use crate::image_archive;
 use crate::oci::{generate_container_id, OciSpec};
 use crate::paths;
 use crate::process::{wait_with_timeout_and_cleanup, WaitResult, TIMEOUT_EXIT_CODE};
-use smolvm_protocol::{ImageInfo, OverlayInfo, RegistryAuth, StorageStatus};
+use sha2::Digest;
+use smolvm_protocol::{
+    BlobDescriptor, ContainerMount, ContainerUsage, DiskUsage, GcItem, GcReport, GcTarget,
+    ImageInfo, ImageUsage, LayerCompression, OverlayInfo, PushPlan, RegistryAuth, StorageStatus,
+    SyncConflictPolicy, UsageSummary, BUILD_CACHE_REPOSITORY,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::OnceLock;
@@ -27,13 +37,35 @@ const CONFIGS_DIR: &str = "configs";
 const MANIFESTS_DIR: &str = "manifests";
 const OVERLAYS_DIR: &str = "overlays";
 
+/// Overlays modified more recently than this are never collected: they may
+/// be in the middle of being set up, before anything is mounted on them.
+const OVERLAY_GC_GRACE_SECS: u64 = 60;
+
+/// OCI image manifest media type.
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// OCI image config media type.
+pub(crate) const OCI_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
 /// Global state for packed layers support.
 /// Set at startup if SMOLVM_PACKED_LAYERS env var is present.
 static PACKED_LAYERS_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
 
 /// Global state for boot-time volume mounts.
 /// Set at startup if SMOLVM_MOUNT_COUNT env var is present.
-static BOOT_VOLUME_MOUNTS: OnceLock<Vec<(String, String, bool)>> = OnceLock::new();
+static BOOT_VOLUME_MOUNTS: OnceLock<Vec<ContainerMount>> = OnceLock::new();
+
+/// Single-file mounts: virtiofs tag -> file name within the shared directory.
+/// Set at startup from SMOLVM_MOUNT_FILE_* env vars.
+static FILE_MOUNTS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Image volume mounts: mount tag -> (block device, attached read-only).
+/// Set at startup from SMOLVM_MOUNT_DISK_* env vars.
+static DISK_MOUNTS: OnceLock<HashMap<String, (String, bool)>> = OnceLock::new();
+
+/// Synced mounts: mount tag -> (conflict policy, read-only).
+/// Set at startup from SMOLVM_MOUNT_SYNC_* env vars.
+static SYNC_MOUNTS: OnceLock<HashMap<String, (SyncConflictPolicy, bool)>> = OnceLock::new();
 
 /// Initialize packed layers support by checking SMOLVM_PACKED_LAYERS env var.
 /// Format: "virtiofs_tag:mount_point" (e.g., "smolvm_layers:/packed_layers")
@@ -109,11 +141,19 @@ pub fn get_packed_layers_dir() -> Option<&'static PathBuf> {
 ///   SMOLVM_MOUNT_COUNT=N
 ///   SMOLVM_MOUNT_0=smolvm0:/data:rw
 ///   SMOLVM_MOUNT_1=smolvm1:/config:ro
+///   SMOLVM_MOUNT_FILE_1=app.toml
+///
+/// `SMOLVM_MOUNT_FILE_i` marks a single-file mount: the device shares the
+/// host directory containing that file, and only the file is exposed.
+/// `SMOLVM_MOUNT_DISK_i=/dev/vdc` marks a named image volume, an ext4 block
+/// device mounted in place of a virtiofs share.
+/// `SMOLVM_MOUNT_SYNC_i=newer|host|guest` marks a synced mount, whose
+/// share is copied to the storage disk and kept in sync (see [`crate::sync`]).
 ///
 /// This mounts each virtiofs device at its staging area and bind-mounts
 /// to the guest target path, making volumes visible to all code paths
 /// including VmExec.
-pub fn init_volume_mounts() -> &'static [(String, String, bool)] {
+pub fn init_volume_mounts() -> &'static [ContainerMount] {
     BOOT_VOLUME_MOUNTS.get_or_init(|| {
         let count: usize = match std::env::var("SMOLVM_MOUNT_COUNT") {
             Ok(v) => match v.parse() {
@@ -127,6 +167,9 @@ pub fn init_volume_mounts() -> &'static [(String, String, bool)] {
         };
 
         let mut mounts = Vec::with_capacity(count);
+        let mut files = HashMap::new();
+        let mut disks = HashMap::new();
+        let mut syncs = HashMap::new();
         for i in 0..count {
             let env_key = format!("SMOLVM_MOUNT_{}", i);
             let env_val = match std::env::var(&env_key) {
@@ -148,9 +191,27 @@ pub fn init_volume_mounts() -> &'static [(String, String, bool)] {
             let guest_path = parts[1].to_string();
             let read_only = parts[2] == "ro";
 
+            if let Ok(file) = std::env::var(format!("SMOLVM_MOUNT_FILE_{}", i)) {
+                files.insert(tag.clone(), file);
+            }
+            if let Ok(device) = std::env::var(format!("SMOLVM_MOUNT_DISK_{}", i)) {
+                disks.insert(tag.clone(), (device, read_only));
+            }
+            if let Ok(policy) = std::env::var(format!("SMOLVM_MOUNT_SYNC_{}", i)) {
+                match policy.parse() {
+                    Ok(policy) => {
+                        syncs.insert(tag.clone(), (policy, read_only));
+                    }
+                    Err(e) => warn!(key = %env_key, error = %e, "invalid sync mount policy"),
+                }
+            }
+
             info!(tag = %tag, guest_path = %guest_path, read_only = read_only, "boot volume mount");
-            mounts.push((tag, guest_path, read_only));
+            mounts.push(ContainerMount::bind(tag, guest_path, read_only));
         }
+        let _ = FILE_MOUNTS.set(files);
+        let _ = DISK_MOUNTS.set(disks);
+        let _ = SYNC_MOUNTS.set(syncs);
 
         // Mount using existing logic with empty rootfs prefix so bind mounts
         // go to absolute guest paths (e.g., "/data"), visible to VmExec.
@@ -315,6 +376,14 @@ pub enum StorageError {
     StorageNotReady { reason: String },
     /// No images found in storage.
     NoImagesFound,
+    /// Resource is in use and cannot be removed.
+    InUse { resource: String, users: String },
+    /// Block device did not grow to the expected size.
+    DeviceNotResized {
+        device: String,
+        expected_bytes: u64,
+        actual_bytes: u64,
+    },
 
     // ========================================================================
     // Generic
@@ -487,6 +556,21 @@ impl std::fmt::Display for StorageError {
             StorageError::NoImagesFound => {
                 write!(f, "no images found")
             }
+            StorageError::InUse { resource, users } => {
+                write!(f, "{} is in use by {}", resource, users)
+            }
+            StorageError::DeviceNotResized {
+                device,
+                expected_bytes,
+                actual_bytes,
+            } => {
+                write!(
+                    f,
+                    "{} still reports {} bytes after the disk was grown to {} bytes: \
+                     the hypervisor does not notify the guest of disk size changes",
+                    device, actual_bytes, expected_bytes
+                )
+            }
 
             // Generic
             StorageError::Internal { message } => {
@@ -699,6 +783,123 @@ pub fn status() -> Result<StorageStatus> {
     })
 }
 
+/// How long to wait for the block device to report a new capacity.
+const RESIZE_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Where the kernel reports block device sizes.
+const SYSFS_BLOCK: &str = "/sys/class/block";
+
+/// `BLKGETSIZE64`: `_IOR(0x12, 114, size_t)`, not exported by libc.
+const BLKGETSIZE64: u64 = 0x8008_1272;
+
+/// Size of a block device in 512-byte sectors, as reported by sysfs.
+pub fn block_device_sectors(device: &str) -> Option<u64> {
+    sysfs_sectors(Path::new(SYSFS_BLOCK), device)
+}
+
+/// Read `<sysfs_block>/<name>/size` for a `/dev/<name>` device.
+fn sysfs_sectors(sysfs_block: &Path, device: &str) -> Option<u64> {
+    let name = Path::new(device).file_name()?;
+    std::fs::read_to_string(sysfs_block.join(name).join("size"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Size of a block device in bytes, asked of the device itself.
+fn ioctl_device_bytes(device: &Path) -> std::io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(device)?;
+    let mut size: u64 = 0;
+    // SAFETY: BLKGETSIZE64 writes a u64 through the pointer, which is valid
+    // for the duration of the call
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64 as _, &mut size) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(size)
+}
+
+/// Current capacity of a block device in bytes.
+///
+/// virtio-blk updates the capacity when the hypervisor signals a config
+/// change, and sysfs reflects it; `BLKGETSIZE64` covers kernels where the
+/// sysfs entry is missing.
+fn block_device_bytes(device: &str) -> Option<u64> {
+    if let Some(sectors) = block_device_sectors(device) {
+        return Some(sectors * 512);
+    }
+    match ioctl_device_bytes(Path::new(device)) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            debug!(device, error = %e, "BLKGETSIZE64 failed");
+            None
+        }
+    }
+}
+
+/// Grow the mounted storage filesystem to `size_bytes`.
+///
+/// The host extends the disk image first, which raises a virtio config
+/// change; this waits for the device to report the new capacity and then
+/// grows ext4 online. [`StorageError::DeviceNotResized`] means the device never
+/// changed size, so the host may safely shrink the image back; any other
+/// error may leave the guest seeing the larger device.
+pub fn resize_online(size_bytes: u64) -> Result<StorageStatus> {
+    let device = paths::STORAGE_DEVICE;
+    let read_bytes = || {
+        block_device_bytes(device)
+            .ok_or_else(|| StorageError::read_error(device, "device size not available"))
+    };
+    let initial = read_bytes()?;
+    let deadline = std::time::Instant::now() + RESIZE_WAIT;
+    let bytes = loop {
+        let bytes = read_bytes()?;
+        if bytes >= size_bytes {
+            break bytes;
+        }
+        if std::time::Instant::now() >= deadline {
+            if bytes != initial {
+                // Grown, but not all the way: not safe to shrink back
+                return Err(StorageError::Internal {
+                    message: format!(
+                        "{} grew to {} bytes, short of the requested {} bytes",
+                        device, bytes, size_bytes
+                    ),
+                });
+            }
+            return Err(StorageError::DeviceNotResized {
+                device: device.to_string(),
+                expected_bytes: size_bytes,
+                actual_bytes: bytes,
+            });
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    };
+    let sectors = bytes / 512;
+
+    info!(device, sectors, "growing storage filesystem online");
+    let output = Command::new("resize2fs")
+        .arg(device)
+        .output()
+        .map_err(|e| StorageError::SpawnFailed {
+            command: "resize2fs".to_string(),
+            cause: e.to_string(),
+        })?;
+    if !output.status.success() {
+        return Err(StorageError::command_failed(
+            "resize2fs",
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+    // Boot-time resize compares against this, so don't redo the work.
+    let _ = std::fs::write(paths::STORAGE_RESIZED_MARKER, sectors.to_string());
+
+    status()
+}
+
 /// Extract a JSON array of strings from a JSON value.
 fn json_string_array(value: &serde_json::Value, key: &str) -> Vec<String> {
     value[key]
@@ -1117,6 +1318,11 @@ pub fn list_images() -> Result<Vec<ImageInfo>> {
                 .map(unsanitize_image_name)
                 .unwrap_or_default();
 
+            // Intermediate build results aren't user-facing images.
+            if name.starts_with(BUILD_CACHE_REPOSITORY) {
+                continue;
+            }
+
             if let Ok(Some(info)) = query_image(&name) {
                 images.push(info);
             }
@@ -1251,54 +1457,738 @@ pub fn get_layer_digest(image_digest: &str, layer_index: usize) -> Result<String
     )))
 }
 
-/// Run garbage collection.
-pub fn garbage_collect(dry_run: bool) -> Result<u64> {
+/// Prepare a locally stored image for pushing to a registry.
+///
+/// Re-tars every layer into the blob store with the requested compression,
+/// rewrites the config's `rootfs.diff_ids` to match, and builds an OCI image
+/// manifest referencing the new blobs. The host then fetches blobs with
+/// `ExportBlob` and uploads them along with the manifest.
+pub fn prepare_push(image: &str, compression: LayerCompression) -> Result<PushPlan> {
     let root = Path::new(STORAGE_ROOT);
-    let layers_dir = root.join(LAYERS_DIR);
-    let manifests_dir = root.join(MANIFESTS_DIR);
+    let (manifest_json, mut config_json) = read_image(image)?;
 
-    // Collect all referenced layers
-    let mut referenced_layers = std::collections::HashSet::new();
-
-    if manifests_dir.exists() {
-        for entry in std::fs::read_dir(&manifests_dir)? {
-            let entry = entry?;
-            let content = std::fs::read_to_string(entry.path())?;
-            if let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&content) {
-                if let Some(layers) = manifest["layers"].as_array() {
-                    for layer in layers {
-                        if let Some(digest) = layer["digest"].as_str() {
-                            let id = digest.strip_prefix("sha256:").unwrap_or(digest);
-                            referenced_layers.insert(id.to_string());
-                        }
-                    }
+    let layer_digests: Vec<&str> = manifest_json["layers"]
+        .as_array()
+        .ok_or_else(|| StorageError::MissingField {
+            context: "manifest".into(),
+            field: "layers".into(),
+        })?
+        .iter()
+        .filter_map(|l| l["digest"].as_str())
+        .collect();
+
+    let store = BlobStore::new(paths::BLOBS_DIR);
+    let mut layers = Vec::with_capacity(layer_digests.len());
+    let mut diff_ids = Vec::with_capacity(layer_digests.len());
+
+    for layer_digest in layer_digests {
+        let layer_id = layer_digest.strip_prefix("sha256:").unwrap_or(layer_digest);
+        let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+        if !is_layer_cached(&layer_dir) {
+            return Err(StorageError::LayerNotFound {
+                digest: layer_digest.to_string(),
+            });
+        }
+
+        let blob = store.layer(layer_id, &layer_dir, compression)?;
+        diff_ids.push(serde_json::Value::String(blob.diff_id));
+        layers.push(BlobDescriptor {
+            media_type: compression.media_type().to_string(),
+            digest: blob.digest,
+            size: blob.size,
+        });
+    }
+
+    // The diff_ids must describe the tar streams we actually push.
+    config_json["rootfs"] = serde_json::json!({
+        "type": "layers",
+        "diff_ids": diff_ids,
+    });
+    let config_bytes =
+        serde_json::to_vec(&config_json).map_err(|e| StorageError::parse_error("config", e))?;
+    let config = BlobDescriptor {
+        media_type: OCI_CONFIG_MEDIA_TYPE.to_string(),
+        digest: store.put(&config_bytes)?,
+        size: config_bytes.len() as u64,
+    };
+
+    let descriptor = |d: &BlobDescriptor| {
+        serde_json::json!({
+            "mediaType": d.media_type,
+            "digest": d.digest,
+            "size": d.size,
+        })
+    };
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+        "config": descriptor(&config),
+        "layers": layers.iter().map(descriptor).collect::<Vec<_>>(),
+    });
+
+    info!(image = %image, layers = layers.len(), "prepared image for push");
+
+    Ok(PushPlan {
+        manifest: manifest.to_string(),
+        manifest_media_type: OCI_MANIFEST_MEDIA_TYPE.to_string(),
+        config,
+        layers,
+    })
+}
+
+/// Read a stored image's manifest and config.
+pub(crate) fn read_image(image: &str) -> Result<(serde_json::Value, serde_json::Value)> {
+    let root = Path::new(STORAGE_ROOT);
+    let manifest_path = root
+        .join(MANIFESTS_DIR)
+        .join(sanitize_image_name(image) + ".json");
+
+    let manifest =
+        std::fs::read_to_string(&manifest_path).map_err(|_| StorageError::ImageNotFound {
+            image: image.to_string(),
+        })?;
+    let manifest_json: serde_json::Value =
+        serde_json::from_str(&manifest).map_err(|e| StorageError::parse_error("manifest", e))?;
+
+    let config_digest =
+        manifest_json["config"]["digest"]
+            .as_str()
+            .ok_or_else(|| StorageError::MissingField {
+                context: "manifest".into(),
+                field: "config digest".into(),
+            })?;
+    let config_id = config_digest
+        .strip_prefix("sha256:")
+        .unwrap_or(config_digest);
+    let config_path = root.join(CONFIGS_DIR).join(format!("{}.json", config_id));
+    let config = std::fs::read_to_string(&config_path)
+        .map_err(|e| StorageError::read_error(config_path.display().to_string(), e))?;
+    let config_json: serde_json::Value =
+        serde_json::from_str(&config).map_err(|e| StorageError::parse_error("config", e))?;
+
+    Ok((manifest_json, config_json))
+}
+
+/// Store an image manifest and config under `reference`.
+///
+/// The config is stored by its digest; the manifest must already reference
+/// that digest and layers present under `layers/`.
+pub(crate) fn store_image(reference: &str, manifest: &str, config: &[u8]) -> Result<()> {
+    let root = Path::new(STORAGE_ROOT);
+    let config_id = format!("{:x}", sha2::Sha256::digest(config));
+    std::fs::write(
+        root.join(CONFIGS_DIR).join(format!("{}.json", config_id)),
+        config,
+    )?;
+
+    let manifest_path = root
+        .join(MANIFESTS_DIR)
+        .join(sanitize_image_name(reference) + ".json");
+    std::fs::write(&manifest_path, manifest)?;
+    Ok(())
+}
+
+/// Store an existing image under an additional reference.
+pub fn tag_image(source: &str, target: &str) -> Result<ImageInfo> {
+    let root = Path::new(STORAGE_ROOT);
+    let manifest = std::fs::read(
+        root.join(MANIFESTS_DIR)
+            .join(sanitize_image_name(source) + ".json"),
+    )
+    .map_err(|_| StorageError::ImageNotFound {
+        image: source.to_string(),
+    })?;
+    std::fs::write(
+        root.join(MANIFESTS_DIR)
+            .join(sanitize_image_name(target) + ".json"),
+        manifest,
+    )?;
+    query_image(target)?.ok_or_else(|| StorageError::ImageNotFound {
+        image: target.to_string(),
+    })
+}
+
+/// Directory holding an extracted layer.
+pub(crate) fn layer_dir(digest: &str) -> PathBuf {
+    let id = digest.strip_prefix("sha256:").unwrap_or(digest);
+    Path::new(STORAGE_ROOT).join(LAYERS_DIR).join(id)
+}
+
+/// Path of a blob in the agent blob store.
+pub fn blob_path(digest: &str) -> Result<PathBuf> {
+    let path = BlobStore::new(paths::BLOBS_DIR).path(digest).map_err(|e| {
+        StorageError::ValidationFailed {
+            context: "blob digest".into(),
+            reason: e.to_string(),
+        }
+    })?;
+    if !path.exists() {
+        return Err(StorageError::new(format!("blob not found: {}", digest)));
+    }
+    Ok(path)
+}
+
+/// Temporary path for an incoming image archive.
+///
+/// Lives on the storage disk so large archives don't fill the guest's
+/// memory-backed filesystems.
+pub fn import_archive_path() -> Result<PathBuf> {
+    let tmp_dir = Path::new(STORAGE_ROOT).join("tmp");
+    std::fs::create_dir_all(&tmp_dir)?;
+    Ok(tmp_dir.join(format!("import-{}.tar", std::process::id())))
+}
+
+/// Import images from an OCI layout or `docker save` tarball.
+///
+/// The archive may be gzip- or zstd-compressed. If `reference` is given,
+/// the archive must contain exactly one image, which is stored under that
+/// name instead of the names recorded in the archive.
+pub fn import_image_archive(archive: &Path, reference: Option<&str>) -> Result<Vec<ImageInfo>> {
+    let root = Path::new(STORAGE_ROOT);
+    let unpack_dir = archive.with_extension("d");
+    let _ = std::fs::remove_dir_all(&unpack_dir);
+    std::fs::create_dir_all(&unpack_dir)?;
+
+    let result = unpack_tar(archive, &unpack_dir)
+        .map_err(|e| StorageError::new(format!("failed to unpack image archive: {}", e)))
+        .and_then(|_| install_archive_images(root, &unpack_dir, reference));
+
+    let _ = std::fs::remove_dir_all(&unpack_dir);
+    result
+}
+
+fn install_archive_images(
+    root: &Path,
+    dir: &Path,
+    reference: Option<&str>,
+) -> Result<Vec<ImageInfo>> {
+    #[cfg(target_arch = "aarch64")]
+    let arch = "arm64";
+    #[cfg(target_arch = "x86_64")]
+    let arch = "amd64";
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    let arch = "unknown";
+
+    let images =
+        image_archive::read_archive(dir, arch).map_err(|e| StorageError::ValidationFailed {
+            context: "image archive".into(),
+            reason: e.to_string(),
+        })?;
+
+    if reference.is_some() && images.len() != 1 {
+        return Err(StorageError::new(format!(
+            "archive contains {} images; a tag can only be applied to a single image",
+            images.len()
+        )));
+    }
+
+    let mut names = Vec::new();
+    for image in &images {
+        let image_names = match reference {
+            Some(r) => vec![r.to_string()],
+            None => image.names.clone(),
+        };
+        if image_names.is_empty() {
+            return Err(StorageError::new(
+                "archive image has no name; specify a tag to import it",
+            ));
+        }
+
+        for layer in &image.layers {
+            let layer_id = layer
+                .digest
+                .strip_prefix("sha256:")
+                .unwrap_or(&layer.digest);
+            let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+            if is_layer_cached(&layer_dir) {
+                info!(layer = %layer_id, "layer already cached");
+                continue;
+            }
+            if layer_dir.exists() {
+                let _ = std::fs::remove_dir_all(&layer_dir);
+            }
+
+            info!(layer = %layer_id, "extracting layer");
+            std::fs::create_dir_all(&layer_dir)?;
+            if let Err(e) = unpack_tar(&layer.path, &layer_dir) {
+                if let Err(e) = std::fs::remove_dir_all(&layer_dir) {
+                    warn!(layer = %layer_id, error = %e, "failed to clean up layer directory after extraction failure");
                 }
+                return Err(StorageError::new(format!(
+                    "failed to extract layer {}: {}",
+                    layer.digest, e
+                )));
             }
         }
+
+        for name in image_names {
+            store_image(&name, &image.manifest, &image.config)?;
+            names.push(name);
+        }
     }
 
-    // Find unreferenced layers
-    let mut freed = 0u64;
+    // Persist layers before reporting success (see pull_image_with_progress_and_auth).
+    // SAFETY: sync() is always safe to call
+    unsafe {
+        libc::sync();
+    }
 
-    if layers_dir.exists() {
-        for entry in std::fs::read_dir(&layers_dir)? {
-            let entry = entry?;
-            let layer_id = entry.file_name().to_string_lossy().to_string();
+    let mut infos = Vec::new();
+    for name in &names {
+        infos.extend(query_image(name)?);
+    }
+    Ok(infos)
+}
 
-            if !referenced_layers.contains(&layer_id) {
-                let size = dir_size(&entry.path()).unwrap_or(0);
-                info!(layer = %layer_id, size = size, dry_run = dry_run, "unreferenced layer");
+/// Extract a plain, gzip or zstd tarball into `dest`.
+pub(crate) fn unpack_tar(path: &Path, dest: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    let mut magic = [0u8; 4];
+    let n = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let reader: Box<dyn Read> = match &magic[..n] {
+        [0x1f, 0x8b, ..] => Box::new(flate2::read::GzDecoder::new(file)),
+        [0x28, 0xb5, 0x2f, 0xfd] => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
 
-                if !dry_run {
-                    std::fs::remove_dir_all(entry.path())?;
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(false);
+    archive.set_overwrite(true);
+    archive.unpack(dest)
+}
+
+// ============================================================================
+// Garbage Collection and Disk Usage
+// ============================================================================
+
+/// A registered container, as far as storage is concerned.
+#[derive(Debug, Clone)]
+pub struct ContainerRef {
+    /// Container ID.
+    pub id: String,
+    /// Image the container was created from.
+    pub image: String,
+    /// Container state.
+    pub state: String,
+    /// Workload ID of the container's overlay.
+    pub workload_id: String,
+}
+
+/// Storage that running workloads hold on to; GC never removes it.
+#[derive(Debug, Default)]
+pub struct InUse {
+    /// Registered containers. Their images and overlays are kept.
+    pub containers: Vec<ContainerRef>,
+    /// Layer IDs mounted as overlay lower directories.
+    pub layers: HashSet<String>,
+    /// Overlay workload IDs that are mounted or being set up.
+    pub overlays: HashSet<String>,
+}
+
+impl InUse {
+    /// Collect what is in use from `/proc/mounts` and the overlay directory.
+    pub fn scan(containers: Vec<ContainerRef>) -> Self {
+        let root = Path::new(STORAGE_ROOT);
+        let layers_dir = root.join(LAYERS_DIR);
+        let overlays_dir = root.join(OVERLAYS_DIR);
+        let mut in_use = Self {
+            containers,
+            ..Self::default()
+        };
+
+        let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+        for line in mounts.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [_, target, fstype, options, ..] = fields.as_slice() else {
+                continue;
+            };
+            if let Some(id) = first_component(Path::new(target), &overlays_dir) {
+                in_use.overlays.insert(id);
+            }
+            if *fstype != "overlay" {
+                continue;
+            }
+            let lowerdirs = options
+                .split(',')
+                .filter_map(|o| o.strip_prefix("lowerdir="))
+                .flat_map(|dirs| dirs.split(':'));
+            for dir in lowerdirs {
+                if let Some(id) = first_component(Path::new(dir), &layers_dir) {
+                    in_use.layers.insert(id);
                 }
+            }
+        }
+
+        let grace = std::time::Duration::from_secs(OVERLAY_GC_GRACE_SECS);
+        for entry in std::fs::read_dir(&overlays_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let recent = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .map(|t| t.elapsed().map_or(true, |age| age < grace))
+                .unwrap_or(false);
+            if recent {
+                in_use
+                    .overlays
+                    .insert(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        in_use
+    }
+
+    /// Containers using the given stored image.
+    fn image_users(&self, image: &StoredImage) -> Vec<&ContainerRef> {
+        self.containers
+            .iter()
+            .filter(|c| sanitize_image_name(&c.image) == image.name)
+            .collect()
+    }
+}
+
+/// First component of `path` below `dir`, if `path` is inside `dir`.
+fn first_component(path: &Path, dir: &Path) -> Option<String> {
+    path.strip_prefix(dir)
+        .ok()?
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+}
+
+/// An image manifest in storage.
+struct StoredImage {
+    /// Manifest file stem (the sanitized reference).
+    name: String,
+    /// Best-effort image reference.
+    reference: String,
+    path: PathBuf,
+    /// Layer IDs (hex digests).
+    layers: Vec<String>,
+    /// Config ID (hex digest).
+    config: Option<String>,
+    build_cache: bool,
+}
+
+fn read_stored_images(root: &Path) -> Result<Vec<StoredImage>> {
+    let mut images = Vec::new();
+    let entries = match std::fs::read_dir(root.join(MANIFESTS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(images),
+        Err(e) => return Err(e.into()),
+    };
+    let hex = |digest: &str| digest.strip_prefix("sha256:").unwrap_or(digest).to_string();
+
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|_| path.extension().is_some_and(|e| e == "json"))
+            .map(String::from)
+        else {
+            continue;
+        };
+        // Unparseable manifests reference nothing, so their layers are
+        // collected like any other unreferenced layer.
+        let manifest: serde_json::Value = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        images.push(StoredImage {
+            reference: unsanitize_image_name(&name),
+            build_cache: name.starts_with(&sanitize_image_name(BUILD_CACHE_REPOSITORY)),
+            layers: manifest["layers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|l| l["digest"].as_str().map(hex))
+                .collect(),
+            config: manifest["config"]["digest"].as_str().map(hex),
+            name,
+            path,
+        });
+    }
+    images.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(images)
+}
+
+/// Names and paths of the entries of `dir` (empty if it does not exist).
+fn dir_entries(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut entries = Vec::new();
+    match std::fs::read_dir(dir) {
+        Ok(iter) => {
+            for entry in iter {
+                let entry = entry?;
+                entries.push((
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                ));
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Find what garbage collection of `targets` would remove.
+///
+/// Removing images or build cache also collects the layers and configs
+/// that only those images used.
+fn plan_gc(root: &Path, targets: &[GcTarget], in_use: &InUse) -> Result<Vec<(GcItem, PathBuf)>> {
+    let mut targets: HashSet<GcTarget> = if targets.is_empty() {
+        GcTarget::DEFAULT.iter().copied().collect()
+    } else {
+        targets.iter().copied().collect()
+    };
+    if targets.contains(&GcTarget::Images) || targets.contains(&GcTarget::BuildCache) {
+        targets.extend([GcTarget::Layers, GcTarget::Configs]);
+    }
+    let mut plan = Vec::new();
+    let mut item = |target, id: String, path: PathBuf| {
+        let size = dir_size(&path).unwrap_or(0);
+        plan.push((GcItem { target, id, size }, path));
+    };
+
+    let mut kept = Vec::new();
+    for image in read_stored_images(root)? {
+        let target = if image.build_cache {
+            GcTarget::BuildCache
+        } else {
+            GcTarget::Images
+        };
+        if targets.contains(&target) && in_use.image_users(&image).is_empty() {
+            item(target, image.reference, image.path);
+        } else {
+            kept.push(image);
+        }
+    }
+
+    if targets.contains(&GcTarget::Layers) {
+        let referenced: HashSet<&String> = kept.iter().flat_map(|i| &i.layers).collect();
+        for (id, path) in dir_entries(&root.join(LAYERS_DIR))? {
+            if !referenced.contains(&id) && !in_use.layers.contains(&id) {
+                item(GcTarget::Layers, format!("sha256:{}", id), path);
+            }
+        }
+    }
+
+    if targets.contains(&GcTarget::Configs) {
+        let referenced: HashSet<&String> = kept.iter().filter_map(|i| i.config.as_ref()).collect();
+        for (name, path) in dir_entries(&root.join(CONFIGS_DIR))? {
+            let id = name.strip_suffix(".json").unwrap_or(&name).to_string();
+            if !referenced.contains(&id) {
+                item(GcTarget::Configs, format!("sha256:{}", id), path);
+            }
+        }
+    }
+
+    if targets.contains(&GcTarget::Overlays) {
+        let owned: HashSet<&String> = in_use.containers.iter().map(|c| &c.workload_id).collect();
+        for (id, path) in dir_entries(&root.join(OVERLAYS_DIR))? {
+            if !owned.contains(&id) && !in_use.overlays.contains(&id) {
+                item(GcTarget::Overlays, id, path);
+            }
+        }
+    }
 
-                freed += size;
+    if targets.contains(&GcTarget::Blobs) {
+        let blobs_dir = Path::new(paths::BLOBS_DIR);
+        for (dir, dir_path) in dir_entries(blobs_dir)? {
+            for (name, path) in dir_entries(&dir_path)? {
+                item(GcTarget::Blobs, format!("{}/{}", dir, name), path);
             }
         }
     }
 
-    Ok(freed)
+    Ok(plan)
+}
+
+/// Remove the planned items, returning the report.
+fn apply_gc(plan: Vec<(GcItem, PathBuf)>, dry_run: bool) -> Result<GcReport> {
+    let mut report = GcReport {
+        dry_run,
+        ..GcReport::default()
+    };
+    for (item, path) in plan {
+        info!(target = item.target.as_str(), id = %item.id, size = item.size, dry_run, "collecting");
+        if !dry_run {
+            let result = if path.is_dir() && !path.is_symlink() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            if let Err(e) = result {
+                warn!(path = %path.display(), error = %e, "failed to remove");
+                continue;
+            }
+        }
+        report.freed_bytes += item.size;
+        report.items.push(item);
+    }
+    Ok(report)
+}
+
+/// Garbage collect the given categories ([`GcTarget::DEFAULT`] if empty).
+///
+/// Nothing that a registered container or a mounted overlay uses is
+/// removed.
+pub fn garbage_collect(dry_run: bool, targets: &[GcTarget], in_use: &InUse) -> Result<GcReport> {
+    let plan = plan_gc(Path::new(STORAGE_ROOT), targets, in_use)?;
+    apply_gc(plan, dry_run)
+}
+
+/// Remove an image reference and the layers and config only it used.
+pub fn remove_image(image: &str, in_use: &InUse) -> Result<GcReport> {
+    remove_image_at(Path::new(STORAGE_ROOT), image, in_use)
+}
+
+fn remove_image_at(root: &Path, image: &str, in_use: &InUse) -> Result<GcReport> {
+    let name = sanitize_image_name(image);
+    let stored = read_stored_images(root)?
+        .into_iter()
+        .find(|i| i.name == name)
+        .ok_or_else(|| StorageError::ImageNotFound {
+            image: image.to_string(),
+        })?;
+
+    let users = in_use.image_users(&stored);
+    if !users.is_empty() {
+        let ids: Vec<&str> = users.iter().map(|c| c.id.as_str()).collect();
+        return Err(StorageError::InUse {
+            resource: format!("image '{}'", image),
+            users: format!("container(s) {}", ids.join(", ")),
+        });
+    }
+
+    let size = dir_size(&stored.path).unwrap_or(0);
+    std::fs::remove_file(&stored.path)?;
+    let mut report = apply_gc(
+        plan_gc(root, &[GcTarget::Layers, GcTarget::Configs], in_use)?,
+        false,
+    )?;
+    report.items.insert(
+        0,
+        GcItem {
+            target: GcTarget::Images,
+            id: image.to_string(),
+            size,
+        },
+    );
+    report.freed_bytes += size;
+    Ok(report)
+}
+
+/// Report storage usage by category.
+pub fn disk_usage(in_use: &InUse) -> Result<DiskUsage> {
+    disk_usage_at(Path::new(STORAGE_ROOT), in_use)
+}
+
+fn disk_usage_at(root: &Path, in_use: &InUse) -> Result<DiskUsage> {
+    let images = read_stored_images(root)?;
+    let layer_sizes: HashMap<String, u64> = dir_entries(&root.join(LAYERS_DIR))?
+        .into_iter()
+        .map(|(id, path)| {
+            let size = dir_size(&path).unwrap_or(0);
+            (id, size)
+        })
+        .collect();
+    let layer_size = |id: &String| layer_sizes.get(id).copied().unwrap_or(0);
+
+    // How many images use each layer
+    let mut users: HashMap<&String, usize> = HashMap::new();
+    for image in &images {
+        let unique: HashSet<&String> = image.layers.iter().collect();
+        for layer in unique {
+            *users.entry(layer).or_default() += 1;
+        }
+    }
+
+    let mut usage = DiskUsage::default();
+    let mut cache_layers = HashSet::new();
+    let mut image_layers = HashSet::new();
+    for image in &images {
+        let layers: HashSet<&String> = image.layers.iter().collect();
+        if image.build_cache {
+            usage.build_cache.count += 1;
+            cache_layers.extend(layers);
+            continue;
+        }
+        image_layers.extend(layers.iter().copied());
+        let (shared, unique): (Vec<&String>, Vec<&String>) =
+            layers.into_iter().partition(|l| users[l] > 1);
+        let shared_size: u64 = shared.into_iter().map(layer_size).sum();
+        let unique_size: u64 = unique.into_iter().map(layer_size).sum();
+        usage.images.push(ImageUsage {
+            reference: image.reference.clone(),
+            size: shared_size + unique_size,
+            shared_size,
+            unique_size,
+            containers: in_use.image_users(image).len(),
+        });
+    }
+    usage.build_cache.size = cache_layers
+        .difference(&image_layers)
+        .map(|l| layer_size(l))
+        .sum();
+    usage.build_cache.reclaimable_count = usage.build_cache.count;
+    usage.build_cache.reclaimable_size = usage.build_cache.size;
+
+    for container in &in_use.containers {
+        let upper = root
+            .join(OVERLAYS_DIR)
+            .join(&container.workload_id)
+            .join("upper");
+        usage.containers.push(ContainerUsage {
+            id: container.id.clone(),
+            image: container.image.clone(),
+            state: container.state.clone(),
+            size: dir_size(&upper).unwrap_or(0),
+        });
+    }
+
+    usage.layers.count = layer_sizes.len();
+    usage.layers.size = layer_sizes.values().sum();
+    for (target, dir, summary) in [
+        (
+            GcTarget::Overlays,
+            root.join(OVERLAYS_DIR),
+            &mut usage.overlays,
+        ),
+        (
+            GcTarget::Configs,
+            root.join(CONFIGS_DIR),
+            &mut usage.configs,
+        ),
+    ] {
+        for (_, path) in dir_entries(&dir)? {
+            summary.count += 1;
+            summary.size += dir_size(&path).unwrap_or(0);
+        }
+        add_reclaimable(summary, &plan_gc(root, &[target], in_use)?);
+    }
+    add_reclaimable(
+        &mut usage.layers,
+        &plan_gc(root, &[GcTarget::Layers], in_use)?,
+    );
+
+    let blobs = plan_gc(root, &[GcTarget::Blobs], in_use)?;
+    usage.blobs.count = blobs.len();
+    usage.blobs.size = blobs.iter().map(|(item, _)| item.size).sum();
+    add_reclaimable(&mut usage.blobs, &blobs);
+
+    Ok(usage)
+}
+
+fn add_reclaimable(summary: &mut UsageSummary, plan: &[(GcItem, PathBuf)]) {
+    summary.reclaimable_count = plan.len();
+    summary.reclaimable_size = plan.iter().map(|(item, _)| item.size).sum();
 }
 
 // ============================================================================
@@ -1358,7 +2248,7 @@ impl OverlaySetup {
         let upper_etc = self.upper_path.join("etc");
         std::fs::create_dir_all(&upper_etc)?;
         let resolv_path = upper_etc.join("resolv.conf");
-        if let Err(e) = std::fs::write(&resolv_path, "nameserver 8.8.8.8\nnameserver 1.1.1.1\n") {
+        if let Err(e) = std::fs::write(&resolv_path, crate::dns::resolv_conf()) {
             warn!(error = %e, "failed to write resolv.conf to upper layer");
         }
 
@@ -1489,6 +2379,11 @@ impl OverlaySetup {
     }
 }
 
+/// Mount an overlay over explicit layer directories (top layer first).
+pub(crate) fn mount_overlay(workload_id: &str, lowerdirs: Vec<String>) -> Result<OverlayInfo> {
+    OverlaySetup::new(workload_id).execute(lowerdirs)
+}
+
 /// Prepare an overlay filesystem for a workload.
 pub fn prepare_overlay(image: &str, workload_id: &str) -> Result<OverlayInfo> {
     // Check if we have packed layers available
@@ -1615,7 +2510,7 @@ pub fn run_command(
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
+    mounts: &[ContainerMount],
     timeout_ms: Option<u64>,
 ) -> Result<RunResult> {
     // Validate inputs
@@ -1632,6 +2527,14 @@ pub fn run_command(
     // Setup volume mounts (mount virtiofs to staging area)
     let mounted_paths = setup_volume_mounts(&overlay.rootfs_path, mounts)?;
 
+    // Trust the VM's custom CA certificates
+    crate::proxy::install_ca_certs_into(Path::new(&overlay.rootfs_path))
+        .map_err(|e| StorageError::new(format!("failed to install CA certificates: {}", e)))?;
+
+    // Apply the VM's DNS settings
+    crate::dns::install_into(Path::new(&overlay.rootfs_path))
+        .map_err(|e| StorageError::new(format!("failed to apply DNS settings: {}", e)))?;
+
     // Get bundle path
     let overlay_root = Path::new(STORAGE_ROOT)
         .join(OVERLAYS_DIR)
@@ -1642,15 +2545,8 @@ pub fn run_command(
     let workdir_str = workdir.unwrap_or("/");
     let mut spec = OciSpec::new(command, env, workdir_str, false);
 
-    // Add virtiofs bind mounts to OCI spec
-    for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        spec.add_bind_mount(
-            &virtiofs_mount.to_string_lossy(),
-            container_path,
-            *read_only,
-        );
-    }
+    // Add bind and tmpfs mounts to OCI spec
+    spec.add_container_mounts(mounts);
 
     // Write config.json to bundle
     spec.write_to(&bundle_path)
@@ -1683,59 +2579,54 @@ pub fn prepare_for_run(image: &str) -> Result<String> {
 }
 
 /// Setup volume mounts for a rootfs (public wrapper).
-pub fn setup_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Result<()> {
+pub fn setup_mounts(rootfs: &str, mounts: &[ContainerMount]) -> Result<()> {
     let _mounted_paths = setup_volume_mounts(rootfs, mounts)?;
     Ok(())
 }
 
 /// Setup volume mounts by mounting virtiofs and bind-mounting into the rootfs.
-fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Result<Vec<PathBuf>> {
+fn setup_volume_mounts(rootfs: &str, mounts: &[ContainerMount]) -> Result<Vec<PathBuf>> {
     let mut mounted_paths = Vec::new();
 
-    for (tag, container_path, read_only) in mounts {
+    // Tmpfs mounts are created by crun from the OCI spec.
+    for mount in mounts {
+        let Some(tag) = mount.tag() else {
+            continue;
+        };
+        let (container_path, read_only) = (&mount.target, &mount.read_only);
         debug!(tag = %tag, container_path = %container_path, read_only = %read_only, "setting up volume mount");
 
         // First, mount the virtiofs device at a staging location
         let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        std::fs::create_dir_all(&virtiofs_mount)?;
-
-        // Check if already mounted
-        if !is_mountpoint(&virtiofs_mount) {
-            info!(tag = %tag, mount_point = %virtiofs_mount.display(), "mounting virtiofs");
-
-            // Mount virtiofs using direct syscall (avoids ~3-5ms fork+exec overhead).
-            // Use sync option to ensure writes are persisted immediately.
-            let src = std::ffi::CString::new(tag.as_str()).map_err(|e| StorageError::Internal {
-                message: format!("invalid tag: {}", e),
-            })?;
-            let dst =
-                std::ffi::CString::new(virtiofs_mount.to_string_lossy().as_ref()).map_err(|e| {
-                    StorageError::Internal {
-                        message: format!("invalid mount point: {}", e),
-                    }
-                })?;
-            let fstype = std::ffi::CString::new("virtiofs").unwrap();
-            let opts = std::ffi::CString::new("sync").unwrap();
-            // SAFETY: mount virtiofs with valid CString arguments
-            let rc = unsafe {
-                libc::mount(
-                    src.as_ptr(),
-                    dst.as_ptr(),
-                    fstype.as_ptr(),
-                    0,
-                    opts.as_ptr() as *const libc::c_void,
-                )
-            };
-            if rc != 0 {
-                let err = std::io::Error::last_os_error();
-                warn!(error = %err, tag = %tag, "failed to mount virtiofs device");
-                continue;
+        let file_mount = FILE_MOUNTS.get().and_then(|files| files.get(tag));
+        let disk_mount = DISK_MOUNTS.get().and_then(|disks| disks.get(tag));
+        let sync_mount = SYNC_MOUNTS.get().and_then(|syncs| syncs.get(tag));
+        let staged = match (file_mount, disk_mount, sync_mount) {
+            (_, Some((device, disk_read_only)), _) => {
+                stage_disk_mount(device, *disk_read_only, &virtiofs_mount)
             }
+            (_, None, Some((policy, sync_read_only))) => stage_sync_mount(
+                tag,
+                container_path,
+                *policy,
+                *sync_read_only,
+                &virtiofs_mount,
+            ),
+            (Some(file), None, None) => stage_file_mount(tag, file, &virtiofs_mount),
+            (None, None, None) => stage_dir_mount(tag, &virtiofs_mount),
+        };
+        if let Err(e) = staged {
+            warn!(error = %e, tag = %tag, "failed to mount virtiofs device");
+            continue;
         }
 
         // Now bind-mount into the container rootfs
         let target_path = format!("{}{}", rootfs, container_path);
-        std::fs::create_dir_all(&target_path)?;
+        if file_mount.is_some() {
+            create_file_target(Path::new(&target_path))?;
+        } else {
+            std::fs::create_dir_all(&target_path)?;
+        }
 
         // Check if already bind-mounted
         if !is_mountpoint(Path::new(&target_path)) {
@@ -1746,45 +2637,10 @@ fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Resul
                 "bind-mounting into container"
             );
 
-            // Bind mount using direct syscall
-            let bind_src = std::ffi::CString::new(virtiofs_mount.to_string_lossy().as_ref())
-                .map_err(|e| StorageError::Internal {
-                    message: format!("invalid source: {}", e),
-                })?;
-            let bind_dst = std::ffi::CString::new(target_path.as_str()).map_err(|e| {
-                StorageError::Internal {
-                    message: format!("invalid target: {}", e),
-                }
-            })?;
-            // SAFETY: bind mount with MS_BIND flag
-            let rc = unsafe {
-                libc::mount(
-                    bind_src.as_ptr(),
-                    bind_dst.as_ptr(),
-                    std::ptr::null(),
-                    libc::MS_BIND,
-                    std::ptr::null(),
-                )
-            };
-            if rc != 0 {
-                let err = std::io::Error::last_os_error();
-                warn!(error = %err, target = %target_path, "failed to bind-mount");
+            if let Err(e) = bind_mount(&virtiofs_mount, Path::new(&target_path), *read_only) {
+                warn!(error = %e, target = %target_path, "failed to bind-mount");
                 continue;
             }
-
-            // Remount read-only if requested
-            if *read_only {
-                // SAFETY: remount with MS_BIND|MS_RDONLY|MS_REMOUNT
-                unsafe {
-                    libc::mount(
-                        std::ptr::null(),
-                        bind_dst.as_ptr(),
-                        std::ptr::null(),
-                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
-                        std::ptr::null(),
-                    );
-                }
-            }
         }
 
         mounted_paths.push(PathBuf::from(target_path));
@@ -1793,6 +2649,202 @@ fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Resul
     Ok(mounted_paths)
 }
 
+/// Mount a shared directory at its staging location.
+fn stage_dir_mount(tag: &str, staging: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(staging)?;
+    if is_mountpoint(staging) {
+        return Ok(());
+    }
+    info!(tag = %tag, mount_point = %staging.display(), "mounting virtiofs");
+    mount_virtiofs(tag, staging)
+}
+
+/// Mount a share for syncing and put its local copy at the staging
+/// location. The first setup of a tag copies the share in, so this can
+/// take a while for large trees.
+fn stage_sync_mount(
+    tag: &str,
+    target: &str,
+    policy: SyncConflictPolicy,
+    read_only: bool,
+    staging: &Path,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(staging)?;
+    if is_mountpoint(staging) {
+        return Ok(());
+    }
+
+    let share = Path::new(paths::VIRTIOFS_MOUNT_ROOT)
+        .join(".shares")
+        .join(tag);
+    std::fs::create_dir_all(&share)?;
+    if !is_mountpoint(&share) {
+        info!(tag = %tag, mount_point = %share.display(), "mounting virtiofs for sync");
+        mount_virtiofs(tag, &share)?;
+    }
+
+    let local = crate::sync::start(tag, target, &share, policy, read_only)?;
+    bind_mount(&local, staging, false)
+}
+
+/// Mount a named image volume's ext4 device at the staging location,
+/// growing the filesystem to the device size (the host creates images from
+/// a small template).
+fn stage_disk_mount(device: &str, read_only: bool, staging: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(staging)?;
+    if is_mountpoint(staging) {
+        return Ok(());
+    }
+    info!(device = %device, mount_point = %staging.display(), read_only, "mounting volume disk");
+
+    let src = path_cstring(Path::new(device))?;
+    let dst = path_cstring(staging)?;
+    let fstype = std::ffi::CString::new("ext4").unwrap();
+    let mut flags = libc::MS_NOATIME;
+    if read_only {
+        flags |= libc::MS_RDONLY;
+    }
+    // SAFETY: mount an ext4 block device with valid CString arguments
+    let rc = unsafe {
+        libc::mount(
+            src.as_ptr(),
+            dst.as_ptr(),
+            fstype.as_ptr(),
+            flags,
+            std::ptr::null(),
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    if !read_only {
+        match Command::new("resize2fs").arg(device).output() {
+            Ok(output) if !output.status.success() => {
+                warn!(device = %device, stderr = %String::from_utf8_lossy(&output.stderr), "failed to grow volume filesystem");
+            }
+            Err(e) => warn!(error = %e, device = %device, "failed to run resize2fs"),
+            Ok(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Stage a single-file mount: the device shares the host directory holding
+/// the file, which is mounted at a hidden path long enough to bind the file
+/// onto the staging path and then detached, so its siblings stay hidden.
+fn stage_file_mount(tag: &str, file: &str, staging: &Path) -> std::io::Result<()> {
+    if is_mountpoint(staging) {
+        return Ok(());
+    }
+    if file.is_empty() || file.contains('/') || file == "." || file == ".." {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid shared file name '{}'", file),
+        ));
+    }
+
+    let share = Path::new(paths::VIRTIOFS_MOUNT_ROOT)
+        .join(".shares")
+        .join(tag);
+    std::fs::create_dir_all(&share)?;
+    create_file_target(staging)?;
+
+    info!(tag = %tag, file = %file, mount_point = %staging.display(), "mounting virtiofs file");
+    mount_virtiofs(tag, &share)?;
+    let bound = bind_mount(&share.join(file), staging, false);
+
+    let share_c = path_cstring(&share)?;
+    // SAFETY: lazy unmount of the directory mounted above
+    unsafe {
+        libc::umount2(share_c.as_ptr(), libc::MNT_DETACH);
+    }
+    let _ = std::fs::remove_dir(&share);
+    bound
+}
+
+/// Create an empty file to bind a single-file mount onto.
+fn create_file_target(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if path.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("cannot mount a file over directory {}", path.display()),
+        ));
+    }
+    if !path.exists() {
+        std::fs::File::create(path)?;
+    }
+    Ok(())
+}
+
+fn path_cstring(path: &Path) -> std::io::Result<std::ffi::CString> {
+    std::ffi::CString::new(path.to_string_lossy().as_ref())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+/// Mount a virtiofs device.
+///
+/// Uses a direct syscall (avoids ~3-5ms fork+exec overhead) and the sync
+/// option to ensure writes are persisted immediately.
+fn mount_virtiofs(tag: &str, mount_point: &Path) -> std::io::Result<()> {
+    let src = std::ffi::CString::new(tag)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let dst = path_cstring(mount_point)?;
+    let fstype = std::ffi::CString::new("virtiofs").unwrap();
+    let opts = std::ffi::CString::new("sync").unwrap();
+    // SAFETY: mount virtiofs with valid CString arguments
+    let rc = unsafe {
+        libc::mount(
+            src.as_ptr(),
+            dst.as_ptr(),
+            fstype.as_ptr(),
+            0,
+            opts.as_ptr() as *const libc::c_void,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Bind-mount a file or directory, optionally read-only.
+fn bind_mount(source: &Path, target: &Path, read_only: bool) -> std::io::Result<()> {
+    let bind_src = path_cstring(source)?;
+    let bind_dst = path_cstring(target)?;
+    // SAFETY: bind mount with MS_BIND flag
+    let rc = unsafe {
+        libc::mount(
+            bind_src.as_ptr(),
+            bind_dst.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND,
+            std::ptr::null(),
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // Remount read-only if requested
+    if read_only {
+        // SAFETY: remount with MS_BIND|MS_REMOUNT|MS_RDONLY
+        unsafe {
+            libc::mount(
+                std::ptr::null(),
+                bind_dst.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                std::ptr::null(),
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Get existing overlay or create new one.
 fn get_or_create_overlay(image: &str, workload_id: &str) -> Result<OverlayInfo> {
     let root = Path::new(STORAGE_ROOT);
@@ -1823,7 +2875,7 @@ fn is_mountpoint(path: &Path) -> bool {
 ///
 /// This uses `crun run` which creates, starts, waits, and deletes the container
 /// in a single operation. Stdout and stderr are captured.
-fn run_with_crun(
+pub(crate) fn run_with_crun(
     bundle_dir: &Path,
     container_id: &str,
     timeout_ms: Option<u64>,
@@ -2275,7 +3327,7 @@ fn get_disk_usage(path: &Path) -> Result<(u64, u64)> {
             let free = stat.f_bfree * stat.f_frsize;
             let used = total - free;
 
-            Ok((total as u64, used as u64))
+            Ok((total, used))
         }
     }
 
@@ -2313,21 +3365,24 @@ fn oci_platform_to_arch(oci_platform: &str) -> String {
 }
 
 /// Calculate directory size recursively.
+///
+/// Symlinks are not followed: layers contain absolute links that would
+/// otherwise resolve into the agent's own filesystem.
 fn dir_size(path: &Path) -> Result<u64> {
-    let mut size = 0;
-
-    if path.is_file() {
-        return Ok(std::fs::metadata(path)?.len());
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
     }
 
+    let mut size = 0;
     for entry in std::fs::read_dir(path)? {
         let entry: std::fs::DirEntry = entry?;
-        let path = entry.path();
+        let file_type = entry.file_type()?;
 
-        if path.is_file() {
-            size += std::fs::metadata(&path)?.len();
-        } else if path.is_dir() {
-            size += dir_size(&path)?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
         }
     }
 
@@ -2361,6 +3416,22 @@ mod tests {
         assert_eq!(oci_platform_to_arch("unknown"), "unknown");
     }
 
+    #[test]
+    fn test_create_file_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("root/.config/git/config");
+        create_file_target(&target).unwrap();
+        assert!(target.is_file());
+
+        // An existing file is left alone
+        std::fs::write(&target, "keep").unwrap();
+        create_file_target(&target).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "keep");
+
+        // A file can't be mounted over a directory
+        assert!(create_file_target(dir.path().join("root").as_path()).is_err());
+    }
+
     #[test]
     fn test_sanitize_image_name() {
         assert_eq!(sanitize_image_name("alpine:latest"), "alpine_latest");
@@ -2373,4 +3444,217 @@ mod tests {
             "ghcr.io_owner_repo_sha256_abc123"
         );
     }
+
+    #[test]
+    fn test_unpack_tar_compressed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "etc/motd", &b"hello"[..])
+            .unwrap();
+        let plain = builder.into_inner().unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut gz, &plain).unwrap();
+        let archives = [
+            ("plain.tar", plain.clone()),
+            ("gz.tar", gz.finish().unwrap()),
+            ("zst.tar", zstd::encode_all(&plain[..], 0).unwrap()),
+        ];
+
+        for (name, data) in archives {
+            let path = tmp.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            let dest = tmp.path().join(format!("{}.d", name));
+            std::fs::create_dir_all(&dest).unwrap();
+            unpack_tar(&path, &dest).unwrap();
+            assert_eq!(std::fs::read(dest.join("etc/motd")).unwrap(), b"hello");
+        }
+    }
+
+    /// Write a layer of `size` bytes and return its ID.
+    fn write_layer(root: &Path, id: &str, size: usize) -> String {
+        let dir = root.join(LAYERS_DIR).join(id);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data"), vec![0u8; size]).unwrap();
+        id.to_string()
+    }
+
+    fn write_image(root: &Path, reference: &str, config: &str, layers: &[&str]) {
+        let manifest = serde_json::json!({
+            "config": { "digest": format!("sha256:{}", config) },
+            "layers": layers
+                .iter()
+                .map(|l| serde_json::json!({ "digest": format!("sha256:{}", l) }))
+                .collect::<Vec<_>>(),
+        });
+        let manifests = root.join(MANIFESTS_DIR);
+        std::fs::create_dir_all(&manifests).unwrap();
+        std::fs::write(
+            manifests.join(format!("{}.json", sanitize_image_name(reference))),
+            manifest.to_string(),
+        )
+        .unwrap();
+        let configs = root.join(CONFIGS_DIR);
+        std::fs::create_dir_all(&configs).unwrap();
+        std::fs::write(configs.join(format!("{}.json", config)), "{}").unwrap();
+    }
+
+    fn container(id: &str, image: &str) -> ContainerRef {
+        ContainerRef {
+            id: id.to_string(),
+            image: image.to_string(),
+            state: "running".to_string(),
+            workload_id: format!("container-{}", id),
+        }
+    }
+
+    fn planned(root: &Path, targets: &[GcTarget], in_use: &InUse) -> Vec<String> {
+        plan_gc(root, targets, in_use)
+            .unwrap()
+            .into_iter()
+            .map(|(item, _)| format!("{}:{}", item.target.as_str(), item.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_gc_default_keeps_referenced_and_in_use() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write_layer(root, "aaa", 10);
+        write_layer(root, "orphan", 10);
+        write_layer(root, "mounted", 10);
+        write_image(root, "alpine:latest", "c1", &["aaa"]);
+        std::fs::write(root.join(CONFIGS_DIR).join("stale.json"), "{}").unwrap();
+        for overlay in ["container-c1", "persistent-old", "build-running"] {
+            std::fs::create_dir_all(root.join(OVERLAYS_DIR).join(overlay)).unwrap();
+        }
+
+        let in_use = InUse {
+            containers: vec![container("c1", "alpine:latest")],
+            layers: HashSet::from(["mounted".to_string()]),
+            overlays: HashSet::from(["build-running".to_string()]),
+        };
+        assert_eq!(
+            planned(root, &[], &in_use),
+            [
+                "layers:sha256:orphan",
+                "configs:sha256:stale",
+                "overlays:persistent-old"
+            ]
+        );
+
+        let report = apply_gc(plan_gc(root, &[], &in_use).unwrap(), false).unwrap();
+        assert_eq!(report.freed_bytes, 12);
+        assert!(!root.join(LAYERS_DIR).join("orphan").exists());
+        assert!(root.join(LAYERS_DIR).join("aaa").exists());
+        assert!(root.join(OVERLAYS_DIR).join("container-c1").exists());
+    }
+
+    #[test]
+    fn test_gc_images_cascades_to_unshared_layers() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write_layer(root, "base", 10);
+        write_layer(root, "app", 10);
+        write_layer(root, "step", 10);
+        write_image(root, "alpine:latest", "c1", &["base"]);
+        write_image(root, "app:dev", "c2", &["base", "app"]);
+        write_image(root, "smolvm-build-cache:abc", "c3", &["base", "step"]);
+
+        let in_use = InUse {
+            containers: vec![container("x", "alpine:latest")],
+            ..InUse::default()
+        };
+        assert_eq!(
+            planned(root, &[GcTarget::Images], &in_use),
+            ["images:app/dev", "layers:sha256:app", "configs:sha256:c2"]
+        );
+        assert_eq!(
+            planned(root, &[GcTarget::BuildCache], &in_use),
+            [
+                "build-cache:smolvm-build-cache/abc",
+                "layers:sha256:step",
+                "configs:sha256:c3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_image() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write_layer(root, "base", 10);
+        write_layer(root, "app", 10);
+        write_image(root, "alpine:latest", "c1", &["base"]);
+        write_image(root, "app:dev", "c2", &["base", "app"]);
+        let in_use = InUse {
+            containers: vec![container("x", "alpine:latest")],
+            ..InUse::default()
+        };
+
+        assert!(matches!(
+            remove_image_at(root, "alpine:latest", &in_use),
+            Err(StorageError::InUse { .. })
+        ));
+        assert!(matches!(
+            remove_image_at(root, "missing:1", &in_use),
+            Err(StorageError::ImageNotFound { .. })
+        ));
+
+        let report = remove_image_at(root, "app:dev", &in_use).unwrap();
+        assert_eq!(report.items.len(), 3);
+        assert!(!root.join(LAYERS_DIR).join("app").exists());
+        assert!(root.join(LAYERS_DIR).join("base").exists());
+        assert!(root.join(CONFIGS_DIR).join("c1.json").exists());
+    }
+
+    #[test]
+    fn test_disk_usage_shared_and_unique() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write_layer(root, "base", 100);
+        write_layer(root, "app", 30);
+        write_layer(root, "step", 7);
+        write_layer(root, "orphan", 5);
+        write_image(root, "alpine:latest", "c1", &["base"]);
+        write_image(root, "app:dev", "c2", &["base", "app"]);
+        write_image(root, "smolvm-build-cache:abc", "c3", &["base", "step"]);
+        let upper = root.join(OVERLAYS_DIR).join("container-x").join("upper");
+        std::fs::create_dir_all(&upper).unwrap();
+        std::fs::write(upper.join("file"), [0u8; 3]).unwrap();
+        let in_use = InUse {
+            containers: vec![container("x", "app:dev")],
+            ..InUse::default()
+        };
+
+        let usage = disk_usage_at(root, &in_use).unwrap();
+        assert_eq!(usage.images.len(), 2);
+        let app = &usage.images[1];
+        assert_eq!(app.reference, "app/dev");
+        assert_eq!((app.size, app.shared_size, app.unique_size), (130, 100, 30));
+        assert_eq!(app.containers, 1);
+        assert_eq!(usage.containers[0].size, 3);
+        assert_eq!((usage.build_cache.count, usage.build_cache.size), (1, 7));
+        assert_eq!((usage.layers.count, usage.layers.size), (4, 142));
+        assert_eq!(usage.layers.reclaimable_size, 5);
+        assert_eq!(usage.overlays.reclaimable_count, 0);
+    }
+
+    #[test]
+    fn test_block_device_size_sources() {
+        let sysfs = tempfile::tempdir().unwrap();
+        std::fs::create_dir(sysfs.path().join("vda")).unwrap();
+        std::fs::write(sysfs.path().join("vda/size"), "4194304\n").unwrap();
+        assert_eq!(sysfs_sectors(sysfs.path(), "/dev/vda"), Some(4_194_304));
+        assert_eq!(sysfs_sectors(sysfs.path(), "/dev/vdb"), None);
+
+        // BLKGETSIZE64 only answers for block devices
+        let file = sysfs.path().join("vda/size");
+        assert!(ioctl_device_bytes(&file).is_err());
+        assert!(ioctl_device_bytes(&sysfs.path().join("missing")).is_err());
+    }
 }
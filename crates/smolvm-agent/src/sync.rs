@@ -0,0 +1,996 @@
+//! Synced mounts (`-v SRC:DST:sync`).
+//!
+//! Every metadata lookup on a virtiofs share is a round trip to the host,
+//! which makes trees of many small files (`node_modules`) slow to build on
+//! a shared directory. A synced mount gives the guest its own copy on the
+//! storage disk instead:
+//!
+//! - When the mount is set up, the host share is copied in before any
+//!   workload sees it.
+//! - Guest changes are picked up with inotify and copied out to the host.
+//! - virtiofs doesn't report host-side changes to guest inotify watches, so
+//!   the host share is polled: every few seconds only the directories whose
+//!   mtime moved are re-read, which catches entries created, removed or
+//!   renamed into place (how most editors save). A full scan, which also
+//!   catches files rewritten in place, runs once a minute.
+//! - Guest changes still pending are copied out before the VM shuts down.
+//!
+//! Each pass compares both sides of a path with what they looked like at
+//! its last sync. A path changed on one side is copied to the other; a
+//! path changed on both is a conflict, settled by the mount's
+//! [`SyncConflictPolicy`]. Read-only synced mounts only ever copy in.
+
+use parking_lot::Mutex;
+use smolvm_protocol::{SyncConflictPolicy, SyncMountStatus};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::paths;
+
+/// How long guest changes must settle before they are copied out.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Longest a guest change waits while events keep arriving.
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Interval between passes over the host directories whose mtime changed.
+const HOST_RESCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval between full scans of the host share. Rewriting a file in place
+/// doesn't touch its directory's mtime, so only these see it.
+const HOST_FULL_RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of conflicts kept for status reporting.
+const RECENT_CONFLICTS: usize = 20;
+
+/// Suffix of the temporary files copies are written to before the rename.
+const TEMP_SUFFIX: &str = ".smolvm-sync";
+
+/// inotify events that mean a guest path changed.
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ATTRIB;
+
+lazy_static::lazy_static! {
+    /// Synced mounts set up so far.
+    static ref MOUNTS: Mutex<Vec<Arc<SyncedMount>>> = Mutex::new(Vec::new());
+}
+
+/// A path as far as syncing is concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Dir,
+    File { size: u64, mtime: (i64, i64) },
+    Symlink(PathBuf),
+}
+
+/// Both sides of a path as of its last sync.
+#[derive(Debug, Clone, Default)]
+struct Synced {
+    host: Option<Entry>,
+    guest: Option<Entry>,
+}
+
+/// Which way a path is copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    In,
+    Out,
+}
+
+/// One synced mount: a host share and its local copy.
+struct SyncedMount {
+    tag: String,
+    target: String,
+    share: PathBuf,
+    local: PathBuf,
+    policy: SyncConflictPolicy,
+    read_only: bool,
+    state: Mutex<SyncState>,
+}
+
+#[derive(Default)]
+struct SyncState {
+    synced: BTreeMap<PathBuf, Synced>,
+    /// mtime of each host directory when it was last read.
+    host_dirs: BTreeMap<PathBuf, Mtime>,
+    copied_in: u64,
+    copied_out: u64,
+    pending: u64,
+    conflicts: u64,
+    recent_conflicts: VecDeque<String>,
+    last_sync: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// Copy the host share mounted at `share` into a fresh local directory and
+/// keep the two in sync in the background.
+///
+/// Returns the local directory, which the caller mounts in place of the
+/// share. The initial copy has finished when this returns.
+pub fn start(
+    tag: &str,
+    target: &str,
+    share: &Path,
+    policy: SyncConflictPolicy,
+    read_only: bool,
+) -> io::Result<PathBuf> {
+    // The local copy is rebuilt from the host on every boot; anything the
+    // guest added before was copied out then.
+    let local = Path::new(paths::SYNC_DIR).join(tag);
+    remove_path(&local)?;
+    std::fs::create_dir_all(&local)?;
+
+    let mount = Arc::new(SyncedMount {
+        tag: tag.to_string(),
+        target: target.to_string(),
+        share: share.to_path_buf(),
+        local: local.clone(),
+        policy,
+        read_only,
+        state: Mutex::default(),
+    });
+
+    let started = Instant::now();
+    mount.sync_all()?;
+    info!(
+        tag = %tag,
+        entries = mount.state.lock().synced.len(),
+        duration_ms = started.elapsed().as_millis() as u64,
+        policy = policy.as_str(),
+        "synced mount copied in"
+    );
+
+    MOUNTS.lock().push(Arc::clone(&mount));
+    std::thread::Builder::new()
+        .name(format!("sync-{}", tag))
+        .spawn(move || mount.run())?;
+    Ok(local)
+}
+
+/// State of every synced mount.
+pub fn status() -> Vec<SyncMountStatus> {
+    MOUNTS.lock().iter().map(|m| m.status()).collect()
+}
+
+/// Copy out guest changes that haven't been synced yet, so nothing written
+/// in the guest is lost when the VM shuts down.
+pub fn flush() {
+    let mounts: Vec<_> = MOUNTS.lock().iter().map(Arc::clone).collect();
+    for mount in mounts.iter().filter(|m| !m.read_only) {
+        if let Err(e) = mount.sync_guest_changes() {
+            warn!(tag = %mount.tag, error = %e, "failed to flush synced mount");
+        }
+    }
+}
+
+impl SyncedMount {
+    /// Background loop: copy guest changes out as inotify reports them and
+    /// poll the host share for changes.
+    fn run(&self) {
+        let mut watcher = match Watcher::new() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!(tag = %self.tag, error = %e, "inotify unavailable, syncing by rescanning");
+                self.state.lock().last_error = Some(format!("inotify unavailable: {}", e));
+                None
+            }
+        };
+        let mut dirty = BTreeSet::new();
+        if let Some(watcher) = watcher.as_mut() {
+            watcher.watch_tree(&self.local, Path::new(""), &mut dirty, false);
+        }
+
+        let mut next_rescan = Instant::now() + HOST_RESCAN_INTERVAL;
+        let mut next_full_rescan = Instant::now() + HOST_FULL_RESCAN_INTERVAL;
+        let mut dirty_since: Option<Instant> = None;
+        loop {
+            let timeout = if dirty.is_empty() {
+                next_rescan.saturating_duration_since(Instant::now())
+            } else {
+                DEBOUNCE
+            };
+            let outcome = match watcher.as_mut() {
+                Some(watcher) => watcher.wait(&self.local, timeout, &mut dirty),
+                None => {
+                    std::thread::sleep(timeout);
+                    Ok(WaitOutcome::Timeout)
+                }
+            };
+            self.state.lock().pending = dirty.len() as u64;
+
+            let flush = match outcome {
+                Ok(WaitOutcome::Events) => {
+                    let since = *dirty_since.get_or_insert_with(Instant::now);
+                    since.elapsed() >= MAX_DELAY
+                }
+                Ok(WaitOutcome::Timeout) => true,
+                Ok(WaitOutcome::Overflow) => {
+                    // Events were lost: re-add watches and compare everything.
+                    debug!(tag = %self.tag, "inotify queue overflow");
+                    if let Some(watcher) = watcher.as_mut() {
+                        watcher.watch_tree(&self.local, Path::new(""), &mut dirty, false);
+                    }
+                    dirty.clear();
+                    next_rescan = Instant::now();
+                    next_full_rescan = Instant::now();
+                    true
+                }
+                Err(e) => {
+                    warn!(tag = %self.tag, error = %e, "inotify read failed");
+                    watcher = None;
+                    true
+                }
+            };
+            if !flush {
+                continue;
+            }
+
+            if !dirty.is_empty() {
+                let paths = std::mem::take(&mut dirty);
+                dirty_since = None;
+                self.state.lock().pending = 0;
+                self.sync_paths(paths.into_iter().map(|rel| (rel, None)));
+            }
+            if Instant::now() >= next_rescan {
+                let result = if Instant::now() >= next_full_rescan {
+                    next_full_rescan = Instant::now() + HOST_FULL_RESCAN_INTERVAL;
+                    self.sync_all()
+                } else {
+                    self.sync_host_changes()
+                };
+                if let Err(e) = result {
+                    warn!(tag = %self.tag, error = %e, "sync rescan failed");
+                    self.state.lock().last_error = Some(e.to_string());
+                }
+                next_rescan = Instant::now() + HOST_RESCAN_INTERVAL;
+            }
+        }
+    }
+
+    /// Compare the whole share with the local copy.
+    fn sync_all(&self) -> io::Result<()> {
+        let mut host_dirs = BTreeMap::new();
+        let host = scan(&self.share, Some(&mut host_dirs))?;
+        let guest = scan(&self.local, None)?;
+        let mut paths: BTreeSet<PathBuf> = host.keys().chain(guest.keys()).cloned().collect();
+        paths.extend(self.state.lock().synced.keys().cloned());
+
+        self.sync_paths(paths.into_iter().map(|rel| {
+            let entries = (host.get(&rel).cloned(), guest.get(&rel).cloned());
+            (rel, Some(entries))
+        }));
+        self.state.lock().host_dirs = host_dirs;
+        Ok(())
+    }
+
+    /// Re-read only the host directories whose mtime moved since they were
+    /// last read, which costs one stat per directory when nothing changed.
+    fn sync_host_changes(&self) -> io::Result<()> {
+        let known = self.state.lock().host_dirs.clone();
+        let mut queue = Vec::new();
+        for (rel, mtime) in &known {
+            let current = dir_mtime(&self.share.join(rel))?;
+            if current != Some(*mtime) {
+                queue.push((rel.clone(), current));
+            }
+        }
+        if queue.is_empty() {
+            return Ok(());
+        }
+
+        let mut paths = BTreeSet::new();
+        let mut read = BTreeMap::new();
+        let mut gone = Vec::new();
+        while let Some((dir, mtime)) = queue.pop() {
+            if !dir.as_os_str().is_empty() {
+                paths.insert(dir.clone());
+            }
+            let Some(mtime) = mtime else {
+                gone.push(dir);
+                continue;
+            };
+            let children = match std::fs::read_dir(self.share.join(&dir)) {
+                Ok(children) => children,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    gone.push(dir);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            for child in children {
+                let child = child?;
+                if is_temp_name(&child.file_name()) {
+                    continue;
+                }
+                let rel = dir.join(child.file_name());
+                // A new directory is read whole: it may have been filled
+                // before this pass saw it.
+                if child.file_type()?.is_dir() && !known.contains_key(&rel) {
+                    if let Some(mtime) = dir_mtime(&child.path())? {
+                        queue.push((rel.clone(), Some(mtime)));
+                    }
+                }
+                paths.insert(rel);
+            }
+            read.insert(dir, mtime);
+        }
+
+        // Entries last synced in a directory that was re-read may be gone.
+        {
+            let state = self.state.lock();
+            paths.extend(
+                state
+                    .synced
+                    .iter()
+                    .filter(|(rel, synced)| {
+                        synced.host.is_some() && rel.parent().is_some_and(|p| read.contains_key(p))
+                    })
+                    .map(|(rel, _)| rel.clone()),
+            );
+        }
+
+        self.sync_paths(paths.into_iter().map(|rel| (rel, None)));
+        let mut state = self.state.lock();
+        for dir in &gone {
+            state.host_dirs.retain(|rel, _| !rel.starts_with(dir));
+        }
+        state.host_dirs.extend(read);
+        Ok(())
+    }
+
+    /// Compare the local copy with what it looked like at its last sync and
+    /// sync the paths that changed.
+    fn sync_guest_changes(&self) -> io::Result<()> {
+        let guest = scan(&self.local, None)?;
+        let paths: BTreeSet<PathBuf> = {
+            let state = self.state.lock();
+            let removed = state
+                .synced
+                .iter()
+                .filter(|(rel, synced)| synced.guest.is_some() && !guest.contains_key(*rel))
+                .map(|(rel, _)| rel.clone());
+            let changed = guest
+                .iter()
+                .filter(|(rel, entry)| {
+                    state.synced.get(*rel).and_then(|s| s.guest.as_ref()) != Some(*entry)
+                })
+                .map(|(rel, _)| rel.clone());
+            removed.chain(changed).collect()
+        };
+        self.sync_paths(paths.into_iter().map(|rel| (rel, None)));
+        Ok(())
+    }
+
+    /// Sync each path, with both sides' entries when a scan already has
+    /// them. Paths come in sorted order, so parents go before children.
+    fn sync_paths(&self, paths: impl Iterator<Item = (PathBuf, Option<EntryPair>)>) {
+        let mut error = None;
+        let mut removed: Option<PathBuf> = None;
+        for (rel, entries) in paths {
+            // Scanned entries under a tree removed in this pass are stale.
+            if removed.as_ref().is_some_and(|dir| rel.starts_with(dir)) {
+                continue;
+            }
+            let result = match entries {
+                Some((host, guest)) => self.sync_path(&rel, host, guest),
+                None => entry(&self.share.join(&rel))
+                    .and_then(|host| Ok((host, entry(&self.local.join(&rel))?)))
+                    .and_then(|(host, guest)| self.sync_path(&rel, host, guest)),
+            };
+            match result {
+                Ok(true) => removed = Some(rel),
+                Ok(false) => {}
+                Err(e) => {
+                    warn!(tag = %self.tag, path = %rel.display(), error = %e, "failed to sync path");
+                    error = Some(format!("{}: {}", rel.display(), e));
+                }
+            }
+        }
+
+        let mut state = self.state.lock();
+        state.last_sync = Some(unix_now());
+        state.last_error = error;
+    }
+
+    /// Bring one path in sync. Returns true if a directory tree at `rel`
+    /// went away on both sides.
+    fn sync_path(&self, rel: &Path, host: Option<Entry>, guest: Option<Entry>) -> io::Result<bool> {
+        let host_path = self.share.join(rel);
+        let guest_path = self.local.join(rel);
+
+        let mut state = self.state.lock();
+        let last = state.synced.get(rel).cloned().unwrap_or_default();
+        let direction = match (host != last.host, guest != last.guest) {
+            (false, false) => return Ok(false),
+            (true, false) => Some(Direction::In),
+            (false, true) if self.read_only => Some(Direction::In),
+            (false, true) => Some(Direction::Out),
+            (true, true) if host == guest => None,
+            (true, true) => {
+                let direction = if self.read_only {
+                    Direction::In
+                } else {
+                    resolve_conflict(self.policy, &host, &guest)
+                };
+                let kept = match direction {
+                    Direction::In => "host",
+                    Direction::Out => "guest",
+                };
+                warn!(tag = %self.tag, path = %rel.display(), kept, "sync conflict");
+                state.conflicts += 1;
+                if state.recent_conflicts.len() == RECENT_CONFLICTS {
+                    state.recent_conflicts.pop_front();
+                }
+                state
+                    .recent_conflicts
+                    .push_back(format!("{} (kept {})", rel.display(), kept));
+                Some(direction)
+            }
+        };
+
+        let (host, guest) = match direction {
+            Some(Direction::In) => {
+                copy_entry(&host_path, host.as_ref(), &guest_path)?;
+                state.copied_in += 1;
+                (host, entry(&guest_path)?)
+            }
+            Some(Direction::Out) => {
+                copy_entry(&guest_path, guest.as_ref(), &host_path)?;
+                state.copied_out += 1;
+                (entry(&host_path)?, guest)
+            }
+            None => (host, guest),
+        };
+
+        let was_dir = last.host == Some(Entry::Dir) || last.guest == Some(Entry::Dir);
+        let dir_removed = was_dir && host != Some(Entry::Dir) && guest != Some(Entry::Dir);
+        if dir_removed {
+            // Forget whatever was under a directory that is gone.
+            state.synced.retain(|path, _| !path.starts_with(rel));
+        }
+        if host.is_none() && guest.is_none() {
+            state.synced.remove(rel);
+        } else {
+            state
+                .synced
+                .insert(rel.to_path_buf(), Synced { host, guest });
+        }
+        Ok(dir_removed)
+    }
+
+    fn status(&self) -> SyncMountStatus {
+        let state = self.state.lock();
+        SyncMountStatus {
+            tag: self.tag.clone(),
+            target: self.target.clone(),
+            policy: self.policy,
+            entries: state.synced.len() as u64,
+            copied_in: state.copied_in,
+            copied_out: state.copied_out,
+            pending: state.pending,
+            conflicts: state.conflicts,
+            recent_conflicts: state.recent_conflicts.iter().cloned().collect(),
+            last_sync: state.last_sync,
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+/// Host and guest entries for a path.
+type EntryPair = (Option<Entry>, Option<Entry>);
+
+/// Modification time as (seconds, nanoseconds).
+type Mtime = (i64, i64);
+
+/// Pick the side that wins a conflict.
+fn resolve_conflict(
+    policy: SyncConflictPolicy,
+    host: &Option<Entry>,
+    guest: &Option<Entry>,
+) -> Direction {
+    match policy {
+        SyncConflictPolicy::Host => Direction::In,
+        SyncConflictPolicy::Guest => Direction::Out,
+        SyncConflictPolicy::Newer => match (host, guest) {
+            // A change beats a deletion.
+            (None, Some(_)) => Direction::Out,
+            (Some(_), None) => Direction::In,
+            (Some(Entry::File { mtime: h, .. }), Some(Entry::File { mtime: g, .. })) if g > h => {
+                Direction::Out
+            }
+            _ => Direction::In,
+        },
+    }
+}
+
+/// Describe `path` without following symlinks (`None` if it doesn't exist).
+fn entry(path: &Path) -> io::Result<Option<Entry>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) => entry_from_metadata(path, &meta),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Describe `path`, whose metadata is `meta`.
+fn entry_from_metadata(path: &Path, meta: &std::fs::Metadata) -> io::Result<Option<Entry>> {
+    let file_type = meta.file_type();
+    Ok(if file_type.is_dir() {
+        Some(Entry::Dir)
+    } else if file_type.is_symlink() {
+        Some(Entry::Symlink(std::fs::read_link(path)?))
+    } else if file_type.is_file() {
+        Some(Entry::File {
+            size: meta.len(),
+            mtime: mtime(meta),
+        })
+    } else {
+        // Sockets, FIFOs and devices aren't synced.
+        None
+    })
+}
+
+fn mtime(meta: &std::fs::Metadata) -> Mtime {
+    (meta.mtime(), meta.mtime_nsec())
+}
+
+/// mtime of the directory at `path` (`None` if it isn't one).
+fn dir_mtime(path: &Path) -> io::Result<Option<Mtime>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => Ok(Some(mtime(&meta))),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Entries under `root`, keyed by relative path. With `dir_mtimes`, the
+/// mtime of every directory read (`root` included) is recorded there.
+fn scan(
+    root: &Path,
+    mut dir_mtimes: Option<&mut BTreeMap<PathBuf, Mtime>>,
+) -> io::Result<BTreeMap<PathBuf, Entry>> {
+    if let Some(mtimes) = dir_mtimes.as_deref_mut() {
+        mtimes.insert(PathBuf::new(), mtime(&std::fs::metadata(root)?));
+    }
+    let mut entries = BTreeMap::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for child in std::fs::read_dir(root.join(&dir))? {
+            let child = child?;
+            if is_temp_name(&child.file_name()) {
+                continue;
+            }
+            let rel = dir.join(child.file_name());
+            let path = child.path();
+            let meta = match std::fs::symlink_metadata(&path) {
+                Ok(meta) => meta,
+                // Removed while scanning; the next pass sees it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            match entry_from_metadata(&path, &meta) {
+                Ok(Some(Entry::Dir)) => {
+                    if let Some(mtimes) = dir_mtimes.as_deref_mut() {
+                        mtimes.insert(rel.clone(), mtime(&meta));
+                    }
+                    dirs.push(rel.clone());
+                    entries.insert(rel, Entry::Dir);
+                }
+                Ok(Some(e)) => {
+                    entries.insert(rel, e);
+                }
+                Ok(None) => {}
+                // Removed while scanning; the next pass sees it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn is_temp_name(name: &std::ffi::OsStr) -> bool {
+    name.as_bytes().ends_with(TEMP_SUFFIX.as_bytes())
+}
+
+/// Make `dst` match `src` (whose entry is `entry`).
+fn copy_entry(src: &Path, entry: Option<&Entry>, dst: &Path) -> io::Result<()> {
+    let Some(entry) = entry else {
+        return remove_path(dst);
+    };
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match entry {
+        Entry::Dir => {
+            if !std::fs::symlink_metadata(dst).is_ok_and(|m| m.is_dir()) {
+                remove_path(dst)?;
+                std::fs::create_dir(dst)?;
+            }
+            Ok(())
+        }
+        Entry::File { mtime, .. } => {
+            // Copy next to the destination and rename over it, so readers
+            // never see a partial file.
+            let mut temp_name = dst.file_name().unwrap_or_default().to_os_string();
+            temp_name.push(TEMP_SUFFIX);
+            let temp = dst.with_file_name(temp_name);
+            std::fs::copy(src, &temp)?;
+            let modified = UNIX_EPOCH + Duration::new(mtime.0.max(0) as u64, mtime.1 as u32);
+            std::fs::File::options()
+                .write(true)
+                .open(&temp)?
+                .set_modified(modified)?;
+            if std::fs::symlink_metadata(dst).is_ok_and(|m| m.is_dir()) {
+                std::fs::remove_dir_all(dst)?;
+            }
+            std::fs::rename(&temp, dst)
+        }
+        Entry::Symlink(target) => {
+            remove_path(dst)?;
+            std::os::unix::fs::symlink(target, dst)
+        }
+    }
+}
+
+/// Remove a file, symlink or directory tree; missing paths are fine.
+fn remove_path(path: &Path) -> io::Result<()> {
+    let result = match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Result of waiting for inotify events.
+enum WaitOutcome {
+    Events,
+    Timeout,
+    Overflow,
+}
+
+/// Recursive inotify watch on a directory tree.
+struct Watcher {
+    fd: OwnedFd,
+    /// Watch descriptor -> directory relative to the root.
+    dirs: HashMap<i32, PathBuf>,
+}
+
+impl Watcher {
+    fn new() -> io::Result<Self> {
+        // SAFETY: inotify_init1 has no memory-safety preconditions
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            // SAFETY: fd is a freshly created descriptor we own
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// Watch `root/rel` and every directory under it. With `mark`, every
+    /// entry found is added to `dirty` (a directory that appeared may have
+    /// been filled before its watch existed).
+    fn watch_tree(&mut self, root: &Path, rel: &Path, dirty: &mut BTreeSet<PathBuf>, mark: bool) {
+        let mut dirs = vec![rel.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let path = root.join(&dir);
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                continue;
+            };
+            // SAFETY: c_path is a valid NUL-terminated string
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    self.fd.as_raw_fd(),
+                    c_path.as_ptr(),
+                    WATCH_MASK | libc::IN_ONLYDIR,
+                )
+            };
+            if wd < 0 {
+                debug!(path = %path.display(), error = %io::Error::last_os_error(), "inotify_add_watch failed");
+                continue;
+            }
+            self.dirs.insert(wd, dir.clone());
+
+            let Ok(children) = std::fs::read_dir(&path) else {
+                continue;
+            };
+            for child in children.flatten() {
+                let child_rel = dir.join(child.file_name());
+                if mark && !is_temp_name(&child.file_name()) {
+                    dirty.insert(child_rel.clone());
+                }
+                if child.file_type().is_ok_and(|t| t.is_dir()) {
+                    dirs.push(child_rel);
+                }
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for events and add the changed paths to `dirty`.
+    fn wait(
+        &mut self,
+        root: &Path,
+        timeout: Duration,
+        dirty: &mut BTreeSet<PathBuf>,
+    ) -> io::Result<WaitOutcome> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: pollfd is a valid, initialized pollfd array of length 1
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::Interrupted => Ok(WaitOutcome::Events),
+                _ => Err(err),
+            };
+        }
+        if ready == 0 {
+            return Ok(WaitOutcome::Timeout);
+        }
+
+        let mut buf = [0u8; 64 * 1024];
+        // SAFETY: buf is valid for writes of buf.len() bytes
+        let len = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(WaitOutcome::Events),
+                _ => Err(err),
+            };
+        }
+
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut offset = 0;
+        let mut overflow = false;
+        let mut new_dirs = Vec::new();
+        while offset + header <= len as usize {
+            // SAFETY: the kernel writes whole events; the header is in bounds
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+            let name_bytes = &buf[offset + header..offset + header + event.len as usize];
+            offset += header + event.len as usize;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                overflow = true;
+                continue;
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let Some(dir) = self.dirs.get(&event.wd) else {
+                continue;
+            };
+            let name_len = name_bytes
+                .iter()
+                .position(|b| *b == 0)
+                .unwrap_or(name_bytes.len());
+            let name = std::ffi::OsStr::from_bytes(&name_bytes[..name_len]);
+            if name.is_empty() || is_temp_name(name) {
+                continue;
+            }
+            let rel = dir.join(name);
+            if event.mask & libc::IN_ISDIR != 0
+                && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+            {
+                new_dirs.push(rel.clone());
+            }
+            dirty.insert(rel);
+        }
+
+        for dir in new_dirs {
+            self.watch_tree(root, &dir, dirty, true);
+        }
+        Ok(if overflow {
+            WaitOutcome::Overflow
+        } else {
+            WaitOutcome::Events
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(policy: SyncConflictPolicy) -> (tempfile::TempDir, SyncedMount) {
+        let dir = tempfile::tempdir().unwrap();
+        let share = dir.path().join("share");
+        let local = dir.path().join("local");
+        std::fs::create_dir_all(&share).unwrap();
+        std::fs::create_dir_all(&local).unwrap();
+        let mount = SyncedMount {
+            tag: "smolvm0".to_string(),
+            target: "/app".to_string(),
+            share,
+            local,
+            policy,
+            read_only: false,
+            state: Mutex::default(),
+        };
+        (dir, mount)
+    }
+
+    fn write(path: &Path, contents: &str, mtime_secs: u64) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(mtime_secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sync_copies_both_ways() {
+        let (_dir, mount) = mount(SyncConflictPolicy::Newer);
+        write(&mount.share.join("src/main.js"), "host", 1_000);
+        std::os::unix::fs::symlink("main.js", mount.share.join("src/index.js")).unwrap();
+
+        mount.sync_all().unwrap();
+        let local = std::fs::read_to_string(mount.local.join("src/main.js")).unwrap();
+        assert_eq!(local, "host");
+        assert_eq!(
+            std::fs::read_link(mount.local.join("src/index.js")).unwrap(),
+            Path::new("main.js")
+        );
+
+        write(&mount.local.join("node_modules/a/index.js"), "guest", 2_000);
+        std::fs::remove_file(mount.local.join("src/main.js")).unwrap();
+        mount.sync_all().unwrap();
+        let host = std::fs::read_to_string(mount.share.join("node_modules/a/index.js")).unwrap();
+        assert_eq!(host, "guest");
+        assert!(!mount.share.join("src/main.js").exists());
+
+        std::fs::remove_dir_all(mount.share.join("node_modules")).unwrap();
+        mount.sync_all().unwrap();
+        assert!(!mount.local.join("node_modules").exists());
+
+        let status = mount.status();
+        assert_eq!(status.conflicts, 0);
+        assert!(status.copied_in >= 3 && status.copied_out >= 3);
+        assert!(status.last_error.is_none());
+    }
+
+    #[test]
+    fn test_sync_conflict_policies() {
+        for (policy, expected) in [
+            (SyncConflictPolicy::Newer, "guest"),
+            (SyncConflictPolicy::Host, "host"),
+            (SyncConflictPolicy::Guest, "guest"),
+        ] {
+            let (_dir, mount) = mount(policy);
+            write(&mount.share.join("app.toml"), "v1", 1_000);
+            mount.sync_all().unwrap();
+
+            write(&mount.share.join("app.toml"), "host", 2_000);
+            write(&mount.local.join("app.toml"), "guest", 3_000);
+            mount.sync_all().unwrap();
+
+            for side in [&mount.share, &mount.local] {
+                let contents = std::fs::read_to_string(side.join("app.toml")).unwrap();
+                assert_eq!(contents, expected, "policy {:?}", policy);
+            }
+            let status = mount.status();
+            assert_eq!(status.conflicts, 1);
+            assert_eq!(
+                status.recent_conflicts,
+                vec![format!("app.toml (kept {})", expected)]
+            );
+        }
+    }
+
+    fn set_dir_mtime(path: &Path, secs: u64) {
+        std::fs::File::open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_host_changes_only_rereads_changed_dirs() {
+        let (_dir, mount) = mount(SyncConflictPolicy::Newer);
+        write(&mount.share.join("src/main.js"), "v1", 1_000);
+        write(&mount.share.join("src/old.js"), "old", 1_000);
+        for dir in [mount.share.join("src"), mount.share.clone()] {
+            set_dir_mtime(&dir, 1_000);
+        }
+        mount.sync_all().unwrap();
+
+        // Rewritten in place: the directory's mtime doesn't move
+        write(&mount.share.join("src/main.js"), "v2", 2_000);
+        set_dir_mtime(&mount.share.join("src"), 1_000);
+        mount.sync_host_changes().unwrap();
+        let local = std::fs::read_to_string(mount.local.join("src/main.js")).unwrap();
+        assert_eq!(local, "v1");
+
+        // Created, removed and new directories are picked up
+        write(&mount.share.join("lib/a/index.js"), "new", 2_000);
+        std::fs::remove_file(mount.share.join("src/old.js")).unwrap();
+        mount.sync_host_changes().unwrap();
+        let local = std::fs::read_to_string(mount.local.join("lib/a/index.js")).unwrap();
+        assert_eq!(local, "new");
+        assert!(!mount.local.join("src/old.js").exists());
+        assert!(mount
+            .state
+            .lock()
+            .host_dirs
+            .contains_key(Path::new("lib/a")));
+
+        // A full scan catches the in-place rewrite
+        mount.sync_all().unwrap();
+        let local = std::fs::read_to_string(mount.local.join("src/main.js")).unwrap();
+        assert_eq!(local, "v2");
+    }
+
+    #[test]
+    fn test_guest_changes_flush_to_host() {
+        let (_dir, mount) = mount(SyncConflictPolicy::Newer);
+        write(&mount.share.join("keep.txt"), "v1", 1_000);
+        write(&mount.share.join("drop.txt"), "v1", 1_000);
+        mount.sync_all().unwrap();
+
+        write(&mount.local.join("keep.txt"), "edited", 2_000);
+        write(&mount.local.join("dist/out.js"), "built", 2_000);
+        std::fs::remove_file(mount.local.join("drop.txt")).unwrap();
+        mount.sync_guest_changes().unwrap();
+
+        let host = std::fs::read_to_string(mount.share.join("keep.txt")).unwrap();
+        assert_eq!(host, "edited");
+        let host = std::fs::read_to_string(mount.share.join("dist/out.js")).unwrap();
+        assert_eq!(host, "built");
+        assert!(!mount.share.join("drop.txt").exists());
+    }
+
+    #[test]
+    fn test_newer_policy_keeps_change_over_deletion() {
+        let (_dir, mount) = mount(SyncConflictPolicy::Newer);
+        write(&mount.share.join("notes.txt"), "v1", 1_000);
+        mount.sync_all().unwrap();
+
+        std::fs::remove_file(mount.share.join("notes.txt")).unwrap();
+        write(&mount.local.join("notes.txt"), "edited", 500);
+        mount.sync_all().unwrap();
+
+        let host = std::fs::read_to_string(mount.share.join("notes.txt")).unwrap();
+        assert_eq!(host, "edited");
+    }
+}
@@ -1,6 +1,7 @@
 //! vsock support for the helper daemon.
 //!
-//! This module provides vsock server functionality for Linux guests.
+//! This module provides vsock server functionality for Linux guests, plus
+//! outbound connections to the host (used by the egress proxy forwarder).
 
 use std::io::{Read, Write};
 #[cfg(target_os = "linux")]
@@ -95,6 +96,54 @@ mod linux {
             }
         }
     }
+
+    impl VsockStream {
+        /// Connect to `port` on the vsock endpoint `cid`.
+        pub fn connect(cid: u32, port: u32) -> std::io::Result<Self> {
+            unsafe {
+                let fd = libc::socket(AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                let fd = OwnedFd::from_raw_fd(fd);
+
+                let addr = sockaddr_vm {
+                    svm_family: AF_VSOCK as u16,
+                    svm_reserved1: 0,
+                    svm_port: port,
+                    svm_cid: cid,
+                    svm_zero: [0; 4],
+                };
+
+                if libc::connect(
+                    fd.as_raw_fd(),
+                    &addr as *const sockaddr_vm as *const libc::sockaddr,
+                    mem::size_of::<sockaddr_vm>() as libc::socklen_t,
+                ) < 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                Ok(Self { fd })
+            }
+        }
+
+        /// Duplicate the stream handle (for splitting reads and writes across threads).
+        pub fn try_clone(&self) -> std::io::Result<Self> {
+            Ok(Self {
+                fd: self.fd.try_clone()?,
+            })
+        }
+
+        /// Shut down the write half, signalling EOF to the peer.
+        pub fn shutdown_write(&self) -> std::io::Result<()> {
+            if unsafe { libc::shutdown(self.fd.as_raw_fd(), libc::SHUT_WR) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
 }
 
 #[cfg(not(target_os = "linux"))]
@@ -113,6 +162,23 @@ mod stub {
             unreachable!()
         }
     }
+
+    impl VsockStream {
+        pub fn connect(_cid: u32, _port: u32) -> std::io::Result<Self> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "vsock only supported on Linux",
+            ))
+        }
+
+        pub fn try_clone(&self) -> std::io::Result<Self> {
+            unreachable!()
+        }
+
+        pub fn shutdown_write(&self) -> std::io::Result<()> {
+            unreachable!()
+        }
+    }
 }
 
 #[cfg(target_os = "linux")]
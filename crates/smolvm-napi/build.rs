@@ -6,6 +6,7 @@
 
 #[cfg(target_os = "linux")]
 use std::path::Path;
+#[cfg(target_os = "macos")]
 use std::process::Command;
 
 #[cfg(target_os = "linux")]
@@ -0,0 +1,613 @@
+//! Reading images from local OCI image layouts and `docker save` archives.
+//!
+//! `oci-layout:PATH` and `docker-archive:PATH` image references are read
+//! on the host instead of being pulled through the agent, so packing them
+//! needs neither a registry nor the agent VM. Either may be a directory or
+//! a tarball of one. An archive holding several images needs the one to
+//! pack named after another colon: `oci-layout:./out:v1` matches the
+//! `org.opencontainers.image.ref.name` or `io.containerd.image.name`
+//! annotation, `docker-archive:./img.tar:app:1.0` matches `RepoTags`.
+//!
+//! The formats are read as the agent's `ImportImage` reads them, except
+//! that layers are decompressed, since packs store plain tarballs.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::assets::{file_digest, AssetCollector};
+use crate::{PackError, Result};
+
+/// Image reference prefix for OCI image layouts.
+pub const OCI_LAYOUT_TRANSPORT: &str = "oci-layout:";
+
+/// Image reference prefix for `docker save` archives.
+pub const DOCKER_ARCHIVE_TRANSPORT: &str = "docker-archive:";
+
+/// Annotation holding the full image name (containerd, Docker 25+).
+const ANNOTATION_IMAGE_NAME: &str = "io.containerd.image.name";
+
+/// Standard OCI annotation holding the image reference name.
+const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// A local image archive named by an `oci-layout:` or `docker-archive:`
+/// reference.
+#[derive(Debug)]
+pub struct ImageArchive {
+    /// Directory holding the layout or archive contents.
+    root: PathBuf,
+    /// Whether this is a `docker save` archive rather than an OCI layout.
+    docker: bool,
+    /// Image to pick when the archive holds several.
+    name: Option<String>,
+    /// Where a tarball was unpacked, removed on drop.
+    _unpacked: Option<tempfile::TempDir>,
+}
+
+/// An image read from an [`ImageArchive`].
+#[derive(Debug, Clone)]
+pub struct ArchiveImage {
+    /// Manifest digest for OCI layouts; config digest (the image ID) for
+    /// `docker save` archives, which carry no manifest.
+    pub digest: String,
+    /// Platform OS.
+    pub os: String,
+    /// Platform architecture.
+    pub architecture: String,
+    /// Platform variant, if any.
+    pub variant: Option<String>,
+    /// Image entrypoint.
+    pub entrypoint: Vec<String>,
+    /// Image default command.
+    pub cmd: Vec<String>,
+    /// Image environment variables.
+    pub env: Vec<String>,
+    /// Image working directory.
+    pub workdir: Option<String>,
+    /// Layers in order.
+    pub layers: Vec<ArchiveLayer>,
+}
+
+/// A layer blob of an [`ArchiveImage`].
+#[derive(Debug, Clone)]
+pub struct ArchiveLayer {
+    /// Blob digest (sha256:...), as referenced by the manifest.
+    pub digest: String,
+    /// Path to the (possibly compressed) layer tarball.
+    pub path: PathBuf,
+    /// Size of the blob.
+    pub size: u64,
+}
+
+impl ImageArchive {
+    /// Open the archive an image reference names, or return `None` for
+    /// references without a local transport prefix (registry images).
+    pub fn open(image: &str) -> Result<Option<Self>> {
+        let (rest, docker) = if let Some(rest) = image.strip_prefix(OCI_LAYOUT_TRANSPORT) {
+            (rest, false)
+        } else if let Some(rest) = image.strip_prefix(DOCKER_ARCHIVE_TRANSPORT) {
+            (rest, true)
+        } else {
+            return Ok(None);
+        };
+        let (path, name) = match rest.split_once(':') {
+            Some((path, name)) => (path, Some(name.to_string())),
+            None => (rest, None),
+        };
+        if path.is_empty() {
+            return Err(invalid(format!("no path in '{}'", image)));
+        }
+
+        let path = Path::new(path);
+        let unpacked = if path.is_file() {
+            let dir = tempfile::tempdir()?;
+            tar::Archive::new(File::open(path)?)
+                .unpack(dir.path())
+                .map_err(|e| PackError::Tar(format!("{}: {}", path.display(), e)))?;
+            Some(dir)
+        } else if path.is_dir() {
+            None
+        } else {
+            return Err(invalid(format!("{} not found", path.display())));
+        };
+        let root = unpacked
+            .as_ref()
+            .map_or_else(|| path.to_path_buf(), |dir| dir.path().to_path_buf());
+
+        let index = if docker {
+            "manifest.json"
+        } else {
+            "index.json"
+        };
+        if !root.join(index).exists() {
+            return Err(invalid(format!(
+                "{} has no {}; not {}",
+                path.display(),
+                index,
+                if docker {
+                    "a docker save archive"
+                } else {
+                    "an OCI image layout"
+                }
+            )));
+        }
+
+        Ok(Some(Self {
+            root,
+            docker,
+            name,
+            _unpacked: unpacked,
+        }))
+    }
+
+    /// The image for `platform` (`os/arch[/variant]`; without a variant,
+    /// any variant matches). Layer digests are checked, or computed for
+    /// `docker save` archives.
+    pub fn image(&self, platform: &str) -> Result<ArchiveImage> {
+        let mut candidates = if self.docker {
+            self.docker_images()?
+        } else {
+            self.oci_images(platform)?
+        };
+        if let Some(ref name) = self.name {
+            candidates.retain(|(names, _)| names.contains(name));
+            if candidates.is_empty() {
+                return Err(invalid(format!("no image named '{}'", name)));
+            }
+        }
+
+        let mut images: Vec<(Vec<String>, ArchiveImage)> = candidates
+            .into_iter()
+            .filter(|(_, image)| {
+                platform_matches(
+                    platform,
+                    &image.os,
+                    &image.architecture,
+                    image.variant.as_deref(),
+                )
+            })
+            .collect();
+        images.dedup_by(|a, b| a.1.digest == b.1.digest);
+        let (_, mut image) = match images.len() {
+            0 => {
+                return Err(invalid(format!(
+                    "no image for {}; use --oci-platform to pick another platform",
+                    platform
+                )))
+            }
+            1 => images.remove(0),
+            _ => {
+                let names: Vec<String> = images.iter().flat_map(|(n, _)| n.clone()).collect();
+                return Err(invalid(format!(
+                    "archive holds {} images for {}; name one after the path \
+                     (PATH:NAME), one of: {}",
+                    images.len(),
+                    platform,
+                    names.join(", ")
+                )));
+            }
+        };
+
+        for layer in &mut image.layers {
+            let (actual, _) = file_digest(&layer.path)?;
+            if layer.digest.is_empty() {
+                layer.digest = actual;
+            } else if actual != layer.digest {
+                return Err(invalid(format!("layer {} is corrupt", layer.digest)));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Every image in a `docker save` archive, with its `RepoTags`.
+    fn docker_images(&self) -> Result<Vec<(Vec<String>, ArchiveImage)>> {
+        let entries: Vec<serde_json::Value> = read_json(&self.root.join("manifest.json"))?;
+
+        let mut images = Vec::new();
+        for entry in entries {
+            let config_path = entry["Config"]
+                .as_str()
+                .ok_or_else(|| invalid("manifest.json entry has no Config"))?;
+            let config = fs::read(archive_path(&self.root, config_path)?)?;
+            let digest = format!("sha256:{:x}", Sha256::digest(&config));
+
+            let mut layers = Vec::new();
+            for layer in entry["Layers"].as_array().into_iter().flatten() {
+                // Digests are computed once an image is chosen
+                let path = archive_path(&self.root, layer.as_str().unwrap_or_default())?;
+                layers.push(ArchiveLayer {
+                    digest: String::new(),
+                    size: fs::metadata(&path)?.len(),
+                    path,
+                });
+            }
+
+            let names = entry["RepoTags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|t| t.as_str().map(String::from))
+                .collect();
+            images.push((names, image_from_config(digest, &config, layers)?));
+        }
+        Ok(images)
+    }
+
+    /// The images in an OCI layout, following image indexes down to the
+    /// manifests that may match `platform`.
+    fn oci_images(&self, platform: &str) -> Result<Vec<(Vec<String>, ArchiveImage)>> {
+        let index: serde_json::Value = read_json(&self.root.join("index.json"))?;
+
+        let mut images = Vec::new();
+        for descriptor in index["manifests"].as_array().into_iter().flatten() {
+            let annotations = &descriptor["annotations"];
+            let names: Vec<String> = [ANNOTATION_IMAGE_NAME, ANNOTATION_REF_NAME]
+                .iter()
+                .filter_map(|key| annotations[key].as_str().map(String::from))
+                .collect();
+            for image in self.resolve(descriptor, platform)? {
+                images.push((names.clone(), image));
+            }
+        }
+        Ok(images)
+    }
+
+    /// Images under a descriptor, skipping index entries whose platform
+    /// does not match.
+    fn resolve(&self, descriptor: &serde_json::Value, platform: &str) -> Result<Vec<ArchiveImage>> {
+        let descriptor_platform = &descriptor["platform"];
+        if let (Some(os), Some(arch)) = (
+            descriptor_platform["os"].as_str(),
+            descriptor_platform["architecture"].as_str(),
+        ) {
+            if !platform_matches(platform, os, arch, descriptor_platform["variant"].as_str()) {
+                return Ok(Vec::new());
+            }
+        }
+
+        let digest = descriptor["digest"]
+            .as_str()
+            .ok_or_else(|| invalid("descriptor has no digest"))?;
+        let manifest: serde_json::Value = serde_json::from_slice(&self.read_blob(digest)?)
+            .map_err(|e| invalid(format!("invalid manifest {}: {}", digest, e)))?;
+
+        if let Some(manifests) = manifest["manifests"].as_array() {
+            let mut images = Vec::new();
+            for entry in manifests {
+                images.extend(self.resolve(entry, platform)?);
+            }
+            return Ok(images);
+        }
+
+        let config_digest = manifest["config"]["digest"]
+            .as_str()
+            .ok_or_else(|| invalid(format!("manifest {} has no config digest", digest)))?;
+        let config = self.read_blob(config_digest)?;
+
+        let mut layers = Vec::new();
+        for layer in manifest["layers"].as_array().into_iter().flatten() {
+            let layer_digest = layer["digest"]
+                .as_str()
+                .ok_or_else(|| invalid("layer descriptor has no digest"))?;
+            let path = self.blob_path(layer_digest)?;
+            layers.push(ArchiveLayer {
+                digest: layer_digest.to_string(),
+                size: fs::metadata(&path)?.len(),
+                path,
+            });
+        }
+        let mut image = image_from_config(digest.to_string(), &config, layers)?;
+        if image.variant.is_none() {
+            image.variant = descriptor_platform["variant"].as_str().map(String::from);
+        }
+        Ok(vec![image])
+    }
+
+    /// Path of a blob in an OCI layout.
+    fn blob_path(&self, digest: &str) -> Result<PathBuf> {
+        let (alg, hex) = digest
+            .split_once(':')
+            .filter(|(alg, hex)| *alg == "sha256" && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| invalid(format!("unsupported digest: {}", digest)))?;
+        Ok(self.root.join("blobs").join(alg).join(hex))
+    }
+
+    /// Read a small blob (index, manifest or config) and verify its digest.
+    fn read_blob(&self, digest: &str) -> Result<Vec<u8>> {
+        let data = fs::read(self.blob_path(digest)?)?;
+        if format!("sha256:{:x}", Sha256::digest(&data)) != digest {
+            return Err(invalid(format!("blob {} is corrupt", digest)));
+        }
+        Ok(data)
+    }
+}
+
+impl ArchiveImage {
+    /// Platform as `os/arch[/variant]`.
+    pub fn platform(&self) -> String {
+        match self.variant {
+            Some(ref variant) => format!("{}/{}/{}", self.os, self.architecture, variant),
+            None => format!("{}/{}", self.os, self.architecture),
+        }
+    }
+
+    /// Total size of the layer blobs.
+    pub fn size(&self) -> u64 {
+        self.layers.iter().map(|l| l.size).sum()
+    }
+
+    /// Decompress each layer and add it to `collector`.
+    pub fn add_layers(&self, collector: &mut AssetCollector) -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        for layer in &self.layers {
+            let mut magic = [0u8; 4];
+            let n = File::open(&layer.path)?.read(&mut magic)?;
+            let reader: Box<dyn Read> = match &magic[..n] {
+                [0x1f, 0x8b, ..] => {
+                    Box::new(flate2::read::MultiGzDecoder::new(File::open(&layer.path)?))
+                }
+                [0x28, 0xb5, 0x2f, 0xfd] => Box::new(
+                    zstd::stream::Decoder::new(File::open(&layer.path)?)
+                        .map_err(|e| PackError::Compression(e.to_string()))?,
+                ),
+                _ => {
+                    collector.add_layer_from_file(&layer.digest, &layer.path)?;
+                    continue;
+                }
+            };
+
+            let tar_path = temp_dir.path().join("layer.tar");
+            io::copy(&mut { reader }, &mut File::create(&tar_path)?)
+                .map_err(|e| PackError::Compression(format!("layer {}: {}", layer.digest, e)))?;
+            collector.add_layer_from_file(&layer.digest, &tar_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Build an image from its config JSON.
+fn image_from_config(
+    digest: String,
+    config: &[u8],
+    layers: Vec<ArchiveLayer>,
+) -> Result<ArchiveImage> {
+    let config: serde_json::Value = serde_json::from_slice(config)
+        .map_err(|e| invalid(format!("invalid image config: {}", e)))?;
+    let strings = |value: &serde_json::Value| -> Vec<String> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect()
+    };
+    let run = &config["config"];
+    Ok(ArchiveImage {
+        digest,
+        os: config["os"].as_str().unwrap_or("linux").to_string(),
+        architecture: config["architecture"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        variant: config["variant"].as_str().map(String::from),
+        entrypoint: strings(&run["Entrypoint"]),
+        cmd: strings(&run["Cmd"]),
+        env: strings(&run["Env"]),
+        workdir: run["WorkingDir"]
+            .as_str()
+            .filter(|w| !w.is_empty())
+            .map(String::from),
+        layers,
+    })
+}
+
+/// Whether an image's platform matches a requested `os/arch[/variant]`.
+fn platform_matches(requested: &str, os: &str, arch: &str, variant: Option<&str>) -> bool {
+    let mut parts = requested.splitn(3, '/');
+    parts.next() == Some(os)
+        && parts.next() == Some(arch)
+        && parts.next().is_none_or(|v| Some(v) == variant)
+}
+
+/// Resolve a path named inside the archive, refusing to leave it.
+fn archive_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if name.is_empty()
+        || relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid(format!("invalid path in archive: {}", name)));
+    }
+    Ok(dir.join(relative))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data).map_err(|e| invalid(format!("invalid {}: {}", path.display(), e)))
+}
+
+fn invalid(message: impl Into<String>) -> PackError {
+    PackError::InvalidImage(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn digest(data: &[u8]) -> String {
+        format!("sha256:{:x}", Sha256::digest(data))
+    }
+
+    fn write_blob(dir: &Path, data: &[u8]) -> String {
+        let d = digest(data);
+        let path = dir.join("blobs/sha256").join(&d[7..]);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+        d
+    }
+
+    fn layer_tar(name: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(name.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, name.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Write an image manifest for `arch` with one layer; returns its
+    /// digest and the layer's.
+    fn write_image(dir: &Path, arch: &str, layer: &[u8]) -> (String, String) {
+        let layer = write_blob(dir, layer);
+        let config = write_blob(
+            dir,
+            format!(
+                r#"{{"os":"linux","architecture":"{}","config":{{"Entrypoint":["/app"],"Env":["A=1"],"WorkingDir":"/srv"}}}}"#,
+                arch
+            )
+            .as_bytes(),
+        );
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"config":{{"digest":"{}"}},"layers":[{{"digest":"{}"}}]}}"#,
+            config, layer
+        );
+        (write_blob(dir, manifest.as_bytes()), layer)
+    }
+
+    #[test]
+    fn test_open_ignores_registry_references() {
+        assert!(ImageArchive::open("alpine:latest").unwrap().is_none());
+        assert!(ImageArchive::open("oci-layout:/nonexistent").is_err());
+    }
+
+    #[test]
+    fn test_oci_layout_multi_platform() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("out");
+        let amd64_tar = layer_tar("amd64");
+        let (amd64, amd64_layer) = write_image(&dir, "amd64", &gzip(&amd64_tar));
+        let (arm64, arm64_layer) = write_image(&dir, "arm64", &layer_tar("arm64"));
+        let index = write_blob(
+            &dir,
+            format!(
+                r#"{{"manifests":[{{"digest":"{}","platform":{{"os":"linux","architecture":"amd64"}}}},{{"digest":"{}","platform":{{"os":"linux","architecture":"arm64","variant":"v8"}}}},{{"digest":"{}","platform":{{"os":"linux","architecture":"riscv64"}}}}]}}"#,
+                amd64,
+                arm64,
+                digest(b"missing")
+            )
+            .as_bytes(),
+        );
+        fs::write(
+            dir.join("index.json"),
+            format!(
+                r#"{{"manifests":[{{"digest":"{}","annotations":{{"{}":"v1"}}}}]}}"#,
+                index, ANNOTATION_REF_NAME
+            ),
+        )
+        .unwrap();
+
+        let archive = ImageArchive::open(&format!("oci-layout:{}:v1", dir.display()))
+            .unwrap()
+            .unwrap();
+        let image = archive.image("linux/arm64").unwrap();
+        assert_eq!(image.digest, arm64);
+        assert_eq!(image.platform(), "linux/arm64/v8");
+        assert_eq!(image.entrypoint, vec!["/app"]);
+        assert_eq!(image.env, vec!["A=1"]);
+        assert_eq!(image.workdir.as_deref(), Some("/srv"));
+        assert_eq!(image.layers[0].digest, arm64_layer);
+        assert!(archive.image("linux/arm64/v7").is_err());
+
+        // Layers are stored decompressed, under the blob's digest
+        let image = archive.image("linux/amd64").unwrap();
+        let mut collector = AssetCollector::new(tmp.path().join("staging")).unwrap();
+        image.add_layers(&mut collector).unwrap();
+        let entry = &collector.inventory().layers[0];
+        assert_eq!(entry.digest, amd64_layer);
+        assert_eq!(
+            fs::read(tmp.path().join("staging").join(&entry.path)).unwrap(),
+            amd64_tar
+        );
+
+        let other = ImageArchive::open(&format!("oci-layout:{}:v2", dir.display()))
+            .unwrap()
+            .unwrap();
+        assert!(other.image("linux/amd64").is_err());
+
+        // A corrupt layer is caught before packing
+        fs::write(
+            dir.join("blobs/sha256").join(&arm64_layer[7..]),
+            b"tampered",
+        )
+        .unwrap();
+        let err = archive.image("linux/arm64").unwrap_err();
+        assert!(err.to_string().contains("corrupt"));
+    }
+
+    #[test]
+    fn test_docker_archive_tarball() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("save");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        let layer = layer_tar("app");
+        fs::write(dir.join("a/layer.tar"), &layer).unwrap();
+        fs::write(
+            dir.join("cfg-amd64.json"),
+            br#"{"os":"linux","architecture":"amd64","config":{"Cmd":["sh"]}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("cfg-other.json"),
+            br#"{"os":"linux","architecture":"amd64"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("manifest.json"),
+            r#"[{"Config":"cfg-amd64.json","RepoTags":["app:1.0"],"Layers":["a/layer.tar"]},
+                {"Config":"cfg-other.json","RepoTags":["other:2"],"Layers":[]}]"#,
+        )
+        .unwrap();
+        let tarball = tmp.path().join("img.tar");
+        let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+        builder.append_dir_all(".", &dir).unwrap();
+        builder.finish().unwrap();
+
+        let reference = format!("docker-archive:{}", tarball.display());
+        let archive = ImageArchive::open(&reference).unwrap().unwrap();
+        let err = archive.image("linux/amd64").unwrap_err();
+        assert!(err.to_string().contains("app:1.0, other:2"));
+
+        let archive = ImageArchive::open(&format!("{}:app:1.0", reference))
+            .unwrap()
+            .unwrap();
+        let image = archive.image("linux/amd64").unwrap();
+        assert_eq!(image.cmd, vec!["sh"]);
+        assert_eq!(image.layers[0].digest, digest(&layer));
+        assert!(archive.image("linux/arm64").is_err());
+
+        // Paths must stay inside the archive
+        fs::write(
+            dir.join("manifest.json"),
+            r#"[{"Config":"../etc/passwd","RepoTags":[],"Layers":[]}]"#,
+        )
+        .unwrap();
+        let archive = ImageArchive::open(&format!("docker-archive:{}", dir.display()))
+            .unwrap()
+            .unwrap();
+        let err = archive.image("linux/amd64").unwrap_err();
+        assert!(err.to_string().contains("invalid path"));
+    }
+}
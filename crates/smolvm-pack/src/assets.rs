@@ -4,17 +4,35 @@
 //! - Runtime libraries (libkrun, libkrunfw)
 //! - Agent rootfs
 //! - OCI image layers
-
+//!
+//! A multi-platform pack stages the libraries and agent rootfs of each
+//! additional platform under `platforms/<os>-<arch>/`. Layers are shared,
+//! so a layer used by several platforms is stored once.
+//!
+//! With a source date epoch set (see
+//! [`AssetCollector::with_source_date_epoch`]), staged tarballs are
+//! rewritten in sorted order with timestamps clamped to the epoch (layers
+//! are then recorded under the digest of the rewritten tarball), and the
+//! storage template is formatted with a fixed UUID and clock, so the same
+//! inputs always pack to the same bytes.
+
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::{BufWriter, Read};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-use crate::format::{AssetEntry, AssetInventory, LayerEntry};
+use sha2::{Digest, Sha256};
+
+use crate::format::{platform_asset_dir, AssetEntry, AssetFrame, AssetInventory, LayerEntry};
 use crate::{PackError, Result};
 
 /// Compression level for zstd (19 = high compression).
 pub const ZSTD_LEVEL: i32 = 19;
 
+/// Filesystem UUID, and directory hash seed, of storage templates
+/// formatted for reproducible packs.
+const REPRODUCIBLE_FS_UUID: &str = "736d6f6c-766d-4000-8000-73746f726167";
+
 /// Find a pre-formatted disk template by filename.
 ///
 /// Searches in order:
@@ -42,6 +60,11 @@ fn find_existing_template(filename: &str) -> Option<PathBuf> {
 pub struct AssetCollector {
     staging_dir: PathBuf,
     inventory: AssetInventory,
+    platforms: BTreeMap<String, AssetInventory>,
+    /// Platform being collected, or `None` for the primary one.
+    current_platform: Option<String>,
+    /// Latest timestamp written into staged assets, for reproducible packs.
+    source_date_epoch: Option<u64>,
 }
 
 impl AssetCollector {
@@ -58,14 +81,98 @@ impl AssetCollector {
                 agent_rootfs: AssetEntry {
                     path: "agent-rootfs.tar".to_string(),
                     size: 0,
+                    sha256: None,
                 },
                 layers: Vec::new(),
                 storage_template: None,
                 overlay_template: None,
+                sbom: None,
             },
+            platforms: BTreeMap::new(),
+            current_platform: None,
+            source_date_epoch: None,
         })
     }
 
+    /// Stage assets reproducibly, as of `epoch` (usually
+    /// `SOURCE_DATE_EPOCH`).
+    ///
+    /// The agent rootfs and layers are rewritten as tarballs sorted by path,
+    /// with no timestamp later than `epoch`; the agent rootfs is also made
+    /// root-owned. Storage templates are formatted with a fixed UUID at
+    /// `epoch`. Packing the same image and runtime then gives
+    /// byte-identical output.
+    pub fn with_source_date_epoch(mut self, epoch: u64) -> Self {
+        self.source_date_epoch = Some(epoch);
+        self
+    }
+
+    /// Start collecting assets for an additional platform.
+    ///
+    /// Libraries, agent rootfs and layers collected afterwards are recorded
+    /// in that platform's inventory. It shares the primary platform's
+    /// storage template, so create that first.
+    pub fn begin_platform(&mut self, platform: &str) -> Result<()> {
+        if self.platforms.contains_key(platform) {
+            return Err(PackError::AssetNotFound(format!(
+                "platform {} collected twice",
+                platform
+            )));
+        }
+        let agent_rootfs = format!("{}/agent-rootfs.tar", platform_asset_dir(platform));
+        fs::create_dir_all(
+            self.staging_dir
+                .join(platform_asset_dir(platform))
+                .join("lib"),
+        )?;
+
+        self.platforms.insert(
+            platform.to_string(),
+            AssetInventory {
+                libraries: Vec::new(),
+                agent_rootfs: AssetEntry {
+                    path: agent_rootfs,
+                    size: 0,
+                    sha256: None,
+                },
+                layers: Vec::new(),
+                storage_template: self.inventory.storage_template.clone(),
+                overlay_template: None,
+                sbom: None,
+            },
+        );
+        self.current_platform = Some(platform.to_string());
+        Ok(())
+    }
+
+    /// Inventory of the platform being collected.
+    fn current_inventory(&mut self) -> &mut AssetInventory {
+        match &self.current_platform {
+            Some(platform) => self
+                .platforms
+                .get_mut(platform)
+                .expect("current platform has an inventory"),
+            None => &mut self.inventory,
+        }
+    }
+
+    /// Archive path for a per-platform asset of the current platform.
+    fn platform_path(&self, name: &str) -> String {
+        match &self.current_platform {
+            Some(platform) => format!("{}/{}", platform_asset_dir(platform), name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Entry for a layer already staged by another platform.
+    fn staged_layer(&self, digest: &str) -> Option<LayerEntry> {
+        std::iter::once(&self.inventory)
+            .chain(self.platforms.values())
+            .flat_map(|inventory| inventory.layers.iter())
+            .find(|l| l.digest == digest)
+            .cloned()
+    }
+
     /// Get the staging directory path.
     pub fn staging_dir(&self) -> &Path {
         &self.staging_dir
@@ -92,14 +199,11 @@ impl AssetCollector {
                 )));
             }
 
-            let dst = self.staging_dir.join("lib").join(name);
-            fs::copy(&src, &dst)?;
+            let path = self.platform_path(&format!("lib/{}", name));
+            fs::copy(&src, self.staging_dir.join(&path))?;
 
-            let metadata = fs::metadata(&dst)?;
-            self.inventory.libraries.push(AssetEntry {
-                path: format!("lib/{}", name),
-                size: metadata.len(),
-            });
+            let entry = self.staged_entry(path)?;
+            self.current_inventory().libraries.push(entry);
         }
 
         Ok(())
@@ -114,8 +218,17 @@ impl AssetCollector {
             )));
         }
 
-        let tar_path = self.staging_dir.join("agent-rootfs.tar");
-        let tar_file = File::create(&tar_path)?;
+        let path = self.platform_path("agent-rootfs.tar");
+        let tar_path = self.staging_dir.join(&path);
+        // Reproducible packs build the tarball aside, then normalize it
+        let unsorted = match self.source_date_epoch {
+            Some(_) => Some(tempfile::NamedTempFile::new_in(&self.staging_dir)?),
+            None => None,
+        };
+        let tar_file = match unsorted {
+            Some(ref unsorted) => unsorted.reopen()?,
+            None => File::create(&tar_path)?,
+        };
         let mut tar_builder = tar::Builder::new(BufWriter::new(tar_file));
 
         // Don't follow symlinks - preserve them as-is
@@ -126,52 +239,103 @@ impl AssetCollector {
             .append_dir_all(".", rootfs_dir)
             .map_err(|e| PackError::Tar(e.to_string()))?;
 
+        // Flush before hashing the tarball
         tar_builder
-            .finish()
-            .map_err(|e| PackError::Tar(e.to_string()))?;
+            .into_inner()
+            .map_err(|e| PackError::Tar(e.to_string()))?
+            .flush()?;
+        if let (Some(unsorted), Some(epoch)) = (unsorted, self.source_date_epoch) {
+            normalize_tar(unsorted.path(), &tar_path, epoch, true)?;
+        }
 
-        let metadata = fs::metadata(&tar_path)?;
-        self.inventory.agent_rootfs = AssetEntry {
-            path: "agent-rootfs.tar".to_string(),
-            size: metadata.len(),
-        };
+        self.current_inventory().agent_rootfs = self.staged_entry(path)?;
 
         Ok(())
     }
 
     /// Add an OCI layer tarball.
     pub fn add_layer(&mut self, digest: &str, layer_data: &[u8]) -> Result<()> {
+        if let Some(entry) = self.staged_layer(digest) {
+            self.current_inventory().layers.push(entry);
+            return Ok(());
+        }
+
         // Create filename from digest (remove sha256: prefix)
         let short_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
         let filename = format!("{}.tar", &short_digest[..12]);
         let path = format!("layers/{}", filename);
 
         let dst = self.staging_dir.join(&path);
+        if self.source_date_epoch.is_some() {
+            let mut unsorted = tempfile::NamedTempFile::new_in(&self.staging_dir)?;
+            unsorted.write_all(layer_data)?;
+            return self.add_layer_from_file(digest, unsorted.path());
+        }
         fs::write(&dst, layer_data)?;
 
-        self.inventory.layers.push(LayerEntry {
+        self.current_inventory().layers.push(LayerEntry {
             digest: digest.to_string(),
             path,
             size: layer_data.len() as u64,
+            sha256: Some(hex_encode(&Sha256::digest(layer_data))),
         });
 
         Ok(())
     }
 
     /// Add an OCI layer from a file path.
+    ///
+    /// A reproducible pack stores the layer normalized, so it is recorded
+    /// under the digest of the normalized tarball (its diff_id) rather than
+    /// `digest`: the same digest always means the same layer bytes.
     pub fn add_layer_from_file(&mut self, digest: &str, layer_path: &Path) -> Result<()> {
-        let short_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+        if let Some(entry) = self.staged_layer(digest) {
+            self.current_inventory().layers.push(entry);
+            return Ok(());
+        }
+
+        let normalized = match self.source_date_epoch {
+            Some(epoch) => {
+                let normalized = tempfile::NamedTempFile::new_in(&self.staging_dir)?;
+                normalize_tar(layer_path, normalized.path(), epoch, false)?;
+                Some(normalized)
+            }
+            None => None,
+        };
+        let digest = match normalized {
+            Some(ref normalized) => {
+                let digest = format!("sha256:{}", sha256_file(normalized.path())?);
+                if let Some(entry) = self.staged_layer(&digest) {
+                    self.current_inventory().layers.push(entry);
+                    return Ok(());
+                }
+                digest
+            }
+            None => digest.to_string(),
+        };
+
+        let short_digest = digest.strip_prefix("sha256:").unwrap_or(&digest);
         let filename = format!("{}.tar", &short_digest[..12]);
         let path = format!("layers/{}", filename);
 
         let dst = self.staging_dir.join(&path);
-        fs::copy(layer_path, &dst)?;
+        match normalized {
+            Some(normalized) => {
+                normalized
+                    .persist(&dst)
+                    .map_err(|e| PackError::Io(e.error))?;
+            }
+            None => {
+                fs::copy(layer_path, &dst)?;
+            }
+        }
 
-        let metadata = fs::metadata(&dst)?;
-        self.inventory.layers.push(LayerEntry {
-            digest: digest.to_string(),
-            path,
-            size: metadata.len(),
+        let entry = self.staged_entry(path)?;
+        self.current_inventory().layers.push(LayerEntry {
+            digest,
+            path: entry.path,
+            size: entry.size,
+            sha256: entry.sha256,
         });
 
         Ok(())
@@ -189,7 +353,6 @@ impl AssetCollector {
     ///
     /// The template is a 512MB sparse file (actual size ~100KB when empty).
     pub fn create_storage_template(&mut self) -> Result<()> {
-        use std::io::{Seek, SeekFrom, Write};
         use std::process::Command;
 
         const TEMPLATE_SIZE: u64 = 512 * 1024 * 1024; // 512MB virtual size
@@ -201,11 +364,7 @@ impl AssetCollector {
         // This avoids requiring e2fsprogs on the build machine.
         if let Some(existing) = find_existing_template("storage-template.ext4") {
             fs::copy(&existing, &template_path)?;
-            let metadata = fs::metadata(&template_path)?;
-            self.inventory.storage_template = Some(AssetEntry {
-                path: TEMPLATE_NAME.to_string(),
-                size: metadata.len(),
-            });
+            self.inventory.storage_template = Some(self.staged_entry(TEMPLATE_NAME.to_string())?);
             return Ok(());
         }
 
@@ -253,13 +412,23 @@ impl AssetCollector {
             libc::signal(libc::SIGCHLD, libc::SIG_DFL);
         }
 
-        let mut child = Command::new(mkfs_path)
-            .args([
-                "-F", // Force (don't ask)
-                "-q", // Quiet
-                "-m", "0", // No reserved blocks
-                "-L", "smolvm", // Label
-            ])
+        let mut command = Command::new(mkfs_path);
+        command.args([
+            "-F", // Force (don't ask)
+            "-q", // Quiet
+            "-m", "0", // No reserved blocks
+            "-L", "smolvm", // Label
+        ]);
+        if let Some(epoch) = self.source_date_epoch {
+            // mkfs otherwise picks a random UUID and hash seed, and stamps
+            // the superblock and inodes with the current time. A fake time
+            // of 0 means the real one, so the earliest is 1.
+            command
+                .args(["-U", REPRODUCIBLE_FS_UUID])
+                .arg(format!("-Ehash_seed={}", REPRODUCIBLE_FS_UUID))
+                .env("E2FSPROGS_FAKE_TIME", epoch.max(1).to_string());
+        }
+        let mut child = command
             .arg(&template_path)
             .stdin(std::process::Stdio::null())
             .stdout(std::process::Stdio::null())
@@ -277,13 +446,17 @@ impl AssetCollector {
             ));
         }
 
-        // Get actual file size (sparse, so much smaller than 512MB)
-        let metadata = fs::metadata(&template_path)?;
-        self.inventory.storage_template = Some(AssetEntry {
-            path: TEMPLATE_NAME.to_string(),
-            size: metadata.len(),
-        });
+        self.inventory.storage_template = Some(self.staged_entry(TEMPLATE_NAME.to_string())?);
+
+        Ok(())
+    }
 
+    /// Add an existing storage template, such as one taken from another
+    /// pack, instead of creating one.
+    pub fn add_storage_template(&mut self, path: &Path) -> Result<()> {
+        const TEMPLATE_NAME: &str = "storage.ext4";
+        fs::copy(path, self.staging_dir.join(TEMPLATE_NAME))?;
+        self.inventory.storage_template = Some(self.staged_entry(TEMPLATE_NAME.to_string())?);
         Ok(())
     }
 
@@ -304,47 +477,94 @@ impl AssetCollector {
         let dst = self.staging_dir.join(OVERLAY_NAME);
         fs::copy(path, &dst)?;
 
-        let metadata = fs::metadata(&dst)?;
-        self.inventory.overlay_template = Some(AssetEntry {
-            path: OVERLAY_NAME.to_string(),
-            size: metadata.len(),
-        });
+        self.inventory.overlay_template = Some(self.staged_entry(OVERLAY_NAME.to_string())?);
 
         Ok(())
     }
 
+    /// Add an SBOM document for the current platform.
+    pub fn add_sbom(&mut self, document: &[u8]) -> Result<()> {
+        let path = self.platform_path(crate::sbom::SBOM_NAME);
+        fs::write(self.staging_dir.join(&path), document)?;
+        self.current_inventory().sbom = Some(self.staged_entry(path)?);
+        Ok(())
+    }
+
+    /// Staged layer tarballs of the current platform, lowest first.
+    pub(crate) fn current_layer_paths(&self) -> Vec<PathBuf> {
+        let inventory = match &self.current_platform {
+            Some(platform) => &self.platforms[platform],
+            None => &self.inventory,
+        };
+        inventory
+            .layers
+            .iter()
+            .map(|l| self.staging_dir.join(&l.path))
+            .collect()
+    }
+
+    /// Inventory entry for a staged file, with its size and SHA-256.
+    fn staged_entry(&self, path: String) -> Result<AssetEntry> {
+        let staged = self.staging_dir.join(&path);
+        Ok(AssetEntry {
+            size: fs::metadata(&staged)?.len(),
+            sha256: Some(sha256_file(&staged)?),
+            path,
+        })
+    }
+
     /// Get the current asset inventory.
     pub fn inventory(&self) -> &AssetInventory {
         &self.inventory
     }
 
+    /// Inventories of the additional platforms, keyed by OS/arch.
+    pub fn platform_inventories(&self) -> &BTreeMap<String, AssetInventory> {
+        &self.platforms
+    }
+
     /// Consume the collector and return the final inventory.
     pub fn into_inventory(self) -> AssetInventory {
         self.inventory
     }
 
-    /// Compress all staged assets into a single zstd-compressed tarball.
-    pub fn compress(&self, output: &Path) -> Result<u64> {
-        let output_file = File::create(output)?;
-        let encoder = zstd::stream::Encoder::new(output_file, ZSTD_LEVEL)
-            .map_err(|e| PackError::Compression(e.to_string()))?;
-        let mut tar_builder = tar::Builder::new(encoder);
+    /// Consume the collector and return the primary inventory and those of
+    /// the additional platforms.
+    pub fn into_inventories(self) -> (AssetInventory, BTreeMap<String, AssetInventory>) {
+        (self.inventory, self.platforms)
+    }
 
-        // Add all files from staging directory
-        tar_builder
-            .append_dir_all(".", &self.staging_dir)
-            .map_err(|e| PackError::Tar(e.to_string()))?;
+    /// Compress all staged assets into a zstd-compressed tarball, returning
+    /// its size.
+    pub fn compress(&self, output: &Path) -> Result<u64> {
+        self.compress_indexed(output)?;
+        Ok(fs::metadata(output)?.len())
+    }
 
-        let encoder = tar_builder
-            .into_inner()
-            .map_err(|e| PackError::Tar(e.to_string()))?;
-        encoder
-            .finish()
-            .map_err(|e| PackError::Compression(e.to_string()))?;
+    /// Compress all staged files, one zstd frame per file, and return the
+    /// index of frames for [`PackManifest::asset_index`].
+    ///
+    /// [`PackManifest::asset_index`]: crate::format::PackManifest::asset_index
+    pub fn compress_indexed(&self, output: &Path) -> Result<Vec<AssetFrame>> {
+        let mut files = Vec::new();
+        collect_files(&self.staging_dir, Path::new(""), &mut files)?;
+        compress_normalized(&self.staging_dir, files.iter().map(String::as_str), output)
+    }
+}
 
-        let metadata = fs::metadata(output)?;
-        Ok(metadata.len())
+/// Relative paths of the regular files under `root/dir`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path.to_string_lossy().into_owned());
+        }
     }
+    Ok(())
 }
 
 /// Decompress a zstd-compressed assets blob.
@@ -378,6 +598,283 @@ pub fn decompress_assets_from_file(compressed_path: &Path, output_dir: &Path) ->
     Ok(())
 }
 
+/// Compress the files at `paths` (relative to `root`) into a zstd tar,
+/// one zstd frame per file.
+///
+/// Entries are written in sorted order with normalized headers (no
+/// timestamps, owners or directory entries), so the same files always
+/// produce the same archive. A final frame holds the end-of-archive
+/// marker, so the blob decompresses as one tar, and the returned index
+/// locates each file's frame for reading it on its own.
+pub fn compress_normalized<'a>(
+    root: &Path,
+    paths: impl IntoIterator<Item = &'a str>,
+    output: &Path,
+) -> Result<Vec<AssetFrame>> {
+    let mut paths: Vec<&str> = paths.into_iter().collect();
+    paths.sort_unstable();
+    paths.dedup();
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut index = Vec::with_capacity(paths.len());
+    let mut offset = 0;
+    for path in paths {
+        let mut file = File::open(root.join(path))?;
+        let size = file.metadata()?.len();
+        let mut header = normalized_header(size);
+        header
+            .set_path(format!("./{}", path))
+            .map_err(|e| PackError::Tar(format!("{}: {}", path, e)))?;
+        header.set_cksum();
+
+        let frame_size = write_frame(&mut writer, |encoder| {
+            encoder.write_all(header.as_bytes())?;
+            let copied = std::io::copy(&mut (&mut file).take(size), encoder)?;
+            if copied != size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!("{} changed while compressing", path),
+                ));
+            }
+            let padding = (512 - size % 512) % 512;
+            encoder.write_all(&[0u8; 512][..padding as usize])
+        })?;
+        index.push(AssetFrame {
+            path: path.to_string(),
+            offset,
+            size: frame_size,
+        });
+        offset += frame_size;
+    }
+    offset += write_frame(&mut writer, |encoder| encoder.write_all(&[0u8; 1024]))?;
+    writer.flush()?;
+
+    debug_assert_eq!(offset, fs::metadata(output)?.len());
+    Ok(index)
+}
+
+/// Write one zstd frame holding what `fill` writes, returning its
+/// compressed size.
+fn write_frame<W: Write>(
+    writer: &mut W,
+    fill: impl FnOnce(&mut zstd::stream::Encoder<'_, CountingWriter<&mut W>>) -> std::io::Result<()>,
+) -> Result<u64> {
+    let mut encoder = zstd::stream::Encoder::new(
+        CountingWriter {
+            inner: writer,
+            count: 0,
+        },
+        ZSTD_LEVEL,
+    )
+    .map_err(|e| PackError::Compression(e.to_string()))?;
+    fill(&mut encoder)?;
+    let counter = encoder
+        .finish()
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    Ok(counter.count)
+}
+
+/// Writer that counts the bytes passed through it.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Tar header for a regular file with no timestamp or owner.
+pub(crate) fn normalized_header(size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+/// A tar entry to copy into a normalized tarball.
+struct TarEntry {
+    path: PathBuf,
+    link_name: Option<PathBuf>,
+    header: tar::Header,
+    xattrs: Vec<(String, Vec<u8>)>,
+    /// Offset of the entry's data in the source tarball.
+    data_offset: u64,
+}
+
+/// Rewrite the tarball at `src` to `dst` in a canonical form.
+///
+/// Entries are sorted by path, with hard links last so their targets come
+/// first. Headers keep only the type, mode, owner, device numbers and
+/// extended attributes, with modification times clamped to
+/// `source_date_epoch`. `root_owned` also resets owners to root.
+fn normalize_tar(src: &Path, dst: &Path, source_date_epoch: u64, root_owned: bool) -> Result<()> {
+    let tar_err = |e: std::io::Error| PackError::Tar(format!("{}: {}", src.display(), e));
+
+    let mut archive = tar::Archive::new(File::open(src)?);
+    let mut entries = Vec::new();
+    for entry in archive.entries_with_seek().map_err(tar_err)? {
+        let mut entry = entry.map_err(tar_err)?;
+        let path = entry.path().map_err(tar_err)?.into_owned();
+        let entry_type = match entry.header().entry_type() {
+            tar::EntryType::XGlobalHeader => continue,
+            tar::EntryType::Regular | tar::EntryType::Continuous => tar::EntryType::Regular,
+            t @ (tar::EntryType::Directory
+            | tar::EntryType::Symlink
+            | tar::EntryType::Link
+            | tar::EntryType::Char
+            | tar::EntryType::Block
+            | tar::EntryType::Fifo) => t,
+            t => {
+                return Err(PackError::Tar(format!(
+                    "{}: unsupported entry type {:?} for {}",
+                    src.display(),
+                    t,
+                    path.display()
+                )))
+            }
+        };
+
+        let old = entry.header();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(old.mode().map_err(tar_err)?);
+        header.set_mtime(old.mtime().map_err(tar_err)?.min(source_date_epoch));
+        if root_owned {
+            header.set_uid(0);
+            header.set_gid(0);
+        } else {
+            header.set_uid(old.uid().map_err(tar_err)?);
+            header.set_gid(old.gid().map_err(tar_err)?);
+            if let Ok(Some(name)) = old.username() {
+                header.set_username(name).map_err(tar_err)?;
+            }
+            if let Ok(Some(name)) = old.groupname() {
+                header.set_groupname(name).map_err(tar_err)?;
+            }
+        }
+        if entry_type.is_character_special() || entry_type.is_block_special() {
+            header.set_device_major(old.device_major().map_err(tar_err)?.unwrap_or(0))?;
+            header.set_device_minor(old.device_minor().map_err(tar_err)?.unwrap_or(0))?;
+        }
+        header.set_size(if entry_type.is_file() {
+            entry.size()
+        } else {
+            0
+        });
+
+        let link_name = match entry_type {
+            tar::EntryType::Symlink | tar::EntryType::Link => Some(
+                entry
+                    .link_name()
+                    .map_err(tar_err)?
+                    .unwrap_or_default()
+                    .into_owned(),
+            ),
+            _ => None,
+        };
+        let mut xattrs = Vec::new();
+        if let Some(extensions) = entry.pax_extensions().map_err(tar_err)? {
+            for extension in extensions {
+                let extension = extension.map_err(tar_err)?;
+                match extension.key() {
+                    Ok(key) if key.starts_with("SCHILY.xattr.") => {
+                        xattrs.push((key.to_string(), extension.value_bytes().to_vec()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        entries.push(TarEntry {
+            path,
+            link_name,
+            header,
+            xattrs,
+            data_offset: entry.raw_file_position(),
+        });
+    }
+    entries.sort_by(|a, b| {
+        let a_link = a.header.entry_type() == tar::EntryType::Link;
+        let b_link = b.header.entry_type() == tar::EntryType::Link;
+        (a_link, &a.path).cmp(&(b_link, &b.path))
+    });
+
+    let mut data = File::open(src)?;
+    let mut builder = tar::Builder::new(BufWriter::new(File::create(dst)?));
+    for mut entry in entries {
+        builder
+            .append_pax_extensions(entry.xattrs.iter().map(|(k, v)| (k.as_str(), &v[..])))
+            .map_err(tar_err)?;
+        match entry.link_name {
+            Some(ref target) => builder.append_link(&mut entry.header, &entry.path, target),
+            None => {
+                data.seek(SeekFrom::Start(entry.data_offset))?;
+                let size = entry.header.size().map_err(tar_err)?;
+                builder.append_data(&mut entry.header, &entry.path, (&mut data).take(size))
+            }
+        }
+        .map_err(tar_err)?;
+    }
+    builder.into_inner().map_err(tar_err)?.flush()?;
+    Ok(())
+}
+
+/// Calculate the SHA-256 of a file (hex).
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let size = fs::metadata(path)?.len();
+    Ok(hex_encode(&sha256_file_range(path, 0, size)?))
+}
+
+/// Calculate the OCI digest (`sha256:<hex>`) and size of a file.
+pub fn file_digest(path: &Path) -> Result<(String, u64)> {
+    let size = fs::metadata(path)?.len();
+    let digest = hex_encode(&sha256_file_range(path, 0, size)?);
+    Ok((format!("sha256:{}", digest), size))
+}
+
+/// Calculate the SHA-256 of a range of a file.
+pub fn sha256_file_range(path: &Path, offset: u64, size: u64) -> Result<[u8; 32]> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut hasher = Sha256::new();
+    let copied = std::io::copy(&mut file.take(size), &mut hasher)?;
+    if copied != size {
+        return Err(PackError::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "file shorter than expected while hashing",
+        )));
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Lowercase hex encoding of bytes.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+}
+
 /// Calculate CRC32 checksum of data.
 pub fn crc32(data: &[u8]) -> u32 {
     crc32fast::hash(data)
@@ -442,6 +939,21 @@ mod tests {
         assert_eq!(checksum, 0); // CRC32 of empty data is 0
     }
 
+    #[test]
+    fn test_file_digest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("blob");
+        fs::write(&path, b"hello world").unwrap();
+
+        let (digest, size) = file_digest(&path).unwrap();
+        assert_eq!(
+            digest,
+            "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(size, 11);
+        assert_eq!(digest, format!("sha256:{}", sha256_file(&path).unwrap()));
+    }
+
     #[test]
     fn test_asset_collector_staging() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -453,6 +965,157 @@ mod tests {
         assert!(staging.join("layers").exists());
     }
 
+    #[test]
+    fn test_asset_collector_platforms_share_layers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let staging = temp_dir.path().join("staging");
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(rootfs.join("sbin")).unwrap();
+        fs::write(rootfs.join("sbin/init"), b"init").unwrap();
+
+        let mut collector = AssetCollector::new(staging.clone()).unwrap();
+        collector.collect_agent_rootfs(&rootfs).unwrap();
+        collector
+            .add_layer("sha256:shared1234567", b"data")
+            .unwrap();
+        collector.add_layer("sha256:arm64only1234", b"arm").unwrap();
+
+        collector.begin_platform("linux/amd64").unwrap();
+        collector.collect_agent_rootfs(&rootfs).unwrap();
+        collector
+            .add_layer("sha256:shared1234567", b"data")
+            .unwrap();
+        collector.add_layer("sha256:amd64only1234", b"amd").unwrap();
+        assert!(collector.begin_platform("linux/amd64").is_err());
+
+        let (primary, platforms) = collector.into_inventories();
+        let amd64 = &platforms["linux/amd64"];
+        assert_eq!(primary.agent_rootfs.path, "agent-rootfs.tar");
+        assert_eq!(
+            amd64.agent_rootfs.path,
+            "platforms/linux-amd64/agent-rootfs.tar"
+        );
+        assert!(staging.join(&amd64.agent_rootfs.path).exists());
+        assert_eq!(
+            primary.agent_rootfs.sha256.as_deref(),
+            Some(
+                sha256_file(&staging.join("agent-rootfs.tar"))
+                    .unwrap()
+                    .as_str()
+            )
+        );
+
+        assert_eq!(amd64.layers.len(), 2);
+        assert_eq!(amd64.layers[0].path, primary.layers[0].path);
+        assert_eq!(fs::read_dir(staging.join("layers")).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_normalize_tar() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src = temp_dir.path().join("src.tar");
+        let mut builder = tar::Builder::new(File::create(&src).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o755);
+        header.set_mtime(2_000_000_000);
+        header.set_uid(1000);
+        header.set_gid(1000);
+        header.set_username("app").unwrap();
+        builder
+            .append_pax_extensions([("SCHILY.xattr.user.test", &b"value"[..]), ("atime", b"1")])
+            .unwrap();
+        builder
+            .append_data(&mut header, "zeta", &b"zeta"[..])
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_mtime(2_000_000_000);
+        header.set_uid(1000);
+        header.set_gid(1000);
+        builder.append_link(&mut header, "beta", "zeta").unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_mtime(5);
+        header.set_uid(0);
+        header.set_gid(0);
+        builder
+            .append_data(&mut header, "alpha", &b"alpha"[..])
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        let dst = temp_dir.path().join("dst.tar");
+        normalize_tar(&src, &dst, 1_000_000_000, false).unwrap();
+        let mut archive = tar::Archive::new(File::open(&dst).unwrap());
+        let mut seen = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().display().to_string();
+            let header = entry.header().clone();
+            match path.as_str() {
+                "zeta" => {
+                    assert_eq!(header.mtime().unwrap(), 1_000_000_000);
+                    assert_eq!(header.mode().unwrap(), 0o755);
+                    assert_eq!(header.uid().unwrap(), 1000);
+                    assert_eq!(header.username().unwrap(), Some("app"));
+                    let extensions: Vec<String> = entry
+                        .pax_extensions()
+                        .unwrap()
+                        .unwrap()
+                        .map(|e| e.unwrap().key().unwrap().to_string())
+                        .collect();
+                    assert_eq!(extensions, ["SCHILY.xattr.user.test"]);
+                    let mut data = String::new();
+                    entry.read_to_string(&mut data).unwrap();
+                    assert_eq!(data, "zeta");
+                }
+                "alpha" => assert_eq!(header.mtime().unwrap(), 5),
+                "beta" => assert_eq!(entry.link_name().unwrap().unwrap().to_str(), Some("zeta")),
+                _ => panic!("unexpected entry {}", path),
+            }
+            seen.push(path);
+        }
+        // Sorted, with hard links after their targets
+        assert_eq!(seen, ["alpha", "zeta", "beta"]);
+
+        // Normalizing is idempotent
+        let again = temp_dir.path().join("again.tar");
+        normalize_tar(&dst, &again, 1_000_000_000, false).unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), fs::read(&again).unwrap());
+
+        normalize_tar(&src, &again, 1_000_000_000, true).unwrap();
+        let mut archive = tar::Archive::new(File::open(&again).unwrap());
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            assert_eq!(entry.header().uid().unwrap(), 0);
+            assert_eq!(entry.header().username().unwrap(), Some(""));
+        }
+    }
+
+    #[test]
+    fn test_agent_rootfs_digest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rootfs = temp_dir.path().join("rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+        // Larger than the BufWriter's buffer, so an unflushed tail would show
+        fs::write(rootfs.join("agent"), vec![7u8; 100_000]).unwrap();
+        let staging = temp_dir.path().join("staging");
+
+        let mut collector = AssetCollector::new(staging.clone()).unwrap();
+        collector.collect_agent_rootfs(&rootfs).unwrap();
+
+        let entry = &collector.inventory().agent_rootfs;
+        let tar_path = staging.join("agent-rootfs.tar");
+        assert_eq!(entry.size, fs::metadata(&tar_path).unwrap().len());
+        assert_eq!(
+            entry.sha256.as_deref(),
+            Some(sha256_file(&tar_path).unwrap().as_str())
+        );
+    }
+
     #[test]
     fn test_compression_roundtrip() {
         let temp_dir = tempfile::tempdir().unwrap();
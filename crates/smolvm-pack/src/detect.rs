@@ -19,6 +19,10 @@ pub enum PackedMode {
     Section {
         /// Parsed manifest from the section.
         manifest: Box<crate::format::PackManifest>,
+        /// Raw manifest JSON, as covered by the signature.
+        manifest_json: Vec<u8>,
+        /// Signature block, if the pack was signed.
+        signature: Option<crate::trust::ManifestSignature>,
         /// CRC32 checksum from the section header.
         checksum: u32,
         /// Pointer to compressed assets in the section (valid for process lifetime).
@@ -42,6 +46,23 @@ pub enum PackedMode {
     },
 }
 
+/// OCI platform of the VMs this host runs, used to pick assets from a
+/// multi-platform pack.
+pub fn host_platform() -> &'static str {
+    #[cfg(target_arch = "aarch64")]
+    {
+        "linux/arm64"
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        "linux/amd64"
+    }
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    {
+        "linux/unknown"
+    }
+}
+
 /// Detect whether this process is running as a packed binary.
 ///
 /// Checks in order:
@@ -127,6 +148,8 @@ fn try_section_mode() -> Option<PackedMode> {
     let embedded = read_embedded_section()?;
     Some(PackedMode::Section {
         manifest: Box::new(embedded.manifest),
+        manifest_json: embedded.manifest_json,
+        signature: embedded.signature,
         checksum: embedded.header.checksum,
         assets_ptr: embedded.assets_ptr,
         assets_size: embedded.assets_size,
@@ -138,6 +161,8 @@ fn try_section_mode() -> Option<PackedMode> {
 struct EmbeddedData {
     header: crate::format::SectionHeader,
     manifest: crate::format::PackManifest,
+    manifest_json: Vec<u8>,
+    signature: Option<crate::trust::ManifestSignature>,
     assets_ptr: *const u8,
     assets_size: usize,
 }
@@ -206,7 +231,8 @@ fn read_embedded_section() -> Option<EmbeddedData> {
         // Validate sizes
         let expected_size = SECTION_HEADER_SIZE
             + section_header.manifest_size as usize
-            + section_header.assets_size as usize;
+            + section_header.assets_size as usize
+            + section_header.signature_size as usize;
         if size < expected_size {
             return None;
         }
@@ -220,9 +246,23 @@ fn read_embedded_section() -> Option<EmbeddedData> {
         // Assets follow the manifest
         let assets_ptr = manifest_start.add(section_header.manifest_size as usize);
 
+        // An unreadable signature block is treated as unsigned; the trust
+        // policy, if any, then rejects the binary.
+        let signature = if section_header.signature_size > 0 {
+            let signature_bytes = std::slice::from_raw_parts(
+                assets_ptr.add(section_header.assets_size as usize),
+                section_header.signature_size as usize,
+            );
+            crate::trust::ManifestSignature::from_json(signature_bytes).ok()
+        } else {
+            None
+        };
+
         Some(EmbeddedData {
             header: section_header,
             manifest,
+            manifest_json: manifest_bytes.to_vec(),
+            signature,
             assets_ptr,
             assets_size: section_header.assets_size as usize,
         })
@@ -251,7 +291,7 @@ mod tests {
     fn test_read_footer_direct_rejects_invalid_magic() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("no_magic.bin");
-        std::fs::write(&path, &[0u8; 128]).unwrap();
+        std::fs::write(&path, [0u8; 128]).unwrap();
         assert!(read_footer_direct(&path).is_err());
     }
 }
@@ -3,10 +3,11 @@
 //! Provides shared extraction logic used by both the main `smolvm` binary
 //! (sidecar mode via `runpack`) and the standalone stub executable.
 
-use crate::format::{PackFooter, SIDECAR_EXTENSION};
+use crate::format::{platform_asset_dir, AssetFrame, PackFooter, PackManifest, SIDECAR_EXTENSION};
+use std::collections::HashSet;
 use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
@@ -19,13 +20,25 @@ use std::os::unix::io::AsRawFd;
 /// `lib/libkrun.dylib → /tmp/evil.so`, and subsequent `dlopen()` would
 /// load the attacker's library. This function rejects any entry that is
 /// not a regular file or directory.
-fn safe_unpack<R: Read>(archive: &mut tar::Archive<R>, dest: &Path) -> std::io::Result<()> {
+///
+/// With a `selection`, entries are skipped or relocated as it says.
+pub(crate) fn safe_unpack<R: Read>(
+    archive: &mut tar::Archive<R>,
+    dest: &Path,
+    selection: Option<&AssetSelection>,
+) -> std::io::Result<()> {
     let canonical_dest = dest.canonicalize().unwrap_or_else(|_| dest.to_path_buf());
 
     for entry_result in archive.entries()? {
         let mut entry = entry_result?;
         let entry_type = entry.header().entry_type();
-        let entry_path = entry.path()?.to_path_buf();
+        let entry_path = match selection {
+            Some(selection) => match selection.target(&entry.path()?) {
+                Some(target) => target,
+                None => continue,
+            },
+            None => entry.path()?.to_path_buf(),
+        };
 
         match entry_type {
             tar::EntryType::Regular | tar::EntryType::GNUSparse | tar::EntryType::Directory => {}
@@ -97,11 +110,82 @@ fn safe_unpack<R: Read>(archive: &mut tar::Archive<R>, dest: &Path) -> std::io::
         }
 
         // Unpack the individual entry
-        entry.unpack_in(dest)?;
+        if selection.is_some() {
+            if let Some(parent) = full_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&full_path)?;
+        } else {
+            entry.unpack_in(dest)?;
+        }
     }
     Ok(())
 }
 
+/// The entries of a multi-platform assets archive to extract for one
+/// platform.
+///
+/// The platform's libraries and agent rootfs are extracted to the usual
+/// `lib/` and `agent-rootfs.tar` locations and assets used only by other
+/// platforms are skipped, so the cache looks like that of a
+/// single-platform pack.
+#[derive(Debug, Clone)]
+pub struct AssetSelection {
+    /// Archive directory of the platform's own assets, if not primary.
+    prefix: Option<PathBuf>,
+    /// Assets belonging only to other platforms.
+    skip: HashSet<PathBuf>,
+}
+
+impl AssetSelection {
+    /// Selection for `platform`, or `None` for a single-platform pack.
+    pub fn for_platform(manifest: &PackManifest, platform: &str) -> std::io::Result<Option<Self>> {
+        if !manifest.is_multi_platform() {
+            return Ok(None);
+        }
+        let selected = manifest.inventory_for(platform).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "pack has no assets for {} (available: {})",
+                    platform,
+                    manifest.platform_names().join(", ")
+                ),
+            )
+        })?;
+
+        let keep: HashSet<&str> = selected.files().map(|(path, _)| path).collect();
+        let skip = std::iter::once(&manifest.assets)
+            .chain(manifest.platforms.values())
+            .flat_map(|inventory| inventory.files())
+            .map(|(path, _)| path)
+            .filter(|path| !keep.contains(path))
+            .map(PathBuf::from)
+            .collect();
+        let prefix =
+            (platform != manifest.platform).then(|| PathBuf::from(platform_asset_dir(platform)));
+
+        Ok(Some(Self { prefix, skip }))
+    }
+
+    /// Where to extract an archive entry, relative to the cache directory,
+    /// or `None` to skip it.
+    fn target(&self, entry_path: &Path) -> Option<PathBuf> {
+        let path: PathBuf = entry_path
+            .components()
+            .filter(|c| !matches!(c, std::path::Component::CurDir))
+            .collect();
+        if self.skip.contains(&path) {
+            return None;
+        }
+        if !path.starts_with("platforms") {
+            return Some(path);
+        }
+        let target = path.strip_prefix(self.prefix.as_ref()?).ok()?;
+        (!target.as_os_str().is_empty()).then(|| target.to_path_buf())
+    }
+}
+
 /// Normalize a path by resolving `.` and `..` components without requiring
 /// the path to exist on disk (unlike `canonicalize()`).
 fn normalize_path(path: &Path) -> PathBuf {
@@ -136,6 +220,62 @@ pub fn is_extracted(cache_dir: &Path) -> bool {
     cache_dir.join(EXTRACTION_MARKER).exists()
 }
 
+/// File remembering the SHA-256 of the pack's assets blob.
+const ASSETS_DIGEST_FILE: &str = ".smolvm-assets-sha256";
+
+/// SHA-256 of a pack's assets blob, remembered in its cache directory.
+///
+/// Hashing the assets means reading the whole pack, which would undo fast
+/// starts if done on every launch. The digest from `compute` is stored with
+/// the identity of the `pack` file (device, inode, size, modification and
+/// change times) and reused while that identity is unchanged; rewriting the
+/// pack in any way changes its change time, so the digest is recomputed.
+/// Failing to store the digest is not an error.
+#[cfg(unix)]
+pub fn cached_assets_sha256(
+    cache_dir: &Path,
+    pack: &Path,
+    compute: impl FnOnce() -> std::io::Result<[u8; 32]>,
+) -> std::io::Result<[u8; 32]> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(pack)?;
+    let identity = format!(
+        "{} {} {} {}.{} {}.{}",
+        meta.dev(),
+        meta.ino(),
+        meta.size(),
+        meta.mtime(),
+        meta.mtime_nsec(),
+        meta.ctime(),
+        meta.ctime_nsec()
+    );
+    let record = cache_dir.join(ASSETS_DIGEST_FILE);
+
+    let cached = fs::read_to_string(&record).ok().and_then(|content| {
+        let (stored_identity, hex) = content.trim_end().split_once('\n')?;
+        if stored_identity != identity || hex.len() != 64 {
+            return None;
+        }
+        let mut digest = [0u8; 32];
+        for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(digest)
+    });
+    if let Some(digest) = cached {
+        return Ok(digest);
+    }
+
+    let digest = compute()?;
+    let content = format!("{}\n{}\n", identity, crate::assets::hex_encode(&digest));
+    let tmp = record.with_extension("tmp");
+    let _ = fs::create_dir_all(cache_dir)
+        .and_then(|()| fs::write(&tmp, content))
+        .and_then(|()| fs::rename(&tmp, &record));
+    Ok(digest)
+}
+
 /// Check if footer indicates sidecar mode.
 fn is_sidecar_mode(footer: &PackFooter) -> bool {
     footer.assets_offset == 0
@@ -160,13 +300,19 @@ pub fn sidecar_path_for(exe_path: &Path) -> PathBuf {
 /// is false and extraction has already completed (marker file present), this
 /// is a no-op (after acquiring the lock to ensure visibility of a concurrent
 /// extraction that just finished).
+///
+/// For packs with an asset index, only the libraries and agent rootfs are
+/// extracted before returning; the OCI layers are returned as a
+/// [`LayerExtraction`] for the caller to run, which keeps the lock until
+/// they are done.
 pub fn extract_sidecar(
     sidecar_path: &Path,
     cache_dir: &Path,
     footer: &PackFooter,
+    selection: Option<&AssetSelection>,
     force: bool,
     debug: bool,
-) -> std::io::Result<()> {
+) -> std::io::Result<Option<LayerExtraction>> {
     if !sidecar_path.exists() {
         return Err(std::io::Error::new(
             std::io::ErrorKind::NotFound,
@@ -174,13 +320,70 @@ pub fn extract_sidecar(
         ));
     }
 
+    extract_file(sidecar_path, cache_dir, footer, selection, force, debug)
+}
+
+/// Extract the assets of a sidecar or an embedded binary under the cache
+/// lock.
+fn extract_file(
+    pack_path: &Path,
+    cache_dir: &Path,
+    footer: &PackFooter,
+    selection: Option<&AssetSelection>,
+    force: bool,
+    debug: bool,
+) -> std::io::Result<Option<LayerExtraction>> {
+    // Serializes concurrent first-run extractions of the same checksum.
+    let lock = lock_cache_dir(cache_dir)?;
+
+    // Double-check inside the lock: another process may have completed
+    // extraction while we were waiting for the lock.
+    if !force && is_extracted(cache_dir) {
+        if debug {
+            eprintln!("debug: assets already extracted (possibly by another process)");
+        }
+        return Ok(None);
+    }
+
+    // If force-extracting over an existing cache, remove it first so we
+    // get a clean slate.
+    if force && cache_dir.exists() {
+        let _ = fs::remove_dir_all(cache_dir);
+    }
+
+    let index = read_asset_index(pack_path, footer)?;
+    if index.is_empty() {
+        extract_stream(pack_path, cache_dir, footer, selection, debug)?;
+        return Ok(None);
+    }
+
+    let source = AssetSource::File(pack_path, footer.assets_offset);
+    let layers = extract_runtime(
+        source,
+        cache_dir,
+        &index,
+        footer.assets_size,
+        selection,
+        debug,
+    )?;
+    Ok(Some(LayerExtraction {
+        pack_path: pack_path.to_path_buf(),
+        assets_offset: footer.assets_offset,
+        cache_dir: cache_dir.to_path_buf(),
+        layers,
+        _lock: lock,
+        debug,
+    }))
+}
+
+/// Take an exclusive lock adjacent to a cache directory, released when the
+/// returned file is dropped.
+fn lock_cache_dir(cache_dir: &Path) -> std::io::Result<File> {
     // Ensure parent directory exists for the lockfile
     if let Some(parent) = cache_dir.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    // Acquire an exclusive lock adjacent to the cache directory.
-    // This serializes concurrent first-run extractions of the same checksum.
     let lock_path = cache_dir.with_extension("lock");
     let lock_file = fs::OpenOptions::new()
         .create(true)
@@ -196,51 +399,93 @@ pub fn extract_sidecar(
         }
     }
 
-    // Double-check inside the lock: another process may have completed
-    // extraction while we were waiting for the lock.
-    if !force && is_extracted(cache_dir) {
-        if debug {
-            eprintln!("debug: assets already extracted (possibly by another process)");
-        }
-        // Lock released on drop of lock_file
-        return Ok(());
+    Ok(lock_file)
+}
+
+/// Extract a pack's assets into `cache_dir`, replacing any existing
+/// extraction there in a single rename.
+///
+/// `pack_path` is the file holding the assets (the sidecar, or the binary
+/// itself in embedded mode). Assets are unpacked into a sibling directory
+/// first, so other processes see either the old cache or the complete new
+/// one, never a partial extraction.
+pub fn install_cache(
+    pack_path: &Path,
+    cache_dir: &Path,
+    footer: &PackFooter,
+    selection: Option<&AssetSelection>,
+    debug: bool,
+) -> std::io::Result<()> {
+    let _lock = lock_cache_dir(cache_dir)?;
+
+    let staging = cache_dir.with_extension("new");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
     }
 
-    // If force-extracting over an existing cache, remove it first so we
-    // get a clean slate.
-    if force && cache_dir.exists() {
-        let _ = fs::remove_dir_all(cache_dir);
+    let index = read_asset_index(pack_path, footer)?;
+    if index.is_empty() {
+        extract_stream(pack_path, &staging, footer, selection, debug)?;
+    } else {
+        let source = AssetSource::File(pack_path, footer.assets_offset);
+        let layers = extract_runtime(
+            source,
+            &staging,
+            &index,
+            footer.assets_size,
+            selection,
+            debug,
+        )?;
+        extract_layers(source, &staging, &layers, debug)?;
+    }
+
+    if cache_dir.exists() {
+        let old = cache_dir.with_extension("old");
+        if old.exists() {
+            fs::remove_dir_all(&old)?;
+        }
+        fs::rename(cache_dir, &old)?;
+        fs::rename(&staging, cache_dir)?;
+        fs::remove_dir_all(&old)?;
+    } else {
+        fs::rename(&staging, cache_dir)?;
     }
 
-    extract_sidecar_inner(sidecar_path, cache_dir, footer, debug)
-    // Lock released on drop of lock_file
+    if debug {
+        eprintln!("debug: installed assets to {}", cache_dir.display());
+    }
+    Ok(())
 }
 
-/// Inner extraction logic (called under the lock).
-fn extract_sidecar_inner(
-    sidecar_path: &Path,
+/// Extract a pack without an asset index by decompressing the whole
+/// assets blob (called under the lock).
+fn extract_stream(
+    pack_path: &Path,
     cache_dir: &Path,
     footer: &PackFooter,
+    selection: Option<&AssetSelection>,
     debug: bool,
 ) -> std::io::Result<()> {
     fs::create_dir_all(cache_dir)?;
 
     if debug {
         eprintln!(
-            "debug: reading {} bytes of compressed assets from sidecar {}",
+            "debug: reading {} bytes of compressed assets from {} at offset {}",
             footer.assets_size,
-            sidecar_path.display()
+            pack_path.display(),
+            footer.assets_offset
         );
     }
 
-    let sidecar_file = File::open(sidecar_path)?;
-    let limited_reader = sidecar_file.take(footer.assets_size);
+    let mut file = File::open(pack_path)?;
+    file.seek(SeekFrom::Start(footer.assets_offset))?;
+    let limited_reader = file.take(footer.assets_size);
 
     let decoder = zstd::stream::Decoder::new(limited_reader)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
     let mut archive = tar::Archive::new(decoder);
-    safe_unpack(&mut archive, cache_dir)?;
+    safe_unpack(&mut archive, cache_dir, selection)?;
 
     if debug {
         eprintln!("debug: extracted assets to {}", cache_dir.display());
@@ -253,49 +498,29 @@ fn extract_sidecar_inner(
 /// Extract assets from a packed binary to the cache directory.
 ///
 /// Supports both sidecar mode (assets_offset == 0) and embedded mode.
-/// This is used by the stub executable.
+/// This is used by the stub executable. As with [`extract_sidecar`], the
+/// layers of an indexed pack are returned for the caller to extract.
 pub fn extract_from_binary(
     exe_path: &Path,
     cache_dir: &Path,
     footer: &PackFooter,
+    selection: Option<&AssetSelection>,
+    force: bool,
     debug: bool,
-) -> std::io::Result<()> {
-    fs::create_dir_all(cache_dir)?;
-
+) -> std::io::Result<Option<LayerExtraction>> {
     if is_sidecar_mode(footer) {
         let sidecar = sidecar_path_for(exe_path);
-        extract_sidecar(&sidecar, cache_dir, footer, false, debug)
+        extract_sidecar(&sidecar, cache_dir, footer, selection, force, debug)
     } else {
-        // Embedded mode: read compressed assets from the executable
-        let mut exe_file = File::open(exe_path)?;
-        exe_file.seek(SeekFrom::Start(footer.assets_offset))?;
-
-        if debug {
-            eprintln!(
-                "debug: reading {} bytes of compressed assets from offset {}",
-                footer.assets_size, footer.assets_offset
-            );
-        }
-
-        let limited_reader = (&mut exe_file).take(footer.assets_size);
-
-        let decoder = zstd::stream::Decoder::new(limited_reader)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-        let mut archive = tar::Archive::new(decoder);
-        safe_unpack(&mut archive, cache_dir)?;
-
-        if debug {
-            eprintln!("debug: extracted assets to {}", cache_dir.display());
-        }
-
-        post_process_extraction(cache_dir, debug)?;
-        Ok(())
+        extract_file(exe_path, cache_dir, footer, selection, force, debug)
     }
 }
 
 /// Extract assets from a memory pointer (for Mach-O section mode on macOS).
 ///
+/// `index` is the manifest's asset index; with one, assets are
+/// decompressed in parallel. Layers are extracted before returning.
+///
 /// # Safety
 ///
 /// `assets_ptr` must point to a valid, readable memory region of at least
@@ -305,10 +530,10 @@ pub unsafe fn extract_from_section(
     cache_dir: &Path,
     assets_ptr: *const u8,
     assets_size: usize,
+    index: &[AssetFrame],
+    selection: Option<&AssetSelection>,
     debug: bool,
 ) -> std::io::Result<()> {
-    fs::create_dir_all(cache_dir)?;
-
     if debug {
         eprintln!(
             "debug: extracting {} bytes of compressed assets from section",
@@ -317,13 +542,27 @@ pub unsafe fn extract_from_section(
     }
 
     let assets_slice = unsafe { std::slice::from_raw_parts(assets_ptr, assets_size) };
+    if !index.is_empty() {
+        let source = AssetSource::Memory(assets_slice);
+        let layers = extract_runtime(
+            source,
+            cache_dir,
+            index,
+            assets_size as u64,
+            selection,
+            debug,
+        )?;
+        return extract_layers(source, cache_dir, &layers, debug);
+    }
+
+    fs::create_dir_all(cache_dir)?;
     let cursor = std::io::Cursor::new(assets_slice);
 
     let decoder = zstd::stream::Decoder::new(cursor)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
     let mut archive = tar::Archive::new(decoder);
-    safe_unpack(&mut archive, cache_dir)?;
+    safe_unpack(&mut archive, cache_dir, selection)?;
 
     if debug {
         eprintln!("debug: extracted assets to {}", cache_dir.display());
@@ -333,6 +572,283 @@ pub unsafe fn extract_from_section(
     Ok(())
 }
 
+/// Marker file indicating the libraries and agent rootfs of an indexed
+/// pack are extracted; the layers may not be.
+const RUNTIME_MARKER: &str = ".smolvm-runtime";
+
+/// OCI layers of an indexed pack still to be extracted, returned once the
+/// libraries and agent rootfs are in place.
+///
+/// Holds the cache lock until the layers are done, so other processes
+/// wait for a complete cache. The `layers/` directory already exists, so
+/// a VM can be started with it while the layers are filled in.
+#[must_use = "layers are only extracted by run() or spawn()"]
+pub struct LayerExtraction {
+    pack_path: PathBuf,
+    assets_offset: u64,
+    cache_dir: PathBuf,
+    layers: Vec<PlannedFrame>,
+    _lock: File,
+    debug: bool,
+}
+
+impl LayerExtraction {
+    /// Extract the layers now, decompressing them in parallel.
+    pub fn run(self) -> std::io::Result<()> {
+        let source = AssetSource::File(&self.pack_path, self.assets_offset);
+        extract_layers(source, &self.cache_dir, &self.layers, self.debug)
+    }
+
+    /// Extract the layers on a background thread.
+    ///
+    /// Fork any child processes before calling this: a process forked
+    /// while the extraction threads run may inherit locks they hold.
+    pub fn spawn(self) -> PendingLayers {
+        PendingLayers(std::thread::spawn(move || self.run()))
+    }
+}
+
+/// Layers being extracted in the background, see [`LayerExtraction::spawn`].
+pub struct PendingLayers(std::thread::JoinHandle<std::io::Result<()>>);
+
+impl PendingLayers {
+    /// Wait for the layers to be extracted.
+    pub fn wait(self) -> std::io::Result<()> {
+        self.0
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("layer extraction panicked")))
+    }
+}
+
+/// Where the assets blob of an indexed pack is read from.
+#[derive(Clone, Copy)]
+enum AssetSource<'a> {
+    /// A file, with the blob at the given offset.
+    File(&'a Path, u64),
+    /// The blob in memory.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    Memory(&'a [u8]),
+}
+
+impl AssetSource<'_> {
+    /// Reader over one compressed frame.
+    fn frame(&self, frame: &AssetFrame) -> std::io::Result<Box<dyn Read + '_>> {
+        match *self {
+            AssetSource::File(path, offset) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset + frame.offset))?;
+                Ok(Box::new(file.take(frame.size)))
+            }
+            AssetSource::Memory(data) => {
+                let start = frame.offset as usize;
+                Ok(Box::new(&data[start..start + frame.size as usize]))
+            }
+        }
+    }
+}
+
+/// An asset frame and where it is extracted to, relative to the cache
+/// directory.
+struct PlannedFrame {
+    frame: AssetFrame,
+    target: PathBuf,
+}
+
+/// Read the asset index from the manifest of a sidecar or embedded binary.
+fn read_asset_index(pack_path: &Path, footer: &PackFooter) -> std::io::Result<Vec<AssetFrame>> {
+    let mut file = File::open(pack_path)?;
+    file.seek(SeekFrom::Start(footer.manifest_offset))?;
+    let mut json = Vec::new();
+    file.take(footer.manifest_size).read_to_end(&mut json)?;
+    let manifest = PackManifest::from_json(&json)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(manifest.asset_index)
+}
+
+/// Extract everything but the layers of an indexed pack, in parallel, and
+/// prepare an empty `layers/` directory. Returns the layers to extract.
+///
+/// Skipped if an earlier run already got this far; its layers, which may
+/// be incomplete, are discarded.
+fn extract_runtime(
+    source: AssetSource<'_>,
+    cache_dir: &Path,
+    index: &[AssetFrame],
+    assets_size: u64,
+    selection: Option<&AssetSelection>,
+    debug: bool,
+) -> std::io::Result<Vec<PlannedFrame>> {
+    let mut runtime = Vec::new();
+    let mut layers = Vec::new();
+    for frame in index {
+        let in_bounds = frame
+            .offset
+            .checked_add(frame.size)
+            .is_some_and(|end| end <= assets_size);
+        let path = Path::new(&frame.path);
+        if !in_bounds || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid asset index entry for {}", frame.path),
+            ));
+        }
+        let target = match selection {
+            Some(selection) => match selection.target(path) {
+                Some(target) => target,
+                None => continue,
+            },
+            None => path.to_path_buf(),
+        };
+        let planned = PlannedFrame {
+            frame: frame.clone(),
+            target,
+        };
+        if planned.target.starts_with("layers") {
+            layers.push(planned);
+        } else {
+            runtime.push(planned);
+        }
+    }
+    // Start the biggest layers first so they don't finish last alone
+    layers.sort_by_key(|planned| std::cmp::Reverse(planned.frame.size));
+
+    fs::create_dir_all(cache_dir)?;
+    if !cache_dir.join(RUNTIME_MARKER).exists() {
+        if debug {
+            eprintln!(
+                "debug: extracting {} runtime assets in parallel",
+                runtime.len()
+            );
+        }
+        for_each_parallel(&runtime, |planned| unpack_frame(source, planned, cache_dir))?;
+        make_libraries_executable(cache_dir)?;
+        fs::write(cache_dir.join(RUNTIME_MARKER), "")?;
+    }
+
+    let layers_dir = cache_dir.join("layers");
+    if layers_dir.exists() {
+        fs::remove_dir_all(&layers_dir)?;
+    }
+    fs::create_dir_all(&layers_dir)?;
+    Ok(layers)
+}
+
+/// Extract the layers of an indexed pack in parallel and mark the cache
+/// complete.
+fn extract_layers(
+    source: AssetSource<'_>,
+    cache_dir: &Path,
+    layers: &[PlannedFrame],
+    debug: bool,
+) -> std::io::Result<()> {
+    if debug {
+        eprintln!("debug: extracting {} layers in parallel", layers.len());
+    }
+    for_each_parallel(layers, |planned| unpack_frame(source, planned, cache_dir))?;
+    fs::write(cache_dir.join(EXTRACTION_MARKER), "")?;
+    if debug {
+        eprintln!("debug: extracted assets to {}", cache_dir.display());
+    }
+    Ok(())
+}
+
+/// Decompress one asset frame into the cache.
+///
+/// The agent rootfs and layer tarballs are only used unpacked, so they are
+/// streamed straight into their directories rather than stored.
+fn unpack_frame(
+    source: AssetSource<'_>,
+    planned: &PlannedFrame,
+    cache_dir: &Path,
+) -> std::io::Result<()> {
+    let decoder = zstd::stream::Decoder::new(source.frame(&planned.frame)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive.entries()?;
+    let mut entry = match entries.next() {
+        Some(entry) => entry?,
+        None => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("asset frame for {} is empty", planned.frame.path),
+            ))
+        }
+    };
+    if entry.header().entry_type() != tar::EntryType::Regular {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("asset {} is not a regular file", planned.frame.path),
+        ));
+    }
+
+    let target = &planned.target;
+    let unpacked_dir = if target == Path::new("agent-rootfs.tar") {
+        Some(cache_dir.join("agent-rootfs"))
+    } else if target.starts_with("layers") && target.extension().is_some_and(|e| e == "tar") {
+        Some(cache_dir.join(target.with_extension("")))
+    } else {
+        None
+    };
+
+    match unpacked_dir {
+        Some(dir) => {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            fs::create_dir_all(&dir)?;
+            safe_unpack(&mut tar::Archive::new(&mut entry), &dir, None)
+        }
+        None => {
+            let dest = cache_dir.join(target);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&dest).map(|_| ())
+        }
+    }
+}
+
+/// Run `f` on each item using up to one thread per CPU. Stops handing out
+/// items after the first error, which is returned.
+pub(crate) fn for_each_parallel<T: Sync, E: Send>(
+    items: &[T],
+    f: impl Fn(&T) -> Result<(), E> + Sync,
+) -> Result<(), E> {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+    if workers <= 1 {
+        return items.iter().try_for_each(f);
+    }
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        if let Err(e) = f(item) {
+                            failed.store(true, Ordering::Relaxed);
+                            return Err(e);
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        // The scope joins any workers left after an error
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("worker thread panicked"))
+    })
+}
+
 /// Post-process extracted assets: unpack agent rootfs, OCI layers, fix permissions.
 fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()> {
     // Extract agent-rootfs.tar to agent-rootfs directory
@@ -345,7 +861,7 @@ fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()>
         fs::create_dir_all(&rootfs_dir)?;
         let tar_file = File::open(&rootfs_tar)?;
         let mut archive = tar::Archive::new(tar_file);
-        safe_unpack(&mut archive, &rootfs_dir)?;
+        safe_unpack(&mut archive, &rootfs_dir, None)?;
     }
 
     // Extract OCI layer tars to layers/{digest}/ directories
@@ -367,7 +883,7 @@ fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()>
                     fs::create_dir_all(&layer_dir)?;
                     let tar_file = File::open(&path)?;
                     let mut archive = tar::Archive::new(tar_file);
-                    safe_unpack(&mut archive, &layer_dir)?;
+                    safe_unpack(&mut archive, &layer_dir, None)?;
                 }
             }
         }
@@ -376,7 +892,11 @@ fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()>
     // Write marker file
     fs::write(cache_dir.join(EXTRACTION_MARKER), "")?;
 
-    // Make libraries executable (they need to be loadable)
+    make_libraries_executable(cache_dir)
+}
+
+/// Make libraries executable (they need to be loadable).
+fn make_libraries_executable(cache_dir: &Path) -> std::io::Result<()> {
     let lib_dir = cache_dir.join("lib");
     if lib_dir.exists() {
         #[cfg(unix)]
@@ -423,7 +943,7 @@ pub fn cleanup_old_caches(keep: usize) -> std::io::Result<()> {
         }
     }
 
-    entries.sort_by(|a, b| b.1.cmp(&a.1));
+    entries.sort_by_key(|e| std::cmp::Reverse(e.1));
 
     for (path, _) in entries.into_iter().skip(keep) {
         let _ = fs::remove_dir_all(path);
@@ -540,6 +1060,25 @@ mod tests {
         assert!(is_extracted(temp_dir.path()));
     }
 
+    #[test]
+    fn test_cached_assets_sha256() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pack = temp_dir.path().join("app.smolmachine");
+        let cache_dir = temp_dir.path().join("cache");
+        fs::write(&pack, "assets").unwrap();
+
+        let digest = cached_assets_sha256(&cache_dir, &pack, || Ok([7; 32])).unwrap();
+        assert_eq!(digest, [7; 32]);
+        let cached =
+            cached_assets_sha256(&cache_dir, &pack, || panic!("digest not cached")).unwrap();
+        assert_eq!(cached, [7; 32]);
+
+        // A rewritten pack is hashed again
+        fs::write(&pack, "other assets").unwrap();
+        let digest = cached_assets_sha256(&cache_dir, &pack, || Ok([9; 32])).unwrap();
+        assert_eq!(digest, [9; 32]);
+    }
+
     #[test]
     fn test_is_extracted_partial() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -567,6 +1106,7 @@ mod tests {
             manifest_offset: 1000,
             manifest_size: 500,
             checksum: 0x12345678,
+            signature_size: 0,
         };
         assert!(is_sidecar_mode(&sidecar_footer));
 
@@ -577,6 +1117,7 @@ mod tests {
             manifest_offset: 51000,
             manifest_size: 500,
             checksum: 0x12345678,
+            signature_size: 0,
         };
         assert!(!is_sidecar_mode(&embedded_footer));
     }
@@ -633,6 +1174,98 @@ mod tests {
         assert!(dest2.exists());
     }
 
+    #[test]
+    fn test_extract_sidecar_selects_platform() {
+        use crate::assets::AssetCollector;
+        use crate::packer::{read_footer_from_sidecar, sidecar_path_for, Packer};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+        let (libkrun, libkrunfw) = if cfg!(target_os = "macos") {
+            ("libkrun.dylib", "libkrunfw.5.dylib")
+        } else {
+            ("libkrun.so", "libkrunfw.so.5")
+        };
+
+        let layer = |name: &str| {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &[][..]).unwrap();
+            builder.into_inner().unwrap()
+        };
+
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        for (platform, arch) in [(None, "arm64"), (Some("linux/amd64"), "amd64")] {
+            let lib_dir = temp_dir.path().join(format!("lib-{}", arch));
+            fs::create_dir_all(&lib_dir).unwrap();
+            fs::write(lib_dir.join(libkrun), arch).unwrap();
+            fs::write(lib_dir.join(libkrunfw), arch).unwrap();
+            if let Some(platform) = platform {
+                collector.begin_platform(platform).unwrap();
+            }
+            collector.collect_libraries(&lib_dir).unwrap();
+            collector
+                .add_layer("sha256:shared1234567", &layer("shared"))
+                .unwrap();
+            collector
+                .add_layer(&format!("sha256:{}only123456", arch), &layer(arch))
+                .unwrap();
+        }
+
+        let manifest = PackManifest::new(
+            "test:multi".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+        let output = temp_dir.path().join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&output)
+            .unwrap();
+
+        let sidecar = sidecar_path_for(&output);
+        let footer = read_footer_from_sidecar(&sidecar).unwrap();
+        let manifest = crate::packer::read_manifest_from_sidecar(&sidecar).unwrap();
+        assert_eq!(
+            manifest.platform_names(),
+            vec!["linux/arm64", "linux/amd64"]
+        );
+        assert!(AssetSelection::for_platform(&manifest, "linux/riscv64").is_err());
+
+        let selection = AssetSelection::for_platform(&manifest, "linux/amd64")
+            .unwrap()
+            .unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        extract_sidecar(
+            &sidecar,
+            &cache_dir,
+            &footer,
+            Some(&selection),
+            false,
+            false,
+        )
+        .unwrap()
+        .expect("packs are indexed")
+        .run()
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(cache_dir.join("lib").join(libkrun)).unwrap(),
+            "amd64"
+        );
+        assert!(!cache_dir.join("platforms").exists());
+        assert!(cache_dir.join("layers/shared123456/shared").exists());
+        assert!(cache_dir.join("layers/amd64only123/amd64").exists());
+        assert!(!cache_dir.join("layers/arm64only123.tar").exists());
+        assert!(!cache_dir.join("layers/amd64only123.tar").exists());
+        assert!(is_extracted(&cache_dir));
+    }
+
     #[test]
     fn test_extract_sidecar_skips_when_already_extracted() {
         // Verifies the double-check pattern inside the lock:
@@ -651,6 +1284,7 @@ mod tests {
             manifest_offset: 0,
             manifest_size: 0,
             checksum: 0,
+            signature_size: 0,
         };
 
         // Should succeed without trying to open a nonexistent sidecar,
@@ -659,6 +1293,7 @@ mod tests {
             Path::new("/nonexistent/sidecar.smolmachine"),
             &cache_dir,
             &dummy_footer,
+            None,
             false, // force=false
             false,
         );
@@ -676,6 +1311,7 @@ mod tests {
             &dummy_sidecar,
             &cache_dir,
             &dummy_footer,
+            None,
             false, // force=false
             false,
         );
@@ -707,12 +1343,14 @@ mod tests {
             manifest_offset: 22,
             manifest_size: 0,
             checksum: 0,
+            signature_size: 0,
         };
 
         let result = extract_sidecar(
             &dummy_sidecar,
             &cache_dir,
             &dummy_footer,
+            None,
             true, // force=true should bypass marker
             false,
         );
@@ -724,4 +1362,169 @@ mod tests {
             "force extraction should attempt (and fail on dummy data)"
         );
     }
+
+    #[test]
+    fn test_install_cache_replaces_existing() {
+        use crate::assets::AssetCollector;
+        use crate::packer::{read_footer_from_sidecar, sidecar_path_for, Packer};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "app", &b"hello"[..])
+            .unwrap();
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        collector
+            .add_layer("sha256:install12345678", &builder.into_inner().unwrap())
+            .unwrap();
+
+        let manifest = PackManifest::new(
+            "test:install".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+        let output = temp_dir.path().join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&output)
+            .unwrap();
+        let sidecar = sidecar_path_for(&output);
+        let footer = read_footer_from_sidecar(&sidecar).unwrap();
+
+        // A stale extraction is swapped out whole
+        let cache_dir = temp_dir.path().join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::write(cache_dir.join("stale"), b"").unwrap();
+
+        install_cache(&sidecar, &cache_dir, &footer, None, false).unwrap();
+        assert!(is_extracted(&cache_dir));
+        assert!(!cache_dir.join("stale").exists());
+        assert_eq!(
+            fs::read(cache_dir.join("layers/install12345/app")).unwrap(),
+            b"hello"
+        );
+        assert!(!cache_dir.with_extension("new").exists());
+        assert!(!cache_dir.with_extension("old").exists());
+    }
+
+    #[test]
+    fn test_extract_sidecar_defers_layers() {
+        use crate::assets::AssetCollector;
+        use crate::packer::{read_footer_from_sidecar, sidecar_path_for, Packer};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+        let lib_dir = temp_dir.path().join("libs");
+        fs::create_dir_all(&lib_dir).unwrap();
+        let (libkrun, libkrunfw) = if cfg!(target_os = "macos") {
+            ("libkrun.dylib", "libkrunfw.5.dylib")
+        } else {
+            ("libkrun.so", "libkrunfw.so.5")
+        };
+        fs::write(lib_dir.join(libkrun), b"krun").unwrap();
+        fs::write(lib_dir.join(libkrunfw), b"krunfw").unwrap();
+
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        collector.collect_libraries(&lib_dir).unwrap();
+        for (digest, name) in [
+            ("sha256:lazyone123456", "one"),
+            ("sha256:lazytwo123456", "two"),
+        ] {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, name.as_bytes())
+                .unwrap();
+            collector
+                .add_layer(digest, &builder.into_inner().unwrap())
+                .unwrap();
+        }
+
+        let manifest = PackManifest::new(
+            "test:lazy".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+        let output = temp_dir.path().join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&output)
+            .unwrap();
+        let sidecar = sidecar_path_for(&output);
+        let footer = read_footer_from_sidecar(&sidecar).unwrap();
+
+        let cache_dir = temp_dir.path().join("cache");
+        let layers = extract_sidecar(&sidecar, &cache_dir, &footer, None, false, false)
+            .unwrap()
+            .expect("packs are indexed");
+
+        // Libraries are ready, layers are not
+        assert_eq!(
+            fs::read(cache_dir.join("lib").join(libkrun)).unwrap(),
+            b"krun"
+        );
+        assert!(cache_dir.join(RUNTIME_MARKER).exists());
+        assert!(!is_extracted(&cache_dir));
+        assert_eq!(fs::read_dir(cache_dir.join("layers")).unwrap().count(), 0);
+
+        layers.spawn().wait().unwrap();
+        assert!(is_extracted(&cache_dir));
+        assert_eq!(
+            fs::read(cache_dir.join("layers/lazyone12345/one")).unwrap(),
+            b"one"
+        );
+        assert_eq!(
+            fs::read(cache_dir.join("layers/lazytwo12345/two")).unwrap(),
+            b"two"
+        );
+        assert!(!cache_dir.join("layers/lazyone12345.tar").exists());
+
+        // An interrupted run redoes only the layers
+        fs::remove_file(cache_dir.join(EXTRACTION_MARKER)).unwrap();
+        fs::write(cache_dir.join("lib").join(libkrun), b"kept").unwrap();
+        extract_sidecar(&sidecar, &cache_dir, &footer, None, false, false)
+            .unwrap()
+            .expect("layers are still pending")
+            .run()
+            .unwrap();
+        assert_eq!(
+            fs::read(cache_dir.join("lib").join(libkrun)).unwrap(),
+            b"kept"
+        );
+        assert!(cache_dir.join("layers/lazytwo12345/two").exists());
+        assert!(
+            extract_sidecar(&sidecar, &cache_dir, &footer, None, false, false)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_for_each_parallel_stops_on_error() {
+        let items: Vec<u32> = (0..64).collect();
+        let seen = std::sync::atomic::AtomicUsize::new(0);
+        let result = for_each_parallel(&items, |&item| {
+            seen.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if item == 3 {
+                Err(item)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err(3));
+        assert!(for_each_parallel(&items, |_| Ok::<(), ()>(())).is_ok());
+    }
 }
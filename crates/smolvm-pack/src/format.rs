@@ -3,6 +3,8 @@
 //! This module defines the footer and manifest structures that describe
 //! the contents of a packed smolvm executable.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{PackError, Result};
@@ -40,12 +42,13 @@ pub const SECTION_HEADER_SIZE: usize = 32;
 /// 12      4     manifest_size (u32 LE)
 /// 16      8     assets_size (u64 LE)
 /// 24      4     checksum (u32 LE)
-/// 28      4     reserved (zeroes)
+/// 28      4     signature_size (u32 LE)
 /// ```
 ///
 /// Following the header:
 /// - Manifest JSON (manifest_size bytes)
 /// - Compressed assets (assets_size bytes)
+/// - Signature block JSON (signature_size bytes, absent when zero)
 #[derive(Debug, Clone, Copy)]
 pub struct SectionHeader {
     /// Size of manifest JSON in bytes.
@@ -54,6 +57,8 @@ pub struct SectionHeader {
     pub assets_size: u64,
     /// CRC32 checksum of manifest + assets.
     pub checksum: u32,
+    /// Size of the signature block in bytes (0 = unsigned).
+    pub signature_size: u32,
 }
 
 impl SectionHeader {
@@ -76,7 +81,8 @@ impl SectionHeader {
         // Checksum
         buf[24..28].copy_from_slice(&self.checksum.to_le_bytes());
 
-        // Reserved (already zeroed)
+        // Signature size
+        buf[28..32].copy_from_slice(&self.signature_size.to_le_bytes());
 
         buf
     }
@@ -104,6 +110,7 @@ impl SectionHeader {
                 buf[16], buf[17], buf[18], buf[19], buf[20], buf[21], buf[22], buf[23],
             ]),
             checksum: u32::from_le_bytes([buf[24], buf[25], buf[26], buf[27]]),
+            signature_size: u32::from_le_bytes([buf[28], buf[29], buf[30], buf[31]]),
         })
     }
 }
@@ -121,8 +128,13 @@ impl SectionHeader {
 /// 36      8     manifest_offset (u64 LE) - offset to manifest JSON
 /// 44      8     manifest_size (u64 LE) - size of manifest JSON
 /// 52      4     checksum (u32 LE) - CRC32 of assets + manifest
-/// 56      8     reserved (zeroes)
+/// 56      4     signature_size (u32 LE) - size of signature block JSON
+/// 60      4     reserved (zeroes)
 /// ```
+///
+/// A signature block, when present, sits between the manifest and the
+/// footer. Packs written before signing existed have zeroes there, which
+/// reads as unsigned.
 #[derive(Debug, Clone, Copy)]
 pub struct PackFooter {
     /// Size of the stub executable.
@@ -137,6 +149,8 @@ pub struct PackFooter {
     pub manifest_size: u64,
     /// CRC32 checksum of assets + manifest.
     pub checksum: u32,
+    /// Size of the signature block in bytes (0 = unsigned).
+    pub signature_size: u32,
 }
 
 impl PackFooter {
@@ -164,6 +178,9 @@ impl PackFooter {
         // Checksum
         buf[52..56].copy_from_slice(&self.checksum.to_le_bytes());
 
+        // Signature size
+        buf[56..60].copy_from_slice(&self.signature_size.to_le_bytes());
+
         // Reserved (already zeroed)
 
         buf
@@ -199,6 +216,7 @@ impl PackFooter {
                 buf[44], buf[45], buf[46], buf[47], buf[48], buf[49], buf[50], buf[51],
             ]),
             checksum: u32::from_le_bytes([buf[52], buf[53], buf[54], buf[55]]),
+            signature_size: u32::from_le_bytes([buf[56], buf[57], buf[58], buf[59]]),
         })
     }
 }
@@ -225,6 +243,14 @@ pub struct PackManifest {
     #[serde(default)]
     pub mode: PackMode,
 
+    /// Pack name, from the pack configuration file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Pack version, from the pack configuration file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
     /// Original image reference (e.g., "alpine:latest").
     pub image: String,
 
@@ -256,8 +282,97 @@ pub struct PackManifest {
     /// Default memory in MiB.
     pub mem: u32,
 
+    /// Further run defaults from the pack configuration file.
+    #[serde(default, skip_serializing_if = "RunDefaults::is_empty")]
+    pub defaults: RunDefaults,
+
+    /// How the pack was made. Absent in packs made before provenance was
+    /// recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+
     /// Asset inventory - files included in the assets blob.
     pub assets: AssetInventory,
+
+    /// Inventories for additional platforms of a multi-platform pack,
+    /// keyed by OS/arch (e.g. "linux/amd64"). `platform` and `assets`
+    /// describe the primary platform and are not repeated here.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platforms: BTreeMap<String, AssetInventory>,
+
+    /// Where each asset's zstd frame sits in the assets blob, so assets
+    /// can be extracted individually. Empty for packs that compressed all
+    /// assets as one stream.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub asset_index: Vec<AssetFrame>,
+}
+
+/// Run settings baked into a pack, used unless the packed binary's flags
+/// say otherwise. Volumes and ports given on the command line are added to
+/// these; the other flags replace them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunDefaults {
+    /// Volume mounts (`HOST:GUEST[:ro]`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+
+    /// Published ports (`[IP:]HOST:GUEST[/udp]`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+
+    /// Enable outbound network access.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub net: bool,
+
+    /// Egress allowlist rules (`github.com`, `*.npmjs.org`,
+    /// `10.0.0.0/8:443`). When set, the VM only reaches these, through the
+    /// host-side proxy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub net_allow: Vec<String>,
+
+    /// HTTP(S) proxy settings, with CA certificates embedded as PEM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<smolvm_protocol::ProxyConfig>,
+
+    /// DNS settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<smolvm_protocol::DnsConfig>,
+
+    /// Shell commands run in the VM after it boots, before the command.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub init: Vec<String>,
+
+    /// Storage disk size in GiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<u64>,
+
+    /// Overlay disk size in GiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<u64>,
+}
+
+impl RunDefaults {
+    /// Whether no defaults are set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Build provenance recorded in the manifest, so it is covered by the
+/// pack's signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Version of smolvm that made the pack.
+    pub smolvm_version: String,
+
+    /// When the pack was made (RFC 3339). Reproducible packs record
+    /// `SOURCE_DATE_EPOCH` instead of the current time.
+    pub created: String,
+
+    /// Digest of the image packed for each platform, keyed by OS/arch.
+    /// Empty for VM snapshots.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, String>,
 }
 
 /// Inventory of assets included in the packed binary.
@@ -281,9 +396,62 @@ pub struct AssetInventory {
     /// Contains the VM's persistent rootfs state from a `--from-vm` pack.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub overlay_template: Option<AssetEntry>,
+
+    /// CycloneDX SBOM of the packages found in the layers (optional).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<AssetEntry>,
 }
 
-/// An asset file entry.
+impl AssetInventory {
+    /// Archive path and recorded SHA-256 of every file in the inventory.
+    pub fn files(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.libraries
+            .iter()
+            .chain(std::iter::once(&self.agent_rootfs))
+            .chain(self.storage_template.iter())
+            .chain(self.overlay_template.iter())
+            .chain(self.sbom.iter())
+            .map(|a| (a.path.as_str(), a.sha256.as_deref()))
+            .chain(
+                self.layers
+                    .iter()
+                    .map(|l| (l.path.as_str(), l.sha256.as_deref())),
+            )
+    }
+}
+
+/// Directory within the assets archive holding the libraries and agent
+/// rootfs of a non-primary platform (e.g. `platforms/linux-amd64`).
+pub fn platform_asset_dir(platform: &str) -> String {
+    format!("platforms/{}", platform.replace('/', "-"))
+}
+
+/// Parse a comma-separated list of OCI platforms (`linux/amd64,linux/arm64`).
+///
+/// Each must be `os/arch` with an optional `/variant`; duplicates are an
+/// error.
+pub fn parse_platforms(spec: &str) -> Result<Vec<String>> {
+    let mut platforms: Vec<String> = Vec::new();
+    for platform in spec.split(',').map(str::trim) {
+        let parts: Vec<&str> = platform.split('/').collect();
+        if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.is_empty()) {
+            return Err(PackError::InvalidPlatform(format!(
+                "'{}' (expected OS/ARCH, e.g. linux/arm64)",
+                platform
+            )));
+        }
+        if platforms.iter().any(|p| p == platform) {
+            return Err(PackError::InvalidPlatform(format!(
+                "'{}' listed twice",
+                platform
+            )));
+        }
+        platforms.push(platform.to_string());
+    }
+    Ok(platforms)
+}
+
+/// An asset file entry./// An asset file entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetEntry {
     /// Path within the assets archive.
@@ -291,6 +459,11 @@ pub struct AssetEntry {
 
     /// Uncompressed size in bytes.
     pub size: u64,
+
+    /// SHA-256 of the file (hex). Absent in packs made before digests
+    /// were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 /// An OCI layer entry.
@@ -304,6 +477,26 @@ pub struct LayerEntry {
 
     /// Uncompressed size in bytes.
     pub size: u64,
+
+    /// SHA-256 of the layer tarball as stored (hex).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// One asset's zstd frame within the assets blob.
+///
+/// The frame decompresses to a tar holding just that asset, without an
+/// end-of-archive marker, so the whole blob still reads as one tar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetFrame {
+    /// Path within the assets archive.
+    pub path: String,
+
+    /// Offset of the frame from the start of the assets blob.
+    pub offset: u64,
+
+    /// Compressed size of the frame.
+    pub size: u64,
 }
 
 impl PackManifest {
@@ -311,6 +504,8 @@ impl PackManifest {
     pub fn new(image: String, digest: String, platform: String) -> Self {
         Self {
             mode: PackMode::default(),
+            name: None,
+            version: None,
             image,
             digest,
             platform,
@@ -320,19 +515,61 @@ impl PackManifest {
             workdir: None,
             cpus: 1,
             mem: 256,
+            defaults: RunDefaults::default(),
+            provenance: None,
             assets: AssetInventory {
                 libraries: Vec::new(),
                 agent_rootfs: AssetEntry {
                     path: "agent-rootfs.tar".to_string(),
                     size: 0,
+                    sha256: None,
                 },
                 layers: Vec::new(),
                 storage_template: None,
                 overlay_template: None,
+                sbom: None,
             },
+            platforms: BTreeMap::new(),
+            asset_index: Vec::new(),
+        }
+    }
+
+    /// Whether the pack carries assets for more than one platform.
+    pub fn is_multi_platform(&self) -> bool {
+        !self.platforms.is_empty()
+    }
+
+    /// All platforms in the pack, primary first.
+    pub fn platform_names(&self) -> Vec<&str> {
+        std::iter::once(self.platform.as_str())
+            .chain(self.platforms.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// Asset inventory for a platform, if the pack has one.
+    pub fn inventory_for(&self, platform: &str) -> Option<&AssetInventory> {
+        if platform == self.platform {
+            Some(&self.assets)
+        } else {
+            self.platforms.get(platform)
         }
     }
 
+    /// Make `platform` the primary platform, so that `platform` and
+    /// `assets` describe it. Returns false if the pack has no assets for it.
+    pub fn select_platform(&mut self, platform: &str) -> bool {
+        if platform == self.platform {
+            return true;
+        }
+        let Some(assets) = self.platforms.remove(platform) else {
+            return false;
+        };
+        let previous = std::mem::replace(&mut self.assets, assets);
+        let previous_platform = std::mem::replace(&mut self.platform, platform.to_string());
+        self.platforms.insert(previous_platform, previous);
+        true
+    }
+
     /// Serialize manifest to JSON.
     pub fn to_json(&self) -> Result<Vec<u8>> {
         Ok(serde_json::to_vec_pretty(self)?)
@@ -357,6 +594,7 @@ mod tests {
             manifest_offset: 512 * 1024 + 50 * 1024 * 1024,
             manifest_size: 2048,
             checksum: 0xDEADBEEF,
+            signature_size: 120,
         };
 
         let bytes = footer.to_bytes();
@@ -369,6 +607,7 @@ mod tests {
         assert_eq!(restored.manifest_offset, footer.manifest_offset);
         assert_eq!(restored.manifest_size, footer.manifest_size);
         assert_eq!(restored.checksum, footer.checksum);
+        assert_eq!(restored.signature_size, footer.signature_size);
     }
 
     #[test]
@@ -404,6 +643,7 @@ mod tests {
         manifest.assets.libraries.push(AssetEntry {
             path: "lib/libkrun.dylib".to_string(),
             size: 4 * 1024 * 1024,
+            sha256: Some("ab".repeat(32)),
         });
 
         let json = manifest.to_json().unwrap();
@@ -415,6 +655,7 @@ mod tests {
         assert_eq!(restored.mem, 1024);
         assert_eq!(restored.entrypoint, vec!["/bin/sh"]);
         assert_eq!(restored.assets.libraries.len(), 1);
+        assert_eq!(restored.assets.libraries[0].sha256, Some("ab".repeat(32)));
     }
 
     #[test]
@@ -430,6 +671,56 @@ mod tests {
         assert!(json.contains("\"platform\": \"linux/amd64\""));
     }
 
+    #[test]
+    fn test_manifest_select_platform() {
+        let mut manifest = PackManifest::new(
+            "alpine:latest".to_string(),
+            "sha256:abc123".to_string(),
+            "linux/arm64".to_string(),
+        );
+        assert!(!manifest.is_multi_platform());
+        assert!(!manifest.select_platform("linux/amd64"));
+
+        let mut amd64 = manifest.assets.clone();
+        amd64.agent_rootfs.path = format!("{}/agent-rootfs.tar", platform_asset_dir("linux/amd64"));
+        manifest.platforms.insert("linux/amd64".to_string(), amd64);
+        assert!(manifest.is_multi_platform());
+        assert_eq!(
+            manifest.platform_names(),
+            vec!["linux/arm64", "linux/amd64"]
+        );
+
+        let json = manifest.to_json().unwrap();
+        let mut restored = PackManifest::from_json(&json).unwrap();
+        assert!(restored.select_platform("linux/amd64"));
+        assert_eq!(restored.platform, "linux/amd64");
+        assert_eq!(
+            restored.assets.agent_rootfs.path,
+            "platforms/linux-amd64/agent-rootfs.tar"
+        );
+        assert_eq!(
+            restored
+                .inventory_for("linux/arm64")
+                .unwrap()
+                .agent_rootfs
+                .path,
+            "agent-rootfs.tar"
+        );
+        assert!(restored.inventory_for("linux/riscv64").is_none());
+    }
+
+    #[test]
+    fn test_parse_platforms() {
+        assert_eq!(
+            parse_platforms("linux/amd64, linux/arm64/v8").unwrap(),
+            vec!["linux/amd64", "linux/arm64/v8"]
+        );
+        assert_eq!(parse_platforms("linux/arm64").unwrap(), vec!["linux/arm64"]);
+        assert!(parse_platforms("linux").is_err());
+        assert!(parse_platforms("linux/amd64,").is_err());
+        assert!(parse_platforms("linux/amd64,linux/amd64").is_err());
+    }
+
     #[test]
     fn test_pack_mode_default_is_container() {
         assert_eq!(PackMode::default(), PackMode::Container);
@@ -457,6 +748,47 @@ mod tests {
         let manifest: PackManifest = serde_json::from_str(json).unwrap();
         assert_eq!(manifest.mode, PackMode::Container);
         assert!(manifest.assets.overlay_template.is_none());
+        assert!(!manifest.is_multi_platform());
+        assert!(manifest.name.is_none());
+        assert!(manifest.defaults.is_empty());
+    }
+
+    #[test]
+    fn test_manifest_run_defaults_roundtrip() {
+        let mut manifest = PackManifest::new(
+            "myapp:latest".to_string(),
+            "sha256:abc".to_string(),
+            "linux/arm64".to_string(),
+        );
+        // Unset defaults are left out of the manifest
+        let json = String::from_utf8(manifest.to_json().unwrap()).unwrap();
+        assert!(!json.contains("defaults"));
+        assert!(!json.contains("\"name\""));
+
+        manifest.name = Some("myapp".to_string());
+        manifest.version = Some("1.2.0".to_string());
+        manifest.defaults = RunDefaults {
+            volumes: vec!["./data:/data".to_string()],
+            ports: vec!["8080:80".to_string()],
+            net: true,
+            net_allow: vec!["github.com".to_string(), "10.0.0.0/8:443".to_string()],
+            proxy: Some(smolvm_protocol::ProxyConfig {
+                https_proxy: Some("http://proxy.internal:3128".to_string()),
+                ..Default::default()
+            }),
+            dns: Some(smolvm_protocol::DnsConfig {
+                search: vec!["corp.internal".to_string()],
+                ..Default::default()
+            }),
+            init: vec!["mkdir -p /data/cache".to_string()],
+            storage: Some(4),
+            overlay: None,
+        };
+
+        let restored = PackManifest::from_json(&manifest.to_json().unwrap()).unwrap();
+        assert_eq!(restored.name.as_deref(), Some("myapp"));
+        assert_eq!(restored.version.as_deref(), Some("1.2.0"));
+        assert_eq!(restored.defaults, manifest.defaults);
     }
 
     #[test]
@@ -470,6 +802,7 @@ mod tests {
         manifest.assets.overlay_template = Some(AssetEntry {
             path: "overlay.raw".to_string(),
             size: 2 * 1024 * 1024 * 1024,
+            sha256: None,
         });
 
         let json = manifest.to_json().unwrap();
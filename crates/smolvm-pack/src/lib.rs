@@ -14,6 +14,8 @@
 //! +---------------------------+
 //! | Manifest (JSON)           |  ~2KB
 //! +---------------------------+
+//! | Signature (optional)      |  Ed25519, see [`trust`]
+//! +---------------------------+
 //! | Footer (64 bytes)         |
 //! |  - magic: "SMOLPACK"      |
 //! |  - version, offsets       |
@@ -33,17 +35,31 @@
 //! ```
 //!
 //! This allows proper code signing on macOS while keeping distribution simple.
+//!
+//! A pack can be updated in place from a `.smolpatch` carrying only the
+//! assets that changed; see [`patch`]. Its image can be exported as an
+//! OCI image layout; see [`oci`]. To move a pack onto a newer runtime
+//! without the original image, see [`rebase`]. Images can be packed from
+//! a local OCI layout or `docker save` archive; see [`archive`]. Packs
+//! carry an SBOM of the packages in their image; see [`sbom`].
 
 #![deny(missing_docs)]
 
+pub mod archive;
 pub mod assets;
 pub mod detect;
 pub mod extract;
 pub mod format;
 #[cfg(target_os = "macos")]
 pub mod macho;
+pub mod oci;
 pub mod packer;
+pub mod patch;
+pub mod rebase;
+mod rpmdb;
+pub mod sbom;
 pub mod signing;
+pub mod trust;
 
 pub use detect::{detect_packed_mode, PackedMode};
 pub use format::{
@@ -52,8 +68,9 @@ pub use format::{
 };
 pub use packer::{
     read_footer, read_footer_from_sidecar, read_manifest, read_manifest_from_sidecar,
-    sidecar_path_for, verify_sidecar_checksum, Packer,
+    sidecar_path_for, verify_sidecar_checksum, PackedFile, Packer,
 };
+pub use trust::{ManifestSignature, PublicKey, SigningKey, TrustPolicy, TrustedKey};
 
 use thiserror::Error;
 
@@ -100,6 +117,22 @@ pub enum PackError {
     /// Tar archive error.
     #[error("tar error: {0}")]
     Tar(String),
+
+    /// Malformed or unusable signing key, public key or trusted keys file.
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
+    /// Signature or asset digest verification failed.
+    #[error("verification failed: {0}")]
+    Verification(String),
+
+    /// Malformed OCI platform.
+    #[error("invalid platform: {0}")]
+    InvalidPlatform(String),
+
+    /// Unreadable or unusable local image archive.
+    #[error("invalid image archive: {0}")]
+    InvalidImage(String),
 }
 
 /// Result type for pack operations.
@@ -0,0 +1,327 @@
+//! Exporting a pack's image as an OCI image layout.
+//!
+//! Packs keep each OCI layer as an uncompressed tarball, so the layers are
+//! written as they are and their digests double as the config's
+//! `diff_ids`. The config is rebuilt from the manifest's entrypoint, cmd,
+//! env and workdir; other image config fields are not kept in a pack.
+//! A multi-platform pack becomes an index with one manifest per platform.
+//!
+//! ```text
+//! oci-layout
+//! index.json
+//! blobs/sha256/<hex>     layers, configs and manifests
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::assets::file_digest;
+use crate::format::{AssetInventory, PackManifest, PackMode};
+use crate::packer::PackedFile;
+use crate::patch::unpack_assets;
+use crate::{PackError, Result};
+
+/// OCI image index media type.
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// OCI image manifest media type.
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// OCI image config media type.
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
+/// Uncompressed OCI layer media type.
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// Annotation holding the full image name (containerd, Docker 25+).
+const ANNOTATION_IMAGE_NAME: &str = "io.containerd.image.name";
+
+/// Standard OCI annotation holding the image reference name.
+const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Summary of an exported layout.
+#[derive(Debug, Clone)]
+pub struct LayoutInfo {
+    /// Digest of each platform's image manifest, with its platform.
+    pub manifests: Vec<(String, String)>,
+    /// Distinct layer blobs written.
+    pub layers: usize,
+    /// Total size of the layer blobs.
+    pub layers_size: u64,
+}
+
+/// Write the image in `pack` to `dest` as an OCI image layout.
+///
+/// `dest` must be empty or not exist yet. VM snapshot packs have no image
+/// to export.
+pub fn write_layout(pack: &PackedFile, dest: &Path) -> Result<LayoutInfo> {
+    let manifest = pack.manifest()?;
+    if manifest.mode == PackMode::Vm {
+        return Err(PackError::AssetNotFound(
+            "VM snapshot packs have no OCI layers to export".to_string(),
+        ));
+    }
+    if dest.exists() && fs::read_dir(dest)?.next().is_some() {
+        return Err(PackError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} is not empty", dest.display()),
+        )));
+    }
+
+    let blobs = dest.join("blobs/sha256");
+    fs::create_dir_all(&blobs)?;
+
+    // Unpack every layer once, however many platforms share it
+    let staging = tempfile::tempdir_in(dest)?;
+    let mut targets: HashMap<String, Vec<String>> = HashMap::new();
+    for platform in manifest.platform_names() {
+        for layer in &inventory(&manifest, platform).layers {
+            targets
+                .entry(layer.path.clone())
+                .or_insert_with(|| vec![layer.path.clone()]);
+        }
+    }
+    unpack_assets(pack, &targets, staging.path())?;
+
+    let mut layer_blobs: HashMap<String, (String, u64)> = HashMap::new();
+    let mut layers_size = 0;
+    for path in targets.keys() {
+        let staged = staging.path().join(path);
+        let (digest, size) = file_digest(&staged)?;
+        fs::rename(&staged, blobs.join(digest.trim_start_matches("sha256:")))?;
+        layers_size += size;
+        layer_blobs.insert(path.clone(), (digest, size));
+    }
+    drop(staging);
+
+    let mut descriptors = Vec::new();
+    let mut manifests = Vec::new();
+    for platform in manifest.platform_names() {
+        let layers: Vec<&(String, u64)> = inventory(&manifest, platform)
+            .layers
+            .iter()
+            .map(|layer| &layer_blobs[&layer.path])
+            .collect();
+        let (os, architecture, variant) = split_platform(platform);
+
+        let mut config = serde_json::json!({
+            "architecture": architecture,
+            "os": os,
+            "config": image_config(&manifest),
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": layers.iter().map(|(digest, _)| digest).collect::<Vec<_>>(),
+            },
+        });
+        if let Some(variant) = variant {
+            config["variant"] = variant.into();
+        }
+        let (config_digest, config_size) = write_blob(&blobs, config.to_string().as_bytes())?;
+
+        let image_manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": MANIFEST_MEDIA_TYPE,
+            "config": {
+                "mediaType": CONFIG_MEDIA_TYPE,
+                "digest": config_digest,
+                "size": config_size,
+            },
+            "layers": layers.iter().map(|(digest, size)| serde_json::json!({
+                "mediaType": LAYER_MEDIA_TYPE,
+                "digest": digest,
+                "size": size,
+            })).collect::<Vec<_>>(),
+        });
+        let (manifest_digest, manifest_size) =
+            write_blob(&blobs, image_manifest.to_string().as_bytes())?;
+
+        let mut platform_json = serde_json::json!({
+            "architecture": architecture,
+            "os": os,
+        });
+        if let Some(variant) = variant {
+            platform_json["variant"] = variant.into();
+        }
+        descriptors.push(serde_json::json!({
+            "mediaType": MANIFEST_MEDIA_TYPE,
+            "digest": manifest_digest,
+            "size": manifest_size,
+            "platform": platform_json,
+            "annotations": {
+                ANNOTATION_IMAGE_NAME: manifest.image,
+                ANNOTATION_REF_NAME: reference_tag(&manifest.image),
+            },
+        }));
+        manifests.push((manifest_digest, platform.to_string()));
+    }
+
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": INDEX_MEDIA_TYPE,
+        "manifests": descriptors,
+    });
+    fs::write(
+        dest.join("oci-layout"),
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    fs::write(dest.join("index.json"), index.to_string())?;
+
+    Ok(LayoutInfo {
+        manifests,
+        layers: layer_blobs.len(),
+        layers_size,
+    })
+}
+
+/// Inventory of a platform listed by `platform_names`.
+fn inventory<'a>(manifest: &'a PackManifest, platform: &str) -> &'a AssetInventory {
+    manifest
+        .inventory_for(platform)
+        .expect("platform_names lists only platforms with an inventory")
+}
+
+/// The image config's `config` object, from the manifest's run settings.
+fn image_config(manifest: &PackManifest) -> serde_json::Value {
+    let mut config = serde_json::Map::new();
+    if !manifest.entrypoint.is_empty() {
+        config.insert("Entrypoint".into(), manifest.entrypoint.clone().into());
+    }
+    if !manifest.cmd.is_empty() {
+        config.insert("Cmd".into(), manifest.cmd.clone().into());
+    }
+    if !manifest.env.is_empty() {
+        config.insert("Env".into(), manifest.env.clone().into());
+    }
+    if let Some(ref workdir) = manifest.workdir {
+        config.insert("WorkingDir".into(), workdir.clone().into());
+    }
+    config.into()
+}
+
+/// Split `os/arch[/variant]`.
+fn split_platform(platform: &str) -> (&str, &str, Option<&str>) {
+    let mut parts = platform.splitn(3, '/');
+    let os = parts.next().unwrap_or_default();
+    let arch = parts.next().unwrap_or_default();
+    (os, arch, parts.next())
+}
+
+/// Tag of an image reference, as the `ref.name` annotation wants it.
+fn reference_tag(image: &str) -> &str {
+    let name = image.split('@').next().unwrap_or(image);
+    let last = name.rsplit('/').next().unwrap_or(name);
+    match last.split_once(':') {
+        Some((_, tag)) => tag,
+        None => "latest",
+    }
+}
+
+/// Write a blob under its digest; returns the digest and size.
+fn write_blob(blobs: &Path, data: &[u8]) -> Result<(String, u64)> {
+    let hex = format!("{:x}", Sha256::digest(data));
+    fs::write(blobs.join(&hex), data)?;
+    Ok((format!("sha256:{}", hex), data.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetCollector;
+    use crate::packer::Packer;
+
+    fn layer(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn read_json(path: &Path) -> serde_json::Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    fn blob(dest: &Path, digest: &serde_json::Value) -> std::path::PathBuf {
+        let digest = digest.as_str().unwrap();
+        dest.join("blobs/sha256")
+            .join(digest.strip_prefix("sha256:").unwrap())
+    }
+
+    #[test]
+    fn test_write_layout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+
+        let base = layer("etc/os-release", b"ID=test");
+        let app = layer("app/run.sh", b"#!/bin/sh");
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        collector.add_layer("sha256:base00000001", &base).unwrap();
+        collector.add_layer("sha256:app000000001", &app).unwrap();
+
+        let mut manifest = PackManifest::new(
+            "registry.example.com/team/app:1.2".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64/v8".to_string(),
+        );
+        manifest.entrypoint = vec!["/app/run.sh".to_string()];
+        manifest.env = vec!["PATH=/usr/bin".to_string()];
+        let output = temp_dir.path().join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&output)
+            .unwrap();
+
+        let dest = temp_dir.path().join("layout");
+        let pack = PackedFile::open(&output).unwrap();
+        let info = write_layout(&pack, &dest).unwrap();
+        assert_eq!(info.layers, 2);
+        assert_eq!(info.layers_size, (base.len() + app.len()) as u64);
+
+        assert_eq!(
+            read_json(&dest.join("oci-layout"))["imageLayoutVersion"],
+            "1.0.0"
+        );
+        let index = read_json(&dest.join("index.json"));
+        let descriptor = &index["manifests"][0];
+        assert_eq!(descriptor["platform"]["architecture"], "arm64");
+        assert_eq!(descriptor["platform"]["variant"], "v8");
+        assert_eq!(descriptor["annotations"][ANNOTATION_REF_NAME], "1.2");
+        assert_eq!(descriptor["digest"], info.manifests[0].0.as_str());
+
+        let image_manifest = read_json(&blob(&dest, &descriptor["digest"]));
+        let layers = image_manifest["layers"].as_array().unwrap();
+        assert_eq!(fs::read(blob(&dest, &layers[0]["digest"])).unwrap(), base);
+        assert_eq!(fs::read(blob(&dest, &layers[1]["digest"])).unwrap(), app);
+
+        let config = read_json(&blob(&dest, &image_manifest["config"]["digest"]));
+        assert_eq!(config["os"], "linux");
+        assert_eq!(config["config"]["Entrypoint"][0], "/app/run.sh");
+        assert_eq!(config["rootfs"]["diff_ids"][1], layers[1]["digest"]);
+
+        // Only the layout is left behind
+        let mut entries: Vec<_> = fs::read_dir(&dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["blobs", "index.json", "oci-layout"]);
+
+        // Refuses to write over an existing layout
+        assert!(write_layout(&pack, &dest).is_err());
+    }
+
+    #[test]
+    fn test_reference_tag() {
+        assert_eq!(reference_tag("alpine"), "latest");
+        assert_eq!(reference_tag("alpine:3.19"), "3.19");
+        assert_eq!(reference_tag("localhost:5000/app"), "latest");
+        assert_eq!(reference_tag("localhost:5000/app:v1@sha256:abc"), "v1");
+    }
+}
@@ -10,9 +10,10 @@ use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-use crate::assets::{crc32_file_range, AssetCollector};
+use crate::assets::{crc32_file_range, hex_encode, sha256_file_range, AssetCollector};
 use crate::format::{PackFooter, PackManifest, FOOTER_SIZE, SIDECAR_EXTENSION};
-use crate::Result;
+use crate::trust::{ManifestSignature, SigningKey, TrustPolicy, TrustedKey, MAX_SIGNATURE_SIZE};
+use crate::{PackError, Result};
 
 /// Maximum allowed manifest size (16 MiB) to prevent malicious/corrupt sidecars
 /// from causing excessive memory allocation.
@@ -23,6 +24,7 @@ pub struct Packer {
     stub_path: Option<std::path::PathBuf>,
     manifest: PackManifest,
     asset_collector: Option<AssetCollector>,
+    signing_key: Option<SigningKey>,
 }
 
 /// Error type for try_pack_embedded_macho (internal).
@@ -41,6 +43,7 @@ impl Packer {
             stub_path: None,
             manifest,
             asset_collector: None,
+            signing_key: None,
         }
     }
 
@@ -54,6 +57,7 @@ impl Packer {
     pub fn with_assets(mut self, collector: AssetCollector) -> Self {
         // Update manifest with the collector's inventory
         self.manifest.assets = collector.inventory().clone();
+        self.manifest.platforms = collector.platform_inventories().clone();
         self.asset_collector = Some(collector);
         self
     }
@@ -67,6 +71,25 @@ impl Packer {
         self
     }
 
+    /// Sign the packed manifest and assets with the given key.
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Signature block for the manifest and the compressed assets at
+    /// `assets_path`, or nothing when not signing.
+    fn signature_block(&self, manifest_json: &[u8], assets_path: &Path) -> Result<Vec<u8>> {
+        match &self.signing_key {
+            Some(key) => {
+                let assets_size = fs::metadata(assets_path)?.len();
+                let assets_sha256 = sha256_file_range(assets_path, 0, assets_size)?;
+                key.sign(manifest_json, &assets_sha256).to_json()
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
     /// Get a mutable reference to the manifest.
     pub fn manifest_mut(&mut self) -> &mut PackManifest {
         &mut self.manifest
@@ -80,7 +103,7 @@ impl Packer {
     ///
     /// This keeps the binary as a pure Mach-O executable that can be
     /// properly code-signed on macOS.
-    pub fn pack(self, output: impl AsRef<Path>) -> Result<PackedInfo> {
+    pub fn pack(mut self, output: impl AsRef<Path>) -> Result<PackedInfo> {
         let output = output.as_ref();
         let temp_dir = tempfile::tempdir()?;
 
@@ -111,7 +134,8 @@ impl Packer {
         // 2a. Write compressed assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
         let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
+            self.manifest.asset_index = collector.compress_indexed(&assets_temp)?;
+            fs::metadata(&assets_temp)?.len()
         } else {
             let empty_file = File::create(&assets_temp)?;
             let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
@@ -136,7 +160,8 @@ impl Packer {
         let checksum_size = assets_size + manifest_size;
         let checksum = crc32_file_range(&sidecar_path, 0, checksum_size)?;
 
-        // 2d. Write footer to sidecar
+        // 2d. Write signature block and footer to sidecar
+        let signature = self.signature_block(&manifest_json, &assets_temp)?;
         let footer = PackFooter {
             stub_size: 0,     // Not used in sidecar mode
             assets_offset: 0, // Assets start at beginning of sidecar
@@ -144,12 +169,15 @@ impl Packer {
             manifest_offset,
             manifest_size,
             checksum,
+            signature_size: signature.len() as u32,
         };
 
         let mut sidecar_file = fs::OpenOptions::new().append(true).open(&sidecar_path)?;
+        sidecar_file.write_all(&signature)?;
         sidecar_file.write_all(&footer.to_bytes())?;
 
-        let sidecar_total = assets_size + manifest_size + FOOTER_SIZE as u64;
+        let sidecar_total =
+            assets_size + manifest_size + signature.len() as u64 + FOOTER_SIZE as u64;
         let total_size = stub_size + sidecar_total;
 
         Ok(PackedInfo {
@@ -239,7 +267,7 @@ impl Packer {
     /// binary as a valid Mach-O that can be properly code-signed.
     #[cfg(target_os = "macos")]
     fn pack_embedded_macho_inner(
-        self,
+        mut self,
         output: &Path,
         stub_data: Vec<u8>,
         mut macho: crate::macho::MachoFile,
@@ -252,7 +280,8 @@ impl Packer {
         // Compress assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
         let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
+            self.manifest.asset_index = collector.compress_indexed(&assets_temp)?;
+            fs::metadata(&assets_temp)?.len()
         } else {
             let empty_file = File::create(&assets_temp)?;
             let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
@@ -273,18 +302,23 @@ impl Packer {
         hasher.update(&assets_data);
         let checksum = hasher.finalize();
 
-        // Build section data: header + manifest + assets
+        let signature = self.signature_block(&manifest_json, &assets_temp)?;
+
+        // Build section data: header + manifest + assets + signature
         let header = SectionHeader {
             manifest_size,
             assets_size,
             checksum,
+            signature_size: signature.len() as u32,
         };
 
-        let mut section_data =
-            Vec::with_capacity(SECTION_HEADER_SIZE + manifest_json.len() + assets_data.len());
+        let mut section_data = Vec::with_capacity(
+            SECTION_HEADER_SIZE + manifest_json.len() + assets_data.len() + signature.len(),
+        );
         section_data.extend_from_slice(&header.to_bytes());
         section_data.extend_from_slice(&manifest_json);
         section_data.extend_from_slice(&assets_data);
+        section_data.extend_from_slice(&signature);
 
         // Write section data to Mach-O
         macho
@@ -324,7 +358,7 @@ impl Packer {
     ///
     /// This is the fallback method on macOS (when stub isn't a valid Mach-O)
     /// and the default method on other platforms.
-    fn pack_embedded_append(self, output: impl AsRef<Path>) -> Result<PackedInfo> {
+    fn pack_embedded_append(mut self, output: impl AsRef<Path>) -> Result<PackedInfo> {
         let output = output.as_ref();
         let temp_dir = tempfile::tempdir()?;
 
@@ -343,7 +377,8 @@ impl Packer {
         // 2. Compress and append assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
         let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
+            self.manifest.asset_index = collector.compress_indexed(&assets_temp)?;
+            fs::metadata(&assets_temp)?.len()
         } else {
             let empty_file = File::create(&assets_temp)?;
             let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
@@ -369,7 +404,8 @@ impl Packer {
         let checksum_size = assets_size + manifest_size;
         let checksum = crc32_file_range(output, assets_offset, checksum_size)?;
 
-        // 5. Append footer
+        // 5. Append signature block and footer
+        let signature = self.signature_block(&manifest_json, &assets_temp)?;
         let footer = PackFooter {
             stub_size,
             assets_offset, // Non-zero indicates embedded mode
@@ -377,9 +413,11 @@ impl Packer {
             manifest_offset,
             manifest_size,
             checksum,
+            signature_size: signature.len() as u32,
         };
 
         let mut output_file = fs::OpenOptions::new().append(true).open(output)?;
+        output_file.write_all(&signature)?;
         output_file.write_all(&footer.to_bytes())?;
 
         // Make executable
@@ -391,7 +429,8 @@ impl Packer {
             fs::set_permissions(output, perms)?;
         }
 
-        let total_size = stub_size + assets_size + manifest_size + FOOTER_SIZE as u64;
+        let total_size =
+            stub_size + assets_size + manifest_size + signature.len() as u64 + FOOTER_SIZE as u64;
 
         Ok(PackedInfo {
             stub_size,
@@ -578,10 +617,21 @@ fn validate_footer_bounds(footer: &PackFooter, file_size: u64) -> Result<()> {
         )));
     }
 
-    // Verify that assets + manifest + footer fit within the file
+    if footer.signature_size > MAX_SIGNATURE_SIZE {
+        return Err(crate::PackError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "signature size ({} bytes) exceeds maximum ({} bytes)",
+                footer.signature_size, MAX_SIGNATURE_SIZE
+            ),
+        )));
+    }
+
+    // Verify that assets + manifest + signature + footer fit within the file
     let content_end = footer
         .assets_size
         .checked_add(footer.manifest_size)
+        .and_then(|s| s.checked_add(footer.signature_size as u64))
         .and_then(|s| s.checked_add(FOOTER_SIZE as u64));
 
     match content_end {
@@ -614,6 +664,7 @@ fn validate_footer_bounds(footer: &PackFooter, file_size: u64) -> Result<()> {
     let manifest_end = footer
         .manifest_offset
         .checked_add(footer.manifest_size)
+        .and_then(|end| end.checked_add(footer.signature_size as u64))
         .and_then(|end| end.checked_add(FOOTER_SIZE as u64));
 
     match manifest_end {
@@ -629,6 +680,256 @@ fn validate_footer_bounds(footer: &PackFooter, file_size: u64) -> Result<()> {
     Ok(())
 }
 
+/// The assets of a packed machine on disk: a `.smolmachine` sidecar, or a
+/// single-file binary with assets appended.
+///
+/// This is the entry point for checking and signing existing packs.
+#[derive(Debug, Clone)]
+pub struct PackedFile {
+    path: PathBuf,
+    footer: PackFooter,
+}
+
+impl PackedFile {
+    /// Open a packed binary or sidecar.
+    ///
+    /// For a binary, its `.smolmachine` sidecar is used if present, else
+    /// the assets appended to the binary itself.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let sidecar = if path.to_string_lossy().ends_with(SIDECAR_EXTENSION) {
+            path.to_path_buf()
+        } else {
+            sidecar_path_for(path)
+        };
+        if sidecar.exists() {
+            let footer = read_footer_from_sidecar(&sidecar)?;
+            return Ok(Self::from_parts(sidecar, footer));
+        }
+
+        let footer = read_footer(path)?;
+        validate_footer_bounds(&footer, fs::metadata(path)?.len())?;
+        if is_sidecar_mode(&footer) {
+            return Err(PackError::AssetNotFound(format!(
+                "sidecar file {}",
+                sidecar.display()
+            )));
+        }
+        Ok(Self::from_parts(path.to_path_buf(), footer))
+    }
+
+    /// Use a footer that was already read from `path`.
+    pub fn from_parts(path: PathBuf, footer: PackFooter) -> Self {
+        Self { path, footer }
+    }
+
+    /// File holding the assets (the sidecar, or the binary itself).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The footer.
+    pub fn footer(&self) -> &PackFooter {
+        &self.footer
+    }
+
+    /// Raw manifest JSON, as covered by the checksum and signature.
+    pub fn manifest_json(&self) -> Result<Vec<u8>> {
+        self.read_range(self.footer.manifest_offset, self.footer.manifest_size)
+    }
+
+    /// Parsed manifest.
+    pub fn manifest(&self) -> Result<PackManifest> {
+        PackManifest::from_json(&self.manifest_json()?)
+    }
+
+    /// Signature block, or `None` for an unsigned pack.
+    pub fn signature(&self) -> Result<Option<ManifestSignature>> {
+        if self.footer.signature_size == 0 {
+            return Ok(None);
+        }
+        let offset = self.footer.manifest_offset + self.footer.manifest_size;
+        let block = self.read_range(offset, self.footer.signature_size as u64)?;
+        ManifestSignature::from_json(&block).map(Some)
+    }
+
+    /// SHA-256 of the compressed assets blob.
+    pub fn assets_sha256(&self) -> Result<[u8; 32]> {
+        sha256_file_range(
+            &self.path,
+            self.footer.assets_offset,
+            self.footer.assets_size,
+        )
+    }
+
+    /// Check the CRC32 of the assets and manifest.
+    pub fn verify_checksum(&self) -> Result<bool> {
+        let actual = crc32_file_range(
+            &self.path,
+            self.footer.assets_offset,
+            self.footer.assets_size + self.footer.manifest_size,
+        )?;
+        Ok(actual == self.footer.checksum)
+    }
+
+    /// Check that the pack is signed by a key the policy trusts.
+    pub fn verify_signature<'a>(&self, policy: &'a TrustPolicy) -> Result<&'a TrustedKey> {
+        policy.check(
+            self.signature()?.as_ref(),
+            &self.manifest_json()?,
+            &self.assets_sha256()?,
+        )
+    }
+
+    /// Like [`verify_signature`](Self::verify_signature), but reuses the
+    /// assets digest remembered in `cache_dir` (see
+    /// [`cached_assets_sha256`](crate::extract::cached_assets_sha256)), so
+    /// a launch doesn't read the whole pack. The signature is still checked.
+    #[cfg(unix)]
+    pub fn verify_signature_cached<'a>(
+        &self,
+        policy: &'a TrustPolicy,
+        cache_dir: &Path,
+    ) -> Result<&'a TrustedKey> {
+        let digest = crate::extract::cached_assets_sha256(cache_dir, &self.path, || {
+            self.assets_sha256()
+                .map_err(|e| std::io::Error::other(e.to_string()))
+        })?;
+        policy.check(self.signature()?.as_ref(), &self.manifest_json()?, &digest)
+    }
+
+    /// Decompress the assets and check each against the SHA-256 recorded
+    /// in the manifest. Returns the number of assets checked; entries from
+    /// packs made before digests were recorded are skipped.
+    pub fn verify_assets(&self, manifest: &PackManifest) -> Result<usize> {
+        use std::collections::HashMap;
+
+        // Platforms share layers and the storage template, so the same path
+        // may be listed more than once.
+        let mut expected: HashMap<&str, &str> = std::iter::once(&manifest.assets)
+            .chain(manifest.platforms.values())
+            .flat_map(|inventory| inventory.files())
+            .filter_map(|(path, sha256)| Some((path, sha256?)))
+            .collect();
+        let total = expected.len();
+
+        if !manifest.asset_index.is_empty() {
+            return self.verify_frames(manifest, expected);
+        }
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.footer.assets_offset))?;
+        let decoder = zstd::stream::Decoder::new(file.take(self.footer.assets_size))
+            .map_err(|e| PackError::Compression(e.to_string()))?;
+        let mut archive = tar::Archive::new(decoder);
+        for entry in archive
+            .entries()
+            .map_err(|e| PackError::Tar(e.to_string()))?
+        {
+            let entry = entry.map_err(|e| PackError::Tar(e.to_string()))?;
+            let path = entry
+                .path()
+                .map_err(|e| PackError::Tar(e.to_string()))?
+                .components()
+                .filter(|c| !matches!(c, std::path::Component::CurDir))
+                .collect::<PathBuf>();
+            let Some(want) = expected.remove(path.to_string_lossy().as_ref()) else {
+                continue;
+            };
+            check_asset_digest(&path.to_string_lossy(), entry, want)?;
+        }
+
+        if let Some(missing) = expected.keys().next() {
+            return Err(PackError::Verification(format!(
+                "asset {} listed in the manifest is missing",
+                missing
+            )));
+        }
+        Ok(total)
+    }
+
+    /// Check the assets listed in `expected` using the manifest's asset
+    /// index, hashing the frames in parallel.
+    fn verify_frames(
+        &self,
+        manifest: &PackManifest,
+        expected: std::collections::HashMap<&str, &str>,
+    ) -> Result<usize> {
+        let mut frames = Vec::with_capacity(expected.len());
+        for (path, want) in expected {
+            let frame = manifest
+                .asset_index
+                .iter()
+                .find(|frame| frame.path == path)
+                .ok_or_else(|| {
+                    PackError::Verification(format!(
+                        "asset {} listed in the manifest is missing",
+                        path
+                    ))
+                })?;
+            if frame
+                .offset
+                .checked_add(frame.size)
+                .is_none_or(|end| end > self.footer.assets_size)
+            {
+                return Err(PackError::Verification(format!(
+                    "asset {} is outside the assets blob",
+                    path
+                )));
+            }
+            frames.push((frame, want));
+        }
+
+        crate::extract::for_each_parallel(&frames, |(frame, want)| {
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(self.footer.assets_offset + frame.offset))?;
+            let decoder = zstd::stream::Decoder::new(file.take(frame.size))
+                .map_err(|e| PackError::Compression(e.to_string()))?;
+            let mut archive = tar::Archive::new(decoder);
+            let entry = archive
+                .entries()
+                .map_err(|e| PackError::Tar(e.to_string()))?
+                .next()
+                .ok_or_else(|| {
+                    PackError::Verification(format!("asset frame for {} is empty", frame.path))
+                })?
+                .map_err(|e| PackError::Tar(e.to_string()))?;
+            check_asset_digest(&frame.path, entry, want)
+        })?;
+        Ok(frames.len())
+    }
+
+    /// Sign the pack in place, replacing any existing signature.
+    ///
+    /// The checksum and extraction cache are unaffected.
+    pub fn sign(&mut self, key: &SigningKey) -> Result<ManifestSignature> {
+        let signature = key.sign(&self.manifest_json()?, &self.assets_sha256()?);
+        let block = signature.to_json()?;
+
+        let mut file = fs::OpenOptions::new().write(true).open(&self.path)?;
+        let signature_offset = self.footer.manifest_offset + self.footer.manifest_size;
+        file.set_len(signature_offset)?;
+        file.seek(SeekFrom::Start(signature_offset))?;
+
+        let mut footer = self.footer;
+        footer.signature_size = block.len() as u32;
+        file.write_all(&block)?;
+        file.write_all(&footer.to_bytes())?;
+        file.sync_all()?;
+
+        self.footer = footer;
+        Ok(signature)
+    }
+
+    fn read_range(&self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0u8; size as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
 /// Extract assets from a packed binary to a directory.
 pub fn extract_assets(packed_path: impl AsRef<Path>, output_dir: impl AsRef<Path>) -> Result<()> {
     let footer = read_footer(packed_path.as_ref())?;
@@ -653,9 +954,25 @@ pub fn extract_assets(packed_path: impl AsRef<Path>, output_dir: impl AsRef<Path
     Ok(())
 }
 
+/// Hash an asset read from the assets tarball and compare it with the
+/// manifest's digest.
+fn check_asset_digest<R: Read>(path: &str, mut entry: tar::Entry<'_, R>, want: &str) -> Result<()> {
+    let mut hasher = <sha2::Sha256 as sha2::Digest>::new();
+    std::io::copy(&mut entry, &mut hasher)?;
+    let actual = hex_encode(&sha2::Digest::finalize(hasher));
+    if actual != want {
+        return Err(PackError::Verification(format!(
+            "asset {} has SHA-256 {}, manifest says {}",
+            path, actual, want
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::assets::sha256_file;
     use std::io::Write;
 
     #[test]
@@ -841,6 +1158,228 @@ mod tests {
         );
     }
 
+    /// Pack an agent rootfs and a layer whose files were written in `order`
+    /// with modification time `mtime`, returning the sha256 of the binary
+    /// and of its sidecar.
+    fn pack_for_reproducibility(
+        dir: &Path,
+        order: &[&str],
+        mtime: u64,
+        source_date_epoch: Option<u64>,
+    ) -> (String, String) {
+        fs::create_dir_all(dir).unwrap();
+        let stub_path = dir.join("stub");
+        fs::write(&stub_path, b"#!/bin/sh\necho stub").unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+
+        let rootfs = dir.join("rootfs");
+        let layer = dir.join("layer.tar");
+        let mut builder = tar::Builder::new(File::create(&layer).unwrap());
+        for name in order {
+            let path = rootfs.join("bin").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, name).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_uid(0);
+            header.set_gid(0);
+            builder
+                .append_data(&mut header, format!("usr/{}", name), name.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap();
+
+        let mut collector = AssetCollector::new(dir.join("staging")).unwrap();
+        if let Some(epoch) = source_date_epoch {
+            collector = collector.with_source_date_epoch(epoch);
+        }
+        collector.collect_agent_rootfs(&rootfs).unwrap();
+        collector
+            .add_layer_from_file("sha256:reproducible01", &layer)
+            .unwrap();
+        let manifest = PackManifest::new(
+            "test:reproducible".to_string(),
+            "sha256:test".to_string(),
+            "linux/amd64".to_string(),
+        );
+        let output_path = dir.join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub_path)
+            .with_assets(collector)
+            .pack(&output_path)
+            .unwrap();
+        (
+            sha256_file(&output_path).unwrap(),
+            sha256_file(&sidecar_path_for(&output_path)).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_pack_reproducible() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let epoch = Some(1_700_000_000);
+        let first = pack_for_reproducibility(
+            &temp_dir.path().join("first"),
+            &["a", "b", "c"],
+            1_800_000_000,
+            epoch,
+        );
+        let second = pack_for_reproducibility(
+            &temp_dir.path().join("second"),
+            &["c", "a", "b"],
+            1_900_000_000,
+            epoch,
+        );
+        assert_eq!(first, second);
+
+        // Without an epoch the timestamps and order leak into the pack
+        let plain = pack_for_reproducibility(
+            &temp_dir.path().join("plain"),
+            &["c", "a", "b"],
+            1_900_000_000,
+            None,
+        );
+        assert_ne!(first.1, plain.1);
+    }
+
+    fn pack_layer_for_signing(dir: &Path, embedded: bool, key: Option<SigningKey>) -> PathBuf {
+        let stub_path = dir.join("stub");
+        fs::write(&stub_path, b"#!/bin/sh\necho stub").unwrap();
+
+        let mut collector = AssetCollector::new(dir.join("staging")).unwrap();
+        collector
+            .add_layer("sha256:signed123456789", b"signed layer content")
+            .unwrap();
+
+        let manifest = PackManifest::new(
+            "test:signed".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+        let mut packer = Packer::new(manifest)
+            .with_stub(&stub_path)
+            .with_assets(collector);
+        if let Some(key) = key {
+            packer = packer.with_signing_key(key);
+        }
+
+        let output_path = dir.join("packed");
+        if embedded {
+            packer.pack_embedded(&output_path).unwrap();
+        } else {
+            packer.pack(&output_path).unwrap();
+        }
+        output_path
+    }
+
+    #[test]
+    fn test_pack_signed_embedded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let key = SigningKey::generate().unwrap();
+        let policy = TrustPolicy::single(key.public_key());
+        let output_path = pack_layer_for_signing(temp_dir.path(), true, Some(key));
+
+        let packed = PackedFile::open(&output_path).unwrap();
+        assert_eq!(packed.path(), output_path);
+        assert!(packed.footer().signature_size > 0);
+        assert!(packed.verify_checksum().unwrap());
+
+        let manifest = packed.manifest().unwrap();
+        assert!(manifest.assets.layers[0].sha256.is_some());
+        assert_eq!(packed.verify_assets(&manifest).unwrap(), 1);
+        packed.verify_signature(&policy).unwrap();
+
+        // Other keys are not trusted
+        let other = TrustPolicy::single(SigningKey::generate().unwrap().public_key());
+        assert!(packed.verify_signature(&other).is_err());
+
+        // The signature block does not disturb extraction
+        let extract_dir = temp_dir.path().join("extracted");
+        extract_assets(&output_path, &extract_dir).unwrap();
+        assert!(extract_dir.join("layers/signed123456.tar").exists());
+    }
+
+    #[test]
+    fn test_verify_assets_indexed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = pack_layer_for_signing(temp_dir.path(), false, None);
+        let packed = PackedFile::open(&output_path).unwrap();
+        let manifest = packed.manifest().unwrap();
+        assert_eq!(manifest.asset_index.len(), 1);
+        assert_eq!(manifest.asset_index[0].path, "layers/signed123456.tar");
+        assert_eq!(packed.verify_assets(&manifest).unwrap(), 1);
+
+        // The whole blob stays a plain tarball for readers without the index
+        let mut legacy = manifest.clone();
+        legacy.asset_index.clear();
+        assert_eq!(packed.verify_assets(&legacy).unwrap(), 1);
+
+        let mut tampered = manifest.clone();
+        tampered.assets.layers[0].sha256 = Some("0".repeat(64));
+        assert!(matches!(
+            packed.verify_assets(&tampered),
+            Err(PackError::Verification(_))
+        ));
+
+        let mut truncated = manifest;
+        truncated.asset_index[0].size = packed.footer().assets_size + 1;
+        assert!(packed.verify_assets(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_sign_existing_sidecar() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = pack_layer_for_signing(temp_dir.path(), false, None);
+        let key = SigningKey::generate().unwrap();
+        let policy = TrustPolicy::single(key.public_key());
+
+        let mut packed = PackedFile::open(&output_path).unwrap();
+        assert_eq!(packed.path(), sidecar_path_for(&output_path));
+        assert!(packed.signature().unwrap().is_none());
+        assert!(packed.verify_signature(&policy).is_err());
+
+        let checksum = packed.footer().checksum;
+        packed.sign(&key).unwrap();
+        // Re-signing replaces the block rather than stacking another
+        packed.sign(&key).unwrap();
+
+        let packed = PackedFile::open(&output_path).unwrap();
+        assert_eq!(packed.footer().checksum, checksum);
+        assert!(packed.verify_checksum().unwrap());
+        packed.verify_signature(&policy).unwrap();
+        assert!(verify_sidecar_checksum(packed.path(), packed.footer()).unwrap());
+    }
+
+    #[test]
+    fn test_signature_rejects_tampered_assets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let key = SigningKey::generate().unwrap();
+        let policy = TrustPolicy::single(key.public_key());
+        let output_path = pack_layer_for_signing(temp_dir.path(), true, Some(key));
+
+        let footer = read_footer(&output_path).unwrap();
+        let mut data = fs::read(&output_path).unwrap();
+        let last = (footer.assets_offset + footer.assets_size - 1) as usize;
+        data[last] ^= 0xff;
+        fs::write(&output_path, &data).unwrap();
+
+        let packed = PackedFile::open(&output_path).unwrap();
+        assert!(!packed.verify_checksum().unwrap());
+        assert!(matches!(
+            packed.verify_signature(&policy),
+            Err(PackError::Verification(_))
+        ));
+    }
+
     #[test]
     fn test_sidecar_checksum_verification() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -890,6 +1429,7 @@ mod tests {
             manifest_offset: 100,
             manifest_size: 32 * 1024 * 1024, // 32 MiB — exceeds cap
             checksum: 0,
+            signature_size: 0,
         };
 
         // Write a minimal sidecar: some bytes + footer
@@ -918,6 +1458,7 @@ mod tests {
             manifest_offset: 50, // should be 100 — points into assets region
             manifest_size: 50,
             checksum: 0,
+            signature_size: 0,
         };
 
         let footer_bytes = footer.to_bytes();
@@ -0,0 +1,742 @@
+//! Delta updates between two packs of the same machine.
+//!
+//! A `.smolpatch` carries the new manifest (and signature) plus only the
+//! assets whose contents are not already in the base pack, matched by the
+//! per-asset SHA-256 recorded in each [`AssetInventory`]. A new image
+//! version that changes one layer ships that layer, not the whole pack.
+//!
+//! Applying a patch rebuilds the assets archive from the base pack and the
+//! patch, checks every asset against the new manifest, and replaces the
+//! pack with a single rename. The rebuilt archive is written with
+//! [`compress_normalized`], so `create_patch` knows the exact pack a client
+//! will end up with: the patch records its checksum, and signing a patch
+//! signs that pack.
+//!
+//! The patch itself is a zstd-compressed tar:
+//!
+//! ```text
+//! smolpatch.json         PatchHeader
+//! manifest.json          new manifest, byte for byte
+//! signature.json         signature of the rebuilt pack (optional)
+//! assets/<path>...       assets not found in the base pack
+//! ```
+//!
+//! [`AssetInventory`]: crate::format::AssetInventory
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{
+    compress_normalized, crc32_file_range, normalized_header, sha256_file, sha256_file_range,
+    ZSTD_LEVEL,
+};
+use crate::format::{AssetFrame, PackFooter, PackManifest};
+use crate::packer::PackedFile;
+use crate::trust::{SigningKey, TrustPolicy};
+use crate::{PackError, Result};
+
+/// Current patch format version.
+pub const PATCH_FORMAT_VERSION: u32 = 1;
+
+const HEADER_ENTRY: &str = "smolpatch.json";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SIGNATURE_ENTRY: &str = "signature.json";
+const ASSETS_DIR: &str = "assets";
+
+/// Describes what a patch applies to and produces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchHeader {
+    /// Patch format version.
+    pub format_version: u32,
+    /// Checksum of the pack the patch applies to.
+    pub base_checksum: u32,
+    /// Checksum of the pack applying the patch produces.
+    pub target_checksum: u32,
+    /// Archive paths of the assets carried in the patch.
+    pub assets: Vec<String>,
+}
+
+/// Summary of a created patch.
+#[derive(Debug, Clone)]
+pub struct PatchInfo {
+    /// Checksum of the pack the patch applies to.
+    pub base_checksum: u32,
+    /// Checksum of the pack applying the patch produces.
+    pub target_checksum: u32,
+    /// Assets taken from the base pack.
+    pub reused: usize,
+    /// Assets carried in the patch.
+    pub added: usize,
+    /// Uncompressed size of the assets carried in the patch.
+    pub added_size: u64,
+    /// Size of the patch file.
+    pub patch_size: u64,
+}
+
+/// Create a patch that turns `base` into `target`.
+///
+/// Applying a patch rebuilds the assets archive. When that reproduces
+/// `target`'s assets byte for byte, as it does for packs written by this
+/// version, `target`'s signature carries over. Otherwise a signed `target`
+/// needs `key` to sign the rebuilt pack. `key` may also sign updates to
+/// unsigned packs, and replaces any signature on `target`.
+pub fn create_patch(
+    base: &PackedFile,
+    target: &PackedFile,
+    output: &Path,
+    key: Option<&SigningKey>,
+) -> Result<PatchInfo> {
+    check_pack(base)?;
+    check_pack(target)?;
+
+    let base_manifest = base.manifest()?;
+    let manifest_json = target.manifest_json()?;
+    let manifest = PackManifest::from_json(&manifest_json)?;
+    let wanted = asset_digests(&manifest)?;
+    let available = base_digests(&base_manifest);
+    let added: BTreeSet<&str> = wanted
+        .iter()
+        .filter(|(_, digest)| !available.contains_key(digest.as_str()))
+        .map(|(path, _)| path.as_str())
+        .collect();
+
+    // Stage the new assets as a client would after unpacking the patch,
+    // then rebuild to learn the resulting pack.
+    let work = tempfile::tempdir()?;
+    let staging = work.path().join(ASSETS_DIR);
+    let targets = added.iter().map(|p| (p.to_string(), vec![p.to_string()]));
+    unpack_assets(target, &targets.collect(), &staging)?;
+
+    let blob = work.path().join("assets.tar.zst");
+    let reused = rebuild(
+        base,
+        &base_manifest,
+        &wanted,
+        &manifest.asset_index,
+        &staging,
+        &blob,
+    )?;
+    let blob_sha256 = sha256_file_range(&blob, 0, fs::metadata(&blob)?.len())?;
+    let signature = match (key, target.signature()?) {
+        (Some(key), _) => key.sign(&manifest_json, &blob_sha256).to_json()?,
+        (None, Some(signature)) if blob_sha256 == target.assets_sha256()? => signature.to_json()?,
+        (None, Some(_)) => {
+            return Err(PackError::Signing(
+                "the new pack is signed and its assets cannot be rebuilt exactly, \
+                 so the patch must be signed too"
+                    .to_string(),
+            ))
+        }
+        (None, None) => Vec::new(),
+    };
+    let rebuilt = work.path().join("rebuilt");
+    let footer = write_pack(&rebuilt, None, &blob, &manifest_json, &signature)?;
+
+    let header = PatchHeader {
+        format_version: PATCH_FORMAT_VERSION,
+        base_checksum: base.footer().checksum,
+        target_checksum: footer.checksum,
+        assets: added.iter().map(|p| p.to_string()).collect(),
+    };
+
+    let encoder = zstd::stream::Encoder::new(BufWriter::new(File::create(output)?), ZSTD_LEVEL)
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    let mut builder = tar::Builder::new(encoder);
+    append_bytes(
+        &mut builder,
+        HEADER_ENTRY,
+        &serde_json::to_vec_pretty(&header)?,
+    )?;
+    append_bytes(&mut builder, MANIFEST_ENTRY, &manifest_json)?;
+    if !signature.is_empty() {
+        append_bytes(&mut builder, SIGNATURE_ENTRY, &signature)?;
+    }
+    let mut added_size = 0;
+    for path in &added {
+        let file = File::open(staging.join(path))?;
+        let size = file.metadata()?.len();
+        added_size += size;
+        builder
+            .append_data(
+                &mut normalized_header(size),
+                format!("{}/{}", ASSETS_DIR, path),
+                file,
+            )
+            .map_err(|e| PackError::Tar(e.to_string()))?;
+    }
+    let mut writer = builder
+        .into_inner()
+        .map_err(|e| PackError::Tar(e.to_string()))?
+        .finish()
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    writer.flush()?;
+    drop(writer);
+
+    Ok(PatchInfo {
+        base_checksum: header.base_checksum,
+        target_checksum: header.target_checksum,
+        reused,
+        added: added.len(),
+        added_size,
+        patch_size: fs::metadata(output)?.len(),
+    })
+}
+
+/// Read the header of a patch file.
+pub fn read_patch_header(patch: &Path) -> Result<PatchHeader> {
+    let decoder = zstd::stream::Decoder::new(File::open(patch)?)
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive
+        .entries()
+        .map_err(|e| PackError::Tar(e.to_string()))?;
+    let mut entry = match entries.next() {
+        Some(entry) => entry.map_err(|e| PackError::Tar(e.to_string()))?,
+        None => return Err(not_a_patch(patch)),
+    };
+    if entry.path().map_err(|e| PackError::Tar(e.to_string()))? != Path::new(HEADER_ENTRY) {
+        return Err(not_a_patch(patch));
+    }
+    let mut json = Vec::new();
+    entry.read_to_end(&mut json)?;
+    parse_header(&json)
+}
+
+/// Apply a patch to `pack`, replacing it in place.
+///
+/// The pack is only replaced once the rebuilt assets match the new
+/// manifest, the result has the checksum the patch promises and, with a
+/// `policy`, is signed by a trusted key. Returns the updated pack.
+pub fn apply_patch(
+    pack: &PackedFile,
+    patch: &Path,
+    policy: Option<&TrustPolicy>,
+) -> Result<PackedFile> {
+    check_pack(pack)?;
+
+    // Work next to the pack so the final rename stays on one filesystem.
+    let dir = match pack.path().parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let work = tempfile::Builder::new()
+        .prefix(".smolpatch-")
+        .tempdir_in(dir)?;
+    let unpacked = work.path().join("patch");
+    fs::create_dir_all(&unpacked)?;
+    let decoder = zstd::stream::Decoder::new(File::open(patch)?)
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    crate::extract::safe_unpack(&mut tar::Archive::new(decoder), &unpacked, None)
+        .map_err(|e| PackError::Tar(e.to_string()))?;
+
+    let header_path = unpacked.join(HEADER_ENTRY);
+    if !header_path.is_file() {
+        return Err(not_a_patch(patch));
+    }
+    let header = parse_header(&fs::read(header_path)?)?;
+    if header.base_checksum != pack.footer().checksum {
+        return Err(PackError::Verification(format!(
+            "patch applies to pack {:08x}, but this pack is {:08x}",
+            header.base_checksum,
+            pack.footer().checksum
+        )));
+    }
+
+    let manifest_json = fs::read(unpacked.join(MANIFEST_ENTRY))?;
+    let manifest = PackManifest::from_json(&manifest_json)?;
+    let wanted = asset_digests(&manifest)?;
+    let signature_path = unpacked.join(SIGNATURE_ENTRY);
+    let signature = if signature_path.is_file() {
+        fs::read(signature_path)?
+    } else {
+        Vec::new()
+    };
+
+    let staging = unpacked.join(ASSETS_DIR);
+    fs::create_dir_all(&staging)?;
+    let blob = work.path().join("assets.tar.zst");
+    rebuild(
+        pack,
+        &pack.manifest()?,
+        &wanted,
+        &manifest.asset_index,
+        &staging,
+        &blob,
+    )?;
+
+    // Embedded packs keep the stub in front of the assets.
+    let stub =
+        (pack.footer().assets_offset != 0).then(|| (pack.path(), pack.footer().assets_offset));
+    let staged = work.path().join("pack");
+    let footer = write_pack(&staged, stub, &blob, &manifest_json, &signature)?;
+    if footer.checksum != header.target_checksum {
+        return Err(PackError::Verification(format!(
+            "patched pack has checksum {:08x}, expected {:08x}; \
+             the patch may have been made by an incompatible smolvm version",
+            footer.checksum, header.target_checksum
+        )));
+    }
+    let updated = PackedFile::from_parts(staged.clone(), footer);
+    if let Some(policy) = policy {
+        updated.verify_signature(policy)?;
+    }
+
+    fs::set_permissions(&staged, fs::metadata(pack.path())?.permissions())?;
+    fs::rename(&staged, pack.path())?;
+    Ok(PackedFile::from_parts(pack.path().to_path_buf(), footer))
+}
+
+/// Fail unless the pack's checksum matches its contents.
+fn check_pack(pack: &PackedFile) -> Result<()> {
+    let footer = pack.footer();
+    let actual = crc32_file_range(
+        pack.path(),
+        footer.assets_offset,
+        footer.assets_size + footer.manifest_size,
+    )?;
+    if actual != footer.checksum {
+        return Err(PackError::ChecksumMismatch {
+            expected: footer.checksum,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+fn parse_header(json: &[u8]) -> Result<PatchHeader> {
+    let header: PatchHeader = serde_json::from_slice(json)?;
+    if header.format_version > PATCH_FORMAT_VERSION {
+        return Err(PackError::UnsupportedVersion(header.format_version));
+    }
+    Ok(header)
+}
+
+fn not_a_patch(path: &Path) -> PackError {
+    PackError::Verification(format!("{} is not a smolvm patch", path.display()))
+}
+
+/// Archive path to SHA-256 for every asset the manifest lists, across all
+/// platforms.
+fn asset_digests(manifest: &PackManifest) -> Result<BTreeMap<String, String>> {
+    let mut digests = BTreeMap::new();
+    for (path, sha256) in std::iter::once(&manifest.assets)
+        .chain(manifest.platforms.values())
+        .flat_map(|inventory| inventory.files())
+    {
+        let sha256 = sha256.ok_or_else(|| {
+            PackError::Verification(format!(
+                "asset {} has no digest; packs made before per-asset digests cannot be patched",
+                path
+            ))
+        })?;
+        if !Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(PackError::Verification(format!(
+                "asset path {} is not a plain relative path",
+                path
+            )));
+        }
+        digests.insert(path.to_string(), sha256.to_string());
+    }
+    Ok(digests)
+}
+
+/// SHA-256 to archive path for the assets of a base pack that have one.
+fn base_digests(manifest: &PackManifest) -> HashMap<&str, &str> {
+    std::iter::once(&manifest.assets)
+        .chain(manifest.platforms.values())
+        .flat_map(|inventory| inventory.files())
+        .filter_map(|(path, sha256)| Some((sha256?, path)))
+        .collect()
+}
+
+/// Assemble the assets `wanted` in `staging`, taking those not already
+/// there from `base`, check them all against their digests and compress
+/// them into `blob`, which must match `index` unless it is empty. Returns
+/// the number taken from `base`.
+fn rebuild(
+    base: &PackedFile,
+    base_manifest: &PackManifest,
+    wanted: &BTreeMap<String, String>,
+    index: &[AssetFrame],
+    staging: &Path,
+    blob: &Path,
+) -> Result<usize> {
+    let available = base_digests(base_manifest);
+    let mut targets: HashMap<String, Vec<String>> = HashMap::new();
+    for (path, digest) in wanted {
+        if staging.join(path).is_file() {
+            continue;
+        }
+        let source = available.get(digest.as_str()).ok_or_else(|| {
+            PackError::Verification(format!(
+                "asset {} is in neither the base pack nor the patch",
+                path
+            ))
+        })?;
+        targets
+            .entry(source.to_string())
+            .or_default()
+            .push(path.clone());
+    }
+    let reused = targets.values().map(Vec::len).sum();
+    unpack_assets(base, &targets, staging)?;
+
+    for (path, digest) in wanted {
+        let actual = sha256_file(&staging.join(path))?;
+        if &actual != digest {
+            return Err(PackError::Verification(format!(
+                "asset {} has SHA-256 {}, manifest says {}",
+                path, actual, digest
+            )));
+        }
+    }
+
+    let frames = compress_normalized(staging, wanted.keys().map(String::as_str), blob)?;
+    if !index.is_empty() && frames != index {
+        return Err(PackError::Verification(
+            "rebuilt assets do not match the manifest's asset index; \
+             the new pack may have been made by an incompatible smolvm version"
+                .to_string(),
+        ));
+    }
+    Ok(reused)
+}
+
+/// Copy assets out of a pack: each archive path in `targets` is written to
+/// the listed paths under `dest`.
+pub(crate) fn unpack_assets(
+    pack: &PackedFile,
+    targets: &HashMap<String, Vec<String>>,
+    dest: &Path,
+) -> Result<()> {
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let mut remaining = targets.len();
+
+    let footer = pack.footer();
+    let mut file = File::open(pack.path())?;
+    file.seek(SeekFrom::Start(footer.assets_offset))?;
+    let decoder = zstd::stream::Decoder::new(file.take(footer.assets_size))
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    let mut archive = tar::Archive::new(decoder);
+    for entry in archive
+        .entries()
+        .map_err(|e| PackError::Tar(e.to_string()))?
+    {
+        let mut entry = entry.map_err(|e| PackError::Tar(e.to_string()))?;
+        let path = entry
+            .path()
+            .map_err(|e| PackError::Tar(e.to_string()))?
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect::<PathBuf>();
+        let Some(outputs) = targets.get(path.to_string_lossy().as_ref()) else {
+            continue;
+        };
+
+        let first = dest.join(&outputs[0]);
+        if let Some(parent) = first.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(&first)?)?;
+        for other in &outputs[1..] {
+            let other = dest.join(other);
+            if let Some(parent) = other.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&first, other)?;
+        }
+
+        remaining -= 1;
+        if remaining == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Write a pack file: the stub prefix if any, then assets, manifest,
+/// signature block and footer.
+fn write_pack(
+    path: &Path,
+    stub: Option<(&Path, u64)>,
+    blob: &Path,
+    manifest_json: &[u8],
+    signature: &[u8],
+) -> Result<PackFooter> {
+    let mut file = File::create(path)?;
+    let stub_size = match stub {
+        Some((source, size)) => {
+            let copied = std::io::copy(&mut File::open(source)?.take(size), &mut file)?;
+            if copied != size {
+                return Err(PackError::Io(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "stub shorter than expected",
+                )));
+            }
+            size
+        }
+        None => 0,
+    };
+    let assets_size = std::io::copy(&mut File::open(blob)?, &mut file)?;
+    file.write_all(manifest_json)?;
+    file.flush()?;
+
+    let manifest_size = manifest_json.len() as u64;
+    let checksum = crc32_file_range(path, stub_size, assets_size + manifest_size)?;
+    let footer = PackFooter {
+        stub_size,
+        assets_offset: stub_size,
+        assets_size,
+        manifest_offset: stub_size + assets_size,
+        manifest_size,
+        checksum,
+        signature_size: signature.len() as u32,
+    };
+    file.write_all(signature)?;
+    file.write_all(&footer.to_bytes())?;
+    file.sync_all()?;
+    Ok(footer)
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> Result<()> {
+    builder
+        .append_data(&mut normalized_header(data.len() as u64), path, data)
+        .map_err(|e| PackError::Tar(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetCollector;
+    use crate::packer::Packer;
+
+    /// Pack a machine with the given layers, returning the packed binary.
+    fn pack_layers(dir: &Path, layers: &[(&str, &[u8])], embedded: bool) -> PathBuf {
+        pack_layers_as_of(dir, layers, embedded, None)
+    }
+
+    /// Like `pack_layers`, reproducibly when given a source date epoch.
+    fn pack_layers_as_of(
+        dir: &Path,
+        layers: &[(&str, &[u8])],
+        embedded: bool,
+        source_date_epoch: Option<u64>,
+    ) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let stub_path = dir.join("stub");
+        fs::write(&stub_path, b"#!/bin/sh\necho stub").unwrap();
+
+        // Packs share the rootfs, as releases of one machine would
+        let rootfs = dir.parent().unwrap().join("rootfs");
+        if !rootfs.exists() {
+            fs::create_dir_all(&rootfs).unwrap();
+            fs::write(rootfs.join("agent"), b"agent").unwrap();
+        }
+
+        let mut collector = AssetCollector::new(dir.join("staging")).unwrap();
+        if let Some(epoch) = source_date_epoch {
+            collector = collector.with_source_date_epoch(epoch);
+        }
+        collector.collect_agent_rootfs(&rootfs).unwrap();
+        for (digest, data) in layers {
+            collector.add_layer(digest, data).unwrap();
+        }
+        let manifest = PackManifest::new(
+            "test:patch".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+        let packer = Packer::new(manifest)
+            .with_stub(&stub_path)
+            .with_assets(collector);
+
+        let output_path = dir.join("packed");
+        if embedded {
+            packer.pack_embedded(&output_path).unwrap();
+        } else {
+            packer.pack(&output_path).unwrap();
+        }
+        output_path
+    }
+
+    const BASE_LAYER: (&str, &[u8]) = ("sha256:aaaaaaaaaaaa0001", b"base layer");
+    const OLD_LAYER: (&str, &[u8]) = ("sha256:bbbbbbbbbbbb0001", b"app v1");
+    const NEW_LAYER: (&str, &[u8]) = ("sha256:cccccccccccc0001", b"app v2");
+
+    #[test]
+    fn test_patch_round_trip() {
+        for embedded in [false, true] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let old = pack_layers(
+                &temp_dir.path().join("old"),
+                &[BASE_LAYER, OLD_LAYER],
+                embedded,
+            );
+            let new = pack_layers(
+                &temp_dir.path().join("new"),
+                &[BASE_LAYER, NEW_LAYER],
+                embedded,
+            );
+            let old = PackedFile::open(&old).unwrap();
+            let new = PackedFile::open(&new).unwrap();
+
+            let patch = temp_dir.path().join("update.smolpatch");
+            let info = create_patch(&old, &new, &patch, None).unwrap();
+            assert_eq!(info.base_checksum, old.footer().checksum);
+            assert_eq!(info.reused, 2);
+            assert_eq!(info.added, 1);
+            assert_eq!(info.added_size, NEW_LAYER.1.len() as u64);
+
+            let header = read_patch_header(&patch).unwrap();
+            assert_eq!(header.assets, vec!["layers/cccccccccccc.tar".to_string()]);
+
+            let updated = apply_patch(&old, &patch, None).unwrap();
+            assert_eq!(updated.path(), old.path());
+            assert_eq!(updated.footer().checksum, info.target_checksum);
+            assert_eq!(updated.footer().assets_offset == 0, !embedded);
+            assert!(updated.verify_checksum().unwrap());
+            assert_eq!(
+                updated.manifest_json().unwrap(),
+                new.manifest_json().unwrap()
+            );
+            let manifest = updated.manifest().unwrap();
+            assert_eq!(updated.verify_assets(&manifest).unwrap(), 3);
+
+            // Re-opening finds the same footer
+            let reopened = PackedFile::open(temp_dir.path().join("old/packed")).unwrap();
+            assert_eq!(reopened.footer().checksum, info.target_checksum);
+
+            // The patch no longer applies to the updated pack
+            assert!(matches!(
+                apply_patch(&updated, &patch, None),
+                Err(PackError::Verification(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_patch_across_reproducible_mode() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        header.set_mtime(1_900_000_000);
+        header.set_uid(0);
+        header.set_gid(0);
+        builder
+            .append_data(&mut header, "usr/app", &b"app v1"[..])
+            .unwrap();
+        let layer = builder.into_inner().unwrap();
+        let digest = "sha256:dddddddddddd0001";
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let normal = pack_layers(&temp_dir.path().join("normal"), &[(digest, &layer)], false);
+        let reproducible = pack_layers_as_of(
+            &temp_dir.path().join("reproducible"),
+            &[(digest, &layer)],
+            false,
+            Some(1_700_000_000),
+        );
+        let normal = PackedFile::open(&normal).unwrap();
+        let reproducible = PackedFile::open(&reproducible).unwrap();
+
+        // The normalized layer has other bytes, so it has another digest
+        let normal_layer = normal.manifest().unwrap().assets.layers.remove(0);
+        let layer_entry = reproducible.manifest().unwrap().assets.layers.remove(0);
+        assert_eq!(normal_layer.digest, digest);
+        assert_ne!(layer_entry.digest, digest);
+        assert_eq!(
+            layer_entry.digest,
+            format!("sha256:{}", layer_entry.sha256.as_deref().unwrap())
+        );
+        assert_ne!(layer_entry.path, normal_layer.path);
+
+        let patch = temp_dir.path().join("update.smolpatch");
+        create_patch(&normal, &reproducible, &patch, None).unwrap();
+        assert!(read_patch_header(&patch)
+            .unwrap()
+            .assets
+            .contains(&layer_entry.path));
+        let updated = apply_patch(&normal, &patch, None).unwrap();
+        let manifest = updated.manifest().unwrap();
+        assert_eq!(updated.verify_assets(&manifest).unwrap(), 2);
+        assert_eq!(manifest.assets.layers[0].digest, layer_entry.digest);
+    }
+
+    #[test]
+    fn test_patch_signed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let old = pack_layers(
+            &temp_dir.path().join("old"),
+            &[BASE_LAYER, OLD_LAYER],
+            false,
+        );
+        let new = pack_layers(
+            &temp_dir.path().join("new"),
+            &[BASE_LAYER, NEW_LAYER],
+            false,
+        );
+        let old = PackedFile::open(&old).unwrap();
+        let mut new = PackedFile::open(&new).unwrap();
+        let key = SigningKey::generate().unwrap();
+        let policy = TrustPolicy::single(key.public_key());
+        new.sign(&key).unwrap();
+
+        // The rebuilt assets match the new pack's, so its signature is kept
+        let patch = temp_dir.path().join("update.smolpatch");
+        let info = create_patch(&old, &new, &patch, None).unwrap();
+        assert_eq!(info.target_checksum, new.footer().checksum);
+        let copy = temp_dir.path().join("copy/packed.smolmachine");
+        fs::create_dir_all(copy.parent().unwrap()).unwrap();
+        fs::copy(old.path(), &copy).unwrap();
+        let updated =
+            apply_patch(&PackedFile::open(&copy).unwrap(), &patch, Some(&policy)).unwrap();
+        updated.verify_signature(&policy).unwrap();
+
+        // An untrusted signer is rejected and the pack left alone
+        let other = SigningKey::generate().unwrap();
+        create_patch(&old, &new, &patch, Some(&other)).unwrap();
+        assert!(apply_patch(&old, &patch, Some(&policy)).is_err());
+        assert!(old.verify_checksum().unwrap());
+        assert!(old.signature().unwrap().is_none());
+
+        create_patch(&old, &new, &patch, Some(&key)).unwrap();
+        let updated = apply_patch(&old, &patch, Some(&policy)).unwrap();
+        updated.verify_signature(&policy).unwrap();
+    }
+
+    #[test]
+    fn test_patch_rejects_other_base() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let old = pack_layers(&temp_dir.path().join("old"), &[BASE_LAYER], false);
+        let new = pack_layers(
+            &temp_dir.path().join("new"),
+            &[BASE_LAYER, NEW_LAYER],
+            false,
+        );
+        let other = pack_layers(&temp_dir.path().join("other"), &[OLD_LAYER], false);
+        let old = PackedFile::open(&old).unwrap();
+        let new = PackedFile::open(&new).unwrap();
+        let other = PackedFile::open(&other).unwrap();
+
+        // A patch made against another pack is refused
+        let patch = temp_dir.path().join("update.smolpatch");
+        let info = create_patch(&other, &new, &patch, None).unwrap();
+        assert_eq!(info.added, 2);
+        assert!(matches!(
+            apply_patch(&old, &patch, None),
+            Err(PackError::Verification(_))
+        ));
+
+        assert!(read_patch_header(old.path()).is_err());
+    }
+}
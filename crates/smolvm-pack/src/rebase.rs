@@ -0,0 +1,225 @@
+//! Moving an existing pack onto a different runtime.
+//!
+//! A pack keeps the stub, libkrun and agent it was made with, so upgrading
+//! smolvm does not reach binaries packed earlier. [`collect_rebased`]
+//! carries a pack's layers, disk templates and SBOM over into a new
+//! [`AssetCollector`] next to another runtime's libraries and agent
+//! rootfs, so it can be packed again without the original image or
+//! registry access.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::assets::AssetCollector;
+use crate::format::PackManifest;
+use crate::packer::PackedFile;
+use crate::patch::unpack_assets;
+use crate::Result;
+
+/// Runtime files for one platform of a rebased pack.
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    /// Directory holding libkrun and libkrunfw.
+    pub lib_dir: PathBuf,
+    /// Agent rootfs directory.
+    pub rootfs_dir: PathBuf,
+}
+
+/// Collect the assets of `pack` into `collector`, taking each platform's
+/// libraries and agent rootfs from `runtime` instead of the pack.
+///
+/// Layers, the storage template, the overlay template and the SBOM are
+/// carried over unchanged. `runtime` is asked for every platform before anything is
+/// unpacked. Returns the pack's manifest; pass the collector to
+/// [`Packer::with_assets`](crate::packer::Packer::with_assets) to replace
+/// its inventories.
+pub fn collect_rebased(
+    pack: &PackedFile,
+    collector: &mut AssetCollector,
+    mut runtime: impl FnMut(&str) -> Result<Runtime>,
+) -> Result<PackManifest> {
+    let mut manifest = pack.manifest()?;
+    let runtimes = manifest
+        .platform_names()
+        .into_iter()
+        .map(|platform| Ok((platform.to_string(), runtime(platform)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Everything kept from the old pack, unpacked under its archive path
+    let mut targets: HashMap<String, Vec<String>> = HashMap::new();
+    for (platform, _) in &runtimes {
+        let inventory = manifest
+            .inventory_for(platform)
+            .expect("platform_names lists only platforms with an inventory");
+        let kept = inventory
+            .storage_template
+            .iter()
+            .chain(inventory.overlay_template.iter())
+            .chain(inventory.sbom.iter())
+            .map(|a| &a.path)
+            .chain(inventory.layers.iter().map(|l| &l.path));
+        for path in kept {
+            targets
+                .entry(path.clone())
+                .or_insert_with(|| vec![path.clone()]);
+        }
+    }
+    let unpacked = tempfile::tempdir()?;
+    unpack_assets(pack, &targets, unpacked.path())?;
+
+    for (i, (platform, runtime)) in runtimes.iter().enumerate() {
+        let inventory = manifest
+            .inventory_for(platform)
+            .expect("platform_names lists only platforms with an inventory");
+        if i > 0 {
+            collector.begin_platform(platform)?;
+        }
+        collector.collect_libraries(&runtime.lib_dir)?;
+        collector.collect_agent_rootfs(&runtime.rootfs_dir)?;
+        if i == 0 {
+            // Later platforms share the primary's disk templates
+            if let Some(ref template) = inventory.storage_template {
+                collector.add_storage_template(&unpacked.path().join(&template.path))?;
+            }
+            if let Some(ref template) = inventory.overlay_template {
+                collector.add_overlay_template(&unpacked.path().join(&template.path))?;
+            }
+        }
+        for layer in &inventory.layers {
+            collector.add_layer_from_file(&layer.digest, &unpacked.path().join(&layer.path))?;
+        }
+        if let Some(ref sbom) = inventory.sbom {
+            collector.add_sbom(&std::fs::read(unpacked.path().join(&sbom.path))?)?;
+        }
+    }
+
+    // Indexes the old assets blob; packing writes a new one
+    manifest.asset_index.clear();
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packer::Packer;
+    use std::fs;
+    use std::path::Path;
+
+    /// Write a runtime whose libraries and agent hold `tag`.
+    fn runtime(dir: &Path, tag: &str) -> Runtime {
+        let lib_dir = dir.join("lib");
+        let rootfs_dir = dir.join("rootfs");
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::create_dir_all(&rootfs_dir).unwrap();
+        let (libkrun, libkrunfw) = if cfg!(target_os = "macos") {
+            ("libkrun.dylib", "libkrunfw.5.dylib")
+        } else {
+            ("libkrun.so", "libkrunfw.so.5")
+        };
+        fs::write(lib_dir.join(libkrun), tag).unwrap();
+        fs::write(lib_dir.join(libkrunfw), tag).unwrap();
+        fs::write(rootfs_dir.join("agent"), tag).unwrap();
+        Runtime {
+            lib_dir,
+            rootfs_dir,
+        }
+    }
+
+    /// Sha256 of every file in an inventory, by archive path.
+    fn digests(manifest: &PackManifest, platform: &str) -> HashMap<String, String> {
+        manifest
+            .inventory_for(platform)
+            .unwrap()
+            .files()
+            .map(|(path, sha256)| (path.to_string(), sha256.unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_rebased() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+        let template = temp_dir.path().join("storage.ext4");
+        fs::write(&template, b"ext4").unwrap();
+
+        let old_amd64 = runtime(&temp_dir.path().join("old-amd64"), "old amd64");
+        let old_arm64 = runtime(&temp_dir.path().join("old-arm64"), "old arm64");
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        collector.collect_libraries(&old_amd64.lib_dir).unwrap();
+        collector
+            .collect_agent_rootfs(&old_amd64.rootfs_dir)
+            .unwrap();
+        collector.add_storage_template(&template).unwrap();
+        collector
+            .add_layer("sha256:aaaaaaaaaaaa01", b"base")
+            .unwrap();
+        collector
+            .add_layer("sha256:bbbbbbbbbbbb01", b"app")
+            .unwrap();
+        collector.begin_platform("linux/arm64").unwrap();
+        collector.collect_libraries(&old_arm64.lib_dir).unwrap();
+        collector
+            .collect_agent_rootfs(&old_arm64.rootfs_dir)
+            .unwrap();
+        collector
+            .add_layer("sha256:aaaaaaaaaaaa01", b"base")
+            .unwrap();
+        collector
+            .add_layer("sha256:cccccccccccc01", b"app arm")
+            .unwrap();
+        collector.add_sbom(b"{}").unwrap();
+
+        let manifest = PackManifest::new(
+            "test:rebase".to_string(),
+            "sha256:test".to_string(),
+            "linux/amd64".to_string(),
+        );
+        let old_path = temp_dir.path().join("old");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&old_path)
+            .unwrap();
+        let old = PackedFile::open(&old_path).unwrap();
+        let old_manifest = old.manifest().unwrap();
+
+        let new_amd64 = runtime(&temp_dir.path().join("new-amd64"), "new amd64");
+        let new_arm64 = runtime(&temp_dir.path().join("new-arm64"), "new arm64");
+        let mut asked = Vec::new();
+        let mut collector = AssetCollector::new(temp_dir.path().join("rebased")).unwrap();
+        let manifest = collect_rebased(&old, &mut collector, |platform| {
+            asked.push(platform.to_string());
+            Ok(match platform {
+                "linux/amd64" => new_amd64.clone(),
+                _ => new_arm64.clone(),
+            })
+        })
+        .unwrap();
+        assert_eq!(asked, ["linux/amd64", "linux/arm64"]);
+        assert!(manifest.asset_index.is_empty());
+
+        let new_path = temp_dir.path().join("new");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&new_path)
+            .unwrap();
+        let new = PackedFile::open(&new_path).unwrap();
+        let new_manifest = new.manifest().unwrap();
+        assert_eq!(new.verify_assets(&new_manifest).unwrap(), 11);
+
+        for platform in ["linux/amd64", "linux/arm64"] {
+            let before = digests(&old_manifest, platform);
+            let after = digests(&new_manifest, platform);
+            for (path, sha256) in &after {
+                if path.contains("lib/") || path.ends_with("agent-rootfs.tar") {
+                    assert_ne!(&before[path], sha256, "{} should be replaced", path);
+                } else {
+                    assert_eq!(&before[path], sha256, "{} should be kept", path);
+                }
+            }
+            assert_eq!(before.len(), after.len());
+        }
+    }
+}
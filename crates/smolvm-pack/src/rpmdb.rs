@@ -0,0 +1,623 @@
+//! Installed packages from an RPM database.
+//!
+//! RPM keeps one header blob per installed package, in SQLite
+//! (`rpmdb.sqlite`, RPM 4.16 and later) or a Berkeley DB hash file
+//! (`Packages`). Both are read directly, without librpm or SQLite, just far
+//! enough to get those blobs and the package fields in them.
+
+/// Package fields from an RPM header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RpmPackage {
+    pub name: String,
+    pub version: String,
+    pub release: String,
+    pub epoch: Option<u32>,
+    pub arch: Option<String>,
+    pub license: Option<String>,
+}
+
+/// Packages in the database `db`, or `None` if it cannot be read.
+///
+/// The `gpg-pubkey` entries RPM uses for imported keys are left out.
+pub(crate) fn read_packages(db: &[u8]) -> Option<Vec<RpmPackage>> {
+    let blobs = if db.starts_with(b"SQLite format 3\0") {
+        sqlite_blobs(db)?
+    } else {
+        bdb_blobs(db)?
+    };
+    Some(
+        blobs
+            .iter()
+            .filter_map(|blob| parse_header(blob))
+            .filter(|p| p.name != "gpg-pubkey")
+            .collect(),
+    )
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// RPM header tags and types
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_EPOCH: u32 = 1003;
+const TAG_LICENSE: u32 = 1014;
+const TAG_ARCH: u32 = 1022;
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_I18NSTRING: u32 = 9;
+
+/// Parse a header blob as stored in the database: entry count, data size,
+/// the index entries, then the data they point into.
+fn parse_header(blob: &[u8]) -> Option<RpmPackage> {
+    let entries = be32(blob, 0)? as usize;
+    let data_size = be32(blob, 4)? as usize;
+    let data_start = entries.checked_mul(16)?.checked_add(8)?;
+    let data = blob.get(data_start..data_start.checked_add(data_size)?)?;
+
+    let mut package = RpmPackage::default();
+    for i in 0..entries {
+        let entry = 8 + i * 16;
+        let tag = be32(blob, entry)?;
+        let kind = be32(blob, entry + 4)?;
+        let offset = be32(blob, entry + 8)? as usize;
+        let string = || {
+            if kind != TYPE_STRING && kind != TYPE_I18NSTRING {
+                return None;
+            }
+            let rest = data.get(offset..)?;
+            let end = rest.iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+        };
+        match tag {
+            TAG_NAME => package.name = string()?,
+            TAG_VERSION => package.version = string()?,
+            TAG_RELEASE => package.release = string()?,
+            TAG_EPOCH if kind == TYPE_INT32 => package.epoch = be32(data, offset),
+            TAG_LICENSE => package.license = string(),
+            TAG_ARCH => package.arch = string(),
+            _ => {}
+        }
+    }
+    (!package.name.is_empty()).then_some(package)
+}
+
+/// Header blobs from the `Packages` table of an SQLite database.
+fn sqlite_blobs(db: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let page_size = match be16(db, 16)? {
+        1 => 65536,
+        n => n as usize,
+    };
+    let usable = page_size.checked_sub(*db.get(20)? as usize)?;
+    if usable < 480 {
+        return None;
+    }
+    let sqlite = Sqlite {
+        db,
+        page_size,
+        usable,
+    };
+
+    // The schema table on page 1 has rows (type, name, tbl_name, rootpage, sql)
+    let mut root = None;
+    sqlite.walk(1, 0, &mut |payload| {
+        let columns = record(&payload)?;
+        if let [Column::Text(b"table"), Column::Text(b"Packages"), _, Column::Int(page), ..] =
+            columns[..]
+        {
+            root = u32::try_from(page).ok();
+        }
+        Some(())
+    })?;
+
+    // Rows are (hnum, blob), with hnum the rowid and stored as NULL
+    let mut blobs = Vec::new();
+    sqlite.walk(root?, 0, &mut |payload| {
+        if let Some(Column::Blob(blob)) = record(&payload)?.get(1) {
+            blobs.push(blob.to_vec());
+        }
+        Some(())
+    })?;
+    Some(blobs)
+}
+
+struct Sqlite<'a> {
+    db: &'a [u8],
+    page_size: usize,
+    usable: usize,
+}
+
+impl Sqlite<'_> {
+    fn page(&self, number: u32) -> Option<&[u8]> {
+        let start = (number as usize)
+            .checked_sub(1)?
+            .checked_mul(self.page_size)?;
+        self.db.get(start..start + self.page_size)
+    }
+
+    /// Call `visit` with the payload of every row in the table b-tree
+    /// rooted at page `number`.
+    fn walk(
+        &self,
+        number: u32,
+        depth: usize,
+        visit: &mut dyn FnMut(Vec<u8>) -> Option<()>,
+    ) -> Option<()> {
+        if depth > 32 {
+            return None;
+        }
+        let page = self.page(number)?;
+        let header = if number == 1 { 100 } else { 0 };
+        let cells = be16(page, header + 3)? as usize;
+        match page.get(header)? {
+            // Interior table page: child pointers, then the rightmost child
+            0x05 => {
+                for i in 0..cells {
+                    let cell = be16(page, header + 12 + 2 * i)? as usize;
+                    self.walk(be32(page, cell)?, depth + 1, visit)?;
+                }
+                self.walk(be32(page, header + 8)?, depth + 1, visit)
+            }
+            // Leaf table page: payload size, rowid, payload
+            0x0d => {
+                for i in 0..cells {
+                    let cell = be16(page, header + 8 + 2 * i)? as usize;
+                    let (size, n) = varint(page.get(cell..)?)?;
+                    let (_, m) = varint(page.get(cell + n..)?)?;
+                    visit(self.payload(page, cell + n + m, usize::try_from(size).ok()?)?)?;
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    /// A cell's payload of `size` bytes at `offset` in `page`, following
+    /// overflow pages for the part that does not fit.
+    fn payload(&self, page: &[u8], offset: usize, size: usize) -> Option<Vec<u8>> {
+        let max_local = self.usable - 35;
+        if size <= max_local {
+            return page.get(offset..offset + size).map(<[u8]>::to_vec);
+        }
+        let min_local = (self.usable - 12) * 32 / 255 - 23;
+        let local = min_local + (size - min_local) % (self.usable - 4);
+        let local = if local <= max_local { local } else { min_local };
+
+        let mut payload = page.get(offset..offset + local)?.to_vec();
+        let mut next = be32(page, offset + local)?;
+        let mut pages = 0;
+        while payload.len() < size {
+            pages += 1;
+            if pages > self.db.len() / self.page_size {
+                return None;
+            }
+            let overflow = self.page(next)?;
+            next = be32(overflow, 0)?;
+            let take = (size - payload.len()).min(self.usable - 4);
+            payload.extend_from_slice(overflow.get(4..4 + take)?);
+        }
+        Some(payload)
+    }
+}
+
+/// A column of an SQLite record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column<'a> {
+    Null,
+    Int(i64),
+    Blob(&'a [u8]),
+    Text(&'a [u8]),
+}
+
+/// Split an SQLite record into its columns.
+fn record(payload: &[u8]) -> Option<Vec<Column<'_>>> {
+    let (header_size, mut pos) = varint(payload)?;
+    let header_size = usize::try_from(header_size).ok()?;
+    let mut types = Vec::new();
+    while pos < header_size {
+        let (serial_type, n) = varint(payload.get(pos..)?)?;
+        types.push(serial_type);
+        pos += n;
+    }
+
+    let mut body = header_size;
+    let mut columns = Vec::with_capacity(types.len());
+    for serial_type in types {
+        let (column, size) = match serial_type {
+            0 => (Column::Null, 0),
+            1..=6 => {
+                let size = [1, 2, 3, 4, 6, 8][serial_type as usize - 1];
+                let bytes = payload.get(body..body + size)?;
+                let value = bytes
+                    .iter()
+                    .fold(if bytes[0] & 0x80 != 0 { -1i64 } else { 0 }, |v, &b| {
+                        (v << 8) | b as i64
+                    });
+                (Column::Int(value), size)
+            }
+            7 => (Column::Null, 8),
+            8 => (Column::Int(0), 0),
+            9 => (Column::Int(1), 0),
+            n if n >= 12 => {
+                let size = usize::try_from((n - 12) / 2).ok()?;
+                let bytes = payload.get(body..body.checked_add(size)?)?;
+                if n % 2 == 0 {
+                    (Column::Blob(bytes), size)
+                } else {
+                    (Column::Text(bytes), size)
+                }
+            }
+            _ => return None,
+        };
+        columns.push(column);
+        body += size;
+    }
+    Some(columns)
+}
+
+/// Decode an SQLite varint, returning it and its length.
+fn varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *data.get(i)?;
+        if i == 8 {
+            return Some(((value << 8) | byte as u64, 9));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// Berkeley DB hash database layout
+const BDB_HASH_MAGIC: u32 = 0x061561;
+const BDB_PAGE_HEADER: usize = 26;
+const BDB_HASH_UNSORTED: u8 = 2;
+const BDB_HASH: u8 = 13;
+const BDB_KEYDATA: u8 = 1;
+const BDB_OFFPAGE: u8 = 3;
+
+/// Values stored in a Berkeley DB hash database.
+fn bdb_blobs(db: &[u8]) -> Option<Vec<Vec<u8>>> {
+    // Pages are in the byte order of the machine that wrote them
+    let little = u32::from_le_bytes(db.get(12..16)?.try_into().ok()?) == BDB_HASH_MAGIC;
+    if !little && be32(db, 12)? != BDB_HASH_MAGIC {
+        return None;
+    }
+    let u16_at = |data: &[u8], offset: usize| -> Option<usize> {
+        let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        } as usize)
+    };
+    let u32_at = |data: &[u8], offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let page_size = u32_at(db, 20)? as usize;
+    if page_size < 512 {
+        return None;
+    }
+    let page = |number: u32| db.get(number as usize * page_size..(number as usize + 1) * page_size);
+
+    let mut blobs = Vec::new();
+    for number in 1..(db.len() / page_size) as u32 {
+        let data = page(number)?;
+        if !matches!(data[25], BDB_HASH | BDB_HASH_UNSORTED) {
+            continue;
+        }
+        // Items come in key/value pairs; each ends where the previous one
+        // starts, counting down from the end of the page
+        let items = u16_at(data, 20)?;
+        for i in (1..items).step_by(2) {
+            let start = u16_at(data, BDB_PAGE_HEADER + 2 * i)?;
+            let end = u16_at(data, BDB_PAGE_HEADER + 2 * (i - 1))?;
+            match *data.get(start)? {
+                BDB_KEYDATA => blobs.push(data.get(start + 1..end)?.to_vec()),
+                BDB_OFFPAGE => {
+                    let mut next = u32_at(data, start + 4)?;
+                    let size = u32_at(data, start + 8)? as usize;
+                    let mut blob = Vec::with_capacity(size.min(db.len()));
+                    let mut pages = 0;
+                    while blob.len() < size && next != 0 {
+                        pages += 1;
+                        if pages > db.len() / page_size {
+                            return None;
+                        }
+                        let overflow = page(next)?;
+                        let used = u16_at(overflow, 22)?;
+                        blob.extend_from_slice(
+                            overflow.get(BDB_PAGE_HEADER..BDB_PAGE_HEADER + used)?,
+                        );
+                        next = u32_at(overflow, 16)?;
+                    }
+                    blob.truncate(size);
+                    blobs.push(blob);
+                }
+                _ => {}
+            }
+        }
+    }
+    Some(blobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header blob with the given string tags and an epoch.
+    fn header(strings: &[(u32, &str)], epoch: Option<u32>) -> Vec<u8> {
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        for (tag, value) in strings {
+            index.extend_from_slice(&tag.to_be_bytes());
+            index.extend_from_slice(&TYPE_STRING.to_be_bytes());
+            index.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            index.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(value.as_bytes());
+            data.push(0);
+        }
+        if let Some(epoch) = epoch {
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            index.extend_from_slice(&TAG_EPOCH.to_be_bytes());
+            index.extend_from_slice(&TYPE_INT32.to_be_bytes());
+            index.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            index.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(&epoch.to_be_bytes());
+        }
+        let entries = strings.len() + epoch.is_some() as usize;
+        let mut blob = Vec::new();
+        blob.extend_from_slice(&(entries as u32).to_be_bytes());
+        blob.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        blob.extend_from_slice(&index);
+        blob.extend_from_slice(&data);
+        blob
+    }
+
+    fn bash_header() -> Vec<u8> {
+        header(
+            &[
+                (TAG_NAME, "bash"),
+                (TAG_VERSION, "5.2.15"),
+                (TAG_RELEASE, "2.fc38"),
+                (TAG_ARCH, "x86_64"),
+                (TAG_LICENSE, "GPL-3.0-or-later"),
+            ],
+            Some(1),
+        )
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let package = parse_header(&bash_header()).unwrap();
+        assert_eq!(
+            package,
+            RpmPackage {
+                name: "bash".to_string(),
+                version: "5.2.15".to_string(),
+                release: "2.fc38".to_string(),
+                epoch: Some(1),
+                arch: Some("x86_64".to_string()),
+                license: Some("GPL-3.0-or-later".to_string()),
+            }
+        );
+        assert!(parse_header(&[0, 0, 0, 9]).is_none());
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(&[0x05]), Some((5, 1)));
+        assert_eq!(varint(&[0x81, 0x00]), Some((128, 2)));
+        assert_eq!(varint(&[0xff; 9]), Some((u64::MAX, 9)));
+        assert_eq!(varint(&[0x81]), None);
+    }
+
+    /// Append an SQLite varint.
+    fn put_varint(out: &mut Vec<u8>, value: u64) {
+        assert!(value < 1 << 56);
+        let mut groups = vec![(value & 0x7f) as u8];
+        let mut rest = value >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        out.extend(groups.iter().rev());
+    }
+
+    /// A leaf table page at `number` holding `rows`, each `(rowid,
+    /// record)`; records that do not fit spill to the pages listed.
+    fn leaf_page(
+        page_size: usize,
+        number: u32,
+        rows: &[(u64, Vec<u8>)],
+        overflow: &mut Vec<Vec<u8>>,
+        first_overflow: u32,
+    ) -> Vec<u8> {
+        let usable = page_size;
+        let header = if number == 1 { 100 } else { 0 };
+        let mut page = vec![0u8; page_size];
+        page[header] = 0x0d;
+        page[header + 3..header + 5].copy_from_slice(&(rows.len() as u16).to_be_bytes());
+        let mut end = page_size;
+        for (i, (rowid, payload)) in rows.iter().enumerate() {
+            let max_local = usable - 35;
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let mut cell = Vec::new();
+            put_varint(&mut cell, payload.len() as u64);
+            put_varint(&mut cell, *rowid);
+            if payload.len() <= max_local {
+                cell.extend_from_slice(payload);
+            } else {
+                let local = min_local + (payload.len() - min_local) % (usable - 4);
+                let local = if local <= max_local { local } else { min_local };
+                cell.extend_from_slice(&payload[..local]);
+                let chunks: Vec<&[u8]> = payload[local..].chunks(usable - 4).collect();
+                let first = first_overflow + overflow.len() as u32;
+                cell.extend_from_slice(&first.to_be_bytes());
+                for (j, chunk) in chunks.iter().enumerate() {
+                    let mut page = vec![0u8; page_size];
+                    let next = if j + 1 < chunks.len() {
+                        first + j as u32 + 1
+                    } else {
+                        0
+                    };
+                    page[..4].copy_from_slice(&next.to_be_bytes());
+                    page[4..4 + chunk.len()].copy_from_slice(chunk);
+                    overflow.push(page);
+                }
+            }
+            end -= cell.len();
+            page[end..end + cell.len()].copy_from_slice(&cell);
+            let pointer = header + 8 + 2 * i;
+            page[pointer..pointer + 2].copy_from_slice(&(end as u16).to_be_bytes());
+        }
+        page
+    }
+
+    /// An SQLite record of text, integer and blob columns.
+    fn sqlite_record(columns: &[Column<'_>]) -> Vec<u8> {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        for column in columns {
+            match column {
+                Column::Null => header.push(0),
+                Column::Int(v) => {
+                    header.push(4);
+                    body.extend_from_slice(&(*v as u32).to_be_bytes());
+                }
+                Column::Text(t) => {
+                    put_varint(&mut header, 13 + 2 * t.len() as u64);
+                    body.extend_from_slice(t);
+                }
+                Column::Blob(b) => {
+                    put_varint(&mut header, 12 + 2 * b.len() as u64);
+                    body.extend_from_slice(b);
+                }
+            }
+        }
+        let mut record = Vec::new();
+        // Header sizes used here fit one varint byte with room to spare
+        record.push(header.len() as u8 + 1);
+        record.extend_from_slice(&header);
+        record.extend_from_slice(&body);
+        record
+    }
+
+    #[test]
+    fn test_sqlite_packages() {
+        let page_size = 4096;
+        let blob = bash_header();
+        let large = header(
+            &[
+                (TAG_NAME, "glibc"),
+                (TAG_VERSION, "2.37"),
+                (TAG_RELEASE, "4.fc38"),
+                (TAG_LICENSE, &"LGPL-2.1-or-later ".repeat(300)),
+            ],
+            None,
+        );
+        let key = header(&[(TAG_NAME, "gpg-pubkey"), (TAG_VERSION, "1")], None);
+
+        let mut overflow = Vec::new();
+        let schema = leaf_page(
+            page_size,
+            1,
+            &[(
+                1,
+                sqlite_record(&[
+                    Column::Text(b"table"),
+                    Column::Text(b"Packages"),
+                    Column::Text(b"Packages"),
+                    Column::Int(2),
+                    Column::Text(b"CREATE TABLE Packages (hnum INTEGER PRIMARY KEY, blob BLOB)"),
+                ]),
+            )],
+            &mut overflow,
+            3,
+        );
+        let packages = leaf_page(
+            page_size,
+            2,
+            &[
+                (1, sqlite_record(&[Column::Null, Column::Blob(&blob)])),
+                (2, sqlite_record(&[Column::Null, Column::Blob(&large)])),
+                (3, sqlite_record(&[Column::Null, Column::Blob(&key)])),
+            ],
+            &mut overflow,
+            3,
+        );
+        let mut db = schema;
+        db[..16].copy_from_slice(b"SQLite format 3\0");
+        db[16..18].copy_from_slice(&(page_size as u16).to_be_bytes());
+        db.extend_from_slice(&packages);
+        for page in overflow {
+            db.extend_from_slice(&page);
+        }
+
+        let packages = read_packages(&db).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "bash");
+        assert_eq!(packages[1].name, "glibc");
+        assert_eq!(packages[1].license.as_ref().unwrap().len(), 18 * 300);
+    }
+
+    #[test]
+    fn test_bdb_packages() {
+        let page_size = 512usize;
+        let blob = bash_header();
+        let mut db = vec![0u8; page_size];
+        db[12..16].copy_from_slice(&BDB_HASH_MAGIC.to_le_bytes());
+        db[20..24].copy_from_slice(&(page_size as u32).to_le_bytes());
+
+        // Page 1: one pair whose value is on overflow pages 2 and 3
+        let mut hash = vec![0u8; page_size];
+        hash[25] = BDB_HASH;
+        hash[20..22].copy_from_slice(&2u16.to_le_bytes());
+        let key_at = page_size - 5;
+        hash[key_at] = BDB_KEYDATA;
+        hash[key_at + 1..].copy_from_slice(&1u32.to_le_bytes());
+        let value_at = key_at - 12;
+        hash[value_at] = BDB_OFFPAGE;
+        hash[value_at + 4..value_at + 8].copy_from_slice(&2u32.to_le_bytes());
+        hash[value_at + 8..value_at + 12].copy_from_slice(&(blob.len() as u32).to_le_bytes());
+        hash[26..28].copy_from_slice(&(key_at as u16).to_le_bytes());
+        hash[28..30].copy_from_slice(&(value_at as u16).to_le_bytes());
+        db.extend_from_slice(&hash);
+
+        let split = 60;
+        for (i, chunk) in [&blob[..split], &blob[split..]].iter().enumerate() {
+            let mut page = vec![0u8; page_size];
+            page[25] = 7;
+            let next = if i == 0 { 3u32 } else { 0 };
+            page[16..20].copy_from_slice(&next.to_le_bytes());
+            page[22..24].copy_from_slice(&(chunk.len() as u16).to_le_bytes());
+            page[26..26 + chunk.len()].copy_from_slice(chunk);
+            db.extend_from_slice(&page);
+        }
+
+        let packages = read_packages(&db).unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].version, "5.2.15");
+        assert!(read_packages(b"not a database").is_none());
+    }
+}
@@ -0,0 +1,925 @@
+//! Software bills of materials for packed images.
+//!
+//! [`scan_layers`] reads an image's layer tarballs in order, applying
+//! whiteouts, and lists the packages recorded in OS package databases
+//! (apk, dpkg, rpm) and language lockfiles (npm, Cargo, Poetry, Pipenv,
+//! Composer, Bundler). [`collect_sbom`] stores them as a CycloneDX JSON
+//! document in a platform's assets, referenced from its inventory, and
+//! [`read_sbom`] reads it back from a pack.
+//!
+//! Only what those files record is listed: binaries copied into an image
+//! by hand, or installed without a lockfile, do not show up.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::assets::{hex_encode, AssetCollector};
+use crate::format::{AssetInventory, Provenance};
+use crate::packer::PackedFile;
+use crate::patch::unpack_assets;
+use crate::{rpmdb, PackError, Result};
+
+/// Name of the SBOM asset; additional platforms keep theirs under
+/// `platforms/<os>-<arch>/`.
+pub const SBOM_NAME: &str = "sbom.cdx.json";
+
+/// CycloneDX specification version of the documents written.
+const CYCLONEDX_VERSION: &str = "1.5";
+
+/// Largest package database or lockfile read from a layer.
+const MAX_SCANNED_FILE: u64 = 256 * 1024 * 1024;
+
+/// A package found in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    /// Package URL type: apk, deb, rpm, npm, cargo, pypi, composer or gem.
+    pub kind: &'static str,
+    /// Package name.
+    pub name: String,
+    /// Package version.
+    pub version: String,
+    /// Package URL (purl) identifying the package.
+    pub purl: String,
+    /// License, as the package database records it.
+    pub license: Option<String>,
+    /// Path of the database or lockfile listing the package.
+    pub location: String,
+}
+
+/// Operating system named by the image's `os-release` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OsRelease {
+    /// Distribution ID (e.g. `alpine`, `debian`).
+    pub id: String,
+    /// Distribution version (e.g. `3.19.1`).
+    pub version_id: Option<String>,
+    /// Human-readable name.
+    pub pretty_name: Option<String>,
+}
+
+/// What [`scan_layers`] found.
+#[derive(Debug, Clone, Default)]
+pub struct Scan {
+    /// Operating system of the image, if it says.
+    pub os: Option<OsRelease>,
+    /// Packages, sorted by package URL and listed once each.
+    pub packages: Vec<Package>,
+    /// Package databases and lockfiles that could not be parsed.
+    pub unreadable: Vec<String>,
+}
+
+/// Kinds of files the scanner reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    OsRelease,
+    Apk,
+    Dpkg,
+    Rpm,
+    NpmLock,
+    TomlLock(&'static str),
+    PipfileLock,
+    ComposerLock,
+    GemfileLock,
+}
+
+/// What the file at `path` (relative to the image root) records, if the
+/// scanner reads it.
+fn source(path: &str) -> Option<Source> {
+    match path {
+        "etc/os-release" | "usr/lib/os-release" => return Some(Source::OsRelease),
+        "lib/apk/db/installed" => return Some(Source::Apk),
+        "var/lib/dpkg/status" => return Some(Source::Dpkg),
+        "var/lib/rpm/rpmdb.sqlite"
+        | "var/lib/rpm/Packages"
+        | "usr/lib/sysimage/rpm/rpmdb.sqlite"
+        | "usr/lib/sysimage/rpm/Packages" => return Some(Source::Rpm),
+        _ => {}
+    }
+    // Distroless images keep one dpkg status file per package
+    if let Some(name) = path.strip_prefix("var/lib/dpkg/status.d/") {
+        return (!name.contains('/') && !name.ends_with(".md5sums")).then_some(Source::Dpkg);
+    }
+
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    // npm's hidden lockfile lists what is installed in node_modules;
+    // lockfiles inside installed packages describe their own development
+    if name == ".package-lock.json" {
+        return (dir == "node_modules" || dir.ends_with("/node_modules"))
+            .then_some(Source::NpmLock);
+    }
+    if dir
+        .split('/')
+        .any(|c| matches!(c, "node_modules" | "gems" | ".cargo"))
+    {
+        return None;
+    }
+    match name {
+        "package-lock.json" => Some(Source::NpmLock),
+        "Cargo.lock" => Some(Source::TomlLock("cargo")),
+        "poetry.lock" => Some(Source::TomlLock("pypi")),
+        "Pipfile.lock" => Some(Source::PipfileLock),
+        "composer.lock" => Some(Source::ComposerLock),
+        "Gemfile.lock" => Some(Source::GemfileLock),
+        _ => None,
+    }
+}
+
+/// Path of a tar entry relative to the image root, without `./` or a
+/// leading `/`.
+fn image_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Remove the files under directory `dir` ("" for the root).
+fn remove_under(files: &mut BTreeMap<String, Vec<u8>>, dir: &str) {
+    if dir.is_empty() {
+        files.clear();
+    } else {
+        let prefix = format!("{}/", dir);
+        files.retain(|path, _| !path.starts_with(&prefix));
+    }
+}
+
+/// List the packages in an image from its layer tarballs, lowest first.
+pub fn scan_layers<P: AsRef<Path>>(layers: impl IntoIterator<Item = P>) -> Result<Scan> {
+    // Contents of the files the scanner reads, as the image ends up
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for layer in layers {
+        let layer = layer.as_ref();
+        let tar_err = |e: std::io::Error| PackError::Tar(format!("{}: {}", layer.display(), e));
+
+        // Whiteouts only hide files from lower layers
+        let mut added: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        let mut archive = tar::Archive::new(File::open(layer)?);
+        for entry in archive.entries().map_err(tar_err)? {
+            let mut entry = entry.map_err(tar_err)?;
+            let path = image_path(&entry.path().map_err(tar_err)?);
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", path.as_str()));
+            if name == ".wh..wh..opq" {
+                remove_under(&mut files, dir);
+                continue;
+            }
+            if let Some(hidden) = name.strip_prefix(".wh.") {
+                let hidden = match dir {
+                    "" => hidden.to_string(),
+                    _ => format!("{}/{}", dir, hidden),
+                };
+                files.remove(&hidden);
+                remove_under(&mut files, &hidden);
+                continue;
+            }
+
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                continue;
+            }
+            // Anything else replaces what lower layers had at the path
+            files.remove(&path);
+            remove_under(&mut files, &path);
+            if source(&path).is_none() {
+                continue;
+            }
+            if entry_type.is_file() && entry.size() <= MAX_SCANNED_FILE {
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data).map_err(tar_err)?;
+                added.insert(path, data);
+            } else if entry_type.is_hard_link() {
+                let target = match entry.link_name().map_err(tar_err)? {
+                    Some(target) => image_path(&target),
+                    None => continue,
+                };
+                if let Some(data) = added.get(&target).or_else(|| files.get(&target)) {
+                    added.insert(path, data.clone());
+                }
+            }
+        }
+        files.extend(added);
+    }
+
+    let os = files
+        .get("etc/os-release")
+        .or_else(|| files.get("usr/lib/os-release"))
+        .and_then(|data| parse_os_release(&String::from_utf8_lossy(data)));
+    let mut scan = Scan {
+        os,
+        ..Default::default()
+    };
+    for (path, data) in &files {
+        let text = || String::from_utf8_lossy(data);
+        let found = match source(path) {
+            None | Some(Source::OsRelease) => continue,
+            Some(Source::Apk) => Some(apk_packages(&text(), scan.os.as_ref(), path)),
+            Some(Source::Dpkg) => Some(dpkg_packages(&text(), scan.os.as_ref(), path)),
+            Some(Source::Rpm) => rpm_packages(data, scan.os.as_ref(), path),
+            Some(Source::NpmLock) => npm_packages(data, path),
+            Some(Source::TomlLock(kind)) => Some(toml_lock_packages(&text(), kind, path)),
+            Some(Source::PipfileLock) => pipfile_packages(data, path),
+            Some(Source::ComposerLock) => composer_packages(data, path),
+            Some(Source::GemfileLock) => Some(gemfile_packages(&text(), path)),
+        };
+        match found {
+            Some(packages) => scan.packages.extend(packages),
+            None => scan.unreadable.push(path.clone()),
+        }
+    }
+    scan.packages.sort_by(|a, b| a.purl.cmp(&b.purl));
+    scan.packages.dedup_by(|a, b| a.purl == b.purl);
+    Ok(scan)
+}
+
+/// Parse `os-release` (`KEY=value` lines, values optionally quoted).
+fn parse_os_release(text: &str) -> Option<OsRelease> {
+    let mut fields = HashMap::new();
+    for line in text.lines() {
+        if let Some((key, value)) = line.trim().split_once('=') {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            fields.insert(key, value.to_string());
+        }
+    }
+    Some(OsRelease {
+        id: fields.remove("ID").filter(|id| !id.is_empty())?,
+        version_id: fields.remove("VERSION_ID"),
+        pretty_name: fields.remove("PRETTY_NAME"),
+    })
+}
+
+/// Percent-encode a package URL component.
+fn purl_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Build a package URL. `namespace` may hold several `/`-separated
+/// segments; `qualifiers` must be sorted by key, and unset ones are left
+/// out.
+fn purl(
+    kind: &str,
+    namespace: Option<&str>,
+    name: &str,
+    version: &str,
+    qualifiers: &[(&str, Option<&str>)],
+) -> String {
+    let mut purl = format!("pkg:{}/", kind);
+    for segment in namespace.into_iter().flat_map(|n| n.split('/')) {
+        purl.push_str(&purl_encode(segment));
+        purl.push('/');
+    }
+    purl.push_str(&purl_encode(name));
+    purl.push('@');
+    purl.push_str(&purl_encode(version));
+    let qualifiers: Vec<String> = qualifiers
+        .iter()
+        .filter_map(|(key, value)| Some(format!("{}={}", key, purl_encode((*value)?))))
+        .collect();
+    if !qualifiers.is_empty() {
+        purl.push('?');
+        purl.push_str(&qualifiers.join("&"));
+    }
+    purl
+}
+
+/// The `distro` qualifier of OS package URLs (e.g. `alpine-3.19.1`).
+fn distro(os: Option<&OsRelease>) -> Option<String> {
+    let os = os?;
+    Some(format!("{}-{}", os.id, os.version_id.as_ref()?))
+}
+
+/// Packages in an apk database: blocks of `X:value` lines.
+fn apk_packages(text: &str, os: Option<&OsRelease>, location: &str) -> Vec<Package> {
+    let namespace = os.map_or("alpine", |os| &os.id);
+    let distro = distro(os);
+    let mut packages = Vec::new();
+    for block in text.split("\n\n") {
+        let mut fields = HashMap::new();
+        for line in block.lines() {
+            if let Some((key, value)) = line.split_once(':') {
+                fields.entry(key).or_insert(value);
+            }
+        }
+        let (Some(name), Some(version)) = (fields.get("P"), fields.get("V")) else {
+            continue;
+        };
+        packages.push(Package {
+            kind: "apk",
+            name: name.to_string(),
+            version: version.to_string(),
+            purl: purl(
+                "apk",
+                Some(namespace),
+                name,
+                version,
+                &[
+                    ("arch", fields.get("A").copied()),
+                    ("distro", distro.as_deref()),
+                ],
+            ),
+            license: fields.get("L").map(|l| l.to_string()),
+            location: location.to_string(),
+        });
+    }
+    packages
+}
+
+/// Installed packages in a dpkg status file: paragraphs of `Key: value`
+/// fields.
+fn dpkg_packages(text: &str, os: Option<&OsRelease>, location: &str) -> Vec<Package> {
+    let namespace = os.map_or("debian", |os| &os.id);
+    let distro = distro(os);
+    let mut packages = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut fields = HashMap::new();
+        for line in paragraph.lines() {
+            if line.starts_with(char::is_whitespace) {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                fields.insert(key, value.trim());
+            }
+        }
+        let (Some(name), Some(version)) = (fields.get("Package"), fields.get("Version")) else {
+            continue;
+        };
+        if fields
+            .get("Status")
+            .is_some_and(|status| !status.ends_with(" installed"))
+        {
+            continue;
+        }
+        packages.push(Package {
+            kind: "deb",
+            name: name.to_string(),
+            version: version.to_string(),
+            purl: purl(
+                "deb",
+                Some(namespace),
+                name,
+                version,
+                &[
+                    ("arch", fields.get("Architecture").copied()),
+                    ("distro", distro.as_deref()),
+                ],
+            ),
+            license: None,
+            location: location.to_string(),
+        });
+    }
+    packages
+}
+
+/// Packages in an RPM database, or `None` if it cannot be read.
+fn rpm_packages(data: &[u8], os: Option<&OsRelease>, location: &str) -> Option<Vec<Package>> {
+    let distro = distro(os);
+    let packages = rpmdb::read_packages(data)?
+        .into_iter()
+        .map(|p| {
+            let version = format!("{}-{}", p.version, p.release);
+            let epoch = p.epoch.map(|e| e.to_string());
+            Package {
+                kind: "rpm",
+                purl: purl(
+                    "rpm",
+                    os.map(|os| os.id.as_str()),
+                    &p.name,
+                    &version,
+                    &[
+                        ("arch", p.arch.as_deref()),
+                        ("distro", distro.as_deref()),
+                        ("epoch", epoch.as_deref()),
+                    ],
+                ),
+                name: p.name,
+                version,
+                license: p.license,
+                location: location.to_string(),
+            }
+        })
+        .collect();
+    Some(packages)
+}
+
+/// Package URL of an npm package, whose name may carry an `@scope/`.
+fn npm_package(name: &str, version: &str, license: Option<&str>, location: &str) -> Package {
+    let (scope, bare) = match name.split_once('/') {
+        Some((scope, bare)) if scope.starts_with('@') => (Some(scope), bare),
+        _ => (None, name),
+    };
+    Package {
+        kind: "npm",
+        name: name.to_string(),
+        version: version.to_string(),
+        purl: purl("npm", scope, bare, version, &[]),
+        license: license.map(str::to_string),
+        location: location.to_string(),
+    }
+}
+
+/// Packages in an npm `package-lock.json`: the `packages` map of lockfile
+/// version 2 and later, or the nested `dependencies` of version 1.
+fn npm_packages(data: &[u8], location: &str) -> Option<Vec<Package>> {
+    fn dependencies(deps: &Value, location: &str, packages: &mut Vec<Package>) {
+        for (name, dep) in deps.as_object().into_iter().flatten() {
+            if let Some(version) = dep["version"].as_str() {
+                packages.push(npm_package(name, version, None, location));
+            }
+            dependencies(&dep["dependencies"], location, packages);
+        }
+    }
+
+    let lock: Value = serde_json::from_slice(data).ok()?;
+    let mut packages = Vec::new();
+    match lock["packages"].as_object() {
+        Some(entries) => {
+            for (key, entry) in entries {
+                // "" is the project itself; links point at local directories
+                if key.is_empty() || entry["link"].as_bool() == Some(true) {
+                    continue;
+                }
+                let name = entry["name"]
+                    .as_str()
+                    .unwrap_or_else(|| key.rsplit("node_modules/").next().unwrap_or(key));
+                if let Some(version) = entry["version"].as_str() {
+                    packages.push(npm_package(
+                        name,
+                        version,
+                        entry["license"].as_str(),
+                        location,
+                    ));
+                }
+            }
+        }
+        None => dependencies(&lock["dependencies"], location, &mut packages),
+    }
+    Some(packages)
+}
+
+/// Packages in a TOML lockfile listing `[[package]]` tables with `name`
+/// and `version` (`Cargo.lock`, `poetry.lock`).
+fn toml_lock_packages(text: &str, kind: &'static str, location: &str) -> Vec<Package> {
+    let mut found = Vec::new();
+    let mut current: Option<(Option<String>, Option<String>)> = None;
+    for line in text.lines().map(str::trim).chain(std::iter::once("[end]")) {
+        if line.starts_with('[') {
+            if let Some((Some(name), Some(version))) = current.take() {
+                found.push((name, version));
+            }
+            current = (line == "[[package]]").then(Default::default);
+            continue;
+        }
+        let (Some((name, version)), Some((key, value))) = (current.as_mut(), line.split_once('='))
+        else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "name" => *name = Some(value),
+            "version" => *version = Some(value),
+            _ => {}
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|(name, version)| {
+            let purl_name = match kind {
+                "pypi" => pypi_name(&name),
+                _ => name.clone(),
+            };
+            Package {
+                kind,
+                purl: purl(kind, None, &purl_name, &version, &[]),
+                name,
+                version,
+                license: None,
+                location: location.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Python package name as package URLs spell it.
+fn pypi_name(name: &str) -> String {
+    name.to_lowercase().replace(['_', '.'], "-")
+}
+
+/// Packages in the `default` section of a `Pipfile.lock`.
+fn pipfile_packages(data: &[u8], location: &str) -> Option<Vec<Package>> {
+    let lock: Value = serde_json::from_slice(data).ok()?;
+    let mut packages = Vec::new();
+    for (name, entry) in lock["default"].as_object().into_iter().flatten() {
+        // Packages installed from a VCS or path have no version
+        let Some(version) = entry["version"].as_str() else {
+            continue;
+        };
+        let version = version.trim_start_matches("==");
+        packages.push(Package {
+            kind: "pypi",
+            name: name.clone(),
+            version: version.to_string(),
+            purl: purl("pypi", None, &pypi_name(name), version, &[]),
+            license: None,
+            location: location.to_string(),
+        });
+    }
+    Some(packages)
+}
+
+/// Packages in the `packages` list of a `composer.lock`.
+fn composer_packages(data: &[u8], location: &str) -> Option<Vec<Package>> {
+    let lock: Value = serde_json::from_slice(data).ok()?;
+    let mut packages = Vec::new();
+    for entry in lock["packages"].as_array().into_iter().flatten() {
+        let (Some(name), Some(version)) = (entry["name"].as_str(), entry["version"].as_str())
+        else {
+            continue;
+        };
+        let (vendor, bare) = match name.split_once('/') {
+            Some((vendor, bare)) => (Some(vendor), bare),
+            None => (None, name),
+        };
+        let licenses: Vec<&str> = entry["license"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        packages.push(Package {
+            kind: "composer",
+            name: name.to_string(),
+            version: version.to_string(),
+            purl: purl("composer", vendor, bare, version, &[]),
+            license: (!licenses.is_empty()).then(|| licenses.join(" OR ")),
+            location: location.to_string(),
+        });
+    }
+    Some(packages)
+}
+
+/// Gems in a `Gemfile.lock`: the `name (version)` lines indented four
+/// spaces under each `specs:`.
+fn gemfile_packages(text: &str, location: &str) -> Vec<Package> {
+    let mut packages = Vec::new();
+    let mut in_specs = false;
+    for line in text.lines() {
+        if line == "  specs:" {
+            in_specs = true;
+            continue;
+        }
+        if !line.starts_with("    ") {
+            in_specs = false;
+        }
+        if !in_specs || line.starts_with("     ") {
+            continue;
+        }
+        let Some((name, version)) = line.trim().split_once(" (") else {
+            continue;
+        };
+        let version = version.trim_end_matches(')');
+        packages.push(Package {
+            kind: "gem",
+            name: name.to_string(),
+            version: version.to_string(),
+            purl: purl("gem", None, name, version, &[]),
+            license: None,
+            location: location.to_string(),
+        });
+    }
+    packages
+}
+
+/// CycloneDX JSON document listing the packages of `scan`, found in
+/// `image` (at `digest`) for `platform`.
+pub fn cyclonedx(
+    scan: &Scan,
+    image: &str,
+    digest: &str,
+    platform: &str,
+    provenance: &Provenance,
+) -> Result<Vec<u8>> {
+    let mut components = Vec::new();
+    if let Some(ref os) = scan.os {
+        let mut component = json!({
+            "type": "operating-system",
+            "bom-ref": "os",
+            "name": os.id,
+        });
+        if let Some(ref version) = os.version_id {
+            component["version"] = json!(version);
+        }
+        if let Some(ref name) = os.pretty_name {
+            component["description"] = json!(name);
+        }
+        components.push(component);
+    }
+    for package in &scan.packages {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": package.purl,
+            "name": package.name,
+            "version": package.version,
+            "purl": package.purl,
+            "properties": [{"name": "smolvm:location", "value": package.location}],
+        });
+        if let Some(ref license) = package.license {
+            component["licenses"] = json!([{"license": {"name": license}}]);
+        }
+        components.push(component);
+    }
+
+    let document = json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": CYCLONEDX_VERSION,
+        "version": 1,
+        "metadata": {
+            "timestamp": provenance.created,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "smolvm",
+                    "version": provenance.smolvm_version,
+                }],
+            },
+            "component": {
+                "type": "container",
+                "bom-ref": "image",
+                "name": image,
+                "version": digest,
+                "properties": [{"name": "smolvm:platform", "value": platform}],
+            },
+        },
+        "components": components,
+    });
+    Ok(serde_json::to_vec_pretty(&document)?)
+}
+
+/// Scan the layers collected for the current platform and add an SBOM of
+/// the packages found to `collector`.
+///
+/// `image`, `digest` and `platform` describe what was packed, and
+/// `provenance` supplies the document's tool version and timestamp.
+pub fn collect_sbom(
+    collector: &mut AssetCollector,
+    image: &str,
+    digest: &str,
+    platform: &str,
+    provenance: &Provenance,
+) -> Result<Scan> {
+    let scan = scan_layers(collector.current_layer_paths())?;
+    collector.add_sbom(&cyclonedx(&scan, image, digest, platform, provenance)?)?;
+    Ok(scan)
+}
+
+/// Read the SBOM of `inventory` from `pack`, checking it against the
+/// recorded SHA-256. Returns `None` if the inventory has no SBOM.
+pub fn read_sbom(pack: &PackedFile, inventory: &AssetInventory) -> Result<Option<Vec<u8>>> {
+    let Some(ref entry) = inventory.sbom else {
+        return Ok(None);
+    };
+    let unpacked = tempfile::tempdir()?;
+    let targets = HashMap::from([(entry.path.clone(), vec![SBOM_NAME.to_string()])]);
+    unpack_assets(pack, &targets, unpacked.path())?;
+    let document = fs::read(unpacked.path().join(SBOM_NAME)).map_err(|_| {
+        PackError::Verification(format!(
+            "asset {} listed in the manifest is missing",
+            entry.path
+        ))
+    })?;
+
+    if let Some(ref want) = entry.sha256 {
+        let actual = hex_encode(&Sha256::digest(&document));
+        if &actual != want {
+            return Err(PackError::Verification(format!(
+                "asset {} has SHA-256 {}, manifest says {}",
+                entry.path, actual, want
+            )));
+        }
+    }
+    Ok(Some(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::PackManifest;
+    use crate::packer::Packer;
+    use std::path::PathBuf;
+
+    /// Write a layer tarball holding `files`.
+    fn layer(dir: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join(name);
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (file, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, file, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap();
+        path
+    }
+
+    const APK_INSTALLED: &str = "C:Q1abc=\nP:musl\nV:1.2.4_git20230717-r4\nA:x86_64\nL:MIT\n\n\
+                                 P:busybox\nV:1.36.1-r15\nA:x86_64\nL:GPL-2.0-only\n\n";
+
+    const NPM_LOCK: &str = r#"{
+        "lockfileVersion": 3,
+        "packages": {
+            "": {"name": "app", "version": "1.0.0"},
+            "node_modules/@types/node": {"version": "20.1.0", "license": "MIT"},
+            "node_modules/left-pad": {"version": "1.3.0"},
+            "node_modules/local": {"resolved": "../local", "link": true}
+        }
+    }"#;
+
+    fn provenance() -> Provenance {
+        Provenance {
+            smolvm_version: "0.1.18".to_string(),
+            created: "2023-11-14T22:13:20Z".to_string(),
+            sources: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_scan_layers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = layer(
+            temp_dir.path(),
+            "base.tar",
+            &[
+                ("etc/os-release", "ID=alpine\nVERSION_ID=3.19.1\nPRETTY_NAME=\"Alpine Linux v3.19\"\n"),
+                ("lib/apk/db/installed", APK_INSTALLED),
+                ("var/lib/dpkg/status", "Package: hidden\nVersion: 1\nStatus: install ok installed\n"),
+                ("app/package-lock.json", NPM_LOCK),
+                ("app/node_modules/left-pad/package-lock.json", r#"{"packages": {"x": {"version": "9"}}}"#),
+                ("usr/src/Cargo.lock", "[[package]]\nname = \"gone\"\nversion = \"0.1.0\"\n"),
+                ("srv/Gemfile.lock", "GEM\n  remote: https://rubygems.org/\n  specs:\n    rack (3.0.8)\n      webrick\n    webrick (1.8.1)\n\nPLATFORMS\n  ruby\n"),
+            ],
+        );
+        let top = layer(
+            temp_dir.path(),
+            "top.tar",
+            &[
+                ("./var/lib/.wh.dpkg", ""),
+                ("./usr/src/.wh..wh..opq", ""),
+                ("./usr/src/poetry.lock", "[[package]]\nname = \"Flask_Login\"\nversion = \"0.6.3\"\n\n[package.dependencies]\nflask = \">=1.0.4\"\n"),
+                ("./srv/composer.lock", "{not json"),
+            ],
+        );
+
+        let scan = scan_layers([&base, &top]).unwrap();
+        assert_eq!(
+            scan.os,
+            Some(OsRelease {
+                id: "alpine".to_string(),
+                version_id: Some("3.19.1".to_string()),
+                pretty_name: Some("Alpine Linux v3.19".to_string()),
+            })
+        );
+        let purls: Vec<&str> = scan.packages.iter().map(|p| p.purl.as_str()).collect();
+        assert_eq!(
+            purls,
+            [
+                "pkg:apk/alpine/busybox@1.36.1-r15?arch=x86_64&distro=alpine-3.19.1",
+                "pkg:apk/alpine/musl@1.2.4_git20230717-r4?arch=x86_64&distro=alpine-3.19.1",
+                "pkg:gem/rack@3.0.8",
+                "pkg:gem/webrick@1.8.1",
+                "pkg:npm/%40types/node@20.1.0",
+                "pkg:npm/left-pad@1.3.0",
+                "pkg:pypi/flask-login@0.6.3",
+            ]
+        );
+        let musl = &scan.packages[1];
+        assert_eq!(musl.license.as_deref(), Some("MIT"));
+        assert_eq!(musl.location, "lib/apk/db/installed");
+        assert_eq!(scan.packages[4].name, "@types/node");
+        assert_eq!(scan.unreadable, ["srv/composer.lock"]);
+    }
+
+    #[test]
+    fn test_dpkg_status() {
+        let status = "Package: libc6\nStatus: install ok installed\nArchitecture: amd64\n\
+                      Version: 2.36-9+deb12u4\nDescription: GNU C Library\n Shared libraries.\n\n\
+                      Package: removed\nStatus: deinstall ok config-files\nVersion: 1.0\n";
+        let os = OsRelease {
+            id: "debian".to_string(),
+            version_id: Some("12".to_string()),
+            pretty_name: None,
+        };
+        let packages = dpkg_packages(status, Some(&os), "var/lib/dpkg/status");
+        assert_eq!(packages.len(), 1);
+        assert_eq!(
+            packages[0].purl,
+            "pkg:deb/debian/libc6@2.36-9%2Bdeb12u4?arch=amd64&distro=debian-12"
+        );
+        assert_eq!(
+            purl(
+                "deb",
+                Some("debian"),
+                "tzdata",
+                "1:2024a",
+                &[("arch", None)]
+            ),
+            "pkg:deb/debian/tzdata@1%3A2024a"
+        );
+    }
+
+    #[test]
+    fn test_sbom_in_pack() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub");
+        fs::write(&stub, b"stub").unwrap();
+        let base = layer(
+            temp_dir.path(),
+            "base.tar",
+            &[("lib/apk/db/installed", APK_INSTALLED)],
+        );
+
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        collector
+            .add_layer_from_file("sha256:aaaaaaaaaaaa01", &base)
+            .unwrap();
+        let scan = collect_sbom(
+            &mut collector,
+            "alpine:3.19",
+            "sha256:test",
+            "linux/amd64",
+            &provenance(),
+        )
+        .unwrap();
+        assert_eq!(scan.packages.len(), 2);
+        collector.begin_platform("linux/arm64").unwrap();
+        collector
+            .add_layer_from_file("sha256:aaaaaaaaaaaa01", &base)
+            .unwrap();
+        collect_sbom(
+            &mut collector,
+            "alpine:3.19",
+            "sha256:arm",
+            "linux/arm64",
+            &provenance(),
+        )
+        .unwrap();
+
+        let manifest = PackManifest::new(
+            "alpine:3.19".to_string(),
+            "sha256:test".to_string(),
+            "linux/amd64".to_string(),
+        );
+        let output = temp_dir.path().join("packed");
+        Packer::new(manifest)
+            .with_stub(&stub)
+            .with_assets(collector)
+            .pack(&output)
+            .unwrap();
+        let pack = PackedFile::open(&output).unwrap();
+        let manifest = pack.manifest().unwrap();
+        assert_eq!(manifest.assets.sbom.as_ref().unwrap().path, SBOM_NAME);
+        assert_eq!(
+            manifest.platforms["linux/arm64"]
+                .sbom
+                .as_ref()
+                .unwrap()
+                .path,
+            "platforms/linux-arm64/sbom.cdx.json"
+        );
+        pack.verify_assets(&manifest).unwrap();
+
+        let document: Value = serde_json::from_slice(
+            &read_sbom(&pack, &manifest.platforms["linux/arm64"])
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert_eq!(document["metadata"]["timestamp"], "2023-11-14T22:13:20Z");
+        assert_eq!(document["metadata"]["component"]["version"], "sha256:arm");
+        assert_eq!(document["components"][1]["name"], "musl");
+        assert_eq!(
+            document["components"][1]["licenses"][0]["license"]["name"],
+            "MIT"
+        );
+
+        let mut without = manifest.assets.clone();
+        without.sbom = None;
+        assert!(read_sbom(&pack, &without).unwrap().is_none());
+    }
+}
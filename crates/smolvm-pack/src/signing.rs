@@ -136,7 +136,10 @@ pub struct SignatureInfo {
 
 #[cfg(test)]
 mod tests {
+    // The tests below are macOS-only; Linux builds use none of these
+    #[cfg(target_os = "macos")]
     use super::*;
+    #[cfg(target_os = "macos")]
     use std::io::Write;
 
     #[test]
@@ -0,0 +1,418 @@
+//! Manifest signatures and trust policy for packed machines.
+//!
+//! A packed machine can carry an Ed25519 signature over its manifest and
+//! its compressed assets blob. The manifest records the SHA-256 of every
+//! asset, so a valid signature vouches for each file that gets extracted.
+//! The signature block sits between the manifest and the footer and is not
+//! covered by the CRC32 checksum, so signing an existing pack doesn't
+//! change its extraction cache.
+//!
+//! Keys are small text files: `ed25519-secret:<hex seed>` for a signing
+//! key and `ed25519:<hex>` for a public key. A trust policy is a file of
+//! public keys, one per line with an optional name after it. Once a policy
+//! is configured, packed machines only run if one of its keys signed them.
+
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ring::rand::SecureRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::assets::hex_encode;
+use crate::{PackError, Result};
+
+/// Environment variable naming the trusted keys file, overriding the
+/// default location.
+pub const TRUSTED_KEYS_ENV: &str = "SMOLVM_PACK_TRUSTED_KEYS";
+
+/// Signature algorithm recorded in signature blocks.
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// Maximum allowed signature block size, so a corrupt footer can't cause
+/// a large allocation.
+pub const MAX_SIGNATURE_SIZE: u32 = 64 * 1024;
+
+const SECRET_PREFIX: &str = "ed25519-secret:";
+const PUBLIC_PREFIX: &str = "ed25519:";
+
+/// Prefix of every signed message, so a pack signature can't be passed
+/// off as a signature over anything else.
+const SIGNATURE_CONTEXT: &[u8] = b"smolvm-pack-signature-v1\0";
+
+/// An Ed25519 key for signing packed machines.
+pub struct SigningKey {
+    seed: [u8; 32],
+    pair: Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// Generate a new random key.
+    pub fn generate() -> Result<Self> {
+        let mut seed = [0u8; 32];
+        ring::rand::SystemRandom::new()
+            .fill(&mut seed)
+            .map_err(|_| PackError::InvalidKey("failed to generate random seed".to_string()))?;
+        Self::from_seed(seed)
+    }
+
+    fn from_seed(seed: [u8; 32]) -> Result<Self> {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|e| PackError::InvalidKey(e.to_string()))?;
+        Ok(Self { seed, pair })
+    }
+
+    /// Load a signing key file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(text.trim()).map_err(|e| key_file_error(path, e))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let hex = text
+            .strip_prefix(SECRET_PREFIX)
+            .ok_or_else(|| PackError::InvalidKey("not an ed25519 signing key".to_string()))?;
+        Self::from_seed(decode_32(hex)?)
+    }
+
+    /// Write the key to a new file readable only by the owner.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        writeln!(file, "{}{}", SECRET_PREFIX, hex_encode(&self.seed))?;
+        Ok(())
+    }
+
+    /// The public half of this key.
+    pub fn public_key(&self) -> PublicKey {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(self.pair.public_key().as_ref());
+        PublicKey(key)
+    }
+
+    /// Sign a manifest and the SHA-256 of the compressed assets blob.
+    pub fn sign(&self, manifest_json: &[u8], assets_sha256: &[u8; 32]) -> ManifestSignature {
+        let signature = self
+            .pair
+            .sign(&signed_message(manifest_json, assets_sha256));
+        ManifestSignature {
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            public_key: self.public_key().to_string(),
+            signature: hex_encode(signature.as_ref()),
+        }
+    }
+}
+
+/// An Ed25519 public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    /// Load a public key file. A signing key file is accepted too, and
+    /// yields its public half.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let text = text.trim();
+        if text.starts_with(SECRET_PREFIX) {
+            return Ok(SigningKey::load(path)?.public_key());
+        }
+        text.parse().map_err(|e| key_file_error(path, e))
+    }
+
+    /// Write the key to a file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, format!("{}\n", self))?;
+        Ok(())
+    }
+
+    /// Short identifier for display: the first 16 hex digits of the
+    /// key's SHA-256.
+    pub fn fingerprint(&self) -> String {
+        hex_encode(&Sha256::digest(self.0)[..8])
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = PackError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s
+            .strip_prefix(PUBLIC_PREFIX)
+            .ok_or_else(|| PackError::InvalidKey("not an ed25519 public key".to_string()))?;
+        Ok(Self(decode_32(hex)?))
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", PUBLIC_PREFIX, hex_encode(&self.0))
+    }
+}
+
+/// Signature block of a packed machine.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestSignature {
+    /// Signature algorithm (`ed25519`).
+    pub algorithm: String,
+
+    /// Public key of the signer (`ed25519:<hex>`).
+    pub public_key: String,
+
+    /// Signature over the manifest and assets digest (hex).
+    pub signature: String,
+}
+
+impl ManifestSignature {
+    /// Serialize the signature block to JSON.
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Deserialize a signature block from JSON.
+    pub fn from_json(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Check the signature and return the key that made it.
+    ///
+    /// This only proves the signer's key signed these bytes; whether that
+    /// key is trusted is up to the caller (see [`TrustPolicy::check`]).
+    pub fn verify(&self, manifest_json: &[u8], assets_sha256: &[u8; 32]) -> Result<PublicKey> {
+        if self.algorithm != SIGNATURE_ALGORITHM {
+            return Err(PackError::Verification(format!(
+                "unsupported signature algorithm '{}'",
+                self.algorithm
+            )));
+        }
+        let key: PublicKey = self.public_key.parse()?;
+        let signature = decode_hex(&self.signature)?;
+        UnparsedPublicKey::new(&ED25519, key.0)
+            .verify(&signed_message(manifest_json, assets_sha256), &signature)
+            .map_err(|_| {
+                PackError::Verification(format!(
+                    "invalid signature by key {}: manifest or assets were modified",
+                    key.fingerprint()
+                ))
+            })?;
+        Ok(key)
+    }
+}
+
+/// A trusted public key and its optional name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedKey {
+    /// The key.
+    pub key: PublicKey,
+    /// Name given after the key in the trusted keys file.
+    pub name: Option<String>,
+}
+
+/// Keys that packed machines must be signed by.
+#[derive(Debug, Clone, Default)]
+pub struct TrustPolicy {
+    keys: Vec<TrustedKey>,
+}
+
+impl TrustPolicy {
+    /// A policy trusting only the given key.
+    pub fn single(key: PublicKey) -> Self {
+        Self {
+            keys: vec![TrustedKey { key, name: None }],
+        }
+    }
+
+    /// Default trusted keys file: `<config dir>/smolvm/trusted-keys`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("smolvm").join("trusted-keys"))
+    }
+
+    /// Load the configured policy.
+    ///
+    /// Reads the file named by `SMOLVM_PACK_TRUSTED_KEYS` (which must
+    /// exist), else the default file if present. Returns `None` when no
+    /// policy is configured.
+    pub fn load() -> Result<Option<Self>> {
+        if let Some(path) = std::env::var_os(TRUSTED_KEYS_ENV) {
+            return Self::load_file(Path::new(&path)).map(Some);
+        }
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_file(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Load a trusted keys file.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            PackError::InvalidKey(format!("trusted keys file {}: {}", path.display(), e))
+        })?;
+        Self::parse(&text).map_err(|e| key_file_error(path, e))
+    }
+
+    /// Parse a trusted keys file: one `ed25519:<hex> [name]` per line,
+    /// with blank lines and `#` comments ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keys = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, name) = match line.split_once(char::is_whitespace) {
+                Some((key, name)) => (key, Some(name.trim().to_string())),
+                None => (line, None),
+            };
+            let key = key.parse().map_err(|e| match e {
+                PackError::InvalidKey(reason) => {
+                    PackError::InvalidKey(format!("line {}: {}", i + 1, reason))
+                }
+                other => other,
+            })?;
+            keys.push(TrustedKey { key, name });
+        }
+        Ok(Self { keys })
+    }
+
+    /// The trusted keys.
+    pub fn keys(&self) -> &[TrustedKey] {
+        &self.keys
+    }
+
+    /// Check that a packed machine is signed by a trusted key.
+    pub fn check(
+        &self,
+        signature: Option<&ManifestSignature>,
+        manifest_json: &[u8],
+        assets_sha256: &[u8; 32],
+    ) -> Result<&TrustedKey> {
+        let signature = signature
+            .ok_or_else(|| PackError::Verification("packed machine is not signed".to_string()))?;
+        let key = signature.verify(manifest_json, assets_sha256)?;
+        self.keys.iter().find(|k| k.key == key).ok_or_else(|| {
+            PackError::Verification(format!(
+                "packed machine is signed by untrusted key {}",
+                key.fingerprint()
+            ))
+        })
+    }
+}
+
+fn signed_message(manifest_json: &[u8], assets_sha256: &[u8; 32]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(SIGNATURE_CONTEXT.len() + 64);
+    message.extend_from_slice(SIGNATURE_CONTEXT);
+    message.extend_from_slice(&Sha256::digest(manifest_json));
+    message.extend_from_slice(assets_sha256);
+    message
+}
+
+fn key_file_error(path: &Path, err: PackError) -> PackError {
+    match err {
+        PackError::InvalidKey(reason) => {
+            PackError::InvalidKey(format!("{}: {}", path.display(), reason))
+        }
+        other => other,
+    }
+}
+
+fn decode_32(hex: &str) -> Result<[u8; 32]> {
+    decode_hex(hex)?
+        .try_into()
+        .map_err(|_| PackError::InvalidKey("key must be 32 bytes".to_string()))
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let invalid = || PackError::InvalidKey("malformed hex".to_string());
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate().unwrap();
+        let digest = [7u8; 32];
+        let signature = key.sign(b"{\"image\":\"alpine\"}", &digest);
+
+        let signer = signature
+            .verify(b"{\"image\":\"alpine\"}", &digest)
+            .unwrap();
+        assert_eq!(signer, key.public_key());
+
+        // Any change to the manifest or assets breaks the signature
+        assert!(signature.verify(b"{\"image\":\"evil\"}", &digest).is_err());
+        assert!(signature
+            .verify(b"{\"image\":\"alpine\"}", &[8u8; 32])
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_files_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = SigningKey::generate().unwrap();
+        key.save(&dir.path().join("pack.key")).unwrap();
+        key.public_key().save(&dir.path().join("pack.pub")).unwrap();
+
+        let loaded = SigningKey::load(&dir.path().join("pack.key")).unwrap();
+        assert_eq!(loaded.public_key(), key.public_key());
+        assert_eq!(
+            PublicKey::load(&dir.path().join("pack.pub")).unwrap(),
+            key.public_key()
+        );
+        // A signing key file also yields its public key
+        assert_eq!(
+            PublicKey::load(&dir.path().join("pack.key")).unwrap(),
+            key.public_key()
+        );
+        // Public keys are not signing keys
+        assert!(SigningKey::load(&dir.path().join("pack.pub")).is_err());
+        // Existing key files are never overwritten
+        assert!(key.save(&dir.path().join("pack.key")).is_err());
+    }
+
+    #[test]
+    fn test_trust_policy() {
+        let trusted = SigningKey::generate().unwrap();
+        let other = SigningKey::generate().unwrap();
+        let text = format!("# release keys\n{} release\n\n", trusted.public_key());
+        let policy = TrustPolicy::parse(&text).unwrap();
+        assert_eq!(policy.keys().len(), 1);
+        assert_eq!(policy.keys()[0].name.as_deref(), Some("release"));
+
+        let digest = [1u8; 32];
+        let signature = trusted.sign(b"manifest", &digest);
+        assert!(policy.check(Some(&signature), b"manifest", &digest).is_ok());
+
+        let untrusted = other.sign(b"manifest", &digest);
+        let err = policy
+            .check(Some(&untrusted), b"manifest", &digest)
+            .unwrap_err();
+        assert!(err.to_string().contains("untrusted key"), "got: {}", err);
+
+        let err = policy.check(None, b"manifest", &digest).unwrap_err();
+        assert!(err.to_string().contains("not signed"), "got: {}", err);
+
+        assert!(TrustPolicy::parse("ed25519:abcd").is_err());
+    }
+}
@@ -18,6 +18,7 @@
 
 use serde::{Deserialize, Serialize};
 
+pub mod port_forward;
 pub mod retry;
 
 /// Serde helper for encoding `Vec<u8>` as a base64 string in JSON.
@@ -42,7 +43,7 @@ pub mod base64_bytes {
 }
 
 /// Protocol version.
-pub const PROTOCOL_VERSION: u32 = 1;
+pub const PROTOCOL_VERSION: u32 = 2;
 
 /// Maximum frame size (32 MB - layer exports use chunked streaming).
 pub const MAX_FRAME_SIZE: u32 = 32 * 1024 * 1024;
@@ -50,6 +51,10 @@ pub const MAX_FRAME_SIZE: u32 = 32 * 1024 * 1024;
 /// Chunk size for streaming layer data (~16 MB raw, ~21 MB as base64 JSON).
 pub const LAYER_CHUNK_SIZE: usize = 16 * 1024 * 1024;
 
+/// Chunk size for streaming image imports (~8 MB raw, ~11 MB as base64 JSON).
+/// Smaller than LAYER_CHUNK_SIZE because agent requests are capped at 16 MB.
+pub const IMPORT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
 /// Well-known vsock ports.
 pub mod ports {
     /// Control channel for workload VMs.
@@ -58,6 +63,12 @@ pub mod ports {
     pub const WORKLOAD_LOGS: u32 = 5001;
     /// Agent control port (for OCI operations and management).
     pub const AGENT_CONTROL: u32 = 6000;
+    /// Egress proxy port (guest connects to the host-side allowlist proxy).
+    pub const EGRESS_PROXY: u32 = 6001;
+    /// Published-port forwarding (host connects for UDP and bound ports).
+    pub const PORT_FORWARD: u32 = 6002;
+    /// Host-side DNS resolver (guest connects; DNS-over-TCP framing).
+    pub const DNS_RESOLVER: u32 = 6003;
 }
 
 /// vsock CID constants.
@@ -101,12 +112,26 @@ pub enum AgentRequest {
     /// List all cached images.
     ListImages,
 
-    /// Run garbage collection on unused layers.
+    /// Run garbage collection. Returns a [`GcReport`].
     GarbageCollect {
         /// If true, only report what would be deleted.
         dry_run: bool,
+        /// Categories to collect. Empty means [`GcTarget::DEFAULT`].
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        targets: Vec<GcTarget>,
     },
 
+    /// Remove an image reference, then collect the layers and config only
+    /// it used. Fails if a registered container uses the image. Returns a
+    /// [`GcReport`].
+    RemoveImage {
+        /// Image reference.
+        image: String,
+    },
+
+    /// Report storage usage by category. Returns a [`DiskUsage`].
+    DiskUsage,
+
     /// Prepare overlay rootfs for a workload.
     PrepareOverlay {
         /// Image reference.
@@ -127,6 +152,41 @@ pub enum AgentRequest {
     /// Get storage disk status.
     StorageStatus,
 
+    /// Get the state of synced mounts (`-v SRC:DST:sync`).
+    ///
+    /// Returns a list of [`SyncMountStatus`].
+    SyncStatus,
+
+    /// Grow the storage filesystem after the host extended the disk image.
+    ///
+    /// Waits for the block device to report the new capacity, then runs an
+    /// online `resize2fs`. Returns the new [`StorageStatus`], or a
+    /// `RESIZE_UNSUPPORTED` error if the device size never changes.
+    ResizeStorage {
+        /// New disk size in bytes.
+        size_bytes: u64,
+    },
+
+    /// Configure the HTTP(S) proxy and extra CA certificates for this VM.
+    ///
+    /// The proxy variables are exported into the agent's environment (image
+    /// pulls, VM exec) and added to every workload's environment unless the
+    /// request sets them itself. CA certificates are installed into the guest
+    /// trust store and into each container rootfs.
+    ConfigureProxy {
+        /// Proxy settings.
+        config: ProxyConfig,
+    },
+
+    /// Configure DNS resolution for this VM.
+    ///
+    /// The agent writes `/etc/resolv.conf` and `/etc/hosts` in the guest
+    /// and into each container rootfs.
+    ConfigureDns {
+        /// DNS settings.
+        config: DnsConfig,
+    },
+
     /// Test network connectivity directly from the agent (not via chroot).
     /// Used to debug TSI networking.
     NetworkTest {
@@ -148,6 +208,91 @@ pub enum AgentRequest {
         layer_index: usize,
     },
 
+    /// Prepare a locally stored image for pushing to a registry.
+    ///
+    /// The agent re-tars each layer deterministically into its blob store
+    /// and returns a [`PushPlan`] describing the blobs and manifest to upload.
+    PreparePush {
+        /// Image reference.
+        image: String,
+        /// Compression to apply to layer blobs.
+        #[serde(default)]
+        compression: LayerCompression,
+    },
+
+    /// Import an image archive (OCI image layout or `docker save` tarball).
+    ///
+    /// The archive is sent as a sequence of ImportImage requests, each
+    /// carrying up to [`IMPORT_CHUNK_SIZE`] bytes. The agent replies once,
+    /// after the chunk with `done: true`, with the imported images.
+    ImportImage {
+        /// Archive data chunk.
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+        /// Whether this is the last chunk.
+        done: bool,
+        /// Name to store the image under, overriding names in the archive.
+        /// Only meaningful on the first chunk.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+    },
+
+    /// Export a blob from the agent blob store.
+    ///
+    /// The agent streams the blob back via LayerData responses.
+    ExportBlob {
+        /// Blob digest (sha256:...).
+        digest: String,
+    },
+
+    /// Upload a build context for COPY/ADD build steps.
+    ///
+    /// Sent in chunks like ImportImage; the data is a tar archive of the
+    /// context directory. The agent replies once, after the chunk with
+    /// `done: true`.
+    UploadBuildContext {
+        /// Context ID chosen by the host, referenced by build steps.
+        id: String,
+        /// Archive data chunk.
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+        /// Whether this is the last chunk.
+        done: bool,
+    },
+
+    /// Remove an uploaded build context.
+    RemoveBuildContext {
+        /// Context ID.
+        id: String,
+    },
+
+    /// Execute one image build step and store the result as an image.
+    ///
+    /// RUN and COPY steps snapshot their filesystem changes as a new layer;
+    /// config steps only rewrite the image config. Returns a
+    /// [`BuildStepResult`].
+    BuildStep {
+        /// Image the step starts from. `None` starts from an empty rootfs
+        /// (`FROM scratch`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent: Option<String>,
+        /// Reference to store the resulting image under.
+        target: String,
+        /// Instruction to execute.
+        step: BuildInstruction,
+        /// Text recorded in the image history (`created_by`).
+        #[serde(default)]
+        created_by: String,
+    },
+
+    /// Store an existing image under an additional reference.
+    TagImage {
+        /// Existing image reference.
+        source: String,
+        /// New reference.
+        target: String,
+    },
+
     /// Execute a command directly in the VM (not in a container).
     ///
     /// This runs the command in the agent's Alpine rootfs without any
@@ -185,10 +330,9 @@ pub enum AgentRequest {
         env: Vec<(String, String)>,
         /// Working directory inside the rootfs.
         workdir: Option<String>,
-        /// Volume mounts to bind into the container.
-        /// Each tuple is (virtiofs_tag, container_path, read_only).
+        /// Mounts for the container.
         #[serde(default)]
-        mounts: Vec<(String, String, bool)>,
+        mounts: Vec<ContainerMount>,
         /// Timeout in milliseconds. If the command exceeds this duration,
         /// it will be killed and return exit code 124.
         #[serde(default)]
@@ -236,9 +380,9 @@ pub enum AgentRequest {
         env: Vec<(String, String)>,
         /// Working directory inside the container.
         workdir: Option<String>,
-        /// Volume mounts (virtiofs_tag, container_path, read_only).
+        /// Mounts for the container.
         #[serde(default)]
-        mounts: Vec<(String, String, bool)>,
+        mounts: Vec<ContainerMount>,
     },
 
     /// Start a created container.
@@ -368,7 +512,7 @@ pub enum AgentResponse {
         exit_code: i32,
     },
 
-    /// Layer data chunk (for ExportLayer).
+    /// Layer data chunk (for ExportLayer and ExportBlob).
     LayerData {
         /// Binary data chunk.
         #[serde(with = "base64_bytes")]
@@ -413,6 +557,14 @@ pub mod error_codes {
     pub const FORMAT_FAILED: &str = "FORMAT_FAILED";
     /// Storage status query failed.
     pub const STATUS_FAILED: &str = "STATUS_FAILED";
+    /// Storage resize failed.
+    pub const RESIZE_FAILED: &str = "RESIZE_FAILED";
+    /// The hypervisor did not notify the guest of a disk size change.
+    pub const RESIZE_UNSUPPORTED: &str = "RESIZE_UNSUPPORTED";
+    /// Applying proxy or CA certificate configuration failed.
+    pub const PROXY_CONFIG_FAILED: &str = "PROXY_CONFIG_FAILED";
+    /// Applying DNS configuration failed.
+    pub const DNS_CONFIG_FAILED: &str = "DNS_CONFIG_FAILED";
     /// List operation failed.
     pub const LIST_FAILED: &str = "LIST_FAILED";
     /// Garbage collection failed.
@@ -427,6 +579,14 @@ pub mod error_codes {
     pub const DELETE_FAILED: &str = "DELETE_FAILED";
     /// Export operation failed.
     pub const EXPORT_FAILED: &str = "EXPORT_FAILED";
+    /// Push preparation failed.
+    pub const PUSH_FAILED: &str = "PUSH_FAILED";
+    /// Image import failed.
+    pub const IMPORT_FAILED: &str = "IMPORT_FAILED";
+    /// Image build step failed.
+    pub const BUILD_FAILED: &str = "BUILD_FAILED";
+    /// Resource is in use and cannot be removed.
+    pub const IN_USE: &str = "IN_USE";
     /// Serialization error.
     pub const SERIALIZATION_ERROR: &str = "SERIALIZATION_ERROR";
     /// Message size exceeds maximum.
@@ -593,6 +753,533 @@ pub struct RegistryAuth {
     pub password: String,
 }
 
+/// HTTP(S) proxy configuration applied inside the guest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// Proxy URL for plain HTTP (`HTTP_PROXY`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_proxy: Option<String>,
+    /// Proxy URL for HTTPS (`HTTPS_PROXY`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https_proxy: Option<String>,
+    /// Hosts, domains, and networks that bypass the proxy (`NO_PROXY`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    /// Extra trusted CA certificates (PEM).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certs: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Environment variables for the proxy settings, in both the upper- and
+    /// lowercase spellings tools look for.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let no_proxy = (!self.no_proxy.is_empty()).then(|| self.no_proxy.join(","));
+        let vars = [
+            ("HTTP_PROXY", &self.http_proxy),
+            ("HTTPS_PROXY", &self.https_proxy),
+            ("NO_PROXY", &no_proxy),
+        ];
+        let mut env = Vec::new();
+        for (key, value) in vars {
+            if let Some(value) = value {
+                env.push((key.to_string(), value.clone()));
+                env.push((key.to_ascii_lowercase(), value.clone()));
+            }
+        }
+        env
+    }
+}
+
+/// DNS configuration applied inside the guest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsConfig {
+    /// Nameservers, in order of preference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<std::net::IpAddr>,
+    /// Search domains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search: Vec<String>,
+    /// Resolver options (`ndots:2`, `edns0`, ...).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// Extra `/etc/hosts` entries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<HostEntry>,
+    /// Resolve through the host's system resolver (see
+    /// [`ports::DNS_RESOLVER`]) instead of querying `servers` directly.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub host_resolver: bool,
+}
+
+/// A static host name entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostEntry {
+    /// Host name.
+    pub name: String,
+    /// Address the name resolves to.
+    pub address: std::net::IpAddr,
+}
+
+/// A filesystem mounted into a container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerMount {
+    /// What is mounted.
+    pub kind: MountKind,
+    /// Absolute path inside the container.
+    pub target: String,
+    /// Mount read-only.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    /// Don't allow executing binaries from the mount (`noexec`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub noexec: bool,
+    /// Ignore set-user-ID and set-group-ID bits (`nosuid`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub nosuid: bool,
+    /// Don't interpret device nodes (`nodev`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub nodev: bool,
+}
+
+impl ContainerMount {
+    /// Bind the host path or volume staged under `tag` at `target`.
+    pub fn bind(tag: impl Into<String>, target: impl Into<String>, read_only: bool) -> Self {
+        Self {
+            kind: MountKind::Bind { tag: tag.into() },
+            target: target.into(),
+            read_only,
+            noexec: false,
+            nosuid: false,
+            nodev: false,
+        }
+    }
+
+    /// Mount an empty tmpfs at `target`.
+    pub fn tmpfs(target: impl Into<String>, size_bytes: Option<u64>, mode: Option<u32>) -> Self {
+        Self {
+            kind: MountKind::Tmpfs { size_bytes, mode },
+            target: target.into(),
+            read_only: false,
+            noexec: false,
+            nosuid: true,
+            nodev: true,
+        }
+    }
+
+    /// The virtiofs tag of a bind mount.
+    pub fn tag(&self) -> Option<&str> {
+        match &self.kind {
+            MountKind::Bind { tag } => Some(tag),
+            MountKind::Tmpfs { .. } => None,
+        }
+    }
+}
+
+/// Source of a [`ContainerMount`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MountKind {
+    /// A host directory, host file or named volume that the agent stages
+    /// at `/mnt/virtiofs/<tag>`.
+    Bind {
+        /// Mount tag (`smolvm0`, `smolvm1`, ...).
+        tag: String,
+    },
+    /// An in-memory filesystem, empty for every container.
+    Tmpfs {
+        /// Size limit in bytes (kernel default: half of the VM's memory).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size_bytes: Option<u64>,
+        /// Permissions of the root directory (e.g. `0o1777`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+}
+
+/// Which copy wins when a file in a synced mount changed on both the host
+/// and the guest since the last sync.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncConflictPolicy {
+    /// The most recently modified copy (the host's when that can't be told).
+    #[default]
+    Newer,
+    /// The host's copy.
+    Host,
+    /// The guest's copy.
+    Guest,
+}
+
+impl SyncConflictPolicy {
+    /// Name used in mount specs (`sync=host`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Newer => "newer",
+            Self::Host => "host",
+            Self::Guest => "guest",
+        }
+    }
+}
+
+impl std::str::FromStr for SyncConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newer" => Ok(Self::Newer),
+            "host" => Ok(Self::Host),
+            "guest" => Ok(Self::Guest),
+            other => Err(format!(
+                "unknown sync conflict policy '{}': expected newer, host or guest",
+                other
+            )),
+        }
+    }
+}
+
+/// State of a synced mount.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncMountStatus {
+    /// Mount tag (`smolvm0`, ...).
+    pub tag: String,
+    /// Path of the mount in the guest.
+    pub target: String,
+    /// Conflict policy.
+    pub policy: SyncConflictPolicy,
+    /// Files, directories and symlinks in sync.
+    pub entries: u64,
+    /// Entries copied from the host since the mount was set up.
+    pub copied_in: u64,
+    /// Entries copied to the host since the mount was set up.
+    pub copied_out: u64,
+    /// Changed guest paths waiting to be copied out.
+    pub pending: u64,
+    /// Conflicts resolved so far.
+    pub conflicts: u64,
+    /// Most recent conflicts (`path (kept host)`), newest last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_conflicts: Vec<String>,
+    /// Unix time of the last completed sync pass.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<u64>,
+    /// Last error, if the most recent pass failed for some path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Category of stored data that garbage collection can remove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcTarget {
+    /// Images not used by any registered container. Their layers and
+    /// configs are collected as well.
+    Images,
+    /// Intermediate images stored by image builds.
+    BuildCache,
+    /// Extracted layers not referenced by any image.
+    Layers,
+    /// Overlay directories not mounted and not owned by a registered
+    /// container, such as those left behind by crashed runs.
+    Overlays,
+    /// Image configs not referenced by any image.
+    Configs,
+    /// Compressed layer blobs cached for image push and save. They are
+    /// recreated when needed.
+    Blobs,
+}
+
+impl GcTarget {
+    /// Targets collected when none are given: data nothing refers to.
+    pub const DEFAULT: &'static [GcTarget] =
+        &[GcTarget::Layers, GcTarget::Overlays, GcTarget::Configs];
+
+    /// All targets.
+    pub const ALL: &'static [GcTarget] = &[
+        GcTarget::Images,
+        GcTarget::BuildCache,
+        GcTarget::Layers,
+        GcTarget::Overlays,
+        GcTarget::Configs,
+        GcTarget::Blobs,
+    ];
+
+    /// Short name used in CLI flags and reports.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GcTarget::Images => "images",
+            GcTarget::BuildCache => "build-cache",
+            GcTarget::Layers => "layers",
+            GcTarget::Overlays => "overlays",
+            GcTarget::Configs => "configs",
+            GcTarget::Blobs => "blobs",
+        }
+    }
+}
+
+impl std::str::FromStr for GcTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GcTarget::ALL
+            .iter()
+            .copied()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = GcTarget::ALL.iter().map(|t| t.as_str()).collect();
+                format!("unknown category '{}' (expected {})", s, names.join(", "))
+            })
+    }
+}
+
+/// An item removed (or, in a dry run, to be removed) by garbage collection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcItem {
+    /// Category of the item.
+    pub target: GcTarget,
+    /// Image reference, layer digest, overlay or blob name.
+    pub id: String,
+    /// Bytes freed by removing it.
+    pub size: u64,
+}
+
+/// Result of garbage collection or image removal.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    /// Total bytes freed.
+    pub freed_bytes: u64,
+    /// Whether this was a dry run.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Removed items.
+    #[serde(default)]
+    pub items: Vec<GcItem>,
+}
+
+/// Storage usage by category, returned by DiskUsage.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskUsage {
+    /// Images, excluding the build cache.
+    pub images: Vec<ImageUsage>,
+    /// Registered containers.
+    pub containers: Vec<ContainerUsage>,
+    /// Extracted layers.
+    pub layers: UsageSummary,
+    /// Overlay directories.
+    pub overlays: UsageSummary,
+    /// Image configs.
+    pub configs: UsageSummary,
+    /// Intermediate build images (size counts layers no other image uses).
+    pub build_cache: UsageSummary,
+    /// Cached compressed layer blobs.
+    pub blobs: UsageSummary,
+}
+
+/// Space used by one image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUsage {
+    /// Image reference.
+    pub reference: String,
+    /// Total size of the image's layers.
+    pub size: u64,
+    /// Size of layers shared with other images.
+    pub shared_size: u64,
+    /// Size of layers only this image uses (freed by removing it).
+    pub unique_size: u64,
+    /// Number of registered containers using the image.
+    pub containers: usize,
+}
+
+/// Space used by one container's writable layer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerUsage {
+    /// Container ID.
+    pub id: String,
+    /// Image the container was created from.
+    pub image: String,
+    /// Container state.
+    pub state: String,
+    /// Size of the container's writable layer.
+    pub size: u64,
+}
+
+/// Count and size of a storage category.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    /// Number of items.
+    pub count: usize,
+    /// Total size in bytes.
+    pub size: u64,
+    /// Items that garbage collection of this category would remove.
+    pub reclaimable_count: usize,
+    /// Bytes that garbage collection of this category would free.
+    pub reclaimable_size: u64,
+}
+
+/// Compression algorithm for layer blobs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerCompression {
+    /// gzip (widest registry and runtime support).
+    #[default]
+    Gzip,
+    /// zstd (smaller and faster, requires a recent runtime to pull).
+    Zstd,
+}
+
+impl LayerCompression {
+    /// OCI media type for a layer compressed with this algorithm.
+    pub fn media_type(self) -> &'static str {
+        match self {
+            LayerCompression::Gzip => "application/vnd.oci.image.layer.v1.tar+gzip",
+            LayerCompression::Zstd => "application/vnd.oci.image.layer.v1.tar+zstd",
+        }
+    }
+
+    /// Short name used in CLI flags and cache keys.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LayerCompression::Gzip => "gzip",
+            LayerCompression::Zstd => "zstd",
+        }
+    }
+}
+
+impl std::str::FromStr for LayerCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(LayerCompression::Gzip),
+            "zstd" => Ok(LayerCompression::Zstd),
+            other => Err(format!(
+                "unknown compression '{}' (expected gzip or zstd)",
+                other
+            )),
+        }
+    }
+}
+
+/// Content descriptor for a blob in the agent blob store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobDescriptor {
+    /// OCI media type of the blob.
+    pub media_type: String,
+    /// Blob digest (sha256:...).
+    pub digest: String,
+    /// Blob size in bytes.
+    pub size: u64,
+}
+
+/// Everything needed to push an image, returned by PreparePush.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushPlan {
+    /// Serialized image manifest. Must be uploaded byte-for-byte so its
+    /// digest matches.
+    pub manifest: String,
+    /// Media type of the manifest.
+    pub manifest_media_type: String,
+    /// Image config blob.
+    pub config: BlobDescriptor,
+    /// Layer blobs in order.
+    pub layers: Vec<BlobDescriptor>,
+}
+
+/// Repository under which intermediate build results are stored.
+///
+/// Each build step's result is stored as `smolvm-build-cache:<key>`, where
+/// the key hashes the step and everything it depends on. A later build with
+/// the same step finds the image and skips it.
+pub const BUILD_CACHE_REPOSITORY: &str = "smolvm-build-cache";
+
+/// A single image build instruction, executed by BuildStep.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BuildInstruction {
+    /// Run a command in the image rootfs (RUN).
+    Run {
+        /// Command and arguments.
+        command: Vec<String>,
+        /// Build-time environment (ARG values), added to the image
+        /// environment for this command only.
+        #[serde(default)]
+        env: Vec<(String, String)>,
+    },
+    /// Copy files into the image rootfs (COPY, ADD).
+    Copy {
+        /// Where the files come from.
+        source: CopySource,
+        /// Source paths, relative to the source root.
+        paths: Vec<String>,
+        /// Destination path; relative paths resolve against the working
+        /// directory. A trailing `/` or multiple sources make it a directory.
+        dest: String,
+        /// Owner for copied files (`user[:group]`, names or IDs).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chown: Option<String>,
+        /// Permission bits for copied files.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chmod: Option<u32>,
+        /// Unpack local tar archives instead of copying them (ADD).
+        #[serde(default)]
+        extract: bool,
+    },
+    /// Change the image config without creating a layer (ENV, WORKDIR,
+    /// USER, ENTRYPOINT, CMD, LABEL).
+    Config {
+        /// Environment variables to set.
+        #[serde(default)]
+        env: Vec<(String, String)>,
+        /// New working directory. Also created in the rootfs if missing.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workdir: Option<String>,
+        /// New user.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        /// New entrypoint.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        entrypoint: Option<Vec<String>>,
+        /// New default command.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cmd: Option<Vec<String>>,
+        /// Labels to set.
+        #[serde(default)]
+        labels: Vec<(String, String)>,
+    },
+}
+
+/// Source of files for a COPY build step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CopySource {
+    /// A build context uploaded with UploadBuildContext.
+    Context {
+        /// Context ID.
+        id: String,
+    },
+    /// The rootfs of a stored image (`COPY --from`).
+    Image {
+        /// Image reference.
+        reference: String,
+    },
+}
+
+/// Result of a BuildStep request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildStepResult {
+    /// Exit code of a RUN command (0 for other steps).
+    pub exit_code: i32,
+    /// Captured stdout of a RUN command.
+    #[serde(default)]
+    pub stdout: String,
+    /// Captured stderr of a RUN command.
+    #[serde(default)]
+    pub stderr: String,
+    /// The stored image; `None` if the RUN command failed.
+    #[serde(default)]
+    pub image: Option<ImageInfo>,
+}
+
 // ============================================================================
 // Workload VM Protocol (Command Execution)
 // ============================================================================
@@ -884,11 +1571,157 @@ mod tests {
         assert!(json.contains("progress"));
     }
 
+    #[test]
+    fn test_prepare_push_compression_default() {
+        let req: AgentRequest =
+            serde_json::from_str(r#"{"method":"prepare_push","image":"alpine"}"#).unwrap();
+        let AgentRequest::PreparePush { image, compression } = req else {
+            panic!("expected PreparePush variant, got {:?}", req);
+        };
+        assert_eq!(image, "alpine");
+        assert_eq!(compression, LayerCompression::Gzip);
+
+        let json = serde_json::to_string(&AgentRequest::PreparePush {
+            image: "alpine".to_string(),
+            compression: LayerCompression::Zstd,
+        })
+        .unwrap();
+        assert!(json.contains(r#""compression":"zstd""#));
+        assert_eq!("zstd".parse(), Ok(LayerCompression::Zstd));
+        assert!("lz4".parse::<LayerCompression>().is_err());
+    }
+
+    #[test]
+    fn test_build_step_serialization() {
+        let req = AgentRequest::BuildStep {
+            parent: None,
+            target: "smolvm-build-cache:abc".to_string(),
+            step: BuildInstruction::Copy {
+                source: CopySource::Context {
+                    id: "ctx".to_string(),
+                },
+                paths: vec!["src".to_string()],
+                dest: "/app/".to_string(),
+                chown: None,
+                chmod: Some(0o755),
+                extract: false,
+            },
+            created_by: "COPY src /app/".to_string(),
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""method":"build_step""#));
+        assert!(json.contains(r#""op":"copy""#));
+        assert!(json.contains(r#""kind":"context""#));
+        assert!(!json.contains("parent"));
+
+        let req: AgentRequest = serde_json::from_str(
+            r#"{"method":"build_step","target":"t","step":{"op":"config","cmd":["sh"]}}"#,
+        )
+        .unwrap();
+        let AgentRequest::BuildStep { parent, step, .. } = req else {
+            panic!("expected BuildStep variant, got {:?}", req);
+        };
+        assert_eq!(parent, None);
+        assert_eq!(
+            step,
+            BuildInstruction::Config {
+                env: vec![],
+                workdir: None,
+                user: None,
+                entrypoint: None,
+                cmd: Some(vec!["sh".to_string()]),
+                labels: vec![],
+            }
+        );
+    }
+
     #[test]
     fn test_ports_constants() {
         assert_eq!(ports::WORKLOAD_CONTROL, 5000);
         assert_eq!(ports::WORKLOAD_LOGS, 5001);
         assert_eq!(ports::AGENT_CONTROL, 6000);
+        assert_eq!(ports::EGRESS_PROXY, 6001);
+        assert_eq!(ports::PORT_FORWARD, 6002);
+        assert_eq!(ports::DNS_RESOLVER, 6003);
+    }
+
+    #[test]
+    fn test_dns_config_serialization() {
+        let config = DnsConfig {
+            servers: vec!["10.0.0.2".parse().unwrap()],
+            hosts: vec![HostEntry {
+                name: "db".into(),
+                address: "10.0.0.5".parse().unwrap(),
+            }],
+            ..Default::default()
+        };
+        let req = AgentRequest::ConfigureDns {
+            config: config.clone(),
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""method":"configure_dns""#));
+        assert!(json.contains(r#""address":"10.0.0.5""#));
+        assert!(!json.contains("search"));
+        assert!(!json.contains("host_resolver"));
+
+        match serde_json::from_str(&json).unwrap() {
+            AgentRequest::ConfigureDns { config: parsed } => assert_eq!(parsed, config),
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_container_mount_serialization() {
+        let mounts = vec![
+            ContainerMount::bind("smolvm0", "/data", true),
+            ContainerMount::tmpfs("/tmp", Some(256 * 1024 * 1024), Some(0o1777)),
+        ];
+        let json = serde_json::to_string(&mounts).unwrap();
+        assert!(json.contains(r#""kind":{"type":"bind","tag":"smolvm0"}"#));
+        assert!(json.contains(r#""type":"tmpfs","size_bytes":268435456,"mode":1023"#));
+        assert!(!json.contains("noexec"));
+
+        let parsed: Vec<ContainerMount> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, mounts);
+        assert_eq!(parsed[0].tag(), Some("smolvm0"));
+        assert_eq!(parsed[1].tag(), None);
+        assert!(parsed[1].nosuid && parsed[1].nodev);
+    }
+
+    #[test]
+    fn test_sync_conflict_policy_names() {
+        for policy in [
+            SyncConflictPolicy::Newer,
+            SyncConflictPolicy::Host,
+            SyncConflictPolicy::Guest,
+        ] {
+            assert_eq!(policy.as_str().parse::<SyncConflictPolicy>(), Ok(policy));
+            let json = serde_json::to_string(&policy).unwrap();
+            assert_eq!(json, format!("\"{}\"", policy.as_str()));
+        }
+        assert!("theirs".parse::<SyncConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_proxy_config_env() {
+        let config = ProxyConfig {
+            http_proxy: Some("http://proxy:3128".into()),
+            https_proxy: None,
+            no_proxy: vec!["localhost".into(), ".corp".into()],
+            ca_certs: vec![],
+        };
+        let env = config.env();
+        assert!(env.contains(&("HTTP_PROXY".into(), "http://proxy:3128".into())));
+        assert!(env.contains(&("http_proxy".into(), "http://proxy:3128".into())));
+        assert!(env.contains(&("no_proxy".into(), "localhost,.corp".into())));
+        assert!(!env
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case("HTTPS_PROXY")));
+
+        let req = AgentRequest::ConfigureProxy { config };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""method":"configure_proxy""#));
+        assert!(!json.contains("ca_certs"));
     }
 
     #[test]
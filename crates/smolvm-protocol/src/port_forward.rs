@@ -0,0 +1,159 @@
+//! Wire format for published ports forwarded over vsock.
+//!
+//! Ports that TSI can't publish (UDP, or TCP bound to a specific host
+//! address) are served by a host-side listener that opens one vsock stream
+//! per TCP connection or UDP peer to [`ports::PORT_FORWARD`]. Each stream
+//! starts with a 3-byte header naming the protocol and guest port:
+//!
+//! ```text
+//! +-------------+-----------------------+
+//! | Proto (1)   | Guest port (2 BE)     |
+//! +-------------+-----------------------+
+//! ```
+//!
+//! TCP streams carry raw bytes after the header. UDP streams carry
+//! datagrams, each prefixed with a 2-byte big-endian length.
+//!
+//! [`ports::PORT_FORWARD`]: crate::ports::PORT_FORWARD
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+/// Transport protocol of a published port.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    /// TCP (the default).
+    #[default]
+    Tcp,
+    /// UDP.
+    Udp,
+}
+
+impl PortProtocol {
+    /// Whether this is the default protocol (TCP).
+    pub fn is_tcp(&self) -> bool {
+        *self == PortProtocol::Tcp
+    }
+
+    fn wire_id(self) -> u8 {
+        match self {
+            PortProtocol::Tcp => 0,
+            PortProtocol::Udp => 1,
+        }
+    }
+}
+
+impl fmt::Display for PortProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortProtocol::Tcp => f.write_str("tcp"),
+            PortProtocol::Udp => f.write_str("udp"),
+        }
+    }
+}
+
+impl FromStr for PortProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(PortProtocol::Tcp),
+            "udp" => Ok(PortProtocol::Udp),
+            _ => Err(format!("unknown protocol '{}' (expected tcp or udp)", s)),
+        }
+    }
+}
+
+/// Largest UDP payload carried in one frame.
+pub const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+/// Write the stream header selecting the guest port to forward to.
+pub fn write_header<W: Write>(
+    w: &mut W,
+    protocol: PortProtocol,
+    guest_port: u16,
+) -> io::Result<()> {
+    let port = guest_port.to_be_bytes();
+    w.write_all(&[protocol.wire_id(), port[0], port[1]])
+}
+
+/// Read the stream header written by [`write_header`].
+pub fn read_header<R: Read>(r: &mut R) -> io::Result<(PortProtocol, u16)> {
+    let mut buf = [0u8; 3];
+    r.read_exact(&mut buf)?;
+    let protocol = match buf[0] {
+        0 => PortProtocol::Tcp,
+        1 => PortProtocol::Udp,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown forward protocol id {}", other),
+            ))
+        }
+    };
+    Ok((protocol, u16::from_be_bytes([buf[1], buf[2]])))
+}
+
+/// Write one length-prefixed UDP datagram.
+pub fn write_datagram<W: Write>(w: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "datagram too large"))?;
+    let mut frame = Vec::with_capacity(2 + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    w.write_all(&frame)
+}
+
+/// Read one length-prefixed UDP datagram into `buf`, returning its length.
+///
+/// `buf` must hold at least [`MAX_DATAGRAM_SIZE`] bytes.
+pub fn read_datagram<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = [0u8; 2];
+    r.read_exact(&mut len)?;
+    let len = u16::from_be_bytes(len) as usize;
+    if len > buf.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "datagram larger than buffer",
+        ));
+    }
+    r.read_exact(&mut buf[..len])?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_and_datagram_roundtrip() {
+        let mut wire = Vec::new();
+        write_header(&mut wire, PortProtocol::Udp, 5353).unwrap();
+        write_datagram(&mut wire, b"query").unwrap();
+        write_datagram(&mut wire, b"").unwrap();
+
+        let mut r = io::Cursor::new(wire);
+        assert_eq!(read_header(&mut r).unwrap(), (PortProtocol::Udp, 5353));
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let n = read_datagram(&mut r, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"query");
+        assert_eq!(read_datagram(&mut r, &mut buf).unwrap(), 0);
+
+        let mut bad = io::Cursor::new(vec![9u8, 0, 80]);
+        assert!(read_header(&mut bad).is_err());
+    }
+
+    #[test]
+    fn test_protocol_parse_and_display() {
+        assert_eq!("UDP".parse::<PortProtocol>().unwrap(), PortProtocol::Udp);
+        assert_eq!(PortProtocol::Tcp.to_string(), "tcp");
+        assert!("sctp".parse::<PortProtocol>().is_err());
+        assert_eq!(
+            serde_json::to_string(&PortProtocol::Udp).unwrap(),
+            "\"udp\""
+        );
+    }
+}
@@ -6,8 +6,10 @@
 use crate::error::{Error, Result};
 use crate::registry::{extract_registry, rewrite_image_registry, RegistryAuth, RegistryConfig};
 use smolvm_protocol::{
-    encode_message, AgentRequest, AgentResponse, ContainerInfo, ImageInfo, OverlayInfo,
-    StorageStatus, MAX_FRAME_SIZE, PROTOCOL_VERSION,
+    encode_message, AgentRequest, AgentResponse, BuildInstruction, BuildStepResult, ContainerInfo,
+    ContainerMount, DiskUsage, DnsConfig, GcReport, GcTarget, ImageInfo, LayerCompression,
+    OverlayInfo, ProxyConfig, PushPlan, StorageStatus, SyncMountStatus, IMPORT_CHUNK_SIZE,
+    MAX_FRAME_SIZE, PROTOCOL_VERSION,
 };
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
@@ -40,6 +42,14 @@ const IMAGE_PULL_TIMEOUT_SECS: u64 = 600;
 /// running long commands or interactive shells.
 const INTERACTIVE_TIMEOUT_SECS: u64 = 3600;
 
+/// Read timeout for a single image build step (1 hour).
+/// RUN steps execute arbitrary commands such as compiles or package installs.
+const BUILD_STEP_TIMEOUT_SECS: u64 = 3600;
+
+/// Read timeout for online storage resize (5 minutes).
+/// resize2fs has to initialize block group metadata for the added space.
+const STORAGE_RESIZE_TIMEOUT_SECS: u64 = 300;
+
 /// Buffer time added to user-specified timeouts (5 seconds).
 /// When users specify a command timeout, we add this buffer to the socket
 /// timeout to allow for protocol overhead and response transmission.
@@ -101,8 +111,8 @@ pub struct RunConfig {
     pub env: Vec<(String, String)>,
     /// Working directory inside the container.
     pub workdir: Option<String>,
-    /// Volume mounts as (tag, guest_path, read_only) tuples.
-    pub mounts: Vec<(String, String, bool)>,
+    /// Bind and tmpfs mounts for the container.
+    pub mounts: Vec<ContainerMount>,
     /// Timeout for command execution.
     pub timeout: Option<Duration>,
     /// Whether to allocate a TTY.
@@ -135,8 +145,8 @@ impl RunConfig {
         self
     }
 
-    /// Set volume mounts.
-    pub fn with_mounts(mut self, mounts: Vec<(String, String, bool)>) -> Self {
+    /// Set container mounts.
+    pub fn with_mounts(mut self, mounts: Vec<ContainerMount>) -> Self {
         self.mounts = mounts;
         self
     }
@@ -289,6 +299,16 @@ fn expect_completed(resp: AgentResponse, op: &str) -> Result<(i32, String, Strin
     }
 }
 
+/// Read up to `IMPORT_CHUNK_SIZE` bytes; returns fewer only at end of input.
+fn read_chunk(input: &mut impl Read) -> Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(IMPORT_CHUNK_SIZE);
+    input
+        .take(IMPORT_CHUNK_SIZE as u64)
+        .read_to_end(&mut chunk)
+        .map_err(|e| Error::agent("read upload data", e.to_string()))?;
+    Ok(chunk)
+}
+
 impl AgentClient {
     /// Set socket read timeout, returning an error if it fails.
     ///
@@ -615,19 +635,196 @@ impl AgentClient {
     /// # Arguments
     ///
     /// * `dry_run` - If true, only report what would be deleted
-    pub fn garbage_collect(&mut self, dry_run: bool) -> Result<u64> {
-        let resp = self.request(&AgentRequest::GarbageCollect { dry_run })?;
+    /// * `targets` - Categories to collect (empty for the default set)
+    pub fn garbage_collect(&mut self, dry_run: bool, targets: &[GcTarget]) -> Result<GcReport> {
+        let resp = self.request(&AgentRequest::GarbageCollect {
+            dry_run,
+            targets: targets.to_vec(),
+        })?;
+        expect_data(resp, "garbage collect")
+    }
 
+    /// Remove an image and the layers no other image uses.
+    ///
+    /// Fails with a conflict error if a container was created from the image.
+    pub fn remove_image(&mut self, image: &str) -> Result<GcReport> {
+        let resp = self.request(&AgentRequest::RemoveImage {
+            image: image.to_string(),
+        })?;
         match resp {
-            AgentResponse::Ok { data: Some(data) } => {
-                let freed = data["freed_bytes"].as_u64().unwrap_or(0);
-                Ok(freed)
+            AgentResponse::Error { message, code } => match code.as_deref() {
+                Some("NOT_FOUND") => Err(Error::agent_not_found("remove image", message)),
+                Some("IN_USE") => Err(Error::agent_conflict("remove image", message)),
+                _ => Err(Error::agent("remove image", message)),
+            },
+            resp => expect_data(resp, "remove image"),
+        }
+    }
+
+    /// Report storage usage by category.
+    pub fn disk_usage(&mut self) -> Result<DiskUsage> {
+        let resp = self.request(&AgentRequest::DiskUsage)?;
+        expect_data(resp, "disk usage")
+    }
+
+    /// Prepare a locally stored image for pushing to a registry.
+    ///
+    /// The agent re-tars the image's layers into its blob store with the
+    /// given compression and returns the blobs and manifest to upload.
+    ///
+    /// # Note
+    ///
+    /// This operation uses a 10-minute timeout, since large layers must be
+    /// re-compressed on first push.
+    pub fn prepare_push(&mut self, image: &str, compression: LayerCompression) -> Result<PushPlan> {
+        self.set_read_timeout(Duration::from_secs(IMAGE_PULL_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        let resp = self.request(&AgentRequest::PreparePush {
+            image: image.to_string(),
+            compression,
+        })?;
+        expect_data(resp, "prepare push")
+    }
+
+    /// Export a blob from the agent blob store, writing it to `out`.
+    ///
+    /// The agent streams the blob as a sequence of `LayerData` chunks.
+    /// Returns the number of bytes written.
+    pub fn export_blob(&mut self, digest: &str, out: &mut impl Write) -> Result<u64> {
+        self.set_read_timeout(Duration::from_secs(IMAGE_PULL_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        self.send(&AgentRequest::ExportBlob {
+            digest: digest.to_string(),
+        })?;
+
+        let mut written = 0u64;
+        loop {
+            match self.receive()? {
+                AgentResponse::LayerData { data, done } => {
+                    out.write_all(&data)
+                        .map_err(|e| Error::agent("export blob", e.to_string()))?;
+                    written += data.len() as u64;
+                    if done {
+                        return Ok(written);
+                    }
+                }
+                AgentResponse::Error { message, .. } => {
+                    return Err(Error::agent("export blob", message));
+                }
+                _ => return Err(Error::agent("export blob", "unexpected response type")),
             }
-            AgentResponse::Error { message, .. } => Err(Error::agent("garbage collect", message)),
-            _ => Err(Error::agent("garbage collect", "unexpected response type")),
         }
     }
 
+    /// Import images from an OCI layout or `docker save` tarball.
+    ///
+    /// The archive is streamed to the agent in `IMPORT_CHUNK_SIZE` chunks.
+    /// If `reference` is given, the archive's single image is stored under
+    /// that name. Returns the imported images.
+    pub fn import_image(
+        &mut self,
+        archive: &mut impl Read,
+        reference: Option<&str>,
+    ) -> Result<Vec<ImageInfo>> {
+        self.set_read_timeout(Duration::from_secs(IMAGE_PULL_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        let mut reference = reference.map(String::from);
+        self.send_chunks(archive, |data, done| AgentRequest::ImportImage {
+            data,
+            done,
+            reference: reference.take(),
+        })?;
+
+        let resp = self.receive()?;
+        expect_data(resp, "import image")
+    }
+
+    /// Stream `input` to the agent in `IMPORT_CHUNK_SIZE` chunks.
+    ///
+    /// `request` builds the request for each chunk. The input is read one
+    /// chunk ahead so the final data-carrying chunk is marked done.
+    fn send_chunks(
+        &mut self,
+        input: &mut impl Read,
+        mut request: impl FnMut(Vec<u8>, bool) -> AgentRequest,
+    ) -> Result<()> {
+        let mut pending = read_chunk(input)?;
+        loop {
+            let next = if pending.len() == IMPORT_CHUNK_SIZE {
+                read_chunk(input)?
+            } else {
+                Vec::new()
+            };
+            let done = next.is_empty();
+            self.send(&request(pending, done))?;
+            if done {
+                return Ok(());
+            }
+            pending = next;
+        }
+    }
+
+    /// Upload a build context (a tar archive) for COPY/ADD build steps.
+    pub fn upload_build_context(&mut self, id: &str, archive: &mut impl Read) -> Result<()> {
+        self.set_read_timeout(Duration::from_secs(IMAGE_PULL_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        self.send_chunks(archive, |data, done| AgentRequest::UploadBuildContext {
+            id: id.to_string(),
+            data,
+            done,
+        })?;
+
+        let resp = self.receive()?;
+        expect_ok(resp, "upload build context")
+    }
+
+    /// Remove an uploaded build context.
+    pub fn remove_build_context(&mut self, id: &str) -> Result<()> {
+        let resp = self.request(&AgentRequest::RemoveBuildContext { id: id.to_string() })?;
+        expect_ok(resp, "remove build context")
+    }
+
+    /// Execute one image build step, storing the result as `target`.
+    ///
+    /// A failing RUN command is not an error: the result carries its exit
+    /// code and output, and no image.
+    ///
+    /// # Note
+    ///
+    /// This operation uses a 1-hour timeout, since RUN steps may compile
+    /// or install large amounts of software.
+    pub fn build_step(
+        &mut self,
+        parent: Option<&str>,
+        target: &str,
+        step: BuildInstruction,
+        created_by: &str,
+    ) -> Result<BuildStepResult> {
+        self.set_read_timeout(Duration::from_secs(BUILD_STEP_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        let resp = self.request(&AgentRequest::BuildStep {
+            parent: parent.map(String::from),
+            target: target.to_string(),
+            step,
+            created_by: created_by.to_string(),
+        })?;
+        expect_data(resp, "build step")
+    }
+
+    /// Store an existing image under an additional reference.
+    pub fn tag_image(&mut self, source: &str, target: &str) -> Result<ImageInfo> {
+        let resp = self.request(&AgentRequest::TagImage {
+            source: source.to_string(),
+            target: target.to_string(),
+        })?;
+        expect_data(resp, "tag image")
+    }
+
     /// Prepare an overlay filesystem for a workload.
     ///
     /// # Arguments
@@ -662,6 +859,48 @@ impl AgentClient {
         expect_data(resp, "storage status")
     }
 
+    /// Get the state of each synced mount.
+    pub fn sync_status(&mut self) -> Result<Vec<SyncMountStatus>> {
+        let resp = self.request(&AgentRequest::SyncStatus)?;
+        expect_data(resp, "sync status")
+    }
+
+    /// Grow the storage filesystem after the disk image was extended.
+    ///
+    /// Fails with a conflict error if the guest never sees the new disk
+    /// size, which is the case when the hypervisor can't signal capacity
+    /// changes to a running VM.
+    pub fn resize_storage(&mut self, size_bytes: u64) -> Result<StorageStatus> {
+        self.set_read_timeout(Duration::from_secs(STORAGE_RESIZE_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        let resp = self.request(&AgentRequest::ResizeStorage { size_bytes })?;
+        match resp {
+            AgentResponse::Error { message, code }
+                if code.as_deref() == Some("RESIZE_UNSUPPORTED") =>
+            {
+                Err(Error::agent_conflict("resize storage", message))
+            }
+            resp => expect_data(resp, "resize storage"),
+        }
+    }
+
+    /// Apply HTTP(S) proxy settings and extra CA certificates in the guest.
+    pub fn configure_proxy(&mut self, config: &ProxyConfig) -> Result<()> {
+        let resp = self.request(&AgentRequest::ConfigureProxy {
+            config: config.clone(),
+        })?;
+        expect_ok(resp, "configure proxy")
+    }
+
+    /// Apply DNS settings (resolv.conf, hosts entries) in the guest.
+    pub fn configure_dns(&mut self, config: &DnsConfig) -> Result<()> {
+        let resp = self.request(&AgentRequest::ConfigureDns {
+            config: config.clone(),
+        })?;
+        expect_ok(resp, "configure dns")
+    }
+
     /// Test network connectivity directly from the agent (not via chroot).
     /// Used to debug TSI networking.
     pub fn network_test(&mut self, url: &str) -> Result<serde_json::Value> {
@@ -955,7 +1194,7 @@ impl AgentClient {
     /// * `command` - Command and arguments
     /// * `env` - Environment variables
     /// * `workdir` - Working directory inside the rootfs
-    /// * `mounts` - Volume mounts as (virtiofs_tag, container_path, read_only)
+    /// * `mounts` - Bind and tmpfs mounts for the container
     ///
     /// # Returns
     ///
@@ -966,7 +1205,7 @@ impl AgentClient {
         command: Vec<String>,
         env: Vec<(String, String)>,
         workdir: Option<String>,
-        mounts: Vec<(String, String, bool)>,
+        mounts: Vec<ContainerMount>,
     ) -> Result<(i32, String, String)> {
         self.run_with_mounts_and_timeout(image, command, env, workdir, mounts, None)
     }
@@ -979,7 +1218,7 @@ impl AgentClient {
     /// * `command` - Command and arguments
     /// * `env` - Environment variables
     /// * `workdir` - Working directory inside the rootfs
-    /// * `mounts` - Volume mounts as (virtiofs_tag, container_path, read_only)
+    /// * `mounts` - Bind and tmpfs mounts for the container
     /// * `timeout` - Optional timeout duration. If exceeded, command is killed with exit code 124.
     ///
     /// # Returns
@@ -991,7 +1230,7 @@ impl AgentClient {
         command: Vec<String>,
         env: Vec<(String, String)>,
         workdir: Option<String>,
-        mounts: Vec<(String, String, bool)>,
+        mounts: Vec<ContainerMount>,
         timeout: Option<Duration>,
     ) -> Result<(i32, String, String)> {
         let _timeout_guard = self.set_exec_timeout(timeout)?;
@@ -1068,7 +1307,7 @@ impl AgentClient {
     /// * `command` - Command to run (e.g., ["sleep", "infinity"])
     /// * `env` - Environment variables
     /// * `workdir` - Working directory inside the container
-    /// * `mounts` - Volume mounts as (virtiofs_tag, container_path, read_only)
+    /// * `mounts` - Bind and tmpfs mounts for the container
     ///
     /// # Returns
     ///
@@ -1079,7 +1318,7 @@ impl AgentClient {
         command: Vec<String>,
         env: Vec<(String, String)>,
         workdir: Option<String>,
-        mounts: Vec<(String, String, bool)>,
+        mounts: Vec<ContainerMount>,
     ) -> Result<ContainerInfo> {
         let resp = self.request(&AgentRequest::CreateContainer {
             image: image.to_string(),
@@ -6,12 +6,17 @@
 
 use crate::consts::ENV_SMOLVM_LIB_DIR;
 use crate::error::{Error, Result};
+use crate::network::{
+    serve_ports, serve_system_resolver, start_switch_port, EgressProxy, NetworkStore, Upstream,
+    DEFAULT_DNS_ADDR,
+};
 use crate::storage::{OverlayDisk, StorageDisk};
 use crate::util::libkrunfw_filename;
 use crate::vm::config::HostMount;
 
 use smolvm_protocol::ports;
 use std::ffi::{CStr, CString};
+use std::os::fd::IntoRawFd;
 use std::path::{Path, PathBuf};
 
 use super::{PortMapping, VmResources};
@@ -57,11 +62,36 @@ extern "C" {
     fn krun_start_enter(ctx: u32) -> i32;
     fn krun_disable_implicit_vsock(ctx: u32) -> i32;
     fn krun_add_vsock(ctx: u32, tsi_features: u32) -> i32;
+    fn krun_add_net_unixgram(
+        ctx: u32,
+        c_path: *const libc::c_char,
+        fd: libc::c_int,
+        c_mac: *mut u8,
+        features: u32,
+        flags: u32,
+    ) -> i32;
 }
 
 // TSI (Transparent Socket Impersonation) feature flags
 const KRUN_TSI_HIJACK_INET: u32 = 1 << 0;
 
+/// Egress proxy socket filename, created next to the control socket.
+pub const EGRESS_SOCKET_FILENAME: &str = "egress.sock";
+
+/// Egress log filename (denied connections), next to the control socket.
+pub const EGRESS_LOG_FILENAME: &str = "egress.log";
+
+/// Port forwarding socket filename (UDP and bound ports), next to the
+/// control socket.
+pub const FORWARD_SOCKET_FILENAME: &str = "forward.sock";
+
+/// Port control socket filename (publish/withdraw ports at runtime), next
+/// to the control socket.
+pub const PORTS_SOCKET_FILENAME: &str = "ports.sock";
+
+/// Host DNS resolver socket filename, next to the control socket.
+pub const RESOLVER_SOCKET_FILENAME: &str = "dns.sock";
+
 /// Find the directory containing libkrunfw by checking explicit overrides and
 /// paths relative to the current executable.
 ///
@@ -160,6 +190,9 @@ pub fn launch_agent_vm(
     // Preload libkrunfw so libkrun's internal dlopen can find it
     preload_libkrunfw();
 
+    // Hold the named volumes' locks until the VM exits
+    let _volume_locks = crate::volume::lock_volumes(mounts)?;
+
     unsafe {
         // Set log level (0 = off, 1 = error, 2 = warn, 3 = info, 4 = debug)
         // Enable debug logging to trace vsock timing issues
@@ -227,7 +260,14 @@ pub fn launch_agent_vm(
             ));
         }
 
-        if resources.network || !port_mappings.is_empty() {
+        // An egress allowlist replaces TSI: the guest reaches the network only
+        // through the host-side proxy on the egress vsock port (added below).
+        let egress_allowlist = !resources.network_allow.is_empty();
+
+        // TSI publishes plain TCP ports; the rest go through host forwarders
+        let tsi_ports: Vec<&PortMapping> = port_mappings.iter().filter(|p| p.uses_tsi()).collect();
+
+        if !egress_allowlist && (resources.network || !tsi_ports.is_empty()) {
             // Add vsock with TSI HIJACK_INET flag to enable network access
             if krun_add_vsock(ctx, KRUN_TSI_HIJACK_INET) < 0 {
                 krun_free_ctx(ctx);
@@ -238,7 +278,7 @@ pub fn launch_agent_vm(
             }
 
             // Set port mappings for TCP port forwarding
-            let port_cstrings: Vec<CString> = port_mappings
+            let port_cstrings: Vec<CString> = tsi_ports
                 .iter()
                 .map(|p| {
                     CString::new(format!("{}:{}", p.host, p.guest))
@@ -256,7 +296,7 @@ pub fn launch_agent_vm(
 
             tracing::debug!(
                 network = resources.network,
-                port_count = port_mappings.len(),
+                port_count = tsi_ports.len(),
                 "configured TSI networking with HIJACK_INET"
             );
         } else {
@@ -319,6 +359,157 @@ pub fn launch_agent_vm(
             ));
         }
 
+        // Serve the egress proxy and route the guest's egress port to it.
+        // The proxy thread lives on in this process alongside the VMM.
+        if egress_allowlist {
+            let runtime_dir = vsock_socket.parent().unwrap_or(Path::new("."));
+            let egress_socket = runtime_dir.join(EGRESS_SOCKET_FILENAME);
+            let proxy = EgressProxy::new(
+                resources.network_allow.clone(),
+                Some(runtime_dir.join(EGRESS_LOG_FILENAME)),
+            );
+            let listener = try_or_free_ctx!(
+                EgressProxy::bind(&egress_socket),
+                "start egress proxy",
+                "failed to bind egress proxy socket"
+            );
+            try_or_free_ctx!(
+                proxy.spawn(listener),
+                "start egress proxy",
+                "failed to spawn egress proxy thread"
+            );
+
+            let egress_path = try_or_free_ctx!(
+                path_to_cstring(&egress_socket),
+                "add vsock port",
+                "path contains null byte"
+            );
+            if krun_add_vsock_port2(ctx, ports::EGRESS_PROXY, egress_path.as_ptr(), false) < 0 {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add vsock port",
+                    "krun_add_vsock_port2 failed for egress proxy",
+                ));
+            }
+
+            tracing::debug!(
+                rules = resources.network_allow.len(),
+                "configured egress allowlist proxy"
+            );
+        }
+
+        // Serve the host's system resolver so the guest follows the host's
+        // split-DNS setup. Allowlisted VMs resolve through the egress proxy.
+        let host_resolver = resources.dns.as_ref().is_some_and(|dns| dns.host_resolver);
+        if host_resolver && resources.network && !egress_allowlist {
+            let runtime_dir = vsock_socket.parent().unwrap_or(Path::new("."));
+            let resolver_socket = runtime_dir.join(RESOLVER_SOCKET_FILENAME);
+            try_or_free_ctx!(
+                serve_system_resolver(&resolver_socket),
+                "start DNS resolver",
+                "failed to serve DNS resolver socket"
+            );
+            let resolver_path = try_or_free_ctx!(
+                path_to_cstring(&resolver_socket),
+                "add vsock port",
+                "path contains null byte"
+            );
+            if krun_add_vsock_port2(ctx, ports::DNS_RESOLVER, resolver_path.as_ptr(), false) < 0 {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add vsock port",
+                    "krun_add_vsock_port2 failed for DNS resolver",
+                ));
+            }
+            tracing::debug!("configured host DNS resolver");
+        }
+
+        // Serve UDP and address-bound ports from this process and route them
+        // to the agent's forwarding port. The port control socket lets ports
+        // be published while the VM runs, so the route is always added.
+        {
+            let runtime_dir = vsock_socket.parent().unwrap_or(Path::new("."));
+            let forward_socket = runtime_dir.join(FORWARD_SOCKET_FILENAME);
+            let control_socket = runtime_dir.join(PORTS_SOCKET_FILENAME);
+            let forwarded = match serve_ports(port_mappings, &forward_socket, &control_socket) {
+                Ok(n) => n,
+                Err(e) => {
+                    krun_free_ctx(ctx);
+                    return Err(Error::agent("publish ports", e.to_string()));
+                }
+            };
+
+            let forward_path = try_or_free_ctx!(
+                path_to_cstring(&forward_socket),
+                "add vsock port",
+                "path contains null byte"
+            );
+            if krun_add_vsock_port2(ctx, ports::PORT_FORWARD, forward_path.as_ptr(), true) < 0 {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add vsock port",
+                    "krun_add_vsock_port2 failed for port forwarding",
+                ));
+            }
+
+            tracing::debug!(count = forwarded, "configured host port forwarders");
+        }
+
+        // Attach the VM to its private network. This process serves the
+        // switch port that relays frames between the VM and the other members.
+        let mut private_net_env = Vec::new();
+        if let Some(attachment) = &resources.private_network {
+            let network = try_or_free_ctx!(
+                NetworkStore::open().and_then(|store| store.get(&attachment.network)),
+                "join private network",
+                format!("network '{}' does not exist", attachment.network)
+            );
+            private_net_env.push(cstr(&format!(
+                "SMOLVM_NET_ADDR={}/{}",
+                attachment.address, network.prefix_len
+            )));
+            private_net_env.push(cstr(&format!("SMOLVM_NET_GATEWAY={}", network.gateway())));
+            private_net_env.push(cstr(&format!("SMOLVM_NET_DOMAIN={}", network.name)));
+
+            // Names outside the network resolve upstream only with egress
+            let upstream = (resources.network && !egress_allowlist).then(|| match &resources.dns {
+                Some(dns) if dns.host_resolver => Upstream::System,
+                Some(dns) => {
+                    Upstream::Server(dns.servers.first().copied().unwrap_or(DEFAULT_DNS_ADDR))
+                }
+                None => Upstream::Server(DEFAULT_DNS_ADDR),
+            });
+            let fd = match start_switch_port(network, attachment, upstream) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    krun_free_ctx(ctx);
+                    return Err(Error::agent("join private network", e.to_string()));
+                }
+            };
+            let mut mac = attachment.mac();
+            if krun_add_net_unixgram(
+                ctx,
+                std::ptr::null(),
+                fd.into_raw_fd(),
+                mac.as_mut_ptr(),
+                0,
+                0,
+            ) < 0
+            {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "join private network",
+                    "krun_add_net_unixgram failed",
+                ));
+            }
+
+            tracing::debug!(
+                network = %attachment.network,
+                address = %attachment.address,
+                "attached private network"
+            );
+        }
+
         // Set console output if specified
         if let Some(log_path) = console_log {
             let console_path = try_or_free_ctx!(
@@ -333,15 +524,76 @@ pub fn launch_agent_vm(
 
         // Add virtiofs mounts
         // Each mount gets a tag like "smolvm0", "smolvm1", etc.
-        // The guest must mount these manually (or via the agent)
+        // The guest must mount these manually (or via the agent).
+        // A single-file mount shares its parent directory, or a copy of the
+        // file next to the storage disk when the parent can't be shared.
+        // Image volumes are attached as disks after storage and overlay
+        // (/dev/vdc, /dev/vdd, ...) instead.
+        let mut volume_disks: Vec<(usize, String)> = Vec::new();
         for (i, mount) in mounts.iter().enumerate() {
+            if crate::volume::is_image_volume(&mount.source) {
+                // Without an overlay disk the volume would become /dev/vdb,
+                // which the agent takes for the overlay
+                if disks.overlay.is_none() {
+                    krun_free_ctx(ctx);
+                    return Err(Error::agent(
+                        "add volume disk",
+                        "image volumes require the rootfs overlay disk",
+                    ));
+                }
+                let device = format!("/dev/vd{}", (b'c' + volume_disks.len() as u8) as char);
+                let block_id = cstr(&format!("volume{}", i));
+                let disk_path = try_or_free_ctx!(
+                    path_to_cstring(&mount.source),
+                    "add volume disk",
+                    "path contains null byte"
+                );
+                tracing::debug!(
+                    tag = %crate::agent::mount_tag(i),
+                    disk = %mount.source.display(),
+                    device = %device,
+                    guest = %mount.target.display(),
+                    "adding volume disk"
+                );
+                if krun_add_disk2(
+                    ctx,
+                    block_id.as_ptr(),
+                    disk_path.as_ptr(),
+                    0,
+                    mount.read_only,
+                ) < 0
+                {
+                    krun_free_ctx(ctx);
+                    return Err(Error::agent(
+                        "add volume disk",
+                        format!("krun_add_disk2 failed for '{}'", mount.source.display()),
+                    ));
+                }
+                volume_disks.push((i, device));
+                continue;
+            }
+
             let tag = try_or_free_ctx!(
                 CString::new(crate::agent::mount_tag(i)),
                 "configure mount",
                 "mount tag contains null byte"
             );
+            let share_dir = disks
+                .storage
+                .path()
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(crate::mount::FILE_SHARES_DIR)
+                .join(crate::agent::mount_tag(i));
+            let share_dir = match crate::mount::prepare_share(mount, &share_dir) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    krun_free_ctx(ctx);
+                    return Err(e);
+                }
+            };
             let host_path = try_or_free_ctx!(
-                path_to_cstring(&mount.source),
+                path_to_cstring(&share_dir),
                 "configure mount",
                 "mount path contains null byte"
             );
@@ -391,6 +643,31 @@ pub fn launch_agent_vm(
             if let Ok(cstr) = CString::new(env_val) {
                 env_strings.push(cstr);
             }
+            // Format: SMOLVM_MOUNT_SYNC_0=newer (synced mounts only)
+            if let Some(policy) = mount.sync {
+                if let Ok(cstr) =
+                    CString::new(format!("SMOLVM_MOUNT_SYNC_{}={}", i, policy.as_str()))
+                {
+                    env_strings.push(cstr);
+                }
+            }
+            // Format: SMOLVM_MOUNT_DISK_0=/dev/vdc (image volumes only)
+            if let Some((_, device)) = volume_disks.iter().find(|(index, _)| *index == i) {
+                if let Ok(cstr) = CString::new(format!("SMOLVM_MOUNT_DISK_{}={}", i, device)) {
+                    env_strings.push(cstr);
+                }
+                continue;
+            }
+            // Format: SMOLVM_MOUNT_FILE_0=file_name (single-file mounts only)
+            if let Some(file) = mount.shared_file() {
+                if let Ok(cstr) = CString::new(format!(
+                    "SMOLVM_MOUNT_FILE_{}={}",
+                    i,
+                    file.to_string_lossy()
+                )) {
+                    env_strings.push(cstr);
+                }
+            }
         }
 
         // Pass mount count
@@ -400,6 +677,12 @@ pub fn launch_agent_vm(
             }
         }
 
+        // Tell the agent to forward its proxy port to the host egress proxy
+        if egress_allowlist {
+            env_strings.push(cstr("SMOLVM_EGRESS_PROXY=1"));
+        }
+        env_strings.extend(private_net_env);
+
         let mut envp: Vec<*const libc::c_char> = env_strings.iter().map(|s| s.as_ptr()).collect();
         envp.push(std::ptr::null());
 
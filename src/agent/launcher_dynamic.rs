@@ -7,11 +7,15 @@
 //! The static FFI path in `launcher.rs` remains untouched for normal operations.
 
 use crate::util::{libkrun_filename, libkrunfw_filename};
-use smolvm_protocol::ports;
+use smolvm_protocol::{ports, SyncConflictPolicy};
 use std::ffi::{CStr, CString};
 use std::path::{Path, PathBuf};
 
-use super::VmResources;
+use super::launcher::{
+    EGRESS_LOG_FILENAME, EGRESS_SOCKET_FILENAME, FORWARD_SOCKET_FILENAME, PORTS_SOCKET_FILENAME,
+    RESOLVER_SOCKET_FILENAME,
+};
+use super::{PortMapping, VmResources};
 
 // TSI (Transparent Socket Impersonation) feature flags
 const KRUN_TSI_HIJACK_INET: u32 = 1 << 0;
@@ -149,12 +153,18 @@ impl Drop for KrunFunctions {
 pub struct PackedMount {
     /// Virtiofs tag (e.g., "smolvm0").
     pub tag: String,
-    /// Host source path (passed to `krun_add_virtiofs`).
+    /// Host source path (passed to `krun_add_virtiofs`). For a single-file
+    /// mount this is the file's parent directory, or a private directory
+    /// holding a copy of the file.
     pub host_path: String,
+    /// File within `host_path` to expose, for a single-file mount.
+    pub file_name: Option<String>,
     /// Guest mount path (passed to agent via `SMOLVM_MOUNT_*` env).
     pub guest_path: String,
     /// Whether the mount is read-only.
     pub read_only: bool,
+    /// Conflict policy, for a mount synced through a guest-side copy.
+    pub sync: Option<SyncConflictPolicy>,
 }
 
 /// Configuration for launching a packed VM.
@@ -169,8 +179,8 @@ pub struct PackedLaunchConfig<'a> {
     pub layers_dir: &'a Path,
     /// Volume mounts.
     pub mounts: &'a [PackedMount],
-    /// Port mappings (host, guest).
-    pub port_mappings: &'a [(u16, u16)],
+    /// Port mappings.
+    pub port_mappings: &'a [PortMapping],
     /// VM resources.
     pub resources: VmResources,
     /// Debug logging.
@@ -272,18 +282,28 @@ pub fn launch_agent_vm_dynamic(
         free_ctx_on_err!("krun_disable_implicit_vsock failed");
     }
 
-    if config.resources.network || !config.port_mappings.is_empty() {
+    // An egress allowlist replaces TSI: the guest reaches the network only
+    // through the host egress proxy
+    let egress_allowlist = !config.resources.network_allow.is_empty();
+
+    // TSI publishes plain TCP ports; the rest go through host forwarders
+    let tsi_ports: Vec<&PortMapping> = config
+        .port_mappings
+        .iter()
+        .filter(|p| p.uses_tsi())
+        .collect();
+
+    if !egress_allowlist && (config.resources.network || !tsi_ports.is_empty()) {
         // SAFETY: ctx is valid, KRUN_TSI_HIJACK_INET is a valid flag
         if unsafe { (krun.add_vsock)(ctx, KRUN_TSI_HIJACK_INET) } < 0 {
             free_ctx_on_err!("krun_add_vsock with TSI failed");
         }
 
         // Set port mappings
-        let port_cstrings: Vec<CString> = config
-            .port_mappings
+        let port_cstrings: Vec<CString> = tsi_ports
             .iter()
-            .map(|(host, guest)| {
-                CString::new(format!("{}:{}", host, guest))
+            .map(|p| {
+                CString::new(format!("{}:{}", p.host, p.guest))
                     .expect("port mapping cannot contain null bytes")
             })
             .collect();
@@ -338,6 +358,81 @@ pub fn launch_agent_vm_dynamic(
         free_ctx_on_err!("krun_add_vsock_port2 failed");
     }
 
+    // Serve the egress proxy and route the guest's egress port to it
+    if egress_allowlist {
+        let runtime_dir = config.vsock_socket.parent().unwrap_or(Path::new("."));
+        let egress_socket = runtime_dir.join(EGRESS_SOCKET_FILENAME);
+        let proxy = crate::network::EgressProxy::new(
+            config.resources.network_allow.clone(),
+            Some(runtime_dir.join(EGRESS_LOG_FILENAME)),
+        );
+        let listener = try_or_free_ctx!(
+            crate::network::EgressProxy::bind(&egress_socket),
+            "failed to bind egress proxy socket"
+        );
+        try_or_free_ctx!(proxy.spawn(listener), "failed to spawn egress proxy thread");
+        let egress_path = try_or_free_ctx!(
+            path_to_cstring(&egress_socket),
+            "egress socket path contains null byte"
+        );
+        // SAFETY: ctx is valid, egress_path is a valid C string
+        if unsafe { (krun.add_vsock_port2)(ctx, ports::EGRESS_PROXY, egress_path.as_ptr(), false) }
+            < 0
+        {
+            free_ctx_on_err!("krun_add_vsock_port2 failed for egress proxy");
+        }
+    }
+
+    // Serve the host's system resolver. Allowlisted VMs resolve through the
+    // egress proxy.
+    let host_resolver = config
+        .resources
+        .dns
+        .as_ref()
+        .is_some_and(|dns| dns.host_resolver);
+    if host_resolver && config.resources.network && !egress_allowlist {
+        let runtime_dir = config.vsock_socket.parent().unwrap_or(Path::new("."));
+        let resolver_socket = runtime_dir.join(RESOLVER_SOCKET_FILENAME);
+        try_or_free_ctx!(
+            crate::network::serve_system_resolver(&resolver_socket),
+            "failed to serve DNS resolver socket"
+        );
+        let resolver_path = try_or_free_ctx!(
+            path_to_cstring(&resolver_socket),
+            "resolver socket path contains null byte"
+        );
+        // SAFETY: ctx is valid, resolver_path is a valid C string
+        if unsafe {
+            (krun.add_vsock_port2)(ctx, ports::DNS_RESOLVER, resolver_path.as_ptr(), false)
+        } < 0
+        {
+            free_ctx_on_err!("krun_add_vsock_port2 failed for DNS resolver");
+        }
+    }
+
+    // Serve UDP and address-bound ports, plus ports published at runtime,
+    // and route them to the agent
+    {
+        let runtime_dir = config.vsock_socket.parent().unwrap_or(Path::new("."));
+        let forward_socket = runtime_dir.join(FORWARD_SOCKET_FILENAME);
+        let control_socket = runtime_dir.join(PORTS_SOCKET_FILENAME);
+        if let Err(e) =
+            crate::network::serve_ports(config.port_mappings, &forward_socket, &control_socket)
+        {
+            free_ctx_on_err!(format!("failed to publish ports: {}", e));
+        }
+        let forward_path = try_or_free_ctx!(
+            path_to_cstring(&forward_socket),
+            "forward socket path contains null byte"
+        );
+        // SAFETY: ctx is valid, forward_path is a valid C string
+        if unsafe { (krun.add_vsock_port2)(ctx, ports::PORT_FORWARD, forward_path.as_ptr(), true) }
+            < 0
+        {
+            free_ctx_on_err!("krun_add_vsock_port2 failed for port forwarding");
+        }
+    }
+
     // Redirect console output to a log file so libkrun doesn't put the
     // inherited terminal into raw mode (which would break terminal echo
     // if the child is killed before exit observers can restore it).
@@ -377,6 +472,16 @@ pub fn launch_agent_vm_dynamic(
         if let Ok(cstr) = CString::new(env_val) {
             env_strings.push(cstr);
         }
+        if let Some(file) = &mount.file_name {
+            if let Ok(cstr) = CString::new(format!("SMOLVM_MOUNT_FILE_{}={}", i, file)) {
+                env_strings.push(cstr);
+            }
+        }
+        if let Some(policy) = mount.sync {
+            if let Ok(cstr) = CString::new(format!("SMOLVM_MOUNT_SYNC_{}={}", i, policy.as_str())) {
+                env_strings.push(cstr);
+            }
+        }
     }
 
     if !config.mounts.is_empty() {
@@ -385,6 +490,11 @@ pub fn launch_agent_vm_dynamic(
         }
     }
 
+    // Tell the agent to forward its proxy port to the host egress proxy
+    if egress_allowlist {
+        env_strings.push(cstr("SMOLVM_EGRESS_PROXY=1"));
+    }
+
     let mut envp: Vec<*const libc::c_char> = env_strings.iter().map(|s| s.as_ptr()).collect();
     envp.push(std::ptr::null());
 
@@ -3,15 +3,17 @@
 //! The AgentManager is responsible for starting and stopping the agent VM,
 //! which runs the smolvm-agent for OCI image management and command execution.
 
-use crate::error::{Error, Result};
+use crate::error::{AgentErrorKind, Error, Result};
+use crate::network::{send_port_control, PortControlRequest, PortControlResponse};
 use crate::process::{self, ChildProcess};
 use crate::storage::{OverlayDisk, StorageDisk};
 use parking_lot::Mutex;
+use smolvm_protocol::StorageStatus;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use super::launcher::{self, launch_agent_vm};
+use super::launcher::{self, launch_agent_vm, PORTS_SOCKET_FILENAME};
 use super::{HostMount, PortMapping, VmResources};
 
 // ============================================================================
@@ -137,6 +139,7 @@ pub fn docker_config_mount() -> Option<HostMount> {
         source: docker_dir,
         target: PathBuf::from("/root/.docker"),
         read_only: true,
+        sync: None,
     })
 }
 
@@ -170,6 +173,19 @@ pub fn vm_data_dir(name: &str) -> PathBuf {
         .join(name)
 }
 
+/// Outcome of [`AgentManager::resize_storage_online`].
+#[derive(Debug)]
+pub enum OnlineResize {
+    /// The guest grew its filesystem to the new size.
+    Grown(StorageStatus),
+    /// The disk image was grown but the filesystem was not, for `reason`.
+    /// It is grown when the VM next boots.
+    Pending {
+        /// Why the online resize did not complete.
+        reason: String,
+    },
+}
+
 /// Agent VM manager.
 ///
 /// Manages the lifecycle of the agent VM which handles OCI image operations
@@ -536,7 +552,7 @@ impl AgentManager {
             version: RunningVmConfig::CURRENT_VERSION,
             mounts: mounts.to_vec(),
             ports: ports.to_vec(),
-            resources: *resources,
+            resources: resources.clone(),
         };
         match serde_json::to_string(&config) {
             Ok(json) => {
@@ -606,6 +622,110 @@ impl AgentManager {
         super::AgentClient::connect_with_retry(&self.vsock_socket)
     }
 
+    /// Grow the storage disk of a running VM without stopping it.
+    ///
+    /// Extends the disk image, then asks the agent to grow the filesystem
+    /// online. Only when the agent reports that the guest never saw the new
+    /// capacity is the image truncated back to its previous size and a
+    /// conflict returned. On any other failure the guest may already be
+    /// using the larger device, so the image is kept and the resize is
+    /// reported as [`OnlineResize::Pending`]: the filesystem is grown on the
+    /// next boot.
+    pub fn resize_storage_online(&self, new_size_gb: u64) -> Result<OnlineResize> {
+        let path = self.storage_path();
+        let old_size = std::fs::metadata(path)
+            .map_err(|e| Error::storage("get disk metadata", e.to_string()))?
+            .len();
+        crate::storage::expand_disk(path, new_size_gb, "storage")?;
+
+        let result = self.connect().and_then(|mut client| {
+            client.resize_storage(new_size_gb * crate::storage::BYTES_PER_GIB)
+        });
+        match result {
+            Ok(status) => Ok(OnlineResize::Grown(status)),
+            Err(Error::Agent {
+                kind: AgentErrorKind::Conflict,
+                reason,
+                ..
+            }) => {
+                let restored = std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|f| f.set_len(old_size));
+                if let Err(restore_err) = restored {
+                    tracing::warn!(error = %restore_err, path = %path.display(), "failed to restore disk size");
+                }
+                Err(Error::agent_conflict(
+                    "resize storage",
+                    format!("{}; stop the VM to resize its storage disk", reason),
+                ))
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, path = %path.display(), "online storage resize pending");
+                Ok(OnlineResize::Pending {
+                    reason: e.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Publish a port on a running VM.
+    ///
+    /// The VM process starts a host-side forwarder for the mapping. Once
+    /// connected with [`Self::try_connect_existing`], the running config is
+    /// updated too so later reconnects see the new port set.
+    pub fn publish_port(&self, mapping: PortMapping) -> Result<()> {
+        self.change_port("publish port", PortControlRequest::Add { mapping })?;
+        let mut inner = self.inner.lock();
+        inner.ports.push(mapping);
+        if matches!(inner.config_state, ConfigState::Known) {
+            self.save_running_config(&inner.mounts, &inner.ports, &inner.resources);
+        }
+        Ok(())
+    }
+
+    /// Withdraw a port published on a running VM.
+    ///
+    /// The mapping is matched on its host side (protocol, bind address and
+    /// host port). Ports published at boot through TSI can't be withdrawn
+    /// without a restart.
+    pub fn unpublish_port(&self, mapping: PortMapping) -> Result<()> {
+        self.change_port("unpublish port", PortControlRequest::Remove { mapping })?;
+        let mut inner = self.inner.lock();
+        inner.ports.retain(|p| !p.same_host_side(&mapping));
+        if matches!(inner.config_state, ConfigState::Known) {
+            self.save_running_config(&inner.mounts, &inner.ports, &inner.resources);
+        }
+        Ok(())
+    }
+
+    fn change_port(&self, operation: &str, request: PortControlRequest) -> Result<()> {
+        let control_socket = self
+            .vsock_socket
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(PORTS_SOCKET_FILENAME);
+        let response = send_port_control(&control_socket, &request).map_err(|e| {
+            Error::agent(
+                operation,
+                format!(
+                    "port control socket unavailable ({}); restart the VM to change its ports",
+                    e
+                ),
+            )
+        })?;
+        match response {
+            PortControlResponse::Ok => Ok(()),
+            PortControlResponse::Conflict { message } => {
+                Err(Error::agent_conflict(operation, message))
+            }
+            PortControlResponse::NotFound { message } => {
+                Err(Error::agent_not_found(operation, message))
+            }
+            PortControlResponse::Failed { message } => Err(Error::agent(operation, message)),
+        }
+    }
+
     /// Get the currently configured mounts.
     pub fn mounts(&self) -> Vec<HostMount> {
         self.inner.lock().mounts.clone()
@@ -766,6 +886,14 @@ impl AgentManager {
         ports: Vec<PortMapping>,
         resources: VmResources,
     ) -> Result<()> {
+        // Port mappings rely on TSI, which an egress allowlist turns off
+        if !resources.network_allow.is_empty() && !ports.is_empty() {
+            return Err(Error::config(
+                "start agent",
+                "port mappings cannot be combined with an egress allowlist",
+            ));
+        }
+
         // Check and update state
         {
             let mut inner = self.inner.lock();
@@ -778,7 +906,7 @@ impl AgentManager {
             inner.state = AgentState::Starting;
             inner.mounts = mounts.clone();
             inner.ports = ports.clone();
-            inner.resources = resources;
+            inner.resources = resources.clone();
             inner.config_state = ConfigState::Known;
         }
 
@@ -855,9 +983,10 @@ impl AgentManager {
         let _ = std::fs::remove_file(&ready_marker);
         let _ = std::fs::remove_file(&self.startup_error_log);
 
-        // Clone mounts/ports for save_running_config (originals move into fork closure)
+        // Clone mounts/ports/resources for save_running_config (originals move into fork closure)
         let mounts_for_config = mounts.clone();
         let ports_for_config = ports.clone();
+        let resources_for_config = resources.clone();
 
         // Clone paths for the child process (owned copies)
         let rootfs_path = self.rootfs_path.clone();
@@ -961,7 +1090,7 @@ impl AgentManager {
 
         // Write running config while child boots (overlaps with VM startup).
         // This is needed for future CLI invocations to detect config changes.
-        self.save_running_config(&mounts_for_config, &ports_for_config, &resources);
+        self.save_running_config(&mounts_for_config, &ports_for_config, &resources_for_config);
 
         // Write PID file so future CLI invocations can find this process.
         // Include start time on second line for PID reuse detection.
@@ -976,9 +1105,13 @@ impl AgentManager {
         // Wait for the agent to be ready
         match self.wait_for_ready() {
             Ok(_) => {
-                let mut inner = self.inner.lock();
-                inner.state = AgentState::Running;
+                {
+                    let mut inner = self.inner.lock();
+                    inner.state = AgentState::Running;
+                }
                 tracing::info!(pid = child_pid, "agent VM is ready");
+                self.apply_proxy_settings(resources_for_config.proxy.as_ref());
+                self.apply_dns_settings(resources_for_config.dns.as_ref());
                 Ok(())
             }
             Err(e) => {
@@ -993,6 +1126,40 @@ impl AgentManager {
         }
     }
 
+    /// Send the effective HTTP(S) proxy settings to a freshly started agent.
+    ///
+    /// Failures are logged rather than returned: the VM is usable without
+    /// the proxy, and pulls will report their own network errors.
+    fn apply_proxy_settings(&self, settings: Option<&crate::network::ProxySettings>) {
+        let Some(settings) = crate::network::ProxySettings::resolve(settings) else {
+            return;
+        };
+        let result = settings
+            .to_protocol()
+            .and_then(|config| self.connect()?.configure_proxy(&config));
+        match result {
+            Ok(()) => tracing::debug!("applied proxy settings"),
+            Err(e) => tracing::warn!(error = %e, "failed to apply proxy settings"),
+        }
+    }
+
+    /// Send the VM's DNS settings to a freshly started agent.
+    ///
+    /// Like proxy settings, failures are logged: the VM keeps its default
+    /// nameservers.
+    fn apply_dns_settings(&self, settings: Option<&crate::network::DnsSettings>) {
+        let Some(settings) = settings.filter(|s| !s.is_empty()) else {
+            return;
+        };
+        let result = settings
+            .to_protocol()
+            .and_then(|config| self.connect()?.configure_dns(&config));
+        match result {
+            Ok(()) => tracing::debug!("applied DNS settings"),
+            Err(e) => tracing::warn!(error = %e, "failed to apply DNS settings"),
+        }
+    }
+
     /// Verify identity of a VM process and kill it.
     ///
     /// Uses two methods to confirm the PID belongs to our VM:
@@ -1038,7 +1205,8 @@ impl AgentManager {
         }
     }
 
-    /// Remove PID file, config file, and vsock socket marker files.
+    /// Remove PID file, config file, and vsock socket marker files, and the
+    /// single-file mount copies next to the storage disk.
     ///
     /// Only call after the VM process is confirmed dead.
     fn cleanup_marker_files(&self) {
@@ -1049,6 +1217,9 @@ impl AgentManager {
                 }
             }
         }
+        if let Some(dir) = self.storage_disk.path().parent() {
+            crate::mount::remove_file_shares(dir);
+        }
     }
 
     /// Stop the agent VM.
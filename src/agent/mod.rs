@@ -11,7 +11,9 @@ pub mod terminal;
 
 pub use crate::vm::config::HostMount;
 pub use client::{AgentClient, PullOptions, RunConfig};
-pub use manager::{docker_config_dir, docker_config_mount, vm_data_dir, AgentManager, AgentState};
+pub use manager::{
+    docker_config_dir, docker_config_mount, vm_data_dir, AgentManager, AgentState, OnlineResize,
+};
 
 /// Default agent VM memory in MiB.
 pub const DEFAULT_MEMORY_MIB: u32 = 512;
@@ -30,32 +32,218 @@ pub fn mount_tag(index: usize) -> String {
     format!("smolvm{}", index)
 }
 
-/// TCP port mapping from host to guest.
+pub use smolvm_protocol::port_forward::PortProtocol;
+pub use smolvm_protocol::{ContainerMount, MountKind, SyncConflictPolicy, SyncMountStatus};
+
+/// Port mapping from host to guest.
+///
+/// TCP ports published on all host interfaces go through TSI. UDP ports and
+/// ports bound to a specific host address are served by a host-side
+/// forwarder that relays traffic to the guest over vsock.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "PortMappingRepr")]
 pub struct PortMapping {
     /// Port on the host.
     pub host: u16,
     /// Port inside the guest.
     pub guest: u16,
+    /// Transport protocol.
+    #[serde(default, skip_serializing_if = "PortProtocol::is_tcp")]
+    pub protocol: PortProtocol,
+    /// Host address to bind (None = all interfaces).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind: Option<std::net::IpAddr>,
+}
+
+/// Accepted serialized forms of [`PortMapping`], including the
+/// `[host, guest]` pairs stored by older VM records.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum PortMappingRepr {
+    Pair(u16, u16),
+    Full {
+        host: u16,
+        guest: u16,
+        #[serde(default)]
+        protocol: PortProtocol,
+        #[serde(default)]
+        bind: Option<std::net::IpAddr>,
+    },
+}
+
+impl From<PortMappingRepr> for PortMapping {
+    fn from(repr: PortMappingRepr) -> Self {
+        match repr {
+            PortMappingRepr::Pair(host, guest) => PortMapping::new(host, guest),
+            PortMappingRepr::Full {
+                host,
+                guest,
+                protocol,
+                bind,
+            } => PortMapping {
+                host,
+                guest,
+                protocol,
+                bind,
+            },
+        }
+    }
 }
 
 impl PortMapping {
-    /// Create a new port mapping.
+    /// Create a new TCP port mapping on all host interfaces.
     pub fn new(host: u16, guest: u16) -> Self {
-        Self { host, guest }
+        Self {
+            host,
+            guest,
+            protocol: PortProtocol::Tcp,
+            bind: None,
+        }
     }
 
     /// Create a port mapping where host and guest ports are the same.
     pub fn same(port: u16) -> Self {
-        Self {
-            host: port,
-            guest: port,
+        Self::new(port, port)
+    }
+
+    /// Set the transport protocol.
+    pub fn with_protocol(mut self, protocol: PortProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Bind the host side to a specific address.
+    pub fn with_bind(mut self, bind: std::net::IpAddr) -> Self {
+        self.bind = Some(bind);
+        self
+    }
+
+    /// Parse a `-p` style specification into one mapping per port.
+    ///
+    /// Format: `[BIND_IP:]HOST[-END][:GUEST[-END]][/tcp|/udp]`, e.g.
+    /// `8080:80`, `127.0.0.1:8080:80`, `[::1]:8080:80`, `5353:53/udp` or
+    /// `9000-9010:9000-9010`. Host and guest ranges must be the same size.
+    pub fn parse_spec(spec: &str) -> Result<Vec<PortMapping>, String> {
+        let (ports, protocol) = match spec.rsplit_once('/') {
+            Some((ports, proto)) => (ports, proto.parse::<PortProtocol>()?),
+            None => (spec, PortProtocol::Tcp),
+        };
+
+        // Split off an optional bind address; IPv6 must be bracketed
+        let (bind, ports) = if let Some(rest) = ports.strip_prefix('[') {
+            let (ip, rest) = rest
+                .split_once("]:")
+                .ok_or_else(|| format!("invalid port mapping: {}", spec))?;
+            let ip: std::net::Ipv6Addr = ip
+                .parse()
+                .map_err(|_| format!("invalid bind address: {}", ip))?;
+            (Some(std::net::IpAddr::V6(ip)), rest)
+        } else {
+            match ports.split(':').count() {
+                3 => {
+                    let (ip, rest) = ports.split_once(':').unwrap_or_default();
+                    let ip: std::net::IpAddr = ip
+                        .parse()
+                        .map_err(|_| format!("invalid bind address: {}", ip))?;
+                    (Some(ip), rest)
+                }
+                1 | 2 => (None, ports),
+                _ => return Err(format!("invalid port mapping: {}", spec)),
+            }
+        };
+
+        let (host, guest) = match ports.split_once(':') {
+            Some((host, guest)) => (
+                parse_port_range(host, "host")?,
+                parse_port_range(guest, "guest")?,
+            ),
+            None => {
+                let range = parse_port_range(ports, "")?;
+                (range, range)
+            }
+        };
+        if host.1 - host.0 != guest.1 - guest.0 {
+            return Err(format!(
+                "host and guest port ranges differ in size: {}",
+                spec
+            ));
+        }
+
+        Ok((0..=host.1 - host.0)
+            .map(|i| PortMapping {
+                host: host.0 + i,
+                guest: guest.0 + i,
+                protocol,
+                bind,
+            })
+            .collect())
+    }
+
+    /// Whether libkrun's TSI port map can publish this mapping.
+    ///
+    /// TSI only forwards TCP and always listens on all host interfaces.
+    pub fn uses_tsi(&self) -> bool {
+        self.protocol.is_tcp() && self.bind.is_none()
+    }
+
+    /// Whether both mappings name the same host port (protocol, bind
+    /// address and host port), regardless of the guest port.
+    pub fn same_host_side(&self, other: &PortMapping) -> bool {
+        self.protocol == other.protocol && self.host == other.host && self.bind == other.bind
+    }
+
+    /// Whether both mappings would claim the same host port. A mapping
+    /// without a bind address listens on every interface.
+    pub fn conflicts_with(&self, other: &PortMapping) -> bool {
+        self.protocol == other.protocol
+            && self.host == other.host
+            && (self.bind.is_none() || other.bind.is_none() || self.bind == other.bind)
+    }
+}
+
+/// Parse `PORT` or `START-END` into an inclusive range.
+fn parse_port_range(s: &str, which: &str) -> Result<(u16, u16), String> {
+    let label = if which.is_empty() {
+        "port".to_string()
+    } else {
+        format!("{} port", which)
+    };
+    let parse = |p: &str| {
+        p.parse::<u16>()
+            .map_err(|_| format!("invalid {}: {}", label, p))
+    };
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(format!("invalid {} range: {}", label, s));
+            }
+            Ok((start, end))
+        }
+        None => {
+            let port = parse(s)?;
+            Ok((port, port))
+        }
+    }
+}
+
+impl std::fmt::Display for PortMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.bind {
+            Some(std::net::IpAddr::V6(ip)) => write!(f, "[{}]:", ip)?,
+            Some(ip) => write!(f, "{}:", ip)?,
+            None => {}
+        }
+        write!(f, "{}:{}", self.host, self.guest)?;
+        if !self.protocol.is_tcp() {
+            write!(f, "/{}", self.protocol)?;
         }
+        Ok(())
     }
 }
 
 /// VM configuration for the agent.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct VmResources {
     /// Number of vCPUs.
     pub cpus: u8,
@@ -67,6 +255,20 @@ pub struct VmResources {
     pub storage_gb: Option<u64>,
     /// Overlay disk size in GiB (None = default 10 GiB).
     pub overlay_gb: Option<u64>,
+    /// Egress allowlist. When non-empty, the VM boots without TSI and
+    /// outbound connections go through the host egress proxy instead;
+    /// clients that don't use the proxy have no route out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network_allow: Vec<crate::network::EgressRule>,
+    /// HTTP(S) proxy settings (None = use the registry config default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<crate::network::ProxySettings>,
+    /// DNS settings (None = default nameservers).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<crate::network::DnsSettings>,
+    /// Private network the VM is attached to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_network: Option<crate::network::NetworkAttachment>,
 }
 
 impl Default for VmResources {
@@ -77,6 +279,90 @@ impl Default for VmResources {
             network: false,
             storage_gb: None,
             overlay_gb: None,
+            network_allow: Vec::new(),
+            proxy: None,
+            dns: None,
+            private_network: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_parse_port_spec() {
+        assert_eq!(
+            PortMapping::parse_spec("8080:80").unwrap(),
+            vec![PortMapping::new(8080, 80)]
+        );
+        assert_eq!(
+            PortMapping::parse_spec("53").unwrap(),
+            vec![PortMapping::same(53)]
+        );
+
+        let bound = PortMapping::parse_spec("127.0.0.1:8080:80").unwrap();
+        assert_eq!(bound[0].bind, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(!bound[0].uses_tsi());
+
+        let v6 = PortMapping::parse_spec("[::1]:8080:80/udp").unwrap();
+        assert_eq!(v6[0].bind, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(v6[0].protocol, PortProtocol::Udp);
+        assert_eq!(v6[0].to_string(), "[::1]:8080:80/udp");
+
+        let udp = PortMapping::parse_spec("5353:53/udp").unwrap();
+        assert_eq!(udp[0].protocol, PortProtocol::Udp);
+        assert_eq!(udp[0].to_string(), "5353:53/udp");
+
+        let range = PortMapping::parse_spec("9000-9002:10000-10002").unwrap();
+        assert_eq!(range.len(), 3);
+        assert_eq!((range[2].host, range[2].guest), (9002, 10002));
+        assert!(range.iter().all(PortMapping::uses_tsi));
+
+        for bad in [
+            "9000-9010:80",
+            "9010-9000",
+            "abc:80",
+            "8080:80/sctp",
+            "::1:8080:80",
+            "1.2.3.4.5:80:80",
+        ] {
+            assert!(PortMapping::parse_spec(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_port_mapping_host_side() {
+        let lo = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let any = PortMapping::new(8080, 80);
+        let bound = PortMapping::new(8080, 81).with_bind(lo);
+        let udp = PortMapping::new(8080, 80).with_protocol(PortProtocol::Udp);
+
+        assert!(any.conflicts_with(&bound));
+        assert!(!any.conflicts_with(&udp));
+        assert!(!any.same_host_side(&bound));
+        assert!(bound.same_host_side(&PortMapping::new(8080, 9000).with_bind(lo)));
+    }
+
+    #[test]
+    fn test_port_mapping_serde_compat() {
+        // Older records stored plain [host, guest] pairs
+        let legacy: Vec<PortMapping> = serde_json::from_str("[[8080, 80]]").unwrap();
+        assert_eq!(legacy, vec![PortMapping::new(8080, 80)]);
+
+        let plain: PortMapping = serde_json::from_str(r#"{"host":1,"guest":2}"#).unwrap();
+        assert_eq!(plain, PortMapping::new(1, 2));
+        assert_eq!(
+            serde_json::to_string(&plain).unwrap(),
+            r#"{"host":1,"guest":2}"#
+        );
+
+        let udp = PortMapping::new(5353, 53)
+            .with_protocol(PortProtocol::Udp)
+            .with_bind(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let json = serde_json::to_string(&udp).unwrap();
+        assert_eq!(serde_json::from_str::<PortMapping>(&json).unwrap(), udp);
+    }
+}
@@ -7,6 +7,7 @@ use axum::{
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::agent::ContainerMount;
 use crate::api::error::{classify_ensure_running_error, ApiError};
 use crate::api::state::{ensure_running_and_persist, with_sandbox_client, ApiState};
 use crate::api::types::{
@@ -53,10 +54,10 @@ pub async fn create_container(
     };
     let env = EnvVar::to_tuples(&req.env);
     let workdir = req.workdir.clone();
-    let mounts: Vec<(String, String, bool)> = req
+    let mounts: Vec<ContainerMount> = req
         .mounts
         .iter()
-        .map(|m| (m.source.clone(), m.target.clone(), m.readonly))
+        .map(|m| ContainerMount::bind(m.source.clone(), m.target.clone(), m.readonly))
         .collect();
 
     let container_info = with_sandbox_client(&entry, move |c| {
@@ -112,8 +112,11 @@ pub async fn run_command(
             .iter()
             .enumerate()
             .map(|(i, m)| {
-                let tag = crate::agent::mount_tag(i);
-                (tag, m.target.clone(), m.readonly)
+                crate::agent::ContainerMount::bind(
+                    crate::agent::mount_tag(i),
+                    m.target.clone(),
+                    m.readonly,
+                )
             })
             .collect::<Vec<_>>()
     };
@@ -20,18 +20,19 @@
 //! Recommended: Use short, descriptive names (e.g., "dev-vm", "test-1").
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json,
 };
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::agent::AgentManager;
+use crate::agent::{AgentManager, HostMount, PortMapping};
 use crate::api::error::ApiError;
 use crate::api::state::ApiState;
 use crate::api::types::{
     ApiErrorResponse, CreateMicrovmRequest, DeleteResponse, EnvVar, ExecResponse,
-    ListMicrovmsResponse, MicrovmExecRequest, MicrovmInfo, ResizeMicrovmRequest,
+    ListMicrovmsResponse, MicrovmExecRequest, MicrovmInfo, PortSpec, ResizeMicrovmRequest,
+    UnpublishPortQuery,
 };
 use crate::api::validation::{validate_command, validate_resource_name};
 use crate::config::{RecordState, VmRecord};
@@ -67,6 +68,7 @@ fn record_to_info(name: &str, record: &VmRecord) -> MicrovmInfo {
         network: record.network,
         storage_gb: record.storage_gb,
         overlay_gb: record.overlay_gb,
+        network_allow: record.network_allow.clone(),
         created_at: record.created_at.clone(),
     }
 }
@@ -146,22 +148,31 @@ pub async fn create_microvm(
     let mem = req.mem;
 
     // Validate and convert mounts to storage format
-    let mut mounts: Vec<(String, String, bool)> = Vec::with_capacity(req.mounts.len());
+    let mut mounts: Vec<HostMount> = Vec::with_capacity(req.mounts.len());
     for mount_spec in &req.mounts {
         // Validate mount paths (checks: absolute paths, source exists, source is directory)
         let binding =
             MountBinding::new(&mount_spec.source, &mount_spec.target, mount_spec.readonly)
                 .map_err(|e| ApiError::BadRequest(e.to_string()))?;
-        mounts.push(binding.to_tuple());
+        mounts.push(HostMount::from(&binding));
     }
 
     // Convert ports to storage format
-    let ports: Vec<(u16, u16)> = req.ports.iter().map(|p| (p.host, p.guest)).collect();
+    let ports: Vec<PortMapping> = req.ports.iter().map(PortMapping::from).collect();
+    if !req.network_allow.is_empty() && !ports.is_empty() {
+        return Err(ApiError::BadRequest(
+            "port mappings cannot be combined with networkAllow".into(),
+        ));
+    }
 
-    // Create record with requested network setting
-    let mut record = VmRecord::new(name.clone(), cpus, mem, mounts, ports, req.network);
+    // Create record with requested network setting (an allowlist implies network)
+    let network = req.network || !req.network_allow.is_empty();
+    let mut record = VmRecord::new(name.clone(), cpus, mem, mounts, ports, network);
     record.storage_gb = req.storage_gb;
     record.overlay_gb = req.overlay_gb;
+    record.network_allow = req.network_allow;
+    record.proxy = req.proxy;
+    record.dns = req.dns;
 
     // Use atomic insert to detect conflicts
     let db = state.db();
@@ -428,6 +439,15 @@ pub async fn delete_microvm(
         )));
     }
 
+    if let Some(attachment) = &record.private_network {
+        if let Ok(store) = crate::network::NetworkStore::open() {
+            store.leave(attachment);
+        }
+    }
+    if let Ok(root) = crate::volume::default_root() {
+        crate::volume::VolumeStore::at(db.clone(), root).release(&name);
+    }
+
     Ok(Json(DeleteResponse { deleted: name }))
 }
 
@@ -515,7 +535,7 @@ pub async fn exec_microvm(
         (status = 200, description = "MicroVM resized", body = MicrovmInfo),
         (status = 400, description = "Invalid request", body = ApiErrorResponse),
         (status = 404, description = "MicroVM not found", body = ApiErrorResponse),
-        (status = 409, description = "MicroVM is running", body = ApiErrorResponse),
+        (status = 409, description = "MicroVM is running and the resize cannot be done online", body = ApiErrorResponse),
         (status = 500, description = "Resize failed", body = ApiErrorResponse)
     )
 )]
@@ -533,18 +553,6 @@ pub async fn resize_microvm(
         .ok_or_else(|| ApiError::NotFound(format!("microvm '{}' not found", name)))?
         .clone();
 
-    // Check state - VM must be stopped (Created state also allowed for never-started VMs)
-    let actual_state = record.actual_state();
-    match actual_state {
-        RecordState::Stopped | RecordState::Created => {} // OK to resize
-        _ => {
-            return Err(ApiError::Conflict(format!(
-                "microvm '{}' must be stopped before resizing. Current state: {:?}",
-                name, actual_state
-            )));
-        }
-    }
-
     // Get current disk sizes (use defaults if not set)
     let current_storage_gb = record
         .storage_gb
@@ -553,6 +561,25 @@ pub async fn resize_microvm(
         .overlay_gb
         .unwrap_or(crate::storage::DEFAULT_OVERLAY_SIZE_GIB);
 
+    // Check state - a running VM can only grow its storage disk (online);
+    // everything else requires it to be stopped (Created state also allowed
+    // for never-started VMs)
+    let actual_state = record.actual_state();
+    let online = match actual_state {
+        RecordState::Stopped | RecordState::Created => false,
+        RecordState::Running
+            if req.overlay_gb.unwrap_or(current_overlay_gb) <= current_overlay_gb =>
+        {
+            true
+        }
+        _ => {
+            return Err(ApiError::Conflict(format!(
+                "microvm '{}' must be stopped before resizing its overlay disk. Current state: {:?}",
+                name, actual_state
+            )));
+        }
+    };
+
     // Validate resize parameters (no shrinking)
     let new_storage_gb = req.storage_gb.unwrap_or(current_storage_gb);
     let new_overlay_gb = req.overlay_gb.unwrap_or(current_overlay_gb);
@@ -580,13 +607,36 @@ pub async fn resize_microvm(
     // Expand disk files if sizes changed
     let manager = crate::agent::AgentManager::for_vm(&name)
         .map_err(|e| ApiError::internal(format!("failed to get agent manager: {}", e)))?;
+    if online {
+        // Dropping an attached manager would stop the running VM
+        manager.detach();
+    }
 
     // Expand storage disk if requested and changed
     if let Some(storage_gb) = req.storage_gb {
         if storage_gb > current_storage_gb {
-            let storage_path = manager.storage_path();
-            expand_disk(storage_path, storage_gb, "storage")
-                .map_err(|e| ApiError::internal(format!("failed to expand storage disk: {}", e)))?;
+            if online {
+                let vm_name = name.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let manager = crate::agent::AgentManager::for_vm(&vm_name)?;
+                    manager.detach();
+                    manager.resize_storage_online(storage_gb)
+                })
+                .await
+                .map_err(|e| ApiError::internal(format!("task error: {}", e)))?;
+                if let crate::agent::OnlineResize::Pending { reason } =
+                    result.map_err(ApiError::from)?
+                {
+                    // The record still takes the new size: the disk image has
+                    // it, and the guest grows its filesystem on next boot
+                    tracing::warn!(microvm = %name, %reason, "storage filesystem resize pending until restart");
+                }
+            } else {
+                let storage_path = manager.storage_path();
+                expand_disk(storage_path, storage_gb, "storage").map_err(|e| {
+                    ApiError::internal(format!("failed to expand storage disk: {}", e))
+                })?;
+            }
         }
     }
 
@@ -620,6 +670,144 @@ pub async fn resize_microvm(
     Ok(Json(record_to_info(&name, &record)))
 }
 
+/// Connect a detached manager to a running microvm's agent.
+fn connect_running(name: &str) -> crate::error::Result<AgentManager> {
+    let manager = AgentManager::for_vm(name)?;
+    // Dropping an attached manager would stop the running VM
+    manager.detach();
+    manager.try_connect_existing().ok_or_else(|| {
+        crate::Error::agent(
+            "connect to microvm",
+            format!(
+                "microvm '{}' is running but its agent is not reachable",
+                name
+            ),
+        )
+    })?;
+    Ok(manager)
+}
+
+/// Publish a port on a microvm.
+///
+/// A running microvm serves the port immediately; the port is also saved so
+/// it is published on every later start.
+#[utoipa::path(
+    post,
+    path = "/api/v1/microvms/{name}/ports",
+    tag = "MicroVMs",
+    params(
+        ("name" = String, Path, description = "MicroVM name")
+    ),
+    request_body = PortSpec,
+    responses(
+        (status = 200, description = "Port published", body = MicrovmInfo),
+        (status = 404, description = "MicroVM not found", body = ApiErrorResponse),
+        (status = 409, description = "Host port already published", body = ApiErrorResponse),
+        (status = 500, description = "Failed to publish port", body = ApiErrorResponse)
+    )
+)]
+pub async fn publish_microvm_port(
+    State(state): State<Arc<ApiState>>,
+    Path(name): Path<String>,
+    Json(req): Json<PortSpec>,
+) -> Result<Json<MicrovmInfo>, ApiError> {
+    let db = state.db();
+    let record = db
+        .get_vm(&name)
+        .map_err(ApiError::database)?
+        .ok_or_else(|| ApiError::NotFound(format!("microvm '{}' not found", name)))?;
+
+    let mapping = PortMapping::from(&req);
+    if let Some(existing) = record.ports.iter().find(|p| p.conflicts_with(&mapping)) {
+        return Err(ApiError::Conflict(format!(
+            "port {} conflicts with published port {}",
+            mapping, existing
+        )));
+    }
+
+    if record.actual_state() == RecordState::Running {
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || connect_running(&name)?.publish_port(mapping))
+            .await
+            .map_err(|e| ApiError::internal(format!("task error: {}", e)))?
+            .map_err(ApiError::from)?;
+    }
+
+    let record = db
+        .update_vm(&name, |r| r.ports.push(mapping))
+        .map_err(ApiError::database)?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "microvm '{}' disappeared from database while publishing a port",
+                name
+            ))
+        })?;
+
+    Ok(Json(record_to_info(&name, &record)))
+}
+
+/// Withdraw a published port from a microvm.
+///
+/// Ports a running microvm published at boot as plain TCP on all interfaces
+/// can't be withdrawn until it restarts.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/microvms/{name}/ports/{host}",
+    tag = "MicroVMs",
+    params(
+        ("name" = String, Path, description = "MicroVM name"),
+        ("host" = u16, Path, description = "Published host port"),
+        ("protocol" = Option<String>, Query, description = "Port protocol (tcp or udp, default tcp)"),
+        ("hostIp" = Option<String>, Query, description = "Host address the port is bound to")
+    ),
+    responses(
+        (status = 200, description = "Port withdrawn", body = MicrovmInfo),
+        (status = 404, description = "MicroVM or port not found", body = ApiErrorResponse),
+        (status = 409, description = "Port can't be withdrawn without a restart", body = ApiErrorResponse),
+        (status = 500, description = "Failed to withdraw port", body = ApiErrorResponse)
+    )
+)]
+pub async fn unpublish_microvm_port(
+    State(state): State<Arc<ApiState>>,
+    Path((name, host)): Path<(String, u16)>,
+    Query(query): Query<UnpublishPortQuery>,
+) -> Result<Json<MicrovmInfo>, ApiError> {
+    let db = state.db();
+    let record = db
+        .get_vm(&name)
+        .map_err(ApiError::database)?
+        .ok_or_else(|| ApiError::NotFound(format!("microvm '{}' not found", name)))?;
+
+    let mut mapping = PortMapping::same(host).with_protocol(query.protocol);
+    mapping.bind = query.host_ip;
+    if !record.ports.iter().any(|p| p.same_host_side(&mapping)) {
+        return Err(ApiError::NotFound(format!(
+            "microvm '{}' does not publish host port {}",
+            name, mapping
+        )));
+    }
+
+    if record.actual_state() == RecordState::Running {
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || connect_running(&name)?.unpublish_port(mapping))
+            .await
+            .map_err(|e| ApiError::internal(format!("task error: {}", e)))?
+            .map_err(ApiError::from)?;
+    }
+
+    let record = db
+        .update_vm(&name, |r| r.ports.retain(|p| !p.same_host_side(&mapping)))
+        .map_err(ApiError::database)?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "microvm '{}' disappeared from database while withdrawing a port",
+                name
+            ))
+        })?;
+
+    Ok(Json(record_to_info(&name, &record)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -633,10 +821,10 @@ mod tests {
             2,
             1024,
             vec![
-                ("/host/path".to_string(), "/guest/path".to_string(), false),
-                ("/host/ro".to_string(), "/guest/ro".to_string(), true),
+                HostMount::new_writable("/host/path", "/guest/path"),
+                HostMount::new("/host/ro", "/guest/ro"),
             ],
-            vec![(8080, 80), (3000, 3000)],
+            vec![PortMapping::new(8080, 80), PortMapping::same(3000)],
             false,
         );
 
@@ -776,6 +964,35 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_resize_running_overlay_rejected() {
+        let (_dir, state) = setup_test_state();
+        let db = state.db();
+
+        // A running VM (this test process stands in for the VM process)
+        let mut record = VmRecord::new("test-vm".to_string(), 1, 512, vec![], vec![], false);
+        record.overlay_gb = Some(10);
+        record.state = RecordState::Running;
+        record.pid = Some(std::process::id() as i32);
+        db.insert_vm("test-vm", &record)
+            .expect("failed to insert test vm");
+
+        // Only the storage disk can grow online
+        let req = ResizeMicrovmRequest {
+            storage_gb: None,
+            overlay_gb: Some(20),
+        };
+
+        let result = resize_microvm(State(state), Path("test-vm".to_string()), Json(req)).await;
+
+        let err = result.unwrap_err();
+        assert!(
+            matches!(err, ApiError::Conflict(_)),
+            "Expected Conflict, got: {:?}",
+            err
+        );
+    }
+
     #[tokio::test]
     async fn test_resize_validation_no_params_rejected() {
         let (_dir, state) = setup_test_state();
@@ -830,4 +1047,77 @@ mod tests {
             err
         );
     }
+
+    fn port_spec(host: u16, guest: u16) -> PortSpec {
+        PortSpec {
+            host,
+            guest,
+            protocol: Default::default(),
+            host_ip: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_port_on_stopped_vm() {
+        let (_dir, state) = setup_test_state();
+        create_test_vm(state.db(), "test-vm", None, None);
+
+        let info = publish_microvm_port(
+            State(state.clone()),
+            Path("test-vm".to_string()),
+            Json(port_spec(8080, 80)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.ports, 1);
+
+        // The same host port can't be published twice
+        let err = publish_microvm_port(
+            State(state.clone()),
+            Path("test-vm".to_string()),
+            Json(port_spec(8080, 81)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ApiError::Conflict(_)), "got: {:?}", err);
+
+        let record = state.db().get_vm("test-vm").unwrap().unwrap();
+        assert_eq!(record.ports, vec![PortMapping::new(8080, 80)]);
+    }
+
+    #[tokio::test]
+    async fn test_unpublish_port_on_stopped_vm() {
+        let (_dir, state) = setup_test_state();
+        let mut record = VmRecord::new(
+            "test-vm".to_string(),
+            1,
+            512,
+            vec![],
+            vec![PortMapping::new(8080, 80), PortMapping::same(9000)],
+            false,
+        );
+        record.state = RecordState::Stopped;
+        state.db().insert_vm("test-vm", &record).unwrap();
+
+        let info = unpublish_microvm_port(
+            State(state.clone()),
+            Path(("test-vm".to_string(), 8080)),
+            Query(UnpublishPortQuery::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.ports, 1);
+
+        let err = unpublish_microvm_port(
+            State(state.clone()),
+            Path(("test-vm".to_string(), 8080)),
+            Query(UnpublishPortQuery::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)), "got: {:?}", err);
+
+        let record = state.db().get_vm("test-vm").unwrap().unwrap();
+        assert_eq!(record.ports, vec![PortMapping::same(9000)]);
+    }
 }
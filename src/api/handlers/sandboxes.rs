@@ -88,10 +88,19 @@ pub async fn create_sandbox(
         network: None,
         storage_gb: None,
         overlay_gb: None,
+        network_allow: Vec::new(),
+        proxy: None,
+        dns: None,
     });
 
-    // Get network setting from resources (default to false)
-    let network = resources.network.unwrap_or(false);
+    // Get network setting from resources (default to false).
+    // An allowlist implies network access.
+    let network = resources.network.unwrap_or(false) || !resources.network_allow.is_empty();
+    if !resources.network_allow.is_empty() && !req.ports.is_empty() {
+        return Err(ApiError::BadRequest(
+            "port mappings cannot be combined with networkAllow".into(),
+        ));
+    }
 
     // Parse restart configuration
     let restart_config = restart_spec_to_config(req.restart.as_ref());
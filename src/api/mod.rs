@@ -89,6 +89,8 @@ use state::ApiState;
         handlers::microvms::delete_microvm,
         handlers::microvms::exec_microvm,
         handlers::microvms::resize_microvm,
+        handlers::microvms::publish_microvm_port,
+        handlers::microvms::unpublish_microvm_port,
     ),
     components(schemas(
         // Request types
@@ -111,6 +113,7 @@ use state::ApiState;
         types::CreateMicrovmRequest,
         types::MicrovmExecRequest,
         types::ResizeMicrovmRequest,
+        types::UnpublishPortQuery,
         // Response types
         types::HealthResponse,
         types::SandboxInfo,
@@ -208,6 +211,14 @@ pub fn create_router(state: Arc<ApiState>, cors_origins: Vec<String>) -> Router
         .route("/:name", delete(handlers::microvms::delete_microvm))
         .route("/:name/exec", post(handlers::microvms::exec_microvm))
         .route("/:name/resize", post(handlers::microvms::resize_microvm))
+        .route(
+            "/:name/ports",
+            post(handlers::microvms::publish_microvm_port),
+        )
+        .route(
+            "/:name/ports/:host",
+            delete(handlers::microvms::unpublish_microvm_port),
+        )
         .layer(TimeoutLayer::new(Duration::from_secs(
             API_REQUEST_TIMEOUT_SECS,
         )));
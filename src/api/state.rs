@@ -165,24 +165,9 @@ impl ApiState {
             }
 
             // Convert VmRecord to SandboxEntry
-            let mounts: Vec<MountSpec> = record
-                .mounts
-                .iter()
-                .map(|(source, target, readonly)| MountSpec {
-                    source: source.clone(),
-                    target: target.clone(),
-                    readonly: *readonly,
-                })
-                .collect();
+            let mounts: Vec<MountSpec> = record.mounts.iter().map(MountSpec::from).collect();
 
-            let ports: Vec<PortSpec> = record
-                .ports
-                .iter()
-                .map(|(host, guest)| PortSpec {
-                    host: *host,
-                    guest: *guest,
-                })
-                .collect();
+            let ports: Vec<PortSpec> = record.ports.iter().map(PortSpec::from).collect();
 
             let resources = ResourceSpec {
                 cpus: Some(record.cpus),
@@ -190,6 +175,9 @@ impl ApiState {
                 network: Some(record.network),
                 storage_gb: record.storage_gb,
                 overlay_gb: record.overlay_gb,
+                network_allow: record.network_allow.clone(),
+                proxy: record.proxy.clone(),
+                dns: record.dns.clone(),
             };
 
             // Create AgentManager and try to reconnect
@@ -428,14 +416,21 @@ impl ApiState {
                 .unwrap_or(crate::agent::DEFAULT_MEMORY_MIB),
             reg.mounts
                 .iter()
-                .map(|m| (m.source.clone(), m.target.clone(), m.readonly))
+                .map(|m| {
+                    let binding =
+                        MountBinding::from_stored(m.source.clone(), m.target.clone(), m.readonly);
+                    HostMount::from(&binding)
+                })
                 .collect(),
-            reg.ports.iter().map(|p| (p.host, p.guest)).collect(),
+            reg.ports.iter().map(PortMapping::from).collect(),
             reg.network,
             reg.restart.clone(),
         );
         record.storage_gb = reg.resources.storage_gb;
         record.overlay_gb = reg.resources.overlay_gb;
+        record.network_allow = reg.resources.network_allow.clone();
+        record.proxy = reg.resources.proxy.clone();
+        record.dns = reg.resources.dns.clone();
 
         // Use insert_vm_if_not_exists for atomic database insert
         match self.db.insert_vm_if_not_exists(&name, &record) {
@@ -671,7 +666,12 @@ impl From<&HostMount> for MountSpec {
 
 impl From<&PortSpec> for PortMapping {
     fn from(spec: &PortSpec) -> Self {
-        PortMapping::new(spec.host, spec.guest)
+        PortMapping {
+            host: spec.host,
+            guest: spec.guest,
+            protocol: spec.protocol,
+            bind: spec.host_ip,
+        }
     }
 }
 
@@ -680,6 +680,8 @@ impl From<&PortMapping> for PortSpec {
         PortSpec {
             host: mapping.host,
             guest: mapping.guest,
+            protocol: mapping.protocol,
+            host_ip: mapping.bind,
         }
     }
 }
@@ -702,6 +704,10 @@ pub fn resource_spec_to_vm_resources(spec: &ResourceSpec, network: bool) -> VmRe
         network,
         storage_gb: spec.storage_gb,
         overlay_gb: spec.overlay_gb,
+        network_allow: spec.network_allow.clone(),
+        proxy: spec.proxy.clone(),
+        dns: spec.dns.clone(),
+        private_network: None,
     }
 }
 
@@ -713,6 +719,9 @@ pub fn vm_resources_to_spec(res: VmResources) -> ResourceSpec {
         network: Some(res.network),
         storage_gb: res.storage_gb,
         overlay_gb: res.overlay_gb,
+        network_allow: res.network_allow,
+        proxy: res.proxy,
+        dns: res.dns,
     }
 }
 
@@ -773,6 +782,9 @@ mod tests {
             network: None,
             storage_gb: None,
             overlay_gb: None,
+            network_allow: Vec::new(),
+            proxy: None,
+            dns: None,
         };
         let res = resource_spec_to_vm_resources(&spec, false);
         assert_eq!(res.cpus, crate::agent::DEFAULT_CPUS);
@@ -782,6 +794,15 @@ mod tests {
         // Test with network enabled
         let res = resource_spec_to_vm_resources(&spec, true);
         assert!(res.network);
+
+        // Allowlist carries through to VmResources
+        let spec: ResourceSpec =
+            serde_json::from_str(r#"{"networkAllow": ["github.com", "10.0.0.0/8:443"]}"#).unwrap();
+        let res = resource_spec_to_vm_resources(&spec, true);
+        assert_eq!(res.network_allow.len(), 2);
+        assert!(
+            serde_json::from_str::<ResourceSpec>(r#"{"networkAllow": ["10.0.0.0/99"]}"#).is_err()
+        );
     }
 
     #[test]
@@ -1,6 +1,9 @@
 //! JSON request and response types for the API.
 
+use crate::agent::PortProtocol;
+use crate::network::{DnsSettings, EgressRule, ProxySettings};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use utoipa::ToSchema;
 
 // ============================================================================
@@ -71,6 +74,7 @@ pub struct MountInfo {
 
 /// Port mapping specification.
 #[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct PortSpec {
     /// Port on the host.
     #[schema(example = 8080)]
@@ -78,6 +82,14 @@ pub struct PortSpec {
     /// Port inside the sandbox.
     #[schema(example = 80)]
     pub guest: u16,
+    /// Transport protocol (`tcp` or `udp`, default: `tcp`).
+    #[serde(default, skip_serializing_if = "PortProtocol::is_tcp")]
+    #[schema(value_type = String, example = "tcp")]
+    pub protocol: PortProtocol,
+    /// Host address to bind (default: all interfaces).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "127.0.0.1")]
+    pub host_ip: Option<IpAddr>,
 }
 
 /// VM resource specification.
@@ -104,6 +116,22 @@ pub struct ResourceSpec {
     #[serde(default)]
     #[schema(example = 10)]
     pub overlay_gb: Option<u64>,
+    /// Egress allowlist (`github.com`, `*.npmjs.org`, `10.0.0.0/8:443`).
+    /// When set, egress is proxy-only: HTTP(S) clients that use the proxy
+    /// reach these destinations, and other traffic has no route.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<String>, example = json!(["github.com", "10.0.0.0/8:443"]))]
+    pub network_allow: Vec<EgressRule>,
+    /// HTTP(S) proxy settings (`http`, `https`, `no_proxy`, `ca_certs`).
+    /// Defaults to the `[proxy]` section of the registry config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub proxy: Option<ProxySettings>,
+    /// DNS settings (`servers`, `search`, `options`, `hosts` as `name:ip`,
+    /// `host_resolver`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub dns: Option<DnsSettings>,
 }
 
 /// Sandbox status information.
@@ -484,6 +512,22 @@ pub struct CreateMicrovmRequest {
     /// Overlay disk size in GiB (default: 10).
     #[serde(default)]
     pub overlay_gb: Option<u64>,
+    /// Egress allowlist (`github.com`, `*.npmjs.org`, `10.0.0.0/8:443`).
+    /// When set, egress is proxy-only: HTTP(S) clients that use the proxy
+    /// reach these destinations, and other traffic has no route.
+    #[serde(default)]
+    #[schema(value_type = Vec<String>, example = json!(["github.com", "10.0.0.0/8:443"]))]
+    pub network_allow: Vec<EgressRule>,
+    /// HTTP(S) proxy settings (`http`, `https`, `no_proxy`, `ca_certs`).
+    /// Defaults to the `[proxy]` section of the registry config.
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub proxy: Option<ProxySettings>,
+    /// DNS settings (`servers`, `search`, `options`, `hosts` as `name:ip`,
+    /// `host_resolver`).
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub dns: Option<DnsSettings>,
 }
 
 /// Request to execute a command in a microvm.
@@ -539,6 +583,10 @@ pub struct MicrovmInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schema(example = 2)]
     pub overlay_gb: Option<u64>,
+    /// Egress allowlist, if outbound access is restricted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<String>)]
+    pub network_allow: Vec<EgressRule>,
     /// Creation timestamp.
     pub created_at: String,
 }
@@ -591,3 +639,17 @@ pub struct ResizeMicrovmRequest {
     #[schema(example = 20)]
     pub overlay_gb: Option<u64>,
 }
+
+/// Query parameters for withdrawing a published microvm port.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpublishPortQuery {
+    /// Transport protocol of the port (`tcp` or `udp`, default: `tcp`).
+    #[serde(default)]
+    #[schema(value_type = String, example = "tcp")]
+    pub protocol: PortProtocol,
+    /// Host address the port is bound to (default: all interfaces).
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "127.0.0.1")]
+    pub host_ip: Option<IpAddr>,
+}
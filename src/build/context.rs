@@ -0,0 +1,431 @@
+//! Build context handling.
+//!
+//! The build context is the directory passed to `smolvm build`, minus the
+//! paths excluded by its `.dockerignore`. COPY and ADD sources are resolved
+//! against it on the host, and the files they select are hashed into the
+//! step's cache key. The context is only uploaded to the agent when a step
+//! that uses it actually has to run.
+
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore file read from the context root.
+pub const DOCKERIGNORE_FILE: &str = ".dockerignore";
+
+/// A scanned build context.
+#[derive(Debug)]
+pub struct BuildContext {
+    root: PathBuf,
+    /// Included paths, relative to `root` with `/` separators, sorted.
+    entries: Vec<String>,
+}
+
+impl BuildContext {
+    /// Scan `root`, skipping paths excluded by its `.dockerignore`.
+    pub fn scan(root: &Path) -> Result<Self> {
+        if !root.is_dir() {
+            return Err(Error::build(
+                "read build context",
+                format!("{} is not a directory", root.display()),
+            ));
+        }
+        let rules = match fs::read_to_string(root.join(DOCKERIGNORE_FILE)) {
+            Ok(text) => parse_ignore_rules(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        scan_dir(root, "", &rules, &mut entries)?;
+        entries.sort();
+        Ok(Self {
+            root: root.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Context root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a COPY/ADD source against the context.
+    ///
+    /// Returns the matching paths, relative to the context root. `.` stands
+    /// for the whole context. Sources may contain `*`, `?` and `[...]`
+    /// wildcards; a source that matches nothing is an error.
+    pub fn resolve(&self, source: &str) -> Result<Vec<String>> {
+        let path = clean_path(source).ok_or_else(|| {
+            Error::build(
+                "resolve COPY source",
+                format!("'{}' is outside the build context", source),
+            )
+        })?;
+        if path.is_empty() {
+            return Ok(vec![".".to_string()]);
+        }
+
+        let matches: Vec<String> = if path.contains(['*', '?', '[']) {
+            self.entries
+                .iter()
+                .filter(|entry| glob_match(&path, entry))
+                .cloned()
+                .collect()
+        } else {
+            self.entries
+                .iter()
+                .filter(|e| **e == path)
+                .cloned()
+                .collect()
+        };
+        if matches.is_empty() {
+            return Err(Error::build(
+                "resolve COPY source",
+                format!(
+                    "'{}' not found in build context (or excluded by {})",
+                    source, DOCKERIGNORE_FILE
+                ),
+            ));
+        }
+        Ok(matches)
+    }
+
+    /// Hash the selected paths and everything below them.
+    ///
+    /// Covers names, file types, permission bits, file contents and symlink
+    /// targets, so any change that could affect a COPY changes the digest.
+    pub fn digest(&self, paths: &[String]) -> Result<String> {
+        let mut hasher = Sha256::new();
+        for entry in self.entries.iter().filter(|e| is_selected(paths, e)) {
+            let path = self.root.join(entry);
+            let meta = fs::symlink_metadata(&path)?;
+            hasher.update(entry.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(mode_bits(&meta).to_le_bytes());
+            if meta.file_type().is_symlink() {
+                hasher.update(fs::read_link(&path)?.as_os_str().as_encoded_bytes());
+            } else if meta.is_file() {
+                std::io::copy(&mut fs::File::open(&path)?, &mut hasher)?;
+            }
+            hasher.update(b"\0");
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Write the whole context as a tar archive.
+    ///
+    /// Headers are deterministic (no owners or timestamps), since neither
+    /// is part of the cache key.
+    pub fn write_tar(&self, out: impl Write) -> Result<()> {
+        let mut builder = tar::Builder::new(out);
+        builder.follow_symlinks(false);
+        builder.mode(tar::HeaderMode::Deterministic);
+        for entry in &self.entries {
+            builder.append_path_with_name(self.root.join(entry), entry)?;
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+fn scan_dir(root: &Path, prefix: &str, rules: &[IgnoreRule], out: &mut Vec<String>) -> Result<()> {
+    let has_exceptions = rules.iter().any(|r| r.exception);
+    for entry in fs::read_dir(root.join(prefix))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            return Err(Error::build(
+                "read build context",
+                format!("non-UTF-8 file name in {}", root.join(prefix).display()),
+            ));
+        };
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let file_type = entry.file_type()?;
+        if !(file_type.is_dir() || file_type.is_file() || file_type.is_symlink()) {
+            continue;
+        }
+
+        let ignored = is_ignored(rules, &path);
+        if !ignored {
+            out.push(path.clone());
+        }
+        // An exception rule may re-include something below an ignored dir.
+        if file_type.is_dir() && (!ignored || has_exceptions) {
+            scan_dir(root, &path, rules, out)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_selected(paths: &[String], entry: &str) -> bool {
+    paths.iter().any(|p| {
+        p == "."
+            || entry == p
+            || (entry.starts_with(p.as_str()) && entry[p.len()..].starts_with('/'))
+    })
+}
+
+fn mode_bits(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.mode()
+}
+
+/// Normalize a relative path: drop `.`, empty components and leading `/`,
+/// and apply `..`. Returns `None` if the path escapes its root.
+fn clean_path(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// ============================================================================
+// .dockerignore
+// ============================================================================
+
+/// A `.dockerignore` pattern.
+#[derive(Debug, Clone, PartialEq)]
+struct IgnoreRule {
+    pattern: String,
+    /// `!pattern`: re-include matching paths.
+    exception: bool,
+}
+
+fn parse_ignore_rules(text: &str) -> Vec<IgnoreRule> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (exception, pattern) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest.trim()),
+                None => (false, line),
+            };
+            // Patterns are relative to the context root; `..` cannot leave it.
+            let pattern = pattern
+                .split('/')
+                .filter(|p| *p != "..")
+                .collect::<Vec<_>>()
+                .join("/");
+            let pattern = clean_path(&pattern)?;
+            (!pattern.is_empty()).then_some(IgnoreRule { pattern, exception })
+        })
+        .collect()
+}
+
+/// The last rule matching `path` or one of its parent directories decides.
+fn is_ignored(rules: &[IgnoreRule], path: &str) -> bool {
+    let mut ignored = false;
+    for rule in rules {
+        let matches = std::iter::once(path)
+            .chain(path.match_indices('/').map(|(i, _)| &path[..i]))
+            .any(|p| glob_match(&rule.pattern, p));
+        if matches {
+            ignored = !rule.exception;
+        }
+    }
+    ignored
+}
+
+// ============================================================================
+// Glob Matching
+// ============================================================================
+
+/// Match a `/`-separated path against a glob pattern.
+///
+/// `*` and `?` do not match `/`; `**` as a whole component matches any
+/// number of components; `[...]` matches a character class (`!` or `^`
+/// negates it); `\` escapes the next character.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    match_components(&pattern, &path)
+}
+
+fn match_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => {
+            match_components(rest, path)
+                || (!path.is_empty() && match_components(pattern, &path[1..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, names)) => {
+                let p: Vec<char> = first.chars().collect();
+                let n: Vec<char> = name.chars().collect();
+                match_component(&p, &n) && match_components(rest, names)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_component(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_component(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), match_class(&pattern[1..])) {
+            (Some(&c), Some((class, len))) => {
+                class(c) && match_component(&pattern[len + 1..], &name[1..])
+            }
+            // An unterminated class is a literal '['.
+            (Some('['), None) => match_component(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && match_component(&pattern[2..], &name[1..])
+        }
+        Some(&c) => name.first() == Some(&c) && match_component(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Parse a character class after its opening `[`.
+///
+/// Returns a predicate and the number of pattern characters consumed,
+/// including the closing `]`.
+fn match_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    let end = start + pattern[start..].iter().skip(1).position(|&c| c == ']')? + 1;
+
+    let mut ranges = Vec::new();
+    let mut i = start;
+    while i < end {
+        let lo = pattern[i];
+        if i + 2 < end && pattern[i + 1] == '-' {
+            ranges.push((lo, pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((lo, lo));
+            i += 1;
+        }
+    }
+    let class = move |c: char| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated;
+    Some((class, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(glob_match("**/*.rs", "main.rs"));
+        assert!(glob_match("**/*.rs", "src/a/b.rs"));
+        assert!(glob_match("src/**", "src/a/b.rs"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file10.txt"));
+        assert!(glob_match("[a-c]*", "bin"));
+        assert!(!glob_match("[!a-c]*", "bin"));
+        assert!(glob_match("\\*", "*"));
+        assert!(!glob_match("\\*", "a"));
+        assert!(glob_match("[]]", "]"));
+    }
+
+    #[test]
+    fn test_dockerignore() {
+        let rules = parse_ignore_rules(
+            "# comment\n/target\n*.log\n!keep.log\nnode_modules/**\n../outside\n",
+        );
+        assert_eq!(rules.len(), 5);
+        assert!(is_ignored(&rules, "target"));
+        assert!(is_ignored(&rules, "target/debug/app"));
+        assert!(is_ignored(&rules, "debug.log"));
+        assert!(!is_ignored(&rules, "keep.log"));
+        assert!(!is_ignored(&rules, "src/debug.log"));
+        assert!(is_ignored(&rules, "node_modules/x/index.js"));
+        assert!(is_ignored(&rules, "outside"));
+        assert!(!is_ignored(&rules, "src/main.rs"));
+    }
+
+    fn context() -> (tempfile::TempDir, BuildContext) {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "").unwrap();
+        fs::write(root.join("target/debug/app"), "binary").unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+        fs::write(root.join(DOCKERIGNORE_FILE), "target\n").unwrap();
+        std::os::unix::fs::symlink("README.md", root.join("link")).unwrap();
+        let ctx = BuildContext::scan(root).unwrap();
+        (tmp, ctx)
+    }
+
+    #[test]
+    fn test_scan_and_resolve() {
+        let (_tmp, ctx) = context();
+        assert_eq!(
+            ctx.entries,
+            vec![
+                ".dockerignore",
+                "README.md",
+                "link",
+                "src",
+                "src/main.rs",
+                "src/nested",
+                "src/nested/lib.rs",
+            ]
+        );
+
+        assert_eq!(ctx.resolve(".").unwrap(), vec!["."]);
+        assert_eq!(ctx.resolve("./src/").unwrap(), vec!["src"]);
+        assert_eq!(ctx.resolve("/src/*.rs").unwrap(), vec!["src/main.rs"]);
+        assert!(ctx.resolve("target/debug/app").is_err());
+        assert!(ctx.resolve("missing").is_err());
+        assert!(ctx.resolve("*.nothing").is_err());
+        assert!(ctx.resolve("../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_digest_tracks_selected_content() {
+        let (tmp, ctx) = context();
+        let src = ctx.digest(&["src".to_string()]).unwrap();
+        let all = ctx.digest(&[".".to_string()]).unwrap();
+
+        // Files outside the selection do not change its digest
+        fs::write(tmp.path().join("README.md"), "changed").unwrap();
+        assert_eq!(ctx.digest(&["src".to_string()]).unwrap(), src);
+        assert_ne!(ctx.digest(&[".".to_string()]).unwrap(), all);
+
+        fs::write(tmp.path().join("src/nested/lib.rs"), "pub fn f() {}").unwrap();
+        assert_ne!(ctx.digest(&["src".to_string()]).unwrap(), src);
+    }
+
+    #[test]
+    fn test_write_tar() {
+        let (tmp, ctx) = context();
+        let mut data = Vec::new();
+        ctx.write_tar(&mut data).unwrap();
+
+        let out = tmp.path().join("out");
+        tar::Archive::new(data.as_slice()).unpack(&out).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.join("src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(
+            fs::read_link(out.join("link")).unwrap(),
+            Path::new("README.md")
+        );
+        assert!(!out.join("target").exists());
+    }
+}
@@ -0,0 +1,654 @@
+//! Dockerfile parser.
+//!
+//! Supports the subset of the Dockerfile syntax used by `smolvm build`:
+//! FROM, RUN, COPY, ADD, ENV, WORKDIR, ENTRYPOINT, CMD, USER, ARG and LABEL,
+//! with line continuations, comments and multi-stage builds. Any other
+//! instruction is rejected rather than silently ignored.
+//!
+//! Arguments are kept unexpanded: variable substitution depends on the
+//! build arguments and environment at each step, so it is done by the
+//! build driver with [`expand`].
+
+use crate::error::{Error, Result};
+
+/// A parsed Dockerfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dockerfile {
+    /// ARG instructions before the first FROM (usable in FROM lines).
+    pub global_args: Vec<(String, Option<String>)>,
+    /// Build stages, in file order.
+    pub stages: Vec<Stage>,
+}
+
+/// A build stage, starting at a FROM instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    /// Base image or earlier stage name (unexpanded).
+    pub base: String,
+    /// Stage name from `FROM ... AS name`, lowercased.
+    pub name: Option<String>,
+    /// Line of the FROM instruction.
+    pub line: usize,
+    /// Instructions after FROM.
+    pub instructions: Vec<Instruction>,
+}
+
+/// A single instruction with its position in the Dockerfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// Line the instruction starts on (1-based).
+    pub line: usize,
+    /// Instruction text with continuations joined.
+    pub text: String,
+    /// Parsed instruction.
+    pub kind: InstructionKind,
+}
+
+/// Parsed instruction arguments (unexpanded).
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionKind {
+    /// RUN command.
+    Run(Command),
+    /// COPY or ADD.
+    Copy(CopyArgs),
+    /// ENV key/value pairs.
+    Env(Vec<(String, String)>),
+    /// WORKDIR path.
+    Workdir(String),
+    /// USER spec.
+    User(String),
+    /// ENTRYPOINT command.
+    Entrypoint(Command),
+    /// CMD command.
+    Cmd(Command),
+    /// ARG names with optional defaults.
+    Arg(Vec<(String, Option<String>)>),
+    /// LABEL key/value pairs.
+    Label(Vec<(String, String)>),
+}
+
+/// Arguments of a COPY or ADD instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyArgs {
+    /// Whether this is ADD (URLs and archive extraction).
+    pub add: bool,
+    /// `--from` stage or image.
+    pub from: Option<String>,
+    /// `--chown` owner.
+    pub chown: Option<String>,
+    /// `--chmod` permission bits (octal).
+    pub chmod: Option<String>,
+    /// Source paths or patterns.
+    pub sources: Vec<String>,
+    /// Destination path.
+    pub dest: String,
+}
+
+/// A command in exec (JSON array) or shell form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `["executable", "arg"]`
+    Exec(Vec<String>),
+    /// `command string`, run with `/bin/sh -c`.
+    Shell(String),
+}
+
+impl Command {
+    /// Convert to an argument vector.
+    pub fn into_argv(self) -> Vec<String> {
+        match self {
+            Command::Exec(argv) => argv,
+            Command::Shell(cmd) => vec!["/bin/sh".to_string(), "-c".to_string(), cmd],
+        }
+    }
+}
+
+/// Parse a Dockerfile.
+pub fn parse(text: &str) -> Result<Dockerfile> {
+    let mut global_args = Vec::new();
+    let mut stages: Vec<Stage> = Vec::new();
+
+    for (line, content) in logical_lines(text) {
+        let content = content.trim();
+        let (keyword, rest) = match content.find(char::is_whitespace) {
+            Some(i) => (&content[..i], content[i..].trim()),
+            None => (content, ""),
+        };
+        let keyword = keyword.to_ascii_uppercase();
+        let err = |reason: String| parse_error(line, reason);
+
+        if keyword == "FROM" {
+            let stage = parse_from(rest, line)?;
+            if let Some(name) = &stage.name {
+                if stages.iter().any(|s| s.name.as_ref() == Some(name)) {
+                    return Err(err(format!("duplicate stage name '{}'", name)));
+                }
+            }
+            stages.push(stage);
+            continue;
+        }
+
+        let kind = match keyword.as_str() {
+            "RUN" => {
+                reject_flags(rest, &keyword).map_err(err)?;
+                InstructionKind::Run(parse_command(rest))
+            }
+            "CMD" => InstructionKind::Cmd(parse_command(rest)),
+            "ENTRYPOINT" => InstructionKind::Entrypoint(parse_command(rest)),
+            "COPY" | "ADD" => {
+                InstructionKind::Copy(parse_copy(rest, keyword == "ADD").map_err(err)?)
+            }
+            "ENV" => InstructionKind::Env(parse_env(rest).map_err(err)?),
+            "LABEL" => InstructionKind::Label(parse_pairs(rest, "LABEL").map_err(err)?),
+            "ARG" => InstructionKind::Arg(parse_args(rest).map_err(err)?),
+            "WORKDIR" | "USER" => {
+                if rest.is_empty() {
+                    return Err(err(format!("{} requires an argument", keyword)));
+                }
+                if keyword == "USER" {
+                    InstructionKind::User(rest.to_string())
+                } else {
+                    InstructionKind::Workdir(rest.to_string())
+                }
+            }
+            _ => return Err(err(format!("unsupported instruction '{}'", keyword))),
+        };
+        if let InstructionKind::Run(Command::Shell(s))
+        | InstructionKind::Cmd(Command::Shell(s))
+        | InstructionKind::Entrypoint(Command::Shell(s)) = &kind
+        {
+            if s.is_empty() {
+                return Err(err(format!("{} requires an argument", keyword)));
+            }
+        }
+
+        match stages.last_mut() {
+            Some(stage) => stage.instructions.push(Instruction {
+                line,
+                text: content.to_string(),
+                kind,
+            }),
+            None => match kind {
+                InstructionKind::Arg(args) => global_args.extend(args),
+                _ => return Err(err(format!("{} before FROM", keyword))),
+            },
+        }
+    }
+
+    if stages.is_empty() {
+        return Err(Error::build("parse Dockerfile", "no FROM instruction"));
+    }
+    Ok(Dockerfile {
+        global_args,
+        stages,
+    })
+}
+
+fn parse_error(line: usize, reason: impl std::fmt::Display) -> Error {
+    Error::build("parse Dockerfile", format!("line {}: {}", line, reason))
+}
+
+/// Join continuation lines and drop comments and blank lines.
+///
+/// Returns each logical line with the number of the line it starts on.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, raw) in text.lines().enumerate() {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        let trimmed = raw.trim();
+        // Comments and blank lines are skipped, also inside continuations.
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (start, mut buf) = pending.take().unwrap_or((index + 1, String::new()));
+        match raw.trim_end().strip_suffix('\\') {
+            Some(part) => {
+                buf.push_str(part);
+                pending = Some((start, buf));
+            }
+            None => {
+                buf.push_str(raw);
+                lines.push((start, buf));
+            }
+        }
+    }
+    if let Some(line) = pending {
+        lines.push(line);
+    }
+    lines
+}
+
+fn parse_from(rest: &str, line: usize) -> Result<Stage> {
+    let words = split_words(rest);
+    if let Some(flag) = words.iter().find(|w| w.starts_with("--")) {
+        return Err(parse_error(
+            line,
+            format!("unsupported FROM flag '{}'", flag),
+        ));
+    }
+    let name = match words.as_slice() {
+        [_] => None,
+        [_, as_kw, name] if as_kw.eq_ignore_ascii_case("as") => Some(name.to_ascii_lowercase()),
+        _ => {
+            return Err(parse_error(
+                line,
+                "FROM requires an image and an optional 'AS name'",
+            ))
+        }
+    };
+    Ok(Stage {
+        base: words[0].clone(),
+        name,
+        line,
+        instructions: Vec::new(),
+    })
+}
+
+fn reject_flags(rest: &str, keyword: &str) -> std::result::Result<(), String> {
+    match split_words(rest).first() {
+        Some(word) if word.starts_with("--") => {
+            Err(format!("unsupported {} flag '{}'", keyword, word))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parse exec form (a JSON string array) or fall back to shell form.
+fn parse_command(rest: &str) -> Command {
+    if rest.starts_with('[') {
+        if let Ok(argv) = serde_json::from_str::<Vec<String>>(rest) {
+            return Command::Exec(argv);
+        }
+    }
+    Command::Shell(rest.to_string())
+}
+
+fn parse_copy(rest: &str, add: bool) -> std::result::Result<CopyArgs, String> {
+    let keyword = if add { "ADD" } else { "COPY" };
+    let mut words = split_words(rest);
+    let mut args = CopyArgs {
+        add,
+        from: None,
+        chown: None,
+        chmod: None,
+        sources: Vec::new(),
+        dest: String::new(),
+    };
+
+    let flags = words.iter().take_while(|w| w.starts_with("--")).count();
+    for flag in words.drain(..flags) {
+        let (name, value) = flag.split_once('=').unwrap_or((&flag, ""));
+        let slot = match name {
+            "--from" if !add => &mut args.from,
+            "--chown" => &mut args.chown,
+            "--chmod" => &mut args.chmod,
+            _ => return Err(format!("unsupported {} flag '{}'", keyword, flag)),
+        };
+        if value.is_empty() {
+            return Err(format!("{} requires a value", name));
+        }
+        *slot = Some(value.to_string());
+    }
+
+    // Exec form: the rest of the line is a JSON array.
+    let json = rest[rest.len() - remaining_len(rest, flags)..].trim();
+    if json.starts_with('[') {
+        if let Ok(list) = serde_json::from_str::<Vec<String>>(json) {
+            words = list;
+        }
+    }
+
+    if words.len() < 2 {
+        return Err(format!(
+            "{} requires at least one source and a destination",
+            keyword
+        ));
+    }
+    args.dest = words.pop().unwrap_or_default();
+    args.sources = words;
+    Ok(args)
+}
+
+/// Length of `rest` after skipping its first `skip` words.
+fn remaining_len(rest: &str, skip: usize) -> usize {
+    let mut s = rest.trim_start();
+    for _ in 0..skip {
+        s = s
+            .find(char::is_whitespace)
+            .map_or("", |i| &s[i..])
+            .trim_start();
+    }
+    s.len()
+}
+
+fn parse_env(rest: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    let words = split_words(rest);
+    match words.first() {
+        None => Err("ENV requires at least one variable".into()),
+        // Legacy form: `ENV key value with spaces`
+        Some(first) if !first.contains('=') => {
+            let value = rest[first.len()..].trim();
+            if value.is_empty() {
+                return Err(format!("ENV {} requires a value", first));
+            }
+            Ok(vec![(first.clone(), value.to_string())])
+        }
+        Some(_) => parse_pairs(rest, "ENV"),
+    }
+}
+
+fn parse_pairs(rest: &str, keyword: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    let words = split_words(rest);
+    if words.is_empty() {
+        return Err(format!("{} requires at least one key=value pair", keyword));
+    }
+    words
+        .into_iter()
+        .map(|word| match word.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => Err(format!("{} expects key=value, got '{}'", keyword, word)),
+        })
+        .collect()
+}
+
+fn parse_args(rest: &str) -> std::result::Result<Vec<(String, Option<String>)>, String> {
+    let words = split_words(rest);
+    if words.is_empty() {
+        return Err("ARG requires a name".into());
+    }
+    words
+        .into_iter()
+        .map(|word| {
+            let (name, default) = match word.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (word, None),
+            };
+            if !is_var_name(&name) {
+                return Err(format!("invalid ARG name '{}'", name));
+            }
+            Ok((name, default))
+        })
+        .collect()
+}
+
+/// Split on unquoted whitespace, keeping quotes and escapes in the words.
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                word.push(c);
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (c, Some(q)) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            (c, None) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Remove quotes from `word` and substitute variables.
+///
+/// Supports `$NAME`, `${NAME}`, `${NAME:-default}` and `${NAME:+alternate}`.
+/// Nothing is substituted inside single quotes; unset variables expand to
+/// an empty string.
+pub fn expand(word: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut out = String::new();
+    let mut quote = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match (c, quote) {
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some('\'')) => out.push(c),
+            ('\\', _) if i + 1 < chars.len() => {
+                let next = chars[i + 1];
+                // Inside double quotes only a few characters are escapable.
+                if quote.is_some() && !matches!(next, '"' | '\\' | '$') {
+                    out.push('\\');
+                }
+                out.push(next);
+                i += 1;
+            }
+            ('$', _) => {
+                let (value, consumed) = expand_var(&chars[i + 1..], lookup)?;
+                out.push_str(&value);
+                i += consumed;
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Expand the variable reference following a `$`.
+///
+/// Returns the value and the number of characters consumed after the `$`.
+fn expand_var(chars: &[char], lookup: &dyn Fn(&str) -> Option<String>) -> Result<(String, usize)> {
+    let name_len = |chars: &[char]| {
+        chars
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+            .count()
+    };
+
+    if chars.first() != Some(&'{') {
+        let len = name_len(chars);
+        if len == 0 {
+            return Ok(("$".to_string(), 0));
+        }
+        let name: String = chars[..len].iter().collect();
+        return Ok((lookup(&name).unwrap_or_default(), len));
+    }
+
+    // Find the matching brace
+    let mut depth = 0;
+    let end = chars
+        .iter()
+        .position(|&c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })
+        .ok_or_else(|| Error::build("expand variables", "missing '}'"))?;
+    let body: String = chars[1..end].iter().collect();
+    let len = name_len(&chars[1..end]);
+    let name = &body[..len];
+    if name.is_empty() {
+        return Err(Error::build(
+            "expand variables",
+            format!("bad substitution '${{{}}}'", body),
+        ));
+    }
+
+    let value = lookup(name).filter(|v| !v.is_empty());
+    let value = match &body[len..] {
+        "" => value.unwrap_or_default(),
+        op if op.starts_with(":-") => match value {
+            Some(v) => v,
+            None => expand(&op[2..], lookup)?,
+        },
+        op if op.starts_with(":+") => match value {
+            Some(_) => expand(&op[2..], lookup)?,
+            None => String::new(),
+        },
+        _ => {
+            return Err(Error::build(
+                "expand variables",
+                format!("unsupported substitution '${{{}}}'", body),
+            ))
+        }
+    };
+    Ok((value, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(name: &str) -> Option<String> {
+        match name {
+            "NAME" => Some("smol".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_dockerfile() {
+        let text = r#"
+# syntax comment
+ARG BASE=alpine:3.19
+FROM ${BASE} AS build
+RUN apk add --no-cache \
+    # comment inside a continuation
+    gcc \
+    musl-dev
+COPY --chown=app:app src/ /src/
+ENV PATH=/opt/bin:$PATH LANG="C.UTF-8"
+ENV LEGACY value with spaces
+
+from scratch
+copy --from=build /src/app /app
+ENTRYPOINT ["/app"]
+CMD --help
+"#;
+        let df = parse(text).unwrap();
+        assert_eq!(
+            df.global_args,
+            vec![("BASE".to_string(), Some("alpine:3.19".to_string()))]
+        );
+        assert_eq!(df.stages.len(), 2);
+
+        let build = &df.stages[0];
+        assert_eq!(build.base, "${BASE}");
+        assert_eq!(build.name.as_deref(), Some("build"));
+        assert_eq!(build.line, 4);
+        assert_eq!(build.instructions[0].line, 5);
+        assert_eq!(
+            build.instructions[0].kind,
+            InstructionKind::Run(Command::Shell(
+                "apk add --no-cache     gcc     musl-dev".to_string()
+            ))
+        );
+        let InstructionKind::Copy(copy) = &build.instructions[1].kind else {
+            panic!("expected COPY");
+        };
+        assert_eq!(copy.chown.as_deref(), Some("app:app"));
+        assert_eq!(copy.sources, vec!["src/"]);
+        assert_eq!(copy.dest, "/src/");
+        assert_eq!(
+            build.instructions[2].kind,
+            InstructionKind::Env(vec![
+                ("PATH".to_string(), "/opt/bin:$PATH".to_string()),
+                ("LANG".to_string(), "\"C.UTF-8\"".to_string()),
+            ])
+        );
+        assert_eq!(
+            build.instructions[3].kind,
+            InstructionKind::Env(vec![(
+                "LEGACY".to_string(),
+                "value with spaces".to_string()
+            )])
+        );
+
+        let app = &df.stages[1];
+        assert_eq!(app.base, "scratch");
+        assert_eq!(app.name, None);
+        let InstructionKind::Copy(copy) = &app.instructions[0].kind else {
+            panic!("expected COPY");
+        };
+        assert_eq!(copy.from.as_deref(), Some("build"));
+        assert_eq!(
+            app.instructions[1].kind,
+            InstructionKind::Entrypoint(Command::Exec(vec!["/app".to_string()]))
+        );
+        assert_eq!(
+            app.instructions[2].kind,
+            InstructionKind::Cmd(Command::Shell("--help".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("RUN true\nFROM alpine").is_err());
+        assert!(parse("FROM alpine\nEXPOSE 80").is_err());
+        assert!(parse("FROM alpine\nRUN --mount=type=cache true").is_err());
+        assert!(parse("FROM alpine\nCOPY onlyone").is_err());
+        assert!(parse("FROM alpine\nADD --from=x a b").is_err());
+        assert!(parse("FROM alpine AS a\nFROM alpine AS A").is_err());
+
+        let err = parse("FROM alpine\nLABEL novalue").unwrap_err().to_string();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_copy_exec_form() {
+        let df = parse("FROM alpine\nCOPY --chmod=755 [\"my file\", \"/dest dir/\"]").unwrap();
+        let InstructionKind::Copy(copy) = &df.stages[0].instructions[0].kind else {
+            panic!("expected COPY");
+        };
+        assert_eq!(copy.chmod.as_deref(), Some("755"));
+        assert_eq!(copy.sources, vec!["my file"]);
+        assert_eq!(copy.dest, "/dest dir/");
+    }
+
+    #[test]
+    fn test_expand() {
+        let cases = [
+            ("$NAME", "smol"),
+            ("${NAME}vm", "smolvm"),
+            ("'$NAME'", "$NAME"),
+            ("\"$NAME vm\"", "smol vm"),
+            ("\\$NAME", "$NAME"),
+            ("${MISSING:-default}", "default"),
+            ("${EMPTY:-x$NAME}", "xsmol"),
+            ("${NAME:+set}", "set"),
+            ("${MISSING:+set}", ""),
+            ("$MISSING-x", "-x"),
+            ("a $ b", "a $ b"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(expand(input, &vars).unwrap(), expected, "{}", input);
+        }
+        assert!(expand("${NAME", &vars).is_err());
+        assert!(expand("${NAME/a/b}", &vars).is_err());
+    }
+}
@@ -0,0 +1,729 @@
+//! Image builds from Dockerfiles.
+//!
+//! The host parses the Dockerfile, expands variables and resolves COPY
+//! sources against the build context. The agent executes each step on top
+//! of the previous step's image and snapshots the changes as a new layer.
+//!
+//! Every step result is stored in the agent as
+//! `smolvm-build-cache:<key>`, where the key hashes the parent step's key,
+//! the step itself and the content of its inputs. A rebuild reuses all
+//! steps up to the first one that changed.
+
+pub mod context;
+pub mod dockerfile;
+
+pub use context::BuildContext;
+pub use dockerfile::Dockerfile;
+
+use crate::agent::AgentClient;
+use crate::error::{Error, Result};
+use dockerfile::{expand, CopyArgs, InstructionKind, Stage};
+use sha2::{Digest, Sha256};
+use smolvm_protocol::{BuildInstruction, CopySource, ImageInfo, BUILD_CACHE_REPOSITORY};
+use std::path::{Path, PathBuf};
+
+/// Dockerfile names looked up in the context when none is given.
+pub const DEFAULT_DOCKERFILES: &[&str] = &["Dockerfile", "Containerfile"];
+
+/// Options for [`build_image`].
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Build context directory.
+    pub context: PathBuf,
+    /// Dockerfile path (default: `Dockerfile` or `Containerfile` in the
+    /// context).
+    pub dockerfile: Option<PathBuf>,
+    /// References to store the final image under.
+    pub tags: Vec<String>,
+    /// Values for ARG instructions.
+    pub build_args: Vec<(String, String)>,
+    /// Stage to build (default: the last one).
+    pub target: Option<String>,
+    /// Execute every step, ignoring cached results.
+    pub no_cache: bool,
+    /// OCI platform for base images (e.g., "linux/arm64").
+    pub oci_platform: Option<String>,
+}
+
+/// Build progress, reported to the caller of [`build_image`].
+#[derive(Debug)]
+pub enum BuildProgress<'a> {
+    /// A step is starting. Steps are numbered across all built stages.
+    Step {
+        /// Step number, starting at 1.
+        number: usize,
+        /// Total number of steps.
+        total: usize,
+        /// Instruction text.
+        instruction: &'a str,
+    },
+    /// The step's result was found in the build cache.
+    Cached,
+    /// A base image is being pulled.
+    Pull {
+        /// Image reference.
+        image: &'a str,
+    },
+    /// A build context is being uploaded to the agent.
+    UploadContext {
+        /// Archive size in bytes.
+        size: u64,
+    },
+    /// Output of a RUN step.
+    Output {
+        /// Captured stdout.
+        stdout: &'a str,
+        /// Captured stderr.
+        stderr: &'a str,
+    },
+}
+
+/// Build an image from a Dockerfile and store it in the agent.
+///
+/// Returns the final image, stored under each of `options.tags`.
+pub fn build_image(
+    client: &mut AgentClient,
+    options: &BuildOptions,
+    progress: impl FnMut(BuildProgress),
+) -> Result<ImageInfo> {
+    for tag in &options.tags {
+        crate::distribution::ImageReference::parse(tag)?;
+    }
+    let path = find_dockerfile(options)?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| Error::build("read Dockerfile", format!("{}: {}", path.display(), e)))?;
+    let dockerfile = dockerfile::parse(&text)?;
+    let context = BuildContext::scan(&options.context)?;
+
+    let mut builder = Builder {
+        client,
+        options,
+        dockerfile: &dockerfile,
+        context,
+        context_id: None,
+        uploads: Vec::new(),
+        global_args: Vec::new(),
+        stages: Vec::new(),
+        progress,
+        step: 0,
+        total: 0,
+    };
+    let result = builder.run();
+
+    // Uploaded contexts are only needed while steps execute.
+    for id in std::mem::take(&mut builder.uploads) {
+        if let Err(e) = builder.client.remove_build_context(&id) {
+            tracing::warn!(id = %id, error = %e, "failed to remove build context");
+        }
+    }
+    let reference = result?;
+
+    let mut image = None;
+    for tag in &options.tags {
+        image = Some(builder.client.tag_image(&reference, tag)?);
+    }
+    match image {
+        Some(image) => Ok(image),
+        None => builder
+            .client
+            .query(&reference)?
+            .ok_or_else(|| Error::build("build", "built image not found")),
+    }
+}
+
+/// Locate the Dockerfile: the explicit path, or a default name in the
+/// context directory.
+fn find_dockerfile(options: &BuildOptions) -> Result<PathBuf> {
+    if let Some(path) = &options.dockerfile {
+        return Ok(path.clone());
+    }
+    DEFAULT_DOCKERFILES
+        .iter()
+        .map(|name| options.context.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            Error::build(
+                "read Dockerfile",
+                format!(
+                    "no Dockerfile or Containerfile in {}",
+                    options.context.display()
+                ),
+            )
+        })
+}
+
+/// Result of a built stage.
+#[derive(Debug, Clone)]
+struct StageResult {
+    /// Stored image; `None` for a `FROM scratch` stage without steps.
+    reference: Option<String>,
+    key: String,
+    env: Vec<(String, String)>,
+}
+
+/// State of the stage being built.
+struct StageState {
+    result: StageResult,
+    /// Declared ARGs and their values.
+    args: Vec<(String, Option<String>)>,
+    /// Whether the stage set CMD (ENTRYPOINT only resets an inherited CMD).
+    has_cmd: bool,
+}
+
+impl StageState {
+    /// Variable lookup for expansion: ENV takes precedence over ARG.
+    fn lookup(&self, name: &str) -> Option<String> {
+        lookup(&self.result.env, name).or_else(|| {
+            self.args
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .and_then(|(_, v)| v.clone())
+        })
+    }
+
+    fn expand(&self, word: &str) -> Result<String> {
+        expand(word, &|name| self.lookup(name))
+    }
+}
+
+/// Where a COPY step's files come from, uploaded only on a cache miss.
+enum StepContext<'a> {
+    None,
+    /// The build context.
+    Main,
+    /// A directory prepared on the host (downloaded ADD sources).
+    Local(&'a BuildContext),
+}
+
+struct Builder<'a, P> {
+    client: &'a mut AgentClient,
+    options: &'a BuildOptions,
+    dockerfile: &'a Dockerfile,
+    context: BuildContext,
+    /// ID of the uploaded build context.
+    context_id: Option<String>,
+    /// Uploaded context IDs, removed after the build.
+    uploads: Vec<String>,
+    global_args: Vec<(String, String)>,
+    stages: Vec<Option<StageResult>>,
+    progress: P,
+    step: usize,
+    total: usize,
+}
+
+impl<P: FnMut(BuildProgress)> Builder<'_, P> {
+    /// Build the target stage and the stages it depends on, returning the
+    /// final image reference.
+    fn run(&mut self) -> Result<String> {
+        let stages = &self.dockerfile.stages;
+        for (name, default) in &self.dockerfile.global_args {
+            let value = match self.build_arg(name) {
+                Some(value) => Some(value),
+                None => default
+                    .as_deref()
+                    .map(|d| expand(d, &|n| lookup(&self.global_args, n)))
+                    .transpose()?,
+            };
+            if let Some(value) = value {
+                self.global_args.push((name.clone(), value));
+            }
+        }
+
+        let target = match &self.options.target {
+            Some(name) => find_stage(stages, &name.to_ascii_lowercase(), stages.len(), false)
+                .ok_or_else(|| {
+                    Error::build("build", format!("target stage '{}' not found", name))
+                })?,
+            None => stages.len() - 1,
+        };
+        let needed = self.needed_stages(target);
+        self.total = (0..=target)
+            .filter(|i| needed[*i])
+            .map(|i| stages[i].instructions.len() + 1)
+            .sum();
+
+        for (index, stage) in stages.iter().enumerate().take(target + 1) {
+            let result = if needed[index] {
+                Some(self.build_stage(index, stage)?)
+            } else {
+                None
+            };
+            self.stages.push(result);
+        }
+
+        self.stages[target]
+            .as_ref()
+            .and_then(|s| s.reference.clone())
+            .ok_or_else(|| Error::build("build", "the target stage produces no image"))
+    }
+
+    fn build_arg(&self, name: &str) -> Option<String> {
+        lookup(&self.options.build_args, name)
+    }
+
+    /// Stages the target depends on through FROM or `COPY --from`.
+    fn needed_stages(&self, target: usize) -> Vec<bool> {
+        let stages = &self.dockerfile.stages;
+        let mut needed = vec![false; stages.len()];
+        let mut pending = vec![target];
+        while let Some(index) = pending.pop() {
+            if std::mem::replace(&mut needed[index], true) {
+                continue;
+            }
+            let stage = &stages[index];
+            let froms = stage.instructions.iter().filter_map(|i| match &i.kind {
+                InstructionKind::Copy(CopyArgs { from: Some(f), .. }) => Some((f, true)),
+                _ => None,
+            });
+            for (name, allow_index) in std::iter::once((&stage.base, false)).chain(froms) {
+                let name = expand(name, &|n| lookup(&self.global_args, n))
+                    .unwrap_or_else(|_| name.clone())
+                    .to_ascii_lowercase();
+                if let Some(dep) = find_stage(stages, &name, index, allow_index) {
+                    pending.push(dep);
+                }
+            }
+        }
+        needed
+    }
+
+    fn build_stage(&mut self, index: usize, stage: &Stage) -> Result<StageResult> {
+        let base = expand(&stage.base, &|n| lookup(&self.global_args, n))?;
+        let mut from = format!("FROM {}", base);
+        if let Some(name) = &stage.name {
+            from.push_str(&format!(" AS {}", name));
+        }
+        self.report_step(&from);
+
+        let stages = &self.dockerfile.stages;
+        let result = match find_stage(stages, &base.to_ascii_lowercase(), index, false) {
+            Some(dep) => self.stage_result(dep)?,
+            None if base == "scratch" => StageResult {
+                reference: None,
+                key: hash_parts(&["scratch"]),
+                env: Vec::new(),
+            },
+            None => {
+                let image = self.ensure_image(&base)?;
+                StageResult {
+                    reference: Some(base),
+                    key: hash_parts(&["image", &image.digest]),
+                    env: image
+                        .env
+                        .iter()
+                        .filter_map(|e| e.split_once('='))
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                }
+            }
+        };
+
+        let mut state = StageState {
+            result,
+            args: Vec::new(),
+            has_cmd: false,
+        };
+        for instruction in &stage.instructions {
+            self.report_step(&instruction.text);
+            self.build_instruction(index, &mut state, &instruction.text, &instruction.kind)
+                .map_err(|e| match e {
+                    Error::Build { operation, reason } => {
+                        Error::build(operation, format!("line {}: {}", instruction.line, reason))
+                    }
+                    e => e,
+                })?;
+        }
+        Ok(state.result)
+    }
+
+    fn build_instruction(
+        &mut self,
+        stage: usize,
+        state: &mut StageState,
+        text: &str,
+        kind: &InstructionKind,
+    ) -> Result<()> {
+        let step = match kind {
+            InstructionKind::Arg(args) => {
+                for (name, default) in args {
+                    let value = match self.build_arg(name) {
+                        Some(value) => Some(value),
+                        None => match default {
+                            Some(d) => Some(state.expand(d)?),
+                            None => lookup(&self.global_args, name),
+                        },
+                    };
+                    state.args.push((name.clone(), value));
+                }
+                return Ok(());
+            }
+            InstructionKind::Run(command) => BuildInstruction::Run {
+                command: command.clone().into_argv(),
+                env: state
+                    .args
+                    .iter()
+                    .filter_map(|(n, v)| Some((n.clone(), v.clone()?)))
+                    .collect(),
+            },
+            InstructionKind::Copy(copy) => return self.copy(stage, state, text, copy),
+            InstructionKind::Env(pairs) => {
+                let mut env = Vec::new();
+                for (key, value) in pairs {
+                    let value = state.expand(value)?;
+                    set_var(&mut state.result.env, key, &value);
+                    env.push((key.clone(), value));
+                }
+                ConfigStep {
+                    env,
+                    ..Default::default()
+                }
+                .into()
+            }
+            InstructionKind::Label(pairs) => {
+                let labels = pairs
+                    .iter()
+                    .map(|(k, v)| Ok((state.expand(k)?, state.expand(v)?)))
+                    .collect::<Result<_>>()?;
+                ConfigStep {
+                    labels,
+                    ..Default::default()
+                }
+                .into()
+            }
+            InstructionKind::Workdir(dir) => ConfigStep {
+                workdir: Some(state.expand(dir)?),
+                ..Default::default()
+            }
+            .into(),
+            InstructionKind::User(user) => ConfigStep {
+                user: Some(state.expand(user)?),
+                ..Default::default()
+            }
+            .into(),
+            InstructionKind::Cmd(command) => {
+                state.has_cmd = true;
+                ConfigStep {
+                    cmd: Some(command.clone().into_argv()),
+                    ..Default::default()
+                }
+                .into()
+            }
+            InstructionKind::Entrypoint(command) => ConfigStep {
+                entrypoint: Some(command.clone().into_argv()),
+                // An empty CMD resets the one inherited from the base image
+                cmd: (!state.has_cmd).then(Vec::new),
+                ..Default::default()
+            }
+            .into(),
+        };
+        self.execute(state, text, step, "", StepContext::None)
+    }
+
+    fn copy(
+        &mut self,
+        stage: usize,
+        state: &mut StageState,
+        text: &str,
+        copy: &CopyArgs,
+    ) -> Result<()> {
+        let op = if copy.add { "ADD" } else { "COPY" };
+        let sources = copy
+            .sources
+            .iter()
+            .map(|s| state.expand(s))
+            .collect::<Result<Vec<_>>>()?;
+        let dest = state.expand(&copy.dest)?;
+        let chown = copy.chown.as_deref().map(|c| state.expand(c)).transpose()?;
+        let chmod = match &copy.chmod {
+            Some(mode) => Some(
+                u32::from_str_radix(&state.expand(mode)?, 8)
+                    .ok()
+                    .filter(|m| *m <= 0o7777)
+                    .ok_or_else(|| Error::build(op, format!("invalid --chmod '{}'", mode)))?,
+            ),
+            None => None,
+        };
+        // Only local archives are extracted by ADD
+        let step = |source, paths, extract| BuildInstruction::Copy {
+            source,
+            paths,
+            dest: dest.clone(),
+            chown: chown.clone(),
+            chmod,
+            extract,
+        };
+
+        // COPY --from: a previous stage or an image
+        if let Some(from) = &copy.from {
+            let from = state.expand(from)?.to_ascii_lowercase();
+            let stages = &self.dockerfile.stages;
+            let (reference, input) = match find_stage(stages, &from, stage, true) {
+                Some(dep) => {
+                    let result = self.stage_result(dep)?;
+                    let reference = result.reference.ok_or_else(|| {
+                        Error::build(op, format!("stage '{}' has no files to copy", from))
+                    })?;
+                    (reference, result.key)
+                }
+                None => {
+                    let image = self.ensure_image(&from)?;
+                    (from, image.digest)
+                }
+            };
+            let step = step(CopySource::Image { reference }, sources, false);
+            return self.execute(state, text, step, &input, StepContext::None);
+        }
+
+        // ADD <url>: download on the host and upload it as its own context
+        if copy.add && sources.iter().any(|s| is_url(s)) {
+            let [url] = sources.as_slice() else {
+                return Err(Error::build(op, "a URL must be the only source"));
+            };
+            let dir = tempfile::tempdir()?;
+            let name = download(url, dir.path())?;
+            let downloaded = BuildContext::scan(dir.path())?;
+            let input = downloaded.digest(std::slice::from_ref(&name))?;
+            let step = step(context_placeholder(), vec![name], false);
+            return self.execute(state, text, step, &input, StepContext::Local(&downloaded));
+        }
+
+        let mut paths: Vec<String> = Vec::new();
+        for source in &sources {
+            for path in self.context.resolve(source)? {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        let input = self.context.digest(&paths)?;
+        let step = step(context_placeholder(), paths, copy.add);
+        self.execute(state, text, step, &input, StepContext::Main)
+    }
+
+    /// Execute a step unless its result is cached.
+    ///
+    /// `input` is hashed into the cache key alongside the step; it covers
+    /// content the step refers to only by name.
+    fn execute(
+        &mut self,
+        state: &mut StageState,
+        text: &str,
+        mut step: BuildInstruction,
+        input: &str,
+        context: StepContext,
+    ) -> Result<()> {
+        let step_json = serde_json::to_string(&step)
+            .map_err(|e| Error::build("serialize step", e.to_string()))?;
+        let key = hash_parts(&[&state.result.key, text, &step_json, input]);
+        let target = format!("{}:{}", BUILD_CACHE_REPOSITORY, key);
+
+        if !self.options.no_cache && self.client.query(&target)?.is_some() {
+            (self.progress)(BuildProgress::Cached);
+        } else {
+            let id = match context {
+                StepContext::None => None,
+                StepContext::Main => Some(self.upload_main_context()?),
+                StepContext::Local(ctx) => {
+                    let id = upload_context(self.client, ctx, &mut self.progress)?;
+                    self.uploads.push(id.clone());
+                    Some(id)
+                }
+            };
+            if let (
+                Some(id),
+                BuildInstruction::Copy {
+                    source: CopySource::Context { id: slot },
+                    ..
+                },
+            ) = (id, &mut step)
+            {
+                *slot = id;
+            }
+
+            let result =
+                self.client
+                    .build_step(state.result.reference.as_deref(), &target, step, text)?;
+            if !result.stdout.is_empty() || !result.stderr.is_empty() {
+                (self.progress)(BuildProgress::Output {
+                    stdout: &result.stdout,
+                    stderr: &result.stderr,
+                });
+            }
+            if result.exit_code != 0 || result.image.is_none() {
+                return Err(Error::build(
+                    "RUN",
+                    format!("'{}' returned exit code {}", text, result.exit_code),
+                ));
+            }
+        }
+
+        state.result.reference = Some(target);
+        state.result.key = key;
+        Ok(())
+    }
+
+    fn upload_main_context(&mut self) -> Result<String> {
+        if let Some(id) = &self.context_id {
+            return Ok(id.clone());
+        }
+        let id = upload_context(self.client, &self.context, &mut self.progress)?;
+        self.uploads.push(id.clone());
+        self.context_id = Some(id.clone());
+        Ok(id)
+    }
+
+    /// Make sure an image is in the agent's store, pulling it if needed.
+    fn ensure_image(&mut self, image: &str) -> Result<ImageInfo> {
+        if let Some(info) = self.client.query(image)? {
+            return Ok(info);
+        }
+        (self.progress)(BuildProgress::Pull { image });
+        self.client.pull_with_registry_config_and_progress(
+            image,
+            self.options.oci_platform.as_deref(),
+            |_, _, _| {},
+        )
+    }
+
+    fn stage_result(&self, index: usize) -> Result<StageResult> {
+        self.stages
+            .get(index)
+            .cloned()
+            .flatten()
+            .ok_or_else(|| Error::build("build", format!("stage {} was not built", index)))
+    }
+
+    fn report_step(&mut self, instruction: &str) {
+        self.step += 1;
+        (self.progress)(BuildProgress::Step {
+            number: self.step,
+            total: self.total,
+            instruction,
+        });
+    }
+}
+
+/// Arguments of a config-only step.
+#[derive(Default)]
+struct ConfigStep {
+    env: Vec<(String, String)>,
+    workdir: Option<String>,
+    user: Option<String>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    labels: Vec<(String, String)>,
+}
+
+impl From<ConfigStep> for BuildInstruction {
+    fn from(c: ConfigStep) -> Self {
+        BuildInstruction::Config {
+            env: c.env,
+            workdir: c.workdir,
+            user: c.user,
+            entrypoint: c.entrypoint,
+            cmd: c.cmd,
+            labels: c.labels,
+        }
+    }
+}
+
+/// A context source whose ID is filled in once the context is uploaded,
+/// so the cache key depends on the copied files rather than the context.
+fn context_placeholder() -> CopySource {
+    CopySource::Context { id: String::new() }
+}
+
+/// Find a stage by name (or by index, for `COPY --from`) among the stages
+/// before `before`.
+fn find_stage(stages: &[Stage], name: &str, before: usize, allow_index: bool) -> Option<usize> {
+    if allow_index {
+        if let Ok(index) = name.parse::<usize>() {
+            return (index < before).then_some(index);
+        }
+    }
+    stages[..before.min(stages.len())]
+        .iter()
+        .position(|s| s.name.as_deref() == Some(name))
+}
+
+fn lookup(vars: &[(String, String)], name: &str) -> Option<String> {
+    vars.iter()
+        .rev()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.clone())
+}
+
+fn set_var(vars: &mut Vec<(String, String)>, name: &str, value: &str) {
+    vars.retain(|(n, _)| n != name);
+    vars.push((name.to_string(), value.to_string()));
+}
+
+/// Hex SHA-256 over length-prefixed parts.
+fn hash_parts(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Upload a context to the agent; its ID is its content digest.
+fn upload_context(
+    client: &mut AgentClient,
+    context: &BuildContext,
+    progress: &mut impl FnMut(BuildProgress),
+) -> Result<String> {
+    let id = context.digest(&[".".to_string()])?;
+    let mut archive = tempfile::tempfile()?;
+    context.write_tar(std::io::BufWriter::new(&mut archive))?;
+    let size = archive.metadata()?.len();
+    std::io::Seek::rewind(&mut archive)?;
+
+    progress(BuildProgress::UploadContext { size });
+    client.upload_build_context(&id, &mut archive)?;
+    Ok(id)
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Download `url` into `dir`, returning the file name used.
+fn download(url: &str, dir: &Path) -> Result<String> {
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty() && *n != "." && *n != "..")
+        .unwrap_or("download")
+        .to_string();
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| Error::build("ADD", format!("download {}: {}", url, e)))?;
+    let mut file = std::fs::File::create(dir.join(&name))?;
+    std::io::copy(&mut response.into_reader(), &mut file)?;
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_stage() {
+        let df = dockerfile::parse("FROM alpine AS base\nFROM base AS app\nFROM scratch").unwrap();
+        assert_eq!(find_stage(&df.stages, "base", 2, false), Some(0));
+        assert_eq!(find_stage(&df.stages, "app", 1, false), None);
+        assert_eq!(find_stage(&df.stages, "1", 2, true), Some(1));
+        assert_eq!(find_stage(&df.stages, "1", 2, false), None);
+        assert_eq!(find_stage(&df.stages, "2", 2, true), None);
+    }
+
+    #[test]
+    fn test_hash_parts_is_unambiguous() {
+        assert_ne!(hash_parts(&["ab", "c"]), hash_parts(&["a", "bc"]));
+        assert_eq!(hash_parts(&["a", "b"]), hash_parts(&["a", "b"]));
+    }
+}
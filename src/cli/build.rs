@@ -0,0 +1,106 @@
+//! Image build command.
+
+use crate::cli::parsers::parse_env_spec;
+use crate::cli::vm_common;
+use clap::Args;
+use smolvm::build::{self, BuildOptions, BuildProgress};
+use std::path::PathBuf;
+
+/// Build an image from a Dockerfile or Containerfile.
+///
+/// Steps execute inside the microVM and each step's result is cached, so
+/// rebuilding after a change only re-runs the steps from the first change
+/// onward. Supported instructions: FROM, RUN, COPY, ADD, ENV, WORKDIR,
+/// ENTRYPOINT, CMD, USER, ARG and LABEL. Paths matching the context's
+/// .dockerignore are not sent to the VM.
+///
+/// Examples:
+///   smolvm build -t myapp:dev .
+///   smolvm build -f Containerfile.prod -t myapp:prod --build-arg VERSION=1.2 .
+///   smolvm build --target builder --no-cache --name builder ./app
+#[derive(Args, Debug)]
+pub struct BuildCmd {
+    /// Build context directory
+    #[arg(value_name = "CONTEXT")]
+    pub context: PathBuf,
+
+    /// Name of the built image (can be used multiple times)
+    #[arg(short = 't', long = "tag", value_name = "REF")]
+    pub tags: Vec<String>,
+
+    /// Dockerfile path (default: CONTEXT/Dockerfile or CONTEXT/Containerfile)
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Set a build argument (NAME takes the value from the environment)
+    #[arg(long = "build-arg", value_name = "NAME[=VALUE]")]
+    pub build_args: Vec<String>,
+
+    /// Build up to this stage of a multi-stage Dockerfile
+    #[arg(long, value_name = "STAGE")]
+    pub target: Option<String>,
+
+    /// Re-run every step instead of using cached results
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Target OCI platform for base images (e.g., linux/arm64, linux/amd64)
+    #[arg(long = "oci-platform", value_name = "OS/ARCH")]
+    pub oci_platform: Option<String>,
+
+    /// microVM to build in (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl BuildCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let build_args = self
+            .build_args
+            .iter()
+            .filter_map(|arg| {
+                parse_env_spec(arg).or_else(|| std::env::var(arg).ok().map(|v| (arg.clone(), v)))
+            })
+            .collect();
+        let options = BuildOptions {
+            context: self.context,
+            dockerfile: self.file,
+            tags: self.tags,
+            build_args,
+            target: self.target,
+            no_cache: self.no_cache,
+            oci_platform: self.oci_platform,
+        };
+
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let result = build::build_image(&mut client, &options, print_progress);
+        manager.detach();
+
+        let image = result?;
+        println!("Built {} ({})", image.reference, image.digest);
+        Ok(())
+    }
+}
+
+fn print_progress(progress: BuildProgress) {
+    match progress {
+        BuildProgress::Step {
+            number,
+            total,
+            instruction,
+        } => println!("Step {}/{} : {}", number, total, instruction),
+        BuildProgress::Cached => println!(" ---> Using cache"),
+        BuildProgress::Pull { image } => println!(" ---> Pulling {}", image),
+        BuildProgress::UploadContext { size } => println!(
+            " ---> Sending build context ({})",
+            crate::cli::format_bytes(size)
+        ),
+        BuildProgress::Output { stdout, stderr } => {
+            print!("{}", stdout);
+            eprint!("{}", stderr);
+        }
+    }
+    crate::cli::flush_output();
+}
@@ -85,6 +85,23 @@ impl ShowCmd {
             }
         }
 
+        if let Some(ref proxy) = registry_config.proxy {
+            println!();
+            println!("  Default proxy:");
+            if let Some(ref http) = proxy.http {
+                println!("    http: {}", http);
+            }
+            if let Some(ref https) = proxy.https {
+                println!("    https: {}", https);
+            }
+            if !proxy.no_proxy.is_empty() {
+                println!("    no_proxy: {}", proxy.no_proxy.join(","));
+            }
+            if !proxy.ca_certs.is_empty() {
+                println!("    ca_certs: {}", proxy.ca_certs.len());
+            }
+        }
+
         Ok(())
     }
 }
@@ -260,4 +277,11 @@ const EXAMPLE_CONFIG: &str = r#"# smolvm Registry Configuration
 # username = "user"
 # password_env = "REGISTRY_PASSWORD"
 # mirror = "mirror.example.com"  # Optional: pull from mirror instead
+
+# Default HTTP(S) proxy for VMs that don't set their own
+# [proxy]
+# http = "http://proxy.example.com:3128"
+# https = "http://proxy.example.com:3128"
+# no_proxy = ["localhost", ".example.com"]
+# ca_certs = ["/etc/ssl/example-ca.pem"]  # Extra CAs trusted in the guest
 "#;
@@ -3,11 +3,16 @@
 //! These commands manage long-running containers via a microvm.
 //! Containers can be created, started, stopped, and deleted independently.
 
-use crate::cli::parsers::{parse_duration, parse_env_list, parse_mounts_to_bindings};
+use crate::cli::parsers::{
+    parse_duration, parse_env_list, parse_mount_arg, parse_mounts_to_bindings, parse_tmpfs,
+};
 use crate::cli::vm_common;
 use crate::cli::{flush_output, truncate, truncate_id, COMMAND_WIDTH, IMAGE_NAME_WIDTH};
 use clap::{Args, Subcommand};
-use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::agent::{AgentClient, AgentManager, ContainerMount};
+use smolvm::db::SmolvmDb;
+use smolvm::mount::{MountArg, MountFlags};
+use smolvm::volume::{is_volume_name, VolumeStore};
 use smolvm::{DEFAULT_IDLE_CMD, DEFAULT_SHELL_CMD};
 use std::time::Duration;
 
@@ -87,9 +92,18 @@ pub struct ContainerCreateCmd {
     #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
     pub env: Vec<String>,
 
-    /// Mount host directory (can be used multiple times)
+    /// Mount host path or named volume (can be used multiple times)
     #[arg(short = 'v', long = "volume", value_name = "HOST:CONTAINER[:ro]")]
     pub volume: Vec<String>,
+
+    /// Attach a mount, e.g. type=tmpfs,target=/cache or
+    /// type=volume,source=data,target=/data,noexec (can be used multiple times)
+    #[arg(long = "mount", value_parser = parse_mount_arg, value_name = "SPEC")]
+    pub mount: Vec<MountArg>,
+
+    /// Mount a tmpfs, e.g. /tmp:size=256m,mode=1777 (can be used multiple times)
+    #[arg(long, value_parser = parse_tmpfs, value_name = "PATH[:OPTS]")]
+    pub tmpfs: Vec<ContainerMount>,
 }
 
 impl ContainerCreateCmd {
@@ -108,7 +122,8 @@ impl ContainerCreateCmd {
         let env = parse_env_list(&self.env);
 
         // Parse mounts
-        let mounts = parse_mounts_to_bindings(&self.volume)?;
+        let mounts =
+            container_mount_bindings(&self.microvm, &self.volume, &self.mount, &self.tmpfs)?;
 
         // Default command is sleep infinity for long-running containers
         let command = if self.command.is_empty() {
@@ -132,6 +147,83 @@ impl ContainerCreateCmd {
     }
 }
 
+/// Resolve `-v`, `--mount` and `--tmpfs` arguments into container mounts.
+///
+/// Host paths given with `-v` use the microVM mount at the same position.
+/// Named volumes and `--mount` sources must already be attached to the
+/// microVM (`microvm create -v SOURCE:/path`), and the container gets that
+/// mount.
+fn container_mount_bindings(
+    microvm: &str,
+    specs: &[String],
+    mount_args: &[MountArg],
+    tmpfs: &[ContainerMount],
+) -> smolvm::Result<Vec<ContainerMount>> {
+    let (volume_specs, path_specs): (Vec<String>, Vec<String>) = specs
+        .iter()
+        .cloned()
+        .partition(|spec| is_volume_name(spec.split(':').next().unwrap_or_default()));
+    let mut bindings = parse_mounts_to_bindings(&path_specs)?;
+
+    let mut attached: Vec<(String, MountFlags)> = volume_specs
+        .into_iter()
+        .map(|spec| (spec, MountFlags::default()))
+        .collect();
+    for arg in mount_args {
+        match arg {
+            MountArg::Share { spec, flags } => attached.push((spec.clone(), *flags)),
+            MountArg::Tmpfs(mount) => bindings.push(mount.clone()),
+        }
+    }
+    bindings.extend(tmpfs.iter().cloned());
+    if attached.is_empty() {
+        return Ok(bindings);
+    }
+
+    let db = SmolvmDb::open()?;
+    let vm_mounts = db
+        .get_vm(microvm)?
+        .map(|record| record.host_mounts())
+        .unwrap_or_default();
+    let volumes = VolumeStore::at(db, smolvm::volume::default_root()?);
+    for (spec, flags) in &attached {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let [source, target, mode @ ..] = parts.as_slice() else {
+            return Err(smolvm::Error::mount(
+                "parse volume spec",
+                format!("invalid format '{}': expected volume:container[:ro]", spec),
+            ));
+        };
+        let path = if is_volume_name(source) {
+            volumes.source_path(&volumes.get(source)?)
+        } else {
+            std::path::PathBuf::from(source)
+        };
+        let path = path.canonicalize().unwrap_or(path);
+        let index = vm_mounts
+            .iter()
+            .position(|m| m.source == path)
+            .ok_or_else(|| {
+                smolvm::Error::mount(
+                    "resolve mount",
+                    format!(
+                        "'{}' is not attached to microVM '{}' (attach it with \
+                         'smolvm microvm create {} -v {}:/path')",
+                        source, microvm, microvm, source
+                    ),
+                )
+            })?;
+        let mut binding = ContainerMount::bind(
+            smolvm::agent::mount_tag(index),
+            target.to_string(),
+            mode.first() == Some(&"ro"),
+        );
+        flags.apply(&mut binding);
+        bindings.push(binding);
+    }
+    Ok(bindings)
+}
+
 // ============================================================================
 // Start
 // ============================================================================
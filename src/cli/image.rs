@@ -0,0 +1,266 @@
+//! Image management commands.
+//!
+//! These commands operate on the image store of a microVM's agent.
+
+use crate::cli::vm_common;
+use clap::{Args, Subcommand};
+use smolvm::distribution::{self, ImageReference};
+use smolvm_protocol::LayerCompression;
+use std::path::PathBuf;
+
+/// Manage images stored in a microVM
+#[derive(Subcommand, Debug)]
+pub enum ImageCmd {
+    /// Push an image to an OCI registry
+    Push(ImagePushCmd),
+
+    /// Save an image to a tar archive
+    Save(ImageSaveCmd),
+
+    /// Load images from a tar archive
+    Load(ImageLoadCmd),
+
+    /// Remove images from a microVM's storage
+    #[command(visible_alias = "remove")]
+    Rm(ImageRmCmd),
+}
+
+impl ImageCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            ImageCmd::Push(cmd) => cmd.run(),
+            ImageCmd::Save(cmd) => cmd.run(),
+            ImageCmd::Load(cmd) => cmd.run(),
+            ImageCmd::Rm(cmd) => cmd.run(),
+        }
+    }
+}
+
+// ============================================================================
+// Push
+// ============================================================================
+
+/// Push an image from a microVM's storage to an OCI registry.
+///
+/// Layers are re-tarred deterministically, so pushing the same image twice
+/// produces the same digests and unchanged blobs are skipped. Credentials
+/// are read from ~/.config/smolvm/registries.toml.
+///
+/// Examples:
+///   smolvm image push ghcr.io/me/app:1.0
+///   smolvm image push alpine --to localhost:5000/alpine:latest
+///   smolvm image push app --to ghcr.io/me/app:v2 --compression zstd --name builder
+#[derive(Args, Debug)]
+pub struct ImagePushCmd {
+    /// Image in the microVM's storage
+    #[arg(value_name = "REF")]
+    pub image: String,
+
+    /// Destination reference (default: REF)
+    #[arg(long, value_name = "TARGET")]
+    pub to: Option<String>,
+
+    /// Layer compression: gzip or zstd
+    #[arg(long, default_value = "gzip", value_name = "ALGO")]
+    pub compression: LayerCompression,
+
+    /// Source microVM (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl ImagePushCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let target = ImageReference::parse(self.to.as_deref().unwrap_or(&self.image))?;
+
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        println!("Pushing {} to {}...", self.image, target);
+        let result = distribution::push_image(
+            &mut client,
+            &self.image,
+            &target,
+            self.compression,
+            |blob, status| {
+                let short = blob.digest.strip_prefix("sha256:").unwrap_or(&blob.digest);
+                println!(
+                    "  {}: {} ({})",
+                    &short[..short.len().min(12)],
+                    status,
+                    crate::cli::format_bytes(blob.size)
+                );
+            },
+        );
+        manager.detach();
+
+        let digest = result?;
+        println!("{}: digest: {}", target, digest);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Save
+// ============================================================================
+
+/// Save an image from a microVM's storage to a tar archive.
+///
+/// The archive is an OCI image layout that also carries a docker-style
+/// manifest.json, so it can be loaded by docker, podman, skopeo or
+/// `smolvm image load`.
+///
+/// Examples:
+///   smolvm image save alpine -o alpine.tar
+///   smolvm image save ghcr.io/me/app:1.0 -o app.tar --name builder
+#[derive(Args, Debug)]
+pub struct ImageSaveCmd {
+    /// Image in the microVM's storage
+    #[arg(value_name = "REF")]
+    pub image: String,
+
+    /// Output archive path
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Source microVM (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl ImageSaveCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        println!("Saving {} to {}...", self.image, self.output.display());
+        let result = write_archive(&mut client, &self.image, &self.output);
+        manager.detach();
+        result?;
+
+        let size = std::fs::metadata(&self.output)
+            .map(|m| m.len())
+            .unwrap_or(0);
+        println!(
+            "Saved {} ({})",
+            self.output.display(),
+            crate::cli::format_bytes(size)
+        );
+        Ok(())
+    }
+}
+
+/// Save into a temp file next to `output`, renaming it into place on success.
+fn write_archive(
+    client: &mut smolvm::agent::AgentClient,
+    image: &str,
+    output: &std::path::Path,
+) -> smolvm::Result<()> {
+    let dir = output
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    distribution::save_image(client, image, std::io::BufWriter::new(tmp.as_file()))?;
+    tmp.persist(output).map_err(|e| e.error)?;
+    Ok(())
+}
+
+// ============================================================================
+// Load
+// ============================================================================
+
+/// Load images from a tar archive into a microVM's storage.
+///
+/// Accepts OCI image layouts (`skopeo copy ... oci-archive:`) and
+/// `docker save` archives, optionally gzip- or zstd-compressed.
+///
+/// Examples:
+///   smolvm image load -i alpine.tar
+///   docker save myapp:dev | gzip > app.tar.gz && smolvm image load -i app.tar.gz
+///   smolvm image load -i app.tar --tag myapp:latest --name builder
+#[derive(Args, Debug)]
+pub struct ImageLoadCmd {
+    /// Archive to load
+    #[arg(short = 'i', long, value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Store the image under this name (archive must hold exactly one image)
+    #[arg(long, value_name = "NAME")]
+    pub tag: Option<String>,
+
+    /// Target microVM (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl ImageLoadCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let mut file = std::fs::File::open(&self.input)?;
+
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        println!("Loading {}...", self.input.display());
+        let result = client.import_image(&mut file, self.tag.as_deref());
+        manager.detach();
+
+        for image in result? {
+            println!(
+                "Loaded image: {} ({})",
+                image.reference,
+                crate::cli::format_bytes(image.size)
+            );
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Remove
+// ============================================================================
+
+/// Remove images from a microVM's storage.
+///
+/// Layers and configs that no remaining image uses are removed with the
+/// image. Images that a container was created from cannot be removed
+/// until the container is deleted.
+///
+/// Examples:
+///   smolvm image rm alpine
+///   smolvm image rm myapp:dev myapp:old --name builder
+#[derive(Args, Debug)]
+pub struct ImageRmCmd {
+    /// Images to remove
+    #[arg(value_name = "REF", required = true)]
+    pub images: Vec<String>,
+
+    /// Target microVM (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl ImageRmCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let mut first_error = None;
+        for image in &self.images {
+            match client.remove_image(image) {
+                Ok(report) => println!(
+                    "Removed {} (freed {})",
+                    image,
+                    crate::cli::format_bytes(report.freed_bytes)
+                ),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        manager.detach();
+
+        first_error.map_or(Ok(()), Err)
+    }
+}
@@ -8,11 +8,13 @@
 //! - delete: Delete a named VM configuration
 //! - status: Show microvm status
 //! - ls: List all named VMs
+//! - port: Publish or withdraw ports (also on a running VM)
 
-use crate::cli::parsers::{parse_duration, parse_env_list, parse_port};
+use crate::cli::parsers::{
+    flatten_ports, parse_duration, parse_env_list, parse_net, parse_port, DnsArgs, NetArg, PortArg,
+};
 use crate::cli::vm_common::{self, DeleteVmOptions, VmKind};
 use clap::{Args, Subcommand};
-use smolvm::agent::PortMapping;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -20,6 +22,7 @@ const KIND: VmKind = VmKind::Microvm;
 
 /// Manage persistent microVMs
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)] // parsed once per process
 pub enum MicrovmCmd {
     /// Run a command directly in the VM (not in a container)
     Exec(ExecCmd),
@@ -47,6 +50,10 @@ pub enum MicrovmCmd {
     /// Resize a microVM's disk resources
     Resize(ResizeCmd),
 
+    /// Publish or withdraw a microVM's ports
+    #[command(subcommand)]
+    Port(PortCmd),
+
     /// Test network connectivity from inside the VM
     #[command(hide = true)]
     NetworkTest(NetworkTestCmd),
@@ -63,6 +70,7 @@ impl MicrovmCmd {
             MicrovmCmd::Status(cmd) => cmd.run(),
             MicrovmCmd::Ls(cmd) => cmd.run(),
             MicrovmCmd::Resize(cmd) => cmd.run(),
+            MicrovmCmd::Port(cmd) => cmd.run(),
             MicrovmCmd::NetworkTest(cmd) => cmd.run(),
         }
     }
@@ -178,17 +186,43 @@ pub struct CreateCmd {
     #[arg(long, value_name = "GiB")]
     pub overlay: Option<u64>,
 
-    /// Mount host directory (can be used multiple times)
+    /// Mount host path or named volume (can be used multiple times)
     #[arg(short = 'v', long = "volume", value_name = "HOST:GUEST[:ro]")]
     pub volume: Vec<String>,
 
     /// Expose port from VM to host (can be used multiple times)
-    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "HOST:GUEST")]
-    pub port: Vec<PortMapping>,
-
-    /// Enable outbound network access
-    #[arg(long)]
-    pub net: bool,
+    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "[IP:]HOST:GUEST[/udp]")]
+    pub port: Vec<PortArg>,
+
+    /// Enable outbound network access; with allow=RULES, proxy-only egress
+    ///
+    /// Bare --net allows all outbound traffic. --net=allow=RULE,... (can be
+    /// repeated) instead sends egress through an HTTP proxy that only
+    /// reaches the listed domains (github.com), wildcard domains
+    /// (*.npmjs.org), IPs or CIDR blocks (10.0.0.0/8), each with an optional
+    /// :PORT. Only HTTP(S) clients that honor HTTP_PROXY/HTTPS_PROXY get
+    /// out; other traffic (SSH, database clients, raw TCP, direct DNS
+    /// lookups) has no route, even to allowed destinations. Denied proxy
+    /// requests are logged to the VM's egress.log.
+    #[arg(
+        long,
+        value_name = "allow=RULES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_parser = parse_net
+    )]
+    pub net: Vec<NetArg>,
+
+    /// Join a private network created with `smolvm network create`
+    ///
+    /// Members reach each other by address or by VM name through the
+    /// network's embedded DNS.
+    #[arg(long = "network", value_name = "NAME")]
+    pub network: Option<String>,
+
+    #[command(flatten)]
+    pub dns: DnsArgs,
 
     /// Run command on every VM start (can be used multiple times)
     #[arg(long = "init", value_name = "COMMAND")]
@@ -214,8 +248,10 @@ impl CreateCmd {
             self.cpus,
             self.mem,
             self.volume,
-            self.port,
+            flatten_ports(self.port),
             self.net,
+            self.network,
+            self.dns.into_settings(),
             self.init,
             self.env,
             self.workdir,
@@ -328,7 +364,11 @@ pub struct StatusCmd {
 
 impl StatusCmd {
     pub fn run(self) -> smolvm::Result<()> {
-        vm_common::status_vm(KIND, &self.name, |_| {})
+        vm_common::status_vm(KIND, &self.name, |manager| {
+            if let Ok(mut client) = smolvm::agent::AgentClient::connect(manager.vsock_socket()) {
+                vm_common::print_sync_status(&mut client);
+            }
+        })
     }
 }
 
@@ -362,9 +402,10 @@ impl LsCmd {
 
 /// Resize a microVM's disk resources.
 ///
-/// Expands the storage and/or overlay disk for a stopped microVM.
-/// The VM must be stopped before resizing. Disk expansion happens
-/// immediately; filesystem resize occurs automatically on next boot.
+/// Expands the storage and/or overlay disk of a microVM. A stopped VM's
+/// filesystems are resized automatically on next boot. The storage disk of
+/// a running VM is grown online when the hypervisor can notify the guest of
+/// the new size; the overlay disk always requires the VM to be stopped.
 ///
 /// Examples:
 ///   smolvm microvm resize my-vm --storage 50
@@ -401,6 +442,76 @@ impl ResizeCmd {
     }
 }
 
+// ============================================================================
+// Port Commands
+// ============================================================================
+
+/// Publish or withdraw a microVM's ports.
+///
+/// Changes apply to a running microVM immediately, without a restart, and
+/// are saved so they persist across restarts. Ports a running microVM
+/// published at boot as plain TCP on all interfaces can only be withdrawn
+/// from its saved configuration after a restart.
+///
+/// Examples:
+///   smolvm microvm port add my-vm 8080:80
+///   smolvm microvm port add my-vm 127.0.0.1:5353:53/udp
+///   smolvm microvm port rm my-vm 8080
+#[derive(Subcommand, Debug)]
+pub enum PortCmd {
+    /// Publish ports
+    Add(PortAddCmd),
+
+    /// Withdraw published ports
+    #[command(visible_alias = "remove")]
+    Rm(PortRmCmd),
+}
+
+impl PortCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            PortCmd::Add(cmd) => cmd.run(),
+            PortCmd::Rm(cmd) => cmd.run(),
+        }
+    }
+}
+
+/// Publish ports on a microVM
+#[derive(Args, Debug)]
+pub struct PortAddCmd {
+    /// MicroVM name
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// Ports to publish
+    #[arg(required = true, value_parser = parse_port, value_name = "[IP:]HOST:GUEST[/udp]")]
+    pub ports: Vec<PortArg>,
+}
+
+impl PortAddCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        vm_common::publish_vm_ports(KIND, &self.name, &flatten_ports(self.ports))
+    }
+}
+
+/// Withdraw published ports from a microVM
+#[derive(Args, Debug)]
+pub struct PortRmCmd {
+    /// MicroVM name
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// Ports to withdraw, matched by host port (guest port optional)
+    #[arg(required = true, value_parser = parse_port, value_name = "[IP:]HOST[:GUEST][/udp]")]
+    pub ports: Vec<PortArg>,
+}
+
+impl PortRmCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        vm_common::unpublish_vm_ports(KIND, &self.name, &flatten_ports(self.ports))
+    }
+}
+
 // ============================================================================
 // Network Test Command
 // ============================================================================
@@ -1,8 +1,11 @@
 //! CLI command implementations.
 
+pub mod build;
 pub mod config;
 pub mod container;
+pub mod image;
 pub mod microvm;
+pub mod network;
 pub mod openapi;
 pub mod pack;
 pub mod pack_run;
@@ -10,7 +13,9 @@ pub mod parsers;
 pub mod sandbox;
 pub mod serve;
 pub mod smolfile;
+pub mod system;
 pub mod vm_common;
+pub mod volume;
 
 use std::io::Write;
 
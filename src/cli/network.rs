@@ -0,0 +1,155 @@
+//! Private network commands.
+//!
+//! Private networks connect microVMs to each other without root or kernel
+//! bridges. VMs join one at creation with `--network NAME` and reach the
+//! other members by name through the network's embedded DNS.
+
+use crate::cli::truncate;
+use clap::{Args, Subcommand};
+use smolvm::network::private::parse_subnet;
+use smolvm::network::NetworkStore;
+use std::net::Ipv4Addr;
+
+/// Manage private networks between microVMs
+#[derive(Subcommand, Debug)]
+pub enum NetworkCmd {
+    /// Create a private network
+    Create(NetworkCreateCmd),
+
+    /// List private networks and their members
+    #[command(visible_alias = "list")]
+    Ls(NetworkLsCmd),
+
+    /// Remove a private network that no VM uses
+    #[command(visible_alias = "remove")]
+    Rm(NetworkRmCmd),
+}
+
+impl NetworkCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            NetworkCmd::Create(cmd) => cmd.run(),
+            NetworkCmd::Ls(cmd) => cmd.run(),
+            NetworkCmd::Rm(cmd) => cmd.run(),
+        }
+    }
+}
+
+/// Create a private network.
+///
+/// Without --subnet, a free 10.89.N.0/24 subnet is picked. The first
+/// address is the gateway, which answers DNS for member names (`db` or
+/// `db.<network>`) and forwards other queries upstream.
+///
+/// Examples:
+///   smolvm network create dev
+///   smolvm network create lab --subnet 172.30.0.0/24
+#[derive(Args, Debug)]
+pub struct NetworkCreateCmd {
+    /// Network name (lowercase letters, digits and '-')
+    pub name: String,
+
+    /// IPv4 subnet in CIDR notation (prefix /16 to /29)
+    #[arg(long, value_parser = parse_subnet, value_name = "CIDR")]
+    pub subnet: Option<(Ipv4Addr, u8)>,
+}
+
+impl NetworkCreateCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let network = NetworkStore::open()?.create(&self.name, self.subnet)?;
+        println!("Created network: {}", network.name);
+        println!("  Subnet: {}", network.cidr());
+        println!("  Gateway/DNS: {}", network.gateway());
+        println!(
+            "\nUse 'smolvm microvm create <name> --network {}' to attach VMs",
+            network.name
+        );
+        Ok(())
+    }
+}
+
+/// List private networks.
+#[derive(Args, Debug)]
+pub struct NetworkLsCmd {
+    /// Show member VMs and their addresses
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl NetworkLsCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let networks = NetworkStore::open()?.list()?;
+
+        if self.json {
+            let json: Vec<_> = networks
+                .iter()
+                .map(|network| {
+                    let members: Vec<_> = network
+                        .members()
+                        .into_iter()
+                        .map(|(vm, address)| serde_json::json!({ "name": vm, "address": address }))
+                        .collect();
+                    serde_json::json!({
+                        "name": network.name,
+                        "subnet": network.cidr(),
+                        "gateway": network.gateway(),
+                        "members": members,
+                        "created_at": network.created_at,
+                    })
+                })
+                .collect();
+            let json = serde_json::to_string_pretty(&json)
+                .map_err(|e| smolvm::Error::config("serialize json", e.to_string()))?;
+            println!("{}", json);
+            return Ok(());
+        }
+
+        if networks.is_empty() {
+            println!("No networks found");
+            return Ok(());
+        }
+
+        println!(
+            "{:<20} {:<20} {:<16} {:>7}",
+            "NAME", "SUBNET", "GATEWAY", "MEMBERS"
+        );
+        println!("{}", "-".repeat(66));
+        for network in &networks {
+            let members = network.members();
+            println!(
+                "{:<20} {:<20} {:<16} {:>7}",
+                truncate(&network.name, 18),
+                network.cidr(),
+                network.gateway().to_string(),
+                members.len()
+            );
+            if self.verbose {
+                for (vm, address) in &members {
+                    println!("  Member: {} ({})", vm, address);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove a private network.
+///
+/// Fails while VMs are attached; delete them first.
+#[derive(Args, Debug)]
+pub struct NetworkRmCmd {
+    /// Network name
+    pub name: String,
+}
+
+impl NetworkRmCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        NetworkStore::open()?.remove(&self.name)?;
+        println!("Removed network: {}", self.name);
+        Ok(())
+    }
+}
@@ -13,15 +13,22 @@ use smolvm::agent::{AgentClient, AgentManager, PullOptions, VmResources};
 /// Default memory for packed VMs (lower than sandbox/microvm because
 /// packed VMs are typically single-purpose, minimal workloads).
 const PACK_DEFAULT_MEMORY_MIB: u32 = 256;
+use crate::cli::parsers::{parse_egress_rule, parse_env_spec, parse_port};
+use crate::cli::smolfile::{self, PackConfig};
 use smolvm::config::{RecordState, SmolvmConfig};
 use smolvm::platform::{Arch, Os, VmExecutor};
 use smolvm::Error;
+use smolvm_pack::archive::{ArchiveImage, ImageArchive};
 use smolvm_pack::assets::AssetCollector;
-use smolvm_pack::format::{PackManifest, PackMode};
-use smolvm_pack::packer::Packer;
+use smolvm_pack::format::{parse_platforms, PackManifest, PackMode, Provenance, RunDefaults};
+use smolvm_pack::packer::{PackedFile, Packer};
+use smolvm_pack::patch::create_patch;
+use smolvm_pack::rebase::{collect_rebased, Runtime};
+use smolvm_pack::sbom;
 use smolvm_pack::signing::sign_with_hypervisor_entitlements;
-use smolvm_protocol::AgentResponse;
-use std::path::PathBuf;
+use smolvm_pack::trust::{PublicKey, SigningKey, TrustPolicy};
+use smolvm_protocol::{AgentResponse, DnsConfig, ImageInfo, ProxyConfig};
+use std::path::{Path, PathBuf};
 use tracing::{debug, info, warn};
 
 /// Package and run self-contained VM executables.
@@ -32,14 +39,1086 @@ pub enum PackCmd {
 
     /// Run a VM from a packed .smolmachine sidecar file
     Run(super::pack_run::PackRunCmd),
+
+    /// Generate an Ed25519 key pair for signing packs
+    Keygen(PackKeygenCmd),
+
+    /// Sign an existing packed binary or .smolmachine file
+    Sign(PackSignCmd),
+
+    /// Verify the checksum, asset digests and signature of a pack
+    Verify(PackVerifyCmd),
+
+    /// Create an update patch between two versions of a pack
+    Diff(PackDiffCmd),
+
+    /// Show a pack's manifest, assets, checksum and signature
+    Inspect(PackInspectCmd),
+
+    /// Export a pack's image as an OCI image layout
+    Extract(PackExtractCmd),
+
+    /// Repack an existing pack onto this smolvm's runtime
+    Rebase(PackRebaseCmd),
+}
+
+impl PackCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            PackCmd::Create(cmd) => cmd.run(),
+            PackCmd::Run(cmd) => cmd.run(),
+            PackCmd::Keygen(cmd) => cmd.run(),
+            PackCmd::Sign(cmd) => cmd.run(),
+            PackCmd::Verify(cmd) => cmd.run(),
+            PackCmd::Diff(cmd) => cmd.run(),
+            PackCmd::Inspect(cmd) => cmd.run(),
+            PackCmd::Extract(cmd) => cmd.run(),
+            PackCmd::Rebase(cmd) => cmd.run(),
+        }
+    }
+}
+
+/// Generate an Ed25519 key pair for signing packs.
+///
+/// Writes the secret key to NAME.key (mode 0600) and the public key to
+/// NAME.pub. Add the public key to the trusted keys file
+/// (smolvm/trusted-keys in the config directory, or $SMOLVM_PACK_TRUSTED_KEYS) on machines
+/// that should only run signed packs.
+///
+/// Examples:
+///   smolvm pack keygen -o release
+///   smolvm pack create myapp:latest -o myapp --sign-key release.key
+#[derive(Args, Debug)]
+pub struct PackKeygenCmd {
+    /// Output path prefix for the .key and .pub files
+    #[arg(short = 'o', long, value_name = "NAME")]
+    pub output: PathBuf,
+}
+
+impl PackKeygenCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let secret_path = self.output.with_extension("key");
+        let public_path = self.output.with_extension("pub");
+        if public_path.exists() {
+            return Err(Error::config(
+                "generate signing key",
+                format!("{} already exists", public_path.display()),
+            ));
+        }
+
+        let key = SigningKey::generate()
+            .map_err(|e| Error::config("generate signing key", e.to_string()))?;
+        key.save(&secret_path)
+            .map_err(|e| Error::config("save signing key", e.to_string()))?;
+        let public_key = key.public_key();
+        public_key
+            .save(&public_path)
+            .map_err(|e| Error::config("save public key", e.to_string()))?;
+
+        println!("Secret key: {}", secret_path.display());
+        println!("Public key: {}", public_path.display());
+        println!("Fingerprint: {}", public_key.fingerprint());
+        println!("\nTrusted keys entry:\n{}", public_key);
+        Ok(())
+    }
+}
+
+/// Sign an existing packed binary or .smolmachine file.
+///
+/// The signature covers the manifest (including per-asset SHA-256 digests)
+/// and the compressed assets. Any existing signature is replaced. The
+/// checksum, and so the extraction cache, is unchanged.
+///
+/// Single-file packs created on macOS keep their assets in a Mach-O
+/// section; sign those at creation time with `pack create --sign-key`.
+///
+/// Examples:
+///   smolvm pack sign ./myapp --key release.key
+#[derive(Args, Debug)]
+pub struct PackSignCmd {
+    /// Packed binary or .smolmachine file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Ed25519 secret key (see `smolvm pack keygen`)
+    #[arg(long, value_name = "PATH")]
+    pub key: PathBuf,
+}
+
+impl PackSignCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let key = SigningKey::load(&self.key)
+            .map_err(|e| Error::config("load signing key", e.to_string()))?;
+        let mut packed = open_packed(&self.path)?;
+        if !packed
+            .verify_checksum()
+            .map_err(|e| Error::agent("verify pack", e.to_string()))?
+        {
+            return Err(Error::agent(
+                "sign pack",
+                format!("checksum mismatch for {}", packed.path().display()),
+            ));
+        }
+
+        packed
+            .sign(&key)
+            .map_err(|e| Error::agent("sign pack", e.to_string()))?;
+        println!(
+            "Signed {} with key {}",
+            packed.path().display(),
+            key.public_key().fingerprint()
+        );
+        Ok(())
+    }
+}
+
+/// Verify the checksum, asset digests and signature of a pack.
+///
+/// With --key, the pack must be signed by that key. Otherwise the configured
+/// trust policy is used if there is one; if not, the signature is checked
+/// and the signing key reported.
+///
+/// Examples:
+///   smolvm pack verify ./myapp
+///   smolvm pack verify ./myapp --key release.pub
+#[derive(Args, Debug)]
+pub struct PackVerifyCmd {
+    /// Packed binary or .smolmachine file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Public key the pack must be signed by
+    #[arg(long, value_name = "PATH")]
+    pub key: Option<PathBuf>,
+}
+
+impl PackVerifyCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let packed = open_packed(&self.path)?;
+        let verify_err = |e: smolvm_pack::PackError| Error::agent("verify pack", e.to_string());
+
+        if !packed.verify_checksum().map_err(verify_err)? {
+            return Err(Error::agent(
+                "verify pack",
+                format!("checksum mismatch for {}", packed.path().display()),
+            ));
+        }
+        println!("Checksum:  {:08x} ok", packed.footer().checksum);
+
+        let manifest = packed.manifest().map_err(verify_err)?;
+        let checked = packed.verify_assets(&manifest).map_err(verify_err)?;
+        println!("Assets:    {} digest(s) ok", checked);
+
+        let policy = match self.key {
+            Some(ref path) => {
+                Some(TrustPolicy::single(PublicKey::load(path).map_err(|e| {
+                    Error::config("load public key", e.to_string())
+                })?))
+            }
+            None => TrustPolicy::load()
+                .map_err(|e| Error::config("load trust policy", e.to_string()))?,
+        };
+
+        match policy {
+            Some(policy) => {
+                let trusted = packed.verify_signature(&policy).map_err(verify_err)?;
+                match trusted.name {
+                    Some(ref name) => println!(
+                        "Signature: ok, trusted key {} ({})",
+                        trusted.key.fingerprint(),
+                        name
+                    ),
+                    None => println!("Signature: ok, trusted key {}", trusted.key.fingerprint()),
+                }
+            }
+            None => match packed.signature().map_err(verify_err)? {
+                Some(signature) => {
+                    let key = signature
+                        .verify(
+                            &packed.manifest_json().map_err(verify_err)?,
+                            &packed.assets_sha256().map_err(verify_err)?,
+                        )
+                        .map_err(verify_err)?;
+                    println!(
+                        "Signature: ok, key {} (no trust policy configured)",
+                        key.fingerprint()
+                    );
+                }
+                None => println!("Signature: none"),
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Create an update patch between two versions of a pack.
+///
+/// The patch carries the new manifest and only the assets (layers,
+/// libraries, agent rootfs) not already in the old pack. Users apply it
+/// with `./myapp --apply-update update.smolpatch`, which rebuilds the pack
+/// in place and refreshes its extraction cache.
+///
+/// Applying a patch rebuilds the assets. The new pack's signature is kept
+/// when the rebuild reproduces its assets exactly; otherwise pass
+/// --sign-key to sign the updated pack.
+///
+/// Examples:
+///   smolvm pack diff myapp-1.0.smolmachine myapp-1.1.smolmachine -o update.smolpatch
+///   smolvm pack diff ./myapp-1.0 ./myapp-1.1 -o update.smolpatch --sign-key release.key
+#[derive(Args, Debug)]
+pub struct PackDiffCmd {
+    /// Pack users have now (packed binary or .smolmachine file)
+    #[arg(value_name = "OLD")]
+    pub old: PathBuf,
+
+    /// Pack to update them to
+    #[arg(value_name = "NEW")]
+    pub new: PathBuf,
+
+    /// Output patch file
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: PathBuf,
+
+    /// Sign the updated pack with this Ed25519 secret key
+    #[arg(long, value_name = "PATH")]
+    pub sign_key: Option<PathBuf>,
+}
+
+impl PackDiffCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let key = self
+            .sign_key
+            .as_ref()
+            .map(|path| SigningKey::load(path))
+            .transpose()
+            .map_err(|e| Error::config("load signing key", e.to_string()))?;
+        let old = open_packed(&self.old)?;
+        let new = open_packed(&self.new)?;
+
+        let info = create_patch(&old, &new, &self.output, key.as_ref())
+            .map_err(|e| Error::agent("create patch", e.to_string()))?;
+
+        println!("Created: {}", self.output.display());
+        println!(
+            "  Base:    {:08x} -> {:08x}",
+            info.base_checksum, info.target_checksum
+        );
+        println!(
+            "  Assets:  {} reused, {} added ({})",
+            info.reused,
+            info.added,
+            crate::cli::format_bytes(info.added_size)
+        );
+        println!("  Size:    {}", crate::cli::format_bytes(info.patch_size));
+        if let Some(key) = key {
+            println!("  Signed:  {}", key.public_key().fingerprint());
+        } else if new
+            .signature()
+            .map_err(|e| Error::agent("read signature", e.to_string()))?
+            .is_some()
+        {
+            println!("  Signed:  kept from {}", new.path().display());
+        }
+        Ok(())
+    }
 }
 
-impl PackCmd {
-    pub fn run(self) -> smolvm::Result<()> {
-        match self {
-            PackCmd::Create(cmd) => cmd.run(),
-            PackCmd::Run(cmd) => cmd.run(),
+/// Show what a pack contains.
+///
+/// Prints the manifest, the size of each asset (as stored and compressed),
+/// the OCI layer digests, the pack format, and whether the checksum and
+/// signature hold. Unlike `pack verify`, a bad checksum or signature is
+/// reported rather than treated as an error, and asset digests are not
+/// checked. With `--sbom`, prints the CycloneDX SBOM of the packages in the
+/// packed image instead.
+///
+/// Examples:
+///   smolvm pack inspect ./myapp
+///   smolvm pack inspect myapp.smolmachine --json
+///   smolvm pack inspect ./myapp --sbom --platform linux/arm64
+#[derive(Args, Debug)]
+pub struct PackInspectCmd {
+    /// Packed binary or .smolmachine file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Output as JSON
+    #[arg(long, conflicts_with = "sbom")]
+    pub json: bool,
+
+    /// Print the pack's SBOM (CycloneDX JSON)
+    #[arg(long)]
+    pub sbom: bool,
+
+    /// Platform whose SBOM to print (default: the primary platform)
+    #[arg(long, value_name = "OS/ARCH", requires = "sbom")]
+    pub platform: Option<String>,
+}
+
+impl PackInspectCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let packed = open_packed(&self.path)?;
+        let inspect_err = |e: smolvm_pack::PackError| Error::agent("inspect pack", e.to_string());
+
+        let footer = *packed.footer();
+        let manifest = packed.manifest().map_err(inspect_err)?;
+        if self.sbom {
+            return print_sbom(&packed, &manifest, self.platform.as_deref());
+        }
+        let checksum_ok = packed.verify_checksum().map_err(inspect_err)?;
+        let signature = signature_status(&packed)?;
+        let layout = if smolvm_pack::packer::is_sidecar_mode(&footer) {
+            "sidecar"
+        } else {
+            "embedded"
+        };
+        let indexed = !manifest.asset_index.is_empty();
+
+        // Compressed size of each asset's frame, for indexed packs
+        let compressed: std::collections::HashMap<&str, u64> = manifest
+            .asset_index
+            .iter()
+            .map(|frame| (frame.path.as_str(), frame.size))
+            .collect();
+        let mut assets = Vec::new();
+        for platform in manifest.platform_names() {
+            let Some(inventory) = manifest.inventory_for(platform) else {
+                continue;
+            };
+            let files = inventory
+                .libraries
+                .iter()
+                .chain(std::iter::once(&inventory.agent_rootfs))
+                .chain(inventory.storage_template.iter())
+                .chain(inventory.overlay_template.iter())
+                .chain(inventory.sbom.iter())
+                .map(|a| (a.path.as_str(), a.size, None))
+                .chain(
+                    inventory
+                        .layers
+                        .iter()
+                        .map(|l| (l.path.as_str(), l.size, Some(l))),
+                );
+            for (path, size, layer) in files {
+                assets.push((platform, path, size, compressed.get(path).copied(), layer));
+            }
+        }
+
+        if self.json {
+            let output = serde_json::json!({
+                "path": packed.path(),
+                "format": {
+                    "version": smolvm_pack::format::FORMAT_VERSION,
+                    "layout": layout,
+                    "indexed": indexed,
+                },
+                "checksum": {
+                    "value": format!("{:08x}", footer.checksum),
+                    "ok": checksum_ok,
+                },
+                "signature": signature.to_json(),
+                "assets_size": footer.assets_size,
+                "assets": assets.iter().map(|(platform, path, size, compressed, layer)| {
+                    serde_json::json!({
+                        "platform": platform,
+                        "path": path,
+                        "size": size,
+                        "compressed_size": compressed,
+                        "digest": layer.map(|l| &l.digest),
+                    })
+                }).collect::<Vec<_>>(),
+                "manifest": manifest,
+            });
+            let json = serde_json::to_string_pretty(&output)
+                .map_err(|e| Error::config("serialize json", e.to_string()))?;
+            println!("{}", json);
+            return Ok(());
+        }
+
+        println!("Pack:       {}", packed.path().display());
+        println!(
+            "Format:     v{} {}, {}",
+            smolvm_pack::format::FORMAT_VERSION,
+            layout,
+            if indexed {
+                "indexed assets"
+            } else {
+                "single asset stream"
+            }
+        );
+        println!(
+            "Checksum:   {:08x} {}",
+            footer.checksum,
+            if checksum_ok { "ok" } else { "MISMATCH" }
+        );
+        println!("Signature:  {}", signature);
+        println!();
+        super::pack_run::print_manifest(&manifest);
+        println!();
+        println!(
+            "Assets ({} compressed):",
+            crate::cli::format_bytes(footer.assets_size)
+        );
+        for (platform, path, size, compressed, _) in &assets {
+            let compressed = compressed
+                .map(|c| format!(" ({} compressed)", crate::cli::format_bytes(c)))
+                .unwrap_or_default();
+            let platform = if manifest.is_multi_platform() {
+                format!("[{}] ", platform)
+            } else {
+                String::new()
+            };
+            println!(
+                "  {}{:<40} {}{}",
+                platform,
+                path,
+                crate::cli::format_bytes(*size),
+                compressed
+            );
+        }
+        let layers: Vec<_> = assets.iter().filter_map(|a| a.4).collect();
+        if !layers.is_empty() {
+            println!();
+            println!("Layers:");
+            for layer in layers {
+                println!("  {}  {}", layer.digest, layer.path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the SBOM of one platform of a pack, for `pack inspect --sbom`.
+fn print_sbom(
+    packed: &PackedFile,
+    manifest: &PackManifest,
+    platform: Option<&str>,
+) -> smolvm::Result<()> {
+    let platform = match platform {
+        Some(platform) => parse_platforms(platform)
+            .map_err(|e| Error::config("--platform", e.to_string()))?
+            .remove(0),
+        None => manifest.platform.clone(),
+    };
+    let inventory = manifest.inventory_for(&platform).ok_or_else(|| {
+        Error::config(
+            "--platform",
+            format!(
+                "pack has no {} platform (has {})",
+                platform,
+                manifest.platform_names().join(", ")
+            ),
+        )
+    })?;
+    let document = sbom::read_sbom(packed, inventory)
+        .map_err(|e| Error::agent("read sbom", e.to_string()))?
+        .ok_or_else(|| {
+            Error::agent(
+                "read sbom",
+                format!(
+                    "{} has no SBOM for {}; packs made from a VM or by older versions do not carry one",
+                    packed.path().display(),
+                    platform
+                ),
+            )
+        })?;
+    std::io::Write::write_all(&mut std::io::stdout(), &document)
+        .map_err(|e| Error::agent("print sbom", e.to_string()))?;
+    println!();
+    Ok(())
+}
+
+/// What is known about a pack's signature, for `pack inspect`.
+enum SignatureStatus {
+    Unsigned,
+    Invalid(String),
+    /// Valid, with the signer's fingerprint and whether the trust policy
+    /// accepts it (`None` without a policy).
+    Valid {
+        fingerprint: String,
+        trusted: Option<Result<Option<String>, String>>,
+    },
+}
+
+impl SignatureStatus {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            SignatureStatus::Unsigned => serde_json::json!({ "status": "unsigned" }),
+            SignatureStatus::Invalid(reason) => {
+                serde_json::json!({ "status": "invalid", "error": reason })
+            }
+            SignatureStatus::Valid {
+                fingerprint,
+                trusted,
+            } => {
+                let mut json = serde_json::json!({ "status": "valid", "key": fingerprint });
+                match trusted {
+                    Some(Ok(name)) => {
+                        json["trusted"] = true.into();
+                        json["name"] = name.clone().into();
+                    }
+                    Some(Err(reason)) => {
+                        json["trusted"] = false.into();
+                        json["error"] = reason.clone().into();
+                    }
+                    None => {}
+                }
+                json
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Unsigned => write!(f, "none"),
+            SignatureStatus::Invalid(reason) => write!(f, "INVALID ({})", reason),
+            SignatureStatus::Valid {
+                fingerprint,
+                trusted,
+            } => match trusted {
+                Some(Ok(Some(name))) => write!(f, "ok, trusted key {} ({})", fingerprint, name),
+                Some(Ok(None)) => write!(f, "ok, trusted key {}", fingerprint),
+                Some(Err(reason)) => write!(f, "ok, key {} NOT TRUSTED ({})", fingerprint, reason),
+                None => write!(f, "ok, key {} (no trust policy configured)", fingerprint),
+            },
+        }
+    }
+}
+
+/// Check a pack's signature, and the trust policy if one is configured.
+fn signature_status(packed: &PackedFile) -> smolvm::Result<SignatureStatus> {
+    let read_err = |e: smolvm_pack::PackError| Error::agent("read signature", e.to_string());
+    let Some(signature) = packed.signature().map_err(read_err)? else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+    let key = match signature.verify(
+        &packed.manifest_json().map_err(read_err)?,
+        &packed.assets_sha256().map_err(read_err)?,
+    ) {
+        Ok(key) => key,
+        Err(e) => return Ok(SignatureStatus::Invalid(e.to_string())),
+    };
+    let policy =
+        TrustPolicy::load().map_err(|e| Error::config("load trust policy", e.to_string()))?;
+    let trusted = policy.map(|policy| {
+        packed
+            .verify_signature(&policy)
+            .map(|trusted| trusted.name.clone())
+            .map_err(|e| e.to_string())
+    });
+    Ok(SignatureStatus::Valid {
+        fingerprint: key.fingerprint(),
+        trusted,
+    })
+}
+
+/// Export a pack's image as an OCI image layout.
+///
+/// Writes the pack's layers, an image config rebuilt from its manifest,
+/// and an index with one image manifest per platform. The layout can be
+/// loaded elsewhere, e.g. with `skopeo copy oci:DIR ...`, or packed again
+/// with `smolvm pack create oci-layout:DIR`. VM snapshot packs have no
+/// image to export.
+///
+/// Examples:
+///   smolvm pack extract ./myapp -o myapp-oci
+#[derive(Args, Debug)]
+pub struct PackExtractCmd {
+    /// Packed binary or .smolmachine file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Directory to write the layout to (must be empty or not exist)
+    #[arg(short = 'o', long = "output", value_name = "DIR")]
+    pub output: PathBuf,
+}
+
+impl PackExtractCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let packed = open_packed(&self.path)?;
+        let info = smolvm_pack::oci::write_layout(&packed, &self.output)
+            .map_err(|e| Error::agent("export OCI layout", e.to_string()))?;
+
+        println!("Exported: {}", self.output.display());
+        println!(
+            "  Layers:  {} ({})",
+            info.layers,
+            crate::cli::format_bytes(info.layers_size)
+        );
+        for (digest, platform) in &info.manifests {
+            println!("  {}: {}", platform, digest);
+        }
+        Ok(())
+    }
+}
+
+/// Repack an existing pack onto this smolvm's stub, libraries and agent.
+///
+/// Packed binaries keep the runtime they were made with. Rebasing reuses
+/// the pack's layers and disk templates as they are, so neither the
+/// original image nor registry access is needed, and writes a new pack
+/// with the current runtime. The defaults baked into the manifest are kept
+/// unless overridden here. A single-file pack stays single-file.
+///
+/// The old signature does not cover the new assets; pass --sign-key to
+/// sign the rebased pack.
+///
+/// Examples:
+///   smolvm pack rebase ./myapp -o ./myapp-new
+///   smolvm pack rebase ./myapp -o ./myapp-new --mem 1024 --sign-key release.key
+#[derive(Args, Debug)]
+pub struct PackRebaseCmd {
+    /// Packed binary or .smolmachine file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Output file path for the rebased binary
+    #[arg(short = 'o', long, value_name = "PATH")]
+    pub output: PathBuf,
+
+    /// Change the default number of vCPUs
+    #[arg(long, value_name = "N")]
+    pub cpus: Option<u8>,
+
+    /// Change the default memory in MiB
+    #[arg(long, value_name = "MiB")]
+    pub mem: Option<u32>,
+
+    /// Change the entrypoint
+    #[arg(long, value_name = "CMD")]
+    pub entrypoint: Option<String>,
+
+    #[command(flatten)]
+    pub runtime: RuntimeArgs,
+
+    /// Skip code signing (macOS only)
+    #[arg(long)]
+    pub no_sign: bool,
+
+    /// Sign the pack with this Ed25519 key (see `smolvm pack keygen`)
+    #[arg(long = "sign-key", value_name = "PATH")]
+    pub sign_key: Option<PathBuf>,
+
+    /// Write byte-identical output for the same inputs (see `pack create`)
+    #[arg(long)]
+    pub reproducible: bool,
+}
+
+impl PackRebaseCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let packed = open_packed(&self.path)?;
+        let rebase_err = |e: smolvm_pack::PackError| Error::agent("rebase pack", e.to_string());
+        let single_file = !smolvm_pack::packer::is_sidecar_mode(packed.footer());
+        let signed = packed.signature().map_err(rebase_err)?.is_some();
+        let manifest = packed.manifest().map_err(rebase_err)?;
+        let multi_platform = manifest.is_multi_platform();
+
+        // Like `pack create`, a single-platform pack gets this host's
+        // runtime whatever its image's platform
+        let mut runtimes = std::collections::HashMap::new();
+        for platform in manifest.platform_names() {
+            let (lib_dir, rootfs_dir) = if multi_platform {
+                let os_arch: Vec<&str> = platform.splitn(3, '/').take(2).collect();
+                self.runtime.runtime_dirs(&os_arch.join("/"))?
+            } else {
+                (
+                    self.runtime.find_lib_dir()?,
+                    self.runtime.find_rootfs_dir()?,
+                )
+            };
+            runtimes.insert(
+                platform.to_string(),
+                Runtime {
+                    lib_dir,
+                    rootfs_dir,
+                },
+            );
+        }
+
+        let temp_dir = tempfile::tempdir()
+            .map_err(|e| Error::agent("create temp directory", e.to_string()))?;
+        let mut collector = new_collector(temp_dir.path().join("staging"), self.reproducible)?;
+
+        println!("Rebasing {}...", packed.path().display());
+        let mut manifest = collect_rebased(&packed, &mut collector, |platform| {
+            Ok(runtimes[platform].clone())
+        })
+        .map_err(rebase_err)?;
+        println!(
+            "Kept {} layers",
+            manifest
+                .platform_names()
+                .iter()
+                .filter_map(|p| manifest.inventory_for(p))
+                .flat_map(|inventory| inventory.layers.iter().map(|l| &l.digest))
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        );
+
+        if let Some(cpus) = self.cpus {
+            manifest.cpus = cpus;
+        }
+        if let Some(mem) = self.mem {
+            manifest.mem = mem;
+        }
+        if let Some(ref ep) = self.entrypoint {
+            manifest.entrypoint = vec![ep.clone()];
+        }
+        // The image sources stay; the runtime and pack time are new
+        if let Some(ref mut provenance) = manifest.provenance {
+            let rebased = new_provenance(self.reproducible)?;
+            provenance.smolvm_version = rebased.smolvm_version;
+            provenance.created = rebased.created;
+        }
+        let platforms = multi_platform.then(|| manifest.platform_names().join(", "));
+
+        if signed && self.sign_key.is_none() {
+            eprintln!(
+                "Warning: {} is signed, but its signature does not cover the new runtime. \
+                 Pass --sign-key to sign the rebased pack.",
+                packed.path().display()
+            );
+        }
+
+        let stub_path = self.runtime.find_smolvm_binary()?;
+        let packer = Packer::new(manifest)
+            .with_stub(&stub_path)
+            .with_assets(collector);
+        write_packed(
+            packer,
+            &self.output,
+            self.sign_key.as_deref(),
+            single_file,
+            self.no_sign,
+            platforms,
+        )
+    }
+}
+
+/// Parse a `--runtime-dir OS/ARCH=DIR` value.
+fn parse_runtime_dir(s: &str) -> Result<(String, PathBuf), String> {
+    let (platform, dir) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected OS/ARCH=DIR, got '{}'", s))?;
+    let platform = parse_platforms(platform)
+        .map_err(|e| e.to_string())?
+        .remove(0);
+    Ok((platform, PathBuf::from(dir)))
+}
+
+/// Describe an image read from a local archive the way the agent
+/// describes a pulled one.
+fn archive_image_info(reference: &str, image: &ArchiveImage) -> ImageInfo {
+    ImageInfo {
+        reference: reference.to_string(),
+        digest: image.digest.clone(),
+        size: image.size(),
+        created: None,
+        architecture: image.architecture.clone(),
+        os: image.os.clone(),
+        layer_count: image.layers.len(),
+        layers: image.layers.iter().map(|l| l.digest.clone()).collect(),
+        entrypoint: image.entrypoint.clone(),
+        cmd: image.cmd.clone(),
+        env: image.env.clone(),
+        workdir: image.workdir.clone(),
+    }
+}
+
+/// Create the asset collector for a pack, staging reproducibly as of
+/// `SOURCE_DATE_EPOCH` (or the Unix epoch when unset) if `reproducible`.
+fn new_collector(staging_dir: PathBuf, reproducible: bool) -> smolvm::Result<AssetCollector> {
+    let collector = AssetCollector::new(staging_dir)
+        .map_err(|e| Error::agent("collect assets", e.to_string()))?;
+    Ok(match source_date_epoch(reproducible)? {
+        Some(epoch) => collector.with_source_date_epoch(epoch),
+        None => collector,
+    })
+}
+
+/// Build provenance for a pack made now, or as of `SOURCE_DATE_EPOCH` if
+/// `reproducible`. Image digests are added per platform as they are read.
+fn new_provenance(reproducible: bool) -> smolvm::Result<Provenance> {
+    let created = match source_date_epoch(reproducible)? {
+        Some(epoch) => std::time::UNIX_EPOCH + std::time::Duration::from_secs(epoch),
+        None => std::time::SystemTime::now(),
+    };
+    Ok(Provenance {
+        smolvm_version: smolvm::VERSION.to_string(),
+        created: humantime::format_rfc3339_seconds(created).to_string(),
+        sources: Default::default(),
+    })
+}
+
+/// The time reproducible packs are made as of: `SOURCE_DATE_EPOCH`, or the
+/// Unix epoch when unset. `None` unless `reproducible`.
+fn source_date_epoch(reproducible: bool) -> smolvm::Result<Option<u64>> {
+    if !reproducible {
+        return Ok(None);
+    }
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| {
+            Error::config(
+                "SOURCE_DATE_EPOCH",
+                format!("expected seconds since the Unix epoch, got '{}'", value),
+            )
+        }),
+        Err(_) => Ok(Some(0)),
+    }
+}
+
+/// Open a pack for the commands that read an existing one.
+fn open_packed(path: &std::path::Path) -> smolvm::Result<PackedFile> {
+    PackedFile::open(path).map_err(|e| {
+        Error::agent(
+            "open pack",
+            format!(
+                "{}: not a packed binary or .smolmachine file ({})",
+                path.display(),
+                e
+            ),
+        )
+    })
+}
+
+/// Write a pack, signing it with `sign_key` if given, then print a summary
+/// and sign the binary for Hypervisor.framework on macOS.
+fn write_packed(
+    mut packer: Packer,
+    output: &Path,
+    sign_key: Option<&Path>,
+    single_file: bool,
+    no_sign: bool,
+    platforms: Option<String>,
+) -> smolvm::Result<()> {
+    if let Some(path) = sign_key {
+        let key =
+            SigningKey::load(path).map_err(|e| Error::config("load signing key", e.to_string()))?;
+        println!("Signing pack with key {}", key.public_key().fingerprint());
+        packer = packer.with_signing_key(key);
+    }
+
+    let info = if single_file {
+        println!("Assembling single-file packed binary...");
+        packer
+            .pack_embedded(output)
+            .map_err(|e| Error::agent("pack binary", e.to_string()))?
+    } else {
+        println!("Assembling packed binary...");
+        packer
+            .pack(output)
+            .map_err(|e| Error::agent("pack binary", e.to_string()))?
+    };
+
+    println!(
+        "Packed: {} (stub: {}KB, total: {}KB)",
+        output.display(),
+        info.stub_size / 1024,
+        info.total_size / 1024
+    );
+    if let Some(ref sidecar) = info.sidecar_path {
+        println!(
+            "Assets: {} ({}KB compressed)",
+            sidecar.display(),
+            info.assets_size / 1024
+        );
+        if let Some(platforms) = platforms {
+            println!("Platforms: {}", platforms);
+            println!(
+                "Note: The binary is this host's smolvm; for other architectures, \
+                 put that architecture's smolvm binary next to the .smolmachine file \
+                 under the same name"
+            );
+        }
+    } else {
+        println!("Mode: single-file (no sidecar)");
+    }
+
+    // Sign on macOS
+    if Os::current().is_macos() && !no_sign {
+        println!("Signing binary with hypervisor entitlements...");
+        if let Err(e) = sign_with_hypervisor_entitlements(output) {
+            warn!(error = %e, "signing failed (binary may not run on fresh macOS)");
+            eprintln!("Warning: Signing failed: {}", e);
+            eprintln!("The binary may require manual signing to use Hypervisor.framework");
+        } else {
+            println!("Signed successfully");
+        }
+    }
+
+    println!("\nRun with: {}", output.display());
+    if info.sidecar_path.is_some() {
+        println!("Note: Keep the .smolmachine file alongside the binary");
+    }
+    println!("Options: --help for usage");
+
+    Ok(())
+}
+
+/// Where `pack create` and `pack rebase` find the runtime they pack.
+#[derive(Args, Debug, Default)]
+pub struct RuntimeArgs {
+    /// smolvm distribution (with lib/ and agent-rootfs/) for a platform
+    /// other than the host's, for multi-platform packs
+    #[arg(long = "runtime-dir", value_name = "OS/ARCH=DIR", value_parser = parse_runtime_dir)]
+    pub runtime_dirs: Vec<(String, PathBuf)>,
+
+    /// Path to stub executable (defaults to built-in)
+    #[arg(long, value_name = "PATH", hide = true)]
+    pub stub: Option<PathBuf>,
+
+    /// Path to library directory containing libkrun and libkrunfw
+    #[arg(long, value_name = "DIR", hide = true)]
+    pub lib_dir: Option<PathBuf>,
+
+    /// Path to agent rootfs directory
+    #[arg(long, value_name = "DIR", hide = true)]
+    pub rootfs_dir: Option<PathBuf>,
+}
+
+impl RuntimeArgs {
+    /// Library and agent rootfs directories for one platform of a
+    /// multi-platform pack: the `--runtime-dir` given for it, else this
+    /// host's own runtime if the architecture matches.
+    fn runtime_dirs(&self, platform: &str) -> smolvm::Result<(PathBuf, PathBuf)> {
+        if let Some((_, dir)) = self.runtime_dirs.iter().find(|(p, _)| p == platform) {
+            return Ok((dir.join("lib"), dir.join("agent-rootfs")));
+        }
+        if platform == format!("linux/{}", Arch::current().oci_arch()) {
+            return Ok((self.find_lib_dir()?, self.find_rootfs_dir()?));
+        }
+        Err(Error::agent(
+            "find runtime",
+            format!(
+                "no runtime for {}. Use --runtime-dir {}=DIR with a smolvm distribution \
+                 for that architecture.",
+                platform, platform
+            ),
+        ))
+    }
+
+    /// Find the library directory containing libkrun and libkrunfw.
+    fn find_lib_dir(&self) -> smolvm::Result<PathBuf> {
+        if let Some(ref dir) = self.lib_dir {
+            return Ok(dir.clone());
+        }
+
+        // Check common locations
+        let platform_lib = format!("lib/linux-{}", std::env::consts::ARCH);
+        let candidates = [
+            // Relative to executable
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|d| d.join("lib"))),
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().and_then(|d| d.parent()).map(|d| d.join("lib"))),
+            // Source tree dev builds: <exe_dir>/../../lib/linux-<arch>/
+            std::env::current_exe().ok().and_then(|p| {
+                p.parent()
+                    .and_then(|d| d.parent())
+                    .map(|d| d.join(&platform_lib))
+            }),
+            // Source tree (CWD)
+            Some(PathBuf::from("lib")),
+            Some(PathBuf::from("./lib")),
+            Some(PathBuf::from(&platform_lib)),
+            // Homebrew
+            Some(PathBuf::from("/opt/homebrew/lib")),
+            Some(PathBuf::from("/usr/local/lib")),
+        ];
+
+        let lib_name = format!(
+            "libkrun.{}",
+            smolvm::platform::vm_executor().dylib_extension()
+        );
+
+        for candidate in candidates.into_iter().flatten() {
+            if candidate.join(&lib_name).exists() {
+                debug!(lib_dir = %candidate.display(), "found library directory");
+                return Ok(candidate);
+            }
+        }
+
+        Err(Error::agent(
+            "find libkrun",
+            "could not find libkrun library. Use --lib-dir to specify the location.",
+        ))
+    }
+
+    /// Find the agent rootfs directory.
+    fn find_rootfs_dir(&self) -> smolvm::Result<PathBuf> {
+        if let Some(ref dir) = self.rootfs_dir {
+            return Ok(dir.clone());
+        }
+
+        // Check common locations
+        let candidates = [
+            // Build output
+            Some(PathBuf::from("target/agent-rootfs/rootfs")),
+            // Distribution
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|d| d.join("agent-rootfs"))),
+            // User data dir
+            dirs::data_dir().map(|d| d.join("smolvm/agent-rootfs")),
+        ];
+
+        for candidate in candidates.into_iter().flatten() {
+            // Use symlink_metadata instead of exists() because sbin/init
+            // is a symlink to a guest-only path (/usr/local/bin/smolvm-agent)
+            // that doesn't exist on the host. exists() follows symlinks and
+            // returns false for broken symlinks.
+            if std::fs::symlink_metadata(candidate.join("sbin/init")).is_ok() {
+                debug!(rootfs_dir = %candidate.display(), "found agent rootfs");
+                return Ok(candidate);
+            }
+        }
+
+        Err(Error::agent(
+            "find agent rootfs",
+            "could not find agent rootfs. Use --rootfs-dir to specify the location.",
+        ))
+    }
+
+    /// Find the smolvm binary to embed as the packed runtime.
+    ///
+    /// The main smolvm binary auto-detects packed mode at startup, so it
+    /// serves as both the normal CLI and the packed binary runtime.
+    fn find_smolvm_binary(&self) -> smolvm::Result<PathBuf> {
+        if let Some(ref path) = self.stub {
+            return Ok(path.clone());
+        }
+
+        let candidates = [
+            // Build output
+            Some(PathBuf::from("target/release/smolvm")),
+            Some(PathBuf::from("target/debug/smolvm")),
+            // Distribution layout: smolvm-bin next to the wrapper script
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|d| d.join("smolvm-bin"))),
+            // The running executable itself
+            std::env::current_exe().ok(),
+            // User data dir
+            dirs::data_dir().map(|d| d.join("smolvm/smolvm-bin")),
+        ];
+
+        for candidate in candidates.into_iter().flatten() {
+            if candidate.exists() {
+                debug!(stub = %candidate.display(), "found smolvm binary for packing");
+                return Ok(candidate);
+            }
         }
+
+        Err(Error::agent(
+            "find smolvm binary",
+            "could not find smolvm binary. Build it with:\n  \
+             cargo build --release\n\
+             Or use --stub to specify the path.",
+        ))
     }
 }
 
@@ -57,12 +1136,26 @@ impl PackCmd {
 ///   smolvm pack create python:3.11-slim -o my-python --cpus 2 --mem 1024
 ///   smolvm pack create myapp:latest -o myapp --entrypoint /app/run.sh
 ///   smolvm pack create --from-vm myvm -o my-devenv
+///   smolvm pack create -f smolpack.toml
+///   smolvm pack create oci-layout:./out -o myapp
+///   smolvm pack create docker-archive:./img.tar:myapp:latest -o myapp
+///
+/// oci-layout:PATH and docker-archive:PATH read an OCI image layout or a
+/// `docker save` archive (a directory or tarball) on the host, without a
+/// registry or the agent VM. Add :NAME to pick one image from an archive
+/// holding several.
+///
+/// A pack configuration file is a Smolfile whose run settings (env,
+/// workdir, volumes, ports, net, init, storage, overlay, cpus, memory)
+/// become the packed binary's defaults, plus a [pack] table with name,
+/// version, image, sign_key and platforms. Command-line flags override it.
 #[derive(Args, Debug)]
 pub struct PackCreateCmd {
-    /// Container image to pack (e.g., alpine:latest, python:3.11-slim)
+    /// Container image to pack (e.g., alpine:latest, python:3.11-slim,
+    /// oci-layout:./out, docker-archive:./img.tar)
     #[arg(
         value_name = "IMAGE",
-        required_unless_present = "from_vm",
+        required_unless_present_any = ["from_vm", "file"],
         conflicts_with = "from_vm"
     )]
     pub image: Option<String>,
@@ -71,9 +1164,18 @@ pub struct PackCreateCmd {
     #[arg(long = "from-vm", value_name = "VM_NAME")]
     pub from_vm: Option<String>,
 
-    /// Output file path for the packed binary
-    #[arg(short = 'o', long, value_name = "PATH")]
-    pub output: PathBuf,
+    /// Output file path for the packed binary (default: the configured name)
+    #[arg(
+        short = 'o',
+        long,
+        value_name = "PATH",
+        required_unless_present = "file"
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Pack configuration file (Smolfile schema plus a [pack] table)
+    #[arg(short = 'f', long = "file", value_name = "PATH")]
+    pub file: Option<PathBuf>,
 
     /// Default number of vCPUs for the packed VM
     #[arg(long, default_value_t = smolvm::agent::DEFAULT_CPUS, value_name = "N")]
@@ -88,9 +1190,17 @@ pub struct PackCreateCmd {
     ///
     /// By default, uses the host architecture. Use this to override, for example
     /// to pack x86_64 images for Rosetta on Apple Silicon.
-    #[arg(long = "oci-platform", value_name = "OS/ARCH")]
+    ///
+    /// A comma-separated list (linux/amd64,linux/arm64) creates one sidecar
+    /// holding each platform's layers, libraries and agent rootfs; packed
+    /// binaries pick the set for their host. Layers shared by several
+    /// platforms are stored once.
+    #[arg(long = "oci-platform", value_name = "OS/ARCH[,...]")]
     pub oci_platform: Option<String>,
 
+    #[command(flatten)]
+    pub runtime: RuntimeArgs,
+
     /// Override the image entrypoint
     #[arg(long, value_name = "CMD")]
     pub entrypoint: Option<String>,
@@ -106,28 +1216,62 @@ pub struct PackCreateCmd {
     #[arg(long)]
     pub single_file: bool,
 
-    /// Path to stub executable (defaults to built-in)
-    #[arg(long, value_name = "PATH", hide = true)]
-    pub stub: Option<PathBuf>,
+    /// Sign the pack with this Ed25519 key (see `smolvm pack keygen`)
+    #[arg(long = "sign-key", value_name = "PATH")]
+    pub sign_key: Option<PathBuf>,
 
-    /// Path to library directory containing libkrun and libkrunfw
-    #[arg(long, value_name = "DIR", hide = true)]
-    pub lib_dir: Option<PathBuf>,
+    /// Write byte-identical output for the same image and runtime
+    ///
+    /// Sorts the agent rootfs and layer tarballs, clamps their timestamps
+    /// to SOURCE_DATE_EPOCH (default 0) and formats the storage template
+    /// with a fixed UUID and clock.
+    #[arg(long)]
+    pub reproducible: bool,
 
-    /// Path to agent rootfs directory
-    #[arg(long, value_name = "DIR", hide = true)]
-    pub rootfs_dir: Option<PathBuf>,
+    /// Settings loaded from --file
+    #[arg(skip)]
+    config: Box<PackConfig>,
 }
 
 impl PackCreateCmd {
-    pub fn run(self) -> smolvm::Result<()> {
+    pub fn run(mut self) -> smolvm::Result<()> {
+        self.load_config()?;
+
         if let Some(vm_name) = self.from_vm.clone() {
-            info!(vm = %vm_name, output = %self.output.display(), "packing from VM");
+            info!(vm = %vm_name, output = %self.output().display(), "packing from VM");
             return self.pack_from_vm(vm_name);
         }
 
         let image = self.image.clone().unwrap();
-        info!(image = %image, output = %self.output.display(), "packing image");
+        info!(image = %image, output = %self.output().display(), "packing image");
+
+        // Local archives are read on the host; only registry images need
+        // the agent VM to pull them
+        let archive = ImageArchive::open(&image)
+            .map_err(|e| Error::config("open image archive", e.to_string()))?;
+
+        let platforms: Vec<Option<String>> = match self.oci_platform {
+            Some(ref spec) => parse_platforms(spec)
+                .map_err(|e| Error::config("--oci-platform", e.to_string()))?
+                .into_iter()
+                .map(Some)
+                .collect(),
+            None => vec![None],
+        };
+        let multi_platform = platforms.len() > 1;
+        if multi_platform && self.single_file {
+            return Err(Error::config(
+                "--oci-platform",
+                "multi-platform packs need a sidecar, since the binary itself runs on one architecture",
+            ));
+        }
+        if multi_platform {
+            // Fail before pulling anything if a runtime is missing
+            for platform in platforms.iter().flatten() {
+                let os_arch: Vec<&str> = platform.splitn(3, '/').take(2).collect();
+                self.runtime.runtime_dirs(&os_arch.join("/"))?;
+            }
+        }
 
         // Create temporary staging directory
         let temp_dir = tempfile::tempdir()
@@ -197,66 +1341,162 @@ impl PackCreateCmd {
             }
         }
 
-        println!("Starting agent VM...");
-        let manager = AgentManager::for_vm(&pack_vm_name)?;
-        manager.start_with_config(
-            Vec::new(),
-            VmResources {
-                cpus: 2,
-                mem: 512,
-                network: true,
-                storage_gb: None,
-                overlay_gb: None,
-            },
-        )?;
-        let mut guard = PackVmGuard {
-            manager,
-            data_dir: vm_data_dir,
-            finalized: false,
-        };
-        let mut client = guard.manager.connect()?;
-
-        // Pull image
-        println!("Pulling {}...", image);
-        let mut pull_opts = PullOptions::new().use_registry_config(true);
-        if let Some(ref oci_platform) = self.oci_platform {
-            pull_opts = pull_opts.oci_platform(oci_platform);
+        let mut agent = None;
+        if archive.is_none() {
+            println!("Starting agent VM...");
+            let manager = AgentManager::for_vm(&pack_vm_name)?;
+            manager.start_with_config(
+                Vec::new(),
+                VmResources {
+                    cpus: 2,
+                    mem: 512,
+                    network: true,
+                    storage_gb: None,
+                    overlay_gb: None,
+                    network_allow: Vec::new(),
+                    proxy: None,
+                    dns: None,
+                    private_network: None,
+                },
+            )?;
+            let guard = PackVmGuard {
+                manager,
+                data_dir: vm_data_dir,
+                finalized: false,
+            };
+            let client = guard.manager.connect()?;
+            agent = Some((guard, client));
         }
-        let image_info = client.pull(&image, pull_opts)?;
-        debug!(image_info = ?image_info, "image pulled");
-
-        println!(
-            "Image: {} ({} layers, {} bytes)",
-            image, image_info.layer_count, image_info.size
-        );
 
-        // Create asset collector and collect base assets
-        let mut collector = AssetCollector::new(staging_dir.clone())
-            .map_err(|e| Error::agent("collect assets", e.to_string()))?;
-        self.collect_base_assets(&mut collector)?;
+        let mut collector = new_collector(staging_dir.clone(), self.reproducible)?;
+        let mut provenance = new_provenance(self.reproducible)?;
+        let mut primary_info = None;
+        for oci_platform in &platforms {
+            let local_image = match archive {
+                Some(ref archive) => {
+                    let platform = oci_platform
+                        .clone()
+                        .unwrap_or_else(|| format!("linux/{}", Arch::current().oci_arch()));
+                    println!("Reading {} for {}...", image, platform);
+                    Some(
+                        archive
+                            .image(&platform)
+                            .map_err(|e| Error::agent("read image archive", e.to_string()))?,
+                    )
+                }
+                None => None,
+            };
+            let image_info = match local_image {
+                Some(ref local_image) => archive_image_info(&image, local_image),
+                None => {
+                    let (_, client) = agent
+                        .as_mut()
+                        .expect("agent VM started for registry images");
+
+                    // Pull image
+                    match oci_platform {
+                        Some(platform) if multi_platform => {
+                            println!("Pulling {} for {}...", image, platform)
+                        }
+                        _ => println!("Pulling {}...", image),
+                    }
+                    let mut pull_opts = PullOptions::new().use_registry_config(true);
+                    if let Some(ref oci_platform) = oci_platform {
+                        pull_opts = pull_opts.oci_platform(oci_platform);
+                    }
+                    let image_info = client.pull(&image, pull_opts)?;
+                    debug!(image_info = ?image_info, "image pulled");
+                    image_info
+                }
+            };
 
-        // Export and collect layers
-        println!("Exporting {} layers...", image_info.layer_count);
-        for (i, layer_digest) in image_info.layers.iter().enumerate() {
             println!(
-                "  Layer {}/{}: {}...",
-                i + 1,
-                image_info.layer_count,
-                &layer_digest[..19]
+                "Image: {} ({} layers, {} bytes)",
+                image, image_info.layer_count, image_info.size
             );
 
-            // Export layer via agent
-            let layer_data = self.export_layer(&mut client, &image_info.digest, i)?;
+            // Collect the runtime for this platform. Only the primary one
+            // carries the storage template; the others share it.
+            if !multi_platform {
+                self.collect_base_assets(&mut collector)?;
+            } else {
+                let platform = format!("{}/{}", image_info.os, image_info.architecture);
+                let requested = oci_platform.as_deref().unwrap_or_default();
+                if !requested.starts_with(&platform) {
+                    return Err(Error::agent(
+                        "pull image",
+                        format!("{} has no {} variant (got {})", image, requested, platform),
+                    ));
+                }
+                let (lib_dir, rootfs_dir) = self.runtime.runtime_dirs(&platform)?;
+                if primary_info.is_some() {
+                    collector
+                        .begin_platform(&platform)
+                        .map_err(|e| Error::agent("collect assets", e.to_string()))?;
+                }
+                self.collect_runtime(&mut collector, &lib_dir, &rootfs_dir)?;
+                if primary_info.is_none() {
+                    self.collect_storage_template(&mut collector)?;
+                }
+            }
+
+            if let Some(ref local_image) = local_image {
+                println!("Collecting {} layers...", image_info.layer_count);
+                local_image
+                    .add_layers(&mut collector)
+                    .map_err(|e| Error::agent("collect layers", e.to_string()))?;
+            } else {
+                let (_, client) = agent
+                    .as_mut()
+                    .expect("agent VM started for registry images");
+
+                // Export and collect layers
+                println!("Exporting {} layers...", image_info.layer_count);
+                for (i, layer_digest) in image_info.layers.iter().enumerate() {
+                    println!(
+                        "  Layer {}/{}: {}...",
+                        i + 1,
+                        image_info.layer_count,
+                        &layer_digest[..19]
+                    );
+
+                    // Export layer via agent
+                    let layer_data = self.export_layer(client, &image_info.digest, i)?;
+
+                    // Add to collector
+                    collector
+                        .add_layer(layer_digest, &layer_data)
+                        .map_err(|e| Error::agent("collect layers", e.to_string()))?;
+                }
+            }
+
+            // Record what went into this platform and what its layers hold
+            let platform = format!("{}/{}", image_info.os, image_info.architecture);
+            provenance
+                .sources
+                .insert(platform.clone(), image_info.digest.clone());
+            let scan = sbom::collect_sbom(
+                &mut collector,
+                &image,
+                &image_info.digest,
+                &platform,
+                &provenance,
+            )
+            .map_err(|e| Error::agent("generate sbom", e.to_string()))?;
+            println!("SBOM: {} packages", scan.packages.len());
+            for path in &scan.unreadable {
+                eprintln!("Warning: could not read package list {}", path);
+            }
 
-            // Add to collector
-            collector
-                .add_layer(layer_digest, &layer_data)
-                .map_err(|e| Error::agent("collect layers", e.to_string()))?;
+            primary_info.get_or_insert(image_info);
         }
+        let image_info = primary_info.expect("at least one platform");
 
         // Stop agent and clean up temp VM data. Propagates stop errors
         // so pack fails visibly if VM cannot be stopped.
-        guard.stop_and_cleanup()?;
+        if let Some((mut guard, _client)) = agent {
+            guard.stop_and_cleanup()?;
+        }
 
         // Build manifest
         let platform = format!("{}/{}", image_info.os, image_info.architecture);
@@ -269,6 +1509,7 @@ impl PackCreateCmd {
         manifest.cmd = image_info.cmd.clone();
         manifest.env = image_info.env.clone();
         manifest.workdir = image_info.workdir.clone();
+        manifest.provenance = Some(provenance);
 
         // Override entrypoint if user provided one
         if let Some(ref ep) = self.entrypoint {
@@ -278,8 +1519,141 @@ impl PackCreateCmd {
         self.finalize_pack(manifest, collector, staging_dir)
     }
 
+    /// Load --file, filling in the flags it sets that were not given on the
+    /// command line.
+    fn load_config(&mut self) -> smolvm::Result<()> {
+        if let Some(ref path) = self.file {
+            *self.config = smolfile::load_pack_config(path)?;
+        }
+        let pack = &self.config.pack;
+        let sf = &self.config.smolfile;
+
+        if self.from_vm.is_some() && pack.image.is_some() {
+            return Err(Error::config(
+                "pack create",
+                "--from-vm cannot be used with a pack config that sets `image`",
+            ));
+        }
+        if self.image.is_none() && self.from_vm.is_none() {
+            self.image = Some(pack.image.clone().ok_or_else(|| {
+                Error::config(
+                    "pack create",
+                    "no image to pack; pass IMAGE or set `image` in the [pack] table",
+                )
+            })?);
+        }
+        if self.output.is_none() {
+            let name = pack.name.as_ref().ok_or_else(|| {
+                Error::config(
+                    "pack create",
+                    "no output path; pass -o or set `name` in the [pack] table",
+                )
+            })?;
+            self.output = Some(PathBuf::from(name));
+        }
+
+        // Flags still at their defaults give way to the file
+        if self.cpus == smolvm::agent::DEFAULT_CPUS {
+            self.cpus = sf.cpus.unwrap_or(self.cpus);
+        }
+        if self.mem == PACK_DEFAULT_MEMORY_MIB {
+            self.mem = sf.memory.unwrap_or(self.mem);
+        }
+        if self.oci_platform.is_none() && !pack.platforms.is_empty() {
+            self.oci_platform = Some(pack.platforms.join(","));
+        }
+        if self.sign_key.is_none() {
+            self.sign_key = pack.sign_key.clone();
+        }
+
+        // Catch bad ports and network settings now rather than when the
+        // packed binary runs
+        for port in &sf.ports {
+            parse_port(port).map_err(|e| Error::config("pack config ports", e))?;
+        }
+        for rule in &sf.net_allow {
+            parse_egress_rule(rule).map_err(|e| Error::config("pack config net_allow", e))?;
+        }
+        if !sf.net_allow.is_empty() && !sf.ports.is_empty() {
+            return Err(Error::config(
+                "pack config",
+                "`ports` cannot be combined with `net_allow`",
+            ));
+        }
+        self.network_config()?;
+        Ok(())
+    }
+
+    /// The --file proxy and DNS settings as applied in the guest. CA
+    /// certificates are read now so the pack carries them.
+    fn network_config(&self) -> smolvm::Result<(Option<ProxyConfig>, Option<DnsConfig>)> {
+        let sf = &self.config.smolfile;
+        let proxy = match sf.proxy.as_ref().filter(|p| !p.is_empty()) {
+            Some(proxy) => Some(proxy.to_protocol()?),
+            None => None,
+        };
+        let dns = match sf.dns.as_ref().filter(|d| !d.is_empty()) {
+            Some(dns) => Some(dns.to_protocol()?),
+            None => None,
+        };
+        Ok((proxy, dns))
+    }
+
+    /// Output path, resolved by `load_config`.
+    fn output(&self) -> &Path {
+        self.output
+            .as_deref()
+            .expect("output is resolved before packing")
+    }
+
+    /// Record the --file settings in the manifest as the packed binary's
+    /// defaults. Its env entries replace the image's for the same key.
+    fn apply_config(&self, manifest: &mut PackManifest) -> smolvm::Result<()> {
+        let sf = &self.config.smolfile;
+        manifest.name = self.config.pack.name.clone();
+        manifest.version = self.config.pack.version.clone();
+
+        for spec in &sf.env {
+            if let Some((key, _)) = parse_env_spec(spec) {
+                manifest
+                    .env
+                    .retain(|e| parse_env_spec(e).is_none_or(|(k, _)| k != key));
+            }
+            manifest.env.push(spec.clone());
+        }
+        if sf.workdir.is_some() {
+            manifest.workdir = sf.workdir.clone();
+        }
+
+        let (proxy, dns) = self.network_config()?;
+        manifest.defaults = RunDefaults {
+            volumes: sf.volumes.clone(),
+            ports: sf.ports.clone(),
+            // An allowlist implies network access
+            net: sf.net.unwrap_or(false) || !sf.net_allow.is_empty(),
+            net_allow: sf.net_allow.clone(),
+            proxy,
+            dns,
+            init: sf.init.clone(),
+            storage: sf.storage,
+            overlay: sf.overlay,
+        };
+        Ok(())
+    }
+
     /// Pack from a stopped VM's overlay disk.
     fn pack_from_vm(self, vm_name: String) -> smolvm::Result<()> {
+        let multi_platform = self
+            .oci_platform
+            .as_deref()
+            .is_some_and(|p| p.contains(','));
+        if multi_platform || !self.runtime.runtime_dirs.is_empty() {
+            return Err(Error::config(
+                "pack from VM",
+                "a VM snapshot can only be packed for its own platform",
+            ));
+        }
+
         // 1. Load config and verify VM exists and is stopped
         let config = SmolvmConfig::load()?;
         let vm = config
@@ -317,8 +1691,7 @@ impl PackCreateCmd {
         let staging_dir = temp_dir.path().join("staging");
 
         // 4. Collect base assets + overlay template
-        let mut collector = AssetCollector::new(staging_dir.clone())
-            .map_err(|e| Error::agent("collect assets", e.to_string()))?;
+        let mut collector = new_collector(staging_dir.clone(), self.reproducible)?;
         self.collect_base_assets(&mut collector)?;
 
         // Add overlay template from VM
@@ -339,6 +1712,7 @@ impl PackCreateCmd {
         // Inherit env/workdir from VmRecord
         manifest.env = vm.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
         manifest.workdir = vm.workdir.clone();
+        manifest.provenance = Some(new_provenance(self.reproducible)?);
 
         // Override entrypoint if user provided one
         if let Some(ref ep) = self.entrypoint {
@@ -351,36 +1725,54 @@ impl PackCreateCmd {
     /// Collect base assets shared by both image and VM packing modes:
     /// runtime libraries, agent rootfs, and a pre-formatted storage template.
     fn collect_base_assets(&self, collector: &mut AssetCollector) -> smolvm::Result<()> {
+        let lib_dir = self.runtime.find_lib_dir()?;
+        let rootfs_dir = self.runtime.find_rootfs_dir()?;
+        self.collect_runtime(collector, &lib_dir, &rootfs_dir)?;
+        self.collect_storage_template(collector)
+    }
+
+    /// Collect runtime libraries and the agent rootfs.
+    fn collect_runtime(
+        &self,
+        collector: &mut AssetCollector,
+        lib_dir: &Path,
+        rootfs_dir: &Path,
+    ) -> smolvm::Result<()> {
         println!("Collecting runtime libraries...");
-        let lib_dir = self.find_lib_dir()?;
         collector
-            .collect_libraries(&lib_dir)
+            .collect_libraries(lib_dir)
             .map_err(|e| Error::agent("collect libraries", e.to_string()))?;
 
         println!("Collecting agent rootfs...");
-        let rootfs_dir = self.find_rootfs_dir()?;
         collector
-            .collect_agent_rootfs(&rootfs_dir)
+            .collect_agent_rootfs(rootfs_dir)
             .map_err(|e| Error::agent("collect rootfs", e.to_string()))?;
 
+        Ok(())
+    }
+
+    /// Create a pre-formatted storage template.
+    fn collect_storage_template(&self, collector: &mut AssetCollector) -> smolvm::Result<()> {
         println!("Creating storage template...");
         collector
             .create_storage_template()
-            .map_err(|e| Error::agent("create storage template", e.to_string()))?;
-
-        Ok(())
+            .map_err(|e| Error::agent("create storage template", e.to_string()))
     }
 
-    /// Finalize pack: set inventory, assemble binary, print summary, and sign.
+    /// Finalize pack: set inventory and build the packer for `write_packed`.
     fn finalize_pack(
         &self,
         mut manifest: PackManifest,
         collector: AssetCollector,
         staging_dir: PathBuf,
     ) -> smolvm::Result<()> {
-        let stub_path = self.find_smolvm_binary()?;
+        let stub_path = self.runtime.find_smolvm_binary()?;
 
-        manifest.assets = collector.into_inventory();
+        self.apply_config(&mut manifest)?;
+        (manifest.assets, manifest.platforms) = collector.into_inventories();
+        let platforms = manifest
+            .is_multi_platform()
+            .then(|| manifest.platform_names().join(", "));
 
         let collector = AssetCollector::new(staging_dir)
             .map_err(|e| Error::agent("collect assets", e.to_string()))?;
@@ -388,176 +1780,14 @@ impl PackCreateCmd {
         let packer = Packer::new(manifest)
             .with_stub(&stub_path)
             .with_asset_collector(collector);
-
-        let info = if self.single_file {
-            println!("Assembling single-file packed binary...");
-            packer
-                .pack_embedded(&self.output)
-                .map_err(|e| Error::agent("pack binary", e.to_string()))?
-        } else {
-            println!("Assembling packed binary...");
-            packer
-                .pack(&self.output)
-                .map_err(|e| Error::agent("pack binary", e.to_string()))?
-        };
-
-        println!(
-            "Packed: {} (stub: {}KB, total: {}KB)",
-            self.output.display(),
-            info.stub_size / 1024,
-            info.total_size / 1024
-        );
-        if let Some(ref sidecar) = info.sidecar_path {
-            println!(
-                "Assets: {} ({}KB compressed)",
-                sidecar.display(),
-                info.assets_size / 1024
-            );
-        } else {
-            println!("Mode: single-file (no sidecar)");
-        }
-
-        // Sign on macOS
-        if Os::current().is_macos() && !self.no_sign {
-            println!("Signing binary with hypervisor entitlements...");
-            if let Err(e) = sign_with_hypervisor_entitlements(&self.output) {
-                warn!(error = %e, "signing failed (binary may not run on fresh macOS)");
-                eprintln!("Warning: Signing failed: {}", e);
-                eprintln!("The binary may require manual signing to use Hypervisor.framework");
-            } else {
-                println!("Signed successfully");
-            }
-        }
-
-        println!("\nRun with: {}", self.output.display());
-        if info.sidecar_path.is_some() {
-            println!("Note: Keep the .smolmachine file alongside the binary");
-        }
-        println!("Options: --help for usage");
-
-        Ok(())
-    }
-
-    /// Find the library directory containing libkrun and libkrunfw.
-    fn find_lib_dir(&self) -> smolvm::Result<PathBuf> {
-        if let Some(ref dir) = self.lib_dir {
-            return Ok(dir.clone());
-        }
-
-        // Check common locations
-        let platform_lib = format!("lib/linux-{}", std::env::consts::ARCH);
-        let candidates = [
-            // Relative to executable
-            std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|d| d.join("lib"))),
-            std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().and_then(|d| d.parent()).map(|d| d.join("lib"))),
-            // Source tree dev builds: <exe_dir>/../../lib/linux-<arch>/
-            std::env::current_exe().ok().and_then(|p| {
-                p.parent()
-                    .and_then(|d| d.parent())
-                    .map(|d| d.join(&platform_lib))
-            }),
-            // Source tree (CWD)
-            Some(PathBuf::from("lib")),
-            Some(PathBuf::from("./lib")),
-            Some(PathBuf::from(&platform_lib)),
-            // Homebrew
-            Some(PathBuf::from("/opt/homebrew/lib")),
-            Some(PathBuf::from("/usr/local/lib")),
-        ];
-
-        let lib_name = format!(
-            "libkrun.{}",
-            smolvm::platform::vm_executor().dylib_extension()
-        );
-
-        for candidate in candidates.into_iter().flatten() {
-            if candidate.join(&lib_name).exists() {
-                debug!(lib_dir = %candidate.display(), "found library directory");
-                return Ok(candidate);
-            }
-        }
-
-        Err(Error::agent(
-            "find libkrun",
-            "could not find libkrun library. Use --lib-dir to specify the location.",
-        ))
-    }
-
-    /// Find the agent rootfs directory.
-    fn find_rootfs_dir(&self) -> smolvm::Result<PathBuf> {
-        if let Some(ref dir) = self.rootfs_dir {
-            return Ok(dir.clone());
-        }
-
-        // Check common locations
-        let candidates = [
-            // Build output
-            Some(PathBuf::from("target/agent-rootfs/rootfs")),
-            // Distribution
-            std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|d| d.join("agent-rootfs"))),
-            // User data dir
-            dirs::data_dir().map(|d| d.join("smolvm/agent-rootfs")),
-        ];
-
-        for candidate in candidates.into_iter().flatten() {
-            // Use symlink_metadata instead of exists() because sbin/init
-            // is a symlink to a guest-only path (/usr/local/bin/smolvm-agent)
-            // that doesn't exist on the host. exists() follows symlinks and
-            // returns false for broken symlinks.
-            if std::fs::symlink_metadata(candidate.join("sbin/init")).is_ok() {
-                debug!(rootfs_dir = %candidate.display(), "found agent rootfs");
-                return Ok(candidate);
-            }
-        }
-
-        Err(Error::agent(
-            "find agent rootfs",
-            "could not find agent rootfs. Use --rootfs-dir to specify the location.",
-        ))
-    }
-
-    /// Find the smolvm binary to embed as the packed runtime.
-    ///
-    /// The main smolvm binary auto-detects packed mode at startup, so it
-    /// serves as both the normal CLI and the packed binary runtime.
-    fn find_smolvm_binary(&self) -> smolvm::Result<PathBuf> {
-        if let Some(ref path) = self.stub {
-            return Ok(path.clone());
-        }
-
-        let candidates = [
-            // Build output
-            Some(PathBuf::from("target/release/smolvm")),
-            Some(PathBuf::from("target/debug/smolvm")),
-            // Distribution layout: smolvm-bin next to the wrapper script
-            std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|d| d.join("smolvm-bin"))),
-            // The running executable itself
-            std::env::current_exe().ok(),
-            // User data dir
-            dirs::data_dir().map(|d| d.join("smolvm/smolvm-bin")),
-        ];
-
-        for candidate in candidates.into_iter().flatten() {
-            if candidate.exists() {
-                debug!(stub = %candidate.display(), "found smolvm binary for packing");
-                return Ok(candidate);
-            }
-        }
-
-        Err(Error::agent(
-            "find smolvm binary",
-            "could not find smolvm binary. Build it with:\n  \
-             cargo build --release\n\
-             Or use --stub to specify the path.",
-        ))
+        write_packed(
+            packer,
+            self.output(),
+            self.sign_key.as_deref(),
+            self.single_file,
+            self.no_sign,
+            platforms,
+        )
     }
 
     /// Export a layer from the agent.
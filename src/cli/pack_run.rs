@@ -8,20 +8,26 @@
 //!
 //! Both paths converge on the same VM launch infrastructure.
 
-use crate::cli::parsers::{mounts_to_virtiofs_bindings, parse_env_spec, parse_mounts, parse_port};
+use crate::cli::parsers::{
+    flatten_ports, mounts_to_container_mounts, parse_egress_rule, parse_env_spec, parse_mounts,
+    parse_port, PortArg,
+};
 use clap::{Args, Parser, Subcommand};
 use smolvm::agent::launcher_dynamic::{
     launch_agent_vm_dynamic, KrunFunctions, PackedLaunchConfig, PackedMount,
 };
-use smolvm::agent::{mount_tag, AgentClient, PortMapping, RunConfig, VmResources};
+use smolvm::agent::{mount_tag, AgentClient, RunConfig, VmResources};
+use smolvm::network::{DnsSettings, EgressRule};
 use smolvm::Error;
 use smolvm::DEFAULT_SHELL_CMD;
 use smolvm_pack::detect::PackedMode;
-use smolvm_pack::extract;
+use smolvm_pack::extract::{self, AssetSelection, LayerExtraction, PendingLayers};
 use smolvm_pack::format::PackMode;
 use smolvm_pack::packer::{
-    read_footer_from_sidecar, read_manifest_from_sidecar, verify_sidecar_checksum,
+    read_footer_from_sidecar, read_manifest_from_sidecar, verify_sidecar_checksum, PackedFile,
 };
+use smolvm_pack::patch::apply_patch;
+use smolvm_pack::trust::TrustPolicy;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -29,15 +35,37 @@ use std::time::Duration;
 const AGENT_READY_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Convert parsed mounts to PackedMount format for the VM launcher.
-fn mounts_to_packed(mounts: &[smolvm::vm::config::HostMount]) -> Vec<PackedMount> {
+///
+/// Image volumes are rejected: the packed launcher only shares directories
+/// and files over virtiofs. Single-file mounts are shared through private
+/// directories under `share_root`.
+fn mounts_to_packed(
+    mounts: &[smolvm::vm::config::HostMount],
+    share_root: &Path,
+) -> smolvm::Result<Vec<PackedMount>> {
     mounts
         .iter()
         .enumerate()
-        .map(|(i, m)| PackedMount {
-            tag: mount_tag(i),
-            host_path: m.source.to_string_lossy().to_string(),
-            guest_path: m.target.to_string_lossy().to_string(),
-            read_only: m.read_only,
+        .map(|(i, m)| {
+            if smolvm::volume::is_image_volume(&m.source) {
+                return Err(smolvm::Error::mount(
+                    "configure mount",
+                    format!(
+                        "image volumes are not supported by packed binaries; use a volume \
+                         created with --driver dir for {}",
+                        m.target.display()
+                    ),
+                ));
+            }
+            let share_dir = smolvm::mount::prepare_share(m, &share_root.join(mount_tag(i)))?;
+            Ok(PackedMount {
+                tag: mount_tag(i),
+                host_path: share_dir.to_string_lossy().to_string(),
+                file_name: m.shared_file().map(|f| f.to_string_lossy().to_string()),
+                guest_path: m.target.to_string_lossy().to_string(),
+                read_only: m.read_only,
+                sync: m.sync,
+            })
         })
         .collect()
 }
@@ -109,10 +137,10 @@ pub struct PackRunCmd {
         short = 'p',
         long = "port",
         value_parser = parse_port,
-        value_name = "HOST:GUEST",
+        value_name = "[IP:]HOST:GUEST[/udp]",
         help_heading = "Network"
     )]
-    pub port: Vec<PortMapping>,
+    pub port: Vec<PortArg>,
 
     /// Enable outbound network access
     #[arg(long, help_heading = "Network")]
@@ -142,6 +170,10 @@ pub struct PackRunCmd {
     #[arg(long)]
     pub info: bool,
 
+    /// Verify the checksum and every asset against the manifest, then exit
+    #[arg(long, conflicts_with = "info")]
+    pub verify_only: bool,
+
     /// Enable debug output
     #[arg(long)]
     pub debug: bool,
@@ -186,7 +218,7 @@ impl PackRunCmd {
         }
 
         // 3. Read manifest (safe now that checksum is verified)
-        let manifest = read_manifest_from_sidecar(&sidecar_path)
+        let mut manifest = read_manifest_from_sidecar(&sidecar_path)
             .map_err(|e| Error::agent("read manifest", e.to_string()))?;
 
         // 4. Handle --info: show manifest and exit
@@ -196,9 +228,21 @@ impl PackRunCmd {
                 PackMode::Vm => "vm",
             };
             println!("Mode:       {}", mode_str);
+            if let Some(ref name) = manifest.name {
+                println!("Name:       {}", name);
+            }
+            if let Some(ref version) = manifest.version {
+                println!("Version:    {}", version);
+            }
             println!("Image:      {}", manifest.image);
             println!("Digest:     {}", manifest.digest);
-            println!("Platform:   {}", manifest.platform);
+            println!("Platform:   {}", manifest.platform_names().join(", "));
+            if let Some(ref provenance) = manifest.provenance {
+                println!(
+                    "Built:      smolvm {} at {}",
+                    provenance.smolvm_version, provenance.created
+                );
+            }
             println!("CPUs:       {}", manifest.cpus);
             println!("Memory:     {} MiB", manifest.mem);
             if !manifest.entrypoint.is_empty() {
@@ -216,24 +260,44 @@ impl PackRunCmd {
                     println!("  {}", e);
                 }
             }
+            print_run_defaults(&manifest.defaults);
             println!("Checksum:   {:08x}", footer.checksum);
             return Ok(());
         }
 
-        // 5. Extract assets to cache (locked to prevent concurrent extraction races)
+        // 5. Refuse unsigned or untrusted assets if a trust policy is set
+        enforce_trust_policy(
+            &PackedMode::Sidecar {
+                sidecar_path: sidecar_path.clone(),
+                footer,
+            },
+            self.debug,
+        )?;
+
+        if self.verify_only {
+            return verify_only(
+                &PackedFile::from_parts(sidecar_path.clone(), footer),
+                &manifest,
+            );
+        }
+
+        // 6. Extract assets to cache (locked to prevent concurrent extraction races).
+        //    Layers of indexed packs are extracted while the VM boots.
         let cache_dir = extract::get_cache_dir(footer.checksum)
             .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
 
-        extract::extract_sidecar(
+        let selection = select_host_platform(&mut manifest)?;
+        let layers = extract::extract_sidecar(
             &sidecar_path,
             &cache_dir,
             &footer,
+            selection.as_ref(),
             self.force_extract,
             self.debug,
         )
         .map_err(|e| Error::agent("extract assets", e.to_string()))?;
 
-        // 6. Set up paths — use a unique runtime directory per invocation so
+        // 7. Set up paths — use a unique runtime directory per invocation so
         //    concurrent runs of the same checksum don't conflict on
         //    storage.ext4 / agent.sock.  tempdir_in gives us a truly unique
         //    directory that survives PID reuse and abrupt termination.
@@ -249,36 +313,47 @@ impl PackRunCmd {
         let storage_path = runtime_dir.path().join("storage.ext4");
         let vsock_path = runtime_dir.path().join("agent.sock");
 
+        // 8. Merge CLI args with the manifest's defaults
+        let settings = RunSettings::new(
+            &manifest,
+            &self.volume,
+            &self.port,
+            self.net,
+            self.storage,
+            self.overlay,
+        )?;
+
         // Create storage disk (each invocation gets its own copy)
         let template = manifest
             .assets
             .storage_template
             .as_ref()
             .map(|t| t.path.as_str());
-        extract::create_or_copy_storage_disk(&cache_dir, template, &storage_path, self.storage)
+        extract::create_or_copy_storage_disk(&cache_dir, template, &storage_path, settings.storage)
             .map_err(|e| Error::agent("create storage disk", e.to_string()))?;
 
         let overlay_runtime_path = setup_vm_overlay(
             &manifest,
             &cache_dir,
             &runtime_dir.path().join("overlay.raw"),
-            self.overlay,
+            settings.overlay,
         )?;
 
-        // 7. Parse CLI args
-        let mounts = parse_mounts(&self.volume)?;
-        let port_mappings: Vec<(u16, u16)> = self.port.iter().map(|p| (p.host, p.guest)).collect();
-
-        let resources = VmResources {
-            cpus: self.cpus.unwrap_or(manifest.cpus),
-            mem: self.mem.unwrap_or(manifest.mem),
-            network: self.net || !self.port.is_empty(),
-            storage_gb: self.storage,
-            overlay_gb: self.overlay,
-        };
+        let resources = settings.resources(
+            self.cpus.unwrap_or(manifest.cpus),
+            self.mem.unwrap_or(manifest.mem),
+        );
+        let RunSettings {
+            mounts,
+            ports: port_mappings,
+            ..
+        } = settings;
 
         // Build packed mounts for the launcher
-        let packed_mounts = mounts_to_packed(&mounts);
+        let packed_mounts = mounts_to_packed(
+            &mounts,
+            &runtime_dir.path().join(smolvm::mount::FILE_SHARES_DIR),
+        )?;
 
         if self.debug {
             eprintln!("debug: rootfs={}", rootfs_path.display());
@@ -291,7 +366,7 @@ impl PackRunCmd {
             );
         }
 
-        // 8. Fork child → launch VM with dynamically loaded libkrun
+        // 9. Fork child → launch VM with dynamically loaded libkrun
         smolvm::process::install_sigchld_handler();
 
         let console_log_path = runtime_dir.path().join("console.log");
@@ -330,6 +405,7 @@ impl PackRunCmd {
             smolvm::process::exit_child(1);
         })
         .map_err(|e| Error::agent("fork VM process", e.to_string()))?;
+        let pending_layers = layers.map(LayerExtraction::spawn);
 
         // Capture the child's start time so we can verify PID identity
         // later (guards against PID reuse).  The proc info may not be
@@ -381,8 +457,11 @@ impl PackRunCmd {
             runtime_dir,
         };
 
-        // 9. Parent: wait for agent, connect, execute command
+        // 10. Parent: wait for agent, connect, execute command
         let mut client = wait_for_agent(&vsock_path, self.debug)?;
+        configure_network(&mut client, &manifest);
+        wait_for_layers(pending_layers)?;
+        run_init(&mut client, &manifest, &self.env)?;
 
         let exit_code = execute_command(&mut client, &manifest, &self, &mounts)?;
 
@@ -571,6 +650,122 @@ fn build_command(manifest: &smolvm_pack::PackManifest, cli_command: &[String]) -
     }
 }
 
+/// Run settings from the manifest's defaults and the command line.
+struct RunSettings {
+    mounts: Vec<smolvm::vm::config::HostMount>,
+    ports: Vec<smolvm::agent::PortMapping>,
+    network: bool,
+    network_allow: Vec<EgressRule>,
+    host_resolver: bool,
+    storage: Option<u64>,
+    overlay: Option<u64>,
+}
+
+impl RunSettings {
+    /// Volumes and ports from the command line are added to the manifest's;
+    /// `--storage` and `--overlay` replace its sizes.
+    fn new(
+        manifest: &smolvm_pack::PackManifest,
+        volume: &[String],
+        port: &[PortArg],
+        net: bool,
+        storage: Option<u64>,
+        overlay: Option<u64>,
+    ) -> smolvm::Result<Self> {
+        let defaults = &manifest.defaults;
+        let volumes: Vec<String> = defaults.volumes.iter().chain(volume).cloned().collect();
+        let mut ports = defaults
+            .ports
+            .iter()
+            .map(|spec| parse_port(spec))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::config("manifest ports", e))?;
+        ports.extend_from_slice(port);
+        let ports = flatten_ports(ports);
+
+        let network_allow = defaults
+            .net_allow
+            .iter()
+            .map(|rule| parse_egress_rule(rule))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::config("manifest net_allow", e))?;
+        if !network_allow.is_empty() && !ports.is_empty() {
+            return Err(Error::config(
+                "pack run",
+                "this pack has an egress allowlist, which cannot be combined with published ports",
+            ));
+        }
+
+        Ok(Self {
+            mounts: parse_mounts(&volumes)?,
+            network: net || defaults.net || !ports.is_empty() || !network_allow.is_empty(),
+            network_allow,
+            host_resolver: defaults.dns.as_ref().is_some_and(|dns| dns.host_resolver),
+            ports,
+            storage: storage.or(defaults.storage),
+            overlay: overlay.or(defaults.overlay),
+        })
+    }
+
+    /// VM resources for these settings. Proxy and DNS settings are sent to
+    /// the agent once it is up (see [`configure_network`]); the launcher
+    /// only needs to know whether to serve the host resolver.
+    fn resources(&self, cpus: u8, mem: u32) -> VmResources {
+        VmResources {
+            cpus,
+            mem,
+            network: self.network,
+            storage_gb: self.storage,
+            overlay_gb: self.overlay,
+            network_allow: self.network_allow.clone(),
+            proxy: None,
+            dns: self.host_resolver.then(|| DnsSettings {
+                host_resolver: true,
+                ..Default::default()
+            }),
+            private_network: None,
+        }
+    }
+}
+
+/// Send the manifest's proxy and DNS settings to a freshly started agent.
+/// Failures are reported but don't stop the run.
+fn configure_network(client: &mut AgentClient, manifest: &smolvm_pack::PackManifest) {
+    if let Some(proxy) = &manifest.defaults.proxy {
+        if let Err(e) = client.configure_proxy(proxy) {
+            eprintln!("warning: failed to apply proxy settings: {}", e);
+        }
+    }
+    if let Some(dns) = &manifest.defaults.dns {
+        if let Err(e) = client.configure_dns(dns) {
+            eprintln!("warning: failed to apply DNS settings: {}", e);
+        }
+    }
+}
+
+/// Run the manifest's init commands in the freshly booted VM. Failures
+/// are reported but don't stop the run.
+fn run_init(
+    client: &mut AgentClient,
+    manifest: &smolvm_pack::PackManifest,
+    cli_env: &[String],
+) -> smolvm::Result<()> {
+    // A container's workdir need not exist in the VM
+    let workdir = match manifest.mode {
+        PackMode::Vm => manifest.workdir.clone(),
+        PackMode::Container => None,
+    };
+    for (i, cmd) in manifest.defaults.init.iter().enumerate() {
+        let argv = vec!["sh".into(), "-c".into(), cmd.clone()];
+        let (exit_code, _stdout, stderr) =
+            client.vm_exec(argv, build_env(manifest, cli_env), workdir.clone(), None)?;
+        if exit_code != 0 {
+            eprintln!("init[{}] failed (exit {}): {}", i, exit_code, stderr.trim());
+        }
+    }
+    Ok(())
+}
+
 /// Build environment variables from manifest defaults and CLI overrides.
 fn build_env(manifest: &smolvm_pack::PackManifest, cli_env: &[String]) -> Vec<(String, String)> {
     let mut env: Vec<(String, String)> = manifest
@@ -626,7 +821,7 @@ fn execute_command(
         }
         PackMode::Container => {
             // Container mode: run inside crun container
-            let mount_bindings = mounts_to_virtiofs_bindings(mounts);
+            let mount_bindings = mounts_to_container_mounts(mounts);
 
             if args.interactive || args.tty {
                 let config = RunConfig::new(&manifest.image, command)
@@ -727,8 +922,8 @@ struct PackedCli {
     overlay: Option<u64>,
 
     /// Expose port from container to host
-    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "HOST:GUEST", global = true)]
-    port: Vec<PortMapping>,
+    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "[IP:]HOST:GUEST[/udp]", global = true)]
+    port: Vec<PortArg>,
 
     /// Enable outbound network access
     #[arg(long, global = true)]
@@ -738,10 +933,18 @@ struct PackedCli {
     #[arg(long)]
     info: bool,
 
+    /// Verify the checksum and every asset against the manifest, then exit
+    #[arg(long, conflicts_with_all = ["command", "daemon_command", "info"])]
+    verify_only: bool,
+
     /// Force re-extraction of assets
     #[arg(long, global = true)]
     force_extract: bool,
 
+    /// Update this binary in place from a .smolpatch file and exit
+    #[arg(long, value_name = "PATCH", conflicts_with_all = ["command", "daemon_command", "info"])]
+    apply_update: Option<PathBuf>,
+
     /// Print debug information
     #[arg(long, global = true)]
     debug: bool,
@@ -794,6 +997,10 @@ pub fn run_as_packed_binary(mode: PackedMode) -> ! {
 }
 
 fn pack_run_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
+    if let Some(ref patch) = cli.apply_update {
+        return apply_update(&mode, patch, cli.debug);
+    }
+
     // Handle daemon subcommands
     if let Some(ref daemon_cmd) = cli.daemon_command {
         let checksum = mode_checksum(&mode);
@@ -821,6 +1028,12 @@ fn pack_run_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
         };
     }
 
+    // Sidecar mode delegates to `PackRunCmd`, which does its own check
+    // after verifying the checksum.
+    if !cli.info && !matches!(mode, PackedMode::Sidecar { .. }) {
+        enforce_trust_policy(&mode, cli.debug)?;
+    }
+
     match mode {
         PackedMode::Sidecar {
             sidecar_path,
@@ -844,6 +1057,7 @@ fn pack_run_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
                 overlay: cli.overlay,
                 force_extract: cli.force_extract,
                 info: cli.info,
+                verify_only: cli.verify_only,
                 debug: cli.debug,
             };
             cmd.run()
@@ -855,6 +1069,7 @@ fn pack_run_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
             checksum,
             assets_ptr,
             assets_size,
+            ..
         } => run_section_mode(*manifest, checksum, assets_ptr, assets_size, cli),
 
         PackedMode::Embedded { exe_path, footer } => run_embedded_mode(exe_path, footer, cli),
@@ -864,7 +1079,7 @@ fn pack_run_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
 /// Run from Mach-O section-embedded assets.
 #[cfg(target_os = "macos")]
 fn run_section_mode(
-    manifest: smolvm_pack::PackManifest,
+    mut manifest: smolvm_pack::PackManifest,
     checksum: u32,
     assets_ptr: *const u8,
     assets_size: usize,
@@ -875,18 +1090,33 @@ fn run_section_mode(
         return Ok(());
     }
 
+    if cli.verify_only {
+        return Err(Error::agent(
+            "verify pack",
+            "single-file macOS binaries are verified by their code signature",
+        ));
+    }
+
+    let selection = select_host_platform(&mut manifest)?;
     let cache_dir = extract::get_cache_dir(checksum)
         .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
 
     let needs_extract = cli.force_extract || !extract::is_extracted(&cache_dir);
     if needs_extract {
         unsafe {
-            extract::extract_from_section(&cache_dir, assets_ptr, assets_size, cli.debug)
-                .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
+            extract::extract_from_section(
+                &cache_dir,
+                assets_ptr,
+                assets_size,
+                &manifest.asset_index,
+                selection.as_ref(),
+                cli.debug,
+            )
+            .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
         }
     }
 
-    run_from_cache(&cache_dir, &manifest, cli)
+    run_from_cache(&cache_dir, &manifest, None, cli)
 }
 
 /// Run from binary-appended assets.
@@ -896,7 +1126,7 @@ fn run_embedded_mode(
     cli: PackedCli,
 ) -> smolvm::Result<()> {
     // Read manifest from the binary
-    let manifest = smolvm_pack::read_manifest(&exe_path)
+    let mut manifest = smolvm_pack::read_manifest(&exe_path)
         .map_err(|e| Error::agent("read manifest", e.to_string()))?;
 
     if cli.info {
@@ -904,24 +1134,35 @@ fn run_embedded_mode(
         return Ok(());
     }
 
+    if cli.verify_only {
+        return verify_only(&PackedFile::from_parts(exe_path, footer), &manifest);
+    }
+
+    let selection = select_host_platform(&mut manifest)?;
     let cache_dir = extract::get_cache_dir(footer.checksum)
         .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
 
-    let needs_extract = cli.force_extract || !extract::is_extracted(&cache_dir);
-    if needs_extract {
-        extract::extract_from_binary(&exe_path, &cache_dir, &footer, cli.debug)
-            .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
-    }
-
-    run_from_cache(&cache_dir, &manifest, cli)
+    let layers = extract::extract_from_binary(
+        &exe_path,
+        &cache_dir,
+        &footer,
+        selection.as_ref(),
+        cli.force_extract,
+        cli.debug,
+    )
+    .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
+
+    run_from_cache(&cache_dir, &manifest, layers, cli)
 }
 
 /// Shared launch path for section and embedded modes.
 ///
-/// Assets are already extracted to `cache_dir`. Boot VM and run the command.
+/// Assets are already extracted to `cache_dir`, apart from any `layers`,
+/// which are extracted while the VM boots. Boot VM and run the command.
 fn run_from_cache(
     cache_dir: &Path,
     manifest: &smolvm_pack::PackManifest,
+    layers: Option<LayerExtraction>,
     cli: PackedCli,
 ) -> smolvm::Result<()> {
     let rootfs_path = cache_dir.join("agent-rootfs");
@@ -941,28 +1182,38 @@ fn run_from_cache(
         .storage_template
         .as_ref()
         .map(|t| t.path.as_str());
-    extract::create_or_copy_storage_disk(cache_dir, template, &storage_path, cli.storage)
+    let settings = RunSettings::new(
+        manifest,
+        &cli.volume,
+        &cli.port,
+        cli.net,
+        cli.storage,
+        cli.overlay,
+    )?;
+    extract::create_or_copy_storage_disk(cache_dir, template, &storage_path, settings.storage)
         .map_err(|e| Error::agent("create storage disk", e.to_string()))?;
 
     let overlay_runtime_path = setup_vm_overlay(
         manifest,
         cache_dir,
         &runtime_dir.path().join("overlay.raw"),
-        cli.overlay,
+        settings.overlay,
     )?;
 
-    let mounts = parse_mounts(&cli.volume)?;
-    let port_mappings: Vec<(u16, u16)> = cli.port.iter().map(|p| (p.host, p.guest)).collect();
-
-    let resources = VmResources {
-        cpus: cli.cpus.unwrap_or(manifest.cpus),
-        mem: cli.mem.unwrap_or(manifest.mem),
-        network: cli.net || !cli.port.is_empty(),
-        storage_gb: cli.storage,
-        overlay_gb: cli.overlay,
-    };
-
-    let packed_mounts = mounts_to_packed(&mounts);
+    let resources = settings.resources(
+        cli.cpus.unwrap_or(manifest.cpus),
+        cli.mem.unwrap_or(manifest.mem),
+    );
+    let RunSettings {
+        mounts,
+        ports: port_mappings,
+        ..
+    } = settings;
+
+    let packed_mounts = mounts_to_packed(
+        &mounts,
+        &runtime_dir.path().join(smolvm::mount::FILE_SHARES_DIR),
+    )?;
 
     smolvm::process::install_sigchld_handler();
 
@@ -1001,6 +1252,7 @@ fn run_from_cache(
         smolvm::process::exit_child(1);
     })
     .map_err(|e| Error::agent("fork VM process", e.to_string()))?;
+    let pending_layers = layers.map(LayerExtraction::spawn);
 
     let child_start_time = {
         let mut st = smolvm::process::process_start_time(child_pid);
@@ -1033,6 +1285,9 @@ fn run_from_cache(
     };
 
     let mut client = wait_for_agent(&vsock_path, debug)?;
+    configure_network(&mut client, manifest);
+    wait_for_layers(pending_layers)?;
+    run_init(&mut client, manifest, &cli.env)?;
 
     // Build a minimal PackRunCmd-like struct for execute_command
     let args = PackRunCmd {
@@ -1052,6 +1307,7 @@ fn run_from_cache(
         overlay: cli.overlay,
         force_extract: false,
         info: false,
+        verify_only: false,
         debug,
     };
 
@@ -1062,14 +1318,32 @@ fn run_from_cache(
 }
 
 fn print_manifest_info(manifest: &smolvm_pack::PackManifest, checksum: u32) {
+    print_manifest(manifest);
+    println!("Checksum:   {:08x}", checksum);
+}
+
+/// Print a manifest's image and run settings, as `--info` shows them.
+pub(super) fn print_manifest(manifest: &smolvm_pack::PackManifest) {
     let mode_str = match manifest.mode {
         PackMode::Container => "container",
         PackMode::Vm => "vm",
     };
     println!("Mode:       {}", mode_str);
+    if let Some(ref name) = manifest.name {
+        println!("Name:       {}", name);
+    }
+    if let Some(ref version) = manifest.version {
+        println!("Version:    {}", version);
+    }
     println!("Image:      {}", manifest.image);
     println!("Digest:     {}", manifest.digest);
-    println!("Platform:   {}", manifest.platform);
+    println!("Platform:   {}", manifest.platform_names().join(", "));
+    if let Some(ref provenance) = manifest.provenance {
+        println!(
+            "Built:      smolvm {} at {}",
+            provenance.smolvm_version, provenance.created
+        );
+    }
     println!("CPUs:       {}", manifest.cpus);
     println!("Memory:     {} MiB", manifest.mem);
     if !manifest.entrypoint.is_empty() {
@@ -1087,7 +1361,49 @@ fn print_manifest_info(manifest: &smolvm_pack::PackManifest, checksum: u32) {
             println!("  {}", e);
         }
     }
-    println!("Checksum:   {:08x}", checksum);
+    print_run_defaults(&manifest.defaults);
+}
+
+fn print_run_defaults(defaults: &smolvm_pack::format::RunDefaults) {
+    if !defaults.volumes.is_empty() {
+        println!("Volumes:    {}", defaults.volumes.join(", "));
+    }
+    if !defaults.ports.is_empty() {
+        println!("Ports:      {}", defaults.ports.join(", "));
+    }
+    if !defaults.net_allow.is_empty() {
+        println!("Network:    {} (allowlist)", defaults.net_allow.join(", "));
+    } else if defaults.net {
+        println!("Network:    enabled");
+    }
+    if let Some(proxy) = &defaults.proxy {
+        let urls: Vec<&str> = [&proxy.http_proxy, &proxy.https_proxy]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        println!("Proxy:      {}", urls.join(", "));
+    }
+    if let Some(dns) = &defaults.dns {
+        let servers: Vec<String> = dns.servers.iter().map(|s| s.to_string()).collect();
+        if dns.host_resolver {
+            println!("DNS:        host resolver");
+        } else if !servers.is_empty() {
+            println!("DNS:        {}", servers.join(", "));
+        }
+    }
+    if let Some(storage) = defaults.storage {
+        println!("Storage:    {} GiB", storage);
+    }
+    if let Some(overlay) = defaults.overlay {
+        println!("Overlay:    {} GiB", overlay);
+    }
+    if !defaults.init.is_empty() {
+        println!("Init:");
+        for cmd in &defaults.init {
+            println!("  {}", cmd);
+        }
+    }
 }
 
 // ===========================================================================
@@ -1104,6 +1420,173 @@ fn mode_checksum(mode: &PackedMode) -> u32 {
     }
 }
 
+/// Pick this host's assets from a multi-platform pack.
+///
+/// Makes the host platform primary in `manifest` and returns what to
+/// extract for it, or `None` for a single-platform pack.
+fn select_host_platform(
+    manifest: &mut smolvm_pack::PackManifest,
+) -> smolvm::Result<Option<AssetSelection>> {
+    let platform = smolvm_pack::detect::host_platform();
+    let selection = AssetSelection::for_platform(manifest, platform)
+        .map_err(|e| Error::agent("select platform", e.to_string()))?;
+    manifest.select_platform(platform);
+    Ok(selection)
+}
+
+/// Update the pack in place from a `.smolpatch` and extract the new assets
+/// into the cache, so the next run starts without extracting.
+///
+/// The daemon's state lives in the cache of the pack it was started from,
+/// so a running daemon must be stopped first.
+fn apply_update(mode: &PackedMode, patch: &Path, debug: bool) -> smolvm::Result<()> {
+    let packed = match mode {
+        #[cfg(target_os = "macos")]
+        PackedMode::Section { .. } => {
+            return Err(Error::agent(
+                "apply update",
+                "single-file macOS binaries cannot be patched; download the new version",
+            ));
+        }
+        PackedMode::Embedded { exe_path, footer } => {
+            PackedFile::from_parts(exe_path.clone(), *footer)
+        }
+        PackedMode::Sidecar {
+            sidecar_path,
+            footer,
+        } => PackedFile::from_parts(sidecar_path.clone(), *footer),
+    };
+    let base_checksum = packed.footer().checksum;
+    if is_daemon_running(base_checksum) {
+        return Err(Error::agent(
+            "apply update",
+            "the daemon is running; stop it before updating",
+        ));
+    }
+
+    let policy =
+        TrustPolicy::load().map_err(|e| Error::agent("load trust policy", e.to_string()))?;
+    let updated = apply_patch(&packed, patch, policy.as_ref())
+        .map_err(|e| Error::agent("apply update", e.to_string()))?;
+    let checksum = updated.footer().checksum;
+
+    let mut manifest = updated
+        .manifest()
+        .map_err(|e| Error::agent("read manifest", e.to_string()))?;
+    let selection = select_host_platform(&mut manifest)?;
+    let cache_dir = extract::get_cache_dir(checksum)
+        .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
+    extract::install_cache(
+        updated.path(),
+        &cache_dir,
+        updated.footer(),
+        selection.as_ref(),
+        debug,
+    )
+    .map_err(|e| Error::agent("extract assets", e.to_string()))?;
+
+    println!(
+        "Updated {} ({:08x} -> {:08x})",
+        updated.path().display(),
+        base_checksum,
+        checksum
+    );
+    Ok(())
+}
+
+/// Check the pack's checksum and every asset against the manifest, for
+/// `--verify-only`. The trust policy has already been enforced.
+fn verify_only(packed: &PackedFile, manifest: &smolvm_pack::PackManifest) -> smolvm::Result<()> {
+    let checksum_ok = packed
+        .verify_checksum()
+        .map_err(|e| Error::agent("verify pack", e.to_string()))?;
+    if !checksum_ok {
+        return Err(Error::agent(
+            "verify pack",
+            format!("checksum mismatch for {}", packed.path().display()),
+        ));
+    }
+    let verified = packed
+        .verify_assets(manifest)
+        .map_err(|e| Error::agent("verify pack", e.to_string()))?;
+    let signed = packed
+        .signature()
+        .map_err(|e| Error::agent("verify pack", e.to_string()))?
+        .is_some();
+
+    println!("Checksum:   {:08x}", packed.footer().checksum);
+    println!("Assets:     {} verified", verified);
+    println!("Signed:     {}", if signed { "yes" } else { "no" });
+    Ok(())
+}
+
+/// Refuse to run unless the pack is signed by a trusted key.
+///
+/// A no-op when no trust policy is configured (see
+/// [`TrustPolicy::load`]). The assets digest is cached with the extracted
+/// files, so only the first launch of a pack hashes the whole blob.
+fn enforce_trust_policy(mode: &PackedMode, debug: bool) -> smolvm::Result<()> {
+    let Some(policy) =
+        TrustPolicy::load().map_err(|e| Error::agent("load trust policy", e.to_string()))?
+    else {
+        return Ok(());
+    };
+
+    let cache_dir = extract::get_cache_dir(mode_checksum(mode))
+        .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
+    let result = match mode {
+        #[cfg(target_os = "macos")]
+        PackedMode::Section {
+            manifest_json,
+            signature,
+            assets_ptr,
+            assets_size,
+            ..
+        } => {
+            use sha2::{Digest, Sha256};
+            std::env::current_exe()
+                .and_then(|exe| {
+                    extract::cached_assets_sha256(&cache_dir, &exe, || {
+                        // SAFETY: the section stays mapped for the life of the process.
+                        let assets =
+                            unsafe { std::slice::from_raw_parts(*assets_ptr, *assets_size) };
+                        Ok(Sha256::digest(assets).into())
+                    })
+                })
+                .map_err(smolvm_pack::PackError::from)
+                .and_then(|digest| policy.check(signature.as_ref(), manifest_json, &digest))
+        }
+        PackedMode::Embedded { exe_path, footer } => {
+            PackedFile::from_parts(exe_path.clone(), *footer)
+                .verify_signature_cached(&policy, &cache_dir)
+        }
+        PackedMode::Sidecar {
+            sidecar_path,
+            footer,
+        } => PackedFile::from_parts(sidecar_path.clone(), *footer)
+            .verify_signature_cached(&policy, &cache_dir),
+    };
+
+    let trusted = result.map_err(|e| {
+        Error::agent(
+            "verify pack signature",
+            format!("{}; a trust policy is configured, refusing to run", e),
+        )
+    })?;
+    if debug {
+        eprintln!(
+            "debug: pack signed by trusted key {}{}",
+            trusted.key.fingerprint(),
+            trusted
+                .name
+                .as_deref()
+                .map(|n| format!(" ({})", n))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
 /// Get the daemon state directory for a given checksum.
 ///
 /// Returns `~/.cache/smolvm-pack/{checksum:08x}/daemon/`.
@@ -1157,41 +1640,65 @@ fn read_manifest_for_mode(mode: &PackedMode) -> smolvm::Result<smolvm_pack::Pack
 }
 
 /// Ensure assets are extracted to the cache directory for the given mode.
-fn ensure_extracted(mode: &PackedMode, force: bool, debug: bool) -> smolvm::Result<PathBuf> {
+///
+/// Also returns the layers still to extract, to be spawned once the VM
+/// has been forked.
+fn ensure_extracted(
+    mode: &PackedMode,
+    selection: Option<&AssetSelection>,
+    force: bool,
+    debug: bool,
+) -> smolvm::Result<(PathBuf, Option<LayerExtraction>)> {
     let checksum = mode_checksum(mode);
     let cache_dir = extract::get_cache_dir(checksum)
         .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
 
-    let needs_extract = force || !extract::is_extracted(&cache_dir);
-    if needs_extract {
-        match mode {
-            #[cfg(target_os = "macos")]
-            PackedMode::Section {
-                assets_ptr,
-                assets_size,
-                ..
-            } => unsafe {
-                extract::extract_from_section(&cache_dir, *assets_ptr, *assets_size, debug)
+    let layers = match mode {
+        #[cfg(target_os = "macos")]
+        PackedMode::Section {
+            manifest,
+            assets_ptr,
+            assets_size,
+            ..
+        } => {
+            if force || !extract::is_extracted(&cache_dir) {
+                unsafe {
+                    extract::extract_from_section(
+                        &cache_dir,
+                        *assets_ptr,
+                        *assets_size,
+                        &manifest.asset_index,
+                        selection,
+                        debug,
+                    )
                     .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
-            },
-            PackedMode::Embedded {
-                exe_path, footer, ..
-            } => {
-                extract::extract_from_binary(exe_path, &cache_dir, footer, debug)
-                    .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
-            }
-            PackedMode::Sidecar {
-                sidecar_path,
-                footer,
-                ..
-            } => {
-                extract::extract_sidecar(sidecar_path, &cache_dir, footer, force, debug)
-                    .map_err(|e| Error::agent("extract sidecar assets", e.to_string()))?;
+                }
             }
+            None
         }
-    }
+        PackedMode::Embedded {
+            exe_path, footer, ..
+        } => extract::extract_from_binary(exe_path, &cache_dir, footer, selection, force, debug)
+            .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?,
+        PackedMode::Sidecar {
+            sidecar_path,
+            footer,
+            ..
+        } => extract::extract_sidecar(sidecar_path, &cache_dir, footer, selection, force, debug)
+            .map_err(|e| Error::agent("extract sidecar assets", e.to_string()))?,
+    };
+
+    Ok((cache_dir, layers))
+}
 
-    Ok(cache_dir)
+/// Wait for layers extracted in the background while the VM booted.
+fn wait_for_layers(pending: Option<PendingLayers>) -> smolvm::Result<()> {
+    match pending {
+        Some(pending) => pending
+            .wait()
+            .map_err(|e| Error::agent("extract layers", e.to_string())),
+        None => Ok(()),
+    }
 }
 
 /// Check if the daemon is currently running and connectable.
@@ -1227,10 +1734,13 @@ fn is_daemon_running(checksum: u32) -> bool {
 /// to become ready.
 fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
     let checksum = mode_checksum(mode);
-    let manifest = read_manifest_for_mode(mode)?;
+    let mut manifest = read_manifest_for_mode(mode)?;
+    enforce_trust_policy(mode, cli.debug)?;
 
     // Extract assets to cache
-    let cache_dir = ensure_extracted(mode, cli.force_extract, cli.debug)?;
+    let selection = select_host_platform(&mut manifest)?;
+    let (cache_dir, layers) =
+        ensure_extracted(mode, selection.as_ref(), cli.force_extract, cli.debug)?;
 
     // Create daemon directory
     let daemon = cache_dir.join("daemon");
@@ -1252,6 +1762,16 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
         tracing::debug!(error = %e, "cleanup: remove stale daemon socket");
     }
 
+    // Merge CLI args with the manifest's defaults
+    let settings = RunSettings::new(
+        &manifest,
+        &cli.volume,
+        &cli.port,
+        cli.net,
+        cli.storage,
+        cli.overlay,
+    )?;
+
     // Create storage disk if not exists (preserves existing disk on restart)
     let storage_path = daemon.join("storage.ext4");
     if !storage_path.exists() {
@@ -1260,7 +1780,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
             .storage_template
             .as_ref()
             .map(|t| t.path.as_str());
-        extract::create_or_copy_storage_disk(&cache_dir, template, &storage_path, cli.storage)
+        extract::create_or_copy_storage_disk(&cache_dir, template, &storage_path, settings.storage)
             .map_err(|e| Error::agent("create storage disk", e.to_string()))?;
     }
 
@@ -1268,7 +1788,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
     let overlay_daemon_path = if manifest.mode == PackMode::Vm {
         let overlay_path = daemon.join("overlay.raw");
         if !overlay_path.exists() {
-            setup_vm_overlay(&manifest, &cache_dir, &overlay_path, cli.overlay)?;
+            setup_vm_overlay(&manifest, &cache_dir, &overlay_path, settings.overlay)?;
         }
         Some(overlay_path)
     } else {
@@ -1277,19 +1797,17 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
 
     let vsock_path = daemon.join("agent.sock");
 
-    // Parse CLI args
-    let mounts = parse_mounts(&cli.volume)?;
-    let port_mappings: Vec<(u16, u16)> = cli.port.iter().map(|p| (p.host, p.guest)).collect();
-
-    let resources = VmResources {
-        cpus: cli.cpus.unwrap_or(manifest.cpus),
-        mem: cli.mem.unwrap_or(manifest.mem),
-        network: cli.net || !cli.port.is_empty(),
-        storage_gb: cli.storage,
-        overlay_gb: cli.overlay,
-    };
+    let resources = settings.resources(
+        cli.cpus.unwrap_or(manifest.cpus),
+        cli.mem.unwrap_or(manifest.mem),
+    );
+    let RunSettings {
+        mounts,
+        ports: port_mappings,
+        ..
+    } = settings;
 
-    let packed_mounts = mounts_to_packed(&mounts);
+    let packed_mounts = mounts_to_packed(&mounts, &daemon.join(smolvm::mount::FILE_SHARES_DIR))?;
 
     let rootfs_path = cache_dir.join("agent-rootfs");
     let lib_dir = cache_dir.join("lib");
@@ -1349,6 +1867,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
         smolvm::process::exit_child(1);
     })
     .map_err(|e| Error::agent("fork VM process", e.to_string()))?;
+    let pending_layers = layers.map(LayerExtraction::spawn);
 
     // Capture child start time for PID identity verification
     let child_start_time = {
@@ -1381,7 +1900,13 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
 
     // Wait for agent to become ready
     println!("Starting daemon...");
-    let _client = wait_for_agent(&vsock_path, debug)?;
+    let mut client = wait_for_agent(&vsock_path, debug)?;
+    configure_network(&mut client, &manifest);
+    if let Err(e) = wait_for_layers(pending_layers) {
+        let _ = smolvm::process::stop_process_fast(child_pid, Duration::from_secs(5), true);
+        return Err(e);
+    }
+    run_init(&mut client, &manifest, &cli.env)?;
 
     println!("Daemon started (PID: {})", child_pid);
     Ok(())
@@ -1435,9 +1960,10 @@ fn daemon_exec(
             }
         }
         PackMode::Container => {
-            // Parse mounts
-            let mounts = parse_mounts(&cli.volume)?;
-            let mount_bindings = mounts_to_virtiofs_bindings(&mounts);
+            // Mount indices must match the daemon's, which include the
+            // manifest's default volumes
+            let mounts = RunSettings::new(manifest, &cli.volume, &[], false, None, None)?.mounts;
+            let mount_bindings = mounts_to_container_mounts(&mounts);
 
             if interactive || tty {
                 let config = RunConfig::new(&manifest.image, command)
@@ -1510,6 +2036,7 @@ fn daemon_stop(checksum: u32, debug: bool) -> smolvm::Result<()> {
     if let Err(e) = std::fs::remove_file(dir.join("agent.sock")) {
         tracing::debug!(error = %e, "cleanup: remove daemon socket");
     }
+    smolvm::mount::remove_file_shares(&dir);
 
     println!("Daemon stopped");
     Ok(())
@@ -3,10 +3,15 @@
 //! This module consolidates parser functions used across multiple CLI commands
 //! to eliminate code duplication and ensure consistent validation.
 
-use smolvm::agent::PortMapping;
+use clap::Args;
+use smolvm::agent::{ContainerMount, PortMapping, SyncConflictPolicy};
+use smolvm::mount::MountArg;
+use smolvm::network::{DnsSettings, EgressRule};
 use smolvm::vm::config::HostMount;
+use smolvm::volume::VolumeStore;
 use smolvm::Error;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 /// Parse a duration string (e.g., "30s", "5m", "1h").
@@ -14,19 +19,108 @@ pub fn parse_duration(s: &str) -> Result<Duration, humantime::DurationError> {
     humantime::parse_duration(s)
 }
 
-/// Parse a port mapping specification (HOST:GUEST or PORT).
-pub fn parse_port(s: &str) -> Result<PortMapping, String> {
-    if let Some((host, guest)) = s.split_once(':') {
-        let host: u16 = host
-            .parse()
-            .map_err(|_| format!("invalid host port: {}", host))?;
-        let guest: u16 = guest
-            .parse()
-            .map_err(|_| format!("invalid guest port: {}", guest))?;
-        Ok(PortMapping::new(host, guest))
-    } else {
-        let port: u16 = s.parse().map_err(|_| format!("invalid port: {}", s))?;
-        Ok(PortMapping::same(port))
+/// Ports published by one `-p` argument (a range yields several).
+#[derive(Debug, Clone)]
+pub struct PortArg(pub Vec<PortMapping>);
+
+/// Parse a port mapping specification
+/// (`[IP:]HOST[-END]:GUEST[-END][/udp]` or `PORT`).
+pub fn parse_port(s: &str) -> Result<PortArg, String> {
+    PortMapping::parse_spec(s).map(PortArg)
+}
+
+/// Flatten parsed `-p` arguments into individual port mappings.
+pub fn flatten_ports(args: Vec<PortArg>) -> Vec<PortMapping> {
+    args.into_iter().flat_map(|a| a.0).collect()
+}
+
+/// Parse an egress allowlist rule (`github.com`, `*.npmjs.org`, `10.0.0.0/8:443`).
+pub fn parse_egress_rule(s: &str) -> Result<EgressRule, String> {
+    s.parse()
+}
+
+/// One `--net` argument.
+#[derive(Debug, Clone)]
+pub enum NetArg {
+    /// Bare `--net`: unrestricted outbound access.
+    Open,
+    /// `--net=allow=RULE,...`: proxy-only egress to these destinations.
+    Allow(Vec<EgressRule>),
+}
+
+/// Parse a `--net` value (empty for a bare `--net`).
+pub fn parse_net(s: &str) -> Result<NetArg, String> {
+    if s.is_empty() {
+        return Ok(NetArg::Open);
+    }
+    let rules = s
+        .strip_prefix("allow=")
+        .ok_or_else(|| format!("expected allow=RULE[,RULE...], got '{}'", s))?;
+    let rules = smolvm::network::parse_allowlist(rules)?;
+    if rules.is_empty() {
+        return Err("allow= needs at least one rule".to_string());
+    }
+    Ok(NetArg::Allow(rules))
+}
+
+/// Reduce `--net` arguments to whether networking is on and the egress
+/// allowlist, empty unless some were `allow=`.
+pub fn split_net_args(args: Vec<NetArg>) -> (bool, Vec<EgressRule>) {
+    let net = !args.is_empty();
+    let rules = args
+        .into_iter()
+        .flat_map(|arg| match arg {
+            NetArg::Open => Vec::new(),
+            NetArg::Allow(rules) => rules,
+        })
+        .collect();
+    (net, rules)
+}
+
+/// Validate an `--add-host` entry (`NAME:IP`).
+pub fn parse_add_host(s: &str) -> Result<String, String> {
+    smolvm::network::parse_host_entry(s).map(|_| s.to_string())
+}
+
+/// DNS flags shared by the commands that create a VM.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DnsArgs {
+    /// Nameserver for the VM (can be used multiple times)
+    #[arg(long = "dns", value_name = "IP", help_heading = "DNS")]
+    pub servers: Vec<IpAddr>,
+
+    /// DNS search domain (can be used multiple times)
+    #[arg(long = "dns-search", value_name = "DOMAIN", help_heading = "DNS")]
+    pub search: Vec<String>,
+
+    /// Resolver option, e.g. ndots:2 (can be used multiple times)
+    #[arg(long = "dns-option", value_name = "OPTION", help_heading = "DNS")]
+    pub options: Vec<String>,
+
+    /// Add an /etc/hosts entry in the VM and its containers
+    #[arg(
+        long = "add-host",
+        value_parser = parse_add_host,
+        value_name = "NAME:IP",
+        help_heading = "DNS"
+    )]
+    pub hosts: Vec<String>,
+
+    /// Resolve names with the host's resolver (follows VPN split DNS)
+    #[arg(long = "host-dns", help_heading = "DNS")]
+    pub host_resolver: bool,
+}
+
+impl DnsArgs {
+    /// The settings given on the command line.
+    pub fn into_settings(self) -> DnsSettings {
+        DnsSettings {
+            servers: self.servers,
+            search: self.search,
+            options: self.options,
+            hosts: self.hosts,
+            host_resolver: self.host_resolver,
+        }
     }
 }
 
@@ -47,15 +141,24 @@ pub fn parse_env_list(env_args: &[String]) -> Vec<(String, String)> {
 
 /// Parse volume mount specifications into HostMount structs.
 ///
-/// Format: `host_path:container_path[:ro]`
+/// Format: `host_path:container_path[:MODE]` or `volume:container_path[:MODE]`,
+/// where MODE is `ro`, `rw` (default), or `sync[=newer|host|guest]` for a
+/// directory kept as a synced copy on the guest storage disk.
 ///
-/// Validates that the host path exists and is a directory.
+/// Validates that the host path exists and is a directory or regular file.
+/// A glob in the last component of `host_path` (`~/.ssh/*.pub`) mounts each
+/// match at `container_path/<name>`. A source that isn't a path names a
+/// volume, which is created with the default driver if it doesn't exist.
 pub fn parse_mounts(specs: &[String]) -> smolvm::Result<Vec<HostMount>> {
-    specs.iter().map(|spec| parse_mount_spec(spec)).collect()
+    let mut mounts = Vec::new();
+    for spec in specs {
+        mounts.extend(parse_mount_spec(spec)?);
+    }
+    Ok(mounts)
 }
 
-/// Parse a single mount specification.
-fn parse_mount_spec(spec: &str) -> smolvm::Result<HostMount> {
+/// Parse a single mount specification (one mount per glob match).
+fn parse_mount_spec(spec: &str) -> smolvm::Result<Vec<HostMount>> {
     let parts: Vec<&str> = spec.split(':').collect();
     if parts.len() < 2 {
         return Err(Error::mount(
@@ -64,10 +167,100 @@ fn parse_mount_spec(spec: &str) -> smolvm::Result<HostMount> {
         ));
     }
 
-    let host_path = PathBuf::from(parts[0]);
+    let source = parts[0];
+    if !smolvm::volume::is_explicit_path(source)
+        && (!smolvm::volume::is_volume_name(source) || smolvm::mount::is_glob_pattern(source))
+    {
+        return Err(Error::mount(
+            "parse volume spec",
+            format!(
+                "'{}' is a relative path; write './{}' to mount it (a source without '/' names a volume)",
+                source, source
+            ),
+        ));
+    }
+
     let guest_path = PathBuf::from(parts[1]);
-    let read_only = parts.get(2).map(|&s| s == "ro").unwrap_or(false);
+    let mode = parts.get(2).copied().unwrap_or("rw");
+    let read_only = mode == "ro";
+    let sync = if mode == "sync" {
+        Some(SyncConflictPolicy::default())
+    } else if let Some(policy) = mode.strip_prefix("sync=") {
+        Some(
+            policy
+                .parse::<SyncConflictPolicy>()
+                .map_err(|e| Error::mount("parse volume spec", e))?,
+        )
+    } else {
+        None
+    };
+
+    if let Some(policy) = sync {
+        if smolvm::volume::is_volume_name(parts[0]) || smolvm::mount::is_glob_pattern(parts[0]) {
+            return Err(Error::mount(
+                "parse volume spec",
+                format!("'{}': only host directories can be synced", spec),
+            ));
+        }
+        let mount = host_mount(PathBuf::from(parts[0]), guest_path, false)?;
+        if !mount.source.is_dir() {
+            return Err(Error::mount(
+                "parse volume spec",
+                format!("'{}': only host directories can be synced", spec),
+            ));
+        }
+        return Ok(vec![mount.synced(policy)]);
+    }
+
+    if smolvm::volume::is_volume_name(parts[0]) {
+        let store = VolumeStore::open()?;
+        let (volume, created) = store.get_or_create(parts[0])?;
+        if created {
+            let size = volume
+                .size_gb
+                .map(|gb| format!(", {} GiB", gb))
+                .unwrap_or_default();
+            eprintln!(
+                "Created volume '{}' ({}{}); remove it with `smolvm volume rm {}`",
+                volume.name,
+                volume.driver.as_str(),
+                size,
+                volume.name
+            );
+        }
+        return Ok(vec![host_mount(
+            store.source_path(&volume),
+            guest_path,
+            read_only,
+        )?]);
+    }
 
+    if !smolvm::mount::is_glob_pattern(parts[0]) {
+        return Ok(vec![host_mount(
+            PathBuf::from(parts[0]),
+            guest_path,
+            read_only,
+        )?]);
+    }
+
+    smolvm::mount::expand_mount_glob(Path::new(parts[0]))?
+        .into_iter()
+        .map(|source| {
+            let target = match source.file_name() {
+                Some(name) => guest_path.join(name),
+                None => guest_path.clone(),
+            };
+            host_mount(source, target, read_only)
+        })
+        .collect()
+}
+
+/// Validate and canonicalize one host path.
+fn host_mount(
+    host_path: PathBuf,
+    guest_path: PathBuf,
+    read_only: bool,
+) -> smolvm::Result<HostMount> {
     // Validate host path exists
     if !host_path.exists() {
         return Err(Error::mount(
@@ -76,21 +269,25 @@ fn parse_mount_spec(spec: &str) -> smolvm::Result<HostMount> {
         ));
     }
 
-    // Must be a directory (virtiofs limitation)
-    if !host_path.is_dir() {
+    // Directories are shared as-is; a file is exposed alone from its
+    // shared parent directory. Sockets and devices can't be shared.
+    if !host_path.is_dir() && !host_path.is_file() {
         return Err(Error::mount(
             "validate host path",
             format!(
-                "path must be a directory (virtiofs limitation): {}",
+                "path must be a directory or regular file: {}",
                 host_path.display()
             ),
         ));
     }
 
     // Canonicalize host path
-    let host_path = host_path
-        .canonicalize()
-        .map_err(|e| Error::mount("canonicalize host path", format!("'{}': {}", parts[0], e)))?;
+    let host_path = host_path.canonicalize().map_err(|e| {
+        Error::mount(
+            "canonicalize host path",
+            format!("'{}': {}", host_path.display(), e),
+        )
+    })?;
 
     Ok(if read_only {
         HostMount::new(host_path, guest_path)
@@ -99,43 +296,86 @@ fn parse_mount_spec(spec: &str) -> smolvm::Result<HostMount> {
     })
 }
 
-/// Parse mounts and convert to tuple format for database storage.
-pub fn parse_mounts_as_tuples(specs: &[String]) -> smolvm::Result<Vec<(String, String, bool)>> {
-    parse_mounts(specs).map(|mounts| {
-        mounts
-            .into_iter()
-            .map(|m| {
-                (
-                    m.source.to_string_lossy().to_string(),
-                    m.target.to_string_lossy().to_string(),
-                    m.read_only,
-                )
-            })
-            .collect()
-    })
-}
-
-/// Parse mounts and convert to virtiofs binding format for agent.
-///
-/// Returns tuples of (virtiofs_tag, container_path, read_only).
-pub fn parse_mounts_to_bindings(specs: &[String]) -> smolvm::Result<Vec<(String, String, bool)>> {
-    parse_mounts(specs).map(|mounts| mounts_to_virtiofs_bindings(&mounts))
+/// Parse mounts and convert to container bind mounts for the agent.
+pub fn parse_mounts_to_bindings(specs: &[String]) -> smolvm::Result<Vec<ContainerMount>> {
+    parse_mounts(specs).map(|mounts| mounts_to_container_mounts(&mounts))
 }
 
-/// Convert parsed HostMount list to virtiofs binding format for agent.
+/// Bind each parsed HostMount into the container at its guest path.
 ///
-/// Returns tuples of (virtiofs_tag, container_path, read_only).
 /// The tag format is "smolvm{index}" to match libkrun virtiofs device naming.
-pub fn mounts_to_virtiofs_bindings(mounts: &[HostMount]) -> Vec<(String, String, bool)> {
+pub fn mounts_to_container_mounts(mounts: &[HostMount]) -> Vec<ContainerMount> {
     mounts
         .iter()
         .enumerate()
         .map(|(i, m)| {
-            (
+            ContainerMount::bind(
                 smolvm::agent::mount_tag(i),
-                m.target.to_string_lossy().to_string(),
+                m.target.to_string_lossy(),
                 m.read_only,
             )
         })
         .collect()
 }
+
+/// Parse a `--tmpfs` argument (`PATH[:size=256m,mode=1777,noexec]`).
+pub fn parse_tmpfs(s: &str) -> Result<ContainerMount, String> {
+    smolvm::mount::parse_tmpfs_spec(s)
+}
+
+/// Parse a `--mount` argument
+/// (`type=bind|volume|tmpfs,source=SRC,target=DST[,readonly][,noexec]`).
+pub fn parse_mount_arg(s: &str) -> Result<MountArg, String> {
+    MountArg::parse_spec(s)
+}
+
+/// Mounts from `-v`, `--mount` and `--tmpfs` for a container in a VM that
+/// is started for it.
+#[derive(Debug, Default)]
+pub struct ContainerMounts {
+    /// Host paths and volumes to share with the VM; share `i` gets tag
+    /// `smolvm{i}`.
+    pub host: Vec<HostMount>,
+    /// Mounts for the container.
+    pub container: Vec<ContainerMount>,
+}
+
+impl ContainerMounts {
+    /// Resolve mount arguments (see [`parse_mounts`] for `-v` specs).
+    pub fn parse(
+        volumes: &[String],
+        mounts: &[MountArg],
+        tmpfs: &[ContainerMount],
+    ) -> smolvm::Result<Self> {
+        let mut parsed = Self::default();
+        for spec in volumes {
+            for mount in parse_mount_spec(spec)? {
+                parsed.share(mount);
+            }
+        }
+        for arg in mounts {
+            match arg {
+                MountArg::Share { spec, flags } => {
+                    for mount in parse_mount_spec(spec)? {
+                        flags.apply(parsed.share(mount));
+                    }
+                }
+                MountArg::Tmpfs(mount) => parsed.container.push(mount.clone()),
+            }
+        }
+        parsed.container.extend(tmpfs.iter().cloned());
+        Ok(parsed)
+    }
+
+    /// Share a host mount with the VM and bind it into the container.
+    pub fn share(&mut self, mount: HostMount) -> &mut ContainerMount {
+        let index = self.container.len();
+        self.container.push(ContainerMount::bind(
+            smolvm::agent::mount_tag(self.host.len()),
+            mount.target.to_string_lossy(),
+            mount.read_only,
+        ));
+        self.host.push(mount);
+        &mut self.container[index]
+    }
+}
@@ -11,15 +11,18 @@
 //! `sandbox create`, managed with `sandbox start/stop/ls/delete`.
 
 use crate::cli::parsers::{
-    mounts_to_virtiofs_bindings, parse_duration, parse_env_list, parse_mounts, parse_port,
+    flatten_ports, parse_duration, parse_env_list, parse_mount_arg, parse_net, parse_port,
+    parse_tmpfs, ContainerMounts, DnsArgs, NetArg, PortArg,
 };
 use crate::cli::vm_common::{self, DeleteVmOptions, VmKind};
 use crate::cli::{flush_output, format_bytes, truncate_id};
 use clap::{Args, Subcommand};
 use smolvm::agent::{
-    docker_config_mount, AgentClient, AgentManager, PortMapping, RunConfig, VmResources,
+    docker_config_mount, AgentClient, AgentManager, ContainerMount, RunConfig, VmResources,
 };
+use smolvm::mount::MountArg;
 use smolvm::{DEFAULT_IDLE_CMD, DEFAULT_SHELL_CMD};
+use smolvm_protocol::GcTarget;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -200,6 +203,7 @@ impl StatusCmd {
                         }
                     }
                 }
+                vm_common::print_sync_status(&mut client);
             }
         })
     }
@@ -270,7 +274,7 @@ pub struct RunCmd {
     )]
     pub oci_platform: Option<String>,
 
-    /// Mount host directory into container (can be used multiple times)
+    /// Mount host path or named volume into container (can be used multiple times)
     #[arg(
         short = 'v',
         long = "volume",
@@ -279,13 +283,52 @@ pub struct RunCmd {
     )]
     pub volume: Vec<String>,
 
+    /// Attach a mount, e.g. type=bind,source=./src,target=/src,readonly,noexec
+    /// or type=tmpfs,target=/cache (can be used multiple times)
+    #[arg(
+        long = "mount",
+        value_parser = parse_mount_arg,
+        value_name = "SPEC",
+        help_heading = "Container"
+    )]
+    pub mount: Vec<MountArg>,
+
+    /// Mount a tmpfs, e.g. /tmp:size=256m,mode=1777 (can be used multiple times)
+    #[arg(
+        long,
+        value_parser = parse_tmpfs,
+        value_name = "PATH[:OPTS]",
+        help_heading = "Container"
+    )]
+    pub tmpfs: Vec<ContainerMount>,
+
     /// Expose port from container to host (can be used multiple times)
-    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "HOST:GUEST", help_heading = "Network")]
-    pub port: Vec<PortMapping>,
+    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "[IP:]HOST:GUEST[/udp]", help_heading = "Network")]
+    pub port: Vec<PortArg>,
 
-    /// Enable outbound network access
-    #[arg(long, help_heading = "Network")]
-    pub net: bool,
+    /// Enable outbound network access; with allow=RULES, proxy-only egress
+    ///
+    /// Bare --net allows all outbound traffic. --net=allow=RULE,... (can be
+    /// repeated) instead sends egress through an HTTP proxy that only
+    /// reaches the listed domains (github.com), wildcard domains
+    /// (*.npmjs.org), IPs or CIDR blocks (10.0.0.0/8), each with an optional
+    /// :PORT. Only HTTP(S) clients that honor HTTP_PROXY/HTTPS_PROXY get
+    /// out; other traffic (SSH, database clients, raw TCP, direct DNS
+    /// lookups) has no route, even to allowed destinations. Denied proxy
+    /// requests are logged to the VM's egress.log.
+    #[arg(
+        long,
+        value_name = "allow=RULES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_parser = parse_net,
+        help_heading = "Network"
+    )]
+    pub net: Vec<NetArg>,
+
+    #[command(flatten)]
+    pub dns: DnsArgs,
 
     /// Number of virtual CPUs
     #[arg(
@@ -341,8 +384,10 @@ impl RunCmd {
             self.cpus,
             self.mem,
             self.volume,
-            self.port,
+            flatten_ports(self.port),
             self.net,
+            None,
+            self.dns.into_settings(),
             vec![],
             self.env,
             self.workdir,
@@ -351,14 +396,14 @@ impl RunCmd {
             self.overlay,
         )?;
 
-        // Parse volume mounts
-        let mut mounts = parse_mounts(&params.volume)?;
+        // Parse volume, --mount and tmpfs mounts
+        let mut mounts = ContainerMounts::parse(&params.volume, &self.mount, &self.tmpfs)?;
         let ports = params.port.clone();
 
         // Add docker config mount if requested
         if self.docker_config {
             if let Some(docker_mount) = docker_config_mount() {
-                mounts.push(docker_mount);
+                mounts.share(docker_mount);
             } else {
                 tracing::warn!(
                     "Docker config directory not found, --docker-config will have no effect"
@@ -372,6 +417,10 @@ impl RunCmd {
             network: params.net,
             storage_gb: params.storage_gb,
             overlay_gb: params.overlay_gb,
+            network_allow: params.net_allow.clone(),
+            proxy: params.proxy.clone(),
+            dns: params.dns.clone(),
+            private_network: None,
         };
 
         // Start agent VM
@@ -384,8 +433,8 @@ impl RunCmd {
         } else {
             "ephemeral"
         };
-        let mount_info = if !mounts.is_empty() {
-            format!(" with {} mount(s)", mounts.len())
+        let mount_info = if !mounts.container.is_empty() {
+            format!(" with {} mount(s)", mounts.container.len())
         } else {
             String::new()
         };
@@ -397,7 +446,7 @@ impl RunCmd {
         println!("Starting {} sandbox{}{}...", mode, mount_info, port_info);
 
         let freshly_started = manager
-            .ensure_running_with_full_config(mounts.clone(), ports, resources)
+            .ensure_running_with_full_config(mounts.host.clone(), ports, resources)
             .map_err(|e| Error::agent("start sandbox", e.to_string()))?;
 
         // Connect to agent
@@ -433,8 +482,7 @@ impl RunCmd {
         // Parse environment variables
         let env = parse_env_list(&params.env);
 
-        // Convert mounts to agent format
-        let mount_bindings = mounts_to_virtiofs_bindings(&mounts);
+        let mount_bindings = mounts.container;
 
         if self.detach {
             // Detached/persistent mode: create container and keep running
@@ -450,18 +498,6 @@ impl RunCmd {
             {
                 use smolvm::config::SmolvmConfig;
                 use vm_common::DefaultVmOverrides;
-                let mount_tuples: Vec<(String, String, bool)> = mounts
-                    .iter()
-                    .map(|m| {
-                        (
-                            m.source.to_string_lossy().to_string(),
-                            m.target.to_string_lossy().to_string(),
-                            m.read_only,
-                        )
-                    })
-                    .collect();
-                let port_tuples: Vec<(u16, u16)> =
-                    params.port.iter().map(|p| (p.host, p.guest)).collect();
                 if let Ok(mut config) = SmolvmConfig::load() {
                     vm_common::persist_default_running(
                         &mut config,
@@ -469,11 +505,14 @@ impl RunCmd {
                         Some(DefaultVmOverrides {
                             cpus: params.cpus,
                             mem: params.mem,
-                            mounts: mount_tuples,
-                            ports: port_tuples,
+                            mounts: mounts.host.clone(),
+                            ports: params.port.clone(),
                             network: params.net,
                             storage_gb: params.storage_gb,
                             overlay_gb: params.overlay_gb,
+                            network_allow: params.net_allow.clone(),
+                            proxy: params.proxy.clone(),
+                            dns: params.dns.clone(),
                             init: params.init.clone(),
                             env: parse_env_list(&params.env),
                             workdir: params.workdir.clone(),
@@ -573,17 +612,40 @@ pub struct CreateCmd {
     #[arg(long, value_name = "GiB")]
     pub overlay: Option<u64>,
 
-    /// Mount host directory (can be used multiple times)
+    /// Mount host path or named volume (can be used multiple times)
     #[arg(short = 'v', long = "volume", value_name = "HOST:GUEST[:ro]")]
     pub volume: Vec<String>,
 
     /// Expose port from sandbox to host (can be used multiple times)
-    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "HOST:GUEST")]
-    pub port: Vec<PortMapping>,
+    #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "[IP:]HOST:GUEST[/udp]")]
+    pub port: Vec<PortArg>,
 
-    /// Enable outbound network access
-    #[arg(long)]
-    pub net: bool,
+    /// Enable outbound network access; with allow=RULES, proxy-only egress
+    ///
+    /// Bare --net allows all outbound traffic. --net=allow=RULE,... (can be
+    /// repeated) instead sends egress through an HTTP proxy that only
+    /// reaches the listed domains (github.com), wildcard domains
+    /// (*.npmjs.org), IPs or CIDR blocks (10.0.0.0/8), each with an optional
+    /// :PORT. Only HTTP(S) clients that honor HTTP_PROXY/HTTPS_PROXY get
+    /// out; other traffic (SSH, database clients, raw TCP, direct DNS
+    /// lookups) has no route, even to allowed destinations. Denied proxy
+    /// requests are logged to the VM's egress.log.
+    #[arg(
+        long,
+        value_name = "allow=RULES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_parser = parse_net
+    )]
+    pub net: Vec<NetArg>,
+
+    /// Join a private network created with `smolvm network create`
+    #[arg(long = "network", value_name = "NAME")]
+    pub network: Option<String>,
+
+    #[command(flatten)]
+    pub dns: DnsArgs,
 
     /// Run command on every VM start (can be used multiple times)
     #[arg(long = "init", value_name = "COMMAND")]
@@ -609,8 +671,10 @@ impl CreateCmd {
             self.cpus,
             self.mem,
             self.volume,
-            self.port,
+            flatten_ports(self.port),
             self.net,
+            self.network,
+            self.dns.into_settings(),
             self.init,
             self.env,
             self.workdir,
@@ -810,12 +874,16 @@ impl ImagesCmd {
 
 /// Remove unused images and layers to free disk space.
 ///
-/// This removes layers that are not referenced by any cached image manifest.
+/// By default this removes layers no image references, overlays left behind
+/// by crashed runs, and orphaned image configs. Category flags select what to
+/// prune instead; images and build cache take their unshared layers with
+/// them. Nothing a registered container uses is removed.
 /// Use --dry-run to see what would be removed without actually deleting.
 ///
 /// Examples:
 ///   smolvm sandbox prune --dry-run
 ///   smolvm sandbox prune
+///   smolvm sandbox prune --build-cache --overlays
 ///   smolvm sandbox prune --all
 #[derive(Args, Debug)]
 pub struct PruneCmd {
@@ -823,12 +891,53 @@ pub struct PruneCmd {
     #[arg(long)]
     pub dry_run: bool,
 
-    /// Remove all cached images (not just unreferenced layers)
+    /// Remove images not used by any container
+    #[arg(long)]
+    pub images: bool,
+
+    /// Remove cached build steps
+    #[arg(long)]
+    pub build_cache: bool,
+
+    /// Remove layers not referenced by any image
+    #[arg(long)]
+    pub layers: bool,
+
+    /// Remove overlays not owned by a container or a running workload
+    #[arg(long)]
+    pub overlays: bool,
+
+    /// Remove image configs not referenced by any image
+    #[arg(long)]
+    pub configs: bool,
+
+    /// Remove re-compressed layers kept for image push and save
+    #[arg(long)]
+    pub blobs: bool,
+
+    /// Prune every category
     #[arg(long)]
     pub all: bool,
 }
 
 impl PruneCmd {
+    fn targets(&self) -> Vec<GcTarget> {
+        if self.all {
+            return GcTarget::ALL.to_vec();
+        }
+        [
+            (self.images, GcTarget::Images),
+            (self.build_cache, GcTarget::BuildCache),
+            (self.layers, GcTarget::Layers),
+            (self.overlays, GcTarget::Overlays),
+            (self.configs, GcTarget::Configs),
+            (self.blobs, GcTarget::Blobs),
+        ]
+        .into_iter()
+        .filter_map(|(selected, target)| selected.then_some(target))
+        .collect()
+    }
+
     pub fn run(self) -> smolvm::Result<()> {
         let manager = AgentManager::new_default()?;
 
@@ -841,72 +950,34 @@ impl PruneCmd {
             AgentClient::connect_with_retry(manager.vsock_socket())?
         };
 
-        if self.all {
-            // Get list of images first
-            let images = client.list_images()?;
-
-            if images.is_empty() {
-                println!("No cached images to remove.");
-                return Ok(());
-            }
-
-            let total_size: u64 = images.iter().map(|i| i.size).sum();
+        let report = client.garbage_collect(self.dry_run, &self.targets())?;
 
-            if self.dry_run {
-                println!(
-                    "Would remove {} images ({})",
-                    images.len(),
-                    format_bytes(total_size)
-                );
-                for image in &images {
-                    println!(
-                        "  - {} ({}, {} layers)",
-                        image.reference,
-                        format_bytes(image.size),
-                        image.layer_count
-                    );
-                }
-            } else {
-                println!("Removing all cached images...");
-
-                // Remove each image by clearing storage
-                // Note: This requires a storage clear API which we may need to add
-                // For now, we use garbage_collect which only removes unreferenced layers
-                let freed = client.garbage_collect(false)?;
+        if report.items.is_empty() {
+            println!("Nothing to remove.");
+            return Ok(());
+        }
 
-                println!("Freed {} of unreferenced layers", format_bytes(freed));
-                println!();
-                println!(
-                    "Note: To remove all images, stop the sandbox and delete the storage disk:"
-                );
-                println!("  smolvm sandbox stop");
-                println!("  rm ~/.smolvm/vms/default/storage.raw");
-            }
+        let verb = if self.dry_run {
+            "Would remove"
         } else {
-            // Just garbage collect unreferenced layers
-            if self.dry_run {
-                println!("Scanning for unreferenced layers...");
-                let would_free = client.garbage_collect(true)?;
-
-                if would_free > 0 {
-                    println!(
-                        "Would free {} of unreferenced layers",
-                        format_bytes(would_free)
-                    );
-                } else {
-                    println!("No unreferenced layers to remove.");
-                }
-            } else {
-                println!("Removing unreferenced layers...");
-                let freed = client.garbage_collect(false)?;
-
-                if freed > 0 {
-                    println!("Freed {}", format_bytes(freed));
-                } else {
-                    println!("No unreferenced layers to remove.");
-                }
-            }
+            "Removed"
+        };
+        for item in &report.items {
+            println!(
+                "{} {} [{}] ({})",
+                verb,
+                item.id,
+                item.target.as_str(),
+                format_bytes(item.size)
+            );
         }
+        println!();
+        println!(
+            "{} {} of {} items",
+            if self.dry_run { "Would free" } else { "Freed" },
+            format_bytes(report.freed_bytes),
+            report.items.len()
+        );
 
         Ok(())
     }
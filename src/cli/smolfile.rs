@@ -8,8 +8,10 @@
 //! cpus = 2
 //! memory = 1024
 //! net = true
+//! net_allow = ["github.com", "*.npmjs.org:443", "10.0.0.0/8"]
+//! network = "dev"
 //!
-//! ports = ["8080:80", "2222:22"]
+//! ports = ["8080:80", "127.0.0.1:2222:22", "5353:53/udp"]
 //! volumes = ["./src:/app"]
 //! env = ["NODE_ENV=production"]
 //! workdir = "/app"
@@ -19,12 +21,41 @@
 //!     "ssh-keygen -A",
 //!     "/usr/sbin/sshd",
 //! ]
+//!
+//! [dns]
+//! servers = ["10.0.0.2"]
+//! search = ["corp.example.com"]
+//! options = ["ndots:2"]
+//! hosts = ["db.internal:10.0.0.5"]
+//! host_resolver = true  # use the host's resolver (VPN split DNS)
+//!
+//! [proxy]
+//! https = "http://proxy.corp:3128"
+//! no_proxy = ["localhost", ".corp"]
+//! ca_certs = ["./corp-ca.pem"]  # relative to the Smolfile
+//! ```
+//!
+//! A pack configuration (`smolvm pack create -f smolpack.toml`) is a
+//! Smolfile with an extra `[pack]` table. Its run settings become the
+//! packed binary's defaults:
+//! ```toml
+//! memory = 512
+//! ports = ["8080:80"]
+//! env = ["NODE_ENV=production"]
+//!
+//! [pack]
+//! name = "my-app"
+//! version = "1.2.0"
+//! image = "my-app:1.2.0"
+//! sign_key = "release.key"  # relative to the file
+//! platforms = ["linux/amd64", "linux/arm64"]
 //! ```
 
-use crate::cli::parsers::parse_port;
+use crate::cli::parsers::{parse_egress_rule, split_net_args, NetArg};
 use crate::cli::vm_common::CreateVmParams;
 use serde::Deserialize;
 use smolvm::agent::PortMapping;
+use smolvm::network::{DnsSettings, EgressRule, ProxySettings};
 use std::path::{Path, PathBuf};
 
 /// Parsed Smolfile configuration.
@@ -35,6 +66,9 @@ pub struct Smolfile {
     pub memory: Option<u32>,
     pub net: Option<bool>,
     #[serde(default)]
+    pub net_allow: Vec<String>,
+    pub network: Option<String>,
+    #[serde(default)]
     pub ports: Vec<String>,
     #[serde(default)]
     pub volumes: Vec<String>,
@@ -45,6 +79,8 @@ pub struct Smolfile {
     pub workdir: Option<String>,
     pub storage: Option<u64>,
     pub overlay: Option<u64>,
+    pub proxy: Option<ProxySettings>,
+    pub dns: Option<DnsSettings>,
 }
 
 /// Load and parse a Smolfile from the given path.
@@ -57,6 +93,80 @@ pub fn load(path: &Path) -> smolvm::Result<Smolfile> {
         .map_err(|e| smolvm::Error::config("parse smolfile", format!("{}: {}", path.display(), e)))
 }
 
+/// Pack-specific keys of a pack configuration, from its `[pack]` table.
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PackSettings {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub image: Option<String>,
+    pub sign_key: Option<PathBuf>,
+    #[serde(default)]
+    pub platforms: Vec<String>,
+}
+
+/// Parsed pack configuration: a Smolfile plus a `[pack]` table.
+#[derive(Debug, Default)]
+pub struct PackConfig {
+    pub smolfile: Smolfile,
+    pub pack: PackSettings,
+}
+
+/// Load a pack configuration (`smolpack.toml`).
+///
+/// Paths in the file (the signing key, volume sources and proxy CA
+/// certificates) are resolved relative to it. Private networks are rejected:
+/// they exist only on the host that created them.
+pub fn load_pack_config(path: &Path) -> smolvm::Result<PackConfig> {
+    let parse_err = |e: toml::de::Error| {
+        smolvm::Error::config("parse pack config", format!("{}: {}", path.display(), e))
+    };
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        smolvm::Error::config("load pack config", format!("{}: {}", path.display(), e))
+    })?;
+
+    let mut table: toml::Table = toml::from_str(&content).map_err(parse_err)?;
+    let mut pack: PackSettings = match table.remove("pack") {
+        Some(value) => value.try_into().map_err(parse_err)?,
+        None => PackSettings::default(),
+    };
+    let mut smolfile: Smolfile = table.try_into().map_err(parse_err)?;
+
+    if smolfile.network.is_some() {
+        return Err(smolvm::Error::config(
+            "load pack config",
+            format!(
+                "{}: `network` is not supported by packed binaries; private networks exist only on the host that created them",
+                path.display()
+            ),
+        ));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    if let Some(ref mut key) = pack.sign_key {
+        if key.is_relative() {
+            *key = dir.join(&*key);
+        }
+    }
+    for spec in &mut smolfile.volumes {
+        let source = spec.split(':').next().unwrap_or_default();
+        // `./x` and `../x`; absolute, `~/` and volume names are kept
+        if smolvm::volume::is_explicit_path(source) && source.starts_with('.') {
+            let joined = dir.join(source);
+            let absolute = std::path::absolute(&joined).unwrap_or(joined);
+            *spec = format!("{}{}", absolute.display(), &spec[source.len()..]);
+        }
+    }
+    if let Some(proxy) = smolfile.proxy.as_mut() {
+        for cert in &mut proxy.ca_certs {
+            if cert.is_relative() {
+                *cert = dir.join(&*cert);
+            }
+        }
+    }
+    Ok(PackConfig { smolfile, pack })
+}
+
 /// Build `CreateVmParams` by merging CLI flags with an optional Smolfile.
 ///
 /// CLI flags override Smolfile values. For Vec fields, CLI values are appended
@@ -68,7 +178,9 @@ pub fn build_create_params(
     cli_mem: u32,
     cli_volume: Vec<String>,
     cli_port: Vec<PortMapping>,
-    cli_net: bool,
+    cli_net: Vec<NetArg>,
+    cli_network: Option<String>,
+    cli_dns: DnsSettings,
     cli_init: Vec<String>,
     cli_env: Vec<String>,
     cli_workdir: Option<String>,
@@ -76,8 +188,12 @@ pub fn build_create_params(
     cli_storage_gb: Option<u64>,
     cli_overlay_gb: Option<u64>,
 ) -> smolvm::Result<CreateVmParams> {
-    let sf = match smolfile_path {
-        Some(path) => load(&path)?,
+    let (cli_net, cli_net_allow) = split_net_args(cli_net);
+    let (sf, smolfile_dir) = match smolfile_path {
+        Some(path) => {
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            (load(&path)?, dir)
+        }
         None => {
             return Ok(CreateVmParams {
                 name,
@@ -85,7 +201,11 @@ pub fn build_create_params(
                 mem: cli_mem,
                 volume: cli_volume,
                 port: cli_port,
-                net: cli_net,
+                net: cli_net || !cli_net_allow.is_empty(),
+                net_allow: cli_net_allow,
+                network: cli_network,
+                proxy: None,
+                dns: (!cli_dns.is_empty()).then_some(cli_dns),
                 init: cli_init,
                 env: cli_env,
                 workdir: cli_workdir,
@@ -99,12 +219,24 @@ pub fn build_create_params(
     let mut ports: Vec<PortMapping> = sf
         .ports
         .iter()
-        .map(|s| parse_port(s))
+        .map(|s| PortMapping::parse_spec(s))
         .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| smolvm::Error::config("smolfile ports", e))?;
+        .map_err(|e| smolvm::Error::config("smolfile ports", e))?
+        .into_iter()
+        .flatten()
+        .collect();
     // CLI ports override/extend
     ports.extend(cli_port);
 
+    // Parse Smolfile egress allowlist; CLI rules extend it
+    let mut net_allow: Vec<EgressRule> = sf
+        .net_allow
+        .iter()
+        .map(|s| parse_egress_rule(s))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| smolvm::Error::config("smolfile net_allow", e))?;
+    net_allow.extend(cli_net_allow);
+
     // Merge volumes: Smolfile first, CLI extends
     let mut volumes = sf.volumes;
     volumes.extend(cli_volume);
@@ -133,18 +265,40 @@ pub fn build_create_params(
         sf.memory.unwrap_or(cli_mem)
     };
 
-    let net = if cli_net {
+    // An allowlist implies network access
+    let net = if cli_net || !net_allow.is_empty() {
         true
     } else {
         sf.net.unwrap_or(false)
     };
 
     let workdir = cli_workdir.or(sf.workdir);
+    let network = cli_network.or(sf.network);
 
     // Scalars: CLI overrides Smolfile
     let storage_gb = cli_storage_gb.or(sf.storage);
     let overlay_gb = cli_overlay_gb.or(sf.overlay);
 
+    // DNS: CLI entries extend the Smolfile's
+    let mut dns = sf.dns.unwrap_or_default();
+    dns.servers.extend(cli_dns.servers);
+    dns.search.extend(cli_dns.search);
+    dns.options.extend(cli_dns.options);
+    dns.hosts.extend(cli_dns.hosts);
+    dns.host_resolver |= cli_dns.host_resolver;
+    let dns = (!dns.is_empty()).then_some(dns);
+
+    // CA certificate paths are relative to the Smolfile
+    let proxy = sf.proxy.map(|mut proxy| {
+        for cert in &mut proxy.ca_certs {
+            if cert.is_relative() {
+                let joined = smolfile_dir.join(&*cert);
+                *cert = std::path::absolute(&joined).unwrap_or(joined);
+            }
+        }
+        proxy
+    });
+
     Ok(CreateVmParams {
         name,
         cpus,
@@ -152,6 +306,10 @@ pub fn build_create_params(
         volume: volumes,
         port: ports,
         net,
+        net_allow,
+        network,
+        proxy,
+        dns,
         init,
         env,
         workdir,
@@ -0,0 +1,129 @@
+//! System commands.
+
+use crate::cli::{format_bytes, truncate, truncate_id, vm_common};
+use clap::{Args, Subcommand};
+use smolvm_protocol::UsageSummary;
+
+/// Inspect microVM storage
+#[derive(Subcommand, Debug)]
+pub enum SystemCmd {
+    /// Show storage usage by images, containers, layers and overlays
+    Df(SystemDfCmd),
+}
+
+impl SystemCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            SystemCmd::Df(cmd) => cmd.run(),
+        }
+    }
+}
+
+/// Show storage usage of a microVM.
+///
+/// The summary lists what each category takes and how much of it
+/// `smolvm sandbox prune` can reclaim. With --verbose, images are broken
+/// down into layers shared with other images and layers only they use, and
+/// containers show the size of their writable layer.
+///
+/// Examples:
+///   smolvm system df
+///   smolvm system df -v --name builder
+#[derive(Args, Debug)]
+pub struct SystemDfCmd {
+    /// Show per-image and per-container usage
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Target microVM (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl SystemDfCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&vm_common::vm_label(&self.name))?;
+        let mut client = smolvm::agent::AgentClient::connect_with_retry(manager.vsock_socket())?;
+        let result = client.disk_usage();
+        manager.detach();
+        let usage = result?;
+
+        let images = UsageSummary {
+            count: usage.images.len(),
+            size: usage.images.iter().map(|i| i.size).sum(),
+            reclaimable_count: usage.images.iter().filter(|i| i.containers == 0).count(),
+            reclaimable_size: usage
+                .images
+                .iter()
+                .filter(|i| i.containers == 0)
+                .map(|i| i.unique_size)
+                .sum(),
+        };
+        let containers = UsageSummary {
+            count: usage.containers.len(),
+            size: usage.containers.iter().map(|c| c.size).sum(),
+            ..UsageSummary::default()
+        };
+
+        println!(
+            "{:<14} {:>8} {:>12} {:>12}",
+            "TYPE", "TOTAL", "SIZE", "RECLAIMABLE"
+        );
+        for (label, summary) in [
+            ("Images", &images),
+            ("Containers", &containers),
+            ("Layers", &usage.layers),
+            ("Overlays", &usage.overlays),
+            ("Configs", &usage.configs),
+            ("Build cache", &usage.build_cache),
+            ("Push blobs", &usage.blobs),
+        ] {
+            println!(
+                "{:<14} {:>8} {:>12} {:>12}",
+                label,
+                summary.count,
+                format_bytes(summary.size),
+                format_bytes(summary.reclaimable_size)
+            );
+        }
+
+        if !self.verbose {
+            return Ok(());
+        }
+
+        println!();
+        println!("Images:");
+        println!(
+            "{:<40} {:>10} {:>12} {:>12} {:>10}",
+            "REFERENCE", "SIZE", "SHARED", "UNIQUE", "CONTAINERS"
+        );
+        for image in &usage.images {
+            println!(
+                "{:<40} {:>10} {:>12} {:>12} {:>10}",
+                truncate(&image.reference, 40),
+                format_bytes(image.size),
+                format_bytes(image.shared_size),
+                format_bytes(image.unique_size),
+                image.containers
+            );
+        }
+
+        println!();
+        println!("Containers:");
+        println!(
+            "{:<12} {:<30} {:<10} {:>10}",
+            "ID", "IMAGE", "STATE", "SIZE"
+        );
+        for container in &usage.containers {
+            println!(
+                "{:<12} {:<30} {:<10} {:>10}",
+                truncate_id(&container.id),
+                truncate(&container.image, 30),
+                container.state,
+                format_bytes(container.size)
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -5,12 +5,15 @@
 //! This module provides the common implementations, parameterised by
 //! [`VmKind`].
 
-use crate::cli::parsers::parse_mounts_as_tuples;
+use crate::cli::parsers::parse_mounts;
 use crate::cli::{format_pid_suffix, truncate};
-use smolvm::agent::{vm_data_dir, AgentManager, PortMapping};
+use smolvm::agent::{vm_data_dir, AgentManager, OnlineResize, PortMapping};
 use smolvm::config::{RecordState, SmolvmConfig, VmRecord};
 use smolvm::db::SmolvmDb;
+use smolvm::network::{DnsSettings, EgressRule, NetworkStore, ProxySettings};
 use smolvm::storage::{DEFAULT_OVERLAY_SIZE_GIB, DEFAULT_STORAGE_SIZE_GIB};
+use smolvm::vm::config::HostMount;
+use smolvm::volume::VolumeStore;
 
 // ============================================================================
 // VmKind
@@ -176,6 +179,10 @@ pub struct CreateVmParams {
     pub volume: Vec<String>,
     pub port: Vec<PortMapping>,
     pub net: bool,
+    pub net_allow: Vec<EgressRule>,
+    pub network: Option<String>,
+    pub proxy: Option<ProxySettings>,
+    pub dns: Option<DnsSettings>,
     pub init: Vec<String>,
     pub env: Vec<String>,
     pub workdir: Option<String>,
@@ -248,6 +255,10 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     // Validate name before touching the database
     validate_name(&params.name, kind)?;
 
+    // Parse and validate volume mounts (this may create named volumes, which
+    // opens the database, so it happens before the config holds it open)
+    let mounts = parse_mounts(&params.volume)?;
+
     let mut config = SmolvmConfig::load()?;
 
     // Check if already exists
@@ -258,11 +269,14 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
         ));
     }
 
-    // Parse and validate volume mounts
-    let mounts = parse_mounts_as_tuples(&params.volume)?;
-
     // Convert port mappings to tuple format for storage
-    let ports: Vec<(u16, u16)> = params.port.iter().map(|p| (p.host, p.guest)).collect();
+    let ports = params.port.clone();
+    if !params.net_allow.is_empty() && !ports.is_empty() {
+        return Err(smolvm::Error::config(
+            format!("create {}", kind.label()),
+            "port mappings cannot be combined with --net=allow=",
+        ));
+    }
 
     // Parse environment variables for init
     let env: Vec<(String, String)> = params
@@ -292,9 +306,36 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     record.workdir = params.workdir.clone();
     record.storage_gb = params.storage_gb;
     record.overlay_gb = params.overlay_gb;
+    record.network_allow = params.net_allow.clone();
+    record.proxy = params.proxy.clone();
+    record.dns = params.dns.clone();
+
+    // Claim an address on the private network before persisting the record
+    let mut network_store = None;
+    if let Some(network) = &params.network {
+        let store = NetworkStore::open()?;
+        record.private_network = Some(store.join(network, &params.name)?);
+        network_store = Some(store);
+    }
+    let attachment = record.private_network.clone();
+
+    // Named volumes in use can't be removed while this VM exists
+    let volumes = VolumeStore::at(config.db().clone(), smolvm::volume::default_root()?);
+    let mut result = volumes.acquire_mounts(&record.mounts, &params.name);
 
     // Store in config (persisted immediately to database)
-    config.insert_vm(params.name.clone(), record)?;
+    if result.is_ok() {
+        result = config.insert_vm(params.name.clone(), record);
+        if result.is_err() {
+            volumes.release(&params.name);
+        }
+    }
+    if let Err(e) = result {
+        if let (Some(store), Some(attachment)) = (&network_store, &attachment) {
+            store.leave(attachment);
+        }
+        return Err(e);
+    }
 
     println!("Created {}: {}", kind.label(), params.name);
     println!("  CPUs: {}, Memory: {} MiB", params.cpus, params.mem);
@@ -304,6 +345,21 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     if !params.port.is_empty() {
         println!("  Ports: {}", params.port.len());
     }
+    if !params.net_allow.is_empty() {
+        println!("  Egress allowlist: {} rule(s)", params.net_allow.len());
+    }
+    if params.proxy.is_some() {
+        println!("  HTTP proxy: configured");
+    }
+    if params.dns.is_some() {
+        println!("  DNS: configured");
+    }
+    if let Some(attachment) = &attachment {
+        println!(
+            "  Private network: {} ({})",
+            attachment.network, attachment.address
+        );
+    }
     if !params.init.is_empty() {
         println!("  Init commands: {}", params.init.len());
     }
@@ -460,6 +516,9 @@ pub fn persist_default_running(
                 r.network = o.network;
                 r.storage_gb = o.storage_gb;
                 r.overlay_gb = o.overlay_gb;
+                r.network_allow = o.network_allow.clone();
+                r.proxy = o.proxy.clone();
+                r.dns = o.dns.clone();
                 r.init = o.init.clone();
                 r.env = o.env.clone();
                 r.workdir = o.workdir.clone();
@@ -475,11 +534,14 @@ pub fn persist_default_running(
 pub struct DefaultVmOverrides {
     pub cpus: u8,
     pub mem: u32,
-    pub mounts: Vec<(String, String, bool)>,
-    pub ports: Vec<(u16, u16)>,
+    pub mounts: Vec<HostMount>,
+    pub ports: Vec<PortMapping>,
     pub network: bool,
     pub storage_gb: Option<u64>,
     pub overlay_gb: Option<u64>,
+    pub network_allow: Vec<EgressRule>,
+    pub proxy: Option<ProxySettings>,
+    pub dns: Option<DnsSettings>,
     pub init: Vec<String>,
     pub env: Vec<(String, String)>,
     pub workdir: Option<String>,
@@ -670,6 +732,16 @@ pub fn delete_vm(
     // Remove from config (persists immediately to database)
     config.remove_vm(name);
 
+    if let Ok(root) = smolvm::volume::default_root() {
+        VolumeStore::at(config.db().clone(), root).release(name);
+    }
+
+    if let Some(attachment) = &record.private_network {
+        if let Ok(store) = NetworkStore::open() {
+            store.leave(attachment);
+        }
+    }
+
     let data_dir = vm_data_dir(name);
     if data_dir.exists() {
         println!("Cleaning up data directory for vm: {}", name);
@@ -709,6 +781,43 @@ where
     Ok(())
 }
 
+/// Print the state of the VM's synced mounts, if it has any.
+pub fn print_sync_status(client: &mut smolvm::agent::AgentClient) {
+    let mounts = match client.sync_status() {
+        Ok(mounts) if !mounts.is_empty() => mounts,
+        _ => return,
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    println!("\nSynced mounts:");
+    for m in mounts {
+        let last_sync = match m.last_sync {
+            Some(t) => format!("synced {}s ago", now.saturating_sub(t)),
+            None => "not synced yet".to_string(),
+        };
+        println!(
+            "  {} ({}): {} entries, {} pending, {} in, {} out, {} conflicts, {}",
+            m.target,
+            m.policy.as_str(),
+            m.entries,
+            m.pending,
+            m.copied_in,
+            m.copied_out,
+            m.conflicts,
+            last_sync
+        );
+        for conflict in &m.recent_conflicts {
+            println!("    conflict: {}", conflict);
+        }
+        if let Some(error) = &m.last_error {
+            println!("    error: {}", error);
+        }
+    }
+}
+
 // ============================================================================
 // List
 // ============================================================================
@@ -748,6 +857,7 @@ pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
                     "created_at": record.created_at,
                     "storage_gb": record.storage_gb,
                     "overlay_gb": record.overlay_gb,
+                    "private_network": record.private_network,
                 });
                 if kind.include_network_in_json() {
                     obj.as_object_mut()
@@ -787,16 +897,27 @@ pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
                 if let Some(pid) = record.pid {
                     println!("  PID: {}", pid);
                 }
-                for (host, guest, ro) in &record.mounts {
-                    let ro_str = if *ro { " (ro)" } else { "" };
-                    println!("  Mount: {} -> {}{}", host, guest, ro_str);
+                for mount in &record.mounts {
+                    let ro_str = if mount.read_only { " (ro)" } else { "" };
+                    println!(
+                        "  Mount: {} -> {}{}",
+                        mount.source.display(),
+                        mount.target.display(),
+                        ro_str
+                    );
                 }
-                for (host, guest) in &record.ports {
-                    println!("  Port: {} -> {}", host, guest);
+                for port in &record.ports {
+                    println!("  Port: {}", port);
                 }
                 if kind.include_network_in_json() && record.network {
                     println!("  Network: enabled");
                 }
+                if let Some(attachment) = &record.private_network {
+                    println!(
+                        "  Private network: {} ({})",
+                        attachment.network, attachment.address
+                    );
+                }
                 for cmd in &record.init {
                     println!("  Init: {}", cmd);
                 }
@@ -821,8 +942,9 @@ pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
 
 /// Resize a microVM's disk resources.
 ///
-/// The VM must be stopped before resizing. Only expansion is supported
-/// (no shrinking to prevent data loss).
+/// Only expansion is supported (no shrinking to prevent data loss). The
+/// storage disk of a running VM is grown online; everything else requires
+/// the VM to be stopped.
 pub fn resize_vm(
     kind: VmKind,
     name: &str,
@@ -840,21 +962,28 @@ pub fn resize_vm(
         .ok_or_else(|| smolvm::Error::vm_not_found(name))?
         .clone();
 
-    // Check state - VM must be stopped (Created state also allowed for never-started VMs)
+    // Get current disk sizes (use defaults if not set)
+    let current_storage_gb = record.storage_gb.unwrap_or(DEFAULT_STORAGE_SIZE_GIB);
+    let current_overlay_gb = record.overlay_gb.unwrap_or(DEFAULT_OVERLAY_SIZE_GIB);
+
+    // Check state - a running VM can only grow its storage disk (online);
+    // everything else requires it to be stopped (Created state also allowed
+    // for never-started VMs)
     let actual_state = record.actual_state();
-    match actual_state {
-        RecordState::Stopped | RecordState::Created => {} // OK to resize
+    let online = match actual_state {
+        RecordState::Stopped | RecordState::Created => false,
+        RecordState::Running
+            if new_overlay_gb.unwrap_or(current_overlay_gb) <= current_overlay_gb =>
+        {
+            true
+        }
         _ => {
             return Err(smolvm::Error::InvalidState {
                 expected: "stopped".into(),
                 actual: format!("{:?}", actual_state),
             });
         }
-    }
-
-    // Get current disk sizes (use defaults if not set)
-    let current_storage_gb = record.storage_gb.unwrap_or(DEFAULT_STORAGE_SIZE_GIB);
-    let current_overlay_gb = record.overlay_gb.unwrap_or(DEFAULT_OVERLAY_SIZE_GIB);
+    };
 
     // Determine target sizes
     let target_storage_gb = new_storage_gb.unwrap_or(current_storage_gb);
@@ -883,9 +1012,14 @@ pub fn resize_vm(
     // Get agent manager for disk paths
     let manager = AgentManager::for_vm(name)
         .map_err(|e| smolvm::Error::agent("get agent manager", e.to_string()))?;
+    if online {
+        // Dropping an attached manager would stop the running VM
+        manager.detach();
+    }
 
     // Print resize header
     println!("Resizing {} '{}'...", kind.label(), name);
+    let mut pending = false;
 
     // Expand storage disk if requested and changed
     if let Some(storage_gb) = new_storage_gb {
@@ -896,10 +1030,30 @@ pub fn resize_vm(
             );
             std::io::Write::flush(&mut std::io::stdout()).ok();
 
-            let storage_path = manager.storage_path();
-            expand_disk(storage_path, storage_gb, "storage")
-                .map_err(|e| smolvm::Error::storage("expand storage disk", e.to_string()))?;
-            println!(" done");
+            if online {
+                match manager.resize_storage_online(storage_gb) {
+                    Ok(OnlineResize::Grown(_)) => println!(" done"),
+                    Ok(OnlineResize::Pending { reason }) => {
+                        println!(" pending");
+                        eprintln!(
+                            "Warning: the disk was grown but its filesystem was not ({}); \
+                             it will be grown when the {} restarts",
+                            reason,
+                            kind.label()
+                        );
+                        pending = true;
+                    }
+                    Err(e) => {
+                        println!(" failed");
+                        return Err(e);
+                    }
+                }
+            } else {
+                let storage_path = manager.storage_path();
+                expand_disk(storage_path, storage_gb, "storage")
+                    .map_err(|e| smolvm::Error::storage("expand storage disk", e.to_string()))?;
+                println!(" done");
+            }
         }
     }
 
@@ -931,7 +1085,112 @@ pub fn resize_vm(
 
     println!();
     println!("{} '{}' resized successfully.", kind.display_name(), name);
-    println!("Disk changes are applied immediately; filesystem will expand on next boot.");
+    if pending {
+        println!("The disk is resized; its filesystem will expand on next boot.");
+    } else if online {
+        println!("The storage filesystem was grown online.");
+    } else {
+        println!("Disk changes are applied immediately; filesystem will expand on next boot.");
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Ports
+// ============================================================================
+
+/// Connect to a VM's agent if its record says it is running.
+///
+/// The returned manager is detached, so dropping it leaves the VM running.
+fn connect_if_running(name: &str, record: &VmRecord) -> smolvm::Result<Option<AgentManager>> {
+    if record.actual_state() != RecordState::Running {
+        return Ok(None);
+    }
+    let manager = AgentManager::for_vm(name)
+        .map_err(|e| smolvm::Error::agent("get agent manager", e.to_string()))?;
+    manager.detach();
+    if manager.try_connect_existing().is_none() {
+        return Err(smolvm::Error::agent(
+            "connect to VM",
+            format!("'{}' is running but its agent is not reachable", name),
+        ));
+    }
+    Ok(Some(manager))
+}
+
+/// Publish ports on a VM.
+///
+/// A running VM starts serving them right away; either way they are saved
+/// to the VM record so they are published on every later start.
+pub fn publish_vm_ports(kind: VmKind, name: &str, ports: &[PortMapping]) -> smolvm::Result<()> {
+    let db = SmolvmDb::open()?;
+    let record = db
+        .get_vm(name)?
+        .ok_or_else(|| smolvm::Error::vm_not_found(name))?;
+
+    let mut published = record.ports.clone();
+    for mapping in ports {
+        if let Some(existing) = published.iter().find(|p| p.conflicts_with(mapping)) {
+            return Err(smolvm::Error::config(
+                "publish port",
+                format!("{} conflicts with published port {}", mapping, existing),
+            ));
+        }
+        published.push(*mapping);
+    }
+
+    let manager = connect_if_running(name, &record)?;
+    for mapping in ports {
+        if let Some(manager) = &manager {
+            manager.publish_port(*mapping)?;
+        }
+        // Saved one at a time so a later failure leaves the record accurate
+        db.update_vm(name, |r| r.ports.push(*mapping))?;
+        println!("Published {}", mapping);
+    }
+
+    if manager.is_none() {
+        println!(
+            "{} '{}' is not running; ports are published on next start.",
+            kind.display_name(),
+            name
+        );
+    }
+    Ok(())
+}
 
+/// Withdraw ports from a VM, matched by protocol, bind address and host port.
+///
+/// Ports a running VM published at boot through TSI (plain TCP on all
+/// interfaces) can't be withdrawn until it restarts.
+pub fn unpublish_vm_ports(kind: VmKind, name: &str, ports: &[PortMapping]) -> smolvm::Result<()> {
+    let db = SmolvmDb::open()?;
+    let record = db
+        .get_vm(name)?
+        .ok_or_else(|| smolvm::Error::vm_not_found(name))?;
+
+    for mapping in ports {
+        if !record.ports.iter().any(|p| p.same_host_side(mapping)) {
+            return Err(smolvm::Error::config(
+                "unpublish port",
+                format!(
+                    "{} '{}' does not publish host port {}",
+                    kind.label(),
+                    name,
+                    mapping
+                ),
+            ));
+        }
+    }
+
+    let manager = connect_if_running(name, &record)?;
+    for mapping in ports {
+        if let Some(manager) = &manager {
+            manager.unpublish_port(*mapping)?;
+        }
+        db.update_vm(name, |r| r.ports.retain(|p| !p.same_host_side(mapping)))?;
+        println!("Unpublished {}", mapping);
+    }
     Ok(())
 }
@@ -308,13 +308,13 @@ pub struct VmRecord {
     #[serde(default = "default_mem")]
     pub mem: u32,
 
-    /// Volume mounts (host_path, guest_path, read_only).
+    /// Volume mounts (older records store `[host, guest, read_only]`).
     #[serde(default)]
-    pub mounts: Vec<(String, String, bool)>,
+    pub mounts: Vec<crate::vm::config::HostMount>,
 
-    /// Port mappings (host_port, guest_port).
+    /// Port mappings (older records store `[host, guest]` pairs).
     #[serde(default)]
-    pub ports: Vec<(u16, u16)>,
+    pub ports: Vec<crate::agent::PortMapping>,
 
     /// Enable outbound network access (TSI).
     #[serde(default)]
@@ -347,6 +347,22 @@ pub struct VmRecord {
     /// Overlay disk size in GiB (None = default 10 GiB).
     #[serde(default)]
     pub overlay_gb: Option<u64>,
+
+    /// Egress allowlist (empty = no allowlist).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network_allow: Vec<crate::network::EgressRule>,
+
+    /// HTTP(S) proxy settings (None = use the registry config default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<crate::network::ProxySettings>,
+
+    /// DNS settings (None = default nameservers).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<crate::network::DnsSettings>,
+
+    /// Private network membership (None = not on a private network).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_network: Option<crate::network::NetworkAttachment>,
 }
 
 fn default_cpus() -> u8 {
@@ -363,8 +379,8 @@ impl VmRecord {
         name: String,
         cpus: u8,
         mem: u32,
-        mounts: Vec<(String, String, bool)>,
-        ports: Vec<(u16, u16)>,
+        mounts: Vec<crate::vm::config::HostMount>,
+        ports: Vec<crate::agent::PortMapping>,
         network: bool,
     ) -> Self {
         Self {
@@ -385,6 +401,10 @@ impl VmRecord {
             workdir: None,
             storage_gb: None,
             overlay_gb: None,
+            network_allow: Vec::new(),
+            proxy: None,
+            dns: None,
+            private_network: None,
         }
     }
 
@@ -393,8 +413,8 @@ impl VmRecord {
         name: String,
         cpus: u8,
         mem: u32,
-        mounts: Vec<(String, String, bool)>,
-        ports: Vec<(u16, u16)>,
+        mounts: Vec<crate::vm::config::HostMount>,
+        ports: Vec<crate::agent::PortMapping>,
         network: bool,
         restart: RestartConfig,
     ) -> Self {
@@ -416,6 +436,10 @@ impl VmRecord {
             workdir: None,
             storage_gb: None,
             overlay_gb: None,
+            network_allow: Vec::new(),
+            proxy: None,
+            dns: None,
+            private_network: None,
         }
     }
 
@@ -444,24 +468,14 @@ impl VmRecord {
         }
     }
 
-    /// Convert stored mounts to HostMount format.
+    /// Get the stored mounts.
     pub fn host_mounts(&self) -> Vec<crate::vm::config::HostMount> {
-        self.mounts
-            .iter()
-            .map(|(host, guest, ro)| crate::vm::config::HostMount {
-                source: std::path::PathBuf::from(host),
-                target: std::path::PathBuf::from(guest),
-                read_only: *ro,
-            })
-            .collect()
+        self.mounts.clone()
     }
 
-    /// Convert stored ports to PortMapping format.
+    /// Get the stored port mappings.
     pub fn port_mappings(&self) -> Vec<crate::agent::PortMapping> {
-        self.ports
-            .iter()
-            .map(|(host, guest)| crate::agent::PortMapping::new(*host, *guest))
-            .collect()
+        self.ports.clone()
     }
 
     /// Convert record fields to VmResources.
@@ -472,6 +486,10 @@ impl VmRecord {
             network: self.network,
             storage_gb: self.storage_gb,
             overlay_gb: self.overlay_gb,
+            network_allow: self.network_allow.clone(),
+            proxy: self.proxy.clone(),
+            dns: self.dns.clone(),
+            private_network: self.private_network.clone(),
         }
     }
 }
@@ -486,8 +504,10 @@ mod tests {
             "test".to_string(),
             2,
             512,
-            vec![("/host".to_string(), "/guest".to_string(), false)],
-            vec![(8080, 80)],
+            vec![crate::vm::config::HostMount::new_writable(
+                "/host", "/guest",
+            )],
+            vec![crate::agent::PortMapping::new(8080, 80)],
             false,
         );
 
@@ -497,6 +517,34 @@ mod tests {
         assert_eq!(deserialized.mounts, record.mounts);
     }
 
+    #[test]
+    fn test_vm_record_keeps_sync_mounts() {
+        use crate::agent::SyncConflictPolicy;
+        use crate::vm::config::HostMount;
+
+        let mounts = vec![
+            HostMount::new_writable("/host/src", "/app").synced(SyncConflictPolicy::Guest),
+            HostMount::new("/host/data", "/data"),
+        ];
+        let record = VmRecord::new("test".to_string(), 1, 512, mounts.clone(), vec![], false);
+
+        let json = serde_json::to_string(&record).unwrap();
+        let deserialized: VmRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.host_mounts(), mounts);
+    }
+
+    #[test]
+    fn test_vm_record_reads_mount_triples() {
+        use crate::vm::config::HostMount;
+
+        let json = r#"{"name":"old","created_at":"0","mounts":[["/host/src","/app",false]]}"#;
+        let record: VmRecord = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            record.host_mounts(),
+            vec![HostMount::new_writable("/host/src", "/app")]
+        );
+    }
+
     #[test]
     fn test_vm_record_with_restart() {
         let restart = RestartConfig {
@@ -8,6 +8,7 @@
 
 use crate::config::VmRecord;
 use crate::error::{Error, Result};
+use crate::volume::VolumeRecord;
 use parking_lot::Mutex;
 use redb::{Database, ReadableTable, TableDefinition, TableError};
 use std::collections::HashMap;
@@ -17,6 +18,9 @@ use std::sync::Arc;
 /// Table for storing VM records (name -> JSON-serialized VmRecord).
 const VMS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("vms");
 
+/// Table for storing named volumes (name -> JSON-serialized VolumeRecord).
+const VOLUMES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("volumes");
+
 /// Table for storing global configuration settings.
 const CONFIG_TABLE: TableDefinition<&str, &str> = TableDefinition::new("config");
 
@@ -113,6 +117,9 @@ impl SmolvmDb {
             write_txn
                 .open_table(CONFIG_TABLE)
                 .db_err("create config table")?;
+            write_txn
+                .open_table(VOLUMES_TABLE)
+                .db_err("create volumes table")?;
             write_txn.commit().db_err("commit table creation")?;
             Ok(())
         })
@@ -300,6 +307,178 @@ impl SmolvmDb {
         })
     }
 
+    // ========================================================================
+    // Volume Operations
+    // ========================================================================
+
+    /// Insert a volume record only if it doesn't already exist.
+    ///
+    /// Returns `Ok(true)` if inserted, `Ok(false)` if already exists.
+    pub fn insert_volume_if_not_exists(&self, name: &str, record: &VolumeRecord) -> Result<bool> {
+        let json = serde_json::to_vec(record).db_err("serialize volume record")?;
+
+        self.with_db(|db| {
+            let write_txn = db.begin_write().db_err("begin write transaction")?;
+
+            let inserted = {
+                let mut table = write_txn
+                    .open_table(VOLUMES_TABLE)
+                    .db_err("open volumes table")?;
+                let exists = table
+                    .get(name)
+                    .db_err(format!("check volume '{}'", name))?
+                    .is_some();
+
+                if exists {
+                    false
+                } else {
+                    table
+                        .insert(name, json.as_slice())
+                        .db_err(format!("insert volume '{}'", name))?;
+                    true
+                }
+            };
+
+            write_txn.commit().db_err("commit volume insert")?;
+            Ok(inserted)
+        })
+    }
+
+    /// Get a volume record by name.
+    pub fn get_volume(&self, name: &str) -> Result<Option<VolumeRecord>> {
+        self.with_db(|db| {
+            let read_txn = db.begin_read().db_err("begin read transaction")?;
+            let table = match read_txn.open_table(VOLUMES_TABLE) {
+                Ok(t) => t,
+                Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+                Err(e) => return Err(Error::database("open volumes table", e.to_string())),
+            };
+
+            match table.get(name) {
+                Ok(Some(guard)) => {
+                    let record: VolumeRecord = serde_json::from_slice(guard.value())
+                        .db_err(format!("deserialize volume record '{}'", name))?;
+                    Ok(Some(record))
+                }
+                Ok(None) => Ok(None),
+                Err(e) => Err(Error::database(
+                    format!("get volume '{}'", name),
+                    e.to_string(),
+                )),
+            }
+        })
+    }
+
+    /// List all volume records.
+    pub fn list_volumes(&self) -> Result<Vec<VolumeRecord>> {
+        self.with_db(|db| {
+            let read_txn = db.begin_read().db_err("begin read transaction")?;
+            let table = match read_txn.open_table(VOLUMES_TABLE) {
+                Ok(t) => t,
+                Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+                Err(e) => return Err(Error::database("open volumes table", e.to_string())),
+            };
+
+            let mut volumes = Vec::new();
+            for entry in table.iter().db_err("iterate volumes table")? {
+                let (key, value) = entry.db_err("read volumes entry")?;
+                let record: VolumeRecord = serde_json::from_slice(value.value())
+                    .db_err(format!("deserialize volume record '{}'", key.value()))?;
+                volumes.push(record);
+            }
+
+            Ok(volumes)
+        })
+    }
+
+    /// Update a volume record in place using a closure.
+    ///
+    /// Returns the updated record if found, `None` if not found.
+    pub fn update_volume<F>(&self, name: &str, f: F) -> Result<Option<VolumeRecord>>
+    where
+        F: FnOnce(&mut VolumeRecord),
+    {
+        self.with_db(|db| {
+            let write_txn = db.begin_write().db_err("begin write transaction")?;
+
+            let updated = {
+                let mut table = write_txn
+                    .open_table(VOLUMES_TABLE)
+                    .db_err("open volumes table")?;
+
+                let record = {
+                    let get_result = table.get(name).db_err(format!("get volume '{}'", name))?;
+                    match get_result {
+                        Some(guard) => {
+                            let r: VolumeRecord = serde_json::from_slice(guard.value())
+                                .db_err(format!("deserialize volume record '{}'", name))?;
+                            Some(r)
+                        }
+                        None => None,
+                    }
+                };
+
+                match record {
+                    Some(mut record) => {
+                        f(&mut record);
+                        let json = serde_json::to_vec(&record).db_err("serialize volume record")?;
+                        table
+                            .insert(name, json.as_slice())
+                            .db_err(format!("update volume '{}'", name))?;
+                        Some(record)
+                    }
+                    None => None,
+                }
+            };
+
+            write_txn.commit().db_err("commit volume update")?;
+            Ok(updated)
+        })
+    }
+
+    /// Remove a volume record if `check` accepts it, returning the removed
+    /// record if it existed.
+    ///
+    /// The check runs in the same write transaction as the removal, so a
+    /// concurrent update can't slip in between.
+    pub fn remove_volume<F>(&self, name: &str, check: F) -> Result<Option<VolumeRecord>>
+    where
+        F: FnOnce(&VolumeRecord) -> Result<()>,
+    {
+        self.with_db(|db| {
+            let write_txn = db.begin_write().db_err("begin write transaction")?;
+
+            let existing = {
+                let mut table = write_txn
+                    .open_table(VOLUMES_TABLE)
+                    .db_err("open volumes table")?;
+
+                let record = {
+                    let get_result = table.get(name).db_err(format!("get volume '{}'", name))?;
+                    match get_result {
+                        Some(guard) => {
+                            let r: VolumeRecord = serde_json::from_slice(guard.value())
+                                .db_err(format!("deserialize volume record '{}'", name))?;
+                            Some(r)
+                        }
+                        None => None,
+                    }
+                };
+
+                if let Some(record) = &record {
+                    check(record)?;
+                    table
+                        .remove(name)
+                        .db_err(format!("remove volume '{}'", name))?;
+                }
+                record
+            };
+
+            write_txn.commit().db_err("commit volume removal")?;
+            Ok(existing)
+        })
+    }
+
     /// Load all VMs into an in-memory HashMap (for compatibility layer).
     pub fn load_all_vms(&self) -> Result<HashMap<String, VmRecord>> {
         let vms = self.list_vms()?;
@@ -436,8 +615,10 @@ mod tests {
             "test-vm".to_string(),
             2,
             1024,
-            vec![("/host".to_string(), "/guest".to_string(), false)],
-            vec![(8080, 80)],
+            vec![crate::vm::config::HostMount::new_writable(
+                "/host", "/guest",
+            )],
+            vec![crate::agent::PortMapping::new(8080, 80)],
             false,
         );
 
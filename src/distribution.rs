@@ -0,0 +1,1142 @@
+//! OCI distribution API client.
+//!
+//! A small blocking client covering what `smolvm image push` needs: blob
+//! existence checks, cross-repository blob mounts, monolithic blob uploads
+//! and manifest uploads.
+//!
+//! Authentication follows the registry token flow: a `401` with a
+//! `WWW-Authenticate: Bearer ...` challenge is answered by fetching a token
+//! from the advertised realm using credentials from [`RegistryConfig`].
+//! Registries that challenge with `Basic` get the credentials directly.
+//!
+//! [`save_image`] writes the same blobs to a tarball instead of a registry,
+//! for `smolvm image save`.
+
+use crate::agent::AgentClient;
+use crate::error::{Error, Result};
+use crate::registry::{extract_registry, RegistryAuth, RegistryConfig, DEFAULT_REGISTRY};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use smolvm_protocol::{BlobDescriptor, LayerCompression, PushPlan};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// API host serving Docker Hub (`docker.io` is only the image namespace).
+const DOCKER_HUB_API_HOST: &str = "registry-1.docker.io";
+
+/// Connect timeout for registry requests.
+const CONNECT_TIMEOUT_SECS: u64 = 30;
+
+/// Read timeout for registry requests. Large blob uploads only complete
+/// once the registry has verified the digest, which can take a while.
+const READ_TIMEOUT_SECS: u64 = 600;
+
+// ============================================================================
+// Image References
+// ============================================================================
+
+/// A parsed image reference (`registry/repository[:tag][@digest]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    /// Registry hostname (e.g., "docker.io", "localhost:5000").
+    pub registry: String,
+    /// Repository path (e.g., "library/alpine", "owner/repo").
+    pub repository: String,
+    /// Tag, if any. Defaults to "latest" when neither tag nor digest is given.
+    pub tag: Option<String>,
+    /// Manifest digest, if any.
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    /// Parse an image reference, applying Docker Hub defaults.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let r = ImageReference::parse("alpine")?;
+    /// assert_eq!(r.to_string(), "docker.io/library/alpine:latest");
+    /// ```
+    pub fn parse(reference: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            Error::registry(
+                "parse image reference",
+                format!("{}: {}", reason, reference),
+            )
+        };
+
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (reference, None),
+        };
+
+        // A ':' after the last '/' separates the tag; earlier ones are ports.
+        let (name, tag) = match name.rfind(':') {
+            Some(i) if !name[i + 1..].contains('/') => {
+                (&name[..i], Some(name[i + 1..].to_string()))
+            }
+            _ => (name, None),
+        };
+
+        let first = name.split('/').next().unwrap_or_default();
+        let registry = if first == "localhost" && name.contains('/') {
+            first.to_string()
+        } else {
+            extract_registry(name)
+        };
+        let mut repository = name
+            .strip_prefix(&format!("{}/", registry))
+            .unwrap_or(name)
+            .to_string();
+        if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            repository = format!("library/{}", repository);
+        }
+
+        if repository.is_empty()
+            || repository.split('/').any(str::is_empty)
+            || repository.chars().any(|c| c.is_ascii_uppercase())
+        {
+            return Err(invalid("invalid repository name"));
+        }
+        if tag.as_deref() == Some("") || digest.as_deref() == Some("") {
+            return Err(invalid("empty tag or digest"));
+        }
+
+        let tag = if tag.is_none() && digest.is_none() {
+            Some("latest".to_string())
+        } else {
+            tag
+        };
+
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// The tag or digest to address the manifest by (digest wins).
+    pub fn reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+}
+
+impl std::fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Base URL of the registry API for a registry hostname.
+///
+/// Loopback registries are assumed to speak plain HTTP, matching Docker's
+/// default for `localhost`.
+pub fn registry_endpoint(registry: &str) -> String {
+    let host = if registry == DEFAULT_REGISTRY {
+        DOCKER_HUB_API_HOST
+    } else {
+        registry
+    };
+    let loopback =
+        host == "localhost" || host.starts_with("localhost:") || host.starts_with("127.");
+    format!("{}://{}", if loopback { "http" } else { "https" }, host)
+}
+
+// ============================================================================
+// Registry Client
+// ============================================================================
+
+/// Request body for [`RegistryClient::send`]. Borrowed so that requests can
+/// be replayed after an authentication challenge.
+#[derive(Clone, Copy)]
+enum Body<'a> {
+    Empty,
+    Bytes(&'a [u8]),
+    File(&'a Path, u64),
+}
+
+/// Blocking client for one repository on an OCI registry.
+pub struct RegistryClient {
+    http: ureq::Agent,
+    endpoint: String,
+    repository: String,
+    auth: Option<RegistryAuth>,
+    /// Authorization header value obtained from the last challenge.
+    authorization: Option<String>,
+}
+
+impl RegistryClient {
+    /// Create a client for the repository named by `reference`.
+    pub fn new(reference: &ImageReference, auth: Option<RegistryAuth>) -> Self {
+        Self::with_endpoint(
+            registry_endpoint(&reference.registry),
+            &reference.repository,
+            auth,
+        )
+    }
+
+    /// Create a client for `repository` on an explicit API endpoint
+    /// (e.g., "http://127.0.0.1:5000").
+    pub fn with_endpoint(
+        endpoint: impl Into<String>,
+        repository: impl Into<String>,
+        auth: Option<RegistryAuth>,
+    ) -> Self {
+        let http = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .timeout_read(Duration::from_secs(READ_TIMEOUT_SECS))
+            .build();
+        Self {
+            http,
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            repository: repository.into(),
+            auth,
+            authorization: None,
+        }
+    }
+
+    /// Check whether a blob already exists in the repository.
+    pub fn blob_exists(&mut self, digest: &str) -> Result<bool> {
+        let url = format!("{}/v2/{}/blobs/{}", self.endpoint, self.repository, digest);
+        let resp = self.send("HEAD", &url, &[], Body::Empty)?;
+        match resp.status() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(status_error("check blob", resp)),
+        }
+    }
+
+    /// Try to mount a blob from another repository on the same registry.
+    ///
+    /// Returns `false` if the registry declined the mount (the blob must then
+    /// be uploaded).
+    pub fn mount_blob(&mut self, digest: &str, from: &str) -> Result<bool> {
+        let url = format!(
+            "{}/v2/{}/blobs/uploads/?mount={}&from={}",
+            self.endpoint, self.repository, digest, from
+        );
+        let resp = self.send("POST", &url, &[], Body::Empty)?;
+        match resp.status() {
+            201 => Ok(true),
+            // The registry opened a regular upload session instead.
+            202 => Ok(false),
+            _ => Err(status_error("mount blob", resp)),
+        }
+    }
+
+    /// Upload a blob from a file in a single request.
+    pub fn upload_blob(&mut self, digest: &str, path: &Path, size: u64) -> Result<()> {
+        let url = format!("{}/v2/{}/blobs/uploads/", self.endpoint, self.repository);
+        let resp = self.send("POST", &url, &[], Body::Empty)?;
+        if resp.status() != 202 {
+            return Err(status_error("start upload", resp));
+        }
+        let location = resp
+            .header("Location")
+            .ok_or_else(|| Error::registry("start upload", "response has no Location header"))?;
+
+        let mut url = if location.starts_with('/') {
+            format!("{}{}", self.endpoint, location)
+        } else {
+            location.to_string()
+        };
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str("digest=");
+        url.push_str(digest);
+
+        let resp = self.send(
+            "PUT",
+            &url,
+            &[("Content-Type", "application/octet-stream")],
+            Body::File(path, size),
+        )?;
+        match resp.status() {
+            201 | 204 => Ok(()),
+            _ => Err(status_error("upload blob", resp)),
+        }
+    }
+
+    /// Upload a manifest under a tag or digest.
+    pub fn put_manifest(&mut self, reference: &str, media_type: &str, body: &[u8]) -> Result<()> {
+        let url = format!(
+            "{}/v2/{}/manifests/{}",
+            self.endpoint, self.repository, reference
+        );
+        let resp = self.send(
+            "PUT",
+            &url,
+            &[("Content-Type", media_type)],
+            Body::Bytes(body),
+        )?;
+        match resp.status() {
+            200 | 201 | 204 => Ok(()),
+            _ => Err(status_error("put manifest", resp)),
+        }
+    }
+
+    /// Send a request, answering at most one authentication challenge.
+    ///
+    /// Error statuses other than the challenge are returned as responses so
+    /// callers can interpret them (e.g. 404 from a blob HEAD).
+    fn send(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+    ) -> Result<ureq::Response> {
+        let mut challenged = false;
+        loop {
+            let mut req = self.http.request(method, url);
+            for (name, value) in headers {
+                req = req.set(name, value);
+            }
+            if let Some(authorization) = &self.authorization {
+                req = req.set("Authorization", authorization);
+            }
+
+            let result = match body {
+                Body::Empty => req.call(),
+                Body::Bytes(bytes) => req.send_bytes(bytes),
+                Body::File(path, size) => {
+                    let file = File::open(path)?;
+                    req.set("Content-Length", &size.to_string()).send(file)
+                }
+            };
+
+            match result {
+                Ok(resp) => return Ok(resp),
+                Err(ureq::Error::Status(401, resp)) if !challenged => {
+                    let challenge = resp.header("WWW-Authenticate").unwrap_or("").to_string();
+                    self.authorize(&challenge)?;
+                    challenged = true;
+                }
+                Err(ureq::Error::Status(_, resp)) => return Ok(resp),
+                Err(e) => {
+                    return Err(Error::registry(
+                        format!("{} {}", method, url),
+                        e.to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Answer a `WWW-Authenticate` challenge, updating `self.authorization`.
+    fn authorize(&mut self, challenge: &str) -> Result<()> {
+        let (scheme, params) = parse_challenge(challenge);
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let auth = self.auth.as_ref().ok_or_else(|| {
+                Error::registry(
+                    "authenticate",
+                    "registry requires credentials; add them to ~/.config/smolvm/registries.toml",
+                )
+            })?;
+            self.authorization = Some(basic_authorization(auth));
+            return Ok(());
+        }
+
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(Error::registry(
+                "authenticate",
+                format!("unsupported authentication challenge: {:?}", challenge),
+            ));
+        }
+
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| Error::registry("authenticate", "bearer challenge has no realm"))?;
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull,push", self.repository));
+
+        let mut req = self.http.get(realm).query("scope", &scope);
+        if let Some(service) = params.get("service") {
+            req = req.query("service", service);
+        }
+        if let Some(auth) = &self.auth {
+            req = req.set("Authorization", &basic_authorization(auth));
+        }
+
+        let resp = req.call().map_err(|e| match e {
+            ureq::Error::Status(code, _) => Error::registry(
+                "fetch token",
+                format!(
+                    "token endpoint returned {} (check registry credentials)",
+                    code
+                ),
+            ),
+            e => Error::registry("fetch token", e.to_string()),
+        })?;
+
+        #[derive(serde::Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let body = resp.into_string()?;
+        let token: TokenResponse = serde_json::from_str(&body)
+            .map_err(|e| Error::registry("fetch token", format!("invalid response: {}", e)))?;
+        let token = token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| Error::registry("fetch token", "response has no token"))?;
+
+        self.authorization = Some(format!("Bearer {}", token));
+        Ok(())
+    }
+}
+
+/// Build an error from an unexpected registry response.
+fn status_error(operation: &str, resp: ureq::Response) -> Error {
+    let status = resp.status();
+    let body = resp.into_string().unwrap_or_default();
+    let body = body.trim();
+    if body.is_empty() {
+        Error::registry(operation, format!("unexpected status {}", status))
+    } else {
+        Error::registry(operation, format!("unexpected status {}: {}", status, body))
+    }
+}
+
+fn basic_authorization(auth: &RegistryAuth) -> String {
+    let credentials = format!("{}:{}", auth.username, auth.password);
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(credentials)
+    )
+}
+
+/// Parse a `WWW-Authenticate` header into its scheme and parameters.
+///
+/// Parameter values may be quoted and contain commas
+/// (`scope="repository:a/b:pull,push"`).
+fn parse_challenge(header: &str) -> (String, HashMap<String, String>) {
+    let header = header.trim();
+    let (scheme, mut rest) = header.split_once(' ').unwrap_or((header, ""));
+    let mut params = HashMap::new();
+
+    loop {
+        rest = rest.trim_start_matches([' ', ',']);
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            }
+        } else {
+            match after.find(',') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            }
+        };
+        params.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = remaining;
+    }
+
+    (scheme.to_string(), params)
+}
+
+// ============================================================================
+// Push
+// ============================================================================
+
+/// What happened to a blob during a push.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobPushStatus {
+    /// The registry already had the blob.
+    Exists,
+    /// The blob was mounted from another repository on the same registry.
+    Mounted,
+    /// The blob was uploaded.
+    Uploaded,
+}
+
+impl std::fmt::Display for BlobPushStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BlobPushStatus::Exists => "already exists",
+            BlobPushStatus::Mounted => "mounted",
+            BlobPushStatus::Uploaded => "pushed",
+        })
+    }
+}
+
+/// Push an image from an agent's storage to a registry.
+///
+/// `image` is the local image reference and `target` the destination.
+/// Credentials for the target registry come from [`RegistryConfig`]. When
+/// the image was pulled from another repository on the same registry, blobs
+/// are mounted from it instead of uploaded where possible.
+///
+/// Returns the digest of the pushed manifest.
+pub fn push_image<F: FnMut(&BlobDescriptor, BlobPushStatus)>(
+    client: &mut AgentClient,
+    image: &str,
+    target: &ImageReference,
+    compression: LayerCompression,
+    progress: F,
+) -> Result<String> {
+    let plan = client.prepare_push(image, compression)?;
+
+    let registry_config = RegistryConfig::load().unwrap_or_default();
+    let auth = registry_config.get_credentials(&target.registry);
+    let mut registry = RegistryClient::new(target, auth);
+
+    let mount_from = ImageReference::parse(image)
+        .ok()
+        .filter(|source| {
+            source.registry == target.registry && source.repository != target.repository
+        })
+        .map(|source| source.repository);
+
+    push_plan(
+        &mut registry,
+        &plan,
+        target.reference(),
+        mount_from.as_deref(),
+        |digest, out| client.export_blob(digest, out).map(|_| ()),
+        progress,
+    )
+}
+
+/// Upload the blobs and manifest described by a [`PushPlan`].
+///
+/// `fetch` writes a blob's contents to the given file; each fetched blob is
+/// verified against its descriptor before upload. Returns the digest of the
+/// pushed manifest.
+pub fn push_plan<B, F>(
+    registry: &mut RegistryClient,
+    plan: &PushPlan,
+    reference: &str,
+    mount_from: Option<&str>,
+    mut fetch: B,
+    mut progress: F,
+) -> Result<String>
+where
+    B: FnMut(&str, &mut File) -> Result<()>,
+    F: FnMut(&BlobDescriptor, BlobPushStatus),
+{
+    let tmp = tempfile::tempdir()?;
+
+    for blob in plan.layers.iter().chain(std::iter::once(&plan.config)) {
+        if registry.blob_exists(&blob.digest)? {
+            progress(blob, BlobPushStatus::Exists);
+            continue;
+        }
+
+        if let Some(from) = mount_from {
+            if registry.mount_blob(&blob.digest, from)? {
+                progress(blob, BlobPushStatus::Mounted);
+                continue;
+            }
+        }
+
+        let path = fetch_verified(tmp.path(), blob, &mut fetch)?;
+        registry.upload_blob(&blob.digest, &path, blob.size)?;
+        let _ = std::fs::remove_file(&path);
+        progress(blob, BlobPushStatus::Uploaded);
+    }
+
+    registry.put_manifest(
+        reference,
+        &plan.manifest_media_type,
+        plan.manifest.as_bytes(),
+    )?;
+
+    Ok(format!(
+        "sha256:{:x}",
+        Sha256::digest(plan.manifest.as_bytes())
+    ))
+}
+
+/// Fetch a blob into `dir` and check it against its descriptor.
+fn fetch_verified<B>(dir: &Path, blob: &BlobDescriptor, fetch: &mut B) -> Result<PathBuf>
+where
+    B: FnMut(&str, &mut File) -> Result<()>,
+{
+    let path = dir.join(blob.digest.replace(':', "-"));
+    let mut file = File::create(&path)?;
+    fetch(&blob.digest, &mut file)?;
+    file.flush()?;
+    drop(file);
+
+    let (digest, size) = file_digest(&path)?;
+    if digest != blob.digest || size != blob.size {
+        return Err(Error::registry(
+            "verify blob",
+            format!(
+                "blob {} does not match its descriptor (got {} with {} bytes, expected {} bytes)",
+                blob.digest, digest, size, blob.size
+            ),
+        ));
+    }
+    Ok(path)
+}
+
+// ============================================================================
+// Image Archives
+// ============================================================================
+
+/// Annotation holding the full image name (containerd, Docker 25+).
+const ANNOTATION_IMAGE_NAME: &str = "io.containerd.image.name";
+
+/// Standard OCI annotation holding the image reference name.
+const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Write an image from an agent's storage to a tar archive.
+///
+/// The archive is both an OCI image layout (`oci-layout`, `index.json`) and
+/// a `docker save` archive (`manifest.json`), the combined format written by
+/// Docker 25+. It loads with `docker load`, `podman load`, skopeo's
+/// `oci-archive:` transport and `smolvm image load`. Layers are gzipped.
+pub fn save_image(client: &mut AgentClient, image: &str, out: impl Write) -> Result<()> {
+    let plan = client.prepare_push(image, LayerCompression::Gzip)?;
+    write_image_archive(&plan, image, out, |digest, file| {
+        client.export_blob(digest, file).map(|_| ())
+    })
+}
+
+/// Write the blobs described by a [`PushPlan`] as an image archive.
+///
+/// `fetch` writes a blob's contents to the given file; each blob is
+/// verified against its descriptor before it is added.
+pub fn write_image_archive<B>(
+    plan: &PushPlan,
+    image: &str,
+    out: impl Write,
+    mut fetch: B,
+) -> Result<()>
+where
+    B: FnMut(&str, &mut File) -> Result<()>,
+{
+    let tmp = tempfile::tempdir()?;
+    let mut archive = tar::Builder::new(out);
+
+    let blob_name = |digest: &str| format!("blobs/sha256/{}", digest.trim_start_matches("sha256:"));
+
+    for blob in std::iter::once(&plan.config).chain(&plan.layers) {
+        let path = fetch_verified(tmp.path(), blob, &mut fetch)?;
+        let mut header = archive_header(blob.size);
+        archive.append_data(&mut header, blob_name(&blob.digest), File::open(&path)?)?;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let manifest_digest = format!("sha256:{:x}", Sha256::digest(plan.manifest.as_bytes()));
+    append_file(
+        &mut archive,
+        &blob_name(&manifest_digest),
+        plan.manifest.as_bytes(),
+    )?;
+
+    let mut annotations = serde_json::Map::new();
+    let mut repo_tags = Vec::new();
+    if let Ok(reference) = ImageReference::parse(image) {
+        annotations.insert(ANNOTATION_IMAGE_NAME.into(), reference.to_string().into());
+        annotations.insert(ANNOTATION_REF_NAME.into(), reference.reference().into());
+        if reference.digest.is_none() {
+            // docker load wants name:tag; keep the name as the user wrote it.
+            let has_tag = image
+                .rsplit('/')
+                .next()
+                .is_some_and(|last| last.contains(':'));
+            repo_tags.push(if has_tag {
+                image.to_string()
+            } else {
+                format!("{}:latest", image)
+            });
+        }
+    }
+
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [{
+            "mediaType": plan.manifest_media_type,
+            "digest": manifest_digest,
+            "size": plan.manifest.len(),
+            "annotations": annotations,
+        }],
+    });
+    let docker_manifest = serde_json::json!([{
+        "Config": blob_name(&plan.config.digest),
+        "RepoTags": repo_tags,
+        "Layers": plan.layers.iter().map(|l| blob_name(&l.digest)).collect::<Vec<_>>(),
+    }]);
+
+    append_file(
+        &mut archive,
+        "oci-layout",
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    append_file(&mut archive, "index.json", index.to_string().as_bytes())?;
+    append_file(
+        &mut archive,
+        "manifest.json",
+        docker_manifest.to_string().as_bytes(),
+    )?;
+
+    archive.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Tar header for an archive member; fixed metadata keeps output stable.
+fn archive_header(size: u64) -> tar::Header {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header
+}
+
+fn append_file<W: Write>(archive: &mut tar::Builder<W>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = archive_header(data.len() as u64);
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Compute the `sha256:` digest and size of a file.
+fn file_digest(path: &Path) -> Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    // ------------------------------------------------------------------------
+    // Local registry stand-in
+    // ------------------------------------------------------------------------
+
+    /// Minimal in-process registry implementing the push endpoints.
+    struct MockRegistry {
+        endpoint: String,
+        state: Arc<Mutex<MockState>>,
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        /// Required basic credentials; when set, requests need a bearer token.
+        credentials: Option<(String, String)>,
+        /// (repository, digest) pairs present in the registry.
+        blobs: HashSet<(String, String)>,
+        /// (repository, reference) -> manifest body.
+        manifests: HashMap<(String, String), Vec<u8>>,
+        uploads: usize,
+        next_session: usize,
+    }
+
+    const MOCK_TOKEN: &str = "mock-token";
+
+    impl MockRegistry {
+        fn start(credentials: Option<(&str, &str)>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let state = Arc::new(Mutex::new(MockState {
+                credentials: credentials.map(|(u, p)| (u.to_string(), p.to_string())),
+                ..Default::default()
+            }));
+
+            let thread_state = state.clone();
+            let thread_endpoint = endpoint.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let _ = handle_mock_request(stream, &thread_state, &thread_endpoint);
+                }
+            });
+
+            Self { endpoint, state }
+        }
+
+        fn client(&self, repository: &str, auth: Option<RegistryAuth>) -> RegistryClient {
+            RegistryClient::with_endpoint(&self.endpoint, repository, auth)
+        }
+    }
+
+    fn handle_mock_request(
+        stream: std::net::TcpStream,
+        state: &Mutex<MockState>,
+        endpoint: &str,
+    ) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((k, v)) = header.split_once(':') {
+                headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+            }
+        }
+        let len: usize = headers
+            .get("content-length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body)?;
+
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let query: HashMap<&str, &str> = query
+            .split('&')
+            .filter_map(|kv| kv.split_once('='))
+            .collect();
+
+        let mut state = state.lock().unwrap();
+        let (status, extra, payload) =
+            mock_route(&mut state, &method, path, &query, &headers, &body, endpoint);
+
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status,
+            payload.len()
+        )?;
+        for (k, v) in extra {
+            write!(stream, "{}: {}\r\n", k, v)?;
+        }
+        write!(stream, "\r\n{}", payload)?;
+        stream.flush()
+    }
+
+    fn mock_route(
+        state: &mut MockState,
+        method: &str,
+        path: &str,
+        query: &HashMap<&str, &str>,
+        headers: &HashMap<String, String>,
+        body: &[u8],
+        endpoint: &str,
+    ) -> (u16, Vec<(&'static str, String)>, String) {
+        let authorization = headers.get("authorization").cloned().unwrap_or_default();
+
+        if path == "/token" {
+            let (user, pass) = state.credentials.clone().unwrap_or_default();
+            return if authorization
+                == basic_authorization(&RegistryAuth {
+                    username: user,
+                    password: pass,
+                }) {
+                (200, vec![], format!(r#"{{"token":"{}"}}"#, MOCK_TOKEN))
+            } else {
+                (401, vec![], String::new())
+            };
+        }
+
+        let Some(rest) = path.strip_prefix("/v2/") else {
+            return (404, vec![], String::new());
+        };
+        let (repo, kind, tail) = if let Some((repo, tail)) = rest.split_once("/blobs/uploads/") {
+            (repo, "uploads", tail)
+        } else if let Some((repo, tail)) = rest.split_once("/blobs/") {
+            (repo, "blobs", tail)
+        } else if let Some((repo, tail)) = rest.split_once("/manifests/") {
+            (repo, "manifests", tail)
+        } else {
+            return (404, vec![], String::new());
+        };
+
+        if state.credentials.is_some() && authorization != format!("Bearer {}", MOCK_TOKEN) {
+            let challenge = format!(
+                r#"Bearer realm="{}/token",service="mock",scope="repository:{}:pull,push""#,
+                endpoint, repo
+            );
+            return (401, vec![("WWW-Authenticate", challenge)], String::new());
+        }
+
+        match (method, kind) {
+            ("HEAD", "blobs") => {
+                let exists = state.blobs.contains(&(repo.to_string(), tail.to_string()));
+                (if exists { 200 } else { 404 }, vec![], String::new())
+            }
+            ("POST", "uploads") => {
+                if let (Some(digest), Some(from)) = (query.get("mount"), query.get("from")) {
+                    if state
+                        .blobs
+                        .contains(&(from.to_string(), digest.to_string()))
+                    {
+                        state.blobs.insert((repo.to_string(), digest.to_string()));
+                        return (201, vec![], String::new());
+                    }
+                }
+                state.next_session += 1;
+                let location = format!("/v2/{}/blobs/uploads/{}", repo, state.next_session);
+                (202, vec![("Location", location)], String::new())
+            }
+            ("PUT", "uploads") => {
+                let digest = query.get("digest").copied().unwrap_or_default();
+                if format!("sha256:{:x}", Sha256::digest(body)) != digest {
+                    return (400, vec![], "DIGEST_INVALID".to_string());
+                }
+                state.uploads += 1;
+                state.blobs.insert((repo.to_string(), digest.to_string()));
+                (201, vec![], String::new())
+            }
+            ("PUT", "manifests") => {
+                state
+                    .manifests
+                    .insert((repo.to_string(), tail.to_string()), body.to_vec());
+                (201, vec![], String::new())
+            }
+            _ => (405, vec![], String::new()),
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Helpers
+    // ------------------------------------------------------------------------
+
+    fn descriptor(media_type: &str, data: &[u8]) -> BlobDescriptor {
+        BlobDescriptor {
+            media_type: media_type.to_string(),
+            digest: format!("sha256:{:x}", Sha256::digest(data)),
+            size: data.len() as u64,
+        }
+    }
+
+    /// Build a plan plus a blob lookup table for `fetch`.
+    fn sample_plan() -> (PushPlan, HashMap<String, Vec<u8>>) {
+        let layers: Vec<Vec<u8>> = vec![b"layer one".to_vec(), b"layer two".to_vec()];
+        let config = br#"{"architecture":"arm64","os":"linux"}"#.to_vec();
+
+        let mut blobs = HashMap::new();
+        let layer_descs: Vec<_> = layers
+            .iter()
+            .map(|l| {
+                let d = descriptor(LayerCompression::Gzip.media_type(), l);
+                blobs.insert(d.digest.clone(), l.clone());
+                d
+            })
+            .collect();
+        let config_desc = descriptor("application/vnd.oci.image.config.v1+json", &config);
+        blobs.insert(config_desc.digest.clone(), config);
+
+        let plan = PushPlan {
+            manifest: r#"{"schemaVersion":2}"#.to_string(),
+            manifest_media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+            config: config_desc,
+            layers: layer_descs,
+        };
+        (plan, blobs)
+    }
+
+    fn push(
+        registry: &mut RegistryClient,
+        plan: &PushPlan,
+        blobs: &HashMap<String, Vec<u8>>,
+        mount_from: Option<&str>,
+    ) -> Result<Vec<BlobPushStatus>> {
+        let mut statuses = Vec::new();
+        push_plan(
+            registry,
+            plan,
+            "v1",
+            mount_from,
+            |digest, out| Ok(out.write_all(&blobs[digest])?),
+            |_, status| statuses.push(status),
+        )?;
+        Ok(statuses)
+    }
+
+    // ------------------------------------------------------------------------
+    // Tests
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn test_parse_image_reference() {
+        let r = ImageReference::parse("alpine").unwrap();
+        assert_eq!(r.registry, "docker.io");
+        assert_eq!(r.repository, "library/alpine");
+        assert_eq!(r.reference(), "latest");
+        assert_eq!(r.to_string(), "docker.io/library/alpine:latest");
+
+        let r = ImageReference::parse("localhost:5000/team/app:1.2").unwrap();
+        assert_eq!(r.registry, "localhost:5000");
+        assert_eq!(r.repository, "team/app");
+        assert_eq!(r.tag.as_deref(), Some("1.2"));
+
+        let r = ImageReference::parse("localhost/app").unwrap();
+        assert_eq!(r.registry, "localhost");
+        assert_eq!(r.repository, "app");
+
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let r = ImageReference::parse(&format!("ghcr.io/owner/repo@{}", digest)).unwrap();
+        assert_eq!(r.tag, None);
+        assert_eq!(r.reference(), digest);
+
+        assert!(ImageReference::parse("ghcr.io/Owner/repo").is_err());
+        assert!(ImageReference::parse("alpine:").is_err());
+        assert!(ImageReference::parse("ghcr.io//repo").is_err());
+    }
+
+    #[test]
+    fn test_registry_endpoint() {
+        assert_eq!(
+            registry_endpoint("docker.io"),
+            "https://registry-1.docker.io"
+        );
+        assert_eq!(registry_endpoint("ghcr.io"), "https://ghcr.io");
+        assert_eq!(registry_endpoint("localhost:5000"), "http://localhost:5000");
+        assert_eq!(registry_endpoint("127.0.0.1:5000"), "http://127.0.0.1:5000");
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let (scheme, params) = parse_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull,push""#,
+        );
+        assert_eq!(scheme, "Bearer");
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/alpine:pull,push");
+
+        let (scheme, params) = parse_challenge(r#"Basic realm=registry"#);
+        assert_eq!(scheme, "Basic");
+        assert_eq!(params["realm"], "registry");
+    }
+
+    #[test]
+    fn test_push_uploads_missing_blobs_only() {
+        let mock = MockRegistry::start(None);
+        let (plan, blobs) = sample_plan();
+
+        let mut registry = mock.client("team/app", None);
+        let statuses = push(&mut registry, &plan, &blobs, None).unwrap();
+        assert_eq!(statuses, vec![BlobPushStatus::Uploaded; 3]);
+
+        let statuses = push(&mut registry, &plan, &blobs, None).unwrap();
+        assert_eq!(statuses, vec![BlobPushStatus::Exists; 3]);
+
+        let state = mock.state.lock().unwrap();
+        assert_eq!(state.uploads, 3);
+        assert_eq!(
+            state.manifests[&("team/app".to_string(), "v1".to_string())],
+            plan.manifest.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_push_mounts_from_source_repository() {
+        let mock = MockRegistry::start(None);
+        let (plan, blobs) = sample_plan();
+
+        push(&mut mock.client("team/base", None), &plan, &blobs, None).unwrap();
+        let statuses = push(
+            &mut mock.client("team/app", None),
+            &plan,
+            &blobs,
+            Some("team/base"),
+        )
+        .unwrap();
+        assert_eq!(statuses, vec![BlobPushStatus::Mounted; 3]);
+        assert_eq!(mock.state.lock().unwrap().uploads, 3);
+    }
+
+    #[test]
+    fn test_push_with_token_auth() {
+        let mock = MockRegistry::start(Some(("user", "secret")));
+        let (plan, blobs) = sample_plan();
+
+        let auth = RegistryAuth {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        };
+        let mut registry = mock.client("team/app", Some(auth));
+        let statuses = push(&mut registry, &plan, &blobs, None).unwrap();
+        assert_eq!(statuses, vec![BlobPushStatus::Uploaded; 3]);
+
+        let wrong = RegistryAuth {
+            username: "user".to_string(),
+            password: "wrong".to_string(),
+        };
+        let mut registry = mock.client("team/app", Some(wrong));
+        let err = push(&mut registry, &plan, &blobs, None).unwrap_err();
+        assert!(err.to_string().contains("fetch token"), "{}", err);
+    }
+
+    #[test]
+    fn test_push_rejects_corrupt_blob() {
+        let mock = MockRegistry::start(None);
+        let (plan, mut blobs) = sample_plan();
+        blobs.insert(plan.layers[0].digest.clone(), b"tampered".to_vec());
+
+        let mut registry = mock.client("team/app", None);
+        let err = push(&mut registry, &plan, &blobs, None).unwrap_err();
+        assert!(err.to_string().contains("verify blob"), "{}", err);
+
+        let state = mock.state.lock().unwrap();
+        assert_eq!(state.uploads, 0);
+        assert!(state.manifests.is_empty());
+    }
+
+    #[test]
+    fn test_write_image_archive() {
+        let (plan, blobs) = sample_plan();
+        let mut out = Vec::new();
+        write_image_archive(&plan, "team/app", &mut out, |digest, file| {
+            Ok(file.write_all(&blobs[digest])?)
+        })
+        .unwrap();
+
+        let mut entries = HashMap::new();
+        let mut archive = tar::Archive::new(&out[..]);
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            entries.insert(name, data);
+        }
+
+        let blob = |digest: &str| entries[&format!("blobs/sha256/{}", &digest[7..])].clone();
+        assert_eq!(blob(&plan.layers[1].digest), b"layer two");
+        assert_eq!(blob(&plan.config.digest), blobs[&plan.config.digest]);
+
+        let index: serde_json::Value = serde_json::from_slice(&entries["index.json"]).unwrap();
+        let desc = &index["manifests"][0];
+        let manifest_digest = desc["digest"].as_str().unwrap();
+        assert_eq!(blob(manifest_digest), plan.manifest.as_bytes());
+        assert_eq!(
+            desc["annotations"][ANNOTATION_IMAGE_NAME],
+            "docker.io/team/app:latest"
+        );
+
+        let docker: serde_json::Value = serde_json::from_slice(&entries["manifest.json"]).unwrap();
+        assert_eq!(docker[0]["RepoTags"][0], "team/app:latest");
+        assert_eq!(docker[0]["Layers"].as_array().unwrap().len(), 2);
+        assert!(entries.contains_key("oci-layout"));
+    }
+}
@@ -201,6 +201,30 @@ pub enum Error {
         kind: AgentErrorKind,
     },
 
+    // ========================================================================
+    // Registry Errors
+    // ========================================================================
+    /// OCI registry operation failed.
+    #[error("registry operation failed: {operation}: {reason}")]
+    Registry {
+        /// The operation that failed (e.g., "upload blob", "put manifest").
+        operation: String,
+        /// The reason for the failure.
+        reason: String,
+    },
+
+    // ========================================================================
+    // Build Errors
+    // ========================================================================
+    /// Image build failed.
+    #[error("build failed: {operation}: {reason}")]
+    Build {
+        /// The operation that failed (e.g., "parse Dockerfile", "RUN").
+        operation: String,
+        /// The reason for the failure.
+        reason: String,
+    },
+
     // ========================================================================
     // KVM Errors (Linux)
     // ========================================================================
@@ -347,6 +371,30 @@ impl Error {
         }
     }
 
+    // ========================================================================
+    // Registry Error Constructors
+    // ========================================================================
+
+    /// Create a registry operation error.
+    pub fn registry(operation: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Registry {
+            operation: operation.into(),
+            reason: reason.into(),
+        }
+    }
+
+    // ========================================================================
+    // Build Error Constructors
+    // ========================================================================
+
+    /// Create an image build error.
+    pub fn build(operation: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Build {
+            operation: operation.into(),
+            reason: reason.into(),
+        }
+    }
+
     // ========================================================================
     // KVM Error Constructors
     // ========================================================================
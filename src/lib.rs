@@ -47,6 +47,7 @@
 //! - Network egress via NAT
 //! - vsock control channel
 //! - Persistent overlay disks
+//! - Named volumes (ext4 images or managed directories)
 //! - `exec` into running VMs
 //!
 //! # Platform Support
@@ -63,9 +64,11 @@
 
 pub mod agent;
 pub mod api;
+pub mod build;
 pub mod config;
 pub mod consts;
 pub mod db;
+pub mod distribution;
 pub mod error;
 pub mod log_rotation;
 pub mod mount;
@@ -76,6 +79,7 @@ pub mod registry;
 pub mod storage;
 pub mod util;
 pub mod vm;
+pub mod volume;
 
 // ============================================================================
 // Default Command Constants
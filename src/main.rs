@@ -39,6 +39,25 @@ enum Commands {
     #[command(subcommand, visible_alias = "ct")]
     Container(cli::container::ContainerCmd),
 
+    /// Manage images stored in a microVM (push, save, load, rm)
+    #[command(subcommand)]
+    Image(cli::image::ImageCmd),
+
+    /// Build an image from a Dockerfile inside a microVM
+    Build(cli::build::BuildCmd),
+
+    /// Manage private networks between microVMs
+    #[command(subcommand)]
+    Network(cli::network::NetworkCmd),
+
+    /// Manage named volumes
+    #[command(subcommand)]
+    Volume(cli::volume::VolumeCmd),
+
+    /// Inspect microVM storage usage
+    #[command(subcommand)]
+    System(cli::system::SystemCmd),
+
     /// Start the HTTP API server for programmatic control
     #[command(subcommand)]
     Serve(cli::serve::ServeCmd),
@@ -72,6 +91,11 @@ fn main() {
         Commands::Sandbox(cmd) => cmd.run(),
         Commands::Microvm(cmd) => cmd.run(),
         Commands::Container(cmd) => cmd.run(),
+        Commands::Image(cmd) => cmd.run(),
+        Commands::Build(cmd) => cmd.run(),
+        Commands::Network(cmd) => cmd.run(),
+        Commands::Volume(cmd) => cmd.run(),
+        Commands::System(cmd) => cmd.run(),
         Commands::Serve(cmd) => cmd.run(),
         Commands::Pack(cmd) => cmd.run(),
         Commands::Config(cmd) => cmd.run(),
@@ -2,10 +2,17 @@
 //!
 //! This module provides utilities for managing host directory mounts
 //! into guest VMs using virtiofs.
+//!
+//! virtiofs only shares directories. A single file is mounted by sharing its
+//! parent directory under a hidden tag (see [`prepare_share`]); the agent
+//! bind-mounts the one file into place and detaches the directory, so the
+//! file's siblings stay hidden in the guest.
 
+use crate::agent::ContainerMount;
 use crate::api::types::{MountInfo, MountSpec};
 use crate::error::{Error, Result};
 use crate::vm::config::HostMount;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 
 /// Canonical mount binding representation.
@@ -24,8 +31,8 @@ use std::path::{Path, PathBuf};
 /// // Convert to database tuple
 /// let (source, target, ro) = binding.to_tuple();
 ///
-/// // Convert to agent binding with virtiofs tag
-/// let (tag, target, ro) = binding.to_agent_binding(0);
+/// // Convert to a container bind mount with virtiofs tag
+/// let mount = binding.to_agent_binding(0);
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MountBinding {
@@ -43,7 +50,7 @@ impl MountBinding {
     /// Validates:
     /// - Source path is absolute
     /// - Target path is absolute
-    /// - Source exists and is a directory
+    /// - Source exists and is a directory or regular file
     /// - Source is canonicalized
     pub fn new(
         source: impl Into<PathBuf>,
@@ -77,12 +84,12 @@ impl MountBinding {
             ));
         }
 
-        // Validate source is a directory
-        if !source.is_dir() {
+        // Validate source is a directory or a regular file
+        if !source.is_dir() && !source.is_file() {
             return Err(Error::mount(
                 "validate source",
                 format!(
-                    "path must be a directory (virtiofs limitation): {}",
+                    "path must be a directory or regular file: {}",
                     source.display()
                 ),
             ));
@@ -123,13 +130,11 @@ impl MountBinding {
         )
     }
 
-    /// Convert to agent binding with virtiofs tag.
-    ///
-    /// Returns (virtiofs_tag, target_path, read_only).
-    pub fn to_agent_binding(&self, index: usize) -> (String, String, bool) {
-        (
+    /// Convert to a container bind mount of virtiofs device `index`.
+    pub fn to_agent_binding(&self, index: usize) -> ContainerMount {
+        ContainerMount::bind(
             crate::agent::mount_tag(index),
-            self.target.to_string_lossy().to_string(),
+            self.target.to_string_lossy(),
             self.read_only,
         )
     }
@@ -181,7 +186,7 @@ impl From<&MountBinding> for HostMount {
 /// Checks that:
 /// - Source path exists on the host
 /// - Source path is absolute
-/// - Source path is a directory (not a file)
+/// - Source path is a directory or regular file (not a socket or device)
 /// - Target path is absolute
 pub fn validate_mount(mount: &HostMount) -> Result<()> {
     // Source must be absolute
@@ -207,19 +212,217 @@ pub fn validate_mount(mount: &HostMount) -> Result<()> {
         });
     }
 
-    // Source must be a directory (virtiofs doesn't support single file mounts)
-    if mount.source.is_file() {
+    // Sockets, FIFOs and devices can't be shared over virtiofs
+    if !mount.source.is_dir() && !mount.source.is_file() {
         return Err(Error::invalid_mount_path(format!(
-            "cannot mount single file '{}': virtiofs only supports directory mounts. \
-             Mount the parent directory instead (e.g., -v {}:/mnt/data)",
-            mount.source.display(),
-            mount.source.parent().unwrap_or(&mount.source).display()
+            "cannot mount '{}': only directories and regular files can be mounted",
+            mount.source.display()
         )));
     }
 
     Ok(())
 }
 
+/// Directory, next to a VM's storage disk, holding copies of single files
+/// whose parent directory can't be shared.
+pub const FILE_SHARES_DIR: &str = "file-shares";
+
+/// Prepare the host directory a mount shares over virtiofs.
+///
+/// A directory mount shares its source. A single-file mount shares the
+/// file's parent directory under the mount's tag; the agent mounts it at a
+/// hidden path, bind-mounts the one file into place and detaches the
+/// directory, so the siblings never show up in the guest. Guest writes go
+/// straight to the host file.
+///
+/// When the parent can't be shared (it is `/`, or can't be listed), the file
+/// is copied into `private_dir` and that is shared instead. Guest writes to
+/// a copy don't reach the host file.
+pub fn prepare_share(mount: &HostMount, private_dir: &Path) -> Result<PathBuf> {
+    let Some(file) = mount.shared_file() else {
+        return Ok(mount.source.clone());
+    };
+
+    if private_dir.exists() {
+        std::fs::remove_dir_all(private_dir)?;
+    }
+    if let Some(parent) = shareable_parent(&mount.source) {
+        return Ok(parent.to_path_buf());
+    }
+
+    tracing::warn!(
+        source = %mount.source.display(),
+        "cannot share the parent directory of a single-file mount; mounting a copy"
+    );
+    if !mount.read_only {
+        tracing::warn!(
+            source = %mount.source.display(),
+            "guest writes to the copied file will not reach the host"
+        );
+    }
+    copy_into_share(&mount.source, file, private_dir).map_err(|e| {
+        Error::mount(
+            "share file",
+            format!(
+                "cannot copy '{}' into {}: {}",
+                mount.source.display(),
+                private_dir.display(),
+                e
+            ),
+        )
+    })?;
+    Ok(private_dir.to_path_buf())
+}
+
+/// Create `private_dir` holding a copy of `source` named `file`.
+fn copy_into_share(source: &Path, file: &OsStr, private_dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    if let Some(parent) = private_dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::DirBuilder::new().mode(0o700).create(private_dir)?;
+    std::fs::copy(source, private_dir.join(file))?;
+    Ok(())
+}
+
+/// The parent directory of `file`, if virtiofs can share it.
+fn shareable_parent(file: &Path) -> Option<&Path> {
+    let parent = file.parent().filter(|p| !p.as_os_str().is_empty())?;
+    if parent == Path::new("/") {
+        return None;
+    }
+    std::fs::read_dir(parent).ok()?;
+    Some(parent)
+}
+
+/// Remove the single-file copies made by [`prepare_share`] under `root`.
+pub fn remove_file_shares(root: &Path) {
+    let dir = root.join(FILE_SHARES_DIR);
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::debug!(error = %e, path = %dir.display(), "failed to remove file shares");
+        }
+    }
+}
+
+/// Whether a mount source contains glob characters (`*`, `?` or `[`).
+pub fn is_glob_pattern(source: &str) -> bool {
+    source.contains(['*', '?', '['])
+}
+
+/// Expand a glob in the last component of a mount source.
+///
+/// Only the file name may contain wildcards (`~/.ssh/*.pub`, not
+/// `~/*/config`). Hidden entries only match a pattern that starts with a
+/// dot. Matches are returned sorted; no match is an error.
+pub fn expand_mount_glob(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let name = pattern
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let dir = match pattern.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => Path::new("/"),
+    };
+    if name.is_empty() || is_glob_pattern(&dir.to_string_lossy()) {
+        return Err(Error::invalid_mount_path(format!(
+            "invalid glob '{}': only the last path component may contain wildcards",
+            pattern.display()
+        )));
+    }
+
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        Error::mount(
+            "expand glob",
+            format!("cannot read '{}': {}", dir.display(), e),
+        )
+    })?;
+    let mut matches: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let entry_name = entry.file_name().to_string_lossy().to_string();
+            (!entry_name.starts_with('.') || name.starts_with('.'))
+                && wildcard_match(&name, &entry_name)
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    matches.sort();
+
+    if matches.is_empty() {
+        return Err(Error::mount(
+            "expand glob",
+            format!("no files match '{}'", pattern.display()),
+        ));
+    }
+    Ok(matches)
+}
+
+/// Match a name against a shell wildcard (`*`, `?`, `[abc]`, `[a-z]`,
+/// `[!abc]`).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last '*' and the name index it currently covers
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match_class(&pattern[p..], name[n]).map(|len| p + len),
+            Some(c) if *c == name[n] => Some(p + 1),
+            _ => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p;
+                n = star_n + 1;
+                backtrack = Some((star_p, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Match `c` against a bracket expression at the start of `pattern`,
+/// returning the expression's length if it matches.
+fn match_class(pattern: &[char], c: char) -> Option<usize> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&start) = pattern.get(i) {
+        if start == ']' && !first {
+            return (matched != negate).then_some(i + 1);
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|e| *e != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+    // Unterminated bracket: treat '[' literally
+    (c == '[').then_some(1)
+}
+
 /// Parse a mount specification string.
 ///
 /// Format: `host_path:guest_path[:ro]`
@@ -265,9 +468,220 @@ pub fn is_safe_mount_source(path: &Path) -> bool {
     true
 }
 
+/// Mount flags a `--mount` argument can add to a bind or volume mount.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MountFlags {
+    /// Don't allow executing binaries.
+    pub noexec: bool,
+    /// Ignore set-user-ID and set-group-ID bits.
+    pub nosuid: bool,
+    /// Don't interpret device nodes.
+    pub nodev: bool,
+}
+
+impl MountFlags {
+    /// Set the flags on a container mount.
+    pub fn apply(self, mount: &mut ContainerMount) {
+        mount.noexec |= self.noexec;
+        mount.nosuid |= self.nosuid;
+        mount.nodev |= self.nodev;
+    }
+}
+
+/// A parsed `--mount` argument.
+#[derive(Debug, Clone)]
+pub enum MountArg {
+    /// A host path or named volume.
+    Share {
+        /// Equivalent `-v` spec (`source:target[:ro]`).
+        spec: String,
+        /// Flags for the container mount.
+        flags: MountFlags,
+    },
+    /// A tmpfs mount.
+    Tmpfs(ContainerMount),
+}
+
+/// Options shared by `--mount` and `--tmpfs`.
+#[derive(Debug, Default)]
+struct MountOptions {
+    read_only: bool,
+    noexec: Option<bool>,
+    nosuid: Option<bool>,
+    nodev: Option<bool>,
+    size_bytes: Option<u64>,
+    mode: Option<u32>,
+}
+
+impl MountOptions {
+    /// Apply one `key[=value]` option. Returns false for unknown keys.
+    fn set(&mut self, key: &str, value: Option<&str>) -> std::result::Result<bool, String> {
+        let flag = |value: Option<&str>| match value {
+            None | Some("true") | Some("1") => Ok(true),
+            Some("false") | Some("0") => Ok(false),
+            Some(other) => Err(format!("invalid value for '{}': {}", key, other)),
+        };
+        match (key, value) {
+            ("ro" | "readonly", _) => self.read_only = flag(value)?,
+            ("rw", None) => self.read_only = false,
+            ("noexec", None) => self.noexec = Some(true),
+            ("exec", None) => self.noexec = Some(false),
+            ("nosuid", None) => self.nosuid = Some(true),
+            ("suid", None) => self.nosuid = Some(false),
+            ("nodev", None) => self.nodev = Some(true),
+            ("dev", None) => self.nodev = Some(false),
+            ("size" | "tmpfs-size", Some(v)) => self.size_bytes = Some(parse_size(v)?),
+            ("mode" | "tmpfs-mode", Some(v)) => {
+                self.mode = Some(
+                    u32::from_str_radix(v, 8)
+                        .ok()
+                        .filter(|m| *m <= 0o7777)
+                        .ok_or_else(|| {
+                            format!("invalid mode '{}': expected octal, e.g. 1777", v)
+                        })?,
+                )
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn has_tmpfs_options(&self) -> bool {
+        self.size_bytes.is_some() || self.mode.is_some()
+    }
+
+    fn tmpfs(&self, target: &str) -> std::result::Result<ContainerMount, String> {
+        let target = container_target(target)?;
+        let mut mount = ContainerMount::tmpfs(target, self.size_bytes, self.mode);
+        mount.read_only = self.read_only;
+        mount.noexec = self.noexec.unwrap_or(mount.noexec);
+        mount.nosuid = self.nosuid.unwrap_or(mount.nosuid);
+        mount.nodev = self.nodev.unwrap_or(mount.nodev);
+        Ok(mount)
+    }
+
+    fn flags(&self) -> MountFlags {
+        MountFlags {
+            noexec: self.noexec.unwrap_or(false),
+            nosuid: self.nosuid.unwrap_or(false),
+            nodev: self.nodev.unwrap_or(false),
+        }
+    }
+}
+
+/// Parse a size with an optional k/m/g/t suffix (`256m`, `1g`, `65536k`).
+fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let lower = s.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(['b', 'i']);
+    let (number, shift) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 10),
+        Some('m') => (&digits[..digits.len() - 1], 20),
+        Some('g') => (&digits[..digits.len() - 1], 30),
+        Some('t') => (&digits[..digits.len() - 1], 40),
+        _ => (digits, 0),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("invalid size '{}': expected e.g. 256m or 1g", s))
+}
+
+/// Validate a path inside the container.
+fn container_target(target: &str) -> std::result::Result<&str, String> {
+    if target.starts_with('/') {
+        Ok(target)
+    } else {
+        Err(format!(
+            "mount target must be an absolute path: '{}'",
+            target
+        ))
+    }
+}
+
+/// Parse a `--tmpfs` specification (`PATH[:size=256m,mode=1777,noexec]`).
+///
+/// Tmpfs mounts are `nosuid` and `nodev` unless `suid` or `dev` is given.
+pub fn parse_tmpfs_spec(s: &str) -> std::result::Result<ContainerMount, String> {
+    let (target, options) = s.split_once(':').unwrap_or((s, ""));
+    let mut opts = MountOptions::default();
+    for option in options.split(',').filter(|o| !o.is_empty()) {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        if !opts.set(key, value)? {
+            return Err(format!("unknown tmpfs option '{}'", option));
+        }
+    }
+    opts.tmpfs(target)
+}
+
+impl MountArg {
+    /// Parse a `--mount` specification:
+    /// `type=bind|volume|tmpfs,source=SRC,target=DST[,readonly][,noexec][,nosuid][,nodev]`.
+    ///
+    /// `type` defaults to `volume`. Bind and volume mounts become a `-v`
+    /// spec; tmpfs mounts also take `tmpfs-size` and `tmpfs-mode`.
+    pub fn parse_spec(s: &str) -> std::result::Result<Self, String> {
+        let mut mount_type = "volume";
+        let mut source = None;
+        let mut target = None;
+        let mut opts = MountOptions::default();
+        for field in s.split(',') {
+            let (key, value) = match field.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (field, None),
+            };
+            match (key, value) {
+                ("type", Some(v)) => mount_type = v,
+                ("source" | "src", Some(v)) => source = Some(v),
+                ("target" | "destination" | "dst", Some(v)) => target = Some(v),
+                _ if opts.set(key, value)? => {}
+                _ => return Err(format!("unknown mount option '{}'", field)),
+            }
+        }
+
+        let target = container_target(target.ok_or("mount needs a target=PATH")?)?;
+        if mount_type == "tmpfs" {
+            if source.is_some() {
+                return Err("tmpfs mounts don't take a source".to_string());
+            }
+            return opts.tmpfs(target).map(Self::Tmpfs);
+        }
+        if opts.has_tmpfs_options() {
+            return Err(format!(
+                "size and mode only apply to tmpfs, not {}",
+                mount_type
+            ));
+        }
+
+        let source = source.ok_or_else(|| format!("{} mount needs a source", mount_type))?;
+        let source = match mount_type {
+            "volume" if crate::volume::is_volume_name(source) => source.to_string(),
+            "volume" => return Err(format!("'{}' is not a volume name", source)),
+            "bind" if crate::volume::is_explicit_path(source) => source.to_string(),
+            // A relative path would otherwise be read as a volume name.
+            "bind" => format!("./{}", source),
+            other => {
+                return Err(format!(
+                    "unknown mount type '{}': expected bind, volume or tmpfs",
+                    other
+                ))
+            }
+        };
+        let mode = if opts.read_only { ":ro" } else { "" };
+        Ok(Self::Share {
+            spec: format!("{}:{}{}", source, target, mode),
+            flags: opts.flags(),
+        })
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::MountKind;
     use std::path::PathBuf;
 
     // === Mount Spec Parsing ===
@@ -356,28 +770,120 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_mount_rejects_single_file() {
-        // Create a temp file
-        let temp_file = std::env::temp_dir().join("smolvm_test_file.txt");
-        std::fs::write(&temp_file, "test").unwrap();
+    fn test_validate_mount_accepts_single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("gitconfig");
+        std::fs::write(&file, "test").unwrap();
 
-        let mount = HostMount::new(temp_file.to_str().unwrap(), "/guest/file.txt");
-        let result = validate_mount(&mount);
+        let mount = HostMount::new(&file, "/root/.gitconfig");
+        assert!(validate_mount(&mount).is_ok());
+        assert_eq!(mount.shared_file().unwrap(), "gitconfig");
 
-        // Cleanup
-        let _ = std::fs::remove_file(&temp_file);
+        let binding = MountBinding::new(&file, "/root/.gitconfig", true).unwrap();
+        assert_eq!(binding.source, file.canonicalize().unwrap());
+    }
 
-        // Should fail with helpful error
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(
-            err_msg.contains("single file"),
-            "Error should mention single file"
-        );
-        assert!(
-            err_msg.contains("directory"),
-            "Error should suggest directory mount"
+    #[test]
+    fn test_prepare_share_single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        std::fs::create_dir(&home).unwrap();
+        let file = home.join("gitconfig");
+        std::fs::write(&file, "test").unwrap();
+
+        let directory = HostMount::new(&home, "/data");
+        let private = dir.path().join(FILE_SHARES_DIR).join("smolvm0");
+        assert_eq!(prepare_share(&directory, &private).unwrap(), home);
+
+        // The parent is shared and a stale copy from an earlier run is dropped
+        std::fs::create_dir_all(&private).unwrap();
+        std::fs::write(private.join("gitconfig"), "stale").unwrap();
+        let mount = HostMount::new(&file, "/root/.gitconfig");
+        assert_eq!(prepare_share(&mount, &private).unwrap(), home);
+        assert!(!private.exists());
+    }
+
+    #[test]
+    fn test_prepare_share_copies_file_when_parent_unshareable() {
+        assert_eq!(shareable_parent(Path::new("/hosts")), None);
+        assert_eq!(shareable_parent(Path::new("/nonexistent-dir/hosts")), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hosts");
+        std::fs::write(&file, "127.0.0.1 localhost").unwrap();
+        let private = dir.path().join(FILE_SHARES_DIR).join("smolvm0");
+        copy_into_share(&file, OsStr::new("hosts"), &private).unwrap();
+        let names: Vec<_> = std::fs::read_dir(&private)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["hosts"]);
+
+        remove_file_shares(dir.path());
+        assert!(!dir.path().join(FILE_SHARES_DIR).exists());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_validate_mount_rejects_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let mount = HostMount::new(&socket, "/run/agent.sock");
+        let err = validate_mount(&mount).unwrap_err().to_string();
+        assert!(err.contains("regular files"), "unexpected error: {}", err);
+    }
+
+    // === Globs ===
+
+    #[test]
+    fn test_wildcard_match() {
+        // (pattern, name, expected)
+        let cases = [
+            ("*.pub", "id_ed25519.pub", true),
+            ("*.pub", "id_ed25519", false),
+            ("id_*", "id_rsa", true),
+            ("*", "", true),
+            ("a*b*c", "aXXbYYc", true),
+            ("a*b*c", "aXXbYY", false),
+            ("file?.txt", "file1.txt", true),
+            ("file?.txt", "file10.txt", false),
+            ("[abc].conf", "b.conf", true),
+            ("[a-c].conf", "d.conf", false),
+            ("[!a-c].conf", "d.conf", true),
+            ("[.toml", "[.toml", true),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(
+                wildcard_match(pattern, name),
+                expected,
+                "'{}' vs '{}'",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_mount_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.toml", "a.toml", "c.yaml", ".hidden.toml"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let matches = expand_mount_glob(&dir.path().join("*.toml")).unwrap();
+        assert_eq!(
+            matches,
+            vec![dir.path().join("a.toml"), dir.path().join("b.toml")]
         );
+        let hidden = expand_mount_glob(&dir.path().join(".*.toml")).unwrap();
+        assert_eq!(hidden, vec![dir.path().join(".hidden.toml")]);
+
+        assert!(expand_mount_glob(&dir.path().join("*.json")).is_err());
+        assert!(expand_mount_glob(&dir.path().join("*/x.toml")).is_err());
+        assert!(is_glob_pattern("~/.ssh/*.pub"));
+        assert!(!is_glob_pattern("/home/user/.gitconfig"));
     }
 
     // === Safe Mount Source Checks ===
@@ -417,4 +923,70 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_parse_tmpfs_spec() {
+        let mount = parse_tmpfs_spec("/tmp:size=256m,mode=1777").unwrap();
+        assert_eq!(mount.target, "/tmp");
+        assert_eq!(
+            mount.kind,
+            MountKind::Tmpfs {
+                size_bytes: Some(256 << 20),
+                mode: Some(0o1777),
+            }
+        );
+        assert!(mount.nosuid && mount.nodev && !mount.noexec);
+
+        let mount = parse_tmpfs_spec("/run:noexec,suid").unwrap();
+        assert!(mount.noexec && !mount.nosuid);
+
+        assert!(parse_tmpfs_spec("tmp").is_err());
+        assert!(parse_tmpfs_spec("/tmp:size=lots").is_err());
+        assert!(parse_tmpfs_spec("/tmp:mode=999").is_err());
+        assert!(parse_tmpfs_spec("/tmp:bogus").is_err());
+    }
+
+    #[test]
+    fn test_parse_mount_arg_spec() {
+        match MountArg::parse_spec("type=bind,source=/srv/app,target=/app,readonly,noexec").unwrap()
+        {
+            MountArg::Share { spec, flags } => {
+                assert_eq!(spec, "/srv/app:/app:ro");
+                assert!(flags.noexec && !flags.nosuid);
+            }
+            other => panic!("unexpected mount: {:?}", other),
+        }
+        match MountArg::parse_spec("type=bind,src=app,dst=/app").unwrap() {
+            MountArg::Share { spec, .. } => assert_eq!(spec, "./app:/app"),
+            other => panic!("unexpected mount: {:?}", other),
+        }
+        match MountArg::parse_spec("type=bind,src=app/conf,dst=/app").unwrap() {
+            MountArg::Share { spec, .. } => assert_eq!(spec, "./app/conf:/app"),
+            other => panic!("unexpected mount: {:?}", other),
+        }
+        match MountArg::parse_spec("source=pgdata,target=/var/lib/postgresql/data,nosuid").unwrap()
+        {
+            MountArg::Share { spec, flags } => {
+                assert_eq!(spec, "pgdata:/var/lib/postgresql/data");
+                assert!(flags.nosuid);
+            }
+            other => panic!("unexpected mount: {:?}", other),
+        }
+        match MountArg::parse_spec("type=tmpfs,target=/cache,tmpfs-size=1g").unwrap() {
+            MountArg::Tmpfs(mount) => assert_eq!(
+                mount.kind,
+                MountKind::Tmpfs {
+                    size_bytes: Some(1 << 30),
+                    mode: None,
+                }
+            ),
+            other => panic!("unexpected mount: {:?}", other),
+        }
+
+        assert!(MountArg::parse_spec("type=volume,source=/srv,target=/srv").is_err());
+        assert!(MountArg::parse_spec("type=bind,source=/srv").is_err());
+        assert!(MountArg::parse_spec("type=bind,source=/srv,target=/srv,size=1m").is_err());
+        assert!(MountArg::parse_spec("type=tmpfs,source=x,target=/x").is_err());
+        assert!(MountArg::parse_spec("type=nfs,source=x,target=/x").is_err());
+    }
 }
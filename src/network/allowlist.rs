@@ -0,0 +1,353 @@
+//! Egress allowlist rules.
+//!
+//! An allowlist is a set of rules of the form `TARGET[:PORT]`, where the
+//! target is a domain (`github.com`), a wildcard domain (`*.npmjs.org`),
+//! an IP address, or a CIDR block (`10.0.0.0/8`). IPv6 targets that carry a
+//! port use brackets: `[fd00::/8]:443`. Rules without a port allow any port.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// What an egress rule matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EgressTarget {
+    /// Exact domain name (lowercase, no trailing dot).
+    Domain(String),
+    /// Any subdomain of the given domain (`*.example.com` matches
+    /// `a.example.com` and `a.b.example.com`, but not `example.com`).
+    Wildcard(String),
+    /// IP network. Plain addresses are stored with a full-length prefix.
+    Cidr {
+        /// Network address (host bits cleared).
+        addr: IpAddr,
+        /// Prefix length in bits.
+        prefix: u8,
+    },
+}
+
+/// A single egress allowlist rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EgressRule {
+    /// Destination the rule applies to.
+    pub target: EgressTarget,
+    /// Destination port, or `None` for any port.
+    pub port: Option<u16>,
+}
+
+impl EgressRule {
+    /// Check whether this rule allows connecting to `host` by name.
+    ///
+    /// Only domain and wildcard rules match names; CIDR rules are checked
+    /// against resolved addresses with [`EgressRule::allows_addr`].
+    pub fn allows_name(&self, host: &str, port: u16) -> bool {
+        if !self.port_matches(port) {
+            return false;
+        }
+        let host = normalize_domain(host);
+        match &self.target {
+            EgressTarget::Domain(domain) => host == *domain,
+            EgressTarget::Wildcard(suffix) => host
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|prefix| !prefix.is_empty()),
+            EgressTarget::Cidr { .. } => false,
+        }
+    }
+
+    /// Check whether this rule allows connecting to `addr`.
+    pub fn allows_addr(&self, addr: SocketAddr) -> bool {
+        if !self.port_matches(addr.port()) {
+            return false;
+        }
+        match &self.target {
+            EgressTarget::Cidr {
+                addr: network,
+                prefix,
+            } => cidr_contains(*network, *prefix, addr.ip()),
+            _ => false,
+        }
+    }
+
+    fn port_matches(&self, port: u16) -> bool {
+        self.port.is_none_or(|p| p == port)
+    }
+}
+
+/// Parse a comma-separated list of rules (e.g. the value of `--net=allow=`).
+pub fn parse_allowlist(spec: &str) -> Result<Vec<EgressRule>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Check whether any rule allows connecting to `host` by name.
+pub fn name_allowed(rules: &[EgressRule], host: &str, port: u16) -> bool {
+    rules.iter().any(|r| r.allows_name(host, port))
+}
+
+/// Check whether any rule allows connecting to `addr`.
+pub fn addr_allowed(rules: &[EgressRule], addr: SocketAddr) -> bool {
+    rules.iter().any(|r| r.allows_addr(addr))
+}
+
+fn normalize_domain(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn validate_domain(domain: &str) -> Result<(), String> {
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid domain '{}'", domain))
+    }
+}
+
+fn cidr_contains(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    let ip = match (network, ip) {
+        (IpAddr::V4(_), IpAddr::V6(v6)) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => return false,
+        },
+        _ => ip,
+    };
+    match (network, ip) {
+        (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+            mask(ip, prefix) == network
+        }
+        _ => false,
+    }
+}
+
+fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let m = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4((bits & m).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let m = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6((bits & m).into())
+        }
+    }
+}
+
+fn parse_target(s: &str) -> Result<EgressTarget, String> {
+    if let Some((addr, prefix)) = s.split_once('/') {
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid network address '{}'", addr))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix: u8 = prefix
+            .parse()
+            .ok()
+            .filter(|p| *p <= max)
+            .ok_or_else(|| format!("invalid prefix length '/{}' for {}", prefix, addr))?;
+        return Ok(EgressTarget::Cidr {
+            addr: mask(addr, prefix),
+            prefix,
+        });
+    }
+    if let Ok(addr) = s.parse::<IpAddr>() {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        return Ok(EgressTarget::Cidr { addr, prefix });
+    }
+    if let Some(suffix) = s.strip_prefix("*.") {
+        let suffix = normalize_domain(suffix);
+        validate_domain(&suffix)?;
+        return Ok(EgressTarget::Wildcard(format!(".{}", suffix)));
+    }
+    let domain = normalize_domain(s);
+    validate_domain(&domain)?;
+    Ok(EgressTarget::Domain(domain))
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    s.parse()
+        .ok()
+        .filter(|p| *p != 0)
+        .ok_or_else(|| format!("invalid port '{}'", s))
+}
+
+impl FromStr for EgressRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (target, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (inner, after) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("missing ']' in '{}'", s))?;
+            let port = match after {
+                "" => None,
+                _ => Some(parse_port(after.strip_prefix(':').ok_or_else(|| {
+                    format!("expected ':PORT' after ']' in '{}'", s)
+                })?)?),
+            };
+            (inner, port)
+        } else if s.matches(':').count() == 1 {
+            let (target, port) = s.split_once(':').expect("contains one ':'");
+            (target, Some(parse_port(port)?))
+        } else {
+            // Bare IPv6 address or network (no port), or a name without a port
+            (s, None)
+        };
+
+        if target.is_empty() {
+            return Err(format!("empty target in allow rule '{}'", s));
+        }
+
+        Ok(Self {
+            target: parse_target(target).map_err(|e| format!("allow rule '{}': {}", s, e))?,
+            port,
+        })
+    }
+}
+
+impl fmt::Display for EgressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match &self.target {
+            EgressTarget::Domain(domain) => domain.clone(),
+            EgressTarget::Wildcard(suffix) => format!("*{}", suffix),
+            EgressTarget::Cidr { addr, prefix } => {
+                let full = if addr.is_ipv4() { 32 } else { 128 };
+                if *prefix == full {
+                    addr.to_string()
+                } else {
+                    format!("{}/{}", addr, prefix)
+                }
+            }
+        };
+        match self.port {
+            Some(port) if target.contains(':') => write!(f, "[{}]:{}", target, port),
+            Some(port) => write!(f, "{}:{}", target, port),
+            None => f.write_str(&target),
+        }
+    }
+}
+
+impl Serialize for EgressRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EgressRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> EgressRule {
+        s.parse().unwrap()
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display_roundtrip() {
+        for spec in [
+            "github.com",
+            "registry.npmjs.org:443",
+            "*.example.com",
+            "10.0.0.0/8:443",
+            "192.168.1.10",
+            "fd00::/8",
+            "[fd00::/8]:443",
+            "[::1]:8080",
+        ] {
+            assert_eq!(rule(spec).to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_parse_normalizes() {
+        assert_eq!(rule("GitHub.COM.").to_string(), "github.com");
+        assert_eq!(rule("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(rule("0.0.0.0/0").to_string(), "0.0.0.0/0");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for spec in [
+            "",
+            "github.com:0",
+            "github.com:99999",
+            "10.0.0.0/33",
+            "bad_host!",
+            "[fd00::",
+            "[fd00::]443",
+            "*.",
+            "-foo.com",
+        ] {
+            assert!(spec.parse::<EgressRule>().is_err(), "{} should fail", spec);
+        }
+    }
+
+    #[test]
+    fn test_parse_allowlist() {
+        let rules = parse_allowlist("github.com, registry.npmjs.org,10.0.0.0/8:443").unwrap();
+        assert_eq!(rules.len(), 3);
+        assert!(parse_allowlist("github.com,nope/99").is_err());
+    }
+
+    #[test]
+    fn test_domain_matching() {
+        let rules = parse_allowlist("github.com,*.npmjs.org:443").unwrap();
+        assert!(name_allowed(&rules, "github.com", 22));
+        assert!(name_allowed(&rules, "GITHUB.com.", 443));
+        assert!(!name_allowed(&rules, "api.github.com", 443));
+        assert!(name_allowed(&rules, "registry.npmjs.org", 443));
+        assert!(name_allowed(&rules, "a.b.npmjs.org", 443));
+        assert!(!name_allowed(&rules, "npmjs.org", 443));
+        assert!(!name_allowed(&rules, "evilnpmjs.org", 443));
+        assert!(!name_allowed(&rules, "registry.npmjs.org", 80));
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let rules = parse_allowlist("10.0.0.0/8:443,192.168.1.10,[fd00::/8]:443").unwrap();
+        assert!(addr_allowed(&rules, addr("10.20.30.40:443")));
+        assert!(!addr_allowed(&rules, addr("10.20.30.40:80")));
+        assert!(!addr_allowed(&rules, addr("11.0.0.1:443")));
+        assert!(addr_allowed(&rules, addr("192.168.1.10:5432")));
+        assert!(!addr_allowed(&rules, addr("192.168.1.11:5432")));
+        assert!(addr_allowed(&rules, addr("[fd12::1]:443")));
+        assert!(!addr_allowed(&rules, addr("[fe80::1]:443")));
+        assert!(addr_allowed(&rules, addr("[::ffff:10.0.0.1]:443")));
+        // CIDR rules never match names
+        assert!(!name_allowed(&rules, "10.0.0.1", 443));
+    }
+
+    #[test]
+    fn test_serde_as_string() {
+        let rules = parse_allowlist("github.com,10.0.0.0/8:443").unwrap();
+        let json = serde_json::to_string(&rules).unwrap();
+        assert_eq!(json, r#"["github.com","10.0.0.0/8:443"]"#);
+        let back: Vec<EgressRule> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, rules);
+        assert!(serde_json::from_str::<Vec<EgressRule>>(r#"["10.0.0.0/99"]"#).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! Minimal DNS message handling for the embedded resolver.
+//!
+//! Only what a stub resolver needs is supported: single-question queries,
+//! and responses with A/AAAA records for names the host knows. Everything
+//! else is forwarded upstream as raw bytes.
+
+use std::net::IpAddr;
+
+/// Size of the fixed DNS header.
+const HEADER_LEN: usize = 12;
+
+/// TTL of synthesized answers, in seconds. Short because VMs come and go.
+const ANSWER_TTL: u32 = 10;
+
+/// Largest message read from an upstream resolver.
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Record type A (IPv4 address).
+pub const TYPE_A: u16 = 1;
+/// Record type AAAA (IPv6 address).
+pub const TYPE_AAAA: u16 = 28;
+
+/// Response code: no error.
+pub const RCODE_NOERROR: u8 = 0;
+/// Response code: the server failed to answer.
+pub const RCODE_SERVFAIL: u8 = 2;
+/// Response code: the name does not exist.
+pub const RCODE_NXDOMAIN: u8 = 3;
+/// Response code: the server refuses to answer.
+pub const RCODE_REFUSED: u8 = 5;
+
+/// The question of a parsed query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    /// Queried name, lowercased, without the trailing dot.
+    pub name: String,
+    /// Record type.
+    pub qtype: u16,
+    /// Offset of the end of the question section in the query.
+    end: usize,
+}
+
+/// Parse a standard query with exactly one question.
+///
+/// Returns `None` for responses, other opcodes, compressed question names,
+/// or malformed packets.
+pub fn parse_query(packet: &[u8]) -> Option<Question> {
+    if packet.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0xf;
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if is_response || opcode != 0 || qdcount != 1 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers never appear in a question we accept
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        let label = packet.get(pos..pos + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += len;
+    }
+    let fixed = packet.get(pos..pos + 4)?;
+    let qtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+
+    Some(Question {
+        name: labels.join("."),
+        qtype,
+        end: pos + 4,
+    })
+}
+
+/// Build a response to `query` answering `question`.
+///
+/// Each address in `answers` becomes an A or AAAA record; without any the
+/// response only carries `rcode`.
+pub fn build_response(query: &[u8], question: &Question, answers: &[IpAddr], rcode: u8) -> Vec<u8> {
+    let recursion_desired = query[2] & 0x01;
+    let mut out = Vec::with_capacity(question.end + 28 * answers.len());
+    out.extend_from_slice(&query[0..2]);
+    // QR, RD copied from the query, RA
+    out.push(0x80 | recursion_desired);
+    out.push(0x80 | (rcode & 0x0f));
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(&query[HEADER_LEN..question.end]);
+
+    for addr in answers {
+        // Name: pointer to the question name
+        out.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        let (rtype, data) = match addr {
+            IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+        };
+        out.extend_from_slice(&rtype.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+        out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        out.extend_from_slice(&data);
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) fn encode_query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out.extend_from_slice(&qtype.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_and_answer() {
+        let query = encode_query(0x1234, "DB.dev", TYPE_A);
+        let question = parse_query(&query).unwrap();
+        assert_eq!(question.name, "db.dev");
+        assert_eq!(question.qtype, TYPE_A);
+
+        let addr = std::net::Ipv4Addr::new(10, 89, 0, 3);
+        let response = build_response(&query, &question, &[addr.into()], RCODE_NOERROR);
+        assert_eq!(&response[0..2], &[0x12, 0x34]);
+        // Response, recursion desired + available, no error, one answer
+        assert_eq!(&response[2..4], &[0x81, 0x80]);
+        assert_eq!(&response[6..8], &[0, 1]);
+        assert_eq!(&response[response.len() - 4..], &addr.octets());
+
+        let nx = build_response(&query, &question, &[], RCODE_NXDOMAIN);
+        assert_eq!(nx[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(&nx[6..8], &[0, 0]);
+        assert_eq!(nx.len(), query.len());
+
+        // Responses and truncated packets are not queries
+        assert!(parse_query(&response).is_none());
+        assert!(parse_query(&query[..query.len() - 2]).is_none());
+    }
+}
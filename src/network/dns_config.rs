@@ -0,0 +1,136 @@
+//! Per-VM DNS settings.
+//!
+//! Settings come from the VM record (CLI, Smolfile, API). After the agent
+//! is ready the host sends them as a [`DnsConfig`]; the agent writes the
+//! guest's `/etc/resolv.conf` and `/etc/hosts` and those of every container.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use smolvm_protocol::{DnsConfig, HostEntry};
+use std::net::IpAddr;
+
+/// Per-VM DNS configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsSettings {
+    /// Nameservers, in order of preference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<IpAddr>,
+    /// Search domains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search: Vec<String>,
+    /// Resolver options (`ndots:2`, `edns0`, ...).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// Extra `/etc/hosts` entries as `name:ip`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// Resolve through the host's system resolver, which follows its
+    /// split-DNS configuration (VPN domains and the like).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub host_resolver: bool,
+}
+
+impl DnsSettings {
+    /// Whether no setting is configured.
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+            && self.search.is_empty()
+            && self.options.is_empty()
+            && self.hosts.is_empty()
+            && !self.host_resolver
+    }
+
+    /// Build the guest configuration, validating every entry.
+    pub fn to_protocol(&self) -> Result<DnsConfig> {
+        for value in self.search.iter().chain(&self.options) {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(Error::config(
+                    "dns settings",
+                    format!("invalid search domain or option: '{}'", value),
+                ));
+            }
+        }
+        let hosts = self
+            .hosts
+            .iter()
+            .map(|h| parse_host_entry(h))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::config("dns settings", e))?;
+
+        Ok(DnsConfig {
+            servers: self.servers.clone(),
+            search: self.search.clone(),
+            options: self.options.clone(),
+            hosts,
+            host_resolver: self.host_resolver,
+        })
+    }
+}
+
+/// Parse a `name:ip` host entry (the IP may be IPv6).
+pub fn parse_host_entry(s: &str) -> std::result::Result<HostEntry, String> {
+    let (name, address) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid host entry '{}': expected NAME:IP", s))?;
+    let valid_name = !name.is_empty()
+        && name.len() <= 253
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_');
+    if !valid_name {
+        return Err(format!("invalid host name '{}'", name));
+    }
+    let address = address
+        .parse()
+        .map_err(|_| format!("invalid address '{}' for host '{}'", address, name))?;
+    Ok(HostEntry {
+        name: name.to_string(),
+        address,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_host_entry() {
+        let entry = parse_host_entry("db.internal:10.0.0.5").unwrap();
+        assert_eq!(entry.name, "db.internal");
+        assert_eq!(entry.address.to_string(), "10.0.0.5");
+
+        let entry = parse_host_entry("v6host:fd00::1").unwrap();
+        assert_eq!(entry.address.to_string(), "fd00::1");
+
+        assert!(parse_host_entry("db").is_err());
+        assert!(parse_host_entry(":10.0.0.5").is_err());
+        assert!(parse_host_entry("bad host:10.0.0.5").is_err());
+        assert!(parse_host_entry("db:not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_dns_settings_toml() {
+        let settings: DnsSettings = toml::from_str(
+            r#"
+            servers = ["10.0.0.2", "10.0.0.3"]
+            search = ["corp.example.com"]
+            options = ["ndots:2"]
+            hosts = ["db:10.0.0.5"]
+            "#,
+        )
+        .unwrap();
+        assert!(!settings.is_empty());
+        let config = settings.to_protocol().unwrap();
+        assert_eq!(config.servers.len(), 2);
+        assert_eq!(config.hosts[0].name, "db");
+        assert!(!config.host_resolver);
+        assert!(DnsSettings::default().is_empty());
+
+        let bad = DnsSettings {
+            options: vec!["ndots: 2".into()],
+            ..Default::default()
+        };
+        assert!(bad.to_protocol().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! HTTP(S) proxy settings for VMs behind a corporate proxy.
+//!
+//! Settings come from the VM record (CLI, Smolfile, API) and fall back to
+//! the `[proxy]` section of `registries.toml`. After the agent is ready the
+//! host sends them as a [`ProxyConfig`], with CA certificate files read from
+//! the host at that point.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use smolvm_protocol::ProxyConfig;
+use std::path::PathBuf;
+
+/// Per-VM HTTP(S) proxy configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxySettings {
+    /// Proxy URL for plain HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+    /// Proxy URL for HTTPS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https: Option<String>,
+    /// Hosts, domains, and networks that bypass the proxy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    /// PEM files with extra CA certificates to trust (host paths).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certs: Vec<PathBuf>,
+}
+
+impl ProxySettings {
+    /// Whether no setting is configured.
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none()
+            && self.https.is_none()
+            && self.no_proxy.is_empty()
+            && self.ca_certs.is_empty()
+    }
+
+    /// Resolve the effective settings for a VM: its own settings, or the
+    /// `[proxy]` section of the registry config.
+    pub fn resolve(vm: Option<&ProxySettings>) -> Option<ProxySettings> {
+        if let Some(settings) = vm.filter(|s| !s.is_empty()) {
+            return Some(settings.clone());
+        }
+        crate::registry::RegistryConfig::load()
+            .ok()
+            .and_then(|c| c.proxy)
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Build the guest configuration, reading the CA certificate files.
+    pub fn to_protocol(&self) -> Result<ProxyConfig> {
+        let mut ca_certs = Vec::with_capacity(self.ca_certs.len());
+        for path in &self.ca_certs {
+            let pem = std::fs::read_to_string(path).map_err(|e| {
+                Error::config("read CA certificate", format!("{}: {}", path.display(), e))
+            })?;
+            if !pem.contains("-----BEGIN CERTIFICATE-----") {
+                return Err(Error::config(
+                    "read CA certificate",
+                    format!("{}: not a PEM certificate", path.display()),
+                ));
+            }
+            ca_certs.push(pem);
+        }
+
+        Ok(ProxyConfig {
+            http_proxy: self.http.clone(),
+            https_proxy: self.https.clone(),
+            no_proxy: self.no_proxy.clone(),
+            ca_certs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_protocol_reads_ca_certs() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("corp.pem");
+        std::fs::write(
+            &cert,
+            "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+
+        let settings = ProxySettings {
+            http: Some("http://proxy.corp:3128".into()),
+            https: None,
+            no_proxy: vec!["localhost".into(), ".corp".into()],
+            ca_certs: vec![cert],
+        };
+        let config = settings.to_protocol().unwrap();
+        assert_eq!(config.http_proxy.as_deref(), Some("http://proxy.corp:3128"));
+        assert_eq!(config.no_proxy, vec!["localhost", ".corp"]);
+        assert_eq!(config.ca_certs.len(), 1);
+
+        let bad = dir.path().join("bad.pem");
+        std::fs::write(&bad, "not a cert").unwrap();
+        let settings = ProxySettings {
+            ca_certs: vec![bad],
+            ..Default::default()
+        };
+        assert!(settings.to_protocol().is_err());
+    }
+
+    #[test]
+    fn test_proxy_settings_toml() {
+        let settings: ProxySettings = toml::from_str(
+            r#"
+            http = "http://proxy:3128"
+            https = "http://proxy:3128"
+            no_proxy = ["localhost"]
+            ca_certs = ["/etc/corp-ca.pem"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.https.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(settings.ca_certs, vec![PathBuf::from("/etc/corp-ca.pem")]);
+        assert!(!settings.is_empty());
+        assert!(ProxySettings::default().is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+//! Network configuration.
+//!
+//! This module provides network policy configuration for VMs, including
+//! egress allowlists, the host-side proxy that enforces them, HTTP(S)
+//! proxy settings for VMs behind a corporate proxy, forwarding for
+//! published ports that TSI can't serve, private VM-to-VM networks, and
+//! per-VM DNS settings with an optional host-side resolver.
+
+pub mod allowlist;
+pub mod dns;
+pub mod dns_config;
+pub mod http_proxy;
+pub mod port_forward;
+pub mod private;
+pub mod proxy;
+pub mod resolver;
+pub mod switch;
+
+pub use allowlist::{parse_allowlist, EgressRule, EgressTarget};
+pub use dns_config::{parse_host_entry, DnsSettings};
+pub use http_proxy::ProxySettings;
+pub use port_forward::{
+    send_port_control, serve_ports, start_forwarders, PortControlRequest, PortControlResponse,
+    PortForwarder,
+};
+pub use private::{NetworkAttachment, NetworkStore, PrivateNetwork};
+pub use proxy::EgressProxy;
+pub use resolver::{serve_system_resolver, Upstream};
+pub use switch::start_switch_port;
+
+use crate::vm::config::NetworkPolicy;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Default DNS server (Cloudflare) as string.
+pub const DEFAULT_DNS: &str = "1.1.1.1";
+/// Default DNS server as IpAddr (compile-time constant).
+pub const DEFAULT_DNS_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+
+/// Get the DNS server for a network policy.
+pub fn get_dns_server(policy: &NetworkPolicy) -> Option<IpAddr> {
+    match policy {
+        NetworkPolicy::None => None,
+        NetworkPolicy::Egress { dns } => Some(dns.unwrap_or(DEFAULT_DNS_ADDR)),
+        // Names are resolved host-side by the egress proxy.
+        NetworkPolicy::Allowlist { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_dns_server() {
+        // None policy returns no DNS
+        assert!(get_dns_server(&NetworkPolicy::None).is_none());
+
+        // Egress with default DNS
+        let dns = get_dns_server(&NetworkPolicy::Egress { dns: None }).unwrap();
+        assert_eq!(dns.to_string(), DEFAULT_DNS);
+
+        // Egress with custom DNS
+        let custom: IpAddr = "8.8.8.8".parse().unwrap();
+        let dns = get_dns_server(&NetworkPolicy::Egress { dns: Some(custom) }).unwrap();
+        assert_eq!(dns.to_string(), "8.8.8.8");
+
+        // Allowlist resolves through the host proxy
+        let allow = parse_allowlist("github.com").unwrap();
+        assert!(get_dns_server(&NetworkPolicy::Allowlist { allow }).is_none());
+    }
+}
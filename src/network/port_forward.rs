@@ -0,0 +1,636 @@
+//! Host-side forwarding for published ports TSI can't serve.
+//!
+//! libkrun's TSI port map only publishes TCP ports on all host interfaces.
+//! UDP ports and ports bound to a specific host address are instead served
+//! by a [`PortForwarder`]: it listens on the host and relays each TCP
+//! connection or UDP peer over its own vsock stream to the guest agent,
+//! which connects to the guest port on loopback. The stream format is
+//! defined in [`smolvm_protocol::port_forward`].
+//!
+//! The VM process also serves a [`PortTable`] on a control socket so ports
+//! can be published and withdrawn while the VM keeps running; every port
+//! added that way goes through a forwarder, since the TSI port map is fixed
+//! at boot.
+
+use crate::agent::{PortMapping, PortProtocol};
+use smolvm_protocol::port_forward::{self, MAX_DATAGRAM_SIZE};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Attempts to reach the forward socket, which libkrun creates at boot.
+const CONNECT_ATTEMPTS: u32 = 50;
+
+/// Delay between forward socket connection attempts.
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// UDP peers idle for this long have their guest stream closed.
+const UDP_SESSION_IDLE: Duration = Duration::from_secs(60);
+
+/// Timeout for a port control request.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A bound host listener for one published port.
+pub struct PortForwarder {
+    mapping: PortMapping,
+    forward_socket: PathBuf,
+    listener: Listener,
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+}
+
+impl PortForwarder {
+    /// Bind the host side of `mapping`, relaying to the guest through the
+    /// vsock unix socket at `forward_socket`.
+    pub fn bind(mapping: PortMapping, forward_socket: &Path) -> io::Result<Self> {
+        let addr = SocketAddr::new(
+            mapping.bind.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            mapping.host,
+        );
+        let listener = match mapping.protocol {
+            PortProtocol::Tcp => Listener::Tcp(TcpListener::bind(addr)?),
+            PortProtocol::Udp => Listener::Udp(UdpSocket::bind(addr)?),
+        };
+        Ok(Self {
+            mapping,
+            forward_socket: forward_socket.to_path_buf(),
+            listener,
+        })
+    }
+
+    /// Address the host listener is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(l) => l.local_addr(),
+            Listener::Udp(s) => s.local_addr(),
+        }
+    }
+
+    /// Serve the port on a background thread.
+    pub fn spawn(self) -> io::Result<ForwardHandle> {
+        let handle = ForwardHandle {
+            mapping: self.mapping,
+            addr: self.local_addr()?,
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        let stopped = handle.stopped.clone();
+        std::thread::Builder::new()
+            .name(format!("port-forward-{}", self.mapping))
+            .spawn(move || {
+                let PortForwarder {
+                    mapping,
+                    forward_socket,
+                    listener,
+                } = self;
+                match listener {
+                    Listener::Tcp(l) => serve_tcp(l, mapping.guest, forward_socket, &stopped),
+                    Listener::Udp(s) => serve_udp(s, mapping.guest, &forward_socket, &stopped),
+                }
+            })?;
+        Ok(handle)
+    }
+}
+
+/// A port being served by a [`PortForwarder`].
+pub struct ForwardHandle {
+    mapping: PortMapping,
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ForwardHandle {
+    /// The published mapping.
+    pub fn mapping(&self) -> PortMapping {
+        self.mapping
+    }
+
+    /// Stop accepting traffic and release the host port.
+    ///
+    /// Established TCP connections are left to finish on their own.
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // Wake the serving thread so it notices the flag
+        let ip = match self.addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(std::net::Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let wake = SocketAddr::new(ip, self.addr.port());
+        match self.mapping.protocol {
+            PortProtocol::Tcp => {
+                let _ = TcpStream::connect_timeout(&wake, CONNECT_RETRY_DELAY);
+            }
+            PortProtocol::Udp => {
+                let local = match wake {
+                    SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                    SocketAddr::V6(_) => SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, 0)),
+                };
+                if let Ok(socket) = UdpSocket::bind(local) {
+                    let _ = socket.send_to(&[], wake);
+                }
+            }
+        }
+    }
+}
+
+/// Bind and serve every mapping TSI can't publish.
+///
+/// All listeners are bound before any is served, so a port conflict fails
+/// the VM start instead of leaving some ports half-published.
+pub fn start_forwarders(
+    mappings: &[PortMapping],
+    forward_socket: &Path,
+) -> io::Result<Vec<ForwardHandle>> {
+    let forwarders = mappings
+        .iter()
+        .filter(|m| !m.uses_tsi())
+        .map(|m| {
+            PortForwarder::bind(*m, forward_socket).map_err(|e| {
+                io::Error::new(e.kind(), format!("failed to publish port {}: {}", m, e))
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    forwarders.into_iter().map(PortForwarder::spawn).collect()
+}
+
+/// Open a guest stream for `guest_port`, waiting for the VM to come up.
+fn connect_guest(
+    forward_socket: &Path,
+    protocol: PortProtocol,
+    guest_port: u16,
+) -> io::Result<UnixStream> {
+    let mut attempt = 0;
+    let mut stream = loop {
+        match UnixStream::connect(forward_socket) {
+            Ok(s) => break s,
+            Err(e) => {
+                attempt += 1;
+                if attempt >= CONNECT_ATTEMPTS {
+                    return Err(e);
+                }
+                std::thread::sleep(CONNECT_RETRY_DELAY);
+            }
+        }
+    };
+    port_forward::write_header(&mut stream, protocol, guest_port)?;
+    Ok(stream)
+}
+
+fn serve_tcp(
+    listener: TcpListener,
+    guest_port: u16,
+    forward_socket: PathBuf,
+    stopped: &AtomicBool,
+) {
+    for conn in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let client = match conn {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::debug!(error = %e, "port forward accept failed");
+                continue;
+            }
+        };
+        let forward_socket = forward_socket.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = relay_tcp(client, &forward_socket, guest_port) {
+                tracing::debug!(error = %e, guest_port, "tcp port forward failed");
+            }
+        });
+    }
+}
+
+fn relay_tcp(client: TcpStream, forward_socket: &Path, guest_port: u16) -> io::Result<()> {
+    let guest = connect_guest(forward_socket, PortProtocol::Tcp, guest_port)?;
+
+    let mut client_read = client.try_clone()?;
+    let mut guest_write = guest.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut guest_write);
+        let _ = guest_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut client, mut guest) = (client, guest);
+    let _ = io::copy(&mut guest, &mut client);
+    let _ = client.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+/// An active UDP peer and its guest stream.
+struct UdpSession {
+    stream: UnixStream,
+    last_seen: Instant,
+}
+
+fn serve_udp(socket: UdpSocket, guest_port: u16, forward_socket: &Path, stopped: &AtomicBool) {
+    if let Err(e) = socket.set_read_timeout(Some(UDP_SESSION_IDLE)) {
+        tracing::warn!(error = %e, "failed to set udp forward timeout");
+    }
+
+    let mut sessions: HashMap<SocketAddr, UdpSession> = HashMap::new();
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let received = socket.recv_from(&mut buf);
+        if stopped.load(Ordering::SeqCst) {
+            for session in sessions.values() {
+                let _ = session.stream.shutdown(Shutdown::Both);
+            }
+            break;
+        }
+
+        // Close sessions whose peers went quiet
+        sessions.retain(|_, s| {
+            let live = s.last_seen.elapsed() < UDP_SESSION_IDLE;
+            if !live {
+                let _ = s.stream.shutdown(Shutdown::Both);
+            }
+            live
+        });
+
+        let (n, peer) = match received {
+            Ok(r) => r,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => {
+                tracing::debug!(error = %e, "udp port forward receive failed");
+                continue;
+            }
+        };
+
+        let session = match sessions.entry(peer) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                match open_udp_session(&socket, peer, forward_socket, guest_port) {
+                    Ok(stream) => entry.insert(UdpSession {
+                        stream,
+                        last_seen: Instant::now(),
+                    }),
+                    Err(e) => {
+                        tracing::debug!(error = %e, %peer, guest_port, "udp port forward failed");
+                        continue;
+                    }
+                }
+            }
+        };
+
+        session.last_seen = Instant::now();
+        if port_forward::write_datagram(&mut session.stream, &buf[..n]).is_err() {
+            sessions.remove(&peer);
+        }
+    }
+}
+
+/// Open a guest stream for `peer` and relay guest replies back to it.
+fn open_udp_session(
+    socket: &UdpSocket,
+    peer: SocketAddr,
+    forward_socket: &Path,
+    guest_port: u16,
+) -> io::Result<UnixStream> {
+    let stream = connect_guest(forward_socket, PortProtocol::Udp, guest_port)?;
+    let mut replies = stream.try_clone()?;
+    let reply_socket = socket.try_clone()?;
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        while let Ok(n) = port_forward::read_datagram(&mut replies, &mut buf) {
+            if reply_socket.send_to(&buf[..n], peer).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(stream)
+}
+
+/// Request sent to a VM's port control socket.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PortControlRequest {
+    /// Start publishing a port.
+    Add {
+        /// Mapping to publish.
+        mapping: PortMapping,
+    },
+    /// Stop publishing a port (matched by protocol, bind address and host port).
+    Remove {
+        /// Mapping to withdraw.
+        mapping: PortMapping,
+    },
+}
+
+/// Reply from a VM's port control socket.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PortControlResponse {
+    /// The change was applied.
+    Ok,
+    /// The change conflicts with the VM's current ports.
+    Conflict {
+        /// Reason for the conflict.
+        message: String,
+    },
+    /// No such published port.
+    NotFound {
+        /// What was not found.
+        message: String,
+    },
+    /// The change could not be applied.
+    Failed {
+        /// Failure reason.
+        message: String,
+    },
+}
+
+/// Ports published by a running VM, owned by the VM process.
+pub struct PortTable {
+    forward_socket: PathBuf,
+    /// Mappings in libkrun's TSI port map (fixed until the VM restarts).
+    tsi: Vec<PortMapping>,
+    forwarded: Vec<ForwardHandle>,
+}
+
+impl PortTable {
+    /// Create a table from the ports published at boot.
+    pub fn new(
+        forward_socket: &Path,
+        tsi: Vec<PortMapping>,
+        forwarded: Vec<ForwardHandle>,
+    ) -> Self {
+        Self {
+            forward_socket: forward_socket.to_path_buf(),
+            tsi,
+            forwarded,
+        }
+    }
+
+    fn apply(&mut self, request: PortControlRequest) -> PortControlResponse {
+        match request {
+            PortControlRequest::Add { mapping } => self.add(mapping),
+            PortControlRequest::Remove { mapping } => self.remove(mapping),
+        }
+    }
+
+    fn add(&mut self, mapping: PortMapping) -> PortControlResponse {
+        let published = self
+            .tsi
+            .iter()
+            .chain(self.forwarded.iter().map(|h| &h.mapping));
+        for existing in published {
+            if existing.conflicts_with(&mapping) {
+                return PortControlResponse::Conflict {
+                    message: format!("host port already published by {}", existing),
+                };
+            }
+        }
+
+        match PortForwarder::bind(mapping, &self.forward_socket).and_then(PortForwarder::spawn) {
+            Ok(handle) => {
+                tracing::info!(port = %mapping, "published port");
+                self.forwarded.push(handle);
+                PortControlResponse::Ok
+            }
+            Err(e) => PortControlResponse::Failed {
+                message: format!("failed to publish port {}: {}", mapping, e),
+            },
+        }
+    }
+
+    fn remove(&mut self, mapping: PortMapping) -> PortControlResponse {
+        if let Some(i) = self
+            .forwarded
+            .iter()
+            .position(|h| h.mapping.same_host_side(&mapping))
+        {
+            let handle = self.forwarded.remove(i);
+            tracing::info!(port = %handle.mapping, "withdrew port");
+            handle.stop();
+            return PortControlResponse::Ok;
+        }
+        if let Some(m) = self.tsi.iter().find(|m| m.same_host_side(&mapping)) {
+            return PortControlResponse::Conflict {
+                message: format!(
+                    "port {} was published at boot and can only be removed by restarting the VM",
+                    m
+                ),
+            };
+        }
+        PortControlResponse::NotFound {
+            message: format!("port {} is not published", mapping),
+        }
+    }
+
+    /// Serve port control requests from `listener` on a background thread.
+    pub fn serve(mut self, listener: UnixListener) -> io::Result<std::thread::JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("port-control".to_string())
+            .spawn(move || {
+                for conn in listener.incoming() {
+                    let result = conn.and_then(|stream| self.handle_control(stream));
+                    if let Err(e) = result {
+                        tracing::debug!(error = %e, "port control request failed");
+                    }
+                }
+            })
+    }
+
+    fn handle_control(&mut self, stream: UnixStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => self.apply(request),
+            Err(e) => PortControlResponse::Failed {
+                message: format!("invalid request: {}", e),
+            },
+        };
+        let mut out = serde_json::to_vec(&response).map_err(io::Error::other)?;
+        out.push(b'\n');
+        (&stream).write_all(&out)
+    }
+}
+
+/// Publish `mappings` for a booting VM and serve its port control socket.
+///
+/// Returns the number of ports served by forwarders.
+pub fn serve_ports(
+    mappings: &[PortMapping],
+    forward_socket: &Path,
+    control_socket: &Path,
+) -> io::Result<usize> {
+    let _ = std::fs::remove_file(forward_socket);
+    let _ = std::fs::remove_file(control_socket);
+    let forwarded = start_forwarders(mappings, forward_socket)?;
+    let count = forwarded.len();
+    let tsi = mappings.iter().filter(|m| m.uses_tsi()).copied().collect();
+    let listener = UnixListener::bind(control_socket)?;
+    PortTable::new(forward_socket, tsi, forwarded).serve(listener)?;
+    Ok(count)
+}
+
+/// Send a request to the port control socket of a running VM.
+pub fn send_port_control(
+    control_socket: &Path,
+    request: &PortControlRequest,
+) -> io::Result<PortControlResponse> {
+    let stream = UnixStream::connect(control_socket)?;
+    stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+    let mut out = serde_json::to_vec(request).map_err(io::Error::other)?;
+    out.push(b'\n');
+    (&stream).write_all(&out)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    /// Stand-in for the guest agent: echoes traffic back, prefixed for UDP.
+    fn fake_guest(path: &Path) {
+        let listener = UnixListener::bind(path).unwrap();
+        std::thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                std::thread::spawn(move || {
+                    let (protocol, port) = port_forward::read_header(&mut conn).unwrap();
+                    assert_eq!(port, 80);
+                    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                    match protocol {
+                        PortProtocol::Tcp => {
+                            let mut writer = conn.try_clone().unwrap();
+                            io::copy(&mut conn, &mut writer).unwrap();
+                        }
+                        PortProtocol::Udp => {
+                            while let Ok(n) = port_forward::read_datagram(&mut conn, &mut buf) {
+                                let mut reply = b"echo:".to_vec();
+                                reply.extend_from_slice(&buf[..n]);
+                                port_forward::write_datagram(&mut conn, &reply).unwrap();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_tcp_forward_on_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let sock = dir.path().join("forward.sock");
+        fake_guest(&sock);
+
+        let mapping = PortMapping::new(0, 80).with_bind(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let forwarder = PortForwarder::bind(mapping, &sock).unwrap();
+        let addr = forwarder.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        forwarder.spawn().unwrap();
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"hello").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "hello");
+    }
+
+    #[test]
+    fn test_port_control_add_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let sock = dir.path().join("forward.sock");
+        let control = dir.path().join("ports.sock");
+        fake_guest(&sock);
+
+        let tsi = PortMapping::same(1);
+        let table = PortTable::new(&sock, vec![tsi], Vec::new());
+        table.serve(UnixListener::bind(&control).unwrap()).unwrap();
+
+        // Find a free loopback port for the new mapping
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mapping = PortMapping::new(port, 80).with_bind(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let add = PortControlRequest::Add { mapping };
+        assert_eq!(
+            send_port_control(&control, &add).unwrap(),
+            PortControlResponse::Ok
+        );
+        assert!(matches!(
+            send_port_control(&control, &add).unwrap(),
+            PortControlResponse::Conflict { .. }
+        ));
+
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        client.write_all(b"ping").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "ping");
+
+        let remove = PortControlRequest::Remove { mapping };
+        assert_eq!(
+            send_port_control(&control, &remove).unwrap(),
+            PortControlResponse::Ok
+        );
+        assert!(matches!(
+            send_port_control(&control, &remove).unwrap(),
+            PortControlResponse::NotFound { .. }
+        ));
+        // The host port is released
+        TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+
+        // Boot-time TSI ports can't be withdrawn from a running VM
+        assert!(matches!(
+            send_port_control(&control, &PortControlRequest::Remove { mapping: tsi }).unwrap(),
+            PortControlResponse::Conflict { .. }
+        ));
+    }
+
+    #[test]
+    fn test_udp_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let sock = dir.path().join("forward.sock");
+        fake_guest(&sock);
+
+        let mapping = PortMapping::new(0, 80)
+            .with_protocol(PortProtocol::Udp)
+            .with_bind(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let forwarder = PortForwarder::bind(mapping, &sock).unwrap();
+        let addr = forwarder.local_addr().unwrap();
+        forwarder.spawn().unwrap();
+
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buf = [0u8; 64];
+        for msg in [&b"one"[..], b"two"] {
+            client.send_to(msg, addr).unwrap();
+            let (n, from) = client.recv_from(&mut buf).unwrap();
+            assert_eq!(from, addr);
+            assert_eq!(&buf[..n], [b"echo:", msg].concat().as_slice());
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Named private networks for VM-to-VM traffic.
+//!
+//! A private network is a user-mode IPv4 subnet that microVMs join at
+//! creation. Each member gets a fixed address; its VM process switches the
+//! guest's frames to the other members (see [`super::switch`]) and answers
+//! DNS for member names, so VMs reach each other by name without root or
+//! kernel bridges.
+//!
+//! Networks live on disk, shared by every smolvm process:
+//!
+//! ```text
+//! networks/<name>/network.json   network definition
+//! networks/<name>/members/<ip>   address claim, containing the VM name
+//! networks/<name>/<ip>.sock      switch port of a running member
+//! ```
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+/// Maximum network name length (names double as DNS search domains).
+const MAX_NAME_LENGTH: usize = 32;
+
+/// Default subnets are `10.89.N.0/24`, one per network.
+const DEFAULT_SUBNET_BASE: [u8; 2] = [10, 89];
+
+/// Smallest and largest accepted prefix lengths.
+const PREFIX_RANGE: std::ops::RangeInclusive<u8> = 16..=29;
+
+/// A network definition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivateNetwork {
+    /// Network name.
+    pub name: String,
+    /// Network address of the subnet.
+    pub subnet: Ipv4Addr,
+    /// Subnet prefix length.
+    pub prefix_len: u8,
+    /// Creation timestamp (Unix seconds).
+    pub created_at: String,
+    /// Directory holding the network's state.
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+/// A VM's membership in a private network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkAttachment {
+    /// Network name.
+    pub network: String,
+    /// The VM's address on the network.
+    pub address: Ipv4Addr,
+}
+
+impl NetworkAttachment {
+    /// MAC address of the VM's interface on the network.
+    pub fn mac(&self) -> [u8; 6] {
+        mac_for(self.address)
+    }
+}
+
+/// Locally administered MAC address derived from a network address.
+///
+/// Every member and the gateway get one, so a VM can answer ARP for its
+/// peers without asking them.
+pub fn mac_for(addr: Ipv4Addr) -> [u8; 6] {
+    let [_, b, c, d] = addr.octets();
+    [0x02, 0x73, 0x6d, b, c, d]
+}
+
+impl PrivateNetwork {
+    /// Address of the embedded gateway (DNS server), the first host address.
+    pub fn gateway(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.subnet) + 1)
+    }
+
+    /// Subnet mask.
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::MAX << (32 - self.prefix_len))
+    }
+
+    /// Broadcast address of the subnet.
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.subnet) | !u32::from(self.netmask()))
+    }
+
+    /// Whether `addr` belongs to the subnet.
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & u32::from(self.netmask()) == u32::from(self.subnet)
+    }
+
+    /// Whether two networks' subnets overlap.
+    fn overlaps(&self, other: &PrivateNetwork) -> bool {
+        let shorter = self.prefix_len.min(other.prefix_len);
+        let mask = u32::MAX << (32 - shorter);
+        u32::from(self.subnet) & mask == u32::from(other.subnet) & mask
+    }
+
+    /// `subnet/prefix` notation.
+    pub fn cidr(&self) -> String {
+        format!("{}/{}", self.subnet, self.prefix_len)
+    }
+
+    /// Path of the switch port socket of the member at `addr`.
+    pub fn socket_path(&self, addr: Ipv4Addr) -> PathBuf {
+        self.dir.join(format!("{}.sock", addr))
+    }
+
+    fn members_dir(&self) -> PathBuf {
+        self.dir.join("members")
+    }
+
+    /// Members of the network as `(vm name, address)`.
+    pub fn members(&self) -> Vec<(String, Ipv4Addr)> {
+        let Ok(entries) = std::fs::read_dir(self.members_dir()) else {
+            return Vec::new();
+        };
+        let mut members: Vec<(String, Ipv4Addr)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let addr = entry.file_name().to_str()?.parse().ok()?;
+                let vm = std::fs::read_to_string(entry.path()).ok()?;
+                Some((vm.trim().to_string(), addr))
+            })
+            .collect();
+        members.sort_by_key(|(_, addr)| *addr);
+        members
+    }
+
+    /// Resolve a member name (`vm` or `vm.<network>`) to its address.
+    pub fn resolve(&self, name: &str) -> Option<Ipv4Addr> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let suffix = format!(".{}", self.name);
+        let vm = name.strip_suffix(&suffix).unwrap_or(&name);
+        self.members()
+            .into_iter()
+            .find(|(member, _)| member.eq_ignore_ascii_case(vm))
+            .map(|(_, addr)| addr)
+    }
+}
+
+/// Parse `a.b.c.d/len` into a subnet address and prefix length.
+///
+/// Host bits are cleared, so `10.1.2.3/24` is `10.1.2.0/24`.
+pub fn parse_subnet(s: &str) -> std::result::Result<(Ipv4Addr, u8), String> {
+    let (addr, len) = s
+        .split_once('/')
+        .ok_or_else(|| format!("invalid subnet '{}': expected ADDRESS/PREFIX", s))?;
+    let addr: Ipv4Addr = addr
+        .parse()
+        .map_err(|_| format!("invalid subnet address '{}'", addr))?;
+    let len: u8 = len
+        .parse()
+        .map_err(|_| format!("invalid prefix length '{}'", len))?;
+    if !PREFIX_RANGE.contains(&len) {
+        return Err(format!(
+            "prefix length must be between {} and {}",
+            PREFIX_RANGE.start(),
+            PREFIX_RANGE.end()
+        ));
+    }
+    let mask = u32::MAX << (32 - len);
+    Ok((Ipv4Addr::from(u32::from(addr) & mask), len))
+}
+
+/// Check that a network name is usable as a DNS label and directory name.
+pub fn validate_network_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::config(
+            "validate network name",
+            format!(
+                "'{}' must be 1-{} lowercase letters, digits or '-', not starting or ending with '-'",
+                name, MAX_NAME_LENGTH
+            ),
+        ))
+    }
+}
+
+/// On-disk store of private networks.
+#[derive(Debug, Clone)]
+pub struct NetworkStore {
+    root: PathBuf,
+}
+
+impl NetworkStore {
+    /// Open the store at the default location.
+    pub fn open() -> Result<Self> {
+        let data_dir = dirs::data_local_dir().ok_or_else(|| {
+            Error::config(
+                "open network store",
+                "could not determine local data directory",
+            )
+        })?;
+        Ok(Self::at(data_dir.join("smolvm").join("networks")))
+    }
+
+    /// Open the store rooted at `root`.
+    pub fn at(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn network_dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Create a network, picking a free `10.89.N.0/24` subnet unless one is given.
+    pub fn create(&self, name: &str, subnet: Option<(Ipv4Addr, u8)>) -> Result<PrivateNetwork> {
+        validate_network_name(name)?;
+        let existing = self.list()?;
+        if existing.iter().any(|n| n.name == name) {
+            return Err(Error::config(
+                "create network",
+                format!("network '{}' already exists", name),
+            ));
+        }
+
+        let mut network = PrivateNetwork {
+            name: name.to_string(),
+            subnet: Ipv4Addr::UNSPECIFIED,
+            prefix_len: 24,
+            created_at: crate::util::current_timestamp(),
+            dir: self.network_dir(name),
+        };
+        match subnet {
+            Some((addr, len)) => {
+                network.subnet = addr;
+                network.prefix_len = len;
+                if let Some(other) = existing.iter().find(|n| n.overlaps(&network)) {
+                    return Err(Error::config(
+                        "create network",
+                        format!(
+                            "subnet {} overlaps network '{}' ({})",
+                            network.cidr(),
+                            other.name,
+                            other.cidr()
+                        ),
+                    ));
+                }
+            }
+            None => {
+                let [a, b] = DEFAULT_SUBNET_BASE;
+                let free = (0..=u8::MAX)
+                    .map(|n| Ipv4Addr::new(a, b, n, 0))
+                    .find(|addr| {
+                        network.subnet = *addr;
+                        !existing.iter().any(|n| n.overlaps(&network))
+                    });
+                network.subnet = free.ok_or_else(|| {
+                    Error::config("create network", "no free subnet left in 10.89.0.0/16")
+                })?;
+            }
+        }
+
+        std::fs::create_dir_all(network.members_dir())
+            .map_err(|e| Error::config("create network", e.to_string()))?;
+        let json = serde_json::to_string_pretty(&network)
+            .map_err(|e| Error::config("create network", e.to_string()))?;
+        std::fs::write(network.dir.join("network.json"), json)
+            .map_err(|e| Error::config("create network", e.to_string()))?;
+        Ok(network)
+    }
+
+    /// Load a network by name.
+    pub fn get(&self, name: &str) -> Result<PrivateNetwork> {
+        let dir = self.network_dir(name);
+        let content = std::fs::read_to_string(dir.join("network.json")).map_err(|_| {
+            Error::config(
+                "load network",
+                format!(
+                    "network '{}' does not exist (create it with `smolvm network create {}`)",
+                    name, name
+                ),
+            )
+        })?;
+        let mut network: PrivateNetwork = serde_json::from_str(&content)
+            .map_err(|e| Error::config("load network", format!("{}: {}", name, e)))?;
+        network.dir = dir;
+        Ok(network)
+    }
+
+    /// List all networks, sorted by name.
+    pub fn list(&self) -> Result<Vec<PrivateNetwork>> {
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::config("list networks", e.to_string())),
+        };
+        let mut networks: Vec<PrivateNetwork> = entries
+            .flatten()
+            .filter_map(|entry| self.get(entry.file_name().to_str()?).ok())
+            .collect();
+        networks.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(networks)
+    }
+
+    /// Remove a network that has no members.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let network = self.get(name)?;
+        let members = network.members();
+        if !members.is_empty() {
+            let names: Vec<&str> = members.iter().map(|(vm, _)| vm.as_str()).collect();
+            return Err(Error::config(
+                "remove network",
+                format!(
+                    "network '{}' is used by {}; delete those VMs first",
+                    name,
+                    names.join(", ")
+                ),
+            ));
+        }
+        std::fs::remove_dir_all(&network.dir)
+            .map_err(|e| Error::config("remove network", e.to_string()))
+    }
+
+    /// Add a VM to a network, claiming the first free address.
+    pub fn join(&self, name: &str, vm: &str) -> Result<NetworkAttachment> {
+        let network = self.get(name)?;
+        if let Some(address) = network.resolve(vm) {
+            return Ok(NetworkAttachment {
+                network: network.name,
+                address,
+            });
+        }
+
+        let first = u32::from(network.gateway()) + 1;
+        let last = u32::from(network.broadcast()) - 1;
+        for addr in (first..=last).map(Ipv4Addr::from) {
+            // create_new makes the claim atomic across concurrent joins
+            let claim = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(network.members_dir().join(addr.to_string()));
+            match claim {
+                Ok(mut file) => {
+                    file.write_all(vm.as_bytes())
+                        .map_err(|e| Error::config("join network", e.to_string()))?;
+                    return Ok(NetworkAttachment {
+                        network: network.name,
+                        address: addr,
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::config("join network", e.to_string())),
+            }
+        }
+        Err(Error::config(
+            "join network",
+            format!("network '{}' ({}) is full", network.name, network.cidr()),
+        ))
+    }
+
+    /// Release a VM's address. Missing networks and claims are ignored.
+    pub fn leave(&self, attachment: &NetworkAttachment) {
+        let Ok(network) = self.get(&attachment.network) else {
+            return;
+        };
+        let claim = network.members_dir().join(attachment.address.to_string());
+        let _ = std::fs::remove_file(claim);
+        let _ = std::fs::remove_file(network.socket_path(attachment.address));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_join_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NetworkStore::at(dir.path());
+
+        let dev = store.create("dev", None).unwrap();
+        assert_eq!(dev.cidr(), "10.89.0.0/24");
+        assert_eq!(dev.gateway(), Ipv4Addr::new(10, 89, 0, 1));
+        assert_eq!(dev.broadcast(), Ipv4Addr::new(10, 89, 0, 255));
+        // Default subnets don't collide
+        assert_eq!(store.create("ci", None).unwrap().cidr(), "10.89.1.0/24");
+        assert!(store.create("dev", None).is_err());
+        assert!(store
+            .create("other", Some(parse_subnet("10.89.0.128/25").unwrap()))
+            .is_err());
+
+        let app = store.join("dev", "app").unwrap();
+        let db = store.join("dev", "db").unwrap();
+        assert_eq!(app.address, Ipv4Addr::new(10, 89, 0, 2));
+        assert_eq!(db.address, Ipv4Addr::new(10, 89, 0, 3));
+        assert_eq!(db.mac(), [0x02, 0x73, 0x6d, 89, 0, 3]);
+        // Joining again keeps the address
+        assert_eq!(store.join("dev", "db").unwrap(), db);
+
+        let dev = store.get("dev").unwrap();
+        assert_eq!(dev.resolve("db"), Some(db.address));
+        assert_eq!(dev.resolve("DB.dev."), Some(db.address));
+        assert_eq!(dev.resolve("cache"), None);
+
+        // Networks in use can't be removed
+        assert!(store.remove("dev").is_err());
+        store.leave(&app);
+        store.leave(&db);
+        assert!(dev.members().is_empty());
+        store.remove("dev").unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_subnet_and_names() {
+        assert_eq!(
+            parse_subnet("172.30.5.9/16").unwrap(),
+            (Ipv4Addr::new(172, 30, 0, 0), 16)
+        );
+        assert!(parse_subnet("10.0.0.0/8").is_err());
+        assert!(parse_subnet("10.0.0.0").is_err());
+
+        assert!(validate_network_name("dev-1").is_ok());
+        assert!(validate_network_name("Dev").is_err());
+        assert!(validate_network_name("-dev").is_err());
+        assert!(validate_network_name("").is_err());
+    }
+}
@@ -0,0 +1,606 @@
+//! Host-side egress proxy for allowlisted networking.
+//!
+//! A VM with an egress allowlist boots without TSI, so the guest has no
+//! direct path to the host network. The agent instead runs an HTTP proxy
+//! port inside the guest and forwards each connection over vsock to a unix
+//! socket served by [`EgressProxy`]. The proxy accepts `CONNECT host:port`
+//! tunnels and absolute-form `http://` requests, checks the destination
+//! against the allowlist, and only then dials out. Denied destinations get
+//! a `403` and are appended to the VM's egress log. A plain-HTTP connection
+//! carries one request, whose response is marked `Connection: close`, so
+//! every request is checked and a kept-alive connection can't be reused to
+//! reach another destination.
+//!
+//! This is proxy-only egress, not a packet filter: clients that ignore
+//! the proxy variables, non-HTTP protocols (SSH, database clients, raw TCP)
+//! and direct DNS lookups have no route out of the guest, so they fail
+//! without reaching the proxy or its log, even for allowed destinations.
+
+use super::allowlist::{addr_allowed, name_allowed, EgressRule, EgressTarget};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Maximum size of a proxied request head.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Timeout for reading the request head from the guest.
+const HEAD_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for connecting to an upstream address.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request headers that only concern the proxy hop and are not forwarded.
+const HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authorization",
+];
+
+/// Allowlist-enforcing egress proxy.
+#[derive(Clone)]
+pub struct EgressProxy {
+    rules: Arc<[EgressRule]>,
+    log_path: Option<PathBuf>,
+}
+
+/// A parsed proxy request.
+#[derive(Debug, PartialEq, Eq)]
+struct ProxyRequest {
+    /// Destination host (name or IP literal, without brackets).
+    host: String,
+    /// Destination port.
+    port: u16,
+    /// `true` for `CONNECT` tunnels.
+    tunnel: bool,
+    /// Rewritten request head to send upstream (empty for tunnels).
+    head: Vec<u8>,
+    /// Length of the request body (`Content-Length`).
+    body_len: u64,
+    /// Whether the client waits for `100 Continue` before sending the body.
+    expect_continue: bool,
+}
+
+impl EgressProxy {
+    /// Create a proxy enforcing `rules`, logging denials to `log_path`.
+    pub fn new(rules: Vec<EgressRule>, log_path: Option<PathBuf>) -> Self {
+        Self {
+            rules: rules.into(),
+            log_path,
+        }
+    }
+
+    /// Bind the proxy socket at `path`, replacing any stale socket file.
+    pub fn bind(path: &Path) -> io::Result<UnixListener> {
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        UnixListener::bind(path)
+    }
+
+    /// Serve connections from `listener` on a background thread.
+    pub fn spawn(self, listener: UnixListener) -> io::Result<std::thread::JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("egress-proxy".to_string())
+            .spawn(move || {
+                for conn in listener.incoming() {
+                    let conn = match conn {
+                        Ok(c) => c,
+                        Err(e) => {
+                            tracing::debug!(error = %e, "egress proxy accept failed");
+                            continue;
+                        }
+                    };
+                    let proxy = self.clone();
+                    let _ = std::thread::Builder::new()
+                        .name("egress-conn".to_string())
+                        .spawn(move || {
+                            if let Err(e) = proxy.handle(conn) {
+                                tracing::debug!(error = %e, "egress proxy connection failed");
+                            }
+                        });
+                }
+            })
+    }
+
+    /// Handle a single proxied connection from the guest.
+    fn handle(&self, mut client: UnixStream) -> io::Result<()> {
+        client.set_read_timeout(Some(HEAD_READ_TIMEOUT))?;
+        let (head, rest) = match read_head(&mut client) {
+            Ok(parts) => parts,
+            Err(e) => {
+                let _ = respond(&mut client, 400, "Bad Request", &e.to_string());
+                return Err(e);
+            }
+        };
+        client.set_read_timeout(None)?;
+
+        let request = match parse_request(&head) {
+            Ok(r) => r,
+            Err(e) => return respond(&mut client, 400, "Bad Request", &e),
+        };
+
+        let addrs = match self.resolve_allowed(&request.host, request.port) {
+            Ok(Some(addrs)) => addrs,
+            Ok(None) => {
+                self.log_denied(&request.host, request.port);
+                return respond(
+                    &mut client,
+                    403,
+                    "Forbidden",
+                    &format!(
+                        "egress to {} denied by network allowlist",
+                        authority(&request.host, request.port)
+                    ),
+                );
+            }
+            Err(e) => {
+                return respond(
+                    &mut client,
+                    502,
+                    "Bad Gateway",
+                    &format!("cannot resolve {}: {}", request.host, e),
+                )
+            }
+        };
+
+        let mut upstream = match connect_any(&addrs) {
+            Ok(s) => s,
+            Err(e) => {
+                return respond(
+                    &mut client,
+                    502,
+                    "Bad Gateway",
+                    &format!(
+                        "cannot connect to {}: {}",
+                        authority(&request.host, request.port),
+                        e
+                    ),
+                )
+            }
+        };
+
+        if !request.tunnel {
+            return forward_request(client, upstream, &request, rest);
+        }
+        client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+        upstream.write_all(&rest)?;
+
+        splice(client, upstream)
+    }
+
+    /// Resolve `host:port` to the addresses the allowlist permits.
+    ///
+    /// Returns `Ok(None)` when the destination is denied. Names matched by a
+    /// domain rule may use every resolved address; otherwise only resolved
+    /// addresses inside an allowed CIDR block are kept.
+    fn resolve_allowed(&self, host: &str, port: u16) -> io::Result<Option<Vec<SocketAddr>>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            let addr = SocketAddr::new(ip, port);
+            return Ok(addr_allowed(&self.rules, addr).then(|| vec![addr]));
+        }
+
+        if name_allowed(&self.rules, host, port) {
+            return Ok(Some((host, port).to_socket_addrs()?.collect()));
+        }
+
+        let has_cidr = self
+            .rules
+            .iter()
+            .any(|r| matches!(r.target, EgressTarget::Cidr { .. }));
+        if !has_cidr {
+            return Ok(None);
+        }
+
+        let addrs: Vec<SocketAddr> = (host, port)
+            .to_socket_addrs()?
+            .filter(|a| addr_allowed(&self.rules, *a))
+            .collect();
+        Ok((!addrs.is_empty()).then_some(addrs))
+    }
+
+    fn log_denied(&self, host: &str, port: u16) {
+        let target = authority(host, port);
+        tracing::warn!(target = %target, "egress connection denied by allowlist");
+
+        let Some(path) = &self.log_path else {
+            return;
+        };
+        let line = format!(
+            "{} denied {}\n",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            target
+        );
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+        if let Err(e) = result {
+            tracing::debug!(error = %e, path = %path.display(), "failed to write egress log");
+        }
+    }
+}
+
+/// Read up to the end of the request head, returning the head and any
+/// bytes that followed it.
+fn read_head(stream: &mut impl Read) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        if let Some(end) = find_head_end(&buf) {
+            let rest = buf.split_off(end);
+            return Ok((buf, rest));
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before request head",
+            ));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+/// Parse a proxy request head.
+fn parse_request(head: &[u8]) -> Result<ProxyRequest, String> {
+    let head = std::str::from_utf8(head).map_err(|_| "request head is not UTF-8".to_string())?;
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v)) if parts.next().is_none() => (m, t, v),
+        _ => return Err(format!("malformed request line '{}'", request_line)),
+    };
+
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = split_authority(target, None)?;
+        return Ok(ProxyRequest {
+            host,
+            port,
+            tunnel: true,
+            head: Vec::new(),
+            body_len: 0,
+            expect_continue: false,
+        });
+    }
+
+    let rest = target.strip_prefix("http://").ok_or_else(|| {
+        format!(
+            "expected CONNECT or an absolute http:// URL, got '{}'",
+            target
+        )
+    })?;
+    let (authority_part, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = split_authority(authority_part, Some(80))?;
+
+    let mut out = format!("{} {} {}\r\n", method, path, version);
+    let mut has_host = false;
+    let mut body_len = 0;
+    let mut expect_continue = false;
+    for line in lines.filter(|l| !l.is_empty()) {
+        let (name, value) = line.split_once(':').unwrap_or((line, ""));
+        let (name, value) = (name.trim(), value.trim());
+        if HOP_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err("chunked request bodies are not supported; send Content-Length".into());
+        }
+        if name.eq_ignore_ascii_case("content-length") {
+            body_len = value
+                .parse()
+                .map_err(|_| format!("invalid Content-Length '{}'", value))?;
+        }
+        // The proxy answers 100 Continue itself
+        if name.eq_ignore_ascii_case("expect") {
+            expect_continue = value.eq_ignore_ascii_case("100-continue");
+            continue;
+        }
+        has_host |= name.eq_ignore_ascii_case("host");
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    if !has_host {
+        out.push_str(&format!("Host: {}\r\n", authority_part));
+    }
+    out.push_str("Connection: close\r\n\r\n");
+
+    Ok(ProxyRequest {
+        host,
+        port,
+        tunnel: false,
+        head: out.into_bytes(),
+        body_len,
+        expect_continue,
+    })
+}
+
+/// Split `host[:port]` (with `[v6]:port` for IPv6 literals).
+fn split_authority(s: &str, default_port: Option<u16>) -> Result<(String, u16), String> {
+    let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+        let (host, after) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("malformed authority '{}'", s))?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match s.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (s, None),
+        }
+    };
+    let port = match port {
+        Some(p) => p.parse().map_err(|_| format!("invalid port in '{}'", s))?,
+        None => default_port.ok_or_else(|| format!("missing port in '{}'", s))?,
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in '{}'", s));
+    }
+    Ok((host.to_string(), port))
+}
+
+fn authority(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+fn respond(stream: &mut UnixStream, code: u16, reason: &str, body: &str) -> io::Result<()> {
+    let body = format!("smolvm: {}\n", body);
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())
+}
+
+fn connect_any(addrs: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "no addresses");
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(s) => return Ok(s),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+/// Forward one plain-HTTP request and its response, then close the
+/// connection.
+///
+/// Bytes the client sends after the request body are dropped, and the
+/// response is marked `Connection: close` so the client opens a new
+/// connection, and gets a new allowlist check, for its next request.
+fn forward_request(
+    mut client: UnixStream,
+    mut upstream: TcpStream,
+    request: &ProxyRequest,
+    rest: Vec<u8>,
+) -> io::Result<()> {
+    upstream.write_all(&request.head)?;
+    if request.expect_continue && request.body_len > 0 {
+        client.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+    let buffered = rest
+        .len()
+        .min(usize::try_from(request.body_len).unwrap_or(usize::MAX));
+    upstream.write_all(&rest[..buffered])?;
+    let remaining = request.body_len - buffered as u64;
+    let copied = io::copy(&mut (&client).take(remaining), &mut upstream)?;
+    if copied != remaining {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed during request body",
+        ));
+    }
+
+    let (head, body) = read_head(&mut upstream)?;
+    client.write_all(&close_response_head(&head))?;
+    client.write_all(&body)?;
+    io::copy(&mut upstream, &mut client)?;
+    client.shutdown(Shutdown::Write)
+}
+
+/// Replace the connection headers of a response head with
+/// `Connection: close`.
+fn close_response_head(head: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(head.len() + 19);
+    for line in head.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let name = line.split(|&b| b == b':').next().unwrap_or_default();
+        let hop = HOP_HEADERS
+            .iter()
+            .any(|h| name.trim_ascii().eq_ignore_ascii_case(h.as_bytes()));
+        if !hop || out.is_empty() {
+            out.extend_from_slice(line);
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    out.extend_from_slice(b"Connection: close\r\n\r\n");
+    out
+}
+
+/// Copy bytes in both directions until each side reaches EOF.
+fn splice(client: UnixStream, upstream: TcpStream) -> io::Result<()> {
+    let mut client_read = client.try_clone()?;
+    let mut upstream_write = upstream.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut upstream_write);
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut client, mut upstream) = (client, upstream);
+    let _ = io::copy(&mut upstream, &mut client);
+    let _ = client.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::allowlist::parse_allowlist;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_connect() {
+        let req =
+            parse_request(b"CONNECT github.com:443 HTTP/1.1\r\nHost: github.com\r\n\r\n").unwrap();
+        assert_eq!(req.host, "github.com");
+        assert_eq!(req.port, 443);
+        assert!(req.tunnel);
+
+        let req = parse_request(b"CONNECT [fd00::1]:8443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(req.host, "fd00::1");
+        assert_eq!(req.port, 8443);
+
+        assert!(parse_request(b"CONNECT github.com HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_absolute_form_rewrites_head() {
+        let req = parse_request(
+            b"GET http://example.com:8080/a?b=1 HTTP/1.1\r\nHost: example.com:8080\r\nProxy-Connection: keep-alive\r\nAccept: */*\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 8080);
+        assert!(!req.tunnel);
+        assert_eq!(
+            String::from_utf8(req.head).unwrap(),
+            "GET /a?b=1 HTTP/1.1\r\nHost: example.com:8080\r\nAccept: */*\r\nConnection: close\r\n\r\n"
+        );
+
+        let req = parse_request(b"GET http://example.com HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(req.port, 80);
+        assert_eq!(
+            String::from_utf8(req.head).unwrap(),
+            "GET / HTTP/1.0\r\nHost: example.com\r\nConnection: close\r\n\r\n"
+        );
+
+        let req = parse_request(
+            b"POST http://example.com/up HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(req.body_len, 5);
+        assert!(req.expect_continue);
+        assert!(!String::from_utf8(req.head).unwrap().contains("Expect"));
+        assert!(parse_request(
+            b"POST http://example.com/ HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"
+        )
+        .is_err());
+
+        assert!(parse_request(b"GET /relative HTTP/1.1\r\n\r\n").is_err());
+        assert!(parse_request(b"GET https://example.com/ HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    fn proxy_roundtrip(rules: &str, log: Option<PathBuf>, request: &str) -> String {
+        let proxy = EgressProxy::new(parse_allowlist(rules).unwrap(), log);
+        let (mut guest, host) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || proxy.handle(host));
+        guest.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        guest.read_to_string(&mut response).unwrap();
+        handle.join().unwrap().unwrap();
+        response
+    }
+
+    #[test]
+    fn test_connect_allowed_tunnels_bytes() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut conn, _) = upstream.accept().unwrap();
+            let mut buf = [0u8; 4];
+            conn.read_exact(&mut buf).unwrap();
+            conn.write_all(&buf).unwrap();
+        });
+
+        let response = proxy_roundtrip(
+            "127.0.0.1",
+            None,
+            &format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\nping", port),
+        );
+        assert_eq!(response, "HTTP/1.1 200 Connection Established\r\n\r\nping");
+    }
+
+    #[test]
+    fn test_plain_http_carries_one_request_per_connection() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = upstream.accept().unwrap();
+            let (head, mut received) = read_head(&mut conn).unwrap();
+            conn.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: keep-alive\r\n\r\nok",
+            )
+            .unwrap();
+            // The request asked for Connection: close
+            conn.shutdown(Shutdown::Write).unwrap();
+            conn.read_to_end(&mut received).unwrap();
+            (String::from_utf8(head).unwrap(), received)
+        });
+
+        // A kept-alive second request to a denied host never goes out
+        let response = proxy_roundtrip(
+            "127.0.0.1",
+            None,
+            &format!(
+                "POST http://127.0.0.1:{}/a HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody\
+                 GET http://192.0.2.1/ HTTP/1.1\r\n\r\n",
+                port
+            ),
+        );
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+        );
+        let (head, received) = server.join().unwrap();
+        assert!(head.starts_with("POST /a HTTP/1.1\r\n"));
+        assert_eq!(received, b"body");
+    }
+
+    #[test]
+    fn test_connect_denied_is_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("egress.log");
+
+        let response = proxy_roundtrip(
+            "github.com,10.0.0.0/8:443",
+            Some(log.clone()),
+            "CONNECT 192.0.2.1:443 HTTP/1.1\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(response.contains("192.0.2.1:443 denied"));
+
+        let logged = std::fs::read_to_string(&log).unwrap();
+        assert!(logged.trim_end().ends_with("denied 192.0.2.1:443"));
+    }
+}
@@ -0,0 +1,191 @@
+//! Upstream DNS resolution for VMs.
+//!
+//! Queries the host doesn't answer itself go either to a DNS server over
+//! UDP, or to the host's system resolver. The latter follows the host's
+//! split-DNS setup (VPN domains, systemd-resolved, macOS scoped resolvers),
+//! which a fixed public server like 1.1.1.1 bypasses.
+//!
+//! The system resolver is also served to the guest over vsock, framed like
+//! DNS over TCP: a two-byte big-endian length before each message.
+
+use super::dns;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long to wait for an upstream DNS server.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The host's resolver configuration, for record types the system resolver
+/// can't look up.
+const HOST_RESOLV_CONF: &str = "/etc/resolv.conf";
+
+const DNS_PORT: u16 = 53;
+
+/// Where queries for names the host doesn't know are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upstream {
+    /// A DNS server, queried over UDP.
+    Server(IpAddr),
+    /// The host's system resolver.
+    System,
+}
+
+impl Upstream {
+    /// Resolve a raw DNS query, returning the raw response.
+    pub fn query(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Upstream::Server(ip) => query_server(SocketAddr::new(*ip, DNS_PORT), query),
+            Upstream::System => query_system(query),
+        }
+    }
+}
+
+/// Send a query to a DNS server over UDP.
+fn query_server(server: SocketAddr, query: &[u8]) -> io::Result<Vec<u8>> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(UPSTREAM_TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(query)?;
+    let mut buf = vec![0u8; dns::MAX_MESSAGE_SIZE];
+    let n = socket.recv(&mut buf)?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+/// Answer a query with the host's system resolver.
+///
+/// A and AAAA lookups go through `getaddrinfo`, which honours the host's
+/// per-domain resolvers. Other record types are forwarded to the first
+/// nameserver in the host's `/etc/resolv.conf`.
+fn query_system(query: &[u8]) -> io::Result<Vec<u8>> {
+    let question = dns::parse_query(query)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a DNS query"))?;
+
+    if question.qtype != dns::TYPE_A && question.qtype != dns::TYPE_AAAA {
+        return match host_nameserver() {
+            Some(server) => query_server(SocketAddr::new(server, DNS_PORT), query),
+            None => Ok(dns::build_response(
+                query,
+                &question,
+                &[],
+                dns::RCODE_REFUSED,
+            )),
+        };
+    }
+
+    let want_v4 = question.qtype == dns::TYPE_A;
+    match (question.name.as_str(), 0).to_socket_addrs() {
+        Ok(addrs) => {
+            let mut answers: Vec<IpAddr> = Vec::new();
+            for addr in addrs.map(|a| a.ip()) {
+                if addr.is_ipv4() == want_v4 && !answers.contains(&addr) {
+                    answers.push(addr);
+                }
+            }
+            Ok(dns::build_response(
+                query,
+                &question,
+                &answers,
+                dns::RCODE_NOERROR,
+            ))
+        }
+        // getaddrinfo doesn't tell a missing name from a failed lookup
+        Err(_) => Ok(dns::build_response(
+            query,
+            &question,
+            &[],
+            dns::RCODE_NXDOMAIN,
+        )),
+    }
+}
+
+/// First nameserver in the host's resolver configuration.
+fn host_nameserver() -> Option<IpAddr> {
+    let content = std::fs::read_to_string(HOST_RESOLV_CONF).ok()?;
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        (fields.next() == Some("nameserver"))
+            .then(|| fields.next()?.parse().ok())
+            .flatten()
+    })
+}
+
+/// Serve the host's system resolver to a guest.
+///
+/// Binds `socket_path` and answers length-prefixed queries on each
+/// connection from a background thread.
+pub fn serve_system_resolver(socket_path: &Path) -> io::Result<()> {
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+    std::thread::Builder::new()
+        .name("dns-resolver".to_string())
+        .spawn(move || {
+            for conn in listener.incoming() {
+                match conn {
+                    Ok(stream) => {
+                        std::thread::spawn(move || {
+                            if let Err(e) = handle_connection(stream) {
+                                tracing::debug!(error = %e, "DNS resolver connection failed");
+                            }
+                        });
+                    }
+                    Err(e) => tracing::debug!(error = %e, "DNS resolver accept failed"),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn handle_connection(mut stream: UnixStream) -> io::Result<()> {
+    loop {
+        let mut len = [0u8; 2];
+        match stream.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut query)?;
+
+        let response = Upstream::System.query(&query)?;
+        let len = u16::try_from(response.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "DNS response too large"))?;
+        stream.write_all(&len.to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_resolver_over_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("dns.sock");
+        serve_system_resolver(&socket_path).unwrap();
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        let query = dns::encode_query(7, "localhost", dns::TYPE_A);
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&query).unwrap();
+
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).unwrap();
+        let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut response).unwrap();
+
+        assert_eq!(&response[0..2], &[0, 7]);
+        assert_eq!(response[3] & 0x0f, dns::RCODE_NOERROR);
+        assert!(response.ends_with(&[127, 0, 0, 1]));
+    }
+}
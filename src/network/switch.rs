@@ -0,0 +1,476 @@
+//! User-mode switch port for private networks.
+//!
+//! Each VM on a [private network](super::private) gets a virtio-net
+//! interface backed by a unix datagram socket pair. Its VM process serves
+//! the other end:
+//!
+//! - ARP requests for any address on the subnet are answered locally with
+//!   the address's derived MAC, so no broadcast ever leaves the VM.
+//! - IPv4 frames for a member address go to that member's switch port
+//!   socket, whose VM process hands them to its guest.
+//! - DNS queries to the gateway resolve member names; other names are
+//!   forwarded upstream when the VM has egress.
+//! - Frames whose source MAC or IP is not the VM's own are dropped, so a
+//!   guest cannot pose as another member.
+//!
+//! Nothing is bridged into the host network stack, so this needs no root.
+
+use super::dns;
+use super::private::{mac_for, NetworkAttachment, PrivateNetwork};
+use super::resolver::Upstream;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixDatagram;
+use std::sync::Arc;
+
+/// Largest frame relayed (MTU 1500 plus the Ethernet header).
+const MAX_FRAME_SIZE: usize = 1514;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETH_HEADER_LEN: usize = 14;
+const ARP_LEN: usize = 28;
+const IPPROTO_UDP: u8 = 17;
+const DNS_PORT: u16 = 53;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// What to do with a frame sent by the guest.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Send this frame back to the guest.
+    Reply(Vec<u8>),
+    /// Relay the frame to the member at this address.
+    Forward(Ipv4Addr),
+    /// Forward a DNS query upstream; the reply goes to this guest UDP port.
+    Upstream { query: Vec<u8>, guest_port: u16 },
+    /// Nothing to do.
+    Drop,
+}
+
+/// The frame-handling side of a switch port.
+struct Port {
+    network: PrivateNetwork,
+    address: Ipv4Addr,
+    mac: [u8; 6],
+    upstream: Option<Upstream>,
+}
+
+impl Port {
+    fn handle(&self, frame: &[u8]) -> Action {
+        if frame.len() < ETH_HEADER_LEN || frame[6..12] != self.mac {
+            return Action::Drop;
+        }
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ETHERTYPE_ARP => self.handle_arp(&frame[ETH_HEADER_LEN..]),
+            ETHERTYPE_IPV4 => self.handle_ipv4(frame),
+            // IPv6 and everything else stays inside the guest
+            _ => Action::Drop,
+        }
+    }
+
+    fn handle_arp(&self, arp: &[u8]) -> Action {
+        // Ethernet/IPv4 requests only
+        if arp.len() < ARP_LEN || arp[0..8] != [0, 1, 8, 0, 6, 4, 0, 1] {
+            return Action::Drop;
+        }
+        let sender_mac = &arp[8..14];
+        let sender_ip = &arp[14..18];
+        if sender_mac != self.mac || sender_ip != self.address.octets() {
+            return Action::Drop;
+        }
+        let target = Ipv4Addr::new(arp[24], arp[25], arp[26], arp[27]);
+        if !self.network.contains(target)
+            || target == self.address
+            || target == self.network.broadcast()
+            || target == self.network.subnet
+        {
+            return Action::Drop;
+        }
+
+        let target_mac = mac_for(target);
+        let mut reply = Vec::with_capacity(ETH_HEADER_LEN + ARP_LEN);
+        reply.extend_from_slice(sender_mac);
+        reply.extend_from_slice(&target_mac);
+        reply.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        reply.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 2]);
+        reply.extend_from_slice(&target_mac);
+        reply.extend_from_slice(&target.octets());
+        reply.extend_from_slice(sender_mac);
+        reply.extend_from_slice(sender_ip);
+        Action::Reply(reply)
+    }
+
+    fn handle_ipv4(&self, frame: &[u8]) -> Action {
+        let ip = &frame[ETH_HEADER_LEN..];
+        if ip.len() < 20 || ip[0] >> 4 != 4 {
+            return Action::Drop;
+        }
+        let header_len = usize::from(ip[0] & 0x0f) * 4;
+        let src = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+        let dst = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+        if src != self.address {
+            return Action::Drop;
+        }
+
+        if dst == self.network.gateway() {
+            let udp = match ip.get(header_len..) {
+                Some(udp) if ip[9] == IPPROTO_UDP && udp.len() >= 8 => udp,
+                _ => return Action::Drop,
+            };
+            let src_port = u16::from_be_bytes([udp[0], udp[1]]);
+            let dst_port = u16::from_be_bytes([udp[2], udp[3]]);
+            if dst_port != DNS_PORT {
+                return Action::Drop;
+            }
+            return self.handle_dns(&udp[8..], src_port);
+        }
+
+        if self.network.contains(dst)
+            && dst != self.address
+            && dst != self.network.broadcast()
+            && dst != self.network.subnet
+        {
+            Action::Forward(dst)
+        } else {
+            Action::Drop
+        }
+    }
+
+    fn handle_dns(&self, query: &[u8], guest_port: u16) -> Action {
+        let Some(question) = dns::parse_query(query) else {
+            return Action::Drop;
+        };
+
+        let response = if let Some(addr) = self.network.resolve(&question.name) {
+            // Members only have IPv4 addresses
+            let answers: &[_] = if question.qtype == dns::TYPE_A {
+                &[addr.into()]
+            } else {
+                &[]
+            };
+            dns::build_response(query, &question, answers, dns::RCODE_NOERROR)
+        } else if question.name == self.network.name
+            || question.name.ends_with(&format!(".{}", self.network.name))
+        {
+            dns::build_response(query, &question, &[], dns::RCODE_NXDOMAIN)
+        } else if self.upstream.is_some() {
+            return Action::Upstream {
+                query: query.to_vec(),
+                guest_port,
+            };
+        } else {
+            dns::build_response(query, &question, &[], dns::RCODE_REFUSED)
+        };
+        Action::Reply(self.dns_reply(&response, guest_port))
+    }
+
+    /// Wrap a DNS payload from the gateway into a frame for the guest.
+    fn dns_reply(&self, payload: &[u8], guest_port: u16) -> Vec<u8> {
+        let gateway = self.network.gateway();
+        let udp_len = 8 + payload.len();
+        let total_len = 20 + udp_len;
+
+        let mut frame = Vec::with_capacity(ETH_HEADER_LEN + total_len);
+        frame.extend_from_slice(&self.mac);
+        frame.extend_from_slice(&mac_for(gateway));
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+        let mut ip = [0u8; 20];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+        ip[6] = 0x40; // Don't fragment
+        ip[8] = 64;
+        ip[9] = IPPROTO_UDP;
+        ip[12..16].copy_from_slice(&gateway.octets());
+        ip[16..20].copy_from_slice(&self.address.octets());
+        let checksum = ipv4_checksum(&ip);
+        ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+        frame.extend_from_slice(&ip);
+
+        // UDP checksum 0 means "none" over IPv4
+        frame.extend_from_slice(&DNS_PORT.to_be_bytes());
+        frame.extend_from_slice(&guest_port.to_be_bytes());
+        frame.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Whether a frame from a peer is addressed to this VM.
+    fn accepts(&self, frame: &[u8]) -> bool {
+        frame.len() >= ETH_HEADER_LEN && (frame[0..6] == self.mac || frame[0..6] == BROADCAST_MAC)
+    }
+}
+
+/// Internet checksum of an IPv4 header (with its checksum field zeroed).
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Start the switch port of a VM joining `network`.
+///
+/// Returns the socket to hand to the hypervisor as the VM's network
+/// backend; each datagram on it is one Ethernet frame. `upstream`
+/// resolves names outside the network (None = refuse them).
+pub fn start_switch_port(
+    network: PrivateNetwork,
+    attachment: &NetworkAttachment,
+    upstream: Option<Upstream>,
+) -> io::Result<OwnedFd> {
+    let (guest, vm_end) = UnixDatagram::pair()?;
+    let socket_path = network.socket_path(attachment.address);
+    let _ = std::fs::remove_file(&socket_path);
+    let peers = UnixDatagram::bind(&socket_path)?;
+
+    let port = Arc::new(Port {
+        network,
+        address: attachment.address,
+        mac: attachment.mac(),
+        upstream,
+    });
+    let guest = Arc::new(guest);
+    let peers = Arc::new(peers);
+
+    let (out_port, out_guest, out_peers) = (port.clone(), guest.clone(), peers.clone());
+    std::thread::Builder::new()
+        .name("net-switch-out".to_string())
+        .spawn(move || serve_guest(&out_port, &out_guest, &out_peers))?;
+    std::thread::Builder::new()
+        .name("net-switch-in".to_string())
+        .spawn(move || serve_peers(&port, &guest, &peers))?;
+
+    Ok(OwnedFd::from(vm_end))
+}
+
+/// Handle frames sent by the guest.
+fn serve_guest(port: &Arc<Port>, guest: &Arc<UnixDatagram>, peers: &UnixDatagram) {
+    let mut buf = vec![0u8; MAX_FRAME_SIZE];
+    loop {
+        let n = match guest.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                tracing::debug!(error = %e, "network switch port closed");
+                return;
+            }
+        };
+        match port.handle(&buf[..n]) {
+            Action::Reply(frame) => {
+                let _ = guest.send(&frame);
+            }
+            Action::Forward(dst) => {
+                // Peers that aren't running simply drop the frame
+                let _ = peers.send_to(&buf[..n], port.network.socket_path(dst));
+            }
+            Action::Upstream { query, guest_port } => {
+                let Some(upstream) = port.upstream else {
+                    continue;
+                };
+                let (port, guest) = (port.clone(), guest.clone());
+                std::thread::spawn(move || match upstream.query(&query) {
+                    Ok(response) if response.len() > MAX_FRAME_SIZE - 42 => {
+                        tracing::debug!(len = response.len(), "upstream DNS reply too large")
+                    }
+                    Ok(response) => {
+                        let _ = guest.send(&port.dns_reply(&response, guest_port));
+                    }
+                    Err(e) => tracing::debug!(error = %e, "upstream DNS query failed"),
+                });
+            }
+            Action::Drop => {}
+        }
+    }
+}
+
+/// Hand frames from other members to the guest.
+fn serve_peers(port: &Port, guest: &UnixDatagram, peers: &UnixDatagram) {
+    let mut buf = vec![0u8; MAX_FRAME_SIZE];
+    loop {
+        match peers.recv(&mut buf) {
+            Ok(n) if port.accepts(&buf[..n]) => {
+                if let Err(e) = guest.send(&buf[..n]) {
+                    tracing::debug!(error = %e, "network switch port closed");
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                tracing::debug!(error = %e, "network peer socket failed");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::private::NetworkStore;
+    use std::time::Duration;
+
+    fn arp_request(sender: &NetworkAttachment, target: Ipv4Addr) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&BROADCAST_MAC);
+        frame.extend_from_slice(&sender.mac());
+        frame.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        frame.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+        frame.extend_from_slice(&sender.mac());
+        frame.extend_from_slice(&sender.address.octets());
+        frame.extend_from_slice(&[0; 6]);
+        frame.extend_from_slice(&target.octets());
+        frame
+    }
+
+    fn ipv4_frame(
+        src: &NetworkAttachment,
+        dst: Ipv4Addr,
+        udp_ports: (u16, u16),
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&mac_for(dst));
+        frame.extend_from_slice(&src.mac());
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let mut ip = [0u8; 20];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&((28 + payload.len()) as u16).to_be_bytes());
+        ip[8] = 64;
+        ip[9] = IPPROTO_UDP;
+        ip[12..16].copy_from_slice(&src.address.octets());
+        ip[16..20].copy_from_slice(&dst.octets());
+        frame.extend_from_slice(&ip);
+        frame.extend_from_slice(&udp_ports.0.to_be_bytes());
+        frame.extend_from_slice(&udp_ports.1.to_be_bytes());
+        frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_port_answers_arp_and_dns() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NetworkStore::at(dir.path());
+        store.create("dev", None).unwrap();
+        let app = store.join("dev", "app").unwrap();
+        let db = store.join("dev", "db").unwrap();
+        let port = Port {
+            network: store.get("dev").unwrap(),
+            address: app.address,
+            mac: app.mac(),
+            upstream: None,
+        };
+
+        // ARP for a peer is answered with the peer's derived MAC
+        let Action::Reply(reply) = port.handle(&arp_request(&app, db.address)) else {
+            panic!("expected ARP reply");
+        };
+        assert_eq!(&reply[0..6], &app.mac());
+        assert_eq!(&reply[22..28], &db.mac());
+        assert_eq!(&reply[28..32], &db.address.octets());
+        // ...but not for the VM's own address
+        assert_eq!(port.handle(&arp_request(&app, app.address)), Action::Drop);
+
+        // DNS for a member name is answered by the gateway
+        let gateway = port.network.gateway();
+        let query = dns::encode_query(7, "db", dns::TYPE_A);
+        let Action::Reply(reply) = port.handle(&ipv4_frame(&app, gateway, (40000, 53), &query))
+        else {
+            panic!("expected DNS reply");
+        };
+        assert_eq!(ipv4_checksum(&reply[14..34]), 0);
+        assert_eq!(&reply[34..36], &53u16.to_be_bytes());
+        assert_eq!(&reply[36..38], &40000u16.to_be_bytes());
+        assert_eq!(&reply[reply.len() - 4..], &db.address.octets());
+
+        // Outside names are refused without an upstream
+        let query = dns::encode_query(8, "example.com", dns::TYPE_A);
+        let Action::Reply(reply) = port.handle(&ipv4_frame(&app, gateway, (40000, 53), &query))
+        else {
+            panic!("expected DNS reply");
+        };
+        assert_eq!(reply[45] & 0x0f, dns::RCODE_REFUSED);
+
+        // Traffic to a member is switched to it; anything else is dropped
+        let frame = ipv4_frame(&app, db.address, (1, 2), b"x");
+        assert_eq!(port.handle(&frame), Action::Forward(db.address));
+        let frame = ipv4_frame(&app, Ipv4Addr::new(192, 168, 1, 1), (1, 2), b"x");
+        assert_eq!(port.handle(&frame), Action::Drop);
+    }
+
+    #[test]
+    fn test_port_drops_spoofed_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NetworkStore::at(dir.path());
+        store.create("dev", None).unwrap();
+        let app = store.join("dev", "app").unwrap();
+        let db = store.join("dev", "db").unwrap();
+        let cache = store.join("dev", "cache").unwrap();
+        let port = Port {
+            network: store.get("dev").unwrap(),
+            address: app.address,
+            mac: app.mac(),
+            upstream: None,
+        };
+        let gateway = port.network.gateway();
+        let query = dns::encode_query(7, "db", dns::TYPE_A);
+
+        // Posing as db, by both addresses, gets nothing switched or answered
+        let frame = ipv4_frame(&db, cache.address, (1, 2), b"x");
+        assert_eq!(port.handle(&frame), Action::Drop);
+        let frame = ipv4_frame(&db, gateway, (40000, 53), &query);
+        assert_eq!(port.handle(&frame), Action::Drop);
+        assert_eq!(port.handle(&arp_request(&db, cache.address)), Action::Drop);
+
+        // Nor does borrowing just its IP or just its MAC
+        let mut frame = ipv4_frame(&app, cache.address, (1, 2), b"x");
+        frame[26..30].copy_from_slice(&db.address.octets());
+        assert_eq!(port.handle(&frame), Action::Drop);
+        let mut frame = ipv4_frame(&app, cache.address, (1, 2), b"x");
+        frame[6..12].copy_from_slice(&db.mac());
+        assert_eq!(port.handle(&frame), Action::Drop);
+        let mut frame = arp_request(&app, cache.address);
+        frame[28..32].copy_from_slice(&db.address.octets());
+        assert_eq!(port.handle(&frame), Action::Drop);
+
+        // The same frames from app's own addresses go through
+        let frame = ipv4_frame(&app, cache.address, (1, 2), b"x");
+        assert_eq!(port.handle(&frame), Action::Forward(cache.address));
+        assert!(matches!(
+            port.handle(&arp_request(&app, cache.address)),
+            Action::Reply(_)
+        ));
+    }
+
+    #[test]
+    fn test_switch_ports_relay_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NetworkStore::at(dir.path());
+        store.create("dev", None).unwrap();
+        let app = store.join("dev", "app").unwrap();
+        let db = store.join("dev", "db").unwrap();
+
+        let app_fd = start_switch_port(store.get("dev").unwrap(), &app, None).unwrap();
+        let db_fd = start_switch_port(store.get("dev").unwrap(), &db, None).unwrap();
+        let app_nic = UnixDatagram::from(app_fd);
+        let db_nic = UnixDatagram::from(db_fd);
+        db_nic
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let frame = ipv4_frame(&app, db.address, (5000, 6000), b"hello");
+        app_nic.send(&frame).unwrap();
+        let mut buf = vec![0u8; MAX_FRAME_SIZE];
+        let n = db_nic.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &frame[..]);
+    }
+}
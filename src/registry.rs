@@ -25,6 +25,12 @@
 //! username = "user"
 //! password = "secret"  # Direct password (not recommended)
 //! mirror = "mirror.example.com"  # Optional mirror
+//!
+//! [proxy]  # Optional: default HTTP(S) proxy for VMs without their own
+//! http = "http://proxy.corp:3128"
+//! https = "http://proxy.corp:3128"
+//! no_proxy = ["localhost", ".corp"]
+//! ca_certs = ["/etc/ssl/corp-ca.pem"]
 //! ```
 
 use crate::error::{Error, Result};
@@ -41,6 +47,9 @@ pub struct RegistryConfig {
     /// Default settings.
     #[serde(default)]
     pub defaults: RegistryDefaults,
+    /// Default HTTP(S) proxy for VMs that don't configure their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<crate::network::ProxySettings>,
 }
 
 /// Configuration for a single registry.
@@ -27,7 +27,7 @@ pub const DEFAULT_STORAGE_SIZE_GIB: u64 = 20;
 pub const STORAGE_DISK_FILENAME: &str = "storage.raw";
 
 /// Bytes per gibibyte (GiB).
-const BYTES_PER_GIB: u64 = 1024 * 1024 * 1024;
+pub(crate) const BYTES_PER_GIB: u64 = 1024 * 1024 * 1024;
 
 /// Common search paths for e2fsprogs tools (mkfs.ext4, e2fsck, resize2fs).
 const E2FSPROGS_PATH_PREFIXES: &[&str] = &[
@@ -241,6 +241,20 @@ fn format_disk_with_mkfs(disk_path: &Path, volume_label: &str, label: &str) -> R
     Ok(())
 }
 
+/// Create a formatted ext4 disk image for a named volume.
+///
+/// Copies the overlay template when available (it is a plain empty ext4
+/// filesystem), else formats with mkfs.ext4. The agent grows the
+/// filesystem to the image size when it mounts the volume.
+pub(crate) fn create_volume_disk(path: &Path, size_gb: u64) -> Result<()> {
+    let size_bytes = size_gb * BYTES_PER_GIB;
+    if let Some(template_path) = find_disk_template("overlay-template.ext4") {
+        return copy_disk_from_template(path, size_bytes, &template_path, "volume");
+    }
+    create_sparse_disk(path, size_bytes, "volume")?;
+    format_disk_with_mkfs(path, "smolvm-volume", "volume")
+}
+
 /// Check if a disk file appears to be a valid ext4 filesystem.
 /// Used in tests; removed from hot path to avoid spawning `file` command on every start.
 #[cfg(test)]
@@ -121,6 +121,15 @@ impl LibkrunVm {
         // Inject init.krun into rootfs (required by libkrunfw kernel)
         inject_init_krun(&rootfs_path)?;
 
+        // Allowlists are enforced by the agent VM's egress proxy, which this
+        // direct backend does not run.
+        if let NetworkPolicy::Allowlist { .. } = &config.network {
+            return Err(Error::config(
+                "network policy",
+                "egress allowlists require the agent VM (use --net=allow=...)",
+            ));
+        }
+
         // Setup DNS if network egress is enabled
         if let NetworkPolicy::Egress { dns } = &config.network {
             setup_dns(&rootfs_path, dns.map(|ip| ip.to_string()).as_deref())?;
@@ -209,6 +218,30 @@ impl LibkrunVm {
             // Build environment with defaults
             let (envp, _env_cstrings) = build_env_args(&config.env, &self.id)?;
 
+            // The wrapper script mounts whole shares; single-file mounts
+            // need the agent to hide the file's siblings
+            if let Some(mount) = config.mounts.iter().find(|m| m.source.is_file()) {
+                krun_free_ctx(ctx);
+                return Err(Error::mount(
+                    "configure mount",
+                    format!(
+                        "single-file mount '{}' requires an agent VM",
+                        mount.source.display()
+                    ),
+                ));
+            }
+            // Synced copies are kept by the agent
+            if let Some(mount) = config.mounts.iter().find(|m| m.sync.is_some()) {
+                krun_free_ctx(ctx);
+                return Err(Error::mount(
+                    "configure mount",
+                    format!(
+                        "synced mount '{}' requires an agent VM",
+                        mount.source.display()
+                    ),
+                ));
+            }
+
             // Build mounts list for wrapper script: (tag, guest_path)
             let mount_specs: Vec<(String, String)> = config
                 .mounts
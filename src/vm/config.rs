@@ -1,6 +1,8 @@
 //! VM configuration types.
 
+use crate::agent::SyncConflictPolicy;
 use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
 use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -174,12 +176,21 @@ pub enum NetworkPolicy {
         /// Custom DNS server (default: inherit from host).
         dns: Option<IpAddr>,
     },
+
+    /// Proxy-only egress: HTTP(S) clients that use the host-side proxy
+    /// reach allowlisted destinations, and nothing else leaves the VM.
+    /// Denied proxy requests are logged.
+    Allowlist {
+        /// Allowed destinations (`github.com`, `*.npmjs.org`, `10.0.0.0/8:443`).
+        allow: Vec<crate::network::EgressRule>,
+    },
 }
 
-/// Host directory mount.
+/// Host directory or file mount.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "HostMountRepr")]
 pub struct HostMount {
-    /// Path on the host.
+    /// Path on the host (a directory or a regular file).
     pub source: PathBuf,
 
     /// Path inside the guest.
@@ -187,6 +198,50 @@ pub struct HostMount {
 
     /// Read-only mount (default: true per DESIGN.md).
     pub read_only: bool,
+
+    /// Keep a copy on the guest storage disk, synced with the share using
+    /// this conflict policy, instead of serving the mount over virtiofs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<SyncConflictPolicy>,
+}
+
+/// Accepted serialized forms of [`HostMount`], including the
+/// `[host, guest, read_only]` triples stored by older VM records.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HostMountRepr {
+    Triple(PathBuf, PathBuf, bool),
+    Full {
+        source: PathBuf,
+        target: PathBuf,
+        read_only: bool,
+        #[serde(default)]
+        sync: Option<SyncConflictPolicy>,
+    },
+}
+
+impl From<HostMountRepr> for HostMount {
+    fn from(repr: HostMountRepr) -> Self {
+        match repr {
+            HostMountRepr::Triple(source, target, read_only) => HostMount {
+                source,
+                target,
+                read_only,
+                sync: None,
+            },
+            HostMountRepr::Full {
+                source,
+                target,
+                read_only,
+                sync,
+            } => HostMount {
+                source,
+                target,
+                read_only,
+                sync,
+            },
+        }
+    }
 }
 
 impl HostMount {
@@ -196,6 +251,7 @@ impl HostMount {
             source: source.into(),
             target: target.into(),
             read_only: true, // Safe default per DESIGN.md
+            sync: None,
         }
     }
 
@@ -211,6 +267,26 @@ impl HostMount {
             source: source.into(),
             target: target.into(),
             read_only: false,
+            sync: None,
+        }
+    }
+
+    /// Sync this mount through a copy on the guest storage disk.
+    pub fn synced(mut self, policy: SyncConflictPolicy) -> Self {
+        self.sync = Some(policy);
+        self
+    }
+
+    /// File name of a single-file mount, or `None` for a directory mount.
+    ///
+    /// A single-file mount is shared through its parent directory, with
+    /// only this file exposed in the guest; see
+    /// [`crate::mount::prepare_share`].
+    pub fn shared_file(&self) -> Option<&OsStr> {
+        if self.source.is_file() {
+            self.source.file_name()
+        } else {
+            None
         }
     }
 }
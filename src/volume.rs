@@ -0,0 +1,525 @@
+//! Named persistent volumes.
+//!
+//! A volume is storage that smolvm manages itself and that outlives the
+//! VMs using it, attached with `-v NAME:/path` instead of a host path.
+//! Two drivers are available:
+//!
+//! - `image` (default): a sparse ext4 disk image attached to the VM as a
+//!   block device. Ownership, permissions and case sensitivity behave like
+//!   a Linux filesystem, but only one running VM can use it at a time.
+//! - `dir`: a directory shared over virtiofs, which several running VMs can
+//!   use at once.
+//!
+//! Volumes are recorded in [`SmolvmDb`]. VMs created with a volume are
+//! listed as its users, and every running VM holds a lock on the volumes
+//! it mounted; a volume can only be removed when it has neither.
+//!
+//! # Layout
+//!
+//! ```text
+//! ~/.local/share/smolvm/volumes/{name}/
+//! ├── lock          # Held (shared, or exclusive for images) by running VMs
+//! ├── data/         # dir driver
+//! └── volume.raw    # image driver
+//! ```
+
+use crate::db::SmolvmDb;
+use crate::error::{Error, Result};
+use crate::vm::config::HostMount;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Default size of an image volume (sparse).
+pub const DEFAULT_VOLUME_SIZE_GIB: u64 = 10;
+
+/// Maximum volume name length.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Directory of a `dir` volume's contents.
+const DATA_DIR: &str = "data";
+
+/// Disk image of an `image` volume.
+const IMAGE_FILENAME: &str = "volume.raw";
+
+/// Lock file held by running VMs.
+const LOCK_FILENAME: &str = "lock";
+
+/// How a volume stores its data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeDriver {
+    /// ext4 disk image attached as a block device.
+    #[default]
+    Image,
+    /// Directory shared over virtiofs.
+    Dir,
+}
+
+impl VolumeDriver {
+    /// Driver name as used on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VolumeDriver::Image => "image",
+            VolumeDriver::Dir => "dir",
+        }
+    }
+}
+
+impl std::str::FromStr for VolumeDriver {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "image" => Ok(VolumeDriver::Image),
+            "dir" => Ok(VolumeDriver::Dir),
+            _ => Err(format!(
+                "unknown volume driver '{}' (expected image or dir)",
+                s
+            )),
+        }
+    }
+}
+
+/// A named volume as recorded in the database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeRecord {
+    /// Volume name.
+    pub name: String,
+    /// Storage driver.
+    pub driver: VolumeDriver,
+    /// Disk size in GiB (image volumes only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_gb: Option<u64>,
+    /// Creation timestamp.
+    pub created_at: String,
+    /// VMs created with this volume, which keep it from being removed.
+    #[serde(default)]
+    pub users: Vec<String>,
+}
+
+/// Whether a `-v` source names a volume rather than a host path.
+///
+/// Like Docker, anything that doesn't look like a path is a volume name.
+pub fn is_volume_name(source: &str) -> bool {
+    !source.is_empty() && !source.contains('/') && !source.starts_with(['.', '~'])
+}
+
+/// Whether a `-v` source is spelled as a host path: absolute, home-relative
+/// or starting with `./` or `../`.
+///
+/// Other relative paths are rejected so that `data/sub` and `data` don't
+/// mean a path and a volume respectively.
+pub fn is_explicit_path(source: &str) -> bool {
+    source.starts_with(['/', '~'])
+        || matches!(source, "." | "..")
+        || source.starts_with("./")
+        || source.starts_with("../")
+}
+
+/// Check that a volume name is usable as a directory name.
+pub fn validate_volume_name(name: &str) -> Result<()> {
+    let valid = name.len() <= MAX_NAME_LENGTH
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::config(
+            "validate volume name",
+            format!(
+                "'{}' must be 1-{} letters, digits, '_', '.' or '-', starting with a letter or digit",
+                name, MAX_NAME_LENGTH
+            ),
+        ))
+    }
+}
+
+/// Named volumes, recorded in the database and stored under a root directory.
+#[derive(Debug, Clone)]
+pub struct VolumeStore {
+    db: SmolvmDb,
+    root: PathBuf,
+}
+
+impl VolumeStore {
+    /// Open the store at the default locations.
+    pub fn open() -> Result<Self> {
+        Ok(Self::at(SmolvmDb::open()?, default_root()?))
+    }
+
+    /// Open a store using `db` with volumes stored under `root`.
+    pub fn at(db: SmolvmDb, root: impl Into<PathBuf>) -> Self {
+        Self {
+            db,
+            root: root.into(),
+        }
+    }
+
+    fn volume_dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Host path to mount for a volume: its data directory or disk image.
+    pub fn source_path(&self, volume: &VolumeRecord) -> PathBuf {
+        let dir = self.volume_dir(&volume.name);
+        match volume.driver {
+            VolumeDriver::Image => dir.join(IMAGE_FILENAME),
+            VolumeDriver::Dir => dir.join(DATA_DIR),
+        }
+    }
+
+    /// Create a volume.
+    pub fn create(
+        &self,
+        name: &str,
+        driver: VolumeDriver,
+        size_gb: Option<u64>,
+    ) -> Result<VolumeRecord> {
+        validate_volume_name(name)?;
+        let size_gb = match driver {
+            VolumeDriver::Image => Some(size_gb.unwrap_or(DEFAULT_VOLUME_SIZE_GIB)),
+            VolumeDriver::Dir if size_gb.is_some() => {
+                return Err(Error::config(
+                    "create volume",
+                    "--size only applies to image volumes",
+                ));
+            }
+            VolumeDriver::Dir => None,
+        };
+        if size_gb == Some(0) {
+            return Err(Error::config(
+                "create volume",
+                "volume size must be greater than 0 GB",
+            ));
+        }
+
+        let record = VolumeRecord {
+            name: name.to_string(),
+            driver,
+            size_gb,
+            created_at: crate::util::current_timestamp(),
+            users: Vec::new(),
+        };
+        if !self.db.insert_volume_if_not_exists(name, &record)? {
+            return Err(Error::config(
+                "create volume",
+                format!("volume '{}' already exists", name),
+            ));
+        }
+
+        if let Err(e) = self.create_storage(&record) {
+            let _ = std::fs::remove_dir_all(self.volume_dir(name));
+            let _ = self.db.remove_volume(name, |_| Ok(()));
+            return Err(e);
+        }
+        Ok(record)
+    }
+
+    fn create_storage(&self, volume: &VolumeRecord) -> Result<()> {
+        let dir = self.volume_dir(&volume.name);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| Error::storage("create volume directory", e.to_string()))?;
+        File::create(dir.join(LOCK_FILENAME))
+            .map_err(|e| Error::storage("create volume lock", e.to_string()))?;
+        let source = self.source_path(volume);
+        match volume.driver {
+            VolumeDriver::Image => crate::storage::create_volume_disk(
+                &source,
+                volume.size_gb.unwrap_or(DEFAULT_VOLUME_SIZE_GIB),
+            ),
+            VolumeDriver::Dir => std::fs::create_dir_all(&source)
+                .map_err(|e| Error::storage("create volume directory", e.to_string())),
+        }
+    }
+
+    /// Load a volume by name.
+    pub fn get(&self, name: &str) -> Result<VolumeRecord> {
+        self.db.get_volume(name)?.ok_or_else(|| {
+            Error::config(
+                "load volume",
+                format!(
+                    "volume '{}' does not exist (create it with `smolvm volume create {}`)",
+                    name, name
+                ),
+            )
+        })
+    }
+
+    /// Load a volume, creating it with the default driver if it doesn't
+    /// exist. Also returns whether this call created it.
+    pub fn get_or_create(&self, name: &str) -> Result<(VolumeRecord, bool)> {
+        if let Some(volume) = self.db.get_volume(name)? {
+            return Ok((volume, false));
+        }
+        match self.create(name, VolumeDriver::default(), None) {
+            Ok(volume) => Ok((volume, true)),
+            // Lost a race with a concurrent create
+            Err(e) => Ok((self.db.get_volume(name)?.ok_or(e)?, false)),
+        }
+    }
+
+    /// List all volumes, sorted by name.
+    pub fn list(&self) -> Result<Vec<VolumeRecord>> {
+        let mut volumes = self.db.list_volumes()?;
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(volumes)
+    }
+
+    /// Whether a running VM has the volume mounted.
+    pub fn is_mounted(&self, name: &str) -> bool {
+        match File::open(self.volume_dir(name).join(LOCK_FILENAME)) {
+            Ok(file) => try_lock(&file, libc::LOCK_EX).is_err(),
+            Err(_) => false,
+        }
+    }
+
+    /// Remove a volume and its data. Fails while any VM uses it.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        // Held until the data is gone so no VM can start using it meanwhile
+        let lock = File::open(self.volume_dir(name).join(LOCK_FILENAME)).ok();
+        if let Some(lock) = &lock {
+            if try_lock(lock, libc::LOCK_EX).is_err() {
+                return Err(Error::config(
+                    "remove volume",
+                    format!("volume '{}' is mounted by a running VM", name),
+                ));
+            }
+        }
+
+        let removed = self.db.remove_volume(name, |volume| {
+            if volume.users.is_empty() {
+                return Ok(());
+            }
+            Err(Error::config(
+                "remove volume",
+                format!(
+                    "volume '{}' is used by {}; delete those VMs first",
+                    name,
+                    volume.users.join(", ")
+                ),
+            ))
+        })?;
+        if removed.is_none() {
+            return Err(Error::config(
+                "remove volume",
+                format!("volume '{}' does not exist", name),
+            ));
+        }
+
+        match std::fs::remove_dir_all(self.volume_dir(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::storage("remove volume data", e.to_string())),
+        }
+    }
+
+    /// Record `user` as a user of a volume.
+    pub fn acquire(&self, name: &str, user: &str) -> Result<()> {
+        let updated = self.db.update_volume(name, |volume| {
+            if !volume.users.iter().any(|u| u == user) {
+                volume.users.push(user.to_string());
+            }
+        })?;
+        match updated {
+            Some(_) => Ok(()),
+            None => self.get(name).map(|_| ()),
+        }
+    }
+
+    /// Drop `user` from every volume it uses.
+    pub fn release(&self, user: &str) {
+        let volumes = match self.db.list_volumes() {
+            Ok(volumes) => volumes,
+            Err(e) => {
+                tracing::warn!(error = %e, user, "failed to release volumes");
+                return;
+            }
+        };
+        for volume in volumes.iter().filter(|v| v.users.iter().any(|u| u == user)) {
+            let result = self.db.update_volume(&volume.name, |volume| {
+                volume.users.retain(|u| u != user);
+            });
+            if let Err(e) = result {
+                tracing::warn!(error = %e, volume = %volume.name, user, "failed to release volume");
+            }
+        }
+    }
+
+    /// Record `user` on every volume among `mounts`.
+    ///
+    /// On failure, volumes acquired so far are released again.
+    pub fn acquire_mounts(&self, mounts: &[HostMount], user: &str) -> Result<()> {
+        for mount in mounts {
+            if let Some((name, _)) = volume_at(&self.root, &mount.source) {
+                if let Err(e) = self.acquire(&name, user) {
+                    self.release(user);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Volume mounted from a host path, if the path is a volume's source.
+    pub fn volume_for_path(&self, path: &Path) -> Option<(String, VolumeDriver)> {
+        volume_at(&self.root, path)
+    }
+}
+
+/// Default directory for volume data.
+pub fn default_root() -> Result<PathBuf> {
+    let data_dir = dirs::data_local_dir().ok_or_else(|| {
+        Error::config(
+            "open volume store",
+            "could not determine local data directory",
+        )
+    })?;
+    Ok(data_dir.join("smolvm").join("volumes"))
+}
+
+/// Name and driver of the volume whose source is `path` under `root`.
+fn volume_at(root: &Path, path: &Path) -> Option<(String, VolumeDriver)> {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let relative = path.strip_prefix(&root).ok()?;
+    let mut components = relative.components();
+    let name = components.next()?.as_os_str().to_str()?.to_string();
+    let driver = match components.next()?.as_os_str().to_str()? {
+        IMAGE_FILENAME => VolumeDriver::Image,
+        DATA_DIR => VolumeDriver::Dir,
+        _ => return None,
+    };
+    components.next().is_none().then_some((name, driver))
+}
+
+/// Whether a mount source is an image volume's disk, which is attached as a
+/// block device instead of being shared over virtiofs.
+pub fn is_image_volume(source: &Path) -> bool {
+    default_root()
+        .ok()
+        .and_then(|root| volume_at(&root, source))
+        .is_some_and(|(_, driver)| driver == VolumeDriver::Image)
+}
+
+/// Lock the volumes among `mounts` for a VM's lifetime.
+///
+/// Directory volumes are locked shared; image volumes exclusively, since
+/// two VMs mounting the same ext4 filesystem would corrupt it. The locks
+/// are released when the returned files are closed.
+pub fn lock_volumes(mounts: &[HostMount]) -> Result<Vec<File>> {
+    let root = default_root()?;
+    let mut locks = Vec::new();
+    for mount in mounts {
+        let Some((name, driver)) = volume_at(&root, &mount.source) else {
+            continue;
+        };
+        let file = File::open(root.join(&name).join(LOCK_FILENAME))
+            .map_err(|e| Error::mount("lock volume", format!("'{}': {}", name, e)))?;
+        let mode = match driver {
+            VolumeDriver::Image => libc::LOCK_EX,
+            VolumeDriver::Dir => libc::LOCK_SH,
+        };
+        try_lock(&file, mode).map_err(|_| {
+            Error::mount(
+                "lock volume",
+                format!("image volume '{}' is in use by another running VM", name),
+            )
+        })?;
+        locks.push(file);
+    }
+    Ok(locks)
+}
+
+/// Take a non-blocking flock.
+fn try_lock(file: &File, mode: libc::c_int) -> std::io::Result<()> {
+    // SAFETY: flock on a descriptor owned by `file`
+    if unsafe { libc::flock(file.as_raw_fd(), mode | libc::LOCK_NB) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> (tempfile::TempDir, VolumeStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SmolvmDb::open_at(&dir.path().join("test.redb")).unwrap();
+        let store = VolumeStore::at(db, dir.path().join("volumes"));
+        (dir, store)
+    }
+
+    #[test]
+    fn test_is_volume_name() {
+        assert!(is_volume_name("myvol"));
+        assert!(is_volume_name("pg_data.v2"));
+        assert!(!is_volume_name("/data"));
+        assert!(!is_volume_name("./data"));
+        assert!(!is_volume_name("data/sub"));
+        assert!(!is_volume_name("~"));
+        assert!(!is_volume_name(""));
+
+        assert!(is_explicit_path("/data"));
+        assert!(is_explicit_path("./data/sub"));
+        assert!(is_explicit_path("../data"));
+        assert!(is_explicit_path("~/data"));
+        assert!(is_explicit_path("."));
+        assert!(!is_explicit_path("data/sub"));
+        assert!(!is_explicit_path(".config/app"));
+        assert!(!is_explicit_path("myvol"));
+
+        assert!(validate_volume_name("pg-data").is_ok());
+        assert!(validate_volume_name("-data").is_err());
+        assert!(validate_volume_name("my vol").is_err());
+    }
+
+    #[test]
+    fn test_dir_volume_lifecycle() {
+        let (_dir, store) = temp_store();
+        let volume = store.create("cache", VolumeDriver::Dir, None).unwrap();
+        let source = store.source_path(&volume);
+        assert!(source.is_dir());
+        assert!(store.create("cache", VolumeDriver::Dir, None).is_err());
+        assert!(store.create("sized", VolumeDriver::Dir, Some(1)).is_err());
+
+        let source = source.canonicalize().unwrap();
+        assert_eq!(
+            store.volume_for_path(&source),
+            Some(("cache".to_string(), VolumeDriver::Dir))
+        );
+        assert_eq!(store.volume_for_path(&source.join("sub")), None);
+
+        // A VM using the volume keeps it from being removed
+        let mounts = vec![HostMount::new_writable(&source, "/data")];
+        store.acquire_mounts(&mounts, "web").unwrap();
+        store.acquire_mounts(&mounts, "web").unwrap();
+        assert_eq!(store.get("cache").unwrap().users, vec!["web"]);
+        let err = store.remove("cache").unwrap_err().to_string();
+        assert!(err.contains("used by web"), "unexpected error: {}", err);
+
+        store.release("web");
+        store.remove("cache").unwrap();
+        assert!(store.list().unwrap().is_empty());
+        assert!(!source.exists());
+        assert!(store.remove("cache").is_err());
+    }
+
+    #[test]
+    fn test_mounted_volume_cannot_be_removed() {
+        let (_dir, store) = temp_store();
+        store.create("shared", VolumeDriver::Dir, None).unwrap();
+
+        let lock = File::open(store.volume_dir("shared").join(LOCK_FILENAME)).unwrap();
+        try_lock(&lock, libc::LOCK_SH).unwrap();
+        assert!(store.is_mounted("shared"));
+        assert!(store.remove("shared").is_err());
+
+        drop(lock);
+        assert!(!store.is_mounted("shared"));
+        store.remove("shared").unwrap();
+    }
+}